@@ -10,6 +10,9 @@ use rand::Rng;
 use ring::aead::Nonce;
 pub use ring::aead::{Aad, LessSafeKey, UnboundKey, NONCE_LEN};
 
+/// Length of the encryption keys in bytes
+pub const KEY_LEN: usize = ring::digest::SHA256_OUTPUT_LEN;
+
 /// Get a random nonce.
 pub fn get_random_nonce() -> ring::aead::Nonce {
     Nonce::assume_unique_for_key(OsRng.gen())
@@ -88,12 +91,23 @@ pub fn encrypted_read(key: &LessSafeKey, file: PathBuf) -> Result<Vec<u8>> {
 /// * `password` - Strong user-created password
 /// * `salt` - Nonce >8 bytes to discourage rainbow attacks
 pub fn get_encryption_key(password: &str, salt: &str) -> Result<LessSafeKey> {
-    let mut key = [0u8; ring::digest::SHA256_OUTPUT_LEN];
+    encryption_key_from_bytes(&get_encryption_key_bytes(password, salt)?)
+}
+
+/// Derives the raw bytes of the key returned by [`get_encryption_key`], which
+/// allows backing up the key independently of the password
+pub fn get_encryption_key_bytes(password: &str, salt: &str) -> Result<[u8; KEY_LEN]> {
+    let mut key = [0u8; KEY_LEN];
 
     argon2()
         .hash_password_into(password.as_bytes(), salt.as_bytes(), &mut key)
         .map_err(|e| format_err!("could not hash password").context(e))?;
-    let key = UnboundKey::new(&ring::aead::CHACHA20_POLY1305, &key)
+    Ok(key)
+}
+
+/// Constructs a key from the bytes returned by [`get_encryption_key_bytes`]
+pub fn encryption_key_from_bytes(key: &[u8; KEY_LEN]) -> Result<LessSafeKey> {
+    let key = UnboundKey::new(&ring::aead::CHACHA20_POLY1305, key)
         .map_err(|_| anyhow::Error::msg("Unable to create key"))?;
     Ok(LessSafeKey::new(key))
 }
@@ -8,7 +8,8 @@ mod bench {
 
     use tbs::{
         aggregate_signature_shares, blind_message, dealer_keygen, sign_blinded_msg,
-        unblind_signature, verify, BlindedSignatureShare, BlindingKey, Message,
+        unblind_signature, verify, verify_batch, AggregatePublicKey, BlindedSignatureShare,
+        BlindingKey, Message, Signature,
     };
     use test::Bencher;
 
@@ -75,4 +76,52 @@ mod bench {
 
         bencher.iter(|| verify(msg, sig, pk));
     }
+
+    /// Signatures of `num_sigs` messages distributed over `num_keys` keys like
+    /// the notes of a transaction over denominations
+    fn signed_messages(
+        num_sigs: usize,
+        num_keys: usize,
+    ) -> Vec<(Message, Signature, AggregatePublicKey)> {
+        let keys = (0..num_keys)
+            .map(|_| dealer_keygen(4, 5))
+            .collect::<Vec<_>>();
+
+        (0..num_sigs)
+            .map(|i| {
+                let (pk, _pks, sks) = &keys[i % num_keys];
+                let msg = Message::from_bytes(&i.to_le_bytes());
+                let bkey = BlindingKey::random();
+                let bmsg = blind_message(msg, bkey);
+                let shares = (1_u64..)
+                    .zip(sks.iter().map(|sk| sign_blinded_msg(bmsg, *sk)))
+                    .take(4)
+                    .collect();
+                let bsig = aggregate_signature_shares(&shares);
+
+                (msg, unblind_signature(bkey, bsig), *pk)
+            })
+            .collect()
+    }
+
+    #[bench]
+    fn bench_verify_100_individually(bencher: &mut Bencher) {
+        let sigs = signed_messages(100, 10);
+
+        bencher.iter(|| sigs.iter().all(|(msg, sig, pk)| verify(*msg, *sig, *pk)));
+    }
+
+    #[bench]
+    fn bench_verify_batch_100(bencher: &mut Bencher) {
+        let sigs = signed_messages(100, 10);
+
+        bencher.iter(|| verify_batch(&sigs));
+    }
+
+    #[bench]
+    fn bench_verify_batch_1000(bencher: &mut Bencher) {
+        let sigs = signed_messages(1000, 20);
+
+        bencher.iter(|| verify_batch(&sigs));
+    }
 }
@@ -3,14 +3,16 @@
 //! This library implements an ad-hoc threshold blind signature scheme based on
 //! BLS signatures using the (unrelated) BLS12-381 curve.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
-use bls12_381::{pairing, G1Affine, G1Projective, G2Affine, G2Projective};
+use bls12_381::{
+    multi_miller_loop, pairing, G1Affine, G1Projective, G2Affine, G2Prepared, G2Projective, Gt,
+};
 pub use bls12_381::{G1Affine as MessagePoint, G2Affine as PubKeyPoint, Scalar};
 use ff::Field;
 use group::{Curve, Group};
 use rand::rngs::OsRng;
-use rand::SeedableRng;
+use rand::{RngCore, SeedableRng};
 use rand_chacha::ChaChaRng;
 use serde::{Deserialize, Serialize};
 use sha3::Digest;
@@ -202,50 +204,89 @@ pub fn verify(msg: Message, sig: Signature, pk: AggregatePublicKey) -> bool {
     pairing(&msg.0, &pk.0) == pairing(&sig.0, &G2Affine::generator())
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::BTreeMap;
+/// Generates the keys of a federation of `keys` peers with the given
+/// `threshold` using a trusted dealer, which is only suitable for tests and
+/// benchmarks
+#[cfg(any(test, feature = "unstable"))]
+pub fn dealer_keygen(
+    threshold: usize,
+    keys: usize,
+) -> (AggregatePublicKey, Vec<PublicKeyShare>, Vec<SecretKeyShare>) {
+    let mut rng = OsRng;
+    let poly: Vec<Scalar> = (0..threshold).map(|_| Scalar::random(&mut rng)).collect();
 
-    use bls12_381::{G2Projective, Scalar};
-    use ff::Field;
-    use group::Curve;
-    use rand::rngs::OsRng;
+    let apk = (G2Projective::generator() * eval_polynomial(&poly, &Scalar::zero())).to_affine();
 
-    use crate::{
-        aggregate_signature_shares, blind_message, sign_blinded_msg, unblind_signature, verify,
-        verify_blind_share, AggregatePublicKey, BlindedSignatureShare, BlindingKey, Message,
-        PublicKeyShare, SecretKeyShare,
-    };
-
-    fn dealer_keygen(
-        threshold: usize,
-        keys: usize,
-    ) -> (AggregatePublicKey, Vec<PublicKeyShare>, Vec<SecretKeyShare>) {
-        let mut rng = OsRng;
-        let poly: Vec<Scalar> = (0..threshold).map(|_| Scalar::random(&mut rng)).collect();
+    let sks: Vec<SecretKeyShare> = (0..keys)
+        .map(|idx| SecretKeyShare(eval_polynomial(&poly, &Scalar::from(idx as u64 + 1))))
+        .collect();
 
-        let apk = (G2Projective::generator() * eval_polynomial(&poly, &Scalar::zero())).to_affine();
+    let pks = sks
+        .iter()
+        .map(|sk| PublicKeyShare((G2Projective::generator() * sk.0).to_affine()))
+        .collect();
 
-        let sks: Vec<SecretKeyShare> = (0..keys)
-            .map(|idx| SecretKeyShare(eval_polynomial(&poly, &Scalar::from(idx as u64 + 1))))
-            .collect();
+    (AggregatePublicKey(apk), pks, sks)
+}
 
-        let pks = sks
-            .iter()
-            .map(|sk| PublicKeyShare((G2Projective::generator() * sk.0).to_affine()))
-            .collect();
+#[cfg(any(test, feature = "unstable"))]
+fn eval_polynomial(coefficients: &[Scalar], x: &Scalar) -> Scalar {
+    coefficients
+        .iter()
+        .cloned()
+        .rev()
+        .reduce(|acc, coefficient| acc * x + coefficient)
+        .expect("We have at least one coefficient")
+}
 
-        (AggregatePublicKey(apk), pks, sks)
+/// Verifies many signatures at once, which is considerably faster than calling
+/// [`verify`] for every one of them since all signatures under the same public
+/// key are checked with a single pairing.
+///
+/// Returns `true` if all signatures are valid. If it returns `false` at least
+/// one signature is invalid, the caller has to verify them one by one to find
+/// out which.
+pub fn verify_batch(items: &[(Message, Signature, AggregatePublicKey)]) -> bool {
+    let mut rng = OsRng;
+    let mut signature_sum = G1Projective::identity();
+    let mut message_sums = HashMap::<AggregatePublicKey, G1Projective>::new();
+
+    // Random weights prevent invalid signatures from canceling each other out,
+    // 128 bits are sufficient for the soundness of the check
+    for (msg, sig, pk) in items {
+        let weight = Scalar::from_raw([rng.next_u64(), rng.next_u64(), 0, 0]);
+
+        signature_sum += sig.0 * weight;
+        *message_sums
+            .entry(*pk)
+            .or_insert_with(G1Projective::identity) += msg.0 * weight;
     }
 
-    fn eval_polynomial(coefficients: &[Scalar], x: &Scalar) -> Scalar {
-        coefficients
-            .iter()
-            .cloned()
-            .rev()
-            .reduce(|acc, coefficient| acc * x + coefficient)
-            .expect("We have at least one coefficient")
-    }
+    let signature_sum = signature_sum.to_affine();
+    let generator = G2Prepared::from(-G2Affine::generator());
+    let message_sums = message_sums
+        .into_iter()
+        .map(|(pk, message_sum)| (message_sum.to_affine(), G2Prepared::from(pk.0)))
+        .collect::<Vec<_>>();
+
+    let terms = message_sums
+        .iter()
+        .map(|(message_sum, pk)| (message_sum, pk))
+        .chain(std::iter::once((&signature_sum, &generator)))
+        .collect::<Vec<_>>();
+
+    multi_miller_loop(&terms).final_exponentiation() == Gt::identity()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        aggregate_signature_shares, blind_message, dealer_keygen, sign_blinded_msg,
+        unblind_signature, verify, verify_batch, verify_blind_share, AggregatePublicKey,
+        BlindedSignatureShare, BlindingKey, Message, Signature,
+    };
 
     #[test]
     fn test_roundtrip() {
@@ -274,4 +315,42 @@ mod tests {
 
         assert!(verify(msg, sig, pk));
     }
+
+    #[test]
+    fn test_verify_batch() {
+        let keys = [dealer_keygen(3, 4), dealer_keygen(3, 4)];
+
+        let mut batch = (0..20_u8)
+            .map(|i| {
+                let (pk, _, sks) = &keys[usize::from(i % 2)];
+                let msg = Message::from_bytes(&[i]);
+                let bkey = BlindingKey::random();
+                let bmsg = blind_message(msg, bkey);
+
+                let bsig_shares = (1_u64..)
+                    .zip(sks.iter().map(|sk| sign_blinded_msg(bmsg, *sk)))
+                    .take(3)
+                    .collect::<BTreeMap<u64, BlindedSignatureShare>>();
+
+                let bsig = aggregate_signature_shares(&bsig_shares);
+                let sig = unblind_signature(bkey, bsig);
+
+                (msg, sig, *pk)
+            })
+            .collect::<Vec<(Message, Signature, AggregatePublicKey)>>();
+
+        assert!(verify_batch(&[]));
+        assert!(verify_batch(&batch));
+
+        // a signature under the wrong key
+        let mut wrong_key = batch.clone();
+        wrong_key[3].2 = keys[0].0;
+        assert!(!verify_batch(&wrong_key));
+
+        // swapped signatures of the same key still sum up to the same point
+        let (sig_4, sig_6) = (batch[4].1, batch[6].1);
+        batch[4].1 = sig_6;
+        batch[6].1 = sig_4;
+        assert!(!verify_batch(&batch));
+    }
 }
@@ -603,9 +603,8 @@ pub async fn cli_tests(dev_fed: DevFed) -> Result<()> {
         .as_str()
         .map(|s| s.to_owned())
         .unwrap();
-    let client_reissue_amt = cmd!(client, "reissue", reissue_notes)
-        .out_json()
-        .await?
+    let client_reissue_amt = cmd!(client, "reissue", reissue_notes).out_json().await?
+        ["amount_msat"]
         .as_u64()
         .unwrap();
     assert_eq!(client_reissue_amt, reissue_amount);
@@ -1,5 +1,7 @@
 //! BIP39 client secret support crate
 
+pub mod shamir;
+
 use std::io::{Read, Write};
 
 use fedimint_client::derivable_secret::DerivableSecret;
@@ -0,0 +1,356 @@
+//! Shamir secret sharing of 32 byte secrets encoded as mnemonic phrases
+//!
+//! The encoding is inspired by SLIP-39: every share carries a random
+//! identifier of the split it belongs to, the threshold and its index and is
+//! protected by a checksum against typos. Unlike SLIP-39 the shares use the
+//! BIP39 English word list, as that is what users of this crate are already
+//! familiar with, and there is no support for groups or passphrases.
+//!
+//! The secret is split byte-wise over GF(256) with the polynomial used by AES,
+//! so any `threshold` shares reconstruct the secret while fewer shares reveal
+//! nothing about it.
+
+use std::collections::BTreeSet;
+
+use anyhow::{bail, ensure, format_err};
+use bitcoin_hashes::{sha256, Hash};
+use rand::{CryptoRng, RngCore};
+
+/// Length of the secrets that can be split
+pub const SECRET_LEN: usize = 32;
+
+/// Identifier, threshold and index preceding the share value
+const HEADER_LEN: usize = 4;
+
+const CHECKSUM_LEN: usize = 4;
+
+const SHARE_LEN: usize = HEADER_LEN + SECRET_LEN + CHECKSUM_LEN;
+
+/// Each word of the BIP39 word list encodes 11 bits
+const BITS_PER_WORD: usize = 11;
+
+/// Number of words of a share mnemonic, the unused bits of the last word are
+/// zero
+pub const SHARE_WORD_COUNT: usize = (SHARE_LEN * 8 + BITS_PER_WORD - 1) / BITS_PER_WORD;
+
+/// Domain separation tag of the share checksum
+const CHECKSUM_TAG: &[u8] = b"fedimint-secret-share";
+
+/// One share of a secret split with [`split_secret`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretShare {
+    /// Random identifier that is the same for all shares of a split
+    pub identifier: u16,
+    /// Number of shares required to reconstruct the secret
+    pub threshold: u8,
+    /// Evaluation point of the share, never zero since that is the secret
+    pub index: u8,
+    pub value: [u8; SECRET_LEN],
+}
+
+impl SecretShare {
+    fn to_bytes(&self) -> [u8; SHARE_LEN] {
+        let mut bytes = [0; SHARE_LEN];
+
+        bytes[..2].copy_from_slice(&self.identifier.to_be_bytes());
+        bytes[2] = self.threshold;
+        bytes[3] = self.index;
+        bytes[HEADER_LEN..HEADER_LEN + SECRET_LEN].copy_from_slice(&self.value);
+
+        let checksum = checksum(&bytes[..HEADER_LEN + SECRET_LEN]);
+        bytes[HEADER_LEN + SECRET_LEN..].copy_from_slice(&checksum);
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; SHARE_LEN]) -> anyhow::Result<Self> {
+        let (data, share_checksum) = bytes.split_at(HEADER_LEN + SECRET_LEN);
+
+        ensure!(
+            checksum(data) == share_checksum,
+            "Invalid share checksum, please check the words for typos"
+        );
+
+        let share = SecretShare {
+            identifier: u16::from_be_bytes([data[0], data[1]]),
+            threshold: data[2],
+            index: data[3],
+            value: data[HEADER_LEN..].try_into().expect("Length checked above"),
+        };
+
+        ensure!(share.threshold != 0, "Share has a threshold of zero");
+        ensure!(share.index != 0, "Share has an index of zero");
+
+        Ok(share)
+    }
+
+    /// Encodes the share as [`SHARE_WORD_COUNT`] words of the BIP39 English
+    /// word list
+    pub fn to_mnemonic(&self) -> String {
+        let word_list = bip39::Language::English.word_list();
+        let bytes = self.to_bytes();
+
+        (0..SHARE_WORD_COUNT)
+            .map(|word| {
+                let index = (0..BITS_PER_WORD).fold(0usize, |index, bit| {
+                    let position = word * BITS_PER_WORD + bit;
+                    let bit = bytes
+                        .get(position / 8)
+                        .map_or(0, |byte| (byte >> (7 - position % 8)) & 1);
+
+                    (index << 1) | bit as usize
+                });
+
+                word_list[index]
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> anyhow::Result<Self> {
+        let words = mnemonic.split_whitespace().collect::<Vec<_>>();
+
+        ensure!(
+            words.len() == SHARE_WORD_COUNT,
+            "A share consists of {SHARE_WORD_COUNT} words but {} were given",
+            words.len()
+        );
+
+        let mut bytes = [0u8; SHARE_LEN];
+
+        for (word_index, word) in words.iter().enumerate() {
+            let index = bip39::Language::English
+                .find_word(&word.to_lowercase())
+                .ok_or_else(|| format_err!("Unknown word {word}"))?;
+
+            for bit in 0..BITS_PER_WORD {
+                let position = word_index * BITS_PER_WORD + bit;
+                let value = ((index >> (BITS_PER_WORD - 1 - bit)) & 1) as u8;
+
+                match bytes.get_mut(position / 8) {
+                    Some(byte) => *byte |= value << (7 - position % 8),
+                    None => ensure!(value == 0, "Invalid padding of the last word"),
+                }
+            }
+        }
+
+        Self::from_bytes(&bytes)
+    }
+}
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let mut engine = sha256::HashEngine::default();
+    bitcoin_hashes::HashEngine::input(&mut engine, CHECKSUM_TAG);
+    bitcoin_hashes::HashEngine::input(&mut engine, data);
+
+    sha256::Hash::from_engine(engine).into_inner()[..CHECKSUM_LEN]
+        .try_into()
+        .expect("Hash is longer than the checksum")
+}
+
+/// Splits the secret into `shares` shares of which any `threshold` can
+/// reconstruct it
+pub fn split_secret<R>(
+    secret: &[u8; SECRET_LEN],
+    threshold: u8,
+    shares: u8,
+    rng: &mut R,
+) -> anyhow::Result<Vec<SecretShare>>
+where
+    R: RngCore + CryptoRng,
+{
+    ensure!(threshold != 0, "The threshold has to be at least one");
+    ensure!(
+        threshold <= shares,
+        "The threshold {threshold} exceeds the number of shares {shares}"
+    );
+
+    let identifier = rng.next_u32() as u16;
+
+    // The constant term of every polynomial is the corresponding secret byte
+    let polynomials = secret
+        .iter()
+        .map(|byte| {
+            let mut coefficients = vec![0u8; threshold as usize];
+            coefficients[0] = *byte;
+            rng.fill_bytes(&mut coefficients[1..]);
+            coefficients
+        })
+        .collect::<Vec<_>>();
+
+    Ok((1..=shares)
+        .map(|index| SecretShare {
+            identifier,
+            threshold,
+            index,
+            value: polynomials
+                .iter()
+                .map(|coefficients| evaluate(coefficients, index))
+                .collect::<Vec<_>>()
+                .try_into()
+                .expect("One value per secret byte"),
+        })
+        .collect())
+}
+
+/// Reconstructs the secret from at least `threshold` shares of the same split
+pub fn combine_shares(shares: &[SecretShare]) -> anyhow::Result<[u8; SECRET_LEN]> {
+    let Some(first) = shares.first() else {
+        bail!("No shares were given");
+    };
+
+    ensure!(
+        shares
+            .iter()
+            .all(|share| share.identifier == first.identifier && share.threshold == first.threshold),
+        "The shares do not belong to the same secret"
+    );
+
+    let indices = shares
+        .iter()
+        .map(|share| share.index)
+        .collect::<BTreeSet<_>>();
+
+    ensure!(
+        indices.len() == shares.len(),
+        "The same share was given more than once"
+    );
+    ensure!(
+        shares.len() >= first.threshold as usize,
+        "{} shares are required but only {} were given",
+        first.threshold,
+        shares.len()
+    );
+
+    let shares = &shares[..first.threshold as usize];
+    let mut secret = [0u8; SECRET_LEN];
+
+    // Lagrange interpolation at zero, subtraction is addition in GF(256)
+    for share in shares {
+        let basis = shares
+            .iter()
+            .filter(|other| other.index != share.index)
+            .fold(1, |basis, other| {
+                gf256_mul(basis, gf256_div(other.index, other.index ^ share.index))
+            });
+
+        for (secret_byte, value_byte) in secret.iter_mut().zip(share.value) {
+            *secret_byte ^= gf256_mul(value_byte, basis);
+        }
+    }
+
+    Ok(secret)
+}
+
+/// Evaluates the polynomial with the given coefficients at `x` using Horner's
+/// method
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0, |value, coefficient| gf256_mul(value, x) ^ coefficient)
+}
+
+fn gf256_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+
+    while b != 0 {
+        if b & 1 == 1 {
+            product ^= a;
+        }
+
+        // multiply a by x and reduce by x^8 + x^4 + x^3 + x + 1
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+
+        b >>= 1;
+    }
+
+    product
+}
+
+fn gf256_div(a: u8, b: u8) -> u8 {
+    assert_ne!(b, 0, "Division by zero");
+
+    // b^254 is the multiplicative inverse of b since b^255 = 1
+    let (inverse, _) = (0..7).fold((1, b), |(inverse, power), _| {
+        let power = gf256_mul(power, power);
+        (gf256_mul(inverse, power), power)
+    });
+
+    gf256_mul(a, inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::{OsRng, StdRng};
+    use rand::SeedableRng;
+
+    use super::{combine_shares, gf256_div, gf256_mul, split_secret, SecretShare};
+
+    #[test]
+    fn division_inverts_multiplication() {
+        for a in 0..=255u8 {
+            for b in 1..=255u8 {
+                assert_eq!(gf256_div(gf256_mul(a, b), b), a);
+            }
+        }
+    }
+
+    #[test]
+    fn any_threshold_of_shares_reconstructs_secret() {
+        let secret = [42; 32];
+        let shares = split_secret(&secret, 3, 5, &mut OsRng).unwrap();
+
+        assert_eq!(combine_shares(&shares).unwrap(), secret);
+        assert_eq!(
+            combine_shares(&[shares[4].clone(), shares[0].clone(), shares[2].clone()]).unwrap(),
+            secret
+        );
+        assert_eq!(combine_shares(&shares[1..4]).unwrap(), secret);
+
+        assert!(combine_shares(&shares[..2]).is_err());
+        assert!(
+            combine_shares(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err()
+        );
+
+        // Seeded, so the splits are known to have different identifiers
+        let shares = split_secret(&secret, 3, 5, &mut StdRng::seed_from_u64(0)).unwrap();
+        let other_split = split_secret(&secret, 3, 5, &mut StdRng::seed_from_u64(1)).unwrap();
+        assert_ne!(shares[0].identifier, other_split[0].identifier);
+        assert!(
+            combine_shares(&[shares[0].clone(), shares[1].clone(), other_split[2].clone()])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        assert!(split_secret(&[0; 32], 0, 3, &mut OsRng).is_err());
+        assert!(split_secret(&[0; 32], 4, 3, &mut OsRng).is_err());
+        assert!(split_secret(&[0; 32], 1, 1, &mut OsRng).is_ok());
+    }
+
+    #[test]
+    fn mnemonic_roundtrip() {
+        let shares = split_secret(&[7; 32], 2, 3, &mut OsRng).unwrap();
+
+        for share in shares {
+            let mnemonic = share.to_mnemonic();
+            assert_eq!(mnemonic.split(' ').count(), super::SHARE_WORD_COUNT);
+            assert_eq!(SecretShare::from_mnemonic(&mnemonic).unwrap(), share);
+
+            // replacing a single word is caught by the checksum
+            let mut words = mnemonic.split(' ').collect::<Vec<_>>();
+            words[5] = if words[5] == "abandon" {
+                "ability"
+            } else {
+                "abandon"
+            };
+            assert!(SecretShare::from_mnemonic(&words.join(" ")).is_err());
+        }
+    }
+}
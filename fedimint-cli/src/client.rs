@@ -13,6 +13,7 @@ use fedimint_client::ClientArc;
 use fedimint_core::config::FederationId;
 use fedimint_core::core::{ModuleInstanceId, ModuleKind, OperationId};
 use fedimint_core::encoding::Encodable;
+use fedimint_core::module::liabilities::latest_liabilities_statement_session;
 use fedimint_core::time::now;
 use fedimint_core::{Amount, BitcoinAmountOrAll, TieredMulti, TieredSummary};
 use fedimint_ln_client::{
@@ -21,7 +22,8 @@ use fedimint_ln_client::{
 };
 use fedimint_ln_common::contracts::ContractId;
 use fedimint_mint_client::{
-    MintClientModule, OOBNotes, SelectNotesWithAtleastAmount, SelectNotesWithExactAmount,
+    MintClientModule, OOBNotes, OOBNotesFragment, OOBNotesFragmentDecoder, OOBNotesMemo,
+    SelectNotesWithAtleastAmount, SelectNotesWithExactAmount,
 };
 use fedimint_wallet_client::{WalletClientModule, WithdrawState};
 use futures::StreamExt;
@@ -58,7 +60,13 @@ pub enum ClientCmd {
     /// Display wallet info (holdings, tiers)
     Info,
     /// Reissue notes received from a third party to avoid double spends
-    Reissue { oob_notes: OOBNotes },
+    Reissue {
+        oob_notes: OOBNotes,
+        /// Only show the fees of reissuing the notes without submitting a
+        /// transaction
+        #[clap(long)]
+        dry_run: bool,
+    },
     /// Prepare notes to send to a third party as a payment
     Spend {
         /// The amount of e-cash to spend
@@ -75,6 +83,16 @@ pub enum ClientCmd {
         /// belongs to should be included in the serialized notes
         #[clap(long)]
         include_invite: bool,
+        /// Note to the recipient describing the payment
+        #[clap(long)]
+        memo: Option<String>,
+        /// Data for the recipient's software, e.g. an order id
+        #[clap(long)]
+        metadata: Option<String>,
+        /// Only show the amount of the notes that would be selected without
+        /// spending them
+        #[clap(long)]
+        dry_run: bool,
     },
     /// Verifies the signatures of e-cash notes, but *not* if they have been
     /// spent already
@@ -87,6 +105,22 @@ pub enum ClientCmd {
         #[clap(required = true)]
         oob_notes: Vec<OOBNotes>,
     },
+    /// Splits e-cash notes into fragments to be shown as an animated QR code,
+    /// fragments after the first ones make up for those the receiver missed
+    SplitFragments {
+        oob_notes: OOBNotes,
+        /// Maximum number of payload bytes per fragment
+        #[clap(long, default_value_t = 200)]
+        max_part_len: usize,
+        /// Number of fragments to output, defaults to twice the number needed
+        #[clap(long)]
+        count: Option<usize>,
+    },
+    /// Reassembles e-cash notes from fragments created by `split-fragments`
+    CombineFragments {
+        #[clap(required = true)]
+        fragments: Vec<OOBNotesFragment>,
+    },
     /// Create a lightning invoice to receive payment via gateway
     LnInvoice {
         #[clap(long)]
@@ -111,6 +145,9 @@ pub enum ClientCmd {
         /// Will return immediately after funding the payment
         #[clap(long, action)]
         finish_in_background: bool,
+        /// Only show the fees of the payment without paying the invoice
+        #[clap(long)]
+        dry_run: bool,
     },
     /// Wait for a lightning payment to complete
     AwaitLnPay { operation_id: OperationId },
@@ -136,6 +173,10 @@ pub enum ClientCmd {
         amount: BitcoinAmountOrAll,
         #[clap(long)]
         address: bitcoin::Address,
+        /// Only show the fees of the withdrawal without submitting a
+        /// transaction
+        #[clap(long)]
+        dry_run: bool,
     },
     /// Upload the (encrypted) snapshot of mint notes to federation
     Backup {
@@ -145,6 +186,13 @@ pub enum ClientCmd {
         // TODO: Can we make it `*Map<String, String>` and avoid custom parsing?
         metadata: Vec<String>,
     },
+    /// Fetch the latest proof-of-liabilities statement signed by the
+    /// guardians and verify its reserves on-chain
+    ProofOfLiabilities {
+        /// Session the statement was produced at, defaults to the latest one
+        #[clap(long)]
+        session_index: Option<u64>,
+    },
     /// Discover the common api version to use to communicate with the
     /// federation
     #[clap(hide = true)]
@@ -184,11 +232,20 @@ pub async fn handle_command(
 ) -> anyhow::Result<serde_json::Value> {
     match command {
         ClientCmd::Info => get_note_summary(&client).await,
-        ClientCmd::Reissue { oob_notes } => {
+        ClientCmd::Reissue { oob_notes, dry_run } => {
             let amount = oob_notes.total_amount();
+            let memo = oob_notes.memo().cloned();
+            if let Some(memo) = &memo {
+                info!(memo = %memo.memo, metadata = %memo.metadata, "Reissuing e-cash with memo");
+            }
 
             let mint = client.get_first_module::<MintClientModule>();
 
+            if dry_run {
+                let preview = mint.preview_reissue_external_notes(oob_notes).await?;
+                return Ok(serde_json::to_value(preview).unwrap());
+            }
+
             let operation_id = mint.reissue_external_notes(oob_notes, ()).await?;
             let mut updates = mint
                 .subscribe_reissue_external_notes(operation_id)
@@ -204,25 +261,50 @@ pub async fn handle_command(
                 info!("Update: {update:?}");
             }
 
-            Ok(serde_json::to_value(amount).unwrap())
+            Ok(json!({
+                "amount_msat": amount,
+                "memo": memo,
+            }))
         }
         ClientCmd::Spend {
             amount,
             allow_overpay,
             timeout,
             include_invite,
+            memo,
+            metadata,
+            dry_run,
         } => {
+            if dry_run {
+                let mint_module = client.get_first_module::<MintClientModule>();
+                let preview = if allow_overpay {
+                    mint_module
+                        .preview_spend_notes_with_selector(&SelectNotesWithAtleastAmount, amount)
+                        .await?
+                } else {
+                    mint_module
+                        .preview_spend_notes_with_selector(&SelectNotesWithExactAmount, amount)
+                        .await?
+                };
+                return Ok(serde_json::to_value(preview).unwrap());
+            }
+
             warn!("The client will try to double-spend these notes after the duration specified by the --timeout option to recover any unclaimed e-cash.");
 
             let mint_module = client.get_first_module::<MintClientModule>();
             let timeout = Duration::from_secs(timeout);
+            let memo = (memo.is_some() || metadata.is_some()).then(|| OOBNotesMemo {
+                memo: memo.unwrap_or_default(),
+                metadata: metadata.unwrap_or_default(),
+            });
             let (operation, notes) = if allow_overpay {
                 let (operation, notes) = mint_module
-                    .spend_notes_with_selector(
+                    .spend_notes_with_selector_and_memo(
                         &SelectNotesWithAtleastAmount,
                         amount,
                         timeout,
                         include_invite,
+                        memo,
                         (),
                     )
                     .await?;
@@ -238,11 +320,12 @@ pub async fn handle_command(
                 (operation, notes)
             } else {
                 mint_module
-                    .spend_notes_with_selector(
+                    .spend_notes_with_selector_and_memo(
                         &SelectNotesWithExactAmount,
                         amount,
                         timeout,
                         include_invite,
+                        memo,
                         (),
                     )
                     .await?
@@ -254,6 +337,7 @@ pub async fn handle_command(
             }))
         }
         ClientCmd::Validate { oob_notes } => {
+            let memo = oob_notes.memo().cloned();
             let amount = client
                 .get_first_module::<MintClientModule>()
                 .validate_notes(oob_notes)
@@ -261,6 +345,7 @@ pub async fn handle_command(
 
             Ok(json!({
                 "amount_msat": amount,
+                "memo": memo,
             }))
         }
         ClientCmd::Split { oob_notes } => {
@@ -312,6 +397,42 @@ pub async fn handle_command(
                 "notes": combined_oob_notes,
             }))
         }
+        ClientCmd::SplitFragments {
+            oob_notes,
+            max_part_len,
+            count,
+        } => {
+            if max_part_len == 0 {
+                bail!("Fragments need to contain at least one byte");
+            }
+
+            let mut fragments = oob_notes.fragments(max_part_len).peekable();
+            let num_parts = fragments.peek().expect("Fragments are endless").num_parts;
+            let count = count.unwrap_or(2 * num_parts as usize);
+
+            let fragments = fragments
+                .take(count)
+                .map(|fragment| fragment.to_string())
+                .collect::<Vec<_>>();
+
+            Ok(json!({
+                "fragments": fragments,
+            }))
+        }
+        ClientCmd::CombineFragments { fragments } => {
+            let mut decoder = OOBNotesFragmentDecoder::new();
+
+            for fragment in fragments {
+                if let Some(notes) = decoder.add_fragment(fragment)? {
+                    return Ok(json!({
+                        "notes": notes,
+                    }));
+                }
+            }
+
+            let (recovered_parts, num_parts) = decoder.progress();
+            bail!("Not enough fragments, recovered {recovered_parts} of {num_parts} parts");
+        }
         ClientCmd::LnInvoice {
             amount,
             description,
@@ -358,6 +479,7 @@ pub async fn handle_command(
             amount,
             finish_in_background,
             lnurl_comment,
+            dry_run,
         } => {
             let bolt11 = get_invoice(&payment_info, amount, lnurl_comment).await?;
             info!("Paying invoice: {bolt11}");
@@ -365,6 +487,13 @@ pub async fn handle_command(
             lightning_module.select_active_gateway().await?;
 
             let gateway = lightning_module.select_active_gateway_opt().await;
+
+            if dry_run {
+                let preview = lightning_module
+                    .preview_pay_bolt11_invoice(gateway, bolt11)
+                    .await?;
+                return Ok(serde_json::to_value(preview).unwrap());
+            }
             let OutgoingLightningPayment {
                 payment_type,
                 contract_id,
@@ -530,7 +659,11 @@ pub async fn handle_command(
                 "operations": operations,
             }))
         }
-        ClientCmd::Withdraw { amount, address } => {
+        ClientCmd::Withdraw {
+            amount,
+            address,
+            dry_run,
+        } => {
             let wallet_module = client.get_first_module::<WalletClientModule>();
             let (amount, fees) = match amount {
                 // If the amount is "all", then we need to subtract the fees from
@@ -556,6 +689,17 @@ pub async fn handle_command(
             };
             let absolute_fees = fees.amount();
 
+            if dry_run {
+                let preview = wallet_module
+                    .preview_withdraw(address, amount, fees)
+                    .await?;
+                return Ok(json!({
+                    "amount_sat": amount.to_sat(),
+                    "fees_sat": absolute_fees.to_sat(),
+                    "transaction": preview,
+                }));
+            }
+
             info!("Attempting withdraw with fees: {fees:?}");
 
             let operation_id = wallet_module.withdraw(address, amount, fees, ()).await?;
@@ -584,6 +728,35 @@ pub async fn handle_command(
 
             unreachable!("Update stream ended without outcome");
         }
+        ClientCmd::ProofOfLiabilities { session_index } => {
+            let session_index = match session_index {
+                Some(session_index) => session_index,
+                None => latest_liabilities_statement_session(client.api().session_count().await?)
+                    .context("The federation has not completed any session yet")?,
+            };
+
+            let public_keys = client.api().broadcast_public_keys().await?;
+            let signed_statement = client
+                .api()
+                .liabilities_statement(session_index, &public_keys)
+                .await?;
+
+            let reserves = client
+                .get_first_module::<WalletClientModule>()
+                .verify_reserves(&signed_statement.statement)
+                .await?;
+
+            let liabilities = signed_statement.statement.total_liabilities();
+
+            Ok(json!({
+                "session_index": session_index,
+                "liabilities_msat": liabilities.msats,
+                "unspent_reserves_sat": reserves.unspent.to_sat(),
+                "spent_reserves": reserves.spent,
+                "fully_backed": Amount::from_sats(reserves.unspent.to_sat()) >= liabilities,
+                "signed_statement": signed_statement,
+            }))
+        }
         ClientCmd::DiscoverVersion => {
             Ok(json!({ "versions": client.discover_common_api_version().await? }))
         }
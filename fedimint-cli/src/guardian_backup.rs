@@ -0,0 +1,224 @@
+//! Splitting the encryption key of a [`GuardianConfigBackup`] into shares for
+//! trusted custodians and recovering the backup from them
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{format_err, Context};
+use fedimint_aead::{
+    decrypt, encrypt, encryption_key_from_bytes, get_encryption_key_bytes, random_salt,
+};
+use fedimint_bip39::shamir::{combine_shares, split_secret, SecretShare};
+use fedimint_core::api::GuardianConfigBackup;
+use fedimint_core::module::ApiAuth;
+use fedimint_server::config::io::{ENCRYPTED_EXT, PRIVATE_CONFIG, SALT_FILE};
+use rand::rngs::OsRng;
+
+/// Derives the key the private config is encrypted with from the password and
+/// the salt stored next to it
+type DeriveKey = fn(&str, &str) -> anyhow::Result<[u8; 32]>;
+
+/// Splits the key the private config of the backup is encrypted with into
+/// `shares` mnemonics of which `threshold` are required to decrypt it
+pub fn split_backup_key(
+    backup: &GuardianConfigBackup,
+    password: &str,
+    threshold: u8,
+    shares: u8,
+) -> anyhow::Result<Vec<String>> {
+    split_backup_key_with(
+        backup,
+        password,
+        threshold,
+        shares,
+        get_encryption_key_bytes,
+    )
+}
+
+fn split_backup_key_with(
+    backup: &GuardianConfigBackup,
+    password: &str,
+    threshold: u8,
+    shares: u8,
+    derive_key: DeriveKey,
+) -> anyhow::Result<Vec<String>> {
+    let salt = String::from_utf8(backup_file(backup, &salt_path())?)?;
+    let key = derive_key(password, &salt)?;
+
+    // Make sure the shares can actually decrypt the backup
+    decrypt_private_config(backup, &key).context("Wrong password for the backup")?;
+
+    Ok(split_secret(&key, threshold, shares, &mut OsRng)?
+        .iter()
+        .map(SecretShare::to_mnemonic)
+        .collect())
+}
+
+/// Decrypts the backup with the key reconstructed from the shares and
+/// encrypts it again with a new password, which also becomes the API password
+/// of the guardian, so it can be restored like any other backup
+pub fn recover_backup_with_shares(
+    backup: &GuardianConfigBackup,
+    shares: &[String],
+    new_password: &str,
+) -> anyhow::Result<GuardianConfigBackup> {
+    recover_backup_with_shares_with(backup, shares, new_password, get_encryption_key_bytes)
+}
+
+fn recover_backup_with_shares_with(
+    backup: &GuardianConfigBackup,
+    shares: &[String],
+    new_password: &str,
+    derive_key: DeriveKey,
+) -> anyhow::Result<GuardianConfigBackup> {
+    let shares = shares
+        .iter()
+        .map(|share| SecretShare::from_mnemonic(share))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let key = combine_shares(&shares)?;
+
+    let private_config =
+        decrypt_private_config(backup, &key).context("The shares do not belong to this backup")?;
+
+    // The API password has to match the password the config is encrypted with
+    let mut private_config: serde_json::Value = serde_json::from_slice(&private_config)?;
+    let api_auth = private_config
+        .get_mut("api_auth")
+        .context("The private config of the backup has no API password")?;
+    *api_auth = serde_json::to_value(ApiAuth(new_password.to_owned()))?;
+
+    let salt = random_salt();
+    let new_key = encryption_key_from_bytes(&derive_key(new_password, &salt)?)?;
+    let private_config_encrypted =
+        hex::encode(encrypt(serde_json::to_vec(&private_config)?, &new_key)?);
+
+    let mut archive = tar::Archive::new(backup.tar_archive_bytes.as_slice());
+    let mut builder = tar::Builder::new(Vec::new());
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        let data = if path == salt_path() {
+            salt.as_bytes().to_vec()
+        } else if path == private_config_path() {
+            private_config_encrypted.as_bytes().to_vec()
+        } else {
+            let mut data = vec![];
+            entry.read_to_end(&mut data)?;
+            data
+        };
+
+        let mut header = entry.header().clone();
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append(&header, data.as_slice())?;
+    }
+
+    Ok(GuardianConfigBackup {
+        tar_archive_bytes: builder.into_inner()?,
+    })
+}
+
+fn decrypt_private_config(
+    backup: &GuardianConfigBackup,
+    key: &[u8; 32],
+) -> anyhow::Result<Vec<u8>> {
+    let mut ciphertext = hex::decode(backup_file(backup, &private_config_path())?)?;
+
+    Ok(decrypt(&mut ciphertext, &encryption_key_from_bytes(key)?)?.to_vec())
+}
+
+fn backup_file(backup: &GuardianConfigBackup, name: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut archive = tar::Archive::new(backup.tar_archive_bytes.as_slice());
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        if entry.path()? == name {
+            let mut data = vec![];
+            entry.read_to_end(&mut data)?;
+            return Ok(data);
+        }
+    }
+
+    Err(format_err!("Backup is missing {}", name.display()))
+}
+
+fn salt_path() -> PathBuf {
+    PathBuf::from(SALT_FILE)
+}
+
+fn private_config_path() -> PathBuf {
+    PathBuf::from(PRIVATE_CONFIG).with_extension(ENCRYPTED_EXT)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::{sha256, Hash};
+    use fedimint_aead::{encrypt, encryption_key_from_bytes};
+    use fedimint_core::api::GuardianConfigBackup;
+
+    use super::{
+        backup_file, decrypt_private_config, private_config_path, recover_backup_with_shares_with,
+        split_backup_key_with,
+    };
+
+    /// Stands in for the slow password hashing
+    fn derive_key(password: &str, salt: &str) -> anyhow::Result<[u8; 32]> {
+        Ok(sha256::Hash::hash(format!("{password}{salt}").as_bytes()).into_inner())
+    }
+
+    fn backup(password: &str) -> GuardianConfigBackup {
+        let salt = "c2FsdHNhbHRzYWx0c2FsdA";
+        let key = encryption_key_from_bytes(&derive_key(password, salt).unwrap()).unwrap();
+        let private = br#"{"api_auth":"password","broadcast_secret_key":"secret"}"#;
+        let private = hex::encode(encrypt(private.to_vec(), &key).unwrap());
+
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in [
+            ("local.json", b"{}".as_slice()),
+            ("private.salt", salt.as_bytes()),
+            ("private.encrypt", private.as_bytes()),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(name).unwrap();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+
+        GuardianConfigBackup {
+            tar_archive_bytes: builder.into_inner().unwrap(),
+        }
+    }
+
+    #[test]
+    fn recovers_backup_from_shares() {
+        let backup = backup("password");
+        assert!(split_backup_key_with(&backup, "wrong", 2, 3, derive_key).is_err());
+
+        let shares = split_backup_key_with(&backup, "password", 2, 3, derive_key).unwrap();
+        assert!(recover_backup_with_shares_with(&backup, &shares[..1], "new", derive_key).is_err());
+
+        let recovered =
+            recover_backup_with_shares_with(&backup, &shares[1..], "new", derive_key).unwrap();
+        assert_eq!(
+            backup_file(&recovered, "local.json".as_ref()).unwrap(),
+            b"{}"
+        );
+
+        let shares = split_backup_key_with(&recovered, "new", 1, 1, derive_key).unwrap();
+        let key = fedimint_bip39::shamir::SecretShare::from_mnemonic(&shares[0])
+            .unwrap()
+            .value;
+        let private: serde_json::Value =
+            serde_json::from_slice(&decrypt_private_config(&recovered, &key).unwrap()).unwrap();
+        assert_eq!(
+            private,
+            serde_json::json!({"api_auth": "new", "broadcast_secret_key": "secret"})
+        );
+        assert!(backup_file(&recovered, &private_config_path()).is_ok());
+    }
+}
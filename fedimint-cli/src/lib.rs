@@ -1,5 +1,7 @@
 mod client;
 mod db_locked;
+mod guardian_backup;
+mod setup;
 mod utils;
 
 use core::fmt;
@@ -10,11 +12,11 @@ use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, UNIX_EPOCH};
 use std::{fs, result};
 
 use bip39::Mnemonic;
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use db_locked::LockedBuilder;
 use fedimint_aead::{encrypted_read, encrypted_write, get_encryption_key};
 use fedimint_bip39::Bip39RootSecretStrategy;
@@ -24,20 +26,23 @@ use fedimint_client::secret::{get_default_client_secret, RootSecretStrategy};
 use fedimint_client::{get_invite_code_from_db, Client, ClientArc, ClientBuilder};
 use fedimint_core::admin_client::WsAdminClient;
 use fedimint_core::api::{
-    FederationApiExt, FederationError, IRawFederationApi, InviteCode, WsFederationApi,
+    FederationApiExt, FederationError, GuardianConfigBackup, IRawFederationApi, InviteCode,
+    WsFederationApi,
 };
 use fedimint_core::config::{ClientConfig, FederationId};
 use fedimint_core::core::OperationId;
 use fedimint_core::db::{Database, DatabaseValue};
+use fedimint_core::module::audit::SessionAuditSummary;
 use fedimint_core::module::{ApiAuth, ApiRequestErased};
 use fedimint_core::util::{handle_version_hash_command, SafeUrl};
-use fedimint_core::{fedimint_build_code_version_env, task, PeerId, TieredMulti};
+use fedimint_core::{fedimint_build_code_version_env, task, PeerId, TieredMulti, TransactionId};
 use fedimint_ln_client::LightningClientInit;
 use fedimint_logging::{TracingSetup, LOG_CLIENT};
 use fedimint_mint_client::{MintClientInit, MintClientModule, SpendableNote};
 use fedimint_server::config::io::SALT_FILE;
 use fedimint_wallet_client::api::WalletFederationApi;
 use fedimint_wallet_client::{WalletClientInit, WalletClientModule};
+use futures::StreamExt;
 use rand::thread_rng;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
@@ -46,6 +51,7 @@ use tracing::{debug, info};
 use utils::parse_peer_id;
 
 use crate::client::ClientCmd;
+use crate::setup::SetupAdminCmd;
 
 /// Type of output the cli produces
 #[derive(Serialize)]
@@ -355,8 +361,90 @@ enum AdminCmd {
     /// Show an audit across all modules
     Audit,
 
+    /// Show the audit summaries persisted at the end of each session
+    AuditHistory {
+        /// First session to include
+        #[arg(long, default_value_t = 0)]
+        start: u64,
+        /// Session to stop at (exclusive), defaults to the current session
+        #[arg(long)]
+        end: Option<u64>,
+        /// Write the history as CSV with one row per session and module to
+        /// this file instead of printing it
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+
     /// Download guardian config to back it up
     GuardianConfigBackup,
+
+    /// Split the key a guardian config backup is encrypted with into mnemonic
+    /// shares for trusted custodians, any `threshold` of them can decrypt
+    /// the backup without the password
+    SplitBackupKey {
+        /// Backup downloaded with `guardian-config-backup`
+        #[arg(long)]
+        backup: PathBuf,
+        /// Number of shares required to decrypt the backup
+        #[arg(long)]
+        threshold: u8,
+        /// Number of shares to create
+        #[arg(long)]
+        shares: u8,
+    },
+
+    /// Decrypt a guardian config backup with the shares created by
+    /// `split-backup-key` and encrypt it with a new password, the result can
+    /// be restored with `fedimintd --restore-from-backup`
+    CombineBackupKey {
+        /// Backup downloaded with `guardian-config-backup`
+        #[arg(long)]
+        backup: PathBuf,
+        /// A share mnemonic, has to be given at least `threshold` times
+        #[arg(long = "share", required = true)]
+        shares: Vec<String>,
+        /// The password to encrypt the recovered backup with
+        #[arg(long)]
+        new_password: String,
+    },
+
+    /// Change the guardian password and re-encrypt the private config with it
+    ///
+    /// If fedimintd is started with `--password`, `FM_PASSWORD` or a password
+    /// in its config file, that has to be updated to the new password as
+    /// well, otherwise fedimintd refuses to start.
+    ChangePassword {
+        /// The password to authenticate with from now on
+        new_password: String,
+    },
+
+    /// List transactions that were submitted but not accepted yet and the
+    /// reasons recent transactions were rejected
+    PendingTransactions,
+
+    /// List the accepted items of a session with their decoded inputs and
+    /// outputs
+    SessionItems {
+        /// Index of the session, the current session if it has not completed
+        /// yet
+        session_index: u64,
+    },
+
+    /// Find the session and position in which a transaction was accepted
+    FindTransaction { txid: TransactionId },
+
+    /// Set up a new federation step by step, talks to the guardian directly
+    /// since there is no client config yet
+    Setup(SetupAdminArgs),
+}
+
+#[derive(Debug, Clone, Args)]
+struct SetupAdminArgs {
+    /// API URL of the guardian to set up
+    endpoint: SafeUrl,
+
+    #[clap(subcommand)]
+    subcommand: SetupAdminCmd,
 }
 
 #[derive(Debug, Clone, Subcommand)]
@@ -644,6 +732,13 @@ impl FedimintCli {
                 let mnemonic = Mnemonic::from_str(&mnemonic).map_err_cli_general()?;
                 let client = self.client_recover(&cli, mnemonic, invite_code).await?;
 
+                let mut progress_updates = client.subscribe_to_recovery_progress();
+                task::spawn("recovery progress", async move {
+                    while let Some((module_instance_id, progress)) = progress_updates.next().await {
+                        info!(module_instance_id, %progress, "Recovery progress");
+                    }
+                });
+
                 // TODO: until we implement recovery for other modules we can't really wait
                 // for more than this one
                 debug!("Waiting for mint module recovery to finish");
@@ -685,6 +780,30 @@ impl FedimintCli {
                         .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
                 ))
             }
+            Command::Admin(AdminCmd::AuditHistory { start, end, csv }) => {
+                let client = self.client_open(&cli).await?;
+
+                let end = match end {
+                    Some(end) => end,
+                    None => client.api().session_count().await?,
+                };
+
+                let history = cli
+                    .admin_client(client.get_config())?
+                    .audit_history(start, end, cli.auth()?)
+                    .await?;
+
+                match csv {
+                    Some(path) => {
+                        fs::write(path, audit_history_csv(&history)).map_err_cli_io()?;
+                        Ok(CliOutput::Raw(serde_json::Value::Null))
+                    }
+                    None => Ok(CliOutput::Raw(
+                        serde_json::to_value(history)
+                            .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                    )),
+                }
+            }
             Command::Admin(AdminCmd::GuardianConfigBackup) => {
                 let client = self.client_open(&cli).await?;
 
@@ -697,6 +816,92 @@ impl FedimintCli {
                         .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
                 ))
             }
+            Command::Admin(AdminCmd::SplitBackupKey {
+                backup,
+                threshold,
+                shares,
+            }) => {
+                let backup = read_guardian_config_backup(&backup)?;
+                let shares =
+                    guardian_backup::split_backup_key(&backup, &cli.auth()?.0, threshold, shares)
+                        .map_err_cli_general()?;
+
+                Ok(CliOutput::Raw(json!({
+                    "threshold": threshold,
+                    "shares": shares,
+                })))
+            }
+            Command::Admin(AdminCmd::CombineBackupKey {
+                backup,
+                shares,
+                new_password,
+            }) => {
+                let backup = read_guardian_config_backup(&backup)?;
+                let recovered =
+                    guardian_backup::recover_backup_with_shares(&backup, &shares, &new_password)
+                        .map_err_cli_general()?;
+
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(recovered)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid backup")?,
+                ))
+            }
+            Command::Admin(AdminCmd::ChangePassword { new_password }) => {
+                let client = self.client_open(&cli).await?;
+
+                cli.admin_client(client.get_config())?
+                    .change_password(ApiAuth(new_password), cli.auth()?)
+                    .await?;
+                Ok(CliOutput::Raw(serde_json::Value::Null))
+            }
+            Command::Admin(AdminCmd::Setup(SetupAdminArgs {
+                endpoint,
+                subcommand,
+            })) => Ok(CliOutput::Raw(
+                setup::handle_command(subcommand, WsAdminClient::new(endpoint), || Ok(cli.auth()?))
+                    .await
+                    .map_err_cli_general()?,
+            )),
+            Command::Admin(AdminCmd::PendingTransactions) => {
+                let client = self.client_open(&cli).await?;
+
+                let pending_transactions = cli
+                    .admin_client(client.get_config())?
+                    .pending_transactions(cli.auth()?)
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(pending_transactions)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::SessionItems { session_index }) => {
+                let client = self.client_open(&cli).await?;
+
+                let session_items = cli
+                    .admin_client(client.get_config())?
+                    .session_items(session_index, cli.auth()?)
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(session_items)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::FindTransaction { txid }) => {
+                let client = self.client_open(&cli).await?;
+
+                let location = cli
+                    .admin_client(client.get_config())?
+                    .find_transaction(txid, cli.auth()?)
+                    .await?
+                    .ok_or_cli_msg(
+                        CliErrorKind::GeneralFailure,
+                        "transaction was not accepted by the federation",
+                    )?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(location)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
             Command::Dev(DevCmd::Api {
                 method,
                 params,
@@ -850,6 +1055,16 @@ impl FedimintCli {
     }
 }
 
+/// Reads a backup as printed by `admin guardian-config-backup`
+fn read_guardian_config_backup(path: &Path) -> CliResult<GuardianConfigBackup> {
+    let backup = fs::read_to_string(path).map_err_cli_io()?;
+
+    serde_json::from_str(&backup).map_err_cli_msg(
+        CliErrorKind::SerializationError,
+        "invalid guardian config backup",
+    )
+}
+
 fn salt_from_file_path(file_path: &Path) -> PathBuf {
     file_path
         .parent()
@@ -857,6 +1072,31 @@ fn salt_from_file_path(file_path: &Path) -> PathBuf {
         .join(SALT_FILE)
 }
 
+/// Render audit summaries as CSV with one row per session and module
+fn audit_history_csv(history: &[SessionAuditSummary]) -> String {
+    let mut csv = "session_index,timestamp,module_instance_id,kind,assets_msat,liabilities_msat,net_assets_msat\n".to_string();
+    for summary in history {
+        let timestamp = summary
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        for (module_instance_id, module_summary) in &summary.module_summaries {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                summary.session_index,
+                timestamp,
+                module_instance_id,
+                module_summary.kind,
+                module_summary.assets_msat,
+                module_summary.liabilities_msat,
+                module_summary.net_assets(),
+            ));
+        }
+    }
+    csv
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct LnInvoiceResponse {
@@ -900,3 +1140,36 @@ fn metadata_from_clap_cli_test() {
         assert_eq!(metadata_from_clap_cli(args).unwrap(), expected);
     }
 }
+
+#[test]
+fn audit_history_csv_test() {
+    let history = vec![SessionAuditSummary {
+        session_index: 3,
+        timestamp: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        module_summaries: BTreeMap::from([
+            (
+                0,
+                fedimint_core::module::audit::ModuleAuditSummary {
+                    kind: "ln".to_string(),
+                    assets_msat: 0,
+                    liabilities_msat: 1_000,
+                },
+            ),
+            (
+                1,
+                fedimint_core::module::audit::ModuleAuditSummary {
+                    kind: "mint".to_string(),
+                    assets_msat: 5_000,
+                    liabilities_msat: 2_000,
+                },
+            ),
+        ]),
+    }];
+
+    assert_eq!(
+        audit_history_csv(&history),
+        "session_index,timestamp,module_instance_id,kind,assets_msat,liabilities_msat,net_assets_msat\n\
+         3,1700000000,0,ln,0,1000,-1000\n\
+         3,1700000000,1,mint,5000,2000,3000\n"
+    );
+}
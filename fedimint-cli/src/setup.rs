@@ -0,0 +1,326 @@
+//! Commands for guardians setting up a federation without the web UI
+//!
+//! Each command wraps one step of the config gen API of a guardian that has
+//! not started consensus yet. Progress is reported on stderr, so stdout only
+//! contains the JSON result. It is printed rather than logged since the CLI
+//! only logs warnings unless run with `--verbose`.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Context};
+use bitcoin_hashes::sha256;
+use clap::Subcommand;
+use fedimint_core::admin_client::{
+    ConfigGenConnectionsRequest, ConfigGenParamsRequest, WsAdminClient,
+};
+use fedimint_core::api::ServerStatus;
+use fedimint_core::config::{ConfigGenModuleParams, ServerModuleConfigGenParamsRegistry};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::module::ApiAuth;
+use fedimint_core::task::sleep;
+use fedimint_core::util::SafeUrl;
+use fedimint_core::PeerId;
+use fedimint_server::config::setup::merge_module_params;
+use serde_json::json;
+
+use crate::utils::parse_peer_id;
+
+/// How long we wait for a guardian to reach the status a step leads to
+const STATUS_POLL_ATTEMPTS: usize = 600;
+
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum SetupAdminCmd {
+    /// Show the setup status of the guardian
+    Status,
+
+    /// Set the guardian password given with `--password`
+    SetPassword,
+
+    /// Set our guardian name and the leader to send our connection info to,
+    /// the leader omits `--leader-api-url`
+    SetLocalParams {
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        leader_api_url: Option<SafeUrl>,
+    },
+
+    /// List the guardians that joined the leader so far
+    ListPeers,
+
+    /// Set the federation meta and module params on top of the defaults of
+    /// the guardian, followers only use their local module params
+    SetConfigGenParams {
+        /// Meta value passed to the clients, as `key=value`
+        #[arg(long, value_parser = parse_key_value)]
+        meta: Vec<(String, String)>,
+        /// Consensus params of a module, as `<module id>=<json>`
+        #[arg(long, value_parser = parse_module_params)]
+        module_consensus: Vec<(ModuleInstanceId, serde_json::Value)>,
+        /// Local params of a module, as `<module id>=<json>`
+        #[arg(long, value_parser = parse_module_params)]
+        module_local: Vec<(ModuleInstanceId, serde_json::Value)>,
+    },
+
+    /// Show the params all guardians will run DKG with
+    ConsensusParams,
+
+    /// Run the distributed key generation and wait for it to complete
+    RunDkg,
+
+    /// Show the config hash of every guardian as computed by this guardian,
+    /// each guardian should see the same hashes
+    ConfigHashes,
+
+    /// Compare our config hashes with the ones other guardians shared and
+    /// mark the configs as verified if they match
+    VerifyConfigHashes {
+        /// The hash a guardian reported for itself, as `<peer id>=<hash>`
+        #[arg(long = "expect", value_parser = parse_peer_hash, required = true)]
+        expected: Vec<(PeerId, sha256::Hash)>,
+    },
+
+    /// Start consensus and wait until it is running
+    StartConsensus,
+
+    /// Discard the generated configs and restart the setup
+    RestartSetup,
+}
+
+pub async fn handle_command(
+    command: SetupAdminCmd,
+    client: WsAdminClient,
+    auth: impl Fn() -> anyhow::Result<ApiAuth>,
+) -> anyhow::Result<serde_json::Value> {
+    match command {
+        SetupAdminCmd::Status => Ok(serde_json::to_value(client.status().await?)?),
+        SetupAdminCmd::SetPassword => {
+            client.set_password(auth()?).await?;
+            eprintln!("Password set");
+            Ok(json!(null))
+        }
+        SetupAdminCmd::SetLocalParams {
+            name,
+            leader_api_url,
+        } => {
+            let is_leader = leader_api_url.is_none();
+
+            client
+                .set_config_gen_connections(
+                    ConfigGenConnectionsRequest {
+                        our_name: name.clone(),
+                        leader_api_url,
+                    },
+                    auth()?,
+                )
+                .await?;
+
+            if is_leader {
+                eprintln!("Set up {name} as leader, followers can join now");
+            } else {
+                eprintln!("Set up {name} as follower and sent our connection info to the leader");
+            }
+
+            Ok(json!(null))
+        }
+        SetupAdminCmd::ListPeers => {
+            let peers = client.get_config_gen_peers().await?;
+
+            eprintln!("{} guardians joined", peers.len());
+
+            Ok(json!(peers
+                .into_iter()
+                .map(|peer| json!({
+                    "name": peer.name,
+                    "api_url": peer.api_url,
+                    "p2p_url": peer.p2p_url,
+                    "status": peer.status,
+                }))
+                .collect::<Vec<_>>()))
+        }
+        SetupAdminCmd::SetConfigGenParams {
+            meta,
+            module_consensus,
+            module_local,
+        } => {
+            let auth = auth()?;
+            let defaults = client.get_default_config_gen_params(auth.clone()).await?;
+
+            let mut overrides = BTreeMap::<ModuleInstanceId, ConfigGenModuleParams>::new();
+            for (id, consensus) in module_consensus {
+                overrides.entry(id).or_default().consensus = Some(consensus);
+            }
+            for (id, local) in module_local {
+                overrides.entry(id).or_default().local = Some(local);
+            }
+
+            let mut overrides_registry = ServerModuleConfigGenParamsRegistry::default();
+            for (id, params) in overrides {
+                let (kind, _) = defaults
+                    .modules
+                    .get_with_kind(id)
+                    .ok_or_else(|| format_err!("The guardian has no module with id {id}"))?;
+                overrides_registry.register_module(id, kind.clone(), params);
+            }
+
+            let request = ConfigGenParamsRequest {
+                meta: defaults.meta.into_iter().chain(meta).collect(),
+                modules: merge_module_params(defaults.modules, &overrides_registry),
+            };
+
+            client.set_config_gen_params(request.clone(), auth).await?;
+
+            eprintln!("Config gen params set");
+
+            Ok(serde_json::to_value(request)?)
+        }
+        SetupAdminCmd::ConsensusParams => {
+            let params = client.consensus_config_gen_params().await?;
+
+            eprintln!(
+                "We are peer {} of {} guardians",
+                params.our_current_id,
+                params.consensus.peers.len()
+            );
+
+            Ok(serde_json::to_value(params)?)
+        }
+        SetupAdminCmd::RunDkg => {
+            client.run_dkg(auth()?).await?;
+
+            eprintln!("Running DKG, this can take a while");
+
+            match await_status(
+                &client,
+                &[
+                    ServerStatus::VerifyingConfigs,
+                    ServerStatus::ConfigGenFailed,
+                ],
+            )
+            .await?
+            {
+                ServerStatus::VerifyingConfigs => {
+                    eprintln!("DKG complete, compare the config hashes with the other guardians");
+                    Ok(json!(null))
+                }
+                _ => bail!("DKG failed, restart the setup to try again"),
+            }
+        }
+        SetupAdminCmd::ConfigHashes => {
+            let hashes = client.get_verify_config_hash(auth()?).await?;
+
+            for (peer, hash) in &hashes {
+                eprintln!("Peer {peer}: {hash}");
+            }
+
+            Ok(serde_json::to_value(hashes)?)
+        }
+        SetupAdminCmd::VerifyConfigHashes { expected } => {
+            let auth = auth()?;
+            let hashes = client.get_verify_config_hash(auth.clone()).await?;
+
+            let mut mismatches = vec![];
+            for (peer, expected_hash) in expected {
+                match hashes.get(&peer) {
+                    Some(hash) if *hash == expected_hash => {
+                        eprintln!("Peer {peer}: hash matches");
+                    }
+                    Some(hash) => {
+                        eprintln!("Peer {peer}: expected {expected_hash} but got {hash}");
+                        mismatches.push(peer);
+                    }
+                    None => {
+                        eprintln!("Peer {peer}: unknown peer");
+                        mismatches.push(peer);
+                    }
+                }
+            }
+
+            if !mismatches.is_empty() {
+                bail!("Config hashes of peers {mismatches:?} do not match, do not start consensus");
+            }
+
+            client.verified_configs(auth).await?;
+
+            eprintln!("Configs verified, consensus can be started");
+
+            Ok(json!(null))
+        }
+        SetupAdminCmd::StartConsensus => {
+            client.start_consensus(auth()?).await?;
+
+            eprintln!("Starting consensus");
+
+            await_status(&client, &[ServerStatus::ConsensusRunning]).await?;
+
+            eprintln!("Consensus is running");
+
+            Ok(json!(null))
+        }
+        SetupAdminCmd::RestartSetup => {
+            client.restart_federation_setup(auth()?).await?;
+
+            eprintln!("Setup restarted, waiting for the other guardians to restart as well");
+
+            await_status(
+                &client,
+                &[
+                    ServerStatus::AwaitingPassword,
+                    ServerStatus::SharingConfigGenParams,
+                ],
+            )
+            .await?;
+
+            eprintln!("Setup can start over");
+
+            Ok(json!(null))
+        }
+    }
+}
+
+/// Polls the status of the guardian until it reaches one of `expected`, the
+/// guardian may be unreachable in between when it switches to consensus
+async fn await_status(
+    client: &WsAdminClient,
+    expected: &[ServerStatus],
+) -> anyhow::Result<ServerStatus> {
+    let mut last_status = None;
+
+    for _ in 0..STATUS_POLL_ATTEMPTS {
+        if let Ok(status) = client.status().await {
+            if expected.contains(&status.server) {
+                return Ok(status.server);
+            }
+
+            if last_status.as_ref() != Some(&status.server) {
+                eprintln!("Status: {:?}", status.server);
+                last_status = Some(status.server);
+            }
+        }
+
+        sleep(STATUS_POLL_INTERVAL).await;
+    }
+
+    bail!("Guardian did not reach any of {expected:?}")
+}
+
+fn parse_key_value(s: &str) -> anyhow::Result<(String, String)> {
+    let (key, value) = s.split_once('=').context("Expected key=value")?;
+
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+fn parse_module_params(s: &str) -> anyhow::Result<(ModuleInstanceId, serde_json::Value)> {
+    let (id, params) = s.split_once('=').context("Expected <module id>=<json>")?;
+
+    Ok((id.parse()?, serde_json::from_str(params)?))
+}
+
+fn parse_peer_hash(s: &str) -> anyhow::Result<(PeerId, sha256::Hash)> {
+    let (peer, hash) = s.split_once('=').context("Expected <peer id>=<hash>")?;
+
+    Ok((parse_peer_id(peer)?, hash.parse()?))
+}
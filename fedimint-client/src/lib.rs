@@ -101,7 +101,7 @@ use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::module::{
     ApiVersion, MultiApiVersion, SupportedApiVersionsSummary, SupportedCoreApiVersions,
-    SupportedModuleApiVersions,
+    SupportedModuleApiVersions, TransactionItemAmount,
 };
 use fedimint_core::task::{MaybeSend, MaybeSync, TaskGroup};
 use fedimint_core::transaction::Transaction;
@@ -140,7 +140,8 @@ use crate::sm::{
 };
 use crate::transaction::{
     tx_submission_sm_decoder, ClientInput, ClientOutput, TransactionBuilder,
-    TransactionBuilderBalance, TxSubmissionContext, TxSubmissionStates,
+    TransactionBuilderBalance, TransactionItemDirection, TransactionPreview,
+    TransactionPreviewItem, TxSubmissionContext, TxSubmissionStates,
     TRANSACTION_SUBMISSION_MODULE_INSTANCE,
 };
 
@@ -980,12 +981,12 @@ impl Client {
             partial_transaction.outputs.extend(change_outputs);
         }
 
-        assert!(
-            matches!(
-                self.transaction_builder_balance(&partial_transaction),
-                TransactionBuilderBalance::Balanced
-            ),
-            "Transaction is balanced after the previous two operations"
+        // Fees that depend on the notes issued as change may make it impossible
+        // to balance the transaction exactly
+        let balance = self.transaction_builder_balance(&partial_transaction);
+        ensure!(
+            matches!(balance, TransactionBuilderBalance::Balanced),
+            "Could not balance transaction after adding funding and change: {balance:?}"
         );
 
         let (tx, states) = partial_transaction.build(&self.secp_ctx, thread_rng());
@@ -1061,6 +1062,78 @@ impl Client {
         }
     }
 
+    /// Adds funding and change to `tx_builder` like
+    /// [`Self::finalize_and_submit_transaction`] and returns the amounts and
+    /// fees of all items of the resulting transaction without submitting it.
+    ///
+    /// The database transaction in which funding and change are created is
+    /// never committed, so no notes are reserved. A later submission of the
+    /// same builder may select different notes and thus pay slightly different
+    /// fees.
+    pub async fn preview_transaction(
+        &self,
+        tx_builder: TransactionBuilder,
+    ) -> anyhow::Result<TransactionPreview> {
+        let num_inputs = tx_builder.inputs.len();
+        let num_outputs = tx_builder.outputs.len();
+
+        let mut dbtx = self.db().begin_transaction().await;
+        let (transaction, _, _) = self
+            .finalize_transaction(&mut dbtx.to_ref_nc(), OperationId::new_random(), tx_builder)
+            .await?;
+        dbtx.ignore_uncommitted();
+
+        let preview_item = |module_instance_id: ModuleInstanceId,
+                            direction: TransactionItemDirection,
+                            amount: TransactionItemAmount,
+                            idx: usize| {
+            let (module_kind, _) = self
+                .modules
+                .get_with_kind(module_instance_id)
+                .expect("Module instance not found");
+
+            TransactionPreviewItem {
+                module_instance_id,
+                module_kind: module_kind.clone(),
+                direction,
+                amount: amount.amount,
+                fee: amount.fee,
+                added_by_client: match direction {
+                    TransactionItemDirection::Input => num_inputs <= idx,
+                    TransactionItemDirection::Output => num_outputs <= idx,
+                },
+            }
+        };
+
+        let inputs = transaction.inputs.iter().enumerate().map(|(idx, input)| {
+            let amount = self
+                .get_module(input.module_instance_id())
+                .input_amount(input)
+                .expect("We only build transactions with input versions supported by the module");
+            preview_item(
+                input.module_instance_id(),
+                TransactionItemDirection::Input,
+                amount,
+                idx,
+            )
+        });
+
+        let outputs = transaction.outputs.iter().enumerate().map(|(idx, output)| {
+            let amount = self
+                .get_module(output.module_instance_id())
+                .output_amount(output)
+                .expect("We only build transactions with output versions supported by the module");
+            preview_item(
+                output.module_instance_id(),
+                TransactionItemDirection::Output,
+                amount,
+                idx,
+            )
+        });
+
+        Ok(TransactionPreview::new(inputs.chain(outputs).collect()))
+    }
+
     async fn finalize_and_submit_transaction_inner(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -1478,6 +1551,8 @@ impl Client {
     /// This will block until the recovery task is done with recoveries.
     /// Returns success if all recovery tasks are complete (success case),
     /// or an error if some modules could not complete the recovery at the time.
+    /// The progress in the meantime can be followed with
+    /// [`Self::subscribe_to_recovery_progress`].
     ///
     /// A bit of a heavy approach.
     pub async fn wait_for_all_recoveries(&self) -> anyhow::Result<()> {
@@ -1494,6 +1569,35 @@ impl Client {
         Ok(())
     }
 
+    /// Stream of the recovery progress of every module that is recovering
+    ///
+    /// Yields the current progress of all modules first and then every update,
+    /// ends once the recovery task is done.
+    pub fn subscribe_to_recovery_progress(
+        &self,
+    ) -> BoxStream<'static, (ModuleInstanceId, RecoveryProgress)> {
+        Box::pin(
+            tokio_stream::wrappers::WatchStream::new(
+                self.client_recovery_progress_receiver.clone(),
+            )
+            .scan(
+                BTreeMap::<ModuleInstanceId, RecoveryProgress>::new(),
+                |last_progress, progress| {
+                    // only report the modules whose progress changed
+                    let updates = progress
+                        .into_iter()
+                        .filter(|(module_instance_id, progress)| {
+                            last_progress.insert(*module_instance_id, *progress) != Some(*progress)
+                        })
+                        .collect::<Vec<_>>();
+
+                    futures::future::ready(Some(futures::stream::iter(updates)))
+                },
+            )
+            .flatten(),
+        )
+    }
+
     pub async fn wait_for_module_kind_recovery(
         &self,
         module_kind: ModuleKind,
@@ -1614,7 +1718,7 @@ impl Client {
 
             info!(
                 module_instance_id,
-                progress = format!("{}/{}", progress.complete, progress.total),
+                progress = %progress,
                 "Recovery progress"
             );
 
@@ -12,6 +12,7 @@ use fedimint_core::task::{MaybeSend, MaybeSync};
 use fedimint_core::transaction::Transaction;
 use fedimint_core::{apply, async_trait_maybe_send, OutPoint};
 use fedimint_logging::LOG_CLIENT_RECOVERY;
+use futures::channel::oneshot;
 use futures::{Stream, StreamExt as _};
 use rand::{thread_rng, Rng as _};
 use serde::{Deserialize, Serialize};
@@ -37,6 +38,19 @@ impl RecoveryFromHistoryCommon {
             end_session,
         }
     }
+
+    /// The sessions processed out of all sessions to recover from
+    fn progress(&self, found_items: u64) -> RecoveryProgress {
+        RecoveryProgress {
+            complete: (self.next_session - self.start_session)
+                .try_into()
+                .unwrap_or(u32::MAX),
+            total: (self.end_session - self.start_session)
+                .try_into()
+                .unwrap_or(u32::MAX),
+            found_items,
+        }
+    }
 }
 
 /// Module specific logic for [`ClientModuleRecoverArgs::recover_from_history`]
@@ -206,6 +220,14 @@ pub trait RecoveryFromHistory: std::fmt::Debug + MaybeSend + MaybeSync + Clone {
         Ok(())
     }
 
+    /// Number of items (e.g. e-cash notes) recovered so far, reported as part
+    /// of the [`RecoveryProgress`]
+    ///
+    /// Default implementation reports none.
+    fn found_items(&self) -> u64 {
+        0
+    }
+
     /// Finalize the recovery converting the tracked state to to final
     /// changes in the database.
     ///
@@ -239,10 +261,12 @@ where
     where
         Recovery: RecoveryFromHistory<Init = Init> + std::fmt::Debug,
     {
-        /// Fetch epochs in a given range and send them over `sender`
+        /// Fetch and decode sessions in a given range, returning them in order
         ///
-        /// Since WASM's `spawn` does not support join handles, we indicate
-        /// errors via `sender` itself.
+        /// Every session is fetched in a task of its own, so up to
+        /// `PARALLELISM_LEVEL` sessions are downloaded and decoded in parallel
+        /// while they are processed one by one. Since WASM's `spawn` does not
+        /// support join handles, the sessions are sent back over a channel.
         fn fetch_block_stream<'a>(
             api: DynGlobalApi,
             core_api_version: ApiVersion,
@@ -250,56 +274,92 @@ where
             epoch_range: ops::Range<u64>,
         ) -> impl futures::Stream<Item = (u64, Vec<AcceptedItem>)> + 'a {
             // How many request for blocks to run in parallel (streaming).
-            const PARALLISM_LEVEL: usize = 8;
-            const VERSION_THAT_INTRODUCED_GET_SESSION_STATUS: ApiVersion =
-                ApiVersion { major: 0, minor: 1 };
+            const PARALLELISM_LEVEL: usize = 32;
 
             futures::stream::iter(epoch_range.clone())
                 .map(move |session_idx| {
-                    let api = api.clone();
-                    let decoders = decoders.clone();
-                    Box::pin(async move {
-                        info!(session_idx, "Fetching epoch");
-
-                        let mut retry_sleep = Duration::from_millis(10);
-                        let block = loop {
-                            info!(target: LOG_CLIENT_RECOVERY, session_idx, "Awaiting signed block");
-
-                            let items_res = if core_api_version < VERSION_THAT_INTRODUCED_GET_SESSION_STATUS {
-                                api.await_block(session_idx, &decoders).await.map(|s| s.items)
-                            } else {
-                                api.get_session_status(session_idx, &decoders).await.map(|s| match s {
-                                    SessionStatus::Initial => panic!("Federation missing session that existed when we started recovery"),
-                                    SessionStatus::Pending(items) => items,
-                                    SessionStatus::Complete(s) => s.items,
-                                })
-                            };
-
-                            match items_res {
-                                Ok(block) => break block,
-                                Err(e) => {
-                                    info!(e = %e, session_idx, "Error trying to fetch signed block");
-                                    // We don't want PARALLISM_LEVEL tasks hammering Federation
-                                    // with requests, so max sleep is significant
-                                    const MAX_SLEEP: Duration = Duration::from_secs(120);
-                                    if retry_sleep <= MAX_SLEEP {
-                                        retry_sleep = retry_sleep
-                                            + thread_rng().gen_range(Duration::ZERO..=retry_sleep);
-                                    }
-                                    fedimint_core::task::sleep(cmp::min(retry_sleep, MAX_SLEEP))
-                                        .await;
-                                }
-                            }
-                        };
+                    let (block_tx, block_rx) = oneshot::channel();
+
+                    fedimint_core::task::spawn(
+                        "recovery fetch session",
+                        fetch_block(
+                            api.clone(),
+                            core_api_version,
+                            decoders.clone(),
+                            session_idx,
+                            block_tx,
+                        ),
+                    );
+
+                    async move {
+                        let block = block_rx
+                            .await
+                            .expect("Session fetch task never exits without sending the session");
 
                         (session_idx, block)
-                    })
+                    }
                 })
-                .buffered(PARALLISM_LEVEL)
+                .buffered(PARALLELISM_LEVEL)
         }
 
-        /// Make enough progress to justify saving a state snapshot
+        /// Fetch a single session, retrying until it succeeds or `block_tx`
+        /// is dropped
+        async fn fetch_block(
+            api: DynGlobalApi,
+            core_api_version: ApiVersion,
+            decoders: ModuleDecoderRegistry,
+            session_idx: u64,
+            block_tx: oneshot::Sender<Vec<AcceptedItem>>,
+        ) {
+            const VERSION_THAT_INTRODUCED_GET_SESSION_STATUS: ApiVersion =
+                ApiVersion { major: 0, minor: 1 };
 
+            let mut retry_sleep = Duration::from_millis(10);
+            let block = loop {
+                if block_tx.is_canceled() {
+                    // recovery was aborted
+                    return;
+                }
+
+                trace!(target: LOG_CLIENT_RECOVERY, session_idx, "Awaiting signed block");
+
+                let items_res = if core_api_version < VERSION_THAT_INTRODUCED_GET_SESSION_STATUS {
+                    api.await_block(session_idx, &decoders)
+                        .await
+                        .map(|s| s.items)
+                } else {
+                    api.get_session_status(session_idx, &decoders)
+                        .await
+                        .map(|s| match s {
+                            SessionStatus::Initial => panic!(
+                                "Federation missing session that existed when we started recovery"
+                            ),
+                            SessionStatus::Pending(items) => items,
+                            SessionStatus::Complete(s) => s.items,
+                        })
+                };
+
+                match items_res {
+                    Ok(block) => break block,
+                    Err(e) => {
+                        info!(e = %e, session_idx, "Error trying to fetch signed block");
+                        // We don't want PARALLELISM_LEVEL tasks hammering Federation
+                        // with requests, so max sleep is significant
+                        const MAX_SLEEP: Duration = Duration::from_secs(120);
+                        if retry_sleep <= MAX_SLEEP {
+                            retry_sleep =
+                                retry_sleep + thread_rng().gen_range(Duration::ZERO..=retry_sleep);
+                        }
+                        fedimint_core::task::sleep(cmp::min(retry_sleep, MAX_SLEEP)).await;
+                    }
+                }
+            };
+
+            // the receiver is gone if the recovery was aborted
+            let _ = block_tx.send(block);
+        }
+
+        /// Make enough progress to justify saving a state snapshot
         async fn make_progress<'a, Init, Recovery: RecoveryFromHistory<Init = Init>>(
             client_ctx: &ClientContext<<Init as ClientModuleInit>::Module>,
             common_state: &mut RecoveryFromHistoryCommon,
@@ -309,14 +369,13 @@ where
         where
             Init: ClientModuleInit,
         {
-            /// the amount of blocks after which we save progress in the
-            /// database (return from this function)
-            ///
-            /// TODO: Instead of a fixed range of session
-            /// indexes, make the loop time-based, so the amount of
-            /// progress we can loose on termination is time-bound,
-            /// and thus more adaptive.
-            const PROGRESS_SNAPSHOT_BLOCKS: u64 = 10;
+            /// The time after which we save progress in the database (return
+            /// from this function), bounding the progress we can lose on
+            /// termination
+            const PROGRESS_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+            /// The maximum amount of blocks processed before we save progress,
+            /// in case the clock is not reliable
+            const PROGRESS_SNAPSHOT_BLOCKS: u64 = 1000;
 
             let block_range = common_state.next_session
                 ..cmp::min(
@@ -332,6 +391,8 @@ where
                 "Processing blocks"
             );
 
+            let start = fedimint_core::time::now();
+
             for _ in block_range {
                 let Some((session_idx, accepted_items)) = block_stream.next().await else {
                     break;
@@ -343,6 +404,13 @@ where
                     .await?;
 
                 common_state.next_session += 1;
+
+                if fedimint_core::time::now()
+                    .duration_since(start)
+                    .map_or(false, |elapsed| PROGRESS_SNAPSHOT_INTERVAL <= elapsed)
+                {
+                    break;
+                }
             }
 
             Ok(())
@@ -410,6 +478,10 @@ where
         let client_ctx = self.context();
 
         while common_state.next_session < common_state.end_session {
+            // the final progress is reported once the recovery completes
+            self.update_recovery_progress(common_state.progress(state.found_items()))
+                .await;
+
             make_progress(
                 &client_ctx,
                 &mut common_state,
@@ -421,16 +493,6 @@ where
             let mut dbtx = db.begin_transaction().await;
             state.store_dbtx(&mut dbtx.to_ref_nc(), &common_state).await;
             dbtx.commit_tx().await;
-
-            self.update_recovery_progress(RecoveryProgress {
-                complete: (common_state.next_session - common_state.start_session)
-                    .try_into()
-                    .unwrap_or(u32::MAX),
-                total: (common_state.end_session - common_state.start_session)
-                    .try_into()
-                    .unwrap_or(u32::MAX),
-            })
-            .await
         }
 
         debug!(
@@ -25,8 +25,8 @@ use secp256k1_zkp::PublicKey;
 use self::init::ClientModuleInit;
 use crate::module::recovery::{DynModuleBackup, ModuleBackup};
 use crate::sm::{self, ActiveStateMeta, Context, DynContext, DynState, State};
-use crate::transaction::{ClientInput, ClientOutput, TransactionBuilder};
-use crate::{oplog, AddStateMachinesResult, ClientArc, ClientWeak, TransactionUpdates};
+use crate::transaction::{ClientInput, ClientOutput, TransactionBuilder, TransactionPreview};
+use crate::{oplog, AddStateMachinesResult, Client, ClientArc, ClientWeak, TransactionUpdates};
 
 pub mod init;
 pub mod recovery;
@@ -55,6 +55,12 @@ impl FinalClient {
             .expect("client module context must not be use past client shutdown")
     }
 
+    /// Get a temporary [`ClientArc`] unless the client has been shut down
+    /// already or was not set yet
+    pub(crate) fn try_get(&self) -> Option<ClientArc> {
+        self.0.get()?.upgrade()
+    }
+
     pub(crate) fn set(&self, client: ClientWeak) {
         self.0.set(client).expect("FinalLazyClient already set");
     }
@@ -161,6 +167,42 @@ where
             .await
     }
 
+    /// Like [`ClientContext::finalize_and_submit_transaction`], but as part of
+    /// this database transaction so the module can atomically update its own
+    /// state, e.g. remove the notes it spends in `tx_builder`
+    pub async fn finalize_and_submit_transaction<Meta>(
+        &mut self,
+        operation_id: OperationId,
+        operation_type: &str,
+        operation_meta: impl FnOnce(TransactionId, Vec<OutPoint>) -> Meta,
+        tx_builder: TransactionBuilder,
+    ) -> anyhow::Result<(TransactionId, Vec<OutPoint>)>
+    where
+        Meta: serde::Serialize,
+    {
+        let client = self.client.client.get();
+
+        if Client::operation_exists(self.dbtx, operation_id).await {
+            bail!("There already exists an operation with id {operation_id:?}")
+        }
+
+        let (txid, change) = client
+            .finalize_and_submit_transaction_inner(self.dbtx, operation_id, tx_builder)
+            .await?;
+
+        client
+            .operation_log()
+            .add_operation_log_entry(
+                self.dbtx,
+                operation_id,
+                operation_type,
+                operation_meta(txid, change.clone()),
+            )
+            .await;
+
+        Ok((txid, change))
+    }
+
     pub async fn add_state_machines_dbtx(
         &mut self,
         states: Vec<DynState>,
@@ -197,6 +239,16 @@ where
         }
     }
 
+    /// Like [`Self::self_ref`], but returns `None` once the client has been
+    /// shut down, so background tasks of the module can terminate
+    pub fn try_self_ref(&self) -> Option<ClientContextSelfRef<'_, M>> {
+        Some(ClientContextSelfRef {
+            client: self.client.try_get()?,
+            module_instance_id: self.module_instance_id,
+            _marker: marker::PhantomData,
+        })
+    }
+
     /// Get a reference to a global Api handle
     pub fn global_api(&self) -> DynGlobalApi {
         self.client.get().api_clone()
@@ -358,6 +410,14 @@ where
             .await
     }
 
+    /// See [`crate::Client::preview_transaction`]
+    pub async fn preview_transaction(
+        &self,
+        tx_builder: TransactionBuilder,
+    ) -> anyhow::Result<TransactionPreview> {
+        self.client.get().preview_transaction(tx_builder).await
+    }
+
     /// See [`crate::Client::transaction_updates`]
     pub async fn transaction_updates(&self, operation_id: OperationId) -> TransactionUpdates {
         self.client.get().transaction_updates(operation_id).await
@@ -569,7 +629,8 @@ pub trait ClientModule: Debug + MaybeSend + MaybeSync + 'static {
     }
 
     /// Creates an output of **exactly** `amount` that will pay into the
-    /// holdings managed by the module.
+    /// holdings managed by the module. If the module charges fees for its
+    /// outputs `amount` includes them.
     ///
     /// It returns:
     /// * The output of **exactly** `amount`.
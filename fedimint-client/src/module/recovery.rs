@@ -2,7 +2,8 @@ use std::any::Any;
 use std::fmt::{self, Debug};
 
 use fedimint_core::core::{IntoDynInstance, ModuleInstanceId};
-use fedimint_core::encoding::{Decodable, DynEncodable, Encodable};
+use fedimint_core::encoding::{Decodable, DecodeError, DynEncodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::task::{MaybeSend, MaybeSync};
 use fedimint_core::{
     maybe_add_send_sync, module_plugin_dyn_newtype_clone_passthrough,
@@ -107,10 +108,27 @@ impl IntoDynInstance for NoModuleBackup {
 ///
 /// This includes "magic" value: if `total` is `0` the progress is "not started
 /// yet"/"empty"/"none"
-#[derive(Debug, Copy, Clone, Encodable, Decodable)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encodable)]
 pub struct RecoveryProgress {
     pub complete: u32,
     pub total: u32,
+    /// Number of items the module recovered so far (e.g. e-cash notes), which
+    /// is only reported while the recovery runs and not persisted
+    #[encodable_ignore]
+    pub found_items: u64,
+}
+
+impl Decodable for RecoveryProgress {
+    fn consensus_decode<R: std::io::Read>(
+        r: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        Ok(RecoveryProgress {
+            complete: u32::consensus_decode(r, modules)?,
+            total: u32::consensus_decode(r, modules)?,
+            found_items: 0,
+        })
+    }
 }
 
 impl RecoveryProgress {
@@ -122,6 +140,7 @@ impl RecoveryProgress {
         Self {
             complete: 0,
             total: 0,
+            found_items: 0,
         }
     }
 
@@ -135,11 +154,13 @@ impl RecoveryProgress {
             Self {
                 complete: 1,
                 total: 1,
+                found_items: self.found_items,
             }
         } else {
             Self {
                 complete: self.total,
                 total: self.total,
+                found_items: self.found_items,
             }
         }
     }
@@ -147,6 +168,49 @@ impl RecoveryProgress {
 
 impl fmt::Display for RecoveryProgress {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_fmt(format_args!("{}/{}", self.complete, self.total))
+        f.write_fmt(format_args!("{}/{}", self.complete, self.total))?;
+
+        if self.found_items != 0 {
+            f.write_fmt(format_args!(" ({} items found)", self.found_items))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::encoding::{Decodable, Encodable};
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+
+    use super::RecoveryProgress;
+
+    #[test]
+    fn test_recovery_progress_encoding_omits_found_items() {
+        let progress = RecoveryProgress {
+            complete: 3,
+            total: 10,
+            found_items: 42,
+        };
+
+        // the encoding is persisted, so it has to stay the same as before
+        // `found_items` was introduced
+        let bytes = progress.consensus_encode_to_vec();
+        assert_eq!(
+            bytes,
+            (3_u32, 10_u32).consensus_encode_to_vec(),
+            "Encoding of the persisted progress changed"
+        );
+
+        let decoded =
+            RecoveryProgress::consensus_decode_vec(bytes, &ModuleDecoderRegistry::default())
+                .expect("Decoding works");
+        assert_eq!(
+            decoded,
+            RecoveryProgress {
+                found_items: 0,
+                ..progress
+            }
+        );
     }
 }
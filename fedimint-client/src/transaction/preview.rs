@@ -0,0 +1,66 @@
+use fedimint_core::core::{ModuleInstanceId, ModuleKind};
+use fedimint_core::Amount;
+use serde::{Deserialize, Serialize};
+
+/// Whether an item of a [`TransactionPreview`] is an input or an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionItemDirection {
+    Input,
+    Output,
+}
+
+/// Amount and fee of a single input or output of a [`TransactionPreview`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionPreviewItem {
+    pub module_instance_id: ModuleInstanceId,
+    pub module_kind: ModuleKind,
+    pub direction: TransactionItemDirection,
+    pub amount: Amount,
+    pub fee: Amount,
+    /// The item was added by the primary module to fund the transaction or
+    /// to return change
+    pub added_by_client: bool,
+}
+
+/// Itemized amounts and fees of a transaction as it would be submitted by
+/// [`crate::Client::finalize_and_submit_transaction`], see
+/// [`crate::Client::preview_transaction`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionPreview {
+    pub items: Vec<TransactionPreviewItem>,
+    /// Sum of the amounts of all inputs
+    pub input_amount: Amount,
+    /// Sum of the amounts of all outputs, excluding fees
+    pub output_amount: Amount,
+    /// Sum of the fees of all items charged by the federation
+    pub fee: Amount,
+}
+
+impl TransactionPreview {
+    pub fn new(items: Vec<TransactionPreviewItem>) -> Self {
+        let sum = |direction| {
+            items
+                .iter()
+                .filter(|item| item.direction == direction)
+                .map(|item| item.amount)
+                .sum()
+        };
+
+        TransactionPreview {
+            input_amount: sum(TransactionItemDirection::Input),
+            output_amount: sum(TransactionItemDirection::Output),
+            fee: items.iter().map(|item| item.fee).sum(),
+            items,
+        }
+    }
+
+    /// Sum of the fees charged by the module instance `module_instance_id`
+    pub fn module_fee(&self, module_instance_id: ModuleInstanceId) -> Amount {
+        self.items
+            .iter()
+            .filter(|item| item.module_instance_id == module_instance_id)
+            .map(|item| item.fee)
+            .sum()
+    }
+}
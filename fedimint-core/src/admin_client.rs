@@ -3,24 +3,30 @@ use std::fmt::Debug;
 
 use bitcoin_hashes::sha256;
 use fedimint_core::api::GuardianConfigBackup;
-use fedimint_core::module::audit::AuditSummary;
+use fedimint_core::module::audit::{AuditSummary, SessionAuditSummary};
 use fedimint_core::task::MaybeSend;
 use fedimint_core::util::SafeUrl;
 use serde::{Deserialize, Serialize};
 use tokio_rustls::rustls;
 
-use crate::api::{DynGlobalApi, FederationApiExt, FederationResult, ServerStatus, StatusResponse};
+use crate::api::{
+    AuditHistoryRequest, DynGlobalApi, FederationApiExt, FederationResult,
+    PendingTransactionsResponse, ServerStatus, SessionItem, StatusResponse, TransactionLocation,
+    MAX_AUDIT_HISTORY_SESSIONS,
+};
 use crate::config::ServerModuleConfigGenParamsRegistry;
 use crate::endpoint_constants::{
-    ADD_CONFIG_GEN_PEER_ENDPOINT, AUDIT_ENDPOINT, AUTH_ENDPOINT, CONFIG_GEN_PEERS_ENDPOINT,
-    CONSENSUS_CONFIG_GEN_PARAMS_ENDPOINT, DEFAULT_CONFIG_GEN_PARAMS_ENDPOINT,
-    GUARDIAN_CONFIG_BACKUP_ENDPOINT, RESTART_FEDERATION_SETUP_ENDPOINT, RUN_DKG_ENDPOINT,
+    ADD_CONFIG_GEN_PEER_ENDPOINT, AUDIT_ENDPOINT, AUDIT_HISTORY_ENDPOINT, AUTH_ENDPOINT,
+    CHANGE_PASSWORD_ENDPOINT, CONFIG_GEN_PEERS_ENDPOINT, CONSENSUS_CONFIG_GEN_PARAMS_ENDPOINT,
+    DEFAULT_CONFIG_GEN_PARAMS_ENDPOINT, FIND_TRANSACTION_ENDPOINT, GUARDIAN_CONFIG_BACKUP_ENDPOINT,
+    PENDING_TRANSACTIONS_ENDPOINT, RESTART_FEDERATION_SETUP_ENDPOINT, RUN_DKG_ENDPOINT,
+    SERVER_CONFIG_CONSENSUS_HASH_ENDPOINT, SESSION_ITEMS_ENDPOINT,
     SET_CONFIG_GEN_CONNECTIONS_ENDPOINT, SET_CONFIG_GEN_PARAMS_ENDPOINT, SET_PASSWORD_ENDPOINT,
     START_CONSENSUS_ENDPOINT, STATUS_ENDPOINT, VERIFIED_CONFIGS_ENDPOINT,
     VERIFY_CONFIG_HASH_ENDPOINT,
 };
 use crate::module::{ApiAuth, ApiRequestErased};
-use crate::PeerId;
+use crate::{PeerId, TransactionId};
 
 /// For a guardian to communicate with their server
 // TODO: Maybe should have it's own CLI client so it doesn't need to be in core
@@ -151,6 +157,16 @@ impl WsAdminClient {
         .await
     }
 
+    /// After DKG, returns the hash of the consensus config. It has to be the
+    /// same for all peers, otherwise they generated different configs.
+    pub async fn server_config_consensus_hash(&self) -> FederationResult<sha256::Hash> {
+        self.request(
+            SERVER_CONFIG_CONSENSUS_HASH_ENDPOINT,
+            ApiRequestErased::default(),
+        )
+        .await
+    }
+
     /// Updates local state and notify leader that we have verified configs.
     /// This allows for a synchronization point, before we start consensus.
     pub async fn verified_configs(
@@ -189,6 +205,79 @@ impl WsAdminClient {
             .await
     }
 
+    /// Audit summaries persisted at the end of the sessions in the range
+    /// `start_session..end_session`, requested in pages of at most
+    /// [`MAX_AUDIT_HISTORY_SESSIONS`] sessions
+    pub async fn audit_history(
+        &self,
+        start_session: u64,
+        end_session: u64,
+        auth: ApiAuth,
+    ) -> FederationResult<Vec<SessionAuditSummary>> {
+        let mut history = vec![];
+
+        let mut page_start = start_session;
+        while page_start < end_session {
+            let page_end = end_session.min(page_start.saturating_add(MAX_AUDIT_HISTORY_SESSIONS));
+
+            let page: Vec<SessionAuditSummary> = self
+                .request(
+                    AUDIT_HISTORY_ENDPOINT,
+                    ApiRequestErased::new(AuditHistoryRequest {
+                        start_session: page_start,
+                        end_session: page_end,
+                    })
+                    .with_auth(auth.clone()),
+                )
+                .await?;
+
+            history.extend(page);
+            page_start = page_end;
+        }
+
+        Ok(history)
+    }
+
+    /// List transactions submitted to the guardian that are not accepted yet
+    /// and recently rejected transactions together with the reason
+    pub async fn pending_transactions(
+        &self,
+        auth: ApiAuth,
+    ) -> FederationResult<PendingTransactionsResponse> {
+        self.request(
+            PENDING_TRANSACTIONS_ENDPOINT,
+            ApiRequestErased::default().with_auth(auth),
+        )
+        .await
+    }
+
+    /// List the accepted items of a session with their inputs and outputs
+    /// decoded by the guardian's modules
+    pub async fn session_items(
+        &self,
+        session_index: u64,
+        auth: ApiAuth,
+    ) -> FederationResult<Vec<SessionItem>> {
+        self.request(
+            SESSION_ITEMS_ENDPOINT,
+            ApiRequestErased::new(session_index).with_auth(auth),
+        )
+        .await
+    }
+
+    /// Find the session and position in which a transaction was accepted
+    pub async fn find_transaction(
+        &self,
+        txid: TransactionId,
+        auth: ApiAuth,
+    ) -> FederationResult<Option<TransactionLocation>> {
+        self.request(
+            FIND_TRANSACTION_ENDPOINT,
+            ApiRequestErased::new(txid).with_auth(auth),
+        )
+        .await
+    }
+
     /// Download the guardian config to back it up
     pub async fn guardian_config_backup(
         &self,
@@ -201,6 +290,16 @@ impl WsAdminClient {
         .await
     }
 
+    /// Change the password of the running guardian, re-encrypting its private
+    /// config. The password `fedimintd` is started with has to be updated too.
+    pub async fn change_password(&self, new_auth: ApiAuth, auth: ApiAuth) -> FederationResult<()> {
+        self.request(
+            CHANGE_PASSWORD_ENDPOINT,
+            ApiRequestErased::new(new_auth).with_auth(auth),
+        )
+        .await
+    }
+
     /// Check auth credentials
     pub async fn auth(&self, auth: ApiAuth) -> FederationResult<()> {
         self.request(AUTH_ENDPOINT, ApiRequestErased::default().with_auth(auth))
@@ -48,12 +48,15 @@ use crate::core::backup::SignedBackupRequest;
 use crate::core::{Decoder, OutputOutcome};
 use crate::encoding::DecodeError;
 use crate::endpoint_constants::{
-    AWAIT_OUTPUT_OUTCOME_ENDPOINT, AWAIT_TRANSACTION_ENDPOINT, BACKUP_ENDPOINT, RECOVER_ENDPOINT,
+    AWAIT_OUTPUT_OUTCOME_ENDPOINT, AWAIT_TRANSACTION_ENDPOINT, BACKUP_ENDPOINT,
+    BROADCAST_PUBLIC_KEYS_ENDPOINT, LIABILITIES_STATEMENT_ENDPOINT, RECOVER_ENDPOINT,
     SESSION_COUNT_ENDPOINT, SESSION_STATUS_ENDPOINT, SUBMIT_TRANSACTION_ENDPOINT, VERSION_ENDPOINT,
 };
+use crate::module::liabilities::SignedLiabilitiesStatement;
 use crate::module::{ApiRequestErased, ApiVersion, SupportedApiVersionsSummary};
 use crate::query::{
-    DiscoverApiVersionSet, QueryStep, QueryStrategy, ThresholdConsensus, UnionResponsesSingle,
+    DiscoverApiVersionSet, FilterMapThresholdConsensus, QueryStep, QueryStrategy,
+    ThresholdConsensus, UnionResponsesSingle,
 };
 use crate::session_outcome::{AcceptedItem, SessionOutcome, SessionStatus};
 use crate::task;
@@ -493,6 +496,24 @@ pub trait IGlobalFederationApi: IRawFederationApi {
         id: &secp256k1::PublicKey,
     ) -> FederationResult<Vec<ClientBackupSnapshot>>;
 
+    /// Fetches the keys the guardians sign consensus messages and liabilities
+    /// statements with if enough peers agree on them
+    async fn broadcast_public_keys(
+        &self,
+    ) -> FederationResult<BTreeMap<PeerId, secp256k1::PublicKey>>;
+
+    /// Fetches the liabilities statement the federation produced at the end
+    /// of the given session, signed by a threshold of the guardians owning the
+    /// given broadcast keys
+    ///
+    /// The signatures only show that the guardians vouch for the statement,
+    /// see [`crate::module::liabilities`] for its limits.
+    async fn liabilities_statement(
+        &self,
+        session_index: u64,
+        public_keys: &BTreeMap<PeerId, secp256k1::PublicKey>,
+    ) -> FederationResult<SignedLiabilitiesStatement>;
+
     /// Query peers and calculate optimal common api versions to use.
     async fn discover_api_version_set(
         &self,
@@ -743,6 +764,74 @@ where
             .collect())
     }
 
+    async fn broadcast_public_keys(
+        &self,
+    ) -> FederationResult<BTreeMap<PeerId, secp256k1::PublicKey>> {
+        self.request_current_consensus(
+            BROADCAST_PUBLIC_KEYS_ENDPOINT.to_owned(),
+            ApiRequestErased::default(),
+        )
+        .await
+    }
+
+    async fn liabilities_statement(
+        &self,
+        session_index: u64,
+        public_keys: &BTreeMap<PeerId, secp256k1::PublicKey>,
+    ) -> FederationResult<SignedLiabilitiesStatement> {
+        let peer_public_keys = public_keys.clone();
+
+        let (statement, signatures) = self
+            .request_with_strategy(
+                // every guardian only signs the statement with its own key, so we wait for a
+                // threshold of identical statements carrying a valid signature of the
+                // responding peer, a single guardian sending another statement cannot stop us
+                FilterMapThresholdConsensus::new(
+                    move |peer, response: Option<SignedLiabilitiesStatement>| {
+                        let response =
+                            response.ok_or_else(|| anyhow!("Peer has no statement yet"))?;
+                        let public_key = peer_public_keys
+                            .get(&peer)
+                            .ok_or_else(|| anyhow!("Unknown peer"))?;
+                        let signature = response
+                            .signatures
+                            .get(&peer)
+                            .ok_or_else(|| anyhow!("Statement is not signed by the peer"))?;
+
+                        ensure!(
+                            response.statement.session_index == session_index,
+                            "Statement is for the wrong session"
+                        );
+                        ensure!(
+                            response.statement.verify_signature(
+                                secp256k1_zkp::SECP256K1,
+                                signature,
+                                public_key
+                            ),
+                            "Invalid signature"
+                        );
+
+                        Ok((response.statement, *signature))
+                    },
+                    self.all_peers().total(),
+                ),
+                LIABILITIES_STATEMENT_ENDPOINT.to_owned(),
+                ApiRequestErased::new(session_index),
+            )
+            .await?;
+
+        let signed = SignedLiabilitiesStatement {
+            statement,
+            signatures,
+        };
+
+        signed
+            .verify(public_keys)
+            .map_err(FederationError::general)?;
+
+        Ok(signed)
+    }
+
     async fn discover_api_version_set(
         &self,
         client_versions: &SupportedApiVersionsSummary,
@@ -1231,6 +1320,102 @@ pub struct GuardianConfigBackup {
     pub tar_archive_bytes: Vec<u8>,
 }
 
+/// A transaction that was submitted to a guardian but has not been accepted
+/// into a session yet
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingTransaction {
+    pub txid: TransactionId,
+    /// Seconds since the transaction was submitted
+    pub age_secs: u64,
+    /// Size of the consensus encoded transaction in bytes
+    pub size: usize,
+}
+
+/// At which point a transaction was found to be invalid
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionRejectionStage {
+    /// The guardian rejected the transaction when it was submitted to its API
+    Submission,
+    /// The transaction was ordered by consensus but failed to be processed
+    Consensus,
+}
+
+/// A recently rejected transaction together with the reason it was rejected
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RejectedTransaction {
+    pub txid: TransactionId,
+    /// Seconds since the transaction was rejected
+    pub age_secs: u64,
+    pub stage: TransactionRejectionStage,
+    pub reason: String,
+}
+
+/// Snapshot of a guardian's transaction pool, used to debug transactions that
+/// were submitted but never landed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingTransactionsResponse {
+    pub pending: Vec<PendingTransaction>,
+    pub rejected: Vec<RejectedTransaction>,
+}
+
+/// An input, output or consensus item decoded by its module
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DecodedModuleItem {
+    pub module_instance_id: ModuleInstanceId,
+    /// Kind of the module, `None` if the guardian does not know the module
+    pub kind: Option<String>,
+    /// JSON representation of the item if the module provides one
+    pub json: Option<serde_json::Value>,
+    /// Human readable summary of the item
+    pub display: String,
+}
+
+/// The contents of an accepted item of a session
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionItemDetails {
+    Transaction {
+        txid: TransactionId,
+        /// Hex encoded nonce of the transaction
+        nonce: String,
+        inputs: Vec<DecodedModuleItem>,
+        outputs: Vec<DecodedModuleItem>,
+    },
+    Module(DecodedModuleItem),
+    /// A consensus item type introduced in a later version
+    Unknown {
+        variant: u64,
+    },
+}
+
+/// An accepted item of a session together with the peer that contributed it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionItem {
+    /// Position of the item in the session outcome
+    pub index: u64,
+    pub peer: PeerId,
+    pub item: SessionItemDetails,
+}
+
+/// Where an accepted transaction can be found in the history of the federation
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Encodable, Decodable)]
+pub struct TransactionLocation {
+    pub session_index: u64,
+    pub item_index: u64,
+}
+
+/// Maximum number of sessions a single [`AuditHistoryRequest`] can span
+pub const MAX_AUDIT_HISTORY_SESSIONS: u64 = 10_000;
+
+/// Range of completed sessions to return the audit summaries for, the end is
+/// exclusive, spanning at most [`MAX_AUDIT_HISTORY_SESSIONS`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuditHistoryRequest {
+    pub start_session: u64,
+    pub end_session: u64,
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
@@ -1,12 +1,14 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
+use std::io::Write;
 use std::ops::Mul;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{anyhow, bail, format_err, Context};
+use anyhow::{anyhow, bail, ensure, format_err, Context};
 use bitcoin::secp256k1;
 use bitcoin_hashes::hex::{format_hex, FromHex};
 use bitcoin_hashes::sha256::{Hash as Sha256, HashEngine};
@@ -38,7 +40,7 @@ use crate::module::{
     ModuleConsensusVersion,
 };
 use crate::query::FilterMap;
-use crate::{maybe_add_send_sync, PeerId};
+use crate::{maybe_add_send_sync, NumPeers, PeerId};
 
 // TODO: make configurable
 /// This limits the RAM consumption of a AlephBFT Unit to roughly 50kB
@@ -118,7 +120,7 @@ pub struct PeerUrl {
 /// Total client config
 ///
 /// This includes global settings and client-side module configs.
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ClientConfig {
     #[serde(flatten)]
     pub global: GlobalClientConfig,
@@ -126,6 +128,41 @@ pub struct ClientConfig {
     pub modules: BTreeMap<ModuleInstanceId, ClientModuleConfig>,
 }
 
+impl Encodable for ClientConfig {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.global.api_endpoints.consensus_encode(writer)?;
+        len += self.global.consensus_version.consensus_encode(writer)?;
+        len += self.global.meta.consensus_encode(writer)?;
+        len += self.modules.consensus_encode(writer)?;
+        len += encode_config_extension(&self.global.reshared_from, writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for ClientConfig {
+    fn consensus_decode<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, crate::encoding::DecodeError> {
+        let api_endpoints = Decodable::consensus_decode(reader, modules)?;
+        let consensus_version = Decodable::consensus_decode(reader, modules)?;
+        let meta = Decodable::consensus_decode(reader, modules)?;
+        let client_modules = Decodable::consensus_decode(reader, modules)?;
+        let reshared_from = decode_config_extension(reader, modules)?;
+
+        Ok(Self {
+            global: GlobalClientConfig {
+                api_endpoints,
+                consensus_version,
+                meta,
+                reshared_from,
+            },
+            modules: client_modules,
+        })
+    }
+}
+
 // FIXME: workaround for https://github.com/serde-rs/json/issues/989
 fn de_int_key<'de, D, K, V>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
 where
@@ -154,7 +191,7 @@ pub struct JsonClientConfig {
 }
 
 /// Federation-wide client config
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct GlobalClientConfig {
     /// API endpoints for each federation member
     #[serde(deserialize_with = "de_int_key")]
@@ -164,6 +201,10 @@ pub struct GlobalClientConfig {
     // TODO: make it a String -> serde_json::Value map?
     /// Additional config the federation wants to transmit to the clients
     pub meta: BTreeMap<String, String>,
+    /// Set if the federation took over the keys of a previous federation by
+    /// resharing them to a new set of guardians
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reshared_from: Option<SignedFederationSuccession>,
 }
 
 impl GlobalClientConfig {
@@ -171,12 +212,131 @@ impl GlobalClientConfig {
         FederationId(self.api_endpoints.consensus_hash())
     }
 
+    /// Checks that the config belongs to the federation with the given id,
+    /// either directly or because a threshold of the guardians of that
+    /// federation signed that we took over its keys
+    pub fn verify_federation_id(&self, federation_id: FederationId) -> anyhow::Result<()> {
+        if self.federation_id() == federation_id {
+            return Ok(());
+        }
+
+        let Some(reshared_from) = &self.reshared_from else {
+            bail!("Guardian api endpoint map does not hash to FederationId");
+        };
+
+        ensure!(
+            reshared_from.verify(&self.api_endpoints)? == federation_id,
+            "Federation was reshared from a different federation"
+        );
+
+        Ok(())
+    }
+
     /// Federation name from config metadata (if set)
     pub fn federation_name(&self) -> Option<&str> {
         self.meta.get(META_FEDERATION_NAME_KEY).map(|x| &**x)
     }
 }
 
+/// Tag prepended to the signing message of a [`FederationSuccession`]
+const FEDERATION_SUCCESSION_TAG: &[u8] = b"fedimint-federation-succession";
+
+/// Statement of the guardians of a federation that a new federation with the
+/// given API endpoints took over their keys by resharing them
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct FederationSuccession {
+    /// API endpoints of the previous federation, they hash to its id
+    #[serde(deserialize_with = "de_int_key")]
+    pub previous_api_endpoints: BTreeMap<PeerId, PeerUrl>,
+    /// Broadcast public keys of the guardians of the previous federation
+    #[serde(deserialize_with = "de_int_key")]
+    pub previous_broadcast_public_keys: BTreeMap<PeerId, secp256k1_zkp::PublicKey>,
+    /// Hash of the API endpoints of the new federation
+    pub api_endpoints_hash: sha256::Hash,
+}
+
+impl FederationSuccession {
+    pub fn previous_federation_id(&self) -> FederationId {
+        FederationId(self.previous_api_endpoints.consensus_hash())
+    }
+
+    /// The message the guardians of the previous federation sign with their
+    /// broadcast keys
+    pub fn signing_message(&self) -> secp256k1_zkp::Message {
+        let mut engine = HashEngine::default();
+
+        engine
+            .write_all(FEDERATION_SUCCESSION_TAG)
+            .expect("Writing to a hash engine can not fail");
+
+        self.consensus_encode(&mut engine)
+            .expect("Writing to a hash engine can not fail");
+
+        secp256k1_zkp::Message::from(Sha256::from_engine(engine))
+    }
+}
+
+/// A [`FederationSuccession`] together with the signatures of the guardians of
+/// the previous federation
+///
+/// The signatures can only be checked against the broadcast keys in the
+/// statement itself, which do not hash to the id of the previous federation.
+/// [`ClientConfig::download_from_invite_code`] hence also requires a threshold
+/// of the previous guardians, which keep serving their old API endpoints, to
+/// serve the config.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct SignedFederationSuccession {
+    pub succession: FederationSuccession,
+    #[serde(deserialize_with = "de_int_key")]
+    pub signatures: BTreeMap<PeerId, secp256k1_zkp::schnorr::Signature>,
+}
+
+impl SignedFederationSuccession {
+    /// Checks that a threshold of the previous guardians signed the
+    /// succession to the federation with the given API endpoints and returns
+    /// the id of the previous federation
+    pub fn verify(
+        &self,
+        api_endpoints: &BTreeMap<PeerId, PeerUrl>,
+    ) -> anyhow::Result<FederationId> {
+        let previous_peers = &self.succession.previous_api_endpoints;
+        let public_keys = &self.succession.previous_broadcast_public_keys;
+
+        ensure!(
+            public_keys.keys().eq(previous_peers.keys()),
+            "Broadcast keys do not match the guardians of the previous federation"
+        );
+        ensure!(
+            self.succession.api_endpoints_hash == api_endpoints.consensus_hash(),
+            "Succession was signed for different api endpoints"
+        );
+
+        let message = self.succession.signing_message();
+
+        for (peer, signature) in &self.signatures {
+            let Some(public_key) = public_keys.get(peer) else {
+                bail!("Succession was signed by unknown peer {peer}");
+            };
+
+            ensure!(
+                secp256k1_zkp::SECP256K1
+                    .verify_schnorr(signature, &message, &public_key.x_only_public_key().0)
+                    .is_ok(),
+                "Invalid signature by peer {peer}"
+            );
+        }
+
+        ensure!(
+            self.signatures.len() >= previous_peers.threshold(),
+            "Succession is signed by {} peers but {} signatures are required",
+            self.signatures.len(),
+            previous_peers.threshold()
+        );
+
+        Ok(self.succession.previous_federation_id())
+    }
+}
+
 impl ClientConfig {
     /// See [`DynRawFallback::redecode_raw`].
     pub fn redecode_raw(
@@ -262,9 +422,7 @@ impl ClientConfig {
 
         let query_strategy = FilterMap::new(
             move |cfg: ClientConfig| {
-                if federation_id.0 != cfg.global.api_endpoints.consensus_hash() {
-                    bail!("Guardian api endpoint map does not hash to FederationId")
-                }
+                cfg.global.verify_federation_id(federation_id)?;
 
                 Ok(cfg.global.api_endpoints)
             },
@@ -292,8 +450,32 @@ impl ClientConfig {
             )
             .await?;
 
-        if client_config.federation_id() != federation_id {
-            bail!("Obtained client config has different federation id");
+        client_config
+            .global
+            .verify_federation_id(federation_id)
+            .context("Obtained client config has different federation id")?;
+
+        // The keys of the succession come with the config, so the guardians of the
+        // previous federation, whose API endpoints hash to its id, have to serve it too
+        if let Some(reshared_from) = &client_config.global.reshared_from {
+            let previous_api_endpoints = reshared_from
+                .succession
+                .previous_api_endpoints
+                .iter()
+                .map(|(peer, url)| (*peer, url.url.clone()))
+                .collect();
+
+            let previous_client_config = WsFederationApi::new(previous_api_endpoints)
+                .request_current_consensus::<ClientConfig>(
+                    CLIENT_CONFIG_ENDPOINT.to_owned(),
+                    ApiRequestErased::default(),
+                )
+                .await?;
+
+            ensure!(
+                previous_client_config == client_config,
+                "Guardians of the previous federation serve a different client config"
+            );
         }
 
         Ok(client_config)
@@ -820,6 +1002,61 @@ pub trait TypedServerModuleConsensusConfig:
     }
 }
 
+/// Encodes `extension`, the fields added to a module config after its
+/// encoding was first released, to be appended to the encoding of the fields
+/// that existed before
+///
+/// Module configs are stored and hashed in their consensus encoding, so adding
+/// a field breaks decoding configs stored by older versions and changes the
+/// hash of configs that do not use the field at all. Hence the extension is
+/// only encoded if it differs from its default, which leaves the encoding of
+/// such configs unchanged. See [`decode_config_extension`] for decoding it.
+pub fn encode_config_extension<T, W>(extension: &T, writer: &mut W) -> Result<usize, std::io::Error>
+where
+    T: Encodable + Default + PartialEq,
+    W: std::io::Write,
+{
+    if *extension == T::default() {
+        return Ok(0);
+    }
+
+    extension.consensus_encode(writer)
+}
+
+/// Decodes the extension encoded by [`encode_config_extension`], returning its
+/// default if the config was encoded without it
+///
+/// The extension has to be the last thing in `reader`, which holds for module
+/// configs as they are always decoded from a buffer of their own.
+pub fn decode_config_extension<T, R>(
+    reader: &mut R,
+    modules: &ModuleDecoderRegistry,
+) -> Result<T, crate::encoding::DecodeError>
+where
+    T: Decodable + Default,
+    R: std::io::Read,
+{
+    let mut bytes = vec![];
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(crate::encoding::DecodeError::from_err)?;
+
+    if bytes.is_empty() {
+        return Ok(T::default());
+    }
+
+    let mut extension_reader = &bytes[..];
+    let extension = T::consensus_decode(&mut extension_reader, modules)?;
+
+    if !extension_reader.is_empty() {
+        return Err(crate::encoding::DecodeError::from_str(
+            "Config extension did not consume all bytes",
+        ));
+    }
+
+    Ok(extension)
+}
+
 /// Module (server side) config, typed
 pub trait TypedServerModuleConfig: DeserializeOwned + Serialize {
     /// Local non-consensus, not security-sensitive settings
@@ -905,7 +1142,7 @@ pub trait ISupportedDkgMessage: Sized + Serialize + DeserializeOwned {
 }
 
 /// `enum` version of [`SupportedDkgMessage`]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum SupportedDkgMessage {
     G1(DkgMessage<G1Projective>),
     G2(DkgMessage<G2Projective>),
@@ -938,6 +1175,7 @@ impl ISupportedDkgMessage for DkgMessage<G2Projective> {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub enum DkgMessage<G: DkgGroup> {
     HashedCommit(Sha256),
     Commit(#[serde(with = "serde_commit")] Vec<G>),
@@ -945,7 +1183,100 @@ pub enum DkgMessage<G: DkgGroup> {
         #[serde(with = "serde_impl::scalar")] Scalar,
         #[serde(with = "serde_impl::scalar")] Scalar,
     ),
-    Extract(#[serde(with = "serde_commit")] Vec<G>),
+    Extract(#[serde(with = "serde_commit")] Vec<G>, ExtractProof<G>),
+    /// Commitment to the polynomial a dealer reshares its previous key share
+    /// with and the share of the recipient
+    Reshare(
+        #[serde(with = "serde_commit")] Vec<G>,
+        #[serde(with = "serde_impl::scalar")] Scalar,
+    ),
+}
+
+/// Proof that the public key shares of a [`DkgMessage::Extract`] are the part
+/// in the first generator of the dealer's commitment, which links the extract
+/// to the commitment for anyone verifying a DKG transcript
+///
+/// For every coefficient the dealer proves knowledge of the discrete log of
+/// the extracted share in the first generator and of the remaining commitment
+/// in the second generator with a Schnorr proof, made non-interactive by
+/// hashing the challenge from all public values.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ExtractProof<G: DkgGroup> {
+    #[serde(with = "serde_commit")]
+    pub nonces_g: Vec<G>,
+    #[serde(with = "serde_commit")]
+    pub nonces_h: Vec<G>,
+    #[serde(with = "serde_scalars")]
+    pub responses_g: Vec<Scalar>,
+    #[serde(with = "serde_scalars")]
+    pub responses_h: Vec<Scalar>,
+}
+
+impl<G: DkgGroup> DkgMessage<G> {
+    /// Whether the message only contains commitments that may be published,
+    /// messages containing shares of the secret key must never leave the DKG
+    pub fn is_public(&self) -> bool {
+        match self {
+            DkgMessage::HashedCommit(_) | DkgMessage::Commit(_) | DkgMessage::Extract(..) => true,
+            DkgMessage::Share(..) | DkgMessage::Reshare(..) => false,
+        }
+    }
+}
+
+/// A public DKG message sent by `peer` for the key `key` of a module
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DkgTranscriptEntry {
+    pub module_instance_id: ModuleInstanceId,
+    /// The DKG key as serialized by the `DkgRunner`
+    pub key: String,
+    pub peer: PeerId,
+    pub message: SupportedDkgMessage,
+}
+
+/// Collects the public messages of all DKGs run over a set of
+/// [`crate::module::PeerHandle`]s, messages containing secret shares are never
+/// recorded
+#[derive(Debug, Clone, Default)]
+pub struct DkgTranscriptRecorder(Arc<std::sync::Mutex<Vec<DkgTranscriptEntry>>>);
+
+impl DkgTranscriptRecorder {
+    pub fn record(
+        &self,
+        module_instance_id: ModuleInstanceId,
+        key: String,
+        peer: PeerId,
+        message: SupportedDkgMessage,
+    ) {
+        self.0
+            .lock()
+            .expect("lock poisoned")
+            .push(DkgTranscriptEntry {
+                module_instance_id,
+                key,
+                peer,
+                message,
+            });
+    }
+
+    /// The recorded messages, ordered by module, key and sender
+    pub fn entries(&self) -> Vec<DkgTranscriptEntry> {
+        let mut entries = self.0.lock().expect("lock poisoned").clone();
+        // Stable sort, so the messages of every peer stay in protocol order
+        entries.sort_by(|a, b| {
+            (a.module_instance_id, &a.key, a.peer).cmp(&(b.module_instance_id, &b.key, b.peer))
+        });
+        entries
+    }
+}
+
+/// The public keys a module derived from a DKG key, used to verify a DKG
+/// transcript against the consensus config
+#[derive(Debug, Clone)]
+pub enum DkgPublicKeys {
+    /// Commitment to the polynomial of a `threshold_crypto` key
+    G1Commitment(Vec<G1Projective>),
+    /// Public key shares of every peer of a `tbs` key
+    G2Shares(BTreeMap<PeerId, G2Projective>),
 }
 
 /// Defines a group (e.g. G1 or G2) that we can generate keys for
@@ -987,6 +1318,36 @@ mod serde_commit {
     }
 }
 
+/// Handling the Scalar serialization with a wrapper
+mod serde_scalars {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use tbs::{serde_impl, Scalar};
+
+    pub fn serialize<S: Serializer>(vec: &[Scalar], s: S) -> Result<S::Ok, S::Error> {
+        let wrap_vec: Vec<Wrap> = vec.iter().cloned().map(Wrap).collect();
+        wrap_vec.serialize(s)
+    }
+
+    pub fn deserialize<'d, D: Deserializer<'d>>(d: D) -> Result<Vec<Scalar>, D::Error> {
+        let wrap_vec = <Vec<Wrap>>::deserialize(d)?;
+        Ok(wrap_vec.into_iter().map(|wrap| wrap.0).collect())
+    }
+
+    struct Wrap(Scalar);
+
+    impl Serialize for Wrap {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            serde_impl::scalar::serialize(&self.0, s)
+        }
+    }
+
+    impl<'d> Deserialize<'d> for Wrap {
+        fn deserialize<D: Deserializer<'d>>(d: D) -> Result<Self, D::Error> {
+            serde_impl::scalar::deserialize(d).map(Wrap)
+        }
+    }
+}
+
 pub trait SGroup: Sized {
     fn serialize2<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error>;
     fn deserialize2<'d, D: Deserializer<'d>>(d: D) -> Result<Self, D::Error>;
@@ -1059,9 +1420,160 @@ pub mod serde_binary_human_readable {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use fedimint_core::config::{ClientConfig, GlobalClientConfig};
+    use secp256k1_zkp::{SecretKey, SECP256K1};
 
+    use super::{FederationSuccession, PeerUrl, SignedFederationSuccession};
+    use crate::encoding::{Decodable, Encodable};
+    use crate::module::registry::ModuleDecoderRegistry;
     use crate::module::CoreConsensusVersion;
+    use crate::PeerId;
+
+    fn peer_urls(peers: u16, host: &str) -> BTreeMap<PeerId, PeerUrl> {
+        (0..peers)
+            .map(|peer| {
+                (
+                    PeerId::from(peer),
+                    PeerUrl {
+                        url: format!("wss://{host}-{peer}:5000")
+                            .parse()
+                            .expect("valid url"),
+                        name: format!("{host}-{peer}"),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn global_config(api_endpoints: BTreeMap<PeerId, PeerUrl>) -> GlobalClientConfig {
+        GlobalClientConfig {
+            api_endpoints,
+            consensus_version: CoreConsensusVersion { major: 0, minor: 0 },
+            meta: BTreeMap::from([("foo".to_string(), "bar".to_string())]),
+            reshared_from: None,
+        }
+    }
+
+    /// Lets the first `signers` of the four previous guardians sign the
+    /// succession to `api_endpoints`
+    fn sign_succession(
+        signers: u16,
+        api_endpoints: &BTreeMap<PeerId, PeerUrl>,
+    ) -> SignedFederationSuccession {
+        let secret_keys = (0..4)
+            .map(|peer| {
+                (
+                    PeerId::from(peer),
+                    SecretKey::from_slice(&[peer as u8 + 1; 32]).expect("valid key"),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let succession = FederationSuccession {
+            previous_api_endpoints: peer_urls(4, "previous"),
+            previous_broadcast_public_keys: secret_keys
+                .iter()
+                .map(|(peer, sk)| (*peer, sk.public_key(SECP256K1)))
+                .collect(),
+            api_endpoints_hash: api_endpoints.consensus_hash(),
+        };
+
+        let signatures = secret_keys
+            .iter()
+            .take(signers as usize)
+            .map(|(peer, sk)| {
+                (
+                    *peer,
+                    SECP256K1.sign_schnorr(&succession.signing_message(), &sk.keypair(SECP256K1)),
+                )
+            })
+            .collect();
+
+        SignedFederationSuccession {
+            succession,
+            signatures,
+        }
+    }
+
+    #[test]
+    fn client_config_encoding_is_unchanged_unless_reshared() {
+        #[derive(Encodable)]
+        struct ClientConfigV0 {
+            api_endpoints: BTreeMap<PeerId, PeerUrl>,
+            consensus_version: CoreConsensusVersion,
+            meta: BTreeMap<String, String>,
+            modules: BTreeMap<u16, u16>,
+        }
+
+        let global = global_config(peer_urls(4, "guardian"));
+        let config = ClientConfig {
+            global: global.clone(),
+            modules: BTreeMap::new(),
+        };
+        let bytes = config.consensus_encode_to_vec();
+
+        assert_eq!(
+            bytes,
+            ClientConfigV0 {
+                api_endpoints: global.api_endpoints,
+                consensus_version: global.consensus_version,
+                meta: global.meta,
+                modules: BTreeMap::new(),
+            }
+            .consensus_encode_to_vec()
+        );
+        assert_eq!(
+            ClientConfig::consensus_decode(&mut &bytes[..], &ModuleDecoderRegistry::default())
+                .expect("decodes"),
+            config
+        );
+
+        let mut reshared = config.clone();
+        reshared.global.reshared_from = Some(sign_succession(3, &reshared.global.api_endpoints));
+        let bytes = reshared.consensus_encode_to_vec();
+
+        assert_eq!(
+            ClientConfig::consensus_decode(&mut &bytes[..], &ModuleDecoderRegistry::default())
+                .expect("decodes"),
+            reshared
+        );
+
+        let json = serde_json::to_string(&reshared).expect("serializes");
+        assert_eq!(
+            serde_json::from_str::<ClientConfig>(&json).expect("deserializes"),
+            reshared
+        );
+    }
+
+    #[test]
+    fn federation_id_is_checked_against_signed_succession() {
+        let mut global = global_config(peer_urls(7, "guardian"));
+        let previous_federation_id = sign_succession(3, &global.api_endpoints)
+            .succession
+            .previous_federation_id();
+
+        // meta can not override the federation id anymore
+        global.meta.insert(
+            "federation_id".to_string(),
+            previous_federation_id.to_string(),
+        );
+        assert_ne!(global.federation_id(), previous_federation_id);
+        assert!(global.verify_federation_id(global.federation_id()).is_ok());
+        assert!(global.verify_federation_id(previous_federation_id).is_err());
+
+        // a threshold of the previous guardians has to sign
+        global.reshared_from = Some(sign_succession(2, &global.api_endpoints));
+        assert!(global.verify_federation_id(previous_federation_id).is_err());
+
+        global.reshared_from = Some(sign_succession(3, &global.api_endpoints));
+        assert!(global.verify_federation_id(previous_federation_id).is_ok());
+
+        // the signatures only cover the api endpoints they were made for
+        global.reshared_from = Some(sign_succession(3, &peer_urls(7, "attacker")));
+        assert!(global.verify_federation_id(previous_federation_id).is_err());
+    }
 
     #[test]
     fn test_dcode_meta() {
@@ -1076,6 +1588,7 @@ mod tests {
                 ]
                 .into_iter()
                 .collect(),
+                reshared_from: None,
             },
             modules: Default::default(),
         };
@@ -7,7 +7,9 @@
 use std::fmt::Debug;
 use std::sync::Arc;
 
+use fedimint_core::health::HealthReport;
 use fedimint_core::module::audit::Audit;
+use fedimint_core::module::liabilities::ModuleLiabilities;
 use fedimint_core::{apply, async_trait_maybe_send, OutPoint, PeerId};
 
 use crate::core::{
@@ -22,6 +24,9 @@ use crate::module::{
     TransactionItemAmount,
 };
 
+/// Type-erased [`ServerModule::VerifiedInput`] of a server module
+pub type DynVerifiedInput = Box<dyn std::any::Any + Send + Sync>;
+
 /// Backend side module interface
 ///
 /// Server side Fedimint module needs to implement this trait.
@@ -49,17 +54,34 @@ pub trait IServerModule: Debug {
         peer_id: PeerId,
     ) -> anyhow::Result<()>;
 
-    /// Try to spend a transaction input. On success all necessary updates will
-    /// be part of the database transaction. On failure (e.g. double spend)
-    /// the database transaction is rolled back and the operation will take
-    /// no effect.
+    /// Verifies all inputs of a transaction that belong to this module at once
+    /// before they are processed one by one with `process_input`, returns
+    /// what was learned about every input in the order of `inputs`.
+    async fn verify_inputs_batch<'a>(
+        &self,
+        dbtx: &mut DatabaseTransaction<'a>,
+        inputs: &[&DynInput],
+        module_instance_id: ModuleInstanceId,
+    ) -> Result<Vec<DynVerifiedInput>, DynInputError>;
+
+    /// Try to spend a transaction input verified by `verify_inputs_batch`. On
+    /// success all necessary updates will be part of the database
+    /// transaction. On failure (e.g. double spend) the database transaction
+    /// is rolled back and the operation will take no effect.
     async fn process_input<'a, 'b, 'c>(
         &'a self,
         dbtx: &mut DatabaseTransaction<'c>,
         input: &'b DynInput,
+        verified_input: DynVerifiedInput,
         module_instance_id: ModuleInstanceId,
     ) -> Result<InputMeta, DynInputError>;
 
+    /// JSON representation of an input of this module, if it provides one
+    fn input_to_json(&self, input: &DynInput) -> Option<serde_json::Value>;
+
+    /// JSON representation of an output of this module, if it provides one
+    fn output_to_json(&self, output: &DynOutput) -> Option<serde_json::Value>;
+
     /// Try to create an output (e.g. issue notes, peg-out BTC, …). On success
     /// all necessary updates to the database will be part of the database
     /// transaction. On failure (e.g. double spend) the database transaction
@@ -99,6 +121,13 @@ pub trait IServerModule: Debug {
         module_instance_id: ModuleInstanceId,
     );
 
+    /// Queries the database and returns the liabilities of the module towards
+    /// its users and the on-chain outputs backing them.
+    async fn liabilities(&self, dbtx: &mut DatabaseTransaction<'_>) -> ModuleLiabilities;
+
+    /// Reports the health of the module's dependencies, keyed by check name
+    async fn health(&self) -> HealthReport;
+
     /// Returns a list of custom API endpoints defined by the module. These are
     /// made available both to users as well as to other modules. They thus
     /// should be deterministic, only dependant on their input and the
@@ -159,29 +188,90 @@ where
         .await
     }
 
-    /// Try to spend a transaction input. On success all necessary updates will
-    /// be part of the database transaction. On failure (e.g. double spend)
-    /// the database transaction is rolled back and the operation will take
-    /// no effect.
+    /// Verifies all inputs of a transaction that belong to this module at once
+    /// before they are processed one by one with `process_input`, returns
+    /// what was learned about every input in the order of `inputs`.
+    async fn verify_inputs_batch<'a>(
+        &self,
+        dbtx: &mut DatabaseTransaction<'a>,
+        inputs: &[&DynInput],
+        module_instance_id: ModuleInstanceId,
+    ) -> Result<Vec<DynVerifiedInput>, DynInputError> {
+        let inputs = inputs
+            .iter()
+            .map(|input| {
+                input
+                    .as_any()
+                    .downcast_ref::<<<Self as ServerModule>::Common as ModuleCommon>::Input>()
+                    .expect("incorrect input type passed to module plugin")
+            })
+            .collect::<Vec<_>>();
+
+        let verified_inputs = <Self as ServerModule>::verify_inputs_batch(self, dbtx, &inputs)
+            .await
+            .map_err(|v| DynInputError::from_typed(module_instance_id, v))?;
+
+        assert_eq!(
+            verified_inputs.len(),
+            inputs.len(),
+            "module has to return a verified input for every input"
+        );
+
+        Ok(verified_inputs
+            .into_iter()
+            .map(|verified_input| Box::new(verified_input) as DynVerifiedInput)
+            .collect())
+    }
+
+    /// Try to spend a transaction input verified by `verify_inputs_batch`. On
+    /// success all necessary updates will be part of the database
+    /// transaction. On failure (e.g. double spend) the database transaction
+    /// is rolled back and the operation will take no effect.
     async fn process_input<'a, 'b, 'c>(
         &'a self,
         dbtx: &mut DatabaseTransaction<'c>,
         input: &'b DynInput,
+        verified_input: DynVerifiedInput,
         module_instance_id: ModuleInstanceId,
     ) -> Result<InputMeta, DynInputError> {
-        <Self as ServerModule>::process_input(
+        <Self as ServerModule>::process_verified_input(
             self,
             dbtx,
             input
                 .as_any()
                 .downcast_ref::<<<Self as ServerModule>::Common as ModuleCommon>::Input>()
                 .expect("incorrect input type passed to module plugin"),
+            *verified_input
+                .downcast::<<Self as ServerModule>::VerifiedInput>()
+                .expect("incorrect verified input type passed to module plugin"),
         )
         .await
         .map(Into::into)
         .map_err(|v| DynInputError::from_typed(module_instance_id, v))
     }
 
+    /// JSON representation of an input of this module, if it provides one
+    fn input_to_json(&self, input: &DynInput) -> Option<serde_json::Value> {
+        <Self as ServerModule>::input_to_json(
+            self,
+            input
+                .as_any()
+                .downcast_ref::<<<Self as ServerModule>::Common as ModuleCommon>::Input>()
+                .expect("incorrect input type passed to module plugin"),
+        )
+    }
+
+    /// JSON representation of an output of this module, if it provides one
+    fn output_to_json(&self, output: &DynOutput) -> Option<serde_json::Value> {
+        <Self as ServerModule>::output_to_json(
+            self,
+            output
+                .as_any()
+                .downcast_ref::<<<Self as ServerModule>::Common as ModuleCommon>::Output>()
+                .expect("incorrect output type passed to module plugin"),
+        )
+    }
+
     /// Try to create an output (e.g. issue notes, peg-out BTC, …). On success
     /// all necessary updates to the database will be part of the database
     /// transaction. On failure (e.g. double spend) the database transaction
@@ -239,6 +329,14 @@ where
         <Self as ServerModule>::audit(self, dbtx, audit, module_instance_id).await
     }
 
+    async fn liabilities(&self, dbtx: &mut DatabaseTransaction<'_>) -> ModuleLiabilities {
+        <Self as ServerModule>::liabilities(self, dbtx).await
+    }
+
+    async fn health(&self) -> HealthReport {
+        <Self as ServerModule>::health(self).await
+    }
+
     fn api_endpoints(&self) -> Vec<ApiEndpoint<DynServerModule>> {
         <Self as ServerModule>::api_endpoints(self)
             .into_iter()
@@ -1,26 +1,34 @@
 pub const ACCOUNT_ENDPOINT: &str = "account";
 pub const ADD_CONFIG_GEN_PEER_ENDPOINT: &str = "add_config_gen_peer";
 pub const AUDIT_ENDPOINT: &str = "audit";
+pub const AUDIT_HISTORY_ENDPOINT: &str = "audit_history";
 pub const GUARDIAN_CONFIG_BACKUP_ENDPOINT: &str = "download_guardian_backup";
 pub const AUTH_ENDPOINT: &str = "auth";
 pub const AWAIT_OUTPUT_OUTCOME_ENDPOINT: &str = "await_output_outcome";
 pub const BACKUP_ENDPOINT: &str = "backup";
 pub const BLOCK_COUNT_ENDPOINT: &str = "block_count";
+pub const BROADCAST_PUBLIC_KEYS_ENDPOINT: &str = "broadcast_public_keys";
 pub const BLOCK_COUNT_LOCAL_ENDPOINT: &str = "block_count_local";
+pub const CHANGE_PASSWORD_ENDPOINT: &str = "change_password";
 pub const CLIENT_CONFIG_ENDPOINT: &str = "client_config";
 pub const SERVER_CONFIG_CONSENSUS_HASH_ENDPOINT: &str = "server_config_consensus_hash";
 pub const SESSION_COUNT_ENDPOINT: &str = "session_count";
 pub const AWAIT_SESSION_OUTCOME_ENDPOINT: &str = "await_session_outcome";
 pub const AWAIT_SIGNED_SESSION_OUTCOME_ENDPOINT: &str = "await_signed_session_outcome";
 pub const SESSION_STATUS_ENDPOINT: &str = "session_status";
+pub const SESSION_ITEMS_ENDPOINT: &str = "session_items";
+pub const FIND_TRANSACTION_ENDPOINT: &str = "find_transaction";
 pub const CONFIG_GEN_PEERS_ENDPOINT: &str = "config_gen_peers";
 pub const CONSENSUS_CONFIG_GEN_PARAMS_ENDPOINT: &str = "consensus_config_gen_params";
 pub const DEFAULT_CONFIG_GEN_PARAMS_ENDPOINT: &str = "default_config_gen_params";
 pub const VERIFY_CONFIG_HASH_ENDPOINT: &str = "verify_config_hash";
+pub const KEY_EPOCH_ENDPOINT: &str = "key_epoch";
+pub const LIABILITIES_STATEMENT_ENDPOINT: &str = "liabilities_statement";
 pub const LIST_GATEWAYS_ENDPOINT: &str = "list_gateways";
 pub const MODULES_CONFIG_JSON_ENDPOINT: &str = "modules_config_json";
 pub const OFFER_ENDPOINT: &str = "offer";
 pub const PEG_OUT_FEES_ENDPOINT: &str = "peg_out_fees";
+pub const PENDING_TRANSACTIONS_ENDPOINT: &str = "pending_transactions";
 pub const RECOVER_ENDPOINT: &str = "recover";
 pub const REGISTER_GATEWAY_ENDPOINT: &str = "register_gateway";
 pub const REMOVE_GATEWAY_CHALLENGE_ENDPOINT: &str = "remove_gateway_challenge";
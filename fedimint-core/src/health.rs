@@ -0,0 +1,93 @@
+//! Health checks exposed by the daemons for orchestration
+//!
+//! A daemon registers an implementation of [`IHealthChecks`] with the metrics
+//! server, which serves the results of all checks as JSON over unauthenticated
+//! HTTP endpoints so that load balancers and container runtimes can probe the
+//! daemon without an admin password. Checks must therefore only report
+//! operational state and never leak secrets or user data.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::db::{Database, DatabaseKey, DatabaseRecord, IDatabaseTransactionOpsCoreTyped};
+use crate::task::{MaybeSend, MaybeSync};
+use crate::{apply, async_trait_maybe_send, maybe_add_send_sync};
+
+/// Outcome of a single health check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthStatus {
+    pub healthy: bool,
+    /// Check specific information to help operators diagnose a failure
+    pub details: serde_json::Value,
+}
+
+impl HealthStatus {
+    pub fn healthy(details: serde_json::Value) -> Self {
+        HealthStatus {
+            healthy: true,
+            details,
+        }
+    }
+
+    pub fn unhealthy(details: serde_json::Value) -> Self {
+        HealthStatus {
+            healthy: false,
+            details,
+        }
+    }
+
+    /// Reports `details` with the outcome given by `healthy`
+    pub fn from_bool(healthy: bool, details: serde_json::Value) -> Self {
+        HealthStatus { healthy, details }
+    }
+}
+
+/// Results of all health checks of a daemon by name
+pub type HealthReport = BTreeMap<String, HealthStatus>;
+
+/// Returns true if all checks of the report passed
+pub fn is_healthy(report: &HealthReport) -> bool {
+    report.values().all(|status| status.healthy)
+}
+
+/// Source of the health checks served by the metrics server
+#[apply(async_trait_maybe_send!)]
+pub trait IHealthChecks: Debug {
+    /// Runs all checks
+    async fn health_checks(&self) -> HealthReport;
+
+    /// Runs only the check called `name`, returns `None` if there is no such
+    /// check
+    async fn health_check(&self, name: &str) -> Option<HealthStatus>;
+}
+
+pub type DynHealthChecks = Arc<maybe_add_send_sync!(dyn IHealthChecks)>;
+
+/// Checks that the database accepts writes by committing the current time
+/// under `key`, which should be reserved for this purpose. Since this commits
+/// a write, the metrics server caches the results of the checks.
+pub async fn check_database_writable<K>(db: &Database, key: &K) -> HealthStatus
+where
+    K: DatabaseKey + DatabaseRecord<Value = SystemTime> + MaybeSend + MaybeSync,
+{
+    let mut dbtx = db.begin_transaction().await;
+    dbtx.insert_entry(key, &crate::time::now()).await;
+
+    match dbtx.commit_tx_result().await {
+        Ok(()) => HealthStatus::healthy(json!({})),
+        Err(e) => HealthStatus::unhealthy(json!({ "error": e.to_string() })),
+    }
+}
+
+/// Seconds elapsed since `time`, zero if `time` lies in the future
+pub fn age_secs(time: SystemTime) -> u64 {
+    crate::time::now()
+        .duration_since(time)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
@@ -39,6 +39,7 @@ pub mod endpoint_constants;
 pub mod envs;
 pub mod epoch;
 pub mod fmt_utils;
+pub mod health;
 pub mod hex;
 #[macro_use]
 pub mod macros;
@@ -1,7 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Formatter};
+use std::time::SystemTime;
 
 use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::encoding::{Decodable, Encodable};
 use futures::StreamExt;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -109,6 +111,80 @@ impl AuditSummary {
     }
 }
 
+/// Balance sheet of the federation as it stood at the end of a session,
+/// persisted by every guardian so that the history can be inspected later on.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Encodable, Decodable)]
+pub struct SessionAuditSummary {
+    pub session_index: u64,
+    /// Local time of the guardian at which the session was completed
+    pub timestamp: SystemTime,
+    pub module_summaries: BTreeMap<ModuleInstanceId, ModuleAuditSummary>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Encodable, Decodable)]
+pub struct ModuleAuditSummary {
+    pub kind: String,
+    pub assets_msat: u64,
+    pub liabilities_msat: u64,
+}
+
+impl ModuleAuditSummary {
+    pub fn net_assets(&self) -> i64 {
+        self.assets_msat as i64 - self.liabilities_msat as i64
+    }
+}
+
+impl SessionAuditSummary {
+    pub fn from_audit(
+        session_index: u64,
+        timestamp: SystemTime,
+        audit: &Audit,
+        module_instance_id_to_kind: &HashMap<ModuleInstanceId, String>,
+    ) -> Self {
+        let mut module_summaries = module_instance_id_to_kind
+            .iter()
+            .map(|(module_instance_id, kind)| {
+                (
+                    *module_instance_id,
+                    ModuleAuditSummary {
+                        kind: kind.clone(),
+                        assets_msat: 0,
+                        liabilities_msat: 0,
+                    },
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        for item in &audit.items {
+            let Some(summary) = item
+                .module_instance_id
+                .and_then(|id| module_summaries.get_mut(&id))
+            else {
+                continue;
+            };
+
+            if item.milli_sat < 0 {
+                summary.liabilities_msat += item.milli_sat.unsigned_abs();
+            } else {
+                summary.assets_msat += item.milli_sat.unsigned_abs();
+            }
+        }
+
+        SessionAuditSummary {
+            session_index,
+            timestamp,
+            module_summaries,
+        }
+    }
+
+    pub fn net_assets(&self) -> i64 {
+        self.module_summaries
+            .values()
+            .map(ModuleAuditSummary::net_assets)
+            .sum()
+    }
+}
+
 fn generate_module_summaries<'a>(
     audit_items: impl Iterator<Item = &'a AuditItem>,
     module_instance_id_to_kind: &HashMap<ModuleInstanceId, String>,
@@ -271,3 +347,71 @@ fn audit_summary_includes_placeholders() {
 
     assert_eq!(audit_summary, expected_audit_summary);
 }
+
+#[test]
+fn creates_session_audit_summary_from_audit() {
+    let audit = Audit {
+        items: vec![
+            AuditItem {
+                name: "IssuanceTotal".to_string(),
+                milli_sat: -50_100_000,
+                module_instance_id: Some(1),
+            },
+            AuditItem {
+                name: "RedemptionTotal".to_string(),
+                milli_sat: 100_101_000,
+                module_instance_id: Some(1),
+            },
+            AuditItem {
+                name: "ContractKey(...)".to_string(),
+                milli_sat: -101_000,
+                module_instance_id: Some(0),
+            },
+        ],
+    };
+
+    let summary = SessionAuditSummary::from_audit(
+        5,
+        SystemTime::UNIX_EPOCH,
+        &audit,
+        &HashMap::from([
+            (0, "ln".to_string()),
+            (1, "mint".to_string()),
+            (2, "wallet".to_string()),
+        ]),
+    );
+
+    assert_eq!(
+        summary.module_summaries,
+        BTreeMap::from([
+            (
+                0,
+                ModuleAuditSummary {
+                    kind: "ln".to_string(),
+                    assets_msat: 0,
+                    liabilities_msat: 101_000,
+                }
+            ),
+            (
+                1,
+                ModuleAuditSummary {
+                    kind: "mint".to_string(),
+                    assets_msat: 100_101_000,
+                    liabilities_msat: 50_100_000,
+                }
+            ),
+            (
+                2,
+                ModuleAuditSummary {
+                    kind: "wallet".to_string(),
+                    assets_msat: 0,
+                    liabilities_msat: 0,
+                }
+            ),
+        ])
+    );
+    assert_eq!(
+        summary.net_assets(),
+        calculate_net_assets(audit.items.iter())
+    );
+}
@@ -0,0 +1,95 @@
+//! Fees proportional to the amount of a transaction item
+//!
+//! Modules charge a [`ProportionalFee`] in addition to their absolute fee per
+//! input or output, which allows federations to cover their operating costs
+//! with a fee that scales with the value moved.
+
+use anyhow::ensure;
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::{Decodable, Encodable};
+use crate::Amount;
+
+/// Fee in parts per million of the amount of a transaction item, rounded down
+/// and bounded by a minimum and an optional maximum
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ProportionalFee {
+    pub ppm: u64,
+    pub min: Amount,
+    pub max: Option<Amount>,
+}
+
+impl Default for ProportionalFee {
+    fn default() -> Self {
+        Self::ZERO
+    }
+}
+
+impl ProportionalFee {
+    pub const ZERO: ProportionalFee = ProportionalFee {
+        ppm: 0,
+        min: Amount::ZERO,
+        max: None,
+    };
+
+    pub fn new(ppm: u64, min: Amount, max: Option<Amount>) -> Self {
+        ProportionalFee { ppm, min, max }
+    }
+
+    /// The fee charged for an item of `amount`
+    pub fn fee(&self, amount: Amount) -> Amount {
+        let proportional = u128::from(amount.msats) * u128::from(self.ppm) / 1_000_000;
+        let fee = Amount::from_msats(u64::try_from(proportional).unwrap_or(u64::MAX)).max(self.min);
+
+        // an invalid config with a maximum below the minimum charges the maximum
+        self.max.map_or(fee, |max| fee.min(max))
+    }
+
+    /// Checks that the maximum is not below the minimum
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(max) = self.max {
+            ensure!(
+                self.min <= max,
+                "Minimum proportional fee {} exceeds the maximum {}",
+                self.min,
+                max
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProportionalFee;
+    use crate::Amount;
+
+    #[test]
+    fn proportional_fee_is_bounded() {
+        let fee = ProportionalFee::new(
+            1_000,
+            Amount::from_msats(10),
+            Some(Amount::from_msats(1_000)),
+        );
+
+        assert_eq!(fee.fee(Amount::ZERO), Amount::from_msats(10));
+        assert_eq!(fee.fee(Amount::from_msats(50_000)), Amount::from_msats(50));
+        assert_eq!(fee.fee(Amount::from_msats(50_999)), Amount::from_msats(50));
+        assert_eq!(
+            fee.fee(Amount::from_sats(10_000)),
+            Amount::from_msats(1_000)
+        );
+        assert_eq!(
+            ProportionalFee::ZERO.fee(Amount::from_sats(10_000)),
+            Amount::ZERO
+        );
+
+        assert!(fee.validate().is_ok());
+        assert!(
+            ProportionalFee::new(0, Amount::from_msats(2), Some(Amount::from_msats(1)))
+                .validate()
+                .is_err()
+        );
+    }
+}
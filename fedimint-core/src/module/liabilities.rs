@@ -0,0 +1,258 @@
+//! Proof-of-liabilities statements
+//!
+//! At the end of every session whose index is a multiple of
+//! [`LIABILITIES_STATEMENT_INTERVAL`] each guardian records the liabilities of
+//! all modules towards their users together with the on-chain outputs backing
+//! them. Since the consensus state is identical across guardians at a session
+//! boundary, all honest guardians produce the same [`LiabilitiesStatement`]
+//! and sign it with their broadcast key. A threshold of these signatures lets
+//! anyone check that the federation vouches for the statement, while the
+//! listed reserves can be checked against the blockchain.
+//!
+//! The statement is only as trustworthy as the federation itself: it is
+//! signed by the same guardians whose books it is supposed to check, so a
+//! threshold of colluding guardians can sign any liabilities they like. Only
+//! the reserves are independently verifiable.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::{bail, ensure};
+use secp256k1_zkp::hashes::{sha256, Hash};
+use secp256k1_zkp::{schnorr, Message, PublicKey, Secp256k1, Verification};
+use serde::{Deserialize, Serialize};
+
+use crate::core::ModuleInstanceId;
+use crate::encoding::{Decodable, Encodable};
+use crate::{Amount, NumPeers, PeerId};
+
+/// Guardians produce a statement at the end of every session whose index is a
+/// multiple of this interval
+pub const LIABILITIES_STATEMENT_INTERVAL: u64 = 100;
+
+/// Domain separation tag for the message signed by the guardians, the
+/// broadcast keys are used to sign consensus messages as well
+const LIABILITIES_STATEMENT_TAG: &[u8] = b"fedimint-liabilities-statement";
+
+/// Session index of the latest statement the federation can have produced
+/// after `session_count` sessions were completed
+pub fn latest_liabilities_statement_session(session_count: u64) -> Option<u64> {
+    let last_session = session_count.checked_sub(1)?;
+
+    Some(last_session - last_session % LIABILITIES_STATEMENT_INTERVAL)
+}
+
+/// Liabilities of a module towards its users and the on-chain outputs it
+/// controls to back them
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ModuleLiabilities {
+    pub liabilities: Amount,
+    pub reserves: Vec<ReserveUtxo>,
+}
+
+impl Default for ModuleLiabilities {
+    fn default() -> Self {
+        ModuleLiabilities {
+            liabilities: Amount::ZERO,
+            reserves: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ReserveUtxo {
+    pub outpoint: bitcoin::OutPoint,
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+    pub script_pubkey: bitcoin::Script,
+}
+
+/// Liabilities and reserves of all modules at the end of a session
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct LiabilitiesStatement {
+    pub session_index: u64,
+    pub modules: BTreeMap<ModuleInstanceId, ModuleLiabilities>,
+}
+
+impl LiabilitiesStatement {
+    pub fn total_liabilities(&self) -> Amount {
+        self.modules.values().map(|module| module.liabilities).sum()
+    }
+
+    pub fn reserves(&self) -> impl Iterator<Item = &ReserveUtxo> {
+        self.modules
+            .values()
+            .flat_map(|module| module.reserves.iter())
+    }
+
+    pub fn total_reserves(&self) -> bitcoin::Amount {
+        self.reserves()
+            .map(|reserve| reserve.amount)
+            .fold(bitcoin::Amount::ZERO, |total, amount| total + amount)
+    }
+
+    /// The message the guardians sign with their broadcast keys
+    pub fn signing_message(&self) -> Message {
+        let mut engine = sha256::HashEngine::default();
+
+        engine
+            .write_all(LIABILITIES_STATEMENT_TAG)
+            .expect("Writing to a hash engine can not fail");
+
+        self.consensus_encode(&mut engine)
+            .expect("Writing to a hash engine can not fail");
+
+        Message::from(sha256::Hash::from_engine(engine))
+    }
+
+    pub fn verify_signature<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        signature: &schnorr::Signature,
+        public_key: &PublicKey,
+    ) -> bool {
+        secp.verify_schnorr(
+            signature,
+            &self.signing_message(),
+            &public_key.x_only_public_key().0,
+        )
+        .is_ok()
+    }
+}
+
+/// A statement together with the signatures of the guardians vouching for it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct SignedLiabilitiesStatement {
+    pub statement: LiabilitiesStatement,
+    pub signatures: BTreeMap<PeerId, schnorr::Signature>,
+}
+
+impl SignedLiabilitiesStatement {
+    /// Checks that a threshold of the guardians with the given broadcast
+    /// public keys signed the statement
+    pub fn verify(&self, public_keys: &BTreeMap<PeerId, PublicKey>) -> anyhow::Result<()> {
+        let secp = Secp256k1::verification_only();
+
+        for (peer, signature) in &self.signatures {
+            let Some(public_key) = public_keys.get(peer) else {
+                bail!("Statement was signed by unknown peer {peer}");
+            };
+
+            ensure!(
+                self.statement
+                    .verify_signature(&secp, signature, public_key),
+                "Invalid signature by peer {peer}"
+            );
+        }
+
+        ensure!(
+            self.signatures.len() >= public_keys.threshold(),
+            "Statement is signed by {} peers but {} signatures are required",
+            self.signatures.len(),
+            public_keys.threshold()
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    use bitcoin_hashes::Hash;
+    use rand::rngs::OsRng;
+    use secp256k1_zkp::Secp256k1;
+
+    use super::{
+        latest_liabilities_statement_session, LiabilitiesStatement, ModuleLiabilities, ReserveUtxo,
+        SignedLiabilitiesStatement,
+    };
+    use crate::{Amount, PeerId};
+
+    fn statement() -> LiabilitiesStatement {
+        LiabilitiesStatement {
+            session_index: 200,
+            modules: BTreeMap::from([
+                (
+                    0,
+                    ModuleLiabilities {
+                        liabilities: Amount::from_sats(1_000),
+                        reserves: vec![],
+                    },
+                ),
+                (
+                    1,
+                    ModuleLiabilities {
+                        liabilities: Amount::ZERO,
+                        reserves: vec![ReserveUtxo {
+                            outpoint: bitcoin::OutPoint {
+                                txid: bitcoin::Txid::from_inner([42; 32]),
+                                vout: 1,
+                            },
+                            amount: bitcoin::Amount::from_sat(2_000),
+                            script_pubkey: bitcoin::Script::from_str("0014deadbeef").unwrap(),
+                        }],
+                    },
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn latest_statement_session() {
+        assert_eq!(latest_liabilities_statement_session(0), None);
+        assert_eq!(latest_liabilities_statement_session(1), Some(0));
+        assert_eq!(latest_liabilities_statement_session(100), Some(0));
+        assert_eq!(latest_liabilities_statement_session(101), Some(100));
+    }
+
+    #[test]
+    fn verify_threshold_signatures() {
+        let secp = Secp256k1::new();
+        let key_pairs = (0..4)
+            .map(|_| bitcoin::KeyPair::new(&secp, &mut OsRng))
+            .collect::<Vec<_>>();
+        let public_keys = key_pairs
+            .iter()
+            .enumerate()
+            .map(|(peer, key_pair)| (PeerId::from(peer as u16), key_pair.public_key()))
+            .collect::<BTreeMap<_, _>>();
+
+        let statement = statement();
+        assert_eq!(statement.total_liabilities(), Amount::from_sats(1_000));
+        assert_eq!(statement.total_reserves(), bitcoin::Amount::from_sat(2_000));
+
+        let signatures = key_pairs
+            .iter()
+            .enumerate()
+            .map(|(peer, key_pair)| {
+                (
+                    PeerId::from(peer as u16),
+                    secp.sign_schnorr(&statement.signing_message(), key_pair),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let mut signed = SignedLiabilitiesStatement {
+            statement,
+            signatures,
+        };
+        assert!(signed.verify(&public_keys).is_ok());
+
+        // one signature short of the threshold of three
+        signed.signatures.retain(|peer, _| peer.to_usize() < 2);
+        assert!(signed.verify(&public_keys).is_err());
+
+        // a signature over a different statement
+        let mut tampered = signed.clone();
+        tampered.signatures.insert(
+            PeerId::from(2),
+            secp.sign_schnorr(&tampered.statement.signing_message(), &key_pairs[2]),
+        );
+        assert!(tampered.verify(&public_keys).is_ok());
+        tampered.statement.session_index += 1;
+        assert!(tampered.verify(&public_keys).is_err());
+    }
+}
@@ -1,4 +1,6 @@
 pub mod audit;
+pub mod fee;
+pub mod liabilities;
 pub mod registry;
 
 use std::collections::BTreeMap;
@@ -18,8 +20,8 @@ use tracing::instrument;
 mod version;
 pub use self::version::*;
 use crate::config::{
-    ClientModuleConfig, ConfigGenModuleParams, DkgPeerMsg, ModuleInitParams, ServerModuleConfig,
-    ServerModuleConsensusConfig,
+    ClientModuleConfig, ConfigGenModuleParams, DkgError, DkgPeerMsg, DkgPublicKeys,
+    DkgTranscriptRecorder, ModuleInitParams, ServerModuleConfig, ServerModuleConsensusConfig,
 };
 use crate::core::{
     ClientConfig, Decoder, DecoderBuilder, Input, InputError, ModuleConsensusItem,
@@ -31,7 +33,9 @@ use crate::db::{
 };
 use crate::encoding::{Decodable, DecodeError, Encodable};
 use crate::fmt_utils::AbbreviateHexBytes;
+use crate::health::HealthReport;
 use crate::module::audit::Audit;
+use crate::module::liabilities::ModuleLiabilities;
 use crate::net::peers::MuxPeerConnections;
 use crate::server::DynServerModule;
 use crate::task::{MaybeSend, TaskGroup};
@@ -150,6 +154,10 @@ impl ApiError {
     pub fn server_error(message: String) -> Self {
         Self::new(500, message)
     }
+
+    pub fn too_many_requests(message: String) -> Self {
+        Self::new(429, message)
+    }
 }
 
 /// State made available to all API endpoints for handling a request
@@ -505,6 +513,13 @@ pub trait IServerModuleInit: IDynCommonModuleInit {
         params: &ConfigGenModuleParams,
     ) -> DkgResult<ServerModuleConfig>;
 
+    async fn distributed_reshare(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+        previous: &PreviousModuleConfig,
+    ) -> DkgResult<ServerModuleConfig>;
+
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()>;
 
     fn get_client_config(
@@ -513,6 +528,11 @@ pub trait IServerModuleInit: IDynCommonModuleInit {
         config: &ServerModuleConsensusConfig,
     ) -> anyhow::Result<ClientModuleConfig>;
 
+    fn dkg_public_keys(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<BTreeMap<String, DkgPublicKeys>>;
+
     /// Retrieves the migrations map from the server module to be applied to the
     /// database before the module is initialized. The migrations map is
     /// indexed on the from version.
@@ -641,6 +661,25 @@ pub trait ServerModuleInit: ModuleInit + Sized {
         params: &ConfigGenModuleParams,
     ) -> DkgResult<ServerModuleConfig>;
 
+    /// Moves the key material of the module to a new set of guardians
+    ///
+    /// The guardians of `previous` reshare their key shares such that the
+    /// new guardians end up with shares of the same keys wherever the
+    /// cryptography allows it. Modules holding key material have to override
+    /// this, the default refuses to reshare so keys are never silently
+    /// replaced.
+    async fn distributed_reshare(
+        &self,
+        _peers: &PeerHandle,
+        _params: &ConfigGenModuleParams,
+        _previous: &PreviousModuleConfig,
+    ) -> DkgResult<ServerModuleConfig> {
+        Err(DkgError::Failed(anyhow::format_err!(
+            "Module {} does not support resharing",
+            Self::kind()
+        )))
+    }
+
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()>;
 
     /// Converts the consensus config into the client config
@@ -649,6 +688,17 @@ pub trait ServerModuleInit: ModuleInit + Sized {
         config: &ServerModuleConsensusConfig,
     ) -> anyhow::Result<<<Self as ModuleInit>::Common as CommonModuleInit>::ClientConfig>;
 
+    /// Public key shares of every key the module generated with
+    /// [`ServerModuleInit::distributed_gen`], keyed by the DKG key serialized
+    /// as JSON. Used to verify DKG transcripts offline, modules without
+    /// threshold keys can rely on the default implementation.
+    fn dkg_public_keys(
+        &self,
+        _config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<BTreeMap<String, DkgPublicKeys>> {
+        Ok(BTreeMap::new())
+    }
+
     /// Retrieves the migrations map from the server module to be applied to the
     /// database before the module is initialized. The migrations map is
     /// indexed on the from version.
@@ -711,6 +761,15 @@ where
         <Self as ServerModuleInit>::distributed_gen(self, peers, params).await
     }
 
+    async fn distributed_reshare(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+        previous: &PreviousModuleConfig,
+    ) -> DkgResult<ServerModuleConfig> {
+        <Self as ServerModuleInit>::distributed_reshare(self, peers, params, previous).await
+    }
+
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
         <Self as ServerModuleInit>::validate_config(self, identity, config)
     }
@@ -728,6 +787,13 @@ where
         )
     }
 
+    fn dkg_public_keys(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<BTreeMap<String, DkgPublicKeys>> {
+        <Self as ServerModuleInit>::dkg_public_keys(self, config)
+    }
+
     fn get_database_migrations(&self) -> BTreeMap<DatabaseVersion, ServerMigrationFn> {
         <Self as ServerModuleInit>::get_database_migrations(self)
     }
@@ -767,6 +833,10 @@ pub trait ServerModule: Debug + Sized {
 
     type Init: ServerModuleInit;
 
+    /// What [`Self::verify_inputs_batch`] learned about an input, the default
+    /// value has to stand for an input that was not verified yet
+    type VerifiedInput: Debug + Default + Send + Sync + 'static;
+
     fn module_kind() -> ModuleKind {
         // Note: All modules should define kinds as &'static str, so this doesn't
         // allocate
@@ -798,6 +868,24 @@ pub trait ServerModule: Debug + Sized {
         peer_id: PeerId,
     ) -> anyhow::Result<()>;
 
+    /// Verifies all inputs of a transaction that belong to this module at once
+    /// before they are processed one by one with
+    /// [`Self::process_verified_input`].
+    ///
+    /// Modules can use this to batch expensive checks like the verification of
+    /// signatures and return what they learned about every input, in the order
+    /// of `inputs`. An error rejects the transaction.
+    async fn verify_inputs_batch<'a, 'b>(
+        &'a self,
+        _dbtx: &mut DatabaseTransaction<'b>,
+        inputs: &[&<Self::Common as ModuleCommon>::Input],
+    ) -> Result<Vec<Self::VerifiedInput>, <Self::Common as ModuleCommon>::InputError> {
+        Ok(inputs
+            .iter()
+            .map(|_| Self::VerifiedInput::default())
+            .collect())
+    }
+
     /// Try to spend a transaction input. On success all necessary updates will
     /// be part of the database transaction. On failure (e.g. double spend)
     /// the database transaction is rolled back and the operation will take
@@ -808,6 +896,35 @@ pub trait ServerModule: Debug + Sized {
         input: &'b <Self::Common as ModuleCommon>::Input,
     ) -> Result<InputMeta, <Self::Common as ModuleCommon>::InputError>;
 
+    /// Like [`Self::process_input`], but skips the checks
+    /// [`Self::verify_inputs_batch`] already did for `input`
+    async fn process_verified_input<'a, 'b, 'c>(
+        &'a self,
+        dbtx: &mut DatabaseTransaction<'c>,
+        input: &'b <Self::Common as ModuleCommon>::Input,
+        _verified_input: Self::VerifiedInput,
+    ) -> Result<InputMeta, <Self::Common as ModuleCommon>::InputError> {
+        self.process_input(dbtx, input).await
+    }
+
+    /// JSON representation of an input shown to guardians exploring the
+    /// history of the federation, `None` if the module does not provide one
+    fn input_to_json(
+        &self,
+        _input: &<Self::Common as ModuleCommon>::Input,
+    ) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// JSON representation of an output shown to guardians exploring the
+    /// history of the federation, `None` if the module does not provide one
+    fn output_to_json(
+        &self,
+        _output: &<Self::Common as ModuleCommon>::Output,
+    ) -> Option<serde_json::Value> {
+        None
+    }
+
     /// Try to create an output (e.g. issue notes, peg-out BTC, …). On success
     /// all necessary updates to the database will be part of the database
     /// transaction. On failure (e.g. double spend) the database transaction
@@ -845,6 +962,23 @@ pub trait ServerModule: Debug + Sized {
         module_instance_id: ModuleInstanceId,
     );
 
+    /// Queries the database and returns the liabilities of the module towards
+    /// its users and the on-chain outputs backing them, as included in the
+    /// federation's proof-of-liabilities statements.
+    ///
+    /// Modules that neither owe funds to users nor hold on-chain reserves can
+    /// rely on the default implementation.
+    async fn liabilities(&self, _dbtx: &mut DatabaseTransaction<'_>) -> ModuleLiabilities {
+        ModuleLiabilities::default()
+    }
+
+    /// Reports the health of the module's dependencies for the health
+    /// endpoints of the guardian, keyed by check name. The checks are polled
+    /// by orchestration and must not block on external services.
+    async fn health(&self) -> HealthReport {
+        HealthReport::new()
+    }
+
     /// Returns a list of custom API endpoints defined by the module. These are
     /// made available both to users as well as to other modules. They thus
     /// should be deterministic, only dependant on their input and the
@@ -924,6 +1058,20 @@ impl<T: Encodable + Decodable + 'static> SerdeModuleEncoding<T> {
     }
 }
 
+/// The config of a module in the federation whose key material is reshared,
+/// passed to [`ServerModuleInit::distributed_reshare`]
+#[derive(Debug, Clone)]
+pub struct PreviousModuleConfig {
+    /// Peer ids of the guardians that were part of the previous federation in
+    /// it, keyed by their new peer id
+    pub previous_peers: BTreeMap<PeerId, PeerId>,
+    /// Consensus config of the module in the previous federation, all
+    /// continuing guardians agreed on it
+    pub consensus: ServerModuleConsensusConfig,
+    /// Our config in the previous federation, `None` if we are joining
+    pub ours: Option<ServerModuleConfig>,
+}
+
 /// A handle passed to [`ServerModuleInit::distributed_gen`]
 ///
 /// This struct encapsulates dkg data that the module should not have a direct
@@ -941,6 +1089,8 @@ pub struct PeerHandle<'a> {
     pub our_id: PeerId,
     #[doc(hidden)]
     pub peers: Vec<PeerId>,
+    #[doc(hidden)]
+    pub transcript: Option<DkgTranscriptRecorder>,
 }
 
 impl<'a> PeerHandle<'a> {
@@ -955,9 +1105,16 @@ impl<'a> PeerHandle<'a> {
             module_instance_id,
             our_id,
             peers,
+            transcript: None,
         }
     }
 
+    /// Records the public messages of the DKGs run with this handle
+    pub fn with_transcript(mut self, transcript: DkgTranscriptRecorder) -> Self {
+        self.transcript = Some(transcript);
+        self
+    }
+
     pub fn peer_ids(&self) -> &[PeerId] {
         self.peers.as_slice()
     }
@@ -182,6 +182,107 @@ impl<R: Eq + Clone + Debug, T> QueryStrategy<R, BTreeMap<PeerId, T>> for FilterM
     }
 }
 
+/// Returns when a threshold of peers sent valid responses that map to the same
+/// value, together with the data the filter map extracted from the response of
+/// every one of those peers, like their signature over the value. Valid
+/// responses that disagree with the others are only fatal once no value can
+/// reach the threshold anymore. The response of a peer is assumed to be final,
+/// hence this query strategy does not implement retry logic.
+pub struct FilterMapThresholdConsensus<R, V, T> {
+    #[allow(clippy::type_complexity)]
+    filter_map: Box<maybe_add_send_sync!(dyn Fn(PeerId, R) -> anyhow::Result<(V, T)>)>,
+    error_strategy: ErrorStrategy,
+    /// Valid responses grouped by the value they map to
+    filtered_responses: Vec<(V, BTreeMap<PeerId, T>)>,
+    /// Number of peers that responded, successfully or not
+    responded: usize,
+    total_peers: usize,
+    threshold: usize,
+}
+
+impl<R, V, T> FilterMapThresholdConsensus<R, V, T> {
+    pub fn new(
+        verifier: impl Fn(PeerId, R) -> anyhow::Result<(V, T)> + MaybeSend + MaybeSync + 'static,
+        total_peers: usize,
+    ) -> Self {
+        let max_evil = (total_peers - 1) / 3;
+        let threshold = total_peers - max_evil;
+
+        Self {
+            filter_map: Box::new(verifier),
+            error_strategy: ErrorStrategy::new(max_evil + 1),
+            filtered_responses: vec![],
+            responded: 0,
+            total_peers,
+            threshold,
+        }
+    }
+}
+
+impl<R: Eq + Clone + Debug, V: Eq, T> QueryStrategy<R, (V, BTreeMap<PeerId, T>)>
+    for FilterMapThresholdConsensus<R, V, T>
+{
+    fn process(
+        &mut self,
+        peer: PeerId,
+        result: PeerResult<R>,
+    ) -> QueryStep<(V, BTreeMap<PeerId, T>)> {
+        self.responded += 1;
+
+        let step = match result {
+            Ok(response) => match (self.filter_map)(peer, response) {
+                Ok((value, data)) => {
+                    let index = match self
+                        .filtered_responses
+                        .iter()
+                        .position(|(v, _)| *v == value)
+                    {
+                        Some(index) => index,
+                        None => {
+                            self.filtered_responses.push((value, BTreeMap::new()));
+                            self.filtered_responses.len() - 1
+                        }
+                    };
+
+                    let responses = &mut self.filtered_responses[index].1;
+                    responses.insert(peer, data);
+
+                    if responses.len() == self.threshold {
+                        return QueryStep::Success(self.filtered_responses.swap_remove(index));
+                    }
+
+                    QueryStep::Continue
+                }
+                Err(error) => self
+                    .error_strategy
+                    .process(peer, PeerError::InvalidResponse(error.to_string())),
+            },
+            Err(error) => self.error_strategy.process(peer, error),
+        };
+
+        let most_agreeing = self
+            .filtered_responses
+            .iter()
+            .map(|(_, responses)| responses.len())
+            .max()
+            .unwrap_or(0);
+
+        match step {
+            QueryStep::Continue
+                if most_agreeing + (self.total_peers - self.responded) < self.threshold =>
+            {
+                QueryStep::Failure {
+                    general: Some(anyhow!(
+                        "Peers sent conflicting responses, no response can reach the threshold"
+                    )),
+                    peers: mem::take(&mut self.error_strategy.errors),
+                }
+            }
+            step => step,
+        }
+    }
+}
+
 /// Returns when we obtain a threshold of identical responses
 pub struct ThresholdConsensus<R> {
     error_strategy: ErrorStrategy,
@@ -680,3 +781,55 @@ fn discover_common_api_versions_set(
             .collect(),
     })
 }
+
+#[test]
+fn filter_map_threshold_consensus_ignores_conflicting_peer() {
+    let mut strategy =
+        FilterMapThresholdConsensus::new(|peer, response: u64| Ok((response, peer.to_usize())), 4);
+
+    assert!(matches!(
+        strategy.process(PeerId(0), Ok(1)),
+        QueryStep::Continue
+    ));
+    assert!(matches!(
+        strategy.process(PeerId(1), Ok(2)),
+        QueryStep::Continue
+    ));
+    assert!(matches!(
+        strategy.process(PeerId(2), Ok(2)),
+        QueryStep::Continue
+    ));
+
+    match strategy.process(PeerId(3), Ok(2)) {
+        QueryStep::Success((value, responses)) => {
+            assert_eq!(value, 2);
+            assert_eq!(
+                responses,
+                BTreeMap::from([(PeerId(1), 1), (PeerId(2), 2), (PeerId(3), 3)])
+            );
+        }
+        step => panic!("Expected success, got {step:?}"),
+    }
+}
+
+#[test]
+fn filter_map_threshold_consensus_fails_without_threshold() {
+    let mut strategy = FilterMapThresholdConsensus::new(|_, response: u64| Ok((response, ())), 4);
+
+    assert!(matches!(
+        strategy.process(PeerId(0), Ok(1)),
+        QueryStep::Continue
+    ));
+    assert!(matches!(
+        strategy.process(PeerId(1), Ok(1)),
+        QueryStep::Continue
+    ));
+    assert!(matches!(
+        strategy.process(PeerId(2), Ok(2)),
+        QueryStep::Continue
+    ));
+    assert!(matches!(
+        strategy.process(PeerId(3), Ok(2)),
+        QueryStep::Failure { .. }
+    ));
+}
@@ -13,7 +13,7 @@ use fedimint_core::db::{
 use fedimint_core::encoding::Encodable;
 use fedimint_core::module::__reexports::serde_json;
 use fedimint_core::module::registry::ModuleDecoderRegistry;
-use fedimint_core::push_db_pair_items_no_serde;
+use fedimint_core::{push_db_pair_items, push_db_pair_items_no_serde};
 use fedimint_rocksdb::RocksDbReadOnly;
 use fedimint_server::config::io::read_server_config;
 use fedimint_server::config::ServerConfig;
@@ -294,6 +294,52 @@ impl DatabaseDump {
                         "Aleph Units"
                     );
                 }
+                ConsensusRange::DbKeyPrefix::SessionAuditSummary => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::SessionAuditSummaryPrefix,
+                        ConsensusRange::SessionAuditSummaryKey,
+                        fedimint_core::module::audit::SessionAuditSummary,
+                        consensus,
+                        "Session Audit Summaries"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::LiabilitiesStatement => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::LiabilitiesStatementPrefix,
+                        ConsensusRange::LiabilitiesStatementKey,
+                        fedimint_core::module::liabilities::LiabilitiesStatement,
+                        consensus,
+                        "Liabilities Statements"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::HealthCheck => {
+                    if let Some(last_check) = dbtx.get_value(&ConsensusRange::HealthCheckKey).await
+                    {
+                        consensus.insert("Health Check".to_string(), Box::new(last_check));
+                    }
+                }
+                ConsensusRange::DbKeyPrefix::CarriedOverItems => {
+                    if let Some(carried_over_items) =
+                        dbtx.get_value(&ConsensusRange::CarriedOverItemsKey).await
+                    {
+                        consensus.insert(
+                            "Carried Over Items".to_string(),
+                            Box::new(carried_over_items),
+                        );
+                    }
+                }
+                ConsensusRange::DbKeyPrefix::TransactionLocation => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::TransactionLocationPrefix,
+                        ConsensusRange::TransactionLocationKey,
+                        fedimint_core::api::TransactionLocation,
+                        consensus,
+                        "Transaction Locations"
+                    );
+                }
                 // Module is a global prefix for all module data
                 ConsensusRange::DbKeyPrefix::Module => {}
             }
@@ -1,16 +1,29 @@
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::routing::get;
-use axum::Router;
+use axum::{Json, Router};
+use fedimint_core::health::{is_healthy, DynHealthChecks, HealthReport, HealthStatus};
 use fedimint_core::task::{TaskGroup, TaskShutdownToken};
 pub use lazy_static::lazy_static;
 pub use prometheus::{
-    self, histogram_opts, opts, register_histogram, register_int_counter, Encoder, Histogram,
-    IntCounter, TextEncoder,
+    self, histogram_opts, opts, register_histogram, register_int_counter, register_int_counter_vec,
+    Encoder, Histogram, IntCounter, IntCounterVec, TextEncoder,
 };
+use tokio::sync::Mutex;
 use tracing::error;
 
+/// How long the results of the health checks are served from the cache
+const HEALTH_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// How often the health checks may run within [`HEALTH_CACHE_TTL`] before
+/// requests that miss the cache are rejected
+const MAX_HEALTH_EVALUATIONS_PER_TTL: u32 = 10;
+
 async fn get_metrics() -> (StatusCode, String) {
     let metric_families = prometheus::gather();
     let result = || -> anyhow::Result<String> {
@@ -25,11 +38,171 @@ async fn get_metrics() -> (StatusCode, String) {
     }
 }
 
+/// Liveness probe, answering at all means the process is not stuck
+async fn get_live() -> StatusCode {
+    StatusCode::OK
+}
+
+fn health_status_code(healthy: bool) -> StatusCode {
+    if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Caches the results of the health checks, which are served without
+/// authentication and may write to the database, and limits how often they
+/// run. Evaluations are serialized by the lock on the cache.
+#[derive(Debug)]
+struct HealthCache {
+    checks: DynHealthChecks,
+    state: Mutex<HealthCacheState>,
+}
+
+#[derive(Debug)]
+struct HealthCacheState {
+    report: Option<(Instant, HealthReport)>,
+    checks: BTreeMap<String, (Instant, Option<HealthStatus>)>,
+    window_start: Instant,
+    evaluations: u32,
+}
+
+fn is_fresh(time: Instant) -> bool {
+    time.elapsed() < HEALTH_CACHE_TTL
+}
+
+impl HealthCacheState {
+    /// Counts an evaluation of the checks, fails if there were too many
+    /// evaluations recently
+    fn try_evaluate(&mut self) -> Result<(), StatusCode> {
+        if !is_fresh(self.window_start) {
+            self.window_start = Instant::now();
+            self.evaluations = 0;
+        }
+
+        if MAX_HEALTH_EVALUATIONS_PER_TTL <= self.evaluations {
+            return Err(StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        self.evaluations += 1;
+
+        Ok(())
+    }
+}
+
+impl HealthCache {
+    fn new(checks: DynHealthChecks) -> Self {
+        HealthCache {
+            checks,
+            state: Mutex::new(HealthCacheState {
+                report: None,
+                checks: BTreeMap::new(),
+                window_start: Instant::now(),
+                evaluations: 0,
+            }),
+        }
+    }
+
+    async fn report(&self) -> Result<HealthReport, StatusCode> {
+        let mut state = self.state.lock().await;
+
+        if let Some((time, report)) = &state.report {
+            if is_fresh(*time) {
+                return Ok(report.clone());
+            }
+        }
+
+        state.try_evaluate()?;
+
+        let report = self.checks.health_checks().await;
+        state.report = Some((Instant::now(), report.clone()));
+
+        Ok(report)
+    }
+
+    async fn check(&self, name: &str) -> Result<Option<HealthStatus>, StatusCode> {
+        let mut state = self.state.lock().await;
+
+        if let Some((time, report)) = &state.report {
+            if is_fresh(*time) {
+                return Ok(report.get(name).cloned());
+            }
+        }
+
+        if let Some((time, status)) = state.checks.get(name) {
+            if is_fresh(*time) {
+                return Ok(status.clone());
+            }
+        }
+
+        state.try_evaluate()?;
+
+        let status = self.checks.health_check(name).await;
+
+        // Stale entries are removed so that the cache is bounded by the number of
+        // evaluations allowed within the ttl
+        state.checks.retain(|_, (time, _)| is_fresh(*time));
+        state
+            .checks
+            .insert(name.to_owned(), (Instant::now(), status.clone()));
+
+        Ok(status)
+    }
+}
+
+/// Readiness probe reporting all checks, fails if any of them failed
+async fn get_health(
+    State(cache): State<Arc<HealthCache>>,
+) -> Result<(StatusCode, Json<HealthReport>), StatusCode> {
+    let report = cache.report().await?;
+
+    Ok((health_status_code(is_healthy(&report)), Json(report)))
+}
+
+async fn get_health_check(
+    State(cache): State<Arc<HealthCache>>,
+    Path(name): Path<String>,
+) -> Result<(StatusCode, Json<HealthStatus>), StatusCode> {
+    let status = cache.check(&name).await?.ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((health_status_code(status.healthy), Json(status)))
+}
+
 pub async fn run_api_server(
     bind_address: &SocketAddr,
     task_group: &mut TaskGroup,
 ) -> anyhow::Result<TaskShutdownToken> {
-    let app = Router::new().route("/metrics", get(get_metrics));
+    run_api_server_with_health(bind_address, task_group, None).await
+}
+
+/// Serves the prometheus metrics and, if `health_checks` are given, the
+/// unauthenticated health endpoints:
+///
+/// * `/health/live` succeeds as long as the server is running
+/// * `/health` reports all checks and fails with status 503 if any failed
+/// * `/health/{check}` reports a single check the same way
+///
+/// Results of the checks are cached for [`HEALTH_CACHE_TTL`] and requests
+/// missing the cache fail with status 429 once the checks ran
+/// [`MAX_HEALTH_EVALUATIONS_PER_TTL`] times within that time.
+pub async fn run_api_server_with_health(
+    bind_address: &SocketAddr,
+    task_group: &mut TaskGroup,
+    health_checks: Option<DynHealthChecks>,
+) -> anyhow::Result<TaskShutdownToken> {
+    let mut app = Router::new().route("/metrics", get(get_metrics));
+
+    if let Some(health_checks) = health_checks {
+        app = app.merge(
+            Router::new()
+                .route("/health", get(get_health))
+                .route("/health/live", get(get_live))
+                .route("/health/:check", get(get_health_check))
+                .with_state(Arc::new(HealthCache::new(health_checks))),
+        );
+    }
+
     let server = axum::Server::bind(bind_address).serve(app.into_make_service());
 
     let handle = task_group.make_handle();
@@ -49,3 +222,67 @@ pub async fn run_api_server(
 
     Ok(shutdown_receiver)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use axum::http::StatusCode;
+    use fedimint_core::health::{HealthReport, HealthStatus, IHealthChecks};
+    use serde_json::json;
+
+    use super::{HealthCache, MAX_HEALTH_EVALUATIONS_PER_TTL};
+
+    #[derive(Debug, Default)]
+    struct CountingChecks {
+        evaluations: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl IHealthChecks for CountingChecks {
+        async fn health_checks(&self) -> HealthReport {
+            self.evaluations.fetch_add(1, Ordering::Relaxed);
+            HealthReport::from([("check".to_string(), HealthStatus::healthy(json!({})))])
+        }
+
+        async fn health_check(&self, name: &str) -> Option<HealthStatus> {
+            self.evaluations.fetch_add(1, Ordering::Relaxed);
+            (name == "check").then(|| HealthStatus::healthy(json!({})))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_cache() {
+        let checks = Arc::new(CountingChecks::default());
+        let cache = HealthCache::new(checks.clone());
+
+        assert!(cache.check("check").await.unwrap().is_some());
+        assert!(cache.check("check").await.unwrap().is_some());
+        assert_eq!(checks.evaluations.load(Ordering::Relaxed), 1);
+
+        assert!(cache.check("unknown").await.unwrap().is_none());
+        assert_eq!(checks.evaluations.load(Ordering::Relaxed), 2);
+
+        // a cached report answers single checks as well
+        assert_eq!(cache.report().await.unwrap().len(), 1);
+        assert!(cache.check("other").await.unwrap().is_none());
+        assert_eq!(cache.report().await.unwrap().len(), 1);
+        assert_eq!(checks.evaluations.load(Ordering::Relaxed), 3);
+
+        let cache = HealthCache::new(checks.clone());
+
+        for i in 0..MAX_HEALTH_EVALUATIONS_PER_TTL {
+            assert!(cache.check(&format!("unknown_{i}")).await.is_ok());
+        }
+        assert_eq!(
+            cache.check("unknown").await.unwrap_err(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            cache.report().await.unwrap_err(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+}
@@ -21,9 +21,10 @@ use fedimint_core::db::Database;
 use fedimint_core::endpoint_constants::{
     ADD_CONFIG_GEN_PEER_ENDPOINT, AUTH_ENDPOINT, CONFIG_GEN_PEERS_ENDPOINT,
     CONSENSUS_CONFIG_GEN_PARAMS_ENDPOINT, DEFAULT_CONFIG_GEN_PARAMS_ENDPOINT,
-    RESTART_FEDERATION_SETUP_ENDPOINT, RUN_DKG_ENDPOINT, SET_CONFIG_GEN_CONNECTIONS_ENDPOINT,
-    SET_CONFIG_GEN_PARAMS_ENDPOINT, SET_PASSWORD_ENDPOINT, START_CONSENSUS_ENDPOINT,
-    STATUS_ENDPOINT, VERIFIED_CONFIGS_ENDPOINT, VERIFY_CONFIG_HASH_ENDPOINT,
+    RESTART_FEDERATION_SETUP_ENDPOINT, RUN_DKG_ENDPOINT, SERVER_CONFIG_CONSENSUS_HASH_ENDPOINT,
+    SET_CONFIG_GEN_CONNECTIONS_ENDPOINT, SET_CONFIG_GEN_PARAMS_ENDPOINT, SET_PASSWORD_ENDPOINT,
+    START_CONSENSUS_ENDPOINT, STATUS_ENDPOINT, VERIFIED_CONFIGS_ENDPOINT,
+    VERIFY_CONFIG_HASH_ENDPOINT,
 };
 use fedimint_core::module::{
     api_endpoint, ApiAuth, ApiEndpoint, ApiEndpointContext, ApiError, ApiRequestErased, ApiVersion,
@@ -37,8 +38,10 @@ use tokio_rustls::rustls;
 use tracing::{error, info};
 
 use crate::config::io::{
-    read_server_config, write_server_config, CONFIG_STAGING_DIR, PLAINTEXT_PASSWORD, SALT_FILE,
+    read_server_config, write_dkg_transcript, write_server_config, CONFIG_STAGING_DIR,
+    PLAINTEXT_PASSWORD, SALT_FILE,
 };
+use crate::config::transcript::SignedDkgTranscript;
 use crate::config::{gen_cert_and_key, ConfigGenParams, ServerConfig};
 use crate::envs::FM_PEER_ID_SORT_BY_URL_ENV;
 use crate::net::peers::DelayCalculator;
@@ -51,7 +54,8 @@ pub struct ConfigGenApi {
     data_dir: PathBuf,
     /// In-memory state machine
     state: Arc<Mutex<ConfigGenState>>,
-    /// DB not really used
+    /// DB the state of the previous federation is transferred into when
+    /// resharing its keys
     db: Database,
     /// Tracks when the config is generated
     config_generated_tx: Sender<ServerConfig>,
@@ -219,7 +223,8 @@ impl ConfigGenApi {
     /// blocking until completion, which can be fragile due to timeouts, poor
     /// network connections, etc.
     ///
-    /// Calling a second time will return an error.
+    /// Calling a second time will return an error, unless the first call
+    /// failed to notify the leader.
     pub async fn run_dkg(&self) -> ApiResult<()> {
         let leader = {
             let mut state = self.require_status(ServerStatus::SharingConfigGenParams)?;
@@ -236,7 +241,11 @@ impl ConfigGenApi {
                 .and_then(|local| local.leader_api_url.map(WsAdminClient::new))
         };
 
-        self.update_leader().await?;
+        if let Err(error) = self.update_leader().await {
+            // Allow running DKG again once the leader can be reached
+            self.state.lock().expect("lock poisoned").status = ServerStatus::SharingConfigGenParams;
+            return Err(error);
+        }
 
         let self_clone = self.clone();
         let sub_group = self.task_group.make_subgroup().await;
@@ -258,12 +267,13 @@ impl ConfigGenApi {
                 // Get params and registry
                 let request = self_clone.get_requested_params()?;
                 let response = self_clone.consensus_config_gen_params(&request).await?;
-                let (params, registry) = {
+                let (params, registry, previous_config) = {
                     let state: MutexGuard<'_, ConfigGenState> =
                         self_clone.require_status(ServerStatus::ReadyForConfigGen)?;
                     let params = state.get_config_gen_params(&request, response.consensus)?;
                     let registry = state.settings.registry.clone();
-                    (params, registry)
+                    let previous_config = state.settings.previous_config.clone();
+                    (params, registry, previous_config)
                 };
 
                 // Run DKG
@@ -274,6 +284,8 @@ impl ConfigGenApi {
                     DelayCalculator::PROD_DEFAULT,
                     &mut task_group,
                     self_clone.version_hash.clone(),
+                    previous_config.as_ref(),
+                    &self_clone.db,
                 )
                 .await;
                 task_group
@@ -284,8 +296,8 @@ impl ConfigGenApi {
                 {
                     let mut state = self_clone.state.lock().expect("lock poisoned");
                     match config {
-                        Ok(config) => {
-                            self_clone.stage_configs(&config, &state)?;
+                        Ok((config, transcript)) => {
+                            self_clone.stage_configs(&config, transcript.as_ref(), &state)?;
                             state.status = ServerStatus::VerifyingConfigs;
                             state.config = Some(config);
                             info!(
@@ -329,8 +341,29 @@ impl ConfigGenApi {
         Ok(get_verification_hashes(&config))
     }
 
+    /// Returns the hash of the generated consensus config, which has to match
+    /// the hash of every other peer
+    pub fn consensus_config_hash(&self) -> ApiResult<sha256::Hash> {
+        let expected_status = [
+            ServerStatus::VerifyingConfigs,
+            ServerStatus::VerifiedConfigs,
+        ];
+        let state = self.require_any_status(&expected_status)?;
+        let config = state
+            .config
+            .as_ref()
+            .ok_or(ApiError::bad_request("Missing config".to_string()))?;
+
+        Ok(config.consensus.consensus_hash())
+    }
+
     /// Writes the configs to a staging directory disk after they are generated
-    fn stage_configs(&self, config: &ServerConfig, state: &ConfigGenState) -> ApiResult<()> {
+    fn stage_configs(
+        &self,
+        config: &ServerConfig,
+        transcript: Option<&SignedDkgTranscript>,
+        state: &ConfigGenState,
+    ) -> ApiResult<()> {
         let cfg_staging_dir = self.data_dir.join(CONFIG_STAGING_DIR);
         fs::create_dir_all(&cfg_staging_dir)
             .map_err(|e| ApiError::server_error(format!("Unable to modify data dir {e:?}")))?;
@@ -341,6 +374,11 @@ impl ConfigGenApi {
         // TODO: Make writing password optional
         write_new(cfg_staging_dir.join(PLAINTEXT_PASSWORD), &auth).map_err(io_error)?;
         write_new(cfg_staging_dir.join(SALT_FILE), random_salt()).map_err(io_error)?;
+        if let Some(transcript) = transcript {
+            write_dkg_transcript(transcript, &cfg_staging_dir).map_err(|e| {
+                ApiError::server_error(format!("Unable to write DKG transcript {e:?}"))
+            })?;
+        }
         write_server_config(config, cfg_staging_dir, &auth, &state.settings.registry)
             .map_err(|e| ApiError::server_error(format!("Unable to encrypt configs {e:?}")))
     }
@@ -566,6 +604,9 @@ pub struct ConfigGenSettings {
     pub max_connections: u32,
     /// Registry for config gen
     pub registry: ServerModuleInitRegistry,
+    /// Config of the federation whose keys we reshare, see
+    /// [`crate::config::reshare`]
+    pub previous_config: Option<ServerConfig>,
 }
 
 /// State held by the API after receiving a `ConfigGenConnectionsRequest`
@@ -845,6 +886,13 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConfigGenApi>> {
                 config.verify_config_hash()
             }
         },
+        api_endpoint! {
+            SERVER_CONFIG_CONSENSUS_HASH_ENDPOINT,
+            ApiVersion::new(0, 0),
+            async |config: &ConfigGenApi, _context, _v: ()| -> sha256::Hash {
+                config.consensus_config_hash()
+            }
+        },
         api_endpoint! {
             VERIFIED_CONFIGS_ENDPOINT,
             ApiVersion::new(0, 0),
@@ -928,6 +976,7 @@ mod tests {
     use crate::config::io::{read_server_config, CONFIG_STAGING_DIR, PLAINTEXT_PASSWORD};
     use crate::config::{DynServerModuleInit, ServerConfig, DEFAULT_MAX_CLIENT_CONNECTIONS};
     use crate::fedimint_core::module::ServerModuleInit;
+    use crate::health::ServerHealth;
     use crate::FedimintServer;
 
     /// Helper in config API tests for simulating a guardian's client and server
@@ -975,6 +1024,7 @@ mod tests {
                 registry: ServerModuleInitRegistry::from(vec![DynServerModuleInit::from(
                     DummyInit,
                 )]),
+                previous_config: None,
             };
             let dir = data_dir.join(name_suffix.to_string());
             fs::create_dir_all(dir.clone()).expect("Unable to create test dir");
@@ -982,7 +1032,9 @@ mod tests {
             let api = FedimintServer {
                 data_dir: dir.clone(),
                 settings: settings.clone(),
+                health: ServerHealth::new(db.clone()),
                 db,
+                setup: None,
                 version_hash: "dummyversionhash".to_owned(),
             };
 
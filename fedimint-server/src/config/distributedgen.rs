@@ -1,4 +1,4 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::io::Write;
@@ -8,7 +8,8 @@ use async_trait::async_trait;
 use bitcoin::secp256k1;
 use bitcoin_hashes::sha256::{Hash as Sha256, HashEngine};
 use fedimint_core::config::{
-    DkgError, DkgGroup, DkgMessage, DkgPeerMsg, DkgResult, ISupportedDkgMessage,
+    DkgError, DkgGroup, DkgMessage, DkgPeerMsg, DkgResult, DkgTranscriptRecorder, ExtractProof,
+    ISupportedDkgMessage,
 };
 use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
 use fedimint_core::encoding::{Decodable, Encodable};
@@ -159,13 +160,21 @@ impl<G: DkgGroup> Dkg<G> {
 
                 if self.sk_shares.len() == self.peers.len() {
                     let extract: Vec<G> = self.f1_poly.iter().map(|c| self.gen_g * *c).collect();
+                    let proof = prove_extract(
+                        self.gen_g,
+                        self.our_id,
+                        &self.f1_poly,
+                        &self.f2_poly,
+                        &self.commitments[&self.our_id],
+                        &extract,
+                    );
 
                     self.pk_shares.insert(self.our_id, extract.clone());
-                    return Ok(self.broadcast(DkgMessage::Extract(extract)));
+                    return Ok(self.broadcast(DkgMessage::Extract(extract, proof)));
                 }
             }
             // Feldman-VSS exposes the public key shares
-            DkgMessage::Extract(extract) => {
+            DkgMessage::Extract(extract, proof) => {
                 let share = self
                     .sk_shares
                     .get(&peer)
@@ -180,6 +189,7 @@ impl<G: DkgGroup> Dkg<G> {
 
                 ensure!(share_product == extract_product, "bad extract from {peer}");
                 ensure!(self.threshold == extract.len(), "wrong degree from {peer}");
+                verify_extract(self.gen_g, peer, &self.commitments[&peer], &extract, &proof)?;
                 match self.pk_shares.get(&peer) {
                     Some(old) if *old != extract => {
                         return Err(format_err!("{peer} sent us two extracts!"))
@@ -206,19 +216,16 @@ impl<G: DkgGroup> Dkg<G> {
                     }));
                 }
             }
+            DkgMessage::Reshare(..) => {
+                return Err(format_err!("{peer} sent us a reshare message during DKG"));
+            }
         }
 
         Ok(DkgStep::Messages(vec![]))
     }
 
     fn hash(&self, poly: Vec<G>) -> Sha256 {
-        let mut engine = HashEngine::default();
-        for element in poly.iter() {
-            engine
-                .write_all(element.to_bytes().as_ref())
-                .expect("hashes");
-        }
-        Sha256::from_engine(engine)
+        hash_commitment(&poly)
     }
 
     fn broadcast(&self, msg: DkgMessage<G>) -> DkgStep<G> {
@@ -226,16 +233,354 @@ impl<G: DkgGroup> Dkg<G> {
         DkgStep::Messages(others.map(|peer| (*peer, msg.clone())).collect())
     }
 
-    /// Get a second generator by hashing the first one to the curve
     fn gen_h(&self) -> G {
-        let mut hash_engine = sha3::Sha3_256::new();
+        gen_h(self.gen_g)
+    }
+}
+
+/// Get a second generator by hashing the first one to the curve
+fn gen_h<G: DkgGroup>(gen_g: G) -> G {
+    let mut hash_engine = sha3::Sha3_256::new();
+
+    hash_engine.update(gen_g.to_bytes().as_ref());
+
+    G::random(&mut ChaChaRng::from_seed(hash_engine.finalize().into()))
+}
+
+/// Proves that `extract` is the part of `commit` in `gen_g`, see
+/// [`ExtractProof`]
+fn prove_extract<G: DkgGroup>(
+    gen_g: G,
+    dealer: PeerId,
+    f1_poly: &[Scalar],
+    f2_poly: &[Scalar],
+    commit: &[G],
+    extract: &[G],
+) -> ExtractProof<G> {
+    let nonces_g_secret = random_scalar_coefficients(f1_poly.len() - 1, &mut OsRng);
+    let nonces_h_secret = random_scalar_coefficients(f2_poly.len() - 1, &mut OsRng);
+
+    let nonces_g = nonces_g_secret
+        .iter()
+        .map(|r| gen_g * *r)
+        .collect::<Vec<_>>();
+    let nonces_h = nonces_h_secret
+        .iter()
+        .map(|r| gen_h(gen_g) * *r)
+        .collect::<Vec<_>>();
+
+    let challenge = extract_challenge(dealer, commit, extract, &nonces_g, &nonces_h);
+
+    ExtractProof {
+        nonces_g,
+        nonces_h,
+        responses_g: nonces_g_secret
+            .iter()
+            .zip(f1_poly)
+            .map(|(r, a)| *r + challenge * *a)
+            .collect(),
+        responses_h: nonces_h_secret
+            .iter()
+            .zip(f2_poly)
+            .map(|(r, b)| *r + challenge * *b)
+            .collect(),
+    }
+}
+
+/// Verifies that `extract` is the part of the commitment `commit` of `dealer`
+/// in `gen_g`, see [`ExtractProof`]
+pub fn verify_extract<G: DkgGroup>(
+    gen_g: G,
+    dealer: PeerId,
+    commit: &[G],
+    extract: &[G],
+    proof: &ExtractProof<G>,
+) -> anyhow::Result<()> {
+    let degree = commit.len();
+
+    ensure!(
+        extract.len() == degree
+            && proof.nonces_g.len() == degree
+            && proof.nonces_h.len() == degree
+            && proof.responses_g.len() == degree
+            && proof.responses_h.len() == degree,
+        "wrong extract proof degree from {dealer}"
+    );
+
+    let challenge = extract_challenge(dealer, commit, extract, &proof.nonces_g, &proof.nonces_h);
+
+    for idx in 0..degree {
+        ensure!(
+            gen_g * proof.responses_g[idx] == proof.nonces_g[idx] + extract[idx] * challenge,
+            "bad extract proof from {dealer}"
+        );
+        ensure!(
+            gen_h(gen_g) * proof.responses_h[idx]
+                == proof.nonces_h[idx] + (commit[idx] - extract[idx]) * challenge,
+            "bad extract proof from {dealer}"
+        );
+    }
+
+    Ok(())
+}
+
+/// Fiat-Shamir challenge of an [`ExtractProof`]
+fn extract_challenge<G: DkgGroup>(
+    dealer: PeerId,
+    commit: &[G],
+    extract: &[G],
+    nonces_g: &[G],
+    nonces_h: &[G],
+) -> Scalar {
+    let mut hash_engine = sha3::Sha3_256::new();
+
+    hash_engine.update(b"fedimint-dkg-extract-proof");
+    hash_engine.update(dealer.to_usize().to_be_bytes());
+
+    for element in [commit, extract, nonces_g, nonces_h].into_iter().flatten() {
+        hash_engine.update(element.to_bytes().as_ref());
+    }
+
+    Scalar::random(&mut ChaChaRng::from_seed(hash_engine.finalize().into()))
+}
 
-        hash_engine.update(self.gen_g.clone().to_bytes().as_ref());
+/// Shares of a threshold key of the federation that is reshared, see
+/// [`Reshare`]
+#[derive(Debug, Clone)]
+pub struct PreviousKeys<G> {
+    /// Number of shares required to use the key
+    pub threshold: usize,
+    /// Public key shares of the previous peers keyed by their previous peer id
+    pub public_key_shares: BTreeMap<PeerId, G>,
+    /// Our secret key share, `None` if we were not part of the previous
+    /// federation
+    pub secret_key_share: Option<Scalar>,
+}
 
-        G::random(&mut ChaChaRng::from_seed(hash_engine.finalize().into()))
+impl PreviousKeys<G1Projective> {
+    /// Previous keys of a `threshold_crypto` key generated by [`Dkg`], our
+    /// secret key share is passed as the scalar kept in
+    /// [`ThresholdKeys::secret_key_scalar`]
+    pub fn threshold_crypto(
+        public_key_set: &PublicKeySet,
+        previous_peers: impl IntoIterator<Item = PeerId>,
+        secret_key_scalar: Option<Scalar>,
+    ) -> Self {
+        let commitment = public_key_set.coefficients().cloned().collect::<Vec<_>>();
+
+        PreviousKeys {
+            threshold: public_key_set.threshold() + 1,
+            public_key_shares: previous_peers
+                .into_iter()
+                .map(|peer| (peer, evaluate_commitment(&commitment, &scalar(&peer))))
+                .collect(),
+            secret_key_share: secret_key_scalar,
+        }
     }
 }
 
+/// Implementation of "Verifiable Secret Redistribution for Threshold Sharing
+/// Schemes" by Theodore M. Wong, Chenxi Wang and Jeannette M. Wing
+///
+/// Every dealer, a peer holding a share of the previous key, shares its key
+/// share with a new random polynomial of the new degree and commits to it with
+/// Feldman-VSS. The constant term of every commitment has to be the public key
+/// share of the dealer, so the new shares interpolate to the same secret and
+/// the aggregate public key does not change.
+///
+/// Like [`Dkg`] this fails with any non-cooperative peers. A dealer sending
+/// different commitments to different peers results in different configs,
+/// which the guardians detect by comparing their config hashes.
+struct Reshare<G> {
+    gen_g: G,
+    our_id: PeerId,
+    threshold: usize,
+    /// Previous peer ids of the dealers keyed by their new peer id
+    dealers: BTreeMap<PeerId, PeerId>,
+    previous_pk_shares: BTreeMap<PeerId, G>,
+    commitments: BTreeMap<PeerId, Vec<G>>,
+    sk_shares: BTreeMap<PeerId, Scalar>,
+}
+
+impl<G: DkgGroup> Reshare<G> {
+    /// Creates the resharing and, if we are a dealer, sends our shares
+    pub fn new(
+        group: G,
+        our_id: PeerId,
+        peers: Vec<PeerId>,
+        threshold: usize,
+        dealers: BTreeMap<PeerId, PeerId>,
+        previous: PreviousKeys<G>,
+        rng: &mut impl rand::RngCore,
+    ) -> anyhow::Result<(Self, DkgStep<G>)> {
+        ensure!(
+            dealers.len() >= previous.threshold,
+            "Resharing requires {} guardians of the previous federation but only {} take part",
+            previous.threshold,
+            dealers.len()
+        );
+        ensure!(
+            dealers.values().collect::<BTreeSet<_>>().len() == dealers.len(),
+            "Two dealers claim the same previous peer id"
+        );
+
+        for (peer, previous_peer) in &dealers {
+            ensure!(
+                peers.contains(peer),
+                "Dealer {peer} is not one of the peers"
+            );
+            ensure!(
+                previous.public_key_shares.contains_key(previous_peer),
+                "Unknown previous peer {previous_peer}"
+            );
+        }
+
+        let step = match dealers.get(&our_id) {
+            Some(previous_peer) => {
+                let secret_key_share = previous
+                    .secret_key_share
+                    .ok_or_else(|| format_err!("We are a dealer without a previous key share"))?;
+
+                ensure!(
+                    group * secret_key_share == previous.public_key_shares[previous_peer],
+                    "Our previous key share does not match our public key share"
+                );
+
+                let mut poly = random_scalar_coefficients(threshold - 1, rng);
+                poly[0] = secret_key_share;
+
+                let commit: Vec<G> = poly.iter().map(|c| group * *c).collect();
+
+                // The share for ourselves is processed like any other
+                DkgStep::Messages(
+                    peers
+                        .iter()
+                        .map(|peer| {
+                            let share = evaluate_polynomial_scalar(&poly, &scalar(peer));
+                            (*peer, DkgMessage::Reshare(commit.clone(), share))
+                        })
+                        .collect(),
+                )
+            }
+            None => DkgStep::Messages(vec![]),
+        };
+
+        let reshare = Reshare {
+            gen_g: group,
+            our_id,
+            threshold,
+            dealers,
+            previous_pk_shares: previous.public_key_shares,
+            commitments: Default::default(),
+            sk_shares: Default::default(),
+        };
+
+        Ok((reshare, step))
+    }
+
+    /// Processes the commitment and share of dealer `peer`
+    pub fn step(&mut self, peer: PeerId, msg: DkgMessage<G>) -> anyhow::Result<DkgStep<G>> {
+        let DkgMessage::Reshare(commit, share) = msg else {
+            return Err(format_err!(
+                "{peer} sent us an unexpected message during resharing"
+            ));
+        };
+
+        let previous_peer = self
+            .dealers
+            .get(&peer)
+            .ok_or_else(|| format_err!("{peer} is not a dealer"))?;
+
+        ensure!(self.threshold == commit.len(), "wrong degree from {peer}");
+        ensure!(
+            commit[0] == self.previous_pk_shares[previous_peer],
+            "{peer} did not reshare its previous key share"
+        );
+        ensure!(
+            self.gen_g * share == evaluate_commitment(&commit, &scalar(&self.our_id)),
+            "bad share from {peer}"
+        );
+
+        match self.commitments.get(&peer) {
+            Some(old) if *old != commit => {
+                return Err(format_err!("{peer} sent us two commitments!"))
+            }
+            _ => self.commitments.insert(peer, commit),
+        };
+
+        match self.sk_shares.get(&peer) {
+            Some(old) if *old != share => return Err(format_err!("{peer} sent us two shares!")),
+            _ => self.sk_shares.insert(peer, share),
+        };
+
+        if self.sk_shares.len() < self.dealers.len() {
+            return Ok(DkgStep::Messages(vec![]));
+        }
+
+        // Interpolating the shares of the dealers at zero yields our share of the
+        // previous secret, all maps are ordered by the new peer id of the dealer
+        let lagrange =
+            lagrange_coefficients(&self.dealers.values().map(scalar).collect::<Vec<_>>());
+
+        let sks = self
+            .sk_shares
+            .values()
+            .zip(&lagrange)
+            .map(|(share, coefficient)| *share * coefficient)
+            .reduce(|a, b| a + b)
+            .expect("sums");
+
+        let pks: Vec<G> = (0..self.threshold)
+            .map(|idx| {
+                self.commitments
+                    .values()
+                    .zip(&lagrange)
+                    .map(|(commit, coefficient)| commit[idx] * *coefficient)
+                    .reduce(|a, b| a + b)
+                    .expect("sums")
+            })
+            .collect();
+
+        Ok(DkgStep::Result(DkgKeys {
+            public_key_set: pks,
+            secret_key_share: sks,
+        }))
+    }
+}
+
+/// A key generation protocol driven by [`DkgRunner`]
+trait DkgProtocol<G: DkgGroup>: Send + 'static {
+    fn step(&mut self, peer: PeerId, msg: DkgMessage<G>) -> anyhow::Result<DkgStep<G>>;
+}
+
+impl<G: DkgGroup> DkgProtocol<G> for Dkg<G> {
+    fn step(&mut self, peer: PeerId, msg: DkgMessage<G>) -> anyhow::Result<DkgStep<G>> {
+        Dkg::step(self, peer, msg)
+    }
+}
+
+impl<G: DkgGroup> DkgProtocol<G> for Reshare<G> {
+    fn step(&mut self, peer: PeerId, msg: DkgMessage<G>) -> anyhow::Result<DkgStep<G>> {
+        Reshare::step(self, peer, msg)
+    }
+}
+
+/// Identifies the DKG of `key` in the messages and transcripts of a module
+pub fn dkg_key<T: Serialize>(key: &T) -> String {
+    serde_json::to_string(key).expect("serialization can't fail")
+}
+
+/// Hash of a commitment to a polynomial, published before the commitment itself
+pub fn hash_commitment<G: DkgGroup>(poly: &[G]) -> Sha256 {
+    let mut engine = HashEngine::default();
+    for element in poly.iter() {
+        engine
+            .write_all(element.to_bytes().as_ref())
+            .expect("hashes");
+    }
+    Sha256::from_engine(engine)
+}
+
 /// PeerIds are offset by 1, since evaluating a poly at 0 reveals the secret
 pub fn scalar(peer: &PeerId) -> Scalar {
     Scalar::from(peer.to_usize() as u64 + 1)
@@ -245,6 +590,7 @@ pub struct DkgRunner<T> {
     peers: Vec<PeerId>,
     our_id: PeerId,
     dkg_config: HashMap<T, usize>,
+    transcript: Option<DkgTranscriptRecorder>,
 }
 
 /// Helper for running multiple DKGs over the same peer connections
@@ -262,6 +608,7 @@ where
             our_id: *our_id,
             peers: peers.to_vec(),
             dkg_config,
+            transcript: None,
         }
     }
 
@@ -275,6 +622,11 @@ where
         self.dkg_config.insert(key, threshold);
     }
 
+    /// Record the public messages sent and received by our DKGs
+    pub fn record_transcript(&mut self, transcript: Option<DkgTranscriptRecorder>) {
+        self.transcript = transcript;
+    }
+
     /// Create keys from G2 (96B keys, 48B messages) used in `tbs`
     pub async fn run_g2(
         &mut self,
@@ -308,31 +660,100 @@ where
     where
         DkgMessage<G>: ISupportedDkgMessage,
     {
-        // Use tokio channel to await on `recv` or we might block
-        let (send, mut receive) = tokio::sync::mpsc::channel(10_000);
+        let protocols = self
+            .dkg_config
+            .clone()
+            .into_iter()
+            .map(|(key, threshold)| {
+                let (dkg, step) = Dkg::new(
+                    group,
+                    self.our_id,
+                    self.peers.clone(),
+                    threshold,
+                    &mut OsRng,
+                );
+                (key, dkg, step)
+            })
+            .collect();
+
+        self.run_protocols(module_id, protocols, connections).await
+    }
 
-        // For every `key` we run DKG in a new tokio task
-        self.dkg_config
+    /// Reshares the `previous` keys held by the `dealers` to our peers, the
+    /// dealers are keyed by their new peer id and map to their previous one
+    pub async fn run_reshare<G: DkgGroup>(
+        &mut self,
+        module_id: ModuleInstanceId,
+        group: G,
+        dealers: &BTreeMap<PeerId, PeerId>,
+        mut previous: HashMap<T, PreviousKeys<G>>,
+        connections: &MuxPeerConnections<(ModuleInstanceId, String), DkgPeerMsg>,
+    ) -> DkgResult<HashMap<T, DkgKeys<G>>>
+    where
+        DkgMessage<G>: ISupportedDkgMessage,
+    {
+        let protocols = self
+            .dkg_config
             .clone()
             .into_iter()
-            .for_each(|(key, threshold)| {
-                let our_id = self.our_id;
-                let peers = self.peers.clone();
-                let connections = connections.clone();
-                let key = serde_json::to_string(&key).expect("serialization can't fail");
-                let send = send.clone();
-
-                spawn("dkg runner", async move {
-                    let (dkg, step) = Dkg::new(group, our_id, peers, threshold, &mut OsRng);
-                    let result =
-                        Self::run_dkg_key((module_id, key.clone()), connections, dkg, step).await;
-                    send.send((key, result)).await.expect("channel open");
-                });
+            .map(|(key, threshold)| {
+                let previous = previous
+                    .remove(&key)
+                    .ok_or_else(|| format_err!("No previous keys to reshare"))?;
+                let (reshare, step) = Reshare::new(
+                    group,
+                    self.our_id,
+                    self.peers.clone(),
+                    threshold,
+                    dealers.clone(),
+                    previous,
+                    &mut OsRng,
+                )?;
+                Ok((key, reshare, step))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        self.run_protocols(module_id, protocols, connections).await
+    }
+
+    /// Runs every protocol in a new tokio task, returning an error if any fails
+    async fn run_protocols<G: DkgGroup, P: DkgProtocol<G>>(
+        &self,
+        module_id: ModuleInstanceId,
+        protocols: Vec<(T, P, DkgStep<G>)>,
+        connections: &MuxPeerConnections<(ModuleInstanceId, String), DkgPeerMsg>,
+    ) -> DkgResult<HashMap<T, DkgKeys<G>>>
+    where
+        DkgMessage<G>: ISupportedDkgMessage,
+    {
+        // Use tokio channel to await on `recv` or we might block
+        let (send, mut receive) = tokio::sync::mpsc::channel(10_000);
+        let num_protocols = protocols.len();
+
+        for (key, protocol, step) in protocols {
+            let our_id = self.our_id;
+            let connections = connections.clone();
+            let transcript = self.transcript.clone();
+            let key = dkg_key(&key);
+            let send = send.clone();
+
+            spawn("dkg runner", async move {
+                let result = Self::run_dkg_key(
+                    (module_id, key.clone()),
+                    our_id,
+                    connections,
+                    protocol,
+                    step,
+                    transcript,
+                )
+                .await;
+                send.send((key, result)).await.expect("channel open");
             });
+        }
 
         // Collect every key, returning an error if any fails
         let mut results: HashMap<T, DkgKeys<G>> = HashMap::new();
-        while results.len() < self.dkg_config.len() {
+        while results.len() < num_protocols {
             let (key, result) = receive.recv().await.expect("channel open");
             let key = serde_json::from_str(&key).expect("serialization can't fail");
             results.insert(key, result?);
@@ -341,24 +762,55 @@ where
     }
 
     /// Runs the DKG algorithms for a given key and module id
-    async fn run_dkg_key<G: DkgGroup>(
+    ///
+    /// Messages a protocol addresses to ourselves are processed locally once
+    /// the messages to the other peers were sent. Public messages are added to
+    /// the `transcript`, our broadcasts only once instead of once per peer.
+    async fn run_dkg_key<G: DkgGroup, P: DkgProtocol<G>>(
         key_id: (ModuleInstanceId, String),
+        our_id: PeerId,
         connections: MuxPeerConnections<(ModuleInstanceId, String), DkgPeerMsg>,
-        mut dkg: Dkg<G>,
+        mut protocol: P,
         initial_step: DkgStep<G>,
+        transcript: Option<DkgTranscriptRecorder>,
     ) -> DkgResult<DkgKeys<G>>
     where
         DkgMessage<G>: ISupportedDkgMessage,
     {
-        if let DkgStep::Messages(messages) = initial_step {
+        let record = |peer: PeerId, msg: &DkgMessage<G>| {
+            if let Some(transcript) = transcript.as_ref().filter(|_| msg.is_public()) {
+                transcript.record(key_id.0, key_id.1.clone(), peer, msg.clone().to_msg());
+            }
+        };
+        let mut step = initial_step;
+
+        // process steps for each key
+        loop {
+            let messages = match step {
+                DkgStep::Messages(messages) => messages,
+                DkgStep::Result(result) => return Ok(result),
+            };
+
+            if let Some((_, msg)) = messages.iter().find(|(_, msg)| msg.is_public()) {
+                record(our_id, msg);
+            }
+
+            let mut own_message = None;
             for (peer, msg) in messages {
+                if peer == our_id {
+                    own_message = Some(msg);
+                    continue;
+                }
+
                 let send_msg = DkgPeerMsg::DistributedGen(msg.to_msg());
                 connections.send(&[peer], key_id.clone(), send_msg).await?;
             }
-        }
 
-        // process steps for each key
-        loop {
+            if let Some(msg) = own_message {
+                step = protocol.step(our_id, msg)?;
+                continue;
+            }
+
             let (peer, msg) = connections.receive(key_id.clone()).await?;
 
             let message = match msg {
@@ -369,19 +821,8 @@ where
             }?;
 
             let message = ISupportedDkgMessage::from_msg(message)?;
-            let step = dkg.step(peer, message)?;
-
-            match step {
-                DkgStep::Messages(messages) => {
-                    for (peer, msg) in messages {
-                        let send_msg = DkgPeerMsg::DistributedGen(msg.to_msg());
-                        connections.send(&[peer], key_id.clone(), send_msg).await?;
-                    }
-                }
-                DkgStep::Result(result) => {
-                    return Ok(result);
-                }
-            }
+            record(peer, &message);
+            step = protocol.step(peer, message)?;
         }
     }
 }
@@ -403,6 +844,30 @@ pub fn evaluate_polynomial_scalar(coefficients: &[Scalar], x: &Scalar) -> Scalar
         .expect("We have at least one coefficient")
 }
 
+/// Evaluates a polynomial given by the commitments to its coefficients
+pub fn evaluate_commitment<G: DkgGroup>(commitment: &[G], x: &Scalar) -> G {
+    commitment
+        .iter()
+        .cloned()
+        .rev()
+        .reduce(|acc, coefficient| acc * *x + coefficient)
+        .expect("We have at least one coefficient")
+}
+
+/// Coefficients interpolating a polynomial at zero from its values at `points`
+fn lagrange_coefficients(points: &[Scalar]) -> Vec<Scalar> {
+    points
+        .iter()
+        .map(|i| {
+            points
+                .iter()
+                .filter(|j| *j != i)
+                .map(|j| j * (j - i).invert().expect("Points are distinct"))
+                .fold(Scalar::one(), |a, b| a * b)
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub enum DkgStep<G: DkgGroup> {
     Messages(Vec<(PeerId, DkgMessage<G>)>),
@@ -420,6 +885,10 @@ pub struct DkgKeys<G> {
 pub struct ThresholdKeys {
     pub public_key_set: PublicKeySet,
     pub secret_key_share: SerdeSecret<SecretKeyShare>,
+    /// The scalar of `secret_key_share`, which `threshold_crypto` does not
+    /// expose but resharing the key requires, see
+    /// [`PreviousKeys::threshold_crypto`]
+    pub secret_key_scalar: Scalar,
 }
 
 impl DkgKeys<G2Projective> {
@@ -438,6 +907,7 @@ impl DkgKeys<G1Projective> {
             secret_key_share: SerdeSecret(SecretKeyShare::from_mut(
                 &mut self.secret_key_share.clone(),
             )),
+            secret_key_scalar: self.secret_key_share,
         }
     }
 }
@@ -464,6 +934,26 @@ pub trait PeerHandleOps {
     where
         T: Serialize + DeserializeOwned + Unpin + Send + Clone + Eq + Hash + Sync;
 
+    /// Reshares the previous `threshold_crypto` key held by the `dealers`, who
+    /// are keyed by their new peer id and map to their previous one
+    async fn run_reshare_g1<T>(
+        &self,
+        v: T,
+        dealers: &BTreeMap<PeerId, PeerId>,
+        previous: PreviousKeys<G1Projective>,
+    ) -> DkgResult<HashMap<T, DkgKeys<G1Projective>>>
+    where
+        T: Serialize + DeserializeOwned + Unpin + Send + Clone + Eq + Hash + Sync;
+
+    /// Reshares multiple previous `tbs` keys held by the `dealers`
+    async fn run_reshare_multi_g2<T>(
+        &self,
+        v: Vec<(T, PreviousKeys<G2Projective>)>,
+        dealers: &BTreeMap<PeerId, PeerId>,
+    ) -> DkgResult<HashMap<T, DkgKeys<G2Projective>>>
+    where
+        T: Serialize + DeserializeOwned + Unpin + Send + Clone + Eq + Hash + Sync;
+
     /// Exchanges a `DkgPeerMsg::PublicKey(key)` with all peers. Used by the
     /// wallet module to setup the multisig wallet during DKG.
     async fn exchange_pubkeys(
@@ -493,6 +983,7 @@ impl<'a> PeerHandleOps for PeerHandle<'a> {
         T: Serialize + DeserializeOwned + Unpin + Send + Clone + Eq + Hash + Sync,
     {
         let mut dkg = DkgRunner::new(v, self.peers.threshold(), &self.our_id, &self.peers);
+        dkg.record_transcript(self.transcript.clone());
         dkg.run_g1(self.module_instance_id, self.connections).await
     }
 
@@ -501,10 +992,55 @@ impl<'a> PeerHandleOps for PeerHandle<'a> {
         T: Serialize + DeserializeOwned + Unpin + Send + Clone + Eq + Hash + Sync,
     {
         let mut dkg = DkgRunner::multi(v, self.peers.threshold(), &self.our_id, &self.peers);
+        dkg.record_transcript(self.transcript.clone());
 
         dkg.run_g2(self.module_instance_id, self.connections).await
     }
 
+    async fn run_reshare_g1<T>(
+        &self,
+        v: T,
+        dealers: &BTreeMap<PeerId, PeerId>,
+        previous: PreviousKeys<G1Projective>,
+    ) -> DkgResult<HashMap<T, DkgKeys<G1Projective>>>
+    where
+        T: Serialize + DeserializeOwned + Unpin + Send + Clone + Eq + Hash + Sync,
+    {
+        let mut dkg = DkgRunner::new(v.clone(), self.peers.threshold(), &self.our_id, &self.peers);
+        dkg.run_reshare(
+            self.module_instance_id,
+            G1Projective::generator(),
+            dealers,
+            HashMap::from([(v, previous)]),
+            self.connections,
+        )
+        .await
+    }
+
+    async fn run_reshare_multi_g2<T>(
+        &self,
+        v: Vec<(T, PreviousKeys<G2Projective>)>,
+        dealers: &BTreeMap<PeerId, PeerId>,
+    ) -> DkgResult<HashMap<T, DkgKeys<G2Projective>>>
+    where
+        T: Serialize + DeserializeOwned + Unpin + Send + Clone + Eq + Hash + Sync,
+    {
+        let mut dkg = DkgRunner::multi(
+            v.iter().map(|(key, _)| key.clone()).collect(),
+            self.peers.threshold(),
+            &self.our_id,
+            &self.peers,
+        );
+        dkg.run_reshare(
+            self.module_instance_id,
+            G2Projective::generator(),
+            dealers,
+            v.into_iter().collect(),
+            self.connections,
+        )
+        .await
+    }
+
     async fn exchange_pubkeys(
         &self,
         dkg_key: String,
@@ -581,15 +1117,21 @@ impl<'a> PeerHandleOps for PeerHandle<'a> {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::{HashMap, VecDeque};
+    use std::collections::{BTreeMap, HashMap, VecDeque};
 
+    use fedimint_core::config::DkgMessage;
     use fedimint_core::PeerId;
     use rand::rngs::OsRng;
     use threshold_crypto::{G1Projective, G2Projective};
 
     use crate::config::distributedgen::{
-        evaluate_polynomial_g2, scalar, Dkg, DkgGroup, DkgKeys, DkgStep, ThresholdKeys,
+        evaluate_commitment, evaluate_polynomial_g2, scalar, Dkg, DkgGroup, DkgKeys, DkgProtocol,
+        DkgStep, PreviousKeys, Reshare, ThresholdKeys,
     };
+    use crate::config::transcript::verify_key_transcript;
+
+    /// Public messages broadcast by every peer in order
+    type Transcript<G> = BTreeMap<PeerId, Vec<DkgMessage<G>>>;
 
     #[test_log::test]
     fn test_dkg() {
@@ -597,6 +1139,7 @@ mod tests {
             let ThresholdKeys {
                 public_key_set,
                 secret_key_share,
+                ..
             } = keys.threshold_crypto();
             assert_eq!(public_key_set.threshold(), 2);
             assert_eq!(
@@ -615,7 +1158,109 @@ mod tests {
         }
     }
 
+    #[test_log::test]
+    fn test_reshare() {
+        let previous = run(G1Projective::generator());
+        let public_key_set = previous[&PeerId::from(0)].threshold_crypto().public_key_set;
+
+        // The guardians 1, 2 and 3 of the previous federation become 0, 1 and 2
+        let dealers = (0..3u16)
+            .map(|peer| (PeerId::from(peer), PeerId::from(peer + 1)))
+            .collect::<BTreeMap<_, _>>();
+
+        let peers = (0..7u16).map(PeerId::from).collect::<Vec<_>>();
+        let mut steps = VecDeque::new();
+        let mut reshares = HashMap::new();
+
+        for peer in &peers {
+            let secret_key_scalar = dealers
+                .get(peer)
+                .map(|previous_peer| previous[previous_peer].threshold_crypto().secret_key_scalar);
+
+            let previous_keys = PreviousKeys::threshold_crypto(
+                &public_key_set,
+                (0..4u16).map(PeerId::from),
+                secret_key_scalar,
+            );
+
+            let (reshare, step) = Reshare::new(
+                G1Projective::generator(),
+                *peer,
+                peers.clone(),
+                5,
+                dealers.clone(),
+                previous_keys,
+                &mut OsRng,
+            )
+            .unwrap();
+
+            reshares.insert(*peer, reshare);
+            steps.push_back((*peer, step));
+        }
+
+        let (keys, transcript) = run_steps(&mut reshares, steps, peers.len());
+        assert!(
+            transcript.is_empty(),
+            "Reshare messages contain secret shares"
+        );
+
+        for (peer, keys) in keys {
+            assert_eq!(keys.public_key_set.len(), 5);
+            assert_eq!(
+                keys.public_key_set[0],
+                public_key_set.coefficients()[0],
+                "The aggregate public key changed"
+            );
+            assert_eq!(
+                evaluate_commitment(&keys.public_key_set, &scalar(&peer)),
+                G1Projective::generator() * keys.secret_key_share
+            );
+        }
+    }
+
+    #[test_log::test]
+    fn test_transcript() {
+        let peers = (0..4u16).map(PeerId::from).collect::<Vec<_>>();
+        let (keys, transcript) = run_with_transcript(G2Projective::generator());
+
+        let pks = verify_key_transcript(&peers, &transcript).unwrap();
+        for keys in keys.values() {
+            assert_eq!(keys.public_key_set, pks);
+        }
+
+        let mut missing_peer = transcript.clone();
+        missing_peer.remove(&PeerId::from(2));
+        assert!(verify_key_transcript(&peers, &missing_peer).is_err());
+
+        let mut swapped_commit = transcript.clone();
+        let DkgMessage::Commit(commit) = &mut swapped_commit.get_mut(&PeerId::from(1)).unwrap()[1]
+        else {
+            panic!("Second message is the commitment");
+        };
+        commit.swap(0, 1);
+        assert!(verify_key_transcript(&peers, &swapped_commit).is_err());
+
+        let mut wrong_degree = transcript.clone();
+        let DkgMessage::Extract(extract, _) =
+            &mut wrong_degree.get_mut(&PeerId::from(3)).unwrap()[2]
+        else {
+            panic!("Third message is the extract");
+        };
+        extract.pop();
+        assert!(verify_key_transcript(&peers, &wrong_degree).is_err());
+
+        // The extract of another dealer does not match the commitment
+        let mut swapped_extract = transcript.clone();
+        let other_extract = swapped_extract[&PeerId::from(0)][2].clone();
+        swapped_extract.get_mut(&PeerId::from(1)).unwrap()[2] = other_extract;
+        assert!(verify_key_transcript(&peers, &swapped_extract).is_err());
+    }
+
     fn run<G: DkgGroup>(group: G) -> HashMap<PeerId, DkgKeys<G>> {
+        run_with_transcript(group).0
+    }
+
+    fn run_with_transcript<G: DkgGroup>(group: G) -> (HashMap<PeerId, DkgKeys<G>>, Transcript<G>) {
         let mut rng = OsRng;
         let num_peers = 4;
         let threshold = 3;
@@ -623,7 +1268,6 @@ mod tests {
 
         let mut steps: VecDeque<(PeerId, DkgStep<G>)> = VecDeque::new();
         let mut dkgs: HashMap<PeerId, Dkg<G>> = HashMap::new();
-        let mut keys: HashMap<PeerId, DkgKeys<G>> = HashMap::new();
 
         for peer in &peers {
             let (dkg, step) = Dkg::new(group, *peer, peers.clone(), threshold, &mut rng);
@@ -631,11 +1275,28 @@ mod tests {
             steps.push_back((*peer, step));
         }
 
-        while keys.len() < peers.len() {
+        run_steps(&mut dkgs, steps, peers.len())
+    }
+
+    /// Runs the protocols to completion, returning the keys and the public
+    /// messages every peer broadcast
+    fn run_steps<G: DkgGroup, P: DkgProtocol<G>>(
+        protocols: &mut HashMap<PeerId, P>,
+        mut steps: VecDeque<(PeerId, DkgStep<G>)>,
+        num_peers: usize,
+    ) -> (HashMap<PeerId, DkgKeys<G>>, Transcript<G>) {
+        let mut keys: HashMap<PeerId, DkgKeys<G>> = HashMap::new();
+        let mut transcript = Transcript::new();
+
+        while keys.len() < num_peers {
             match steps.pop_front() {
                 Some((peer, DkgStep::Messages(messages))) => {
+                    if let Some((_, msg)) = messages.iter().find(|(_, msg)| msg.is_public()) {
+                        transcript.entry(peer).or_default().push(msg.clone());
+                    }
+
                     for (receive_peer, msg) in messages {
-                        let receive_dkg = dkgs.get_mut(&receive_peer).unwrap();
+                        let receive_dkg = protocols.get_mut(&receive_peer).unwrap();
                         let step = receive_dkg.step(peer, msg);
                         steps.push_back((receive_peer, step.unwrap()));
                     }
@@ -647,6 +1308,6 @@ mod tests {
             }
         }
 
-        keys
+        (keys, transcript)
     }
 }
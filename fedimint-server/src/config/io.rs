@@ -1,14 +1,22 @@
+use std::ffi::OsString;
 use std::fmt::Display;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use fedimint_aead::{encrypted_read, encrypted_write, get_encryption_key, LessSafeKey};
+use anyhow::{bail, Context};
+use fedimint_aead::{
+    encrypt, encrypted_read, encrypted_write, get_encryption_key, random_salt, LessSafeKey,
+};
 use fedimint_core::config::ServerModuleInitRegistry;
+use fedimint_core::util::write_new;
+use fedimint_logging::LOG_CORE;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use tracing::warn;
 
-use crate::config::ServerConfig;
+use crate::config::transcript::SignedDkgTranscript;
+use crate::config::{ServerConfig, ServerConfigConsensus, ServerConfigPrivate};
 
 /// Client configuration file
 pub const CLIENT_CONFIG: &str = "client";
@@ -32,6 +40,10 @@ pub const SALT_FILE: &str = "private.salt";
 /// send a password in via the API
 pub const PLAINTEXT_PASSWORD: &str = "password.private";
 
+/// Signed transcript of the public DKG messages, see
+/// [`crate::config::transcript`]
+pub const DKG_TRANSCRIPT: &str = "dkg-transcript";
+
 /// Database file name
 pub const DB_FILE: &str = "database";
 
@@ -44,6 +56,12 @@ pub const ENCRYPTED_EXT: &str = "encrypt";
 /// directory and the staging directory is removed
 pub const CONFIG_STAGING_DIR: &str = "cfg_staging";
 
+/// Suffix of the files written while the private config is re-encrypted
+const STAGED_SUFFIX: &str = "new";
+
+/// Suffix of the previous files that are kept until re-encryption succeeded
+const BACKUP_SUFFIX: &str = "bak";
+
 /// Reads the server from the local, private, and consensus cfg files
 pub fn read_server_config(password: &str, path: PathBuf) -> anyhow::Result<ServerConfig> {
     let salt = fs::read_to_string(path.join(SALT_FILE))?;
@@ -56,6 +74,31 @@ pub fn read_server_config(password: &str, path: PathBuf) -> anyhow::Result<Serve
     })
 }
 
+/// Checks that `password` decrypts the private config in `path`, passes if no
+/// config was written yet
+pub fn check_config_password(password: &str, path: &Path) -> anyhow::Result<()> {
+    let private_config = path.join(PRIVATE_CONFIG).with_extension(ENCRYPTED_EXT);
+    if !private_config.exists() {
+        return Ok(());
+    }
+
+    let salt = fs::read_to_string(path.join(SALT_FILE))?;
+    let key = get_encryption_key(password, &salt)?;
+    encrypted_read(&key, private_config).context("The password does not decrypt the config")?;
+
+    Ok(())
+}
+
+/// Reads the consensus cfg file, which does not require the password
+pub fn read_consensus_config(path: &Path) -> anyhow::Result<ServerConfigConsensus> {
+    plaintext_json_read(path.join(CONSENSUS_CONFIG))
+}
+
+/// Writes our signed DKG transcript next to the configs
+pub fn write_dkg_transcript(transcript: &SignedDkgTranscript, path: &Path) -> anyhow::Result<()> {
+    plaintext_json_write(transcript, path.join(DKG_TRANSCRIPT))
+}
+
 /// Reads a plaintext json file into a struct
 fn plaintext_json_read<T: Serialize + DeserializeOwned>(path: PathBuf) -> anyhow::Result<T> {
     let string = fs::read_to_string(path.with_extension(JSON_EXT))?;
@@ -125,3 +168,178 @@ fn encrypted_json_write<T: Serialize + DeserializeOwned>(
     let bytes = serde_json::to_string(obj)?.into_bytes();
     encrypted_write(bytes, key, path.with_extension(ENCRYPTED_EXT))
 }
+
+/// Re-encrypts the private config with a new password and a fresh salt
+///
+/// All new files are written next to the existing ones before any of them is
+/// replaced and the previous files are kept until the new config was read back
+/// successfully. If any step fails the previous files are restored, so the
+/// private config stays readable with the old password.
+pub fn reencrypt_private_config(
+    private: &ServerConfigPrivate,
+    path: &Path,
+    new_password: &str,
+) -> anyhow::Result<()> {
+    let salt = random_salt();
+    let key = get_encryption_key(new_password, &salt)?;
+    let private_bytes = serde_json::to_string(private)?.into_bytes();
+
+    let mut files = vec![
+        (
+            path.join(PRIVATE_CONFIG).with_extension(ENCRYPTED_EXT),
+            hex::encode(encrypt(private_bytes, &key)?).into_bytes(),
+        ),
+        (path.join(SALT_FILE), salt.into_bytes()),
+    ];
+
+    // The plaintext password is only present if the guardian opted to store it
+    let password_file = path.join(PLAINTEXT_PASSWORD);
+    if password_file.exists() {
+        files.push((password_file, new_password.as_bytes().to_vec()));
+    }
+
+    let result = replace_files(&files).and_then(|()| {
+        let stored = read_server_config(new_password, path.to_owned())
+            .context("Reading back the re-encrypted config failed")?;
+
+        if serde_json::to_value(&stored.private)? != serde_json::to_value(private)? {
+            bail!("Re-encrypted config does not match the running config");
+        }
+
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            for (file, _) in &files {
+                if let Err(error) = fs::remove_file(with_suffix(file, BACKUP_SUFFIX)) {
+                    warn!(target: LOG_CORE, ?file, %error, "Could not remove config backup");
+                }
+            }
+            Ok(())
+        }
+        Err(error) => {
+            restore_files(&files);
+            Err(error)
+        }
+    }
+}
+
+/// Writes all files to a staging location and then moves them into place,
+/// keeping the previous files as backups
+fn replace_files(files: &[(PathBuf, Vec<u8>)]) -> anyhow::Result<()> {
+    for (file, contents) in files {
+        let staged = with_suffix(file, STAGED_SUFFIX);
+        // A leftover from a previous attempt that was interrupted
+        let _ = fs::remove_file(&staged);
+        write_new(&staged, contents)
+            .with_context(|| format!("Could not write {}", staged.display()))?;
+    }
+
+    for (file, _) in files {
+        fs::rename(file, with_suffix(file, BACKUP_SUFFIX))
+            .with_context(|| format!("Could not back up {}", file.display()))?;
+        fs::rename(with_suffix(file, STAGED_SUFFIX), file)
+            .with_context(|| format!("Could not replace {}", file.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Restores the backups created by [`replace_files`] and removes staged files
+fn restore_files(files: &[(PathBuf, Vec<u8>)]) {
+    for (file, _) in files {
+        let backup = with_suffix(file, BACKUP_SUFFIX);
+        if backup.exists() {
+            if let Err(error) = fs::rename(&backup, file) {
+                warn!(target: LOG_CORE, ?file, %error, "Could not restore config backup");
+            }
+        }
+
+        let _ = fs::remove_file(with_suffix(file, STAGED_SUFFIX));
+    }
+}
+
+fn with_suffix(file: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(file.as_os_str());
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use fedimint_aead::{encrypted_write, get_encryption_key, random_salt};
+
+    use super::{
+        check_config_password, replace_files, restore_files, with_suffix, BACKUP_SUFFIX,
+        ENCRYPTED_EXT, PRIVATE_CONFIG, SALT_FILE,
+    };
+
+    #[test]
+    fn config_password_is_checked() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // nothing to check before the config was written
+        assert!(check_config_password("old", dir.path()).is_ok());
+
+        let salt = random_salt();
+        fs::write(dir.path().join(SALT_FILE), &salt).unwrap();
+        encrypted_write(
+            b"{}".to_vec(),
+            &get_encryption_key("new", &salt).unwrap(),
+            dir.path()
+                .join(PRIVATE_CONFIG)
+                .with_extension(ENCRYPTED_EXT),
+        )
+        .unwrap();
+
+        assert!(check_config_password("new", dir.path()).is_ok());
+        assert!(check_config_password("old", dir.path()).is_err());
+    }
+
+    #[test]
+    fn replaced_files_can_be_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        fs::write(&first, "old first").unwrap();
+        fs::write(&second, "old second").unwrap();
+
+        let files = vec![
+            (first.clone(), b"new first".to_vec()),
+            (second.clone(), b"new second".to_vec()),
+        ];
+
+        replace_files(&files).unwrap();
+        assert_eq!(fs::read_to_string(&first).unwrap(), "new first");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "new second");
+        assert!(with_suffix(&first, BACKUP_SUFFIX).exists());
+
+        restore_files(&files);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "old first");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "old second");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn failed_replacement_is_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first");
+        fs::write(&first, "old first").unwrap();
+
+        // The second file does not exist, so it can not be backed up
+        let files = vec![
+            (first.clone(), b"new first".to_vec()),
+            (dir.path().join("missing"), b"new missing".to_vec()),
+        ];
+
+        assert!(replace_files(&files).is_err());
+
+        restore_files(&files);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "old first");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -3,19 +3,25 @@ use std::env;
 use std::net::SocketAddr;
 use std::time::Duration;
 
-use anyhow::{bail, format_err};
+use anyhow::{bail, ensure, format_err};
+use bitcoin_hashes::sha256;
 use fedimint_core::admin_client::ConfigGenParamsConsensus;
 use fedimint_core::api::InviteCode;
 use fedimint_core::cancellable::Cancelled;
+use fedimint_core::config::{
+    encode_config_extension, DkgTranscriptRecorder, FederationSuccession,
+    SignedFederationSuccession,
+};
 pub use fedimint_core::config::{
     serde_binary_human_readable, ClientConfig, DkgError, DkgPeerMsg, DkgResult, FederationId,
     GlobalClientConfig, JsonWithKind, ModuleInitRegistry, PeerUrl, ServerModuleConfig,
     ServerModuleConsensusConfig, ServerModuleInitRegistry, TypedServerModuleConfig,
 };
-use fedimint_core::core::{ModuleInstanceId, ModuleKind, MODULE_INSTANCE_ID_GLOBAL};
+use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind, MODULE_INSTANCE_ID_GLOBAL};
+use fedimint_core::db::Database;
 use fedimint_core::module::{
     ApiAuth, ApiVersion, CoreConsensusVersion, DynServerModuleInit, MultiApiVersion, PeerHandle,
-    SupportedApiVersionsSummary, SupportedCoreApiVersions,
+    PreviousModuleConfig, SupportedApiVersionsSummary, SupportedCoreApiVersions,
 };
 use fedimint_core::net::peers::{IMuxPeerConnections, IPeerConnections, PeerConnections};
 use fedimint_core::task::{timeout, Elapsed, TaskGroup};
@@ -23,14 +29,16 @@ use fedimint_core::{timing, PeerId};
 use fedimint_logging::{LOG_NET_PEER, LOG_NET_PEER_DKG};
 use futures::future::join_all;
 use rand::rngs::OsRng;
-use secp256k1_zkp::{PublicKey, Secp256k1, SecretKey};
+use secp256k1_zkp::{PublicKey, Secp256k1, SecretKey, SECP256K1};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio_rustls::rustls;
 use tracing::{error, info};
 
 use crate::config::api::ConfigGenParamsLocal;
-use crate::config::distributedgen::{DkgRunner, PeerHandleOps};
+use crate::config::distributedgen::PeerHandleOps;
+use crate::config::reshare::{transfer_state, validate_previous_peers};
+use crate::config::transcript::SignedDkgTranscript;
 use crate::envs::FM_MAX_CLIENT_CONNECTIONS_ENV;
 use crate::fedimint_core::encoding::Encodable;
 use crate::fedimint_core::NumPeers;
@@ -43,6 +51,10 @@ use crate::TlsTcpConnector;
 pub mod api;
 pub mod distributedgen;
 pub mod io;
+pub mod reshare;
+pub mod restore;
+pub mod setup;
+pub mod transcript;
 
 /// The default maximum open connections the API can handle
 const DEFAULT_MAX_CLIENT_CONNECTIONS: u32 = 1000;
@@ -106,7 +118,7 @@ pub struct ServerConfigPrivate {
     pub modules: BTreeMap<ModuleInstanceId, JsonWithKind>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Encodable)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfigConsensus {
     /// The version of the binary code running
     pub code_version: String,
@@ -126,12 +138,37 @@ pub struct ServerConfigConsensus {
     pub tls_certs: BTreeMap<PeerId, rustls::Certificate>,
     /// All configuration that needs to be the same for modules
     pub modules: BTreeMap<ModuleInstanceId, ServerModuleConsensusConfig>,
-    #[encodable_ignore]
     // FIXME: Make modules encodable or we will not check module keys
     /// Human readable representation of [`Self::modules`]
     pub modules_json: BTreeMap<ModuleInstanceId, JsonWithKind>,
     /// Additional config the federation wants to transmit to the clients
     pub meta: BTreeMap<String, String>,
+    /// Set if the federation took over the keys of a previous federation by
+    /// resharing them to a new set of guardians
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reshared_from: Option<SignedFederationSuccession>,
+}
+
+impl Encodable for ServerConfigConsensus {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.code_version.consensus_encode(writer)?;
+        len += self.version.consensus_encode(writer)?;
+        len += self.broadcast_public_keys.consensus_encode(writer)?;
+        len += self
+            .broadcast_expected_rounds_per_session
+            .consensus_encode(writer)?;
+        len += self
+            .broadcast_max_rounds_per_session
+            .consensus_encode(writer)?;
+        len += self.api_endpoints.consensus_encode(writer)?;
+        len += self.tls_certs.consensus_encode(writer)?;
+        len += self.modules.consensus_encode(writer)?;
+        len += self.meta.consensus_encode(writer)?;
+        // keeps the hash of configs from before resharing was supported
+        len += encode_config_extension(&self.reshared_from, writer)?;
+        Ok(len)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -170,22 +207,38 @@ pub struct ConfigGenParams {
 }
 
 impl ServerConfigConsensus {
+    /// The hash guardians compare to make sure they run the same consensus
+    /// config
+    pub fn consensus_hash(&self) -> sha256::Hash {
+        Encodable::consensus_hash::<sha256::Hash>(self)
+    }
+
     pub fn iter_module_instances(
         &self,
     ) -> impl Iterator<Item = (ModuleInstanceId, &ModuleKind)> + '_ {
         self.modules.iter().map(|(k, v)| (*k, &v.kind))
     }
 
+    /// The id of the federation, see [`GlobalClientConfig::federation_id`]
+    pub fn federation_id(&self) -> FederationId {
+        self.global_client_config().federation_id()
+    }
+
+    fn global_client_config(&self) -> GlobalClientConfig {
+        GlobalClientConfig {
+            api_endpoints: self.api_endpoints.clone(),
+            consensus_version: self.version,
+            meta: self.meta.clone(),
+            reshared_from: self.reshared_from.clone(),
+        }
+    }
+
     pub fn to_client_config(
         &self,
         module_config_gens: &ModuleInitRegistry<DynServerModuleInit>,
     ) -> Result<ClientConfig, anyhow::Error> {
         let client = ClientConfig {
-            global: GlobalClientConfig {
-                api_endpoints: self.api_endpoints.clone(),
-                consensus_version: self.version,
-                meta: self.meta.clone(),
-            },
+            global: self.global_client_config(),
             modules: self
                 .modules
                 .iter()
@@ -208,7 +261,7 @@ impl ServerConfig {
     pub fn supported_api_versions() -> SupportedCoreApiVersions {
         SupportedCoreApiVersions {
             core_consensus: CORE_CONSENSUS_VERSION,
-            api: MultiApiVersion::try_from_iter([ApiVersion { major: 0, minor: 2 }])
+            api: MultiApiVersion::try_from_iter([ApiVersion { major: 0, minor: 7 }])
                 .expect("not version conflicts"),
         }
     }
@@ -248,6 +301,7 @@ impl ServerConfig {
             modules: Default::default(),
             modules_json: Default::default(),
             meta: params.consensus.meta,
+            reshared_from: None,
         };
         let mut cfg = Self {
             consensus,
@@ -264,7 +318,7 @@ impl ServerConfig {
                 .url
                 .clone(),
             self.local.identity,
-            FederationId(self.consensus.api_endpoints.consensus_hash()),
+            self.consensus.federation_id(),
         )
     }
 
@@ -435,13 +489,18 @@ impl ServerConfig {
     }
 
     /// Runs the distributed key gen algorithm
+    ///
+    /// Returns our signed transcript of the DKG messages too, unless the keys
+    /// were generated by ourselves or reshared from a previous federation.
     pub async fn distributed_gen(
         params: &ConfigGenParams,
         registry: ServerModuleInitRegistry,
         delay_calculator: DelayCalculator,
         task_group: &mut TaskGroup,
         version_hash: String,
-    ) -> DkgResult<Self> {
+        previous: Option<&ServerConfig>,
+        db: &Database,
+    ) -> DkgResult<(Self, Option<SignedDkgTranscript>)> {
         let _timing /* logs on drop */ = timing::TimeReporter::new("distributed-gen").info();
         let server_conn = connect(
             params.p2p_network(),
@@ -468,36 +527,88 @@ impl ServerConfig {
             .exchange_pubkeys("broadcast".to_string(), broadcast_pk)
             .await?;
 
+        // Continuing guardians of a federation we reshare the keys of send us
+        // their previous peer id and consensus config
+        let previous_configs = broadcast_keys_exchange
+            .exchange_with_peers(
+                "previous config".to_string(),
+                previous.map(|cfg| {
+                    (
+                        cfg.local.identity,
+                        serde_json::to_vec(&cfg.consensus).expect("serialization can't fail"),
+                    )
+                }),
+                ModuleKind::from_static_str("core"),
+                Decoder::builder().build(),
+            )
+            .await?;
+        let reshare = &Self::previous_federation(previous_configs)?;
+
+        let mut reshared_from = None;
+        if let Some((previous_peers, previous_consensus)) = reshare {
+            transfer_state(
+                &broadcast_keys_exchange,
+                db,
+                previous_peers,
+                previous_consensus.api_endpoints.threshold(),
+                previous.is_some(),
+            )
+            .await?;
+
+            reshared_from = Some(
+                Self::sign_succession(
+                    &broadcast_keys_exchange,
+                    previous_peers,
+                    previous_consensus,
+                    previous,
+                    &params.api_urls(),
+                )
+                .await?,
+            );
+        }
+
         // in case we are running by ourselves, avoid DKG
-        if peers.len() == 1 {
+        if peers.len() == 1 && reshare.is_none() {
             let server = Self::trusted_dealer_gen(
                 &HashMap::from([(*our_id, params.clone())]),
                 registry,
                 version_hash,
             );
-            return Ok(server[our_id].clone());
+            return Ok((server[our_id].clone(), None));
         }
         info!(
             target: LOG_NET_PEER_DKG,
             "Peer {} running distributed key generation...", our_id
         );
 
-        // BFT uses a lower threshold of signing keys (f+1)
-        let mut dkg = DkgRunner::new(KeyType::Bft, peers.one_honest(), our_id, peers);
-        dkg.add(KeyType::Auth, peers.threshold());
-        dkg.add(KeyType::Epoch, peers.threshold());
-
+        let transcript = DkgTranscriptRecorder::default();
         let mut registered_modules = registry.kinds();
         let mut module_cfgs: BTreeMap<ModuleInstanceId, ServerModuleConfig> = Default::default();
         let modules = params.consensus.modules.iter_modules();
         let modules_runner = modules.map(|(module_instance_id, kind, module_params)| {
-            let dkg = PeerHandle::new(&connections, module_instance_id, *our_id, peers.clone());
+            let dkg = PeerHandle::new(&connections, module_instance_id, *our_id, peers.clone())
+                .with_transcript(transcript.clone());
             let registry = registry.clone();
 
             async move {
-                let result = match registry.get(kind) {
-                    None => Err(DkgError::ModuleNotFound(kind.clone())),
-                    Some(gen) => gen.distributed_gen(&dkg, module_params).await,
+                let result = match (registry.get(kind), reshare) {
+                    (None, _) => Err(DkgError::ModuleNotFound(kind.clone())),
+                    (Some(gen), None) => gen.distributed_gen(&dkg, module_params).await,
+                    (Some(gen), Some((previous_peers, previous_consensus))) => {
+                        match Self::previous_module_config(
+                            module_instance_id,
+                            kind,
+                            previous_peers,
+                            previous_consensus,
+                            previous,
+                        ) {
+                            Ok(previous) => {
+                                gen.distributed_reshare(&dkg, module_params, &previous)
+                                    .await
+                            }
+                            Err(error) => Err(error.into()),
+                        }
+                    }
                 };
                 (module_instance_id, result)
             }
@@ -554,7 +665,7 @@ impl ServerConfig {
             error!(target: LOG_NET_PEER_DKG, "Timeout waiting for dkg completion confirmation from other peers");
         };
 
-        let server = ServerConfig::from(
+        let mut server = ServerConfig::from(
             params.clone(),
             *our_id,
             broadcast_public_keys,
@@ -562,22 +673,143 @@ impl ServerConfig {
             module_cfgs,
             version_hash,
         );
+        server.consensus.reshared_from = reshared_from;
+
+        let transcript = reshare
+            .is_none()
+            .then(|| SignedDkgTranscript::sign(*our_id, transcript.entries(), &broadcast_sk));
 
         info!(
             target: LOG_NET_PEER,
             "Distributed key generation has completed successfully!"
         );
 
-        Ok(server)
+        Ok((server, transcript))
     }
 }
 
-/// The types of keys to run distributed key generation for
-#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
-pub enum KeyType {
-    Bft,
-    Epoch,
-    Auth,
+impl ServerConfig {
+    /// Parses the previous configs the continuing guardians sent us, returns
+    /// `None` unless we reshare the keys of a previous federation
+    fn previous_federation(
+        previous_configs: BTreeMap<PeerId, Option<(PeerId, Vec<u8>)>>,
+    ) -> anyhow::Result<Option<(BTreeMap<PeerId, PeerId>, ServerConfigConsensus)>> {
+        let mut previous_peers = BTreeMap::new();
+        let mut previous_consensus: Option<ServerConfigConsensus> = None;
+
+        for (peer, previous) in previous_configs {
+            let Some((previous_peer, consensus)) = previous else {
+                continue;
+            };
+
+            let consensus: ServerConfigConsensus = serde_json::from_slice(&consensus)?;
+
+            if let Some(previous_consensus) = &previous_consensus {
+                ensure!(
+                    previous_consensus.consensus_hash() == consensus.consensus_hash(),
+                    "Guardian {peer} continues from a different federation"
+                );
+            }
+
+            previous_consensus = Some(consensus);
+            previous_peers.insert(peer, previous_peer);
+        }
+
+        let Some(previous_consensus) = previous_consensus else {
+            return Ok(None);
+        };
+
+        validate_previous_peers(
+            &previous_peers,
+            &previous_consensus
+                .api_endpoints
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+        )?;
+
+        info!(
+            target: LOG_NET_PEER_DKG,
+            ?previous_peers,
+            "Resharing the keys of the previous federation"
+        );
+
+        Ok(Some((previous_peers, previous_consensus)))
+    }
+
+    /// Collects the signatures of the continuing guardians on the succession
+    /// of the previous federation by the one we generate the config for
+    async fn sign_succession(
+        broadcast_keys_exchange: &PeerHandle<'_>,
+        previous_peers: &BTreeMap<PeerId, PeerId>,
+        previous_consensus: &ServerConfigConsensus,
+        previous: Option<&ServerConfig>,
+        api_endpoints: &BTreeMap<PeerId, PeerUrl>,
+    ) -> DkgResult<SignedFederationSuccession> {
+        let succession = FederationSuccession {
+            previous_api_endpoints: previous_consensus.api_endpoints.clone(),
+            previous_broadcast_public_keys: previous_consensus.broadcast_public_keys.clone(),
+            api_endpoints_hash: api_endpoints.consensus_hash(),
+        };
+
+        let signature = previous.map(|cfg| {
+            SECP256K1.sign_schnorr(
+                &succession.signing_message(),
+                &cfg.private.broadcast_secret_key.keypair(SECP256K1),
+            )
+        });
+
+        let signatures = broadcast_keys_exchange
+            .exchange_with_peers(
+                "federation succession".to_string(),
+                signature,
+                ModuleKind::from_static_str("core"),
+                Decoder::builder().build(),
+            )
+            .await?;
+
+        let signed = SignedFederationSuccession {
+            succession,
+            signatures: signatures
+                .into_iter()
+                .filter_map(|(peer, signature)| Some((*previous_peers.get(&peer)?, signature?)))
+                .collect(),
+        };
+
+        signed.verify(api_endpoints)?;
+
+        Ok(signed)
+    }
+
+    fn previous_module_config(
+        module_instance_id: ModuleInstanceId,
+        kind: &ModuleKind,
+        previous_peers: &BTreeMap<PeerId, PeerId>,
+        previous_consensus: &ServerConfigConsensus,
+        previous: Option<&ServerConfig>,
+    ) -> anyhow::Result<PreviousModuleConfig> {
+        let consensus = previous_consensus
+            .modules
+            .get(&module_instance_id)
+            .ok_or_else(|| {
+                format_err!("Module {module_instance_id} is not part of the previous federation")
+            })?
+            .clone();
+
+        ensure!(
+            consensus.kind == *kind,
+            "Module {module_instance_id} was of kind {} in the previous federation",
+            consensus.kind
+        );
+
+        Ok(PreviousModuleConfig {
+            previous_peers: previous_peers.clone(),
+            consensus,
+            ours: previous
+                .map(|cfg| cfg.get_module_config(module_instance_id))
+                .transpose()?,
+        })
+    }
 }
 
 impl ServerConfig {
@@ -0,0 +1,475 @@
+//! Moves the key material of a running federation to a new set of guardians
+//!
+//! Guardians continuing from the previous federation start `fedimintd` with
+//! `--reshare`, which moves their configs out of the data directory and runs
+//! config gen again with the previous config attached. Guardians joining the
+//! federation run config gen as usual with an empty database. During DKG the
+//! continuing guardians reshare their key shares to all new guardians, see
+//! [`crate::config::distributedgen`], so the new federation has to contain at
+//! least a threshold of the previous guardians.
+//!
+//! The state the new federation needs to continue, its module state such as
+//! spent e-cash notes and wallet UTXOs, the accepted transactions, client
+//! backups and the items accepted in the current session, is carried over by
+//! [`transfer_state`]. The new federation continues with the session the
+//! previous federation was in the middle of. The consensus history is not
+//! carried over apart from the last signed session outcome, which determines
+//! the session index, so only the continuing guardians can serve the sessions
+//! completed before resharing. These remain signed by the previous guardians.
+//!
+//! The API endpoints of the new federation, and hence its federation id,
+//! differ from the previous federation, so the continuing guardians sign a
+//! [`fedimint_core::config::FederationSuccession`] that lets invite codes of
+//! the previous federation download the new config. There is no path for
+//! existing clients to refresh their config, they keep using the peer ids and
+//! API endpoints of the previous federation and have to be recreated from an
+//! invite code once the previous guardians are shut down.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, format_err, Context};
+use bitcoin_hashes::sha256;
+use fedimint_core::config::{DkgResult, ServerModuleInitRegistry};
+use fedimint_core::core::{Decoder, ModuleKind};
+use fedimint_core::db::{
+    Database, DatabaseTransaction, DbKeyPrefix as CoreDbKeyPrefix, IDatabaseTransactionOpsCore,
+    IDatabaseTransactionOpsCoreTyped,
+};
+use fedimint_core::encoding::Encodable;
+use fedimint_core::module::PeerHandle;
+use fedimint_core::{NumPeers, PeerId};
+use fedimint_logging::{LOG_CORE, LOG_NET_PEER_DKG};
+use futures::StreamExt;
+use tracing::info;
+
+use crate::config::distributedgen::PeerHandleOps;
+use crate::config::io::{
+    read_server_config, CLIENT_CONFIG, CLIENT_INVITE_CODE_FILE, CONSENSUS_CONFIG, ENCRYPTED_EXT,
+    JSON_EXT, LOCAL_CONFIG, PRIVATE_CONFIG, SALT_FILE,
+};
+use crate::config::ServerConfig;
+use crate::db::{AcceptedItemPrefix, AlephUnitsPrefix, CarriedOverItemsKey, DbKeyPrefix};
+
+/// Directory within the data directory the configs of the previous federation
+/// are moved to
+pub const PREVIOUS_CONFIG_DIR: &str = "previous_config";
+
+/// Loads the config of the federation we reshare the keys of, moving it out of
+/// the data directory so config gen is started
+///
+/// If resharing was interrupted before the new configs were written, the
+/// previous config is loaded from [`PREVIOUS_CONFIG_DIR`] again.
+pub async fn prepare_reshare(
+    data_dir: &Path,
+    password: &str,
+    module_inits: &ServerModuleInitRegistry,
+    db: &Database,
+) -> anyhow::Result<ServerConfig> {
+    let previous_dir = data_dir.join(PREVIOUS_CONFIG_DIR);
+    let has_config = data_dir.join(SALT_FILE).exists();
+
+    if previous_dir.exists() {
+        ensure!(
+            !has_config,
+            "Resharing already completed, start without resharing or remove {}",
+            previous_dir.display()
+        );
+
+        info!(target: LOG_CORE, "Resuming resharing of the previous config");
+
+        return read_previous_config(&previous_dir, password, module_inits);
+    }
+
+    if !has_config {
+        bail!("Resharing requires the config of the previous federation");
+    }
+
+    let cfg = read_previous_config(data_dir, password, module_inits)?;
+
+    fs::create_dir_all(&previous_dir)?;
+    for file in config_files() {
+        let path = data_dir.join(&file);
+        if path.exists() {
+            fs::rename(&path, previous_dir.join(&file))?;
+        }
+    }
+
+    remove_session_backup(db).await;
+
+    info!(
+        target: LOG_CORE,
+        consensus_hash = %cfg.consensus.consensus_hash(),
+        "Moved the previous config to {}",
+        previous_dir.display()
+    );
+
+    Ok(cfg)
+}
+
+fn read_previous_config(
+    dir: &Path,
+    password: &str,
+    module_inits: &ServerModuleInitRegistry,
+) -> anyhow::Result<ServerConfig> {
+    let cfg = read_server_config(password, dir.to_owned())
+        .context("Could not read the previous config, is the password correct?")?;
+
+    cfg.validate_config(&cfg.local.identity, module_inits)
+        .context("The previous config is invalid")?;
+
+    Ok(cfg)
+}
+
+fn config_files() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from(LOCAL_CONFIG).with_extension(JSON_EXT),
+        PathBuf::from(CONSENSUS_CONFIG).with_extension(JSON_EXT),
+        PathBuf::from(CLIENT_CONFIG).with_extension(JSON_EXT),
+        PathBuf::from(PRIVATE_CONFIG).with_extension(ENCRYPTED_EXT),
+        PathBuf::from(CLIENT_INVITE_CODE_FILE),
+        PathBuf::from(SALT_FILE),
+    ]
+}
+
+/// Removes the backup of the units of the session the previous federation was
+/// in the middle of, the new guardians cannot continue its broadcast. The items
+/// accepted in this session so far are carried over, see [`transfer_state`].
+async fn remove_session_backup(db: &Database) {
+    let mut dbtx = db.begin_transaction().await;
+
+    dbtx.remove_by_prefix(&AlephUnitsPrefix).await;
+
+    dbtx.commit_tx().await;
+}
+
+/// The state of a guardian that is carried over into the new federation, as
+/// raw database entries sorted by key
+type FederationState = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Maximum encoded size of the database entries sent in one DKG message when
+/// transferring the state, larger states are sent in several messages
+const STATE_CHUNK_SIZE: usize = 1 << 20;
+
+/// Database prefixes that are carried over in full, the history of the
+/// federation and the entries local to a guardian are not
+const TRANSFERRED_PREFIXES: [u8; 5] = [
+    DbKeyPrefix::AcceptedItem as u8,
+    DbKeyPrefix::AcceptedTransaction as u8,
+    CoreDbKeyPrefix::DatabaseVersion as u8,
+    CoreDbKeyPrefix::ClientBackup as u8,
+    DbKeyPrefix::Module as u8,
+];
+
+/// Transfers the state of the previous federation to all guardians of the new
+/// federation
+///
+/// Every continuing guardian announces the hash of its state and all
+/// guardians adopt the state announced by a threshold of the previous
+/// federation. The continuing guardian with the lowest peer id holding this
+/// state sends it to the others in chunks of at most [`STATE_CHUNK_SIZE`], so
+/// guardians joining the federation and continuing guardians that shut down in
+/// a different place of the last session end up with the same state.
+pub async fn transfer_state(
+    peers: &PeerHandle<'_>,
+    db: &Database,
+    previous_peers: &BTreeMap<PeerId, PeerId>,
+    previous_threshold: usize,
+    continuing: bool,
+) -> DkgResult<()> {
+    let our_state = if continuing {
+        Some(read_state(&mut db.begin_transaction_nc().await).await)
+    } else {
+        None
+    };
+    let our_hash = our_state.as_ref().map(state_hash);
+
+    let state_hashes = peers
+        .exchange_with_peers(
+            "state hash".to_string(),
+            our_hash,
+            ModuleKind::from_static_str("core"),
+            Decoder::builder().build(),
+        )
+        .await?;
+
+    let (sender, agreed_hash) = agreed_state(&state_hashes, previous_peers, previous_threshold)?;
+
+    let our_chunks = match our_state.filter(|_| sender == peers.our_id) {
+        Some(state) => Some(state_chunks(state)?),
+        None => None,
+    };
+
+    let chunk_count = peers
+        .exchange_with_peers(
+            "state chunks".to_string(),
+            our_chunks.as_ref().map(|chunks| chunks.len() as u64),
+            ModuleKind::from_static_str("core"),
+            Decoder::builder().build(),
+        )
+        .await?
+        .remove(&sender)
+        .flatten()
+        .ok_or_else(|| format_err!("Guardian {sender} did not announce the state"))?;
+
+    let mut received_state = FederationState::new();
+    for index in 0..chunk_count {
+        let chunk = peers
+            .exchange_with_peers(
+                format!("state chunk {index}"),
+                our_chunks
+                    .as_ref()
+                    .map(|chunks| chunks[index as usize].clone()),
+                ModuleKind::from_static_str("core"),
+                Decoder::builder().build(),
+            )
+            .await?
+            .remove(&sender)
+            .flatten()
+            .ok_or_else(|| format_err!("Guardian {sender} did not send the state"))?;
+
+        received_state.extend(chunk);
+    }
+
+    let mut dbtx = db.begin_transaction().await;
+
+    if our_hash != Some(agreed_hash) {
+        if state_hash(&received_state) != agreed_hash {
+            return Err(format_err!("Guardian {sender} sent a state not matching its hash").into());
+        }
+
+        let stale_state = read_state(&mut dbtx.to_ref_nc()).await;
+
+        for (key, _) in stale_state {
+            dbtx.raw_remove_entry(&key)
+                .await
+                .expect("Removing an entry cannot fail");
+        }
+
+        for (key, value) in &received_state {
+            dbtx.raw_insert_bytes(key, value)
+                .await
+                .expect("Inserting an entry cannot fail");
+        }
+
+        info!(
+            target: LOG_NET_PEER_DKG,
+            %sender,
+            entries = received_state.len(),
+            "Adopted the state of the previous federation"
+        );
+    }
+
+    // The new federation completes the session the previous federation was in
+    // the middle of, starting after the items accepted so far
+    let carried_over_items = dbtx.find_by_prefix(&AcceptedItemPrefix).await.count().await as u64;
+
+    if carried_over_items == 0 {
+        dbtx.remove_entry(&CarriedOverItemsKey).await;
+    } else {
+        dbtx.insert_entry(&CarriedOverItemsKey, &carried_over_items)
+            .await;
+    }
+
+    dbtx.commit_tx_result()
+        .await
+        .map_err(|error| format_err!("Could not write the state: {error}"))?;
+
+    Ok(())
+}
+
+/// Returns the continuing guardian that sends the state announced by a
+/// threshold of the previous federation, and its hash
+fn agreed_state(
+    state_hashes: &BTreeMap<PeerId, Option<sha256::Hash>>,
+    previous_peers: &BTreeMap<PeerId, PeerId>,
+    previous_threshold: usize,
+) -> anyhow::Result<(PeerId, sha256::Hash)> {
+    let mut votes = BTreeMap::<sha256::Hash, Vec<PeerId>>::new();
+
+    for peer in previous_peers.keys() {
+        if let Some(Some(hash)) = state_hashes.get(peer) {
+            votes.entry(*hash).or_default().push(*peer);
+        }
+    }
+
+    votes
+        .into_iter()
+        .find(|(_, peers)| peers.len() >= previous_threshold)
+        .map(|(hash, peers)| (peers[0], hash))
+        .ok_or_else(|| {
+            format_err!(
+                "A threshold of the continuing guardians has to agree on the state of the previous federation, shut it down at once and try again"
+            )
+        })
+}
+
+/// Splits the state into chunks whose entries are encoded in at most
+/// [`STATE_CHUNK_SIZE`] bytes
+fn state_chunks(state: FederationState) -> anyhow::Result<Vec<FederationState>> {
+    let mut chunks = vec![];
+    let mut chunk = FederationState::new();
+    let mut chunk_size = 0;
+
+    for entry in state {
+        let entry_size = entry.consensus_encode_to_vec().len();
+
+        ensure!(
+            entry_size <= STATE_CHUNK_SIZE,
+            "Database entry of {entry_size} bytes exceeds the maximum size of {STATE_CHUNK_SIZE} bytes"
+        );
+
+        if STATE_CHUNK_SIZE < chunk_size + entry_size {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_size = 0;
+        }
+
+        chunk.push(entry);
+        chunk_size += entry_size;
+    }
+
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+
+    Ok(chunks)
+}
+
+/// Reads the entries of [`TRANSFERRED_PREFIXES`] and the last signed session
+/// outcome, from which the session index of the new federation follows
+async fn read_state(dbtx: &mut DatabaseTransaction<'_>) -> FederationState {
+    let mut state = FederationState::new();
+
+    for prefix in TRANSFERRED_PREFIXES {
+        state.extend(
+            dbtx.raw_find_by_prefix(&[prefix])
+                .await
+                .expect("Reading the database cannot fail")
+                .collect::<Vec<_>>()
+                .await,
+        );
+    }
+
+    state.extend(
+        dbtx.raw_find_by_prefix_sorted_descending(&[DbKeyPrefix::SignedSessionOutcome as u8])
+            .await
+            .expect("Reading the database cannot fail")
+            .next()
+            .await,
+    );
+
+    state.sort();
+
+    state
+}
+
+fn state_hash(state: &FederationState) -> sha256::Hash {
+    state.consensus_hash::<sha256::Hash>()
+}
+
+/// Checks the previous peer ids the continuing guardians claim, keyed by
+/// their new peer id, against the guardians of the previous federation
+pub fn validate_previous_peers(
+    previous_peers: &BTreeMap<PeerId, PeerId>,
+    previous_federation: &[PeerId],
+) -> anyhow::Result<()> {
+    for (peer, previous_peer) in previous_peers {
+        ensure!(
+            previous_federation.contains(previous_peer),
+            "Guardian {peer} claims to be the unknown previous guardian {previous_peer}"
+        );
+    }
+
+    ensure!(
+        previous_peers.values().collect::<BTreeSet<_>>().len() == previous_peers.len(),
+        "Two guardians claim to be the same previous guardian"
+    );
+    ensure!(
+        previous_peers.len() >= previous_federation.threshold(),
+        "Resharing requires {} guardians of the previous federation but only {} take part",
+        previous_federation.threshold(),
+        previous_peers.len()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use bitcoin_hashes::{sha256, Hash};
+    use fedimint_core::encoding::Encodable;
+    use fedimint_core::PeerId;
+
+    use super::{agreed_state, state_chunks, validate_previous_peers, STATE_CHUNK_SIZE};
+
+    #[test]
+    fn requires_threshold_of_previous_guardians() {
+        let previous_federation = (0..4u16).map(PeerId::from).collect::<Vec<_>>();
+        let continuing = |previous: &[u16]| {
+            previous
+                .iter()
+                .enumerate()
+                .map(|(peer, previous)| (PeerId::from(peer as u16), PeerId::from(*previous)))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        assert!(validate_previous_peers(&continuing(&[3, 1, 0]), &previous_federation).is_ok());
+        assert!(validate_previous_peers(&continuing(&[3, 1]), &previous_federation).is_err());
+        assert!(validate_previous_peers(&continuing(&[3, 3, 1]), &previous_federation).is_err());
+        assert!(validate_previous_peers(&continuing(&[3, 1, 4]), &previous_federation).is_err());
+    }
+
+    #[test]
+    fn adopts_state_of_threshold_of_previous_guardians() {
+        let peer = PeerId::from;
+        let hash = |byte: u8| sha256::Hash::hash(&[byte]);
+        // Guardian 3 is joining, the others continue from the previous federation
+        let previous_peers =
+            BTreeMap::from([(peer(0), peer(2)), (peer(1), peer(0)), (peer(2), peer(1))]);
+
+        let state_hashes = BTreeMap::from([
+            (peer(0), Some(hash(0))),
+            (peer(1), Some(hash(1))),
+            (peer(2), Some(hash(1))),
+            (peer(3), Some(hash(0))),
+        ]);
+        assert_eq!(
+            agreed_state(&state_hashes, &previous_peers, 2).unwrap(),
+            (peer(1), hash(1))
+        );
+
+        // Joining guardians cannot vote for a state
+        let state_hashes = BTreeMap::from([
+            (peer(0), Some(hash(0))),
+            (peer(1), Some(hash(1))),
+            (peer(2), None),
+            (peer(3), Some(hash(0))),
+        ]);
+        assert!(agreed_state(&state_hashes, &previous_peers, 2).is_err());
+    }
+
+    #[test]
+    fn splits_state_into_chunks() {
+        let state = (0..1000u32)
+            .map(|index| (index.to_be_bytes().to_vec(), vec![0; 4000]))
+            .collect::<Vec<_>>();
+
+        let chunks = state_chunks(state.clone()).unwrap();
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| {
+            chunk
+                .iter()
+                .map(|entry| entry.consensus_encode_to_vec().len())
+                .sum::<usize>()
+                <= STATE_CHUNK_SIZE
+        }));
+        assert_eq!(chunks.concat(), state);
+
+        assert!(state_chunks(vec![]).unwrap().is_empty());
+        assert!(state_chunks(vec![(vec![0], vec![0; STATE_CHUNK_SIZE])]).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Restores a guardian from a [`GuardianConfigBackup`]
+//!
+//! The backup contains the local, consensus and encrypted private config of a
+//! guardian. Before anything is written to the data directory we decrypt the
+//! private config with the guardian password and check that the consensus
+//! config is the one the rest of the federation is running. The consensus
+//! history itself is not part of the backup and is downloaded from the peers
+//! once consensus is started.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context};
+use fedimint_core::api::{DynGlobalApi, GuardianConfigBackup};
+use fedimint_core::config::ServerModuleInitRegistry;
+use fedimint_core::task::sleep;
+use fedimint_core::util::write_new;
+use fedimint_logging::LOG_CORE;
+use tracing::{info, warn};
+
+use crate::config::io::{
+    read_server_config, write_server_config, CONSENSUS_CONFIG, ENCRYPTED_EXT, JSON_EXT,
+    LOCAL_CONFIG, PRIVATE_CONFIG, SALT_FILE,
+};
+use crate::config::ServerConfig;
+
+/// Temporary directory the backup is unpacked to before it was validated
+pub const RESTORE_STAGING_DIR: &str = "restore_staging";
+
+/// How often we ask our peers for their consensus config hash before giving up
+const CONSENSUS_HASH_ATTEMPTS: usize = 10;
+
+/// Writes the configs contained in the backup to `data_dir` after validating
+/// them against the federation
+///
+/// If `data_dir` already contains the configs of the backup, e.g. because the
+/// guardian is restarted with the same options, nothing is written. Configs of
+/// any other guardian or federation are never overwritten.
+pub async fn restore_guardian_config_backup(
+    backup: &GuardianConfigBackup,
+    password: &str,
+    data_dir: &Path,
+    module_inits: &ServerModuleInitRegistry,
+) -> anyhow::Result<ServerConfig> {
+    let staging_dir = data_dir.join(RESTORE_STAGING_DIR);
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    fs::create_dir_all(&staging_dir)?;
+
+    let result =
+        restore_from_staging_dir(backup, password, data_dir, &staging_dir, module_inits).await;
+
+    if let Err(error) = fs::remove_dir_all(&staging_dir) {
+        warn!(target: LOG_CORE, %error, "Could not remove restore staging directory");
+    }
+
+    result
+}
+
+async fn restore_from_staging_dir(
+    backup: &GuardianConfigBackup,
+    password: &str,
+    data_dir: &Path,
+    staging_dir: &Path,
+    module_inits: &ServerModuleInitRegistry,
+) -> anyhow::Result<ServerConfig> {
+    unpack_backup(backup, staging_dir)?;
+
+    let cfg = read_server_config(password, staging_dir.to_owned())
+        .context("Could not read the backup, is the password correct?")?;
+
+    if contains_configs(data_dir) {
+        let existing_cfg =
+            read_server_config(password, data_dir.to_owned()).with_context(|| {
+                format!(
+                    "Refusing to restore the backup, {} already contains configs we cannot read",
+                    data_dir.display()
+                )
+            })?;
+
+        ensure!(
+            existing_cfg.local.identity == cfg.local.identity
+                && existing_cfg.consensus.consensus_hash() == cfg.consensus.consensus_hash(),
+            "Refusing to restore the backup, {} already contains the configs of a different guardian",
+            data_dir.display()
+        );
+
+        info!(
+            target: LOG_CORE,
+            peer = %cfg.local.identity,
+            "Guardian config from backup was restored already"
+        );
+
+        return Ok(existing_cfg);
+    }
+
+    cfg.validate_config(&cfg.local.identity, module_inits)
+        .context("The backup contains an invalid config")?;
+
+    verify_consensus_hash(&cfg).await?;
+
+    // The salt has to be in place before the private config is encrypted again
+    write_new(
+        data_dir.join(SALT_FILE),
+        fs::read(staging_dir.join(SALT_FILE))?,
+    )?;
+    write_server_config(&cfg, data_dir.to_owned(), password, module_inits)?;
+
+    info!(
+        target: LOG_CORE,
+        peer = %cfg.local.identity,
+        "Restored guardian config from backup"
+    );
+
+    Ok(cfg)
+}
+
+fn contains_configs(data_dir: &Path) -> bool {
+    data_dir.join(SALT_FILE).exists()
+        || data_dir
+            .join(PRIVATE_CONFIG)
+            .with_extension(ENCRYPTED_EXT)
+            .exists()
+}
+
+/// Unpacks the tar archive of the backup, rejecting any unexpected files
+fn unpack_backup(backup: &GuardianConfigBackup, staging_dir: &Path) -> anyhow::Result<()> {
+    let expected_files = [
+        PathBuf::from(LOCAL_CONFIG).with_extension(JSON_EXT),
+        PathBuf::from(CONSENSUS_CONFIG).with_extension(JSON_EXT),
+        PathBuf::from(SALT_FILE),
+        PathBuf::from(PRIVATE_CONFIG).with_extension(ENCRYPTED_EXT),
+    ];
+
+    let mut archive = tar::Archive::new(backup.tar_archive_bytes.as_slice());
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        if !expected_files.contains(&path) {
+            bail!("Unexpected file {} in backup", path.display());
+        }
+
+        entry.unpack(staging_dir.join(&path))?;
+    }
+
+    for file in &expected_files {
+        ensure!(
+            staging_dir.join(file).exists(),
+            "Backup is missing {}",
+            file.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that a threshold of our peers runs the consensus config from the
+/// backup, otherwise we would restore a guardian of a different federation or
+/// an outdated config
+async fn verify_consensus_hash(cfg: &ServerConfig) -> anyhow::Result<()> {
+    let our_hash = cfg.consensus.consensus_hash();
+
+    if cfg.consensus.api_endpoints.len() == 1 {
+        warn!(
+            target: LOG_CORE,
+            "Single guardian federation, cannot verify the consensus config of the backup"
+        );
+        return Ok(());
+    }
+
+    let api = DynGlobalApi::from_endpoints(
+        cfg.consensus
+            .api_endpoints
+            .iter()
+            .map(|(peer, endpoint)| (*peer, endpoint.url.clone()))
+            .collect(),
+    );
+
+    for attempt in 1..=CONSENSUS_HASH_ATTEMPTS {
+        match api.server_config_consensus_hash().await {
+            Ok(federation_hash) => {
+                ensure!(
+                    federation_hash == our_hash,
+                    "Consensus config of the backup {our_hash} does not match the federation {federation_hash}"
+                );
+
+                info!(target: LOG_CORE, "Confirmed consensus config {our_hash} with peers");
+
+                return Ok(());
+            }
+            Err(error) => {
+                warn!(
+                    target: LOG_CORE,
+                    %error,
+                    attempt,
+                    "Could not fetch consensus config hash from peers"
+                );
+            }
+        }
+
+        sleep(Duration::from_secs(5)).await;
+    }
+
+    bail!("Could not verify the consensus config of the backup with our peers")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use fedimint_core::api::GuardianConfigBackup;
+
+    use super::unpack_backup;
+
+    fn backup(files: &[&str]) -> GuardianConfigBackup {
+        let mut builder = tar::Builder::new(Vec::new());
+
+        for file in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(Path::new(file)).unwrap();
+            header.set_size(4);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, b"data".as_slice()).unwrap();
+        }
+
+        GuardianConfigBackup {
+            tar_archive_bytes: builder.into_inner().unwrap(),
+        }
+    }
+
+    #[test]
+    fn unpacks_only_expected_files() {
+        let complete = [
+            "local.json",
+            "consensus.json",
+            "private.salt",
+            "private.encrypt",
+        ];
+
+        let dir = tempfile::tempdir().unwrap();
+        unpack_backup(&backup(&complete), dir.path()).unwrap();
+        assert!(dir.path().join("private.encrypt").exists());
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(unpack_backup(&backup(&complete[..3]), dir.path()).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let unexpected = backup(&["local.json", "password.private"]);
+        assert!(unpack_backup(&unexpected, dir.path()).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! Declarative federation setup without the interactive config gen API
+//!
+//! Every guardian passes a [`HeadlessSetup`] to `fedimintd` which then drives
+//! its own [`ConfigGenApi`] through the same steps a guardian would take in the
+//! UI. The config gen API is still served while the setup runs, since the
+//! guardians exchange their connection info and DKG status through it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, format_err, Context};
+use fedimint_core::admin_client::{
+    ConfigGenConnectionsRequest, ConfigGenParamsRequest, WsAdminClient,
+};
+use fedimint_core::api::ServerStatus;
+use fedimint_core::config::ServerModuleConfigGenParamsRegistry;
+use fedimint_core::module::registry::ModuleRegistry;
+use fedimint_core::module::{ApiAuth, ApiError};
+use fedimint_core::task::sleep;
+use fedimint_core::util::SafeUrl;
+use fedimint_logging::LOG_NET_PEER_DKG;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config::api::ConfigGenApi;
+
+/// How often a step of the setup is attempted before the setup fails
+const SETUP_STEP_ATTEMPTS: usize = 60;
+
+/// Delay between attempts of a setup step
+const SETUP_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Setup of a single guardian, every guardian of the federation needs its own
+/// file listing the same peers
+///
+/// ```toml
+/// name = "alice"
+/// leader_api_url = "ws://leader.example.com:8174"
+/// peers = ["ws://leader.example.com:8174", "ws://alice.example.com:8174"]
+///
+/// [meta]
+/// federation_name = "Example Federation"
+///
+/// [modules]
+/// 0 = ["dummy", { consensus = { tx_fee = 10 } }]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct HeadlessSetup {
+    /// Our guardian name
+    pub name: String,
+    /// API URL of the leader guardian, `None` if we are the leader
+    #[serde(default)]
+    pub leader_api_url: Option<SafeUrl>,
+    /// API URLs of all guardians including us, DKG is only started once all
+    /// of them have joined
+    pub peers: BTreeSet<SafeUrl>,
+    /// Guardian-defined key-value pairs passed to the clients, only the meta
+    /// of the leader is used
+    #[serde(default)]
+    pub meta: BTreeMap<String, String>,
+    /// Module params overriding the defaults of `fedimintd`, only the
+    /// consensus params of the leader are used
+    #[serde(default)]
+    pub modules: ServerModuleConfigGenParamsRegistry,
+}
+
+impl HeadlessSetup {
+    /// Reads the setup from a TOML file like the `fedimintd` config file, or
+    /// from a JSON file if its extension is `json`
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read setup file {}", path.display()))?;
+
+        let setup = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            Self::from_json(&content)
+        } else {
+            Self::from_toml(&content)
+        }
+        .with_context(|| format!("Invalid setup file {}", path.display()))?;
+
+        ensure!(
+            setup.peers.len() > 1 || setup.leader_api_url.is_none(),
+            "A single guardian federation has no leader"
+        );
+
+        Ok(setup)
+    }
+
+    fn from_json(content: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(content)?)
+    }
+
+    fn from_toml(content: &str) -> anyhow::Result<Self> {
+        // TOML keys are strings, going through JSON lets the module params be
+        // keyed by their instance id like in JSON files
+        let value: serde_json::Value = toml::from_str(content)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Drives the config gen API until consensus is started or a step fails
+    pub async fn run(self, config_gen: ConfigGenApi, auth: ApiAuth) -> anyhow::Result<()> {
+        let api = &config_gen;
+
+        if api.server_status().await == ServerStatus::AwaitingPassword {
+            api.set_password(auth.clone())
+                .map_err(api_error)
+                .context("Setting password failed")?;
+        }
+
+        let connections = &ConfigGenConnectionsRequest {
+            our_name: self.name.clone(),
+            leader_api_url: self.leader_api_url.clone(),
+        };
+
+        retry("set connections", move || {
+            api.set_config_gen_connections(connections.clone())
+        })
+        .await?;
+
+        let request = &ConfigGenParamsRequest {
+            meta: self.meta.clone(),
+            modules: merge_module_params(
+                api.default_config_gen_params().map_err(api_error)?.modules,
+                &self.modules,
+            ),
+        };
+
+        retry("set params", move || {
+            api.set_config_gen_params(request.clone())
+        })
+        .await?;
+
+        // The leader collects the connection info of all peers
+        let peers = &self.peers;
+        retry("wait for peers", move || async move {
+            let response = api.consensus_config_gen_params(request).await?;
+            let joined = response
+                .consensus
+                .peers
+                .values()
+                .map(|peer| peer.api_url.clone())
+                .collect::<BTreeSet<_>>();
+
+            if &joined != peers {
+                return Err(ApiError::not_found(format!(
+                    "{} of {} peers joined",
+                    joined.intersection(peers).count(),
+                    peers.len()
+                )));
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        info!(target: LOG_NET_PEER_DKG, "All peers joined, running DKG");
+
+        // Fails without changing our status if the leader cannot be reached
+        retry("start DKG", move || async move {
+            match api.server_status().await {
+                ServerStatus::SharingConfigGenParams => api.run_dkg().await,
+                _ => Ok(()),
+            }
+        })
+        .await?;
+
+        retry("run DKG", move || async move {
+            match api.server_status().await {
+                ServerStatus::VerifyingConfigs => Ok(Ok(())),
+                ServerStatus::ConfigGenFailed => Ok(Err(format_err!("DKG failed"))),
+                status => Err(ApiError::not_found(format!("Status is {status:?}"))),
+            }
+        })
+        .await??;
+
+        // Starting consensus with differing configs would never complete, so we
+        // wait until every peer finished DKG and generated the same config
+        let our_hash = api.consensus_config_hash().map_err(api_error)?;
+        retry("verify config hashes", move || async move {
+            for url in peers {
+                let hash = WsAdminClient::new(url.clone())
+                    .server_config_consensus_hash()
+                    .await
+                    .map_err(|e| {
+                        ApiError::not_found(format!("No config hash from peer {url}: {e}"))
+                    })?;
+
+                if hash != our_hash {
+                    return Ok(Err(format_err!(
+                        "Peer {url} generated a different config, hash {hash} instead of {our_hash}"
+                    )));
+                }
+            }
+
+            Ok(Ok(()))
+        })
+        .await??;
+
+        api.verified_configs().await.map_err(api_error)?;
+
+        api.start_consensus(auth).await.map_err(api_error)?;
+
+        info!(target: LOG_NET_PEER_DKG, "Headless setup complete, starting consensus");
+
+        Ok(())
+    }
+}
+
+/// Applies the module params in `overrides` on top of the `defaults`, keeping
+/// the default local or consensus params a module override does not set
+pub fn merge_module_params(
+    defaults: ServerModuleConfigGenParamsRegistry,
+    overrides: &ServerModuleConfigGenParamsRegistry,
+) -> ServerModuleConfigGenParamsRegistry {
+    let mut modules = defaults
+        .into_iter_modules()
+        .map(|(id, kind, params)| (id, (kind, params)))
+        .collect::<BTreeMap<_, _>>();
+
+    for (id, kind, params) in overrides.iter_modules() {
+        let (_, merged) = modules
+            .entry(id)
+            .or_insert_with(|| (kind.clone(), Default::default()));
+
+        if params.local.is_some() {
+            merged.local = params.local.clone();
+        }
+
+        if params.consensus.is_some() {
+            merged.consensus = params.consensus.clone();
+        }
+    }
+
+    ModuleRegistry::from(modules)
+}
+
+fn api_error(error: ApiError) -> anyhow::Error {
+    format_err!("{}", error.message)
+}
+
+/// Retries a setup step that can fail because other guardians are not ready
+/// yet
+async fn retry<F, Fut, T>(step: &str, f: F) -> anyhow::Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, ApiError>>,
+{
+    for attempt in 1..=SETUP_STEP_ATTEMPTS {
+        match f().await {
+            Ok(value) => {
+                info!(target: LOG_NET_PEER_DKG, step, "Setup step complete");
+                return Ok(value);
+            }
+            Err(error) => {
+                warn!(
+                    target: LOG_NET_PEER_DKG,
+                    step,
+                    attempt,
+                    error = %error.message,
+                    "Setup step not complete yet"
+                );
+            }
+        }
+
+        sleep(SETUP_RETRY_DELAY).await;
+    }
+
+    bail!("Setup step {step} did not complete after {SETUP_STEP_ATTEMPTS} attempts")
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::config::{ConfigGenModuleParams, ServerModuleConfigGenParamsRegistry};
+    use fedimint_core::core::ModuleKind;
+    use serde_json::json;
+
+    use super::{merge_module_params, HeadlessSetup};
+
+    #[test]
+    fn toml_and_json_setups_match() {
+        let toml = HeadlessSetup::from_toml(
+            r#"
+            name = "alice"
+            leader_api_url = "ws://leader:8174"
+            peers = ["ws://leader:8174", "ws://alice:8174"]
+
+            [meta]
+            federation_name = "Example Federation"
+
+            [modules]
+            0 = ["dummy", { consensus = { tx_fee = 10 } }]
+            "#,
+        )
+        .unwrap();
+
+        let json = HeadlessSetup::from_json(
+            &json!({
+                "name": "alice",
+                "leader_api_url": "ws://leader:8174",
+                "peers": ["ws://leader:8174", "ws://alice:8174"],
+                "meta": { "federation_name": "Example Federation" },
+                "modules": {
+                    "0": ["dummy", { "consensus": { "tx_fee": 10 } }]
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(toml, json);
+        assert!(HeadlessSetup::from_toml("name = \"alice\"\nunknown = 1").is_err());
+    }
+
+    #[test]
+    fn setup_params_override_defaults() {
+        let setup: HeadlessSetup = serde_json::from_value(json!({
+            "name": "alice",
+            "leader_api_url": "ws://leader:8174",
+            "peers": ["ws://leader:8174", "ws://alice:8174"],
+            "modules": {
+                "0": ["dummy", { "consensus": { "tx_fee": 10 } }]
+            }
+        }))
+        .unwrap();
+
+        let mut defaults = ServerModuleConfigGenParamsRegistry::default();
+        defaults.register_module(
+            0,
+            ModuleKind::from_static_str("dummy"),
+            ConfigGenModuleParams::new(Some(json!({ "bind": "local" })), Some(json!({}))),
+        );
+
+        let params = merge_module_params(defaults, &setup.modules);
+
+        assert_eq!(
+            params.get(0),
+            Some(&ConfigGenModuleParams::new(
+                Some(json!({ "bind": "local" })),
+                Some(json!({ "tx_fee": 10 }))
+            ))
+        );
+    }
+}
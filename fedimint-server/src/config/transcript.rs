@@ -0,0 +1,245 @@
+//! Verifiable transcripts of the distributed key generation
+//!
+//! While running the DKG of the module keys every guardian records the public
+//! messages it sent and received, that is the hashed commitments, the
+//! commitments and the extracted public key shares of all peers together with
+//! the proofs linking them to the commitments, but never the secret shares.
+//! After config gen the guardian signs the transcript with its broadcast key
+//! and stores it next to its configs as [`DKG_TRANSCRIPT`].
+//!
+//! Anyone holding the consensus config can then check offline with
+//! [`verify_dkg_transcripts`] that every guardian contributed a polynomial of
+//! the expected degree, that the commitments match the hashes published before
+//! them, that every extract belongs to its commitment and that the public keys
+//! in the consensus config are the sum of the contributions. The module keys
+//! are the only keys generated by DKG, the broadcast keys signing the
+//! transcripts are exchanged directly. Verifying the transcripts of multiple
+//! guardians additionally shows that they all observed the same messages.
+//!
+//! Keys moved to a new set of guardians by resharing, see
+//! [`crate::config::reshare`], are not covered, since every message of the
+//! resharing protocol contains a secret share.
+//!
+//! [`DKG_TRANSCRIPT`]: crate::config::io::DKG_TRANSCRIPT
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::{bail, ensure, format_err, Context};
+use fedimint_core::config::{
+    DkgGroup, DkgMessage, DkgPublicKeys, DkgTranscriptEntry, ISupportedDkgMessage,
+    ServerModuleInitRegistry,
+};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::{NumPeers, PeerId};
+use secp256k1_zkp::hashes::{sha256, Hash};
+use secp256k1_zkp::{schnorr, Message, PublicKey, SecretKey, SECP256K1};
+use serde::{Deserialize, Serialize};
+use threshold_crypto::{G1Projective, G2Projective};
+
+use crate::config::distributedgen::{evaluate_commitment, hash_commitment, scalar, verify_extract};
+use crate::config::ServerConfigConsensus;
+
+/// Domain separation tag for the message signed by the guardians, the
+/// broadcast keys are used to sign consensus messages as well
+const DKG_TRANSCRIPT_TAG: &[u8] = b"fedimint-dkg-transcript";
+
+/// The public DKG messages observed by `peer`, signed with its broadcast key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedDkgTranscript {
+    pub peer: PeerId,
+    pub entries: Vec<DkgTranscriptEntry>,
+    pub signature: schnorr::Signature,
+}
+
+impl SignedDkgTranscript {
+    pub fn sign(peer: PeerId, entries: Vec<DkgTranscriptEntry>, secret_key: &SecretKey) -> Self {
+        let signature =
+            SECP256K1.sign_schnorr(&signing_message(&entries), &secret_key.keypair(SECP256K1));
+
+        SignedDkgTranscript {
+            peer,
+            entries,
+            signature,
+        }
+    }
+
+    /// Checks the signature against the broadcast public keys of the
+    /// federation
+    pub fn verify_signature(
+        &self,
+        public_keys: &BTreeMap<PeerId, PublicKey>,
+    ) -> anyhow::Result<()> {
+        let Some(public_key) = public_keys.get(&self.peer) else {
+            bail!("Transcript was signed by unknown peer {}", self.peer);
+        };
+
+        SECP256K1
+            .verify_schnorr(
+                &self.signature,
+                &signing_message(&self.entries),
+                &public_key.x_only_public_key().0,
+            )
+            .map_err(|_| format_err!("Invalid signature by peer {}", self.peer))
+    }
+}
+
+/// The message the guardians sign with their broadcast keys, the DKG messages
+/// have no consensus encoding so the JSON serialization is signed instead
+fn signing_message(entries: &[DkgTranscriptEntry]) -> Message {
+    let mut engine = sha256::HashEngine::default();
+
+    engine
+        .write_all(DKG_TRANSCRIPT_TAG)
+        .expect("Writing to a hash engine can not fail");
+
+    serde_json::to_writer(&mut engine, entries).expect("Writing to a hash engine can not fail");
+
+    Message::from(sha256::Hash::from_engine(engine))
+}
+
+/// Verifies the DKG transcripts of one or more guardians against the consensus
+/// config of the federation
+///
+/// Every transcript has to be signed by a guardian of the federation and all
+/// transcripts have to contain the same messages. The messages of every key
+/// are checked with [`verify_key_transcript`] against the public keys the
+/// modules report with `ServerModuleInit::dkg_public_keys`.
+pub fn verify_dkg_transcripts(
+    consensus: &ServerConfigConsensus,
+    registry: &ServerModuleInitRegistry,
+    transcripts: &[SignedDkgTranscript],
+) -> anyhow::Result<()> {
+    let Some(first) = transcripts.first() else {
+        bail!("No transcript to verify");
+    };
+
+    for transcript in transcripts {
+        transcript.verify_signature(&consensus.broadcast_public_keys)?;
+
+        ensure!(
+            transcript.entries == first.entries,
+            "The transcripts of peers {} and {} differ",
+            first.peer,
+            transcript.peer
+        );
+    }
+
+    let peers = consensus
+        .broadcast_public_keys
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+
+    let mut messages: BTreeMap<(ModuleInstanceId, String), Vec<&DkgTranscriptEntry>> =
+        BTreeMap::new();
+    for entry in &first.entries {
+        messages
+            .entry((entry.module_instance_id, entry.key.clone()))
+            .or_default()
+            .push(entry);
+    }
+
+    for (module_instance_id, module) in &consensus.modules {
+        let gen = registry
+            .get(&module.kind)
+            .ok_or_else(|| format_err!("Module gen kind={} not found", module.kind))?;
+
+        for (key, public_keys) in gen.dkg_public_keys(module)? {
+            let entries = messages
+                .remove(&(*module_instance_id, key.clone()))
+                .unwrap_or_default();
+
+            match public_keys {
+                DkgPublicKeys::G1Commitment(commitment) => {
+                    let pks =
+                        verify_key_transcript::<G1Projective>(&peers, &peer_messages(&entries)?)?;
+
+                    ensure!(
+                        pks == commitment,
+                        "Public keys of {key} in module {module_instance_id} do not match"
+                    );
+                }
+                DkgPublicKeys::G2Shares(shares) => {
+                    let pks =
+                        verify_key_transcript::<G2Projective>(&peers, &peer_messages(&entries)?)?;
+
+                    ensure!(
+                        shares.keys().eq(peers.iter()),
+                        "Shares of {key} in module {module_instance_id} do not match our peers"
+                    );
+
+                    for (peer, share) in shares {
+                        ensure!(
+                            evaluate_commitment(&pks, &scalar(&peer)) == share,
+                            "Share of {peer} for {key} in module {module_instance_id} does not match"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some((module_instance_id, key)) = messages.into_keys().next() {
+        bail!("Transcript contains unknown key {key} of module {module_instance_id}");
+    }
+
+    Ok(())
+}
+
+fn peer_messages<G: DkgGroup>(
+    entries: &[&DkgTranscriptEntry],
+) -> anyhow::Result<BTreeMap<PeerId, Vec<DkgMessage<G>>>>
+where
+    DkgMessage<G>: ISupportedDkgMessage,
+{
+    let mut messages: BTreeMap<PeerId, Vec<DkgMessage<G>>> = BTreeMap::new();
+    for entry in entries {
+        let message = ISupportedDkgMessage::from_msg(entry.message.clone())
+            .with_context(|| format!("Invalid message of {} for key {}", entry.peer, entry.key))?;
+        messages.entry(entry.peer).or_default().push(message);
+    }
+    Ok(messages)
+}
+
+/// Checks that every peer published a hashed commitment, the matching
+/// commitment and its extracted public key shares proven to belong to the
+/// commitment, each of the degree required for the threshold of `peers`, and
+/// returns the aggregate public key set the DKG produced
+pub fn verify_key_transcript<G: DkgGroup>(
+    peers: &[PeerId],
+    messages: &BTreeMap<PeerId, Vec<DkgMessage<G>>>,
+) -> anyhow::Result<Vec<G>> {
+    let threshold = peers.threshold();
+
+    ensure!(
+        messages.keys().eq(peers.iter()),
+        "Transcript does not contain the messages of exactly our peers"
+    );
+
+    let mut pks: Vec<G> = vec![G::identity(); threshold];
+    for (peer, messages) in messages {
+        let [DkgMessage::HashedCommit(hashed), DkgMessage::Commit(commit), DkgMessage::Extract(extract, proof)] =
+            messages.as_slice()
+        else {
+            bail!("Unexpected messages from {peer}");
+        };
+
+        ensure!(
+            hash_commitment(commit) == *hashed,
+            "Commitment of {peer} does not match its hash"
+        );
+        ensure!(commit.len() == threshold, "wrong commit degree from {peer}");
+        ensure!(
+            extract.len() == threshold,
+            "wrong extract degree from {peer}"
+        );
+        verify_extract(G::generator(), *peer, commit, extract, proof)?;
+
+        for (pk, coefficient) in pks.iter_mut().zip(extract) {
+            *pk += coefficient;
+        }
+    }
+
+    Ok(pks)
+}
@@ -2,7 +2,12 @@
 
 pub mod debug;
 pub mod server;
+pub mod transaction_pool;
 
+use std::collections::BTreeMap;
+
+use fedimint_core::core::server::DynVerifiedInput;
+use fedimint_core::core::{DynInput, ModuleInstanceId};
 use fedimint_core::db::DatabaseTransaction;
 use fedimint_core::module::registry::ServerModuleRegistry;
 use fedimint_core::module::TransactionItemAmount;
@@ -18,12 +23,44 @@ pub async fn process_transaction_with_dbtx(
     let mut funding_verifier = FundingVerifier::default();
     let mut public_keys = Vec::new();
 
-    for input in transaction.inputs.iter() {
+    let mut module_inputs = BTreeMap::<ModuleInstanceId, Vec<(usize, &DynInput)>>::new();
+    for (input_idx, input) in transaction.inputs.iter().enumerate() {
+        module_inputs
+            .entry(input.module_instance_id())
+            .or_default()
+            .push((input_idx, input));
+    }
+
+    // Every module verifies its inputs at once, what it learned about an input
+    // is passed back to it when the input is processed
+    let mut verified_inputs = BTreeMap::<usize, DynVerifiedInput>::new();
+    for (module_instance_id, inputs) in module_inputs {
+        let (input_idxs, inputs): (Vec<_>, Vec<_>) = inputs.into_iter().unzip();
+
+        let verified = modules
+            .get_expect(module_instance_id)
+            .verify_inputs_batch(
+                &mut dbtx.to_ref_with_prefix_module_id(module_instance_id),
+                &inputs,
+                module_instance_id,
+            )
+            .await
+            .map_err(TransactionError::Input)?;
+
+        verified_inputs.extend(input_idxs.into_iter().zip(verified));
+    }
+
+    for (input_idx, input) in transaction.inputs.iter().enumerate() {
+        let verified_input = verified_inputs
+            .remove(&input_idx)
+            .expect("Every input was verified");
+
         let meta = modules
             .get_expect(input.module_instance_id())
             .process_input(
                 &mut dbtx.to_ref_with_prefix_module_id(input.module_instance_id()),
                 input,
+                verified_input,
                 input.module_instance_id(),
             )
             .await
@@ -5,7 +5,9 @@ use std::time::Duration;
 use aleph_bft::Keychain as KeychainTrait;
 use anyhow::{anyhow, bail};
 use async_channel::{Receiver, Sender};
-use fedimint_core::api::{DynGlobalApi, FederationApiExt, WsFederationApi};
+use fedimint_core::api::{
+    DynGlobalApi, FederationApiExt, TransactionLocation, TransactionRejectionStage, WsFederationApi,
+};
 use fedimint_core::config::ServerModuleInitRegistry;
 use fedimint_core::db::{
     apply_migrations, apply_migrations_server, Database, DatabaseTransaction,
@@ -15,7 +17,8 @@ use fedimint_core::encoding::Decodable;
 use fedimint_core::endpoint_constants::AWAIT_SIGNED_SESSION_OUTCOME_ENDPOINT;
 use fedimint_core::epoch::ConsensusItem;
 use fedimint_core::fmt_utils::OptStacktrace;
-use fedimint_core::module::audit::Audit;
+use fedimint_core::module::audit::{Audit, SessionAuditSummary};
+use fedimint_core::module::liabilities::{LiabilitiesStatement, LIABILITIES_STATEMENT_INTERVAL};
 use fedimint_core::module::registry::{
     ModuleDecoderRegistry, ModuleRegistry, ServerModuleRegistry,
 };
@@ -24,7 +27,7 @@ use fedimint_core::query::FilterMap;
 use fedimint_core::session_outcome::{
     AcceptedItem, SchnorrSignature, SessionOutcome, SignedSessionOutcome,
 };
-use fedimint_core::task::{sleep, spawn, RwLock, TaskGroup, TaskHandle};
+use fedimint_core::task::{sleep, spawn, timeout, RwLock, TaskGroup, TaskHandle};
 use fedimint_core::timing::TimeReporter;
 use fedimint_core::util::SafeUrl;
 use fedimint_core::{timing, PeerId};
@@ -40,19 +43,26 @@ use crate::atomic_broadcast::{to_node_index, Keychain, Message};
 use crate::config::ServerConfig;
 use crate::consensus::debug::FmtDbgConsensusItem;
 use crate::consensus::process_transaction_with_dbtx;
+use crate::consensus::transaction_pool::TransactionPool;
 use crate::db::{
     get_global_database_migrations, AcceptedItemKey, AcceptedItemPrefix, AcceptedTransactionKey,
-    AlephUnitsPrefix, SignedSessionOutcomeKey, SignedSessionOutcomePrefix, GLOBAL_DATABASE_VERSION,
+    AlephUnitsPrefix, CarriedOverItemsKey, LiabilitiesStatementKey, SessionAuditSummaryKey,
+    SignedSessionOutcomeKey, SignedSessionOutcomePrefix, TransactionLocationKey,
+    GLOBAL_DATABASE_VERSION,
 };
-use crate::fedimint_core::encoding::Encodable;
 use crate::net::api::{ConsensusApi, ExpiringCache};
 use crate::net::connect::{Connector, TlsTcpConnector};
 use crate::net::peers::{DelayCalculator, PeerConnector, ReconnectPeerConnections};
+use crate::net::rate_limit::{ApiLimiter, ApiLimits};
 use crate::{atomic_broadcast, LOG_CONSENSUS, LOG_CORE};
 
 /// How many txs can be stored in memory before blocking the API
 const TRANSACTION_BUFFER: usize = 1000;
 
+/// How long we retry fetching the session count or a session from our peers
+/// when syncing the session history on startup
+const SESSION_HISTORY_SYNC_TIMEOUT: Duration = Duration::from_secs(60);
+
 pub(crate) type LatestContributionByPeer = HashMap<PeerId, u64>;
 
 /// Runs the main server consensus loop
@@ -65,6 +75,7 @@ pub struct ConsensusServer {
     cfg: ServerConfig,
     submission_receiver: Receiver<ConsensusItem>,
     latest_contribution_by_peer: Arc<RwLock<LatestContributionByPeer>>,
+    transaction_pool: Arc<RwLock<TransactionPool>>,
 }
 
 impl ConsensusServer {
@@ -167,6 +178,7 @@ impl ConsensusServer {
 
         // Build API that can handle requests
         let latest_contribution_by_peer = Default::default();
+        let transaction_pool = Arc::new(RwLock::new(TransactionPool::default()));
 
         let consensus_api = ConsensusApi {
             cfg: cfg.clone(),
@@ -179,8 +191,12 @@ impl ConsensusServer {
                 &module_inits,
             ),
             latest_contribution_by_peer: Arc::clone(&latest_contribution_by_peer),
+            transaction_pool: Arc::clone(&transaction_pool),
             peer_status_channels,
             consensus_status_cache: ExpiringCache::new(Duration::from_millis(500)),
+            api_limiter: ApiLimiter::new(ApiLimits::from_env()),
+            api_auth: Arc::new(RwLock::new(cfg.private.api_auth.clone())),
+            data_dir: None,
         };
 
         submit_module_consensus_items(
@@ -207,6 +223,7 @@ impl ConsensusServer {
             cfg: cfg.clone(),
             submission_receiver,
             latest_contribution_by_peer,
+            transaction_pool,
             modules,
         };
 
@@ -287,6 +304,8 @@ impl ConsensusServer {
 
         self.confirm_server_config_consensus_hash().await?;
 
+        self.sync_session_history().await;
+
         while !task_handle.is_shutting_down() {
             let session_index = self.get_finished_session_count().await;
 
@@ -326,6 +345,94 @@ impl ConsensusServer {
         }
     }
 
+    /// Downloads and processes the sessions our peers completed while we were
+    /// offline, for example after a guardian was restored from a backup.
+    ///
+    /// The atomic broadcast would eventually catch up as well, but only after
+    /// waiting for units in every session it is behind. If our peers do not
+    /// respond within [`SESSION_HISTORY_SYNC_TIMEOUT`] we leave catching up to
+    /// the atomic broadcast.
+    async fn sync_session_history(&self) {
+        let federation_api = DynGlobalApi::from_endpoints(self.api_endpoints.clone());
+
+        let federation_session_count = timeout(SESSION_HISTORY_SYNC_TIMEOUT, async {
+            loop {
+                match federation_api.session_count().await {
+                    Ok(session_count) => return session_count,
+                    Err(e) => {
+                        warn!(target: LOG_CONSENSUS, "Could not fetch session count from peers: {}", OptStacktrace(e))
+                    }
+                }
+
+                sleep(Duration::from_secs(1)).await;
+            }
+        })
+        .await;
+
+        let Ok(federation_session_count) = federation_session_count else {
+            warn!(target: LOG_CONSENSUS, "Could not fetch session count from peers, catching up with the atomic broadcast instead");
+            return;
+        };
+
+        let session_count = self.get_finished_session_count().await;
+
+        if session_count < federation_session_count {
+            info!(
+                target: LOG_CONSENSUS,
+                "Syncing sessions {session_count} to {federation_session_count} from peers"
+            );
+        }
+
+        for session_index in session_count..federation_session_count {
+            let signed_session_outcome = timeout(SESSION_HISTORY_SYNC_TIMEOUT, async {
+                loop {
+                    match self.fetch_signed_session_outcome(session_index).await {
+                        Ok(signed_session_outcome) => return signed_session_outcome,
+                        Err(error) => {
+                            warn!(target: LOG_CONSENSUS, "Could not fetch signed session outcome {session_index}: {error}")
+                        }
+                    }
+
+                    sleep(Duration::from_secs(1)).await;
+                }
+            })
+            .await;
+
+            let Ok(signed_session_outcome) = signed_session_outcome else {
+                warn!(target: LOG_CONSENSUS, "Could not fetch signed session outcome {session_index}, catching up with the atomic broadcast instead");
+                return;
+            };
+
+            let pending_accepted_items = self.pending_accepted_items().await;
+
+            // this panics if we have more accepted items than the signed session outcome
+            let (processed, unprocessed) = signed_session_outcome
+                .session_outcome
+                .items
+                .split_at(pending_accepted_items.len());
+
+            assert!(processed.iter().eq(pending_accepted_items.iter()));
+
+            for (accepted_item, item_index) in unprocessed.iter().zip(processed.len() as u64..) {
+                let result = self
+                    .process_consensus_item(
+                        session_index,
+                        item_index,
+                        accepted_item.item.clone(),
+                        accepted_item.peer,
+                    )
+                    .await;
+
+                assert!(result.is_ok());
+            }
+
+            self.complete_session(session_index, signed_session_outcome)
+                .await;
+
+            info!(target: LOG_CONSENSUS, "Session {session_index} synced from peers");
+        }
+    }
+
     pub async fn run_session(&self, session_index: u64) -> anyhow::Result<()> {
         // In order to bound a sessions RAM consumption we need to bound its number of
         // units and therefore its number of rounds. Since we use a session to
@@ -433,7 +540,15 @@ impl ConsensusServer {
         signature_sender: watch::Sender<Option<SchnorrSignature>>,
     ) -> anyhow::Result<SignedSessionOutcome> {
         let mut num_batches = 0;
-        let mut item_index = 0;
+        // Items carried over from the previous federation when resharing its keys
+        // precede the ones ordered in this session
+        let mut item_index = self
+            .db
+            .begin_transaction_nc()
+            .await
+            .get_value(&CarriedOverItemsKey)
+            .await
+            .unwrap_or(0);
 
         // We build a session outcome out of the ordered batches until either we have
         // processed batches_per_session_outcome of batches or a threshold signed
@@ -555,6 +670,8 @@ impl ConsensusServer {
 
         dbtx.remove_by_prefix(&AcceptedItemPrefix).await;
 
+        dbtx.remove_entry(&CarriedOverItemsKey).await;
+
         if dbtx
             .insert_entry(
                 &SignedSessionOutcomeKey(session_index),
@@ -566,11 +683,75 @@ impl ConsensusServer {
             panic!("We tried to overwrite a signed session outcome");
         }
 
+        let audit_summary = self
+            .session_audit_summary(&mut dbtx.to_ref_nc(), session_index)
+            .await;
+
+        dbtx.insert_entry(&SessionAuditSummaryKey(session_index), &audit_summary)
+            .await;
+
+        if session_index % LIABILITIES_STATEMENT_INTERVAL == 0 {
+            let statement = self
+                .liabilities_statement(&mut dbtx.to_ref_nc(), session_index)
+                .await;
+
+            dbtx.insert_entry(&LiabilitiesStatementKey(session_index), &statement)
+                .await;
+        }
+
         dbtx.commit_tx_result()
             .await
             .expect("This is the only place where we write to this key");
     }
 
+    async fn session_audit_summary(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        session_index: u64,
+    ) -> SessionAuditSummary {
+        let mut audit = Audit::default();
+        let mut module_instance_id_to_kind = HashMap::new();
+
+        for (module_instance_id, kind, module) in self.modules.iter_modules() {
+            module_instance_id_to_kind.insert(module_instance_id, kind.as_str().to_string());
+            module
+                .audit(
+                    &mut dbtx.to_ref_with_prefix_module_id(module_instance_id),
+                    &mut audit,
+                    module_instance_id,
+                )
+                .await
+        }
+
+        SessionAuditSummary::from_audit(
+            session_index,
+            fedimint_core::time::now(),
+            &audit,
+            &module_instance_id_to_kind,
+        )
+    }
+
+    async fn liabilities_statement(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        session_index: u64,
+    ) -> LiabilitiesStatement {
+        let mut modules = BTreeMap::new();
+
+        for (module_instance_id, _, module) in self.modules.iter_modules() {
+            let liabilities = module
+                .liabilities(&mut dbtx.to_ref_with_prefix_module_id(module_instance_id))
+                .await;
+
+            modules.insert(module_instance_id, liabilities);
+        }
+
+        LiabilitiesStatement {
+            session_index,
+            modules,
+        }
+    }
+
     pub async fn process_consensus_item(
         &self,
         session_index: u64,
@@ -612,6 +793,17 @@ impl ConsensusServer {
         // item has been fully processed without errors
         dbtx.warn_uncommitted();
 
+        if let ConsensusItem::Transaction(transaction) = &item {
+            dbtx.insert_entry(
+                &TransactionLocationKey(transaction.tx_hash()),
+                &TransactionLocation {
+                    session_index,
+                    item_index,
+                },
+            )
+            .await;
+        }
+
         dbtx.insert_entry(&AcceptedItemKey(item_index), &AcceptedItem { item, peer })
             .await;
 
@@ -678,9 +870,19 @@ impl ConsensusServer {
                     .map(|output| output.module_instance_id())
                     .collect::<Vec<_>>();
 
-                process_transaction_with_dbtx(self.modules.clone(), dbtx, transaction)
-                    .await
-                    .map_err(|error| anyhow!(error.to_string()))?;
+                if let Err(error) =
+                    process_transaction_with_dbtx(self.modules.clone(), dbtx, transaction).await
+                {
+                    self.transaction_pool.write().await.reject(
+                        txid,
+                        TransactionRejectionStage::Consensus,
+                        &error,
+                    );
+
+                    bail!(error.to_string());
+                }
+
+                self.transaction_pool.write().await.accept(&txid);
 
                 dbtx.insert_entry(&AcceptedTransactionKey(txid), &modules_ids)
                     .await;
@@ -698,6 +900,27 @@ impl ConsensusServer {
     }
 
     async fn request_signed_session_outcome(&self, index: u64) -> SignedSessionOutcome {
+        loop {
+            // We only want to initiate the request if we have not ordered a unit in a
+            // while. This indicates that we have fallen behind and our peers
+            // have already switched sessions without us
+            sleep(Duration::from_secs(5)).await;
+
+            match self.fetch_signed_session_outcome(index).await {
+                Ok(signed_session_outcome) => return signed_session_outcome,
+                Err(error) => {
+                    tracing::error!("Error while requesting signed session outcome: {}", error)
+                }
+            }
+        }
+    }
+
+    /// Requests a signed session outcome with a valid threshold signature from
+    /// our peers
+    async fn fetch_signed_session_outcome(
+        &self,
+        index: u64,
+    ) -> anyhow::Result<SignedSessionOutcome> {
         let keychain = self.keychain.clone();
         let total_peers = self.keychain.peer_count();
         let decoders = self.decoders();
@@ -726,27 +949,13 @@ impl ConsensusServer {
 
         let federation_api = WsFederationApi::new(self.api_endpoints.clone());
 
-        loop {
-            // We only want to initiate the request if we have not ordered a unit in a
-            // while. This indicates that we have fallen behind and our peers
-            // have already switched sessions without us
-            sleep(Duration::from_secs(5)).await;
-
-            let result = federation_api
-                .request_with_strategy(
-                    FilterMap::new(filter_map.clone(), total_peers),
-                    AWAIT_SIGNED_SESSION_OUTCOME_ENDPOINT.to_string(),
-                    ApiRequestErased::new(index),
-                )
-                .await;
-
-            match result {
-                Ok(signed_session_outcome) => return signed_session_outcome,
-                Err(error) => {
-                    tracing::error!("Error while requesting signed session outcome: {}", error)
-                }
-            }
-        }
+        Ok(federation_api
+            .request_with_strategy(
+                FilterMap::new(filter_map, total_peers),
+                AWAIT_SIGNED_SESSION_OUTCOME_ENDPOINT.to_string(),
+                ApiRequestErased::new(index),
+            )
+            .await?)
     }
 
     /// Returns the number of sessions already saved in the database. This count
@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Instant;
+
+use fedimint_core::api::{
+    PendingTransaction, PendingTransactionsResponse, RejectedTransaction, TransactionRejectionStage,
+};
+use fedimint_core::transaction::TransactionError;
+use fedimint_core::TransactionId;
+
+/// How many submitted transactions we keep track of before we start dropping
+/// the oldest ones
+const MAX_PENDING_TRANSACTIONS: usize = 1000;
+
+/// How many rejected transactions we remember for debugging
+const MAX_REJECTED_TRANSACTIONS: usize = 100;
+
+/// Keeps track of the transactions submitted to our API that have not been
+/// accepted yet and of recently rejected transactions, so guardians can find
+/// out why a transaction never landed.
+///
+/// This is purely informational and not part of consensus, hence it is only
+/// kept in memory.
+#[derive(Debug, Default)]
+pub struct TransactionPool {
+    pending: BTreeMap<TransactionId, (Instant, usize)>,
+    rejected: VecDeque<(TransactionId, Instant, TransactionRejectionStage, String)>,
+}
+
+impl TransactionPool {
+    /// Records a transaction that was submitted to our API and forwarded to
+    /// consensus
+    pub fn insert_pending(&mut self, txid: TransactionId, size: usize) {
+        if self.pending.contains_key(&txid) {
+            return;
+        }
+
+        if self.pending.len() >= MAX_PENDING_TRANSACTIONS {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, (submitted, _))| *submitted)
+                .map(|(txid, _)| *txid);
+
+            if let Some(oldest) = oldest {
+                self.pending.remove(&oldest);
+            }
+        }
+
+        self.pending.insert(txid, (Instant::now(), size));
+    }
+
+    /// Removes a transaction from the pending set once it has been accepted
+    pub fn accept(&mut self, txid: &TransactionId) {
+        self.pending.remove(txid);
+    }
+
+    /// Records why a transaction was rejected and removes it from the pending
+    /// set
+    pub fn reject(
+        &mut self,
+        txid: TransactionId,
+        stage: TransactionRejectionStage,
+        error: &TransactionError,
+    ) {
+        self.pending.remove(&txid);
+
+        if self.rejected.len() >= MAX_REJECTED_TRANSACTIONS {
+            self.rejected.pop_front();
+        }
+
+        self.rejected
+            .push_back((txid, Instant::now(), stage, rejection_reason(error)));
+    }
+
+    /// Pending transactions ordered from oldest to newest and rejected
+    /// transactions ordered from newest to oldest
+    pub fn to_response(&self) -> PendingTransactionsResponse {
+        let mut pending = self
+            .pending
+            .iter()
+            .map(|(txid, (submitted, size))| PendingTransaction {
+                txid: *txid,
+                age_secs: submitted.elapsed().as_secs(),
+                size: *size,
+            })
+            .collect::<Vec<_>>();
+
+        pending.sort_by_key(|tx| std::cmp::Reverse(tx.age_secs));
+
+        let rejected = self
+            .rejected
+            .iter()
+            .rev()
+            .map(|(txid, rejected, stage, reason)| RejectedTransaction {
+                txid: *txid,
+                age_secs: rejected.elapsed().as_secs(),
+                stage: *stage,
+                reason: reason.clone(),
+            })
+            .collect();
+
+        PendingTransactionsResponse { pending, rejected }
+    }
+}
+
+/// The `Display` impl of [`TransactionError`] does not include the errors
+/// returned by modules, so we append them here
+fn rejection_reason(error: &TransactionError) -> String {
+    match error {
+        TransactionError::Input(input_error) => format!("{error}: {input_error}"),
+        TransactionError::Output(output_error) => format!("{error}: {output_error}"),
+        error => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::Hash;
+    use fedimint_core::api::TransactionRejectionStage;
+    use fedimint_core::transaction::TransactionError;
+    use fedimint_core::TransactionId;
+
+    use super::{TransactionPool, MAX_PENDING_TRANSACTIONS, MAX_REJECTED_TRANSACTIONS};
+
+    fn txid(index: u64) -> TransactionId {
+        TransactionId::hash(&index.to_le_bytes())
+    }
+
+    #[test]
+    fn test_transaction_pool() {
+        let mut pool = TransactionPool::default();
+
+        pool.insert_pending(txid(0), 100);
+        pool.insert_pending(txid(1), 200);
+        pool.insert_pending(txid(2), 300);
+
+        pool.accept(&txid(0));
+        pool.reject(
+            txid(1),
+            TransactionRejectionStage::Consensus,
+            &TransactionError::InvalidWitnessLength,
+        );
+
+        let response = pool.to_response();
+
+        assert_eq!(response.pending.len(), 1);
+        assert_eq!(response.pending[0].txid, txid(2));
+        assert_eq!(response.pending[0].size, 300);
+
+        assert_eq!(response.rejected.len(), 1);
+        assert_eq!(response.rejected[0].txid, txid(1));
+        assert_eq!(
+            response.rejected[0].stage,
+            TransactionRejectionStage::Consensus
+        );
+        assert_eq!(
+            response.rejected[0].reason,
+            TransactionError::InvalidWitnessLength.to_string()
+        );
+    }
+
+    #[test]
+    fn test_transaction_pool_is_bounded() {
+        let mut pool = TransactionPool::default();
+
+        for index in 0..(2 * MAX_PENDING_TRANSACTIONS as u64) {
+            pool.insert_pending(txid(index), 0);
+            pool.reject(
+                txid(u64::MAX - index),
+                TransactionRejectionStage::Submission,
+                &TransactionError::InvalidWitnessLength,
+            );
+        }
+
+        let response = pool.to_response();
+
+        assert_eq!(response.pending.len(), MAX_PENDING_TRANSACTIONS);
+        assert_eq!(response.rejected.len(), MAX_REJECTED_TRANSACTIONS);
+    }
+}
@@ -1,15 +1,24 @@
 use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::time::SystemTime;
 
+use fedimint_core::api::TransactionLocation;
 use fedimint_core::core::ModuleInstanceId;
-use fedimint_core::db::{DatabaseVersion, ServerMigrationFn, MODULE_GLOBAL_PREFIX};
+use fedimint_core::db::{
+    DatabaseTransaction, DatabaseVersion, IDatabaseTransactionOpsCoreTyped, ServerMigrationFn,
+    MODULE_GLOBAL_PREFIX,
+};
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::epoch::ConsensusItem;
+use fedimint_core::module::audit::SessionAuditSummary;
+use fedimint_core::module::liabilities::LiabilitiesStatement;
 use fedimint_core::session_outcome::{AcceptedItem, SignedSessionOutcome};
 use fedimint_core::{impl_db_lookup, impl_db_record, TransactionId};
+use futures::{FutureExt, StreamExt};
 use serde::Serialize;
 use strum_macros::EnumIter;
 
-pub const GLOBAL_DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+pub const GLOBAL_DATABASE_VERSION: DatabaseVersion = DatabaseVersion(1);
 
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
@@ -18,6 +27,11 @@ pub enum DbKeyPrefix {
     AcceptedTransaction = 0x02,
     SignedSessionOutcome = 0x04,
     AlephUnits = 0x05,
+    SessionAuditSummary = 0x06,
+    LiabilitiesStatement = 0x07,
+    HealthCheck = 0x08,
+    CarriedOverItems = 0x09,
+    TransactionLocation = 0x0a,
     Module = MODULE_GLOBAL_PREFIX,
 }
 
@@ -89,23 +103,157 @@ impl_db_record!(
 );
 impl_db_lookup!(key = AlephUnitsKey, query_prefix = AlephUnitsPrefix);
 
+#[derive(Debug, Encodable, Decodable)]
+pub struct SessionAuditSummaryKey(pub u64);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct SessionAuditSummaryPrefix;
+
+impl_db_record!(
+    key = SessionAuditSummaryKey,
+    value = SessionAuditSummary,
+    db_prefix = DbKeyPrefix::SessionAuditSummary,
+    notify_on_modify = false,
+);
+impl_db_lookup!(
+    key = SessionAuditSummaryKey,
+    query_prefix = SessionAuditSummaryPrefix
+);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct LiabilitiesStatementKey(pub u64);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct LiabilitiesStatementPrefix;
+
+impl_db_record!(
+    key = LiabilitiesStatementKey,
+    value = LiabilitiesStatement,
+    db_prefix = DbKeyPrefix::LiabilitiesStatement,
+    notify_on_modify = false,
+);
+impl_db_lookup!(
+    key = LiabilitiesStatementKey,
+    query_prefix = LiabilitiesStatementPrefix
+);
+
+/// Written by the database health check to make sure the database accepts
+/// writes, holds the time of the last check
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct HealthCheckKey;
+
+impl_db_record!(
+    key = HealthCheckKey,
+    value = SystemTime,
+    db_prefix = DbKeyPrefix::HealthCheck,
+    notify_on_modify = false,
+);
+
+/// Number of accepted items the current session carried over from the
+/// previous federation when its keys were reshared, see
+/// [`crate::config::reshare`]
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct CarriedOverItemsKey;
+
+impl_db_record!(
+    key = CarriedOverItemsKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::CarriedOverItems,
+    notify_on_modify = false,
+);
+
+/// Session and position of every accepted transaction, so transactions can be
+/// looked up in the history of the federation
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct TransactionLocationKey(pub TransactionId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct TransactionLocationPrefix;
+
+impl_db_record!(
+    key = TransactionLocationKey,
+    value = TransactionLocation,
+    db_prefix = DbKeyPrefix::TransactionLocation,
+    notify_on_modify = false,
+);
+impl_db_lookup!(
+    key = TransactionLocationKey,
+    query_prefix = TransactionLocationPrefix
+);
+
 pub fn get_global_database_migrations() -> BTreeMap<DatabaseVersion, ServerMigrationFn> {
-    BTreeMap::new()
+    let mut migrations: BTreeMap<DatabaseVersion, ServerMigrationFn> = BTreeMap::new();
+    migrations.insert(DatabaseVersion(0), move |dbtx| migrate_to_v1(dbtx).boxed());
+    migrations
+}
+
+/// Indexes the location of the transactions accepted before it was recorded
+async fn migrate_to_v1(dbtx: &mut DatabaseTransaction<'_>) -> anyhow::Result<()> {
+    let signed_session_outcomes = dbtx
+        .find_by_prefix(&SignedSessionOutcomePrefix)
+        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut locations = Vec::new();
+    for (key, signed_session_outcome) in &signed_session_outcomes {
+        for (accepted_item, item_index) in signed_session_outcome
+            .session_outcome
+            .items
+            .iter()
+            .zip(0u64..)
+        {
+            locations.push((&accepted_item.item, key.0, item_index));
+        }
+    }
+
+    // The accepted items of the running session follow the last finished one
+    let session_count = signed_session_outcomes
+        .last()
+        .map_or(0, |(key, _)| key.0 + 1);
+    let pending_items = dbtx
+        .find_by_prefix(&AcceptedItemPrefix)
+        .await
+        .collect::<Vec<_>>()
+        .await;
+    for (key, accepted_item) in &pending_items {
+        locations.push((&accepted_item.item, session_count, key.0));
+    }
+
+    for (item, session_index, item_index) in locations {
+        if let ConsensusItem::Transaction(transaction) = item {
+            dbtx.insert_entry(
+                &TransactionLocationKey(transaction.tx_hash()),
+                &TransactionLocation {
+                    session_index,
+                    item_index,
+                },
+            )
+            .await;
+        }
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod fedimint_migration_tests {
     use std::collections::BTreeMap;
     use std::str::FromStr;
+    use std::time::UNIX_EPOCH;
 
     use anyhow::ensure;
     use bitcoin::{secp256k1, KeyPair};
     use bitcoin_hashes::Hash;
+    use fedimint_core::api::TransactionLocation;
     use fedimint_core::core::{DynInput, DynOutput};
     use fedimint_core::db::{
-        Database, DatabaseVersion, DatabaseVersionKeyV0, IDatabaseTransactionOpsCoreTyped,
+        Database, DatabaseVersion, DatabaseVersionKey, DatabaseVersionKeyV0,
+        IDatabaseTransactionOpsCoreTyped, MODULE_GLOBAL_PREFIX,
     };
     use fedimint_core::epoch::ConsensusItem;
+    use fedimint_core::module::audit::{ModuleAuditSummary, SessionAuditSummary};
+    use fedimint_core::module::liabilities::{LiabilitiesStatement, ModuleLiabilities};
     use fedimint_core::module::registry::ModuleDecoderRegistry;
     use fedimint_core::module::CommonModuleInit;
     use fedimint_core::session_outcome::{SessionOutcome, SignedSessionOutcome};
@@ -128,7 +276,9 @@ mod fedimint_migration_tests {
     use crate::db::{
         get_global_database_migrations, AcceptedItem, AcceptedItemKey, AcceptedItemPrefix,
         AcceptedTransactionKeyPrefix, AlephUnitsKey, AlephUnitsPrefix, DbKeyPrefix,
-        SignedSessionOutcomeKey, SignedSessionOutcomePrefix, GLOBAL_DATABASE_VERSION,
+        LiabilitiesStatementKey, LiabilitiesStatementPrefix, SessionAuditSummaryKey,
+        SessionAuditSummaryPrefix, SignedSessionOutcomeKey, SignedSessionOutcomePrefix,
+        TransactionLocationKey, TransactionLocationPrefix, GLOBAL_DATABASE_VERSION,
     };
 
     /// Create a database with version 0 data. The database produced is not
@@ -201,6 +351,118 @@ mod fedimint_migration_tests {
         dbtx.commit_tx().await;
     }
 
+    /// Create a database with version 1 data, which also contains the records
+    /// introduced after the v0 snapshot was taken. Like
+    /// [`create_server_db_with_v0_data`] this function should not be updated
+    /// when database keys/values change.
+    async fn create_server_db_with_v1_data(db: Database) {
+        let mut dbtx = db.begin_transaction().await;
+
+        dbtx.insert_new_entry(
+            &DatabaseVersionKey(MODULE_GLOBAL_PREFIX.into()),
+            &DatabaseVersion(1),
+        )
+        .await;
+
+        let (sk, _) = secp256k1::generate_keypair(&mut OsRng);
+        let secp = secp256k1::Secp256k1::new();
+        let key_pair = KeyPair::from_secret_key(&secp, &sk);
+        let schnorr = secp.sign_schnorr(&Message::from_slice(&BYTE_32).unwrap(), &key_pair);
+        let transaction = Transaction {
+            inputs: vec![DynInput::from_typed(
+                0,
+                DummyInput {
+                    amount: Amount::ZERO,
+                    account: key_pair.public_key(),
+                },
+            )],
+            outputs: vec![DynOutput::from_typed(
+                0,
+                DummyOutput {
+                    amount: Amount::ZERO,
+                    account: key_pair.public_key(),
+                },
+            )],
+            nonce: [0x42; 8],
+            signatures: TransactionSignature::NaiveMultisig(vec![schnorr]),
+        };
+        let txid = transaction.tx_hash();
+
+        let module_ids = transaction
+            .outputs
+            .iter()
+            .map(|output| output.module_instance_id())
+            .collect::<Vec<_>>();
+
+        dbtx.insert_new_entry(&AcceptedTransactionKey(txid), &module_ids)
+            .await;
+
+        dbtx.insert_new_entry(
+            &SignedSessionOutcomeKey(0),
+            &SignedSessionOutcome {
+                session_outcome: SessionOutcome { items: Vec::new() },
+                signatures: BTreeMap::new(),
+            },
+        )
+        .await;
+
+        // the transaction is pending in the session following the signed one
+        dbtx.insert_new_entry(
+            &AcceptedItemKey(0),
+            &AcceptedItem {
+                item: ConsensusItem::Transaction(transaction),
+                peer: PeerId::from_str("0").unwrap(),
+            },
+        )
+        .await;
+
+        dbtx.insert_new_entry(
+            &TransactionLocationKey(txid),
+            &TransactionLocation {
+                session_index: 1,
+                item_index: 0,
+            },
+        )
+        .await;
+
+        dbtx.insert_new_entry(&AlephUnitsKey(0), &vec![42, 42, 42])
+            .await;
+
+        dbtx.insert_new_entry(
+            &SessionAuditSummaryKey(0),
+            &SessionAuditSummary {
+                session_index: 0,
+                timestamp: UNIX_EPOCH,
+                module_summaries: BTreeMap::from([(
+                    TEST_MODULE_INSTANCE_ID,
+                    ModuleAuditSummary {
+                        kind: DummyCommonInit::KIND.to_string(),
+                        assets_msat: 42,
+                        liabilities_msat: 21,
+                    },
+                )]),
+            },
+        )
+        .await;
+
+        dbtx.insert_new_entry(
+            &LiabilitiesStatementKey(0),
+            &LiabilitiesStatement {
+                session_index: 0,
+                modules: BTreeMap::from([(
+                    TEST_MODULE_INSTANCE_ID,
+                    ModuleLiabilities {
+                        liabilities: Amount::from_sats(21),
+                        reserves: vec![],
+                    },
+                )]),
+            },
+        )
+        .await;
+
+        dbtx.commit_tx().await;
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn snapshot_server_db_migrations() -> anyhow::Result<()> {
         snapshot_db_migrations_with_decoders(
@@ -219,6 +481,24 @@ mod fedimint_migration_tests {
         .await
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn snapshot_server_db_migrations_v1() -> anyhow::Result<()> {
+        snapshot_db_migrations_with_decoders(
+            "fedimint-server-v1",
+            |db| {
+                Box::pin(async move {
+                    create_server_db_with_v1_data(db).await;
+                })
+            },
+            ModuleDecoderRegistry::from_iter([(
+                TEST_MODULE_INSTANCE_ID,
+                DummyCommonInit::KIND,
+                <Dummy as ServerModule>::decoder(),
+            )]),
+        )
+        .await
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_server_db_migrations() -> anyhow::Result<()> {
         let _ = TracingSetup::default().init();
@@ -281,6 +561,53 @@ mod fedimint_migration_tests {
                             );
                             info!(target: LOG_DB, "Validated AlephUnits");
                         }
+                        // Introduced after the v0 snapshot and covered by
+                        // `test_server_db_migrations_v1`, we only make sure that existing
+                        // ones decode
+                        DbKeyPrefix::SessionAuditSummary => {
+                            let num_session_audit_summaries = dbtx
+                                .find_by_prefix(&SessionAuditSummaryPrefix)
+                                .await
+                                .collect::<Vec<_>>()
+                                .await
+                                .len();
+                            info!(
+                                target: LOG_DB,
+                                num_session_audit_summaries, "Validated SessionAuditSummary"
+                            );
+                        }
+                        // Introduced after the v0 snapshot and covered by
+                        // `test_server_db_migrations_v1`, we only make sure that existing
+                        // ones decode
+                        DbKeyPrefix::LiabilitiesStatement => {
+                            let num_liabilities_statements = dbtx
+                                .find_by_prefix(&LiabilitiesStatementPrefix)
+                                .await
+                                .collect::<Vec<_>>()
+                                .await
+                                .len();
+                            info!(
+                                target: LOG_DB,
+                                num_liabilities_statements, "Validated LiabilitiesStatement"
+                            );
+                        }
+                        // Only records the time of the last health check
+                        DbKeyPrefix::HealthCheck => {}
+                        // Introduced after the v0 snapshot, nothing to migrate
+                        DbKeyPrefix::CarriedOverItems => {}
+                        DbKeyPrefix::TransactionLocation => {
+                            let num_transaction_locations = dbtx
+                                .find_by_prefix(&TransactionLocationPrefix)
+                                .await
+                                .collect::<Vec<_>>()
+                                .await
+                                .len();
+                            ensure!(
+                                num_transaction_locations > 0,
+                                "validate_migrations was not able to read any TransactionLocations"
+                            );
+                            info!(target: LOG_DB, "Validated TransactionLocation");
+                        }
                         // Module prefix is reserved for modules, no migration testing is needed
                         DbKeyPrefix::Module => {}
                     }
@@ -298,4 +625,80 @@ mod fedimint_migration_tests {
         )
         .await
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_server_db_migrations_v1() -> anyhow::Result<()> {
+        let _ = TracingSetup::default().init();
+
+        validate_migrations_global(
+            |db| async move {
+                let mut dbtx = db.begin_transaction().await;
+
+                for prefix in DbKeyPrefix::iter() {
+                    match prefix {
+                        DbKeyPrefix::SessionAuditSummary => {
+                            let num_session_audit_summaries = dbtx
+                                .find_by_prefix(&SessionAuditSummaryPrefix)
+                                .await
+                                .collect::<Vec<_>>()
+                                .await
+                                .len();
+                            ensure!(
+                                num_session_audit_summaries > 0,
+                                "validate_migrations was not able to read any SessionAuditSummaries"
+                            );
+                            info!(target: LOG_DB, "Validated SessionAuditSummary");
+                        }
+                        DbKeyPrefix::LiabilitiesStatement => {
+                            let num_liabilities_statements = dbtx
+                                .find_by_prefix(&LiabilitiesStatementPrefix)
+                                .await
+                                .collect::<Vec<_>>()
+                                .await
+                                .len();
+                            ensure!(
+                                num_liabilities_statements > 0,
+                                "validate_migrations was not able to read any LiabilitiesStatements"
+                            );
+                            info!(target: LOG_DB, "Validated LiabilitiesStatement");
+                        }
+                        DbKeyPrefix::TransactionLocation => {
+                            let num_transaction_locations = dbtx
+                                .find_by_prefix(&TransactionLocationPrefix)
+                                .await
+                                .collect::<Vec<_>>()
+                                .await
+                                .len();
+                            ensure!(
+                                num_transaction_locations > 0,
+                                "validate_migrations was not able to read any TransactionLocations"
+                            );
+                            info!(target: LOG_DB, "Validated TransactionLocation");
+                        }
+                        // Covered by the v0 snapshot in `test_server_db_migrations`
+                        DbKeyPrefix::AcceptedItem
+                        | DbKeyPrefix::AcceptedTransaction
+                        | DbKeyPrefix::SignedSessionOutcome
+                        | DbKeyPrefix::AlephUnits => {}
+                        // Only records the time of the last health check
+                        DbKeyPrefix::HealthCheck => {}
+                        // Only written when the keys of the federation are reshared
+                        DbKeyPrefix::CarriedOverItems => {}
+                        // Module prefix is reserved for modules, no migration testing is needed
+                        DbKeyPrefix::Module => {}
+                    }
+                }
+                Ok(())
+            },
+            "fedimint-server-v1",
+            GLOBAL_DATABASE_VERSION,
+            get_global_database_migrations(),
+            ModuleDecoderRegistry::from_iter([(
+                TEST_MODULE_INSTANCE_ID,
+                DummyCommonInit::KIND,
+                <Dummy as ServerModule>::decoder(),
+            )]),
+        )
+        .await
+    }
 }
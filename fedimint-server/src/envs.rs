@@ -1,3 +1,36 @@
 /// The env var for maximum open connections the API can handle
 pub const FM_MAX_CLIENT_CONNECTIONS_ENV: &str = "FM_MAX_CLIENT_CONNECTIONS";
 pub const FM_PEER_ID_SORT_BY_URL_ENV: &str = "FM_PEER_ID_SORT_BY_URL";
+
+/// The env var for how many transactions per second `submit_transaction`
+/// accepts from a single address
+pub const FM_API_SUBMIT_TRANSACTION_RATE_ENV: &str = "FM_API_SUBMIT_TRANSACTION_RATE";
+/// The env var for how many `await_*` requests a single address can have open
+/// at the same time
+pub const FM_API_MAX_CONCURRENT_AWAITS_ENV: &str = "FM_API_MAX_CONCURRENT_AWAITS";
+/// The env var for how many backups per second the API accepts from a single
+/// address
+pub const FM_API_BACKUP_RATE_ENV: &str = "FM_API_BACKUP_RATE";
+/// The env var for the maximum size of a backup in bytes
+pub const FM_API_MAX_BACKUP_SIZE_ENV: &str = "FM_API_MAX_BACKUP_SIZE";
+/// The env var for the minimum time between two backups with the same id
+pub const FM_API_BACKUP_MIN_INTERVAL_SECS_ENV: &str = "FM_API_BACKUP_MIN_INTERVAL_SECS";
+/// The env var for how many requests per second the API accepts from a single
+/// connection
+pub const FM_API_CONNECTION_REQUEST_RATE_ENV: &str = "FM_API_CONNECTION_REQUEST_RATE";
+/// The env var for how many `await_*` requests a single connection can have
+/// open at the same time
+pub const FM_API_MAX_CONNECTION_AWAITS_ENV: &str = "FM_API_MAX_CONNECTION_AWAITS";
+/// The env var for whether the API limits clients by the address in the
+/// `X-Forwarded-For` header, only set this behind a reverse proxy that appends
+/// to it
+pub const FM_API_TRUST_FORWARDED_FOR_ENV: &str = "FM_API_TRUST_FORWARDED_FOR";
+/// The env var for how many seconds may pass since the last finished session
+/// before the health endpoint reports the guardian as unhealthy
+pub const FM_HEALTH_MAX_SESSION_AGE_SECS_ENV: &str = "FM_HEALTH_MAX_SESSION_AGE_SECS";
+
+/// The env var for the path of a guardian config backup to restore from
+pub const FM_RESTORE_FROM_BACKUP_ENV: &str = "FM_RESTORE_FROM_BACKUP";
+/// The env var for the path of a headless setup file to set up the federation
+/// from
+pub const FM_SETUP_FILE_ENV: &str = "FM_SETUP_FILE";
@@ -0,0 +1,210 @@
+//! Health checks of the guardian served by the metrics server
+//!
+//! The checks depend on the phase the guardian is in, during config gen only
+//! the [`ServerStatus`] and the database are reported, once consensus is
+//! running the progress of the sessions, the connections to our peers and the
+//! checks of the modules are reported as well.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use fedimint_core::api::{PeerConnectionStatus, ServerStatus};
+use fedimint_core::db::{Database, IDatabaseTransactionOpsCoreTyped};
+use fedimint_core::health::{
+    age_secs, check_database_writable, HealthReport, HealthStatus, IHealthChecks,
+};
+use fedimint_core::NumPeers;
+use futures::StreamExt;
+use serde_json::json;
+use tokio::sync::RwLock;
+
+use crate::config::api::ConfigGenApi;
+use crate::db::{HealthCheckKey, SessionAuditSummaryPrefix};
+use crate::envs::FM_HEALTH_MAX_SESSION_AGE_SECS_ENV;
+use crate::net::api::ConsensusApi;
+
+/// How long ago the last session may have finished before the guardian is
+/// reported as unhealthy, unless overridden by
+/// [`FM_HEALTH_MAX_SESSION_AGE_SECS_ENV`]
+const DEFAULT_MAX_SESSION_AGE: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Clone)]
+enum HealthPhase {
+    Starting,
+    ConfigGen(ConfigGenApi),
+    Consensus {
+        api: Box<ConsensusApi>,
+        started: SystemTime,
+    },
+}
+
+/// Shared handle reporting the health of the guardian, updated by
+/// [`crate::FedimintServer`] as it moves from config gen to consensus
+#[derive(Clone)]
+pub struct ServerHealth {
+    db: Database,
+    max_session_age: Duration,
+    phase: Arc<RwLock<HealthPhase>>,
+}
+
+impl fmt::Debug for ServerHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerHealth")
+            .field("max_session_age", &self.max_session_age)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ServerHealth {
+    pub fn new(db: Database) -> Self {
+        let max_session_age = std::env::var(FM_HEALTH_MAX_SESSION_AGE_SECS_ENV)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map_or(DEFAULT_MAX_SESSION_AGE, Duration::from_secs);
+
+        ServerHealth {
+            db,
+            max_session_age,
+            phase: Arc::new(RwLock::new(HealthPhase::Starting)),
+        }
+    }
+
+    pub(crate) async fn set_config_gen(&self, api: ConfigGenApi) {
+        *self.phase.write().await = HealthPhase::ConfigGen(api);
+    }
+
+    pub(crate) async fn set_consensus(&self, api: ConsensusApi) {
+        *self.phase.write().await = HealthPhase::Consensus {
+            api: Box::new(api),
+            started: fedimint_core::time::now(),
+        };
+    }
+
+    async fn check_status(phase: &HealthPhase) -> HealthStatus {
+        match phase {
+            HealthPhase::Starting => HealthStatus::unhealthy(json!({ "status": "starting" })),
+            HealthPhase::ConfigGen(api) => {
+                let status = api.server_status().await;
+                HealthStatus::from_bool(
+                    status == ServerStatus::ConsensusRunning,
+                    json!({ "status": status }),
+                )
+            }
+            HealthPhase::Consensus { .. } => {
+                HealthStatus::healthy(json!({ "status": ServerStatus::ConsensusRunning }))
+            }
+        }
+    }
+
+    async fn check_session(&self, api: &ConsensusApi, started: SystemTime) -> HealthStatus {
+        let last_session = api
+            .db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix_sorted_descending(&SessionAuditSummaryPrefix)
+            .await
+            .next()
+            .await
+            .map(|(_, summary)| summary);
+
+        // A guardian that just started has not had the chance to finish a session
+        let since = last_session
+            .as_ref()
+            .map_or(started, |summary| summary.timestamp.max(started));
+        let age = age_secs(since);
+
+        HealthStatus::from_bool(
+            age <= self.max_session_age.as_secs(),
+            json!({
+                "session_index": last_session.map(|summary| summary.session_index),
+                "age_secs": age,
+                "max_age_secs": self.max_session_age.as_secs(),
+            }),
+        )
+    }
+
+    async fn check_peers(api: &ConsensusApi) -> HealthStatus {
+        let peers = api
+            .peer_status_channels
+            .get_all_status()
+            .await
+            .into_iter()
+            .map(|(peer, status)| (peer, status.unwrap_or(PeerConnectionStatus::Disconnected)))
+            .collect::<BTreeMap<_, _>>();
+
+        let connected = peers
+            .values()
+            .filter(|status| **status == PeerConnectionStatus::Connected)
+            .count();
+
+        // we count ourselves towards the threshold
+        let threshold = api.cfg.consensus.broadcast_public_keys.threshold();
+
+        HealthStatus::from_bool(
+            connected + 1 >= threshold,
+            json!({
+                "connected": connected,
+                "threshold": threshold,
+                "peers": peers,
+            }),
+        )
+    }
+}
+
+#[async_trait]
+impl IHealthChecks for ServerHealth {
+    async fn health_checks(&self) -> HealthReport {
+        let phase = self.phase.read().await.clone();
+
+        let mut report = HealthReport::new();
+        report.insert(
+            "database".to_string(),
+            check_database_writable(&self.db, &HealthCheckKey).await,
+        );
+        report.insert("status".to_string(), Self::check_status(&phase).await);
+
+        if let HealthPhase::Consensus { api, started } = phase {
+            report.insert(
+                "session".to_string(),
+                self.check_session(&api, started).await,
+            );
+            report.insert("peers".to_string(), Self::check_peers(&api).await);
+
+            for (module_instance_id, _, module) in api.modules.iter_modules() {
+                for (name, status) in module.health().await {
+                    report.insert(format!("module_{module_instance_id}_{name}"), status);
+                }
+            }
+        }
+
+        report
+    }
+
+    async fn health_check(&self, name: &str) -> Option<HealthStatus> {
+        let phase = self.phase.read().await.clone();
+
+        match name {
+            "database" => return Some(check_database_writable(&self.db, &HealthCheckKey).await),
+            "status" => return Some(Self::check_status(&phase).await),
+            _ => {}
+        }
+
+        let HealthPhase::Consensus { api, started } = phase else {
+            return None;
+        };
+
+        match name {
+            "session" => Some(self.check_session(&api, started).await),
+            "peers" => Some(Self::check_peers(&api).await),
+            _ => {
+                let (module_instance_id, check) = name.strip_prefix("module_")?.split_once('_')?;
+                let module = api.modules.get(module_instance_id.parse().ok()?)?;
+
+                module.health().await.remove(check)
+            }
+        }
+    }
+}
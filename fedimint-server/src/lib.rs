@@ -31,9 +31,12 @@ use tokio::runtime::Runtime;
 use tracing::{error, info};
 
 use crate::config::api::{ConfigGenApi, ConfigGenSettings};
+use crate::config::setup::HeadlessSetup;
 use crate::consensus::server::ConsensusServer;
+use crate::health::ServerHealth;
 use crate::net::api::{ConsensusApi, RpcHandlerCtx};
 use crate::net::connect::TlsTcpConnector;
+use crate::net::rate_limit::{scope_connection, take_call_connection, ApiLimiter, ApiLimits};
 
 pub mod envs;
 
@@ -51,6 +54,9 @@ pub mod net;
 /// Fedimint toplevel config
 pub mod config;
 
+/// Health checks served alongside the metrics
+pub mod health;
+
 /// Implementation of multiplexed peer connections
 pub mod multiplexed;
 
@@ -78,6 +84,11 @@ pub struct FedimintServer {
     pub settings: ConfigGenSettings,
     /// Database shared by the API and consensus
     pub db: Database,
+    /// Runs the config gen steps automatically instead of waiting for the
+    /// guardian to call the config gen API
+    pub setup: Option<HeadlessSetup>,
+    /// Reports the health of the phase the server is in
+    pub health: ServerHealth,
 
     /// Version hash
     pub version_hash: String,
@@ -92,7 +103,7 @@ impl FedimintServer {
             .run_config_gen(task_group.make_subgroup().await)
             .await?;
 
-        let (consensus_server, consensus_api) = ConsensusServer::new(
+        let (consensus_server, mut consensus_api) = ConsensusServer::new(
             cfg,
             self.db.clone(),
             self.settings.registry.clone(),
@@ -101,6 +112,9 @@ impl FedimintServer {
         .await
         .unwrap();
 
+        consensus_api.data_dir = Some(self.data_dir.clone());
+        self.health.set_consensus(consensus_api.clone()).await;
+
         info!(target: LOG_CONSENSUS, "Starting consensus API");
 
         let handler = Self::spawn_consensus_api(consensus_api, true).await;
@@ -129,26 +143,70 @@ impl FedimintServer {
             &mut task_group,
             self.version_hash.clone(),
         );
+        self.health.set_config_gen(config_gen.clone()).await;
+
+        let password = fs::read_to_string(self.data_dir.join(PLAINTEXT_PASSWORD)).ok();
 
         // Attempt get the config with local password, otherwise start config gen
-        if let Ok(password) = fs::read_to_string(self.data_dir.join(PLAINTEXT_PASSWORD)) {
+        if let Some(password) = &password {
             config_gen
                 .set_password(ApiAuth(password.clone()))
                 .map_err(|_| format_err!("Unable to use local password"))?;
             info!(target: LOG_CONSENSUS, "Setting password from local file");
 
-            if config_gen.start_consensus(ApiAuth(password)).await.is_ok() {
+            if config_gen
+                .start_consensus(ApiAuth(password.clone()))
+                .await
+                .is_ok()
+            {
                 info!(target: LOG_CONSENSUS, "Configs found locally");
                 return Ok(config_generated_rx.recv().await.expect("should not close"));
             }
         }
 
-        let mut rpc_module = RpcHandlerCtx::new_module(config_gen);
-        Self::attach_endpoints(&mut rpc_module, config::api::server_endpoints(), None);
-        let handler =
-            Self::spawn_api("config-gen", &self.settings.api_bind, rpc_module, 10, true).await;
+        let (setup_error_tx, mut setup_error_rx) = tokio::sync::mpsc::channel(1);
+        if let Some(setup) = self.setup.clone() {
+            let password = password.context("Headless setup requires a local password")?;
+            let config_gen = config_gen.clone();
+
+            info!(target: LOG_CONSENSUS, "Running headless setup");
+
+            task_group
+                .spawn("headless setup", move |_handle| async move {
+                    if let Err(error) = setup.run(config_gen, ApiAuth(password)).await {
+                        error!(target: LOG_CONSENSUS, ?error, "Headless setup failed");
+                        let _ = setup_error_tx.send(error).await;
+                    }
+                })
+                .await;
+        }
 
-        let cfg = config_generated_rx.recv().await.expect("should not close");
+        let api_limiter = ApiLimiter::new(ApiLimits::from_env());
+        let mut rpc_module = RpcHandlerCtx::new_module(config_gen);
+        Self::attach_endpoints(
+            &mut rpc_module,
+            config::api::server_endpoints(),
+            None,
+            &api_limiter,
+        );
+        let handler = Self::spawn_api(
+            "config-gen",
+            &self.settings.api_bind,
+            rpc_module,
+            10,
+            &api_limiter,
+            true,
+        )
+        .await;
+
+        // A failed headless setup cannot be completed through the UI, so we exit
+        let cfg = tokio::select! {
+            cfg = config_generated_rx.recv() => cfg.expect("should not close"),
+            Some(error) = setup_error_rx.recv() => {
+                handler.stop().await;
+                return Err(error.context("Headless setup failed"));
+            }
+        };
         handler.stop().await;
         Ok(cfg)
     }
@@ -160,10 +218,21 @@ impl FedimintServer {
         force_shutdown: bool,
     ) -> FedimintApiHandler {
         let cfg = &api.cfg.local;
+        let api_limiter = &api.api_limiter;
         let mut rpc_module = RpcHandlerCtx::new_module(api.clone());
-        Self::attach_endpoints(&mut rpc_module, net::api::server_endpoints(), None);
+        Self::attach_endpoints(
+            &mut rpc_module,
+            net::api::server_endpoints(),
+            None,
+            api_limiter,
+        );
         for (id, _, module) in api.modules.iter_modules() {
-            Self::attach_endpoints(&mut rpc_module, module.api_endpoints(), Some(id));
+            Self::attach_endpoints(
+                &mut rpc_module,
+                module.api_endpoints(),
+                Some(id),
+                api_limiter,
+            );
         }
 
         Self::spawn_api(
@@ -171,6 +240,7 @@ impl FedimintServer {
             &cfg.api_bind,
             rpc_module,
             cfg.max_connections,
+            api_limiter,
             force_shutdown,
         )
         .await
@@ -186,11 +256,13 @@ impl FedimintServer {
         api_bind: &SocketAddr,
         module: RpcModule<RpcHandlerCtx<T>>,
         max_connections: u32,
+        api_limiter: &ApiLimiter,
         force_shutdown: bool,
     ) -> FedimintApiHandler {
         let mut builder = ServerBuilder::new()
             .max_connections(max_connections)
-            .ping_interval(Duration::from_secs(10));
+            .ping_interval(Duration::from_secs(10))
+            .set_logger(api_limiter.logger());
 
         let runtime = if force_shutdown {
             let runtime = Runtime::new().expect("Creates runtime");
@@ -213,11 +285,13 @@ impl FedimintServer {
         FedimintApiHandler { handle, runtime }
     }
 
-    /// Attaches `endpoints` to the `RpcModule`
+    /// Attaches `endpoints` to the `RpcModule`, every call is checked against
+    /// the limits of `api_limiter`
     fn attach_endpoints<State, T>(
         rpc_module: &mut RpcModule<RpcHandlerCtx<T>>,
         endpoints: Vec<ApiEndpoint<State>>,
         module_instance_id: Option<ModuleInstanceId>,
+        api_limiter: &ApiLimiter,
     ) where
         T: HasApiContext<State> + Sync + Send + 'static,
         State: Sync + Send + 'static,
@@ -240,44 +314,56 @@ impl FedimintServer {
             // Another memory leak that is fine because the function is only called once at
             // startup
             let handler: &'static _ = Box::leak(endpoint.handler);
+            let endpoint_path = endpoint.path;
+            let api_limiter = api_limiter.clone();
 
             rpc_module
-                .register_async_method(path, move |params, rpc_state| async move {
-                    let params = params.one::<serde_json::Value>()?;
-                    let rpc_context = &rpc_state.rpc_context;
-
-                    // Using AssertUnwindSafe here is far from ideal. In theory this means we could
-                    // end up with an inconsistent state in theory. In practice most API functions
-                    // are only reading and the few that do write anything are atomic. Lastly, this
-                    // is only the last line of defense
-                    AssertUnwindSafe(tokio::time::timeout(API_ENDPOINT_TIMEOUT, async {
-                        let request = serde_json::from_value(params)
-                            .map_err(|e| ApiError::bad_request(e.to_string()))?;
-                        let (state, context) =
-                            rpc_context.context(&request, module_instance_id).await;
-
-                        (handler)(state, context, request).await
-                    }))
-                    .catch_unwind()
-                    .await
-                    .map_err(|_| {
-                        error!(
-                            target: LOG_NET_API,
-                            path, "API handler panicked, DO NOT IGNORE, FIX IT!!!"
-                        );
-                        jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
-                            500,
-                            "API handler panicked",
-                            None::<()>,
-                        )))
-                    })?
-                    .map_err(|tokio::time::error::Elapsed { .. }| {
-                        jsonrpsee::core::Error::RequestTimeout
-                    })?
-                    .map_err(|e| {
-                        jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
-                            e.code, e.message, None::<()>,
-                        )))
+                .register_async_method(path, move |params, rpc_state| {
+                    let connection = take_call_connection();
+                    let api_limiter = api_limiter.clone();
+
+                    scope_connection(connection.clone(), async move {
+                        let params = params.one::<serde_json::Value>()?;
+                        let rpc_context = &rpc_state.rpc_context;
+
+                        // Using AssertUnwindSafe here is far from ideal. In theory this means we
+                        // could end up with an inconsistent state in theory. In practice most API
+                        // functions are only reading and the few that do write anything are
+                        // atomic. Lastly, this is only the last line of defense
+                        AssertUnwindSafe(tokio::time::timeout(API_ENDPOINT_TIMEOUT, async {
+                            // Limits are applied by the name of the endpoint within its module,
+                            // so module `await_*` endpoints are limited like the core ones
+                            let _permits =
+                                api_limiter.check_call(connection.as_deref(), endpoint_path)?;
+
+                            let request = serde_json::from_value(params)
+                                .map_err(|e| ApiError::bad_request(e.to_string()))?;
+                            let (state, context) =
+                                rpc_context.context(&request, module_instance_id).await;
+
+                            (handler)(state, context, request).await
+                        }))
+                        .catch_unwind()
+                        .await
+                        .map_err(|_| {
+                            error!(
+                                target: LOG_NET_API,
+                                path, "API handler panicked, DO NOT IGNORE, FIX IT!!!"
+                            );
+                            jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+                                500,
+                                "API handler panicked",
+                                None::<()>,
+                            )))
+                        })?
+                        .map_err(|tokio::time::error::Elapsed { .. }| {
+                            jsonrpsee::core::Error::RequestTimeout
+                        })?
+                        .map_err(|e| {
+                            jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+                                e.code, e.message, None::<()>,
+                            )))
+                        })
                     })
                 })
                 .expect("Failed to register async method");
@@ -11,29 +11,35 @@ use async_trait::async_trait;
 use bitcoin_hashes::sha256;
 use fedimint_aead::{encrypt, get_encryption_key, random_salt};
 use fedimint_core::api::{
-    FederationStatus, GuardianConfigBackup, PeerConnectionStatus, PeerStatus, ServerStatus,
-    StatusResponse,
+    AuditHistoryRequest, DecodedModuleItem, FederationStatus, GuardianConfigBackup,
+    PeerConnectionStatus, PeerStatus, PendingTransactionsResponse, ServerStatus, SessionItem,
+    SessionItemDetails, StatusResponse, TransactionLocation, TransactionRejectionStage,
+    MAX_AUDIT_HISTORY_SESSIONS,
 };
 use fedimint_core::backup::{ClientBackupKey, ClientBackupSnapshot};
 use fedimint_core::config::{ClientConfig, JsonWithKind};
-use fedimint_core::core::backup::{SignedBackupRequest, BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES};
+use fedimint_core::core::backup::SignedBackupRequest;
 use fedimint_core::core::{DynOutputOutcome, ModuleInstanceId};
 use fedimint_core::db::{
     Committable, Database, DatabaseTransaction, IDatabaseTransactionOpsCoreTyped,
 };
 use fedimint_core::endpoint_constants::{
-    AUDIT_ENDPOINT, AUTH_ENDPOINT, AWAIT_OUTPUT_OUTCOME_ENDPOINT, AWAIT_SESSION_OUTCOME_ENDPOINT,
-    AWAIT_SIGNED_SESSION_OUTCOME_ENDPOINT, AWAIT_TRANSACTION_ENDPOINT, BACKUP_ENDPOINT,
-    CLIENT_CONFIG_ENDPOINT, GUARDIAN_CONFIG_BACKUP_ENDPOINT, INVITE_CODE_ENDPOINT,
-    MODULES_CONFIG_JSON_ENDPOINT, RECOVER_ENDPOINT, SERVER_CONFIG_CONSENSUS_HASH_ENDPOINT,
-    SESSION_COUNT_ENDPOINT, SESSION_STATUS_ENDPOINT, STATUS_ENDPOINT, SUBMIT_TRANSACTION_ENDPOINT,
+    AUDIT_ENDPOINT, AUDIT_HISTORY_ENDPOINT, AUTH_ENDPOINT, AWAIT_OUTPUT_OUTCOME_ENDPOINT,
+    AWAIT_SESSION_OUTCOME_ENDPOINT, AWAIT_SIGNED_SESSION_OUTCOME_ENDPOINT,
+    AWAIT_TRANSACTION_ENDPOINT, BACKUP_ENDPOINT, BROADCAST_PUBLIC_KEYS_ENDPOINT,
+    CHANGE_PASSWORD_ENDPOINT, CLIENT_CONFIG_ENDPOINT, FIND_TRANSACTION_ENDPOINT,
+    GUARDIAN_CONFIG_BACKUP_ENDPOINT, INVITE_CODE_ENDPOINT, LIABILITIES_STATEMENT_ENDPOINT,
+    MODULES_CONFIG_JSON_ENDPOINT, PENDING_TRANSACTIONS_ENDPOINT, RECOVER_ENDPOINT,
+    SERVER_CONFIG_CONSENSUS_HASH_ENDPOINT, SESSION_COUNT_ENDPOINT, SESSION_ITEMS_ENDPOINT,
+    SESSION_STATUS_ENDPOINT, STATUS_ENDPOINT, SUBMIT_TRANSACTION_ENDPOINT,
     VERIFY_CONFIG_HASH_ENDPOINT, VERSION_ENDPOINT,
 };
 use fedimint_core::epoch::ConsensusItem;
-use fedimint_core::module::audit::{Audit, AuditSummary};
+use fedimint_core::module::audit::{Audit, AuditSummary, SessionAuditSummary};
+use fedimint_core::module::liabilities::SignedLiabilitiesStatement;
 use fedimint_core::module::registry::ServerModuleRegistry;
 use fedimint_core::module::{
-    api_endpoint, ApiEndpoint, ApiEndpointContext, ApiError, ApiRequestErased, ApiVersion,
+    api_endpoint, ApiAuth, ApiEndpoint, ApiEndpointContext, ApiError, ApiRequestErased, ApiVersion,
     SerdeModuleEncoding, SupportedApiVersionsSummary,
 };
 use fedimint_core::server::DynServerModule;
@@ -43,18 +49,24 @@ use fedimint_core::{OutPoint, PeerId, TransactionId};
 use fedimint_logging::LOG_NET_API;
 use futures::StreamExt;
 use jsonrpsee::RpcModule;
-use secp256k1_zkp::SECP256K1;
+use secp256k1_zkp::{PublicKey, SECP256K1};
 use tokio::sync::RwLock;
 use tracing::{debug, info};
 
 use super::peers::PeerStatusChannels;
+use super::rate_limit::{remote_ip, ApiLimiter};
 use crate::config::io::{
-    CONSENSUS_CONFIG, ENCRYPTED_EXT, JSON_EXT, LOCAL_CONFIG, PRIVATE_CONFIG, SALT_FILE,
+    reencrypt_private_config, CONSENSUS_CONFIG, ENCRYPTED_EXT, JSON_EXT, LOCAL_CONFIG,
+    PRIVATE_CONFIG, SALT_FILE,
 };
-use crate::config::ServerConfig;
+use crate::config::{ServerConfig, ServerConfigPrivate};
 use crate::consensus::process_transaction_with_dbtx;
 use crate::consensus::server::{get_finished_session_count_static, LatestContributionByPeer};
-use crate::db::{AcceptedItemPrefix, AcceptedTransactionKey, SignedSessionOutcomeKey};
+use crate::consensus::transaction_pool::TransactionPool;
+use crate::db::{
+    AcceptedItemPrefix, AcceptedTransactionKey, LiabilitiesStatementKey, SessionAuditSummaryKey,
+    SignedSessionOutcomeKey, TransactionLocationKey,
+};
 use crate::fedimint_core::encoding::Encodable;
 use crate::{check_auth, get_verification_hashes, ApiResult, HasApiContext};
 
@@ -92,8 +104,17 @@ pub struct ConsensusApi {
     pub submission_sender: async_channel::Sender<ConsensusItem>,
     pub peer_status_channels: PeerStatusChannels,
     pub latest_contribution_by_peer: Arc<RwLock<LatestContributionByPeer>>,
+    /// Transactions submitted to us that are not accepted yet and recently
+    /// rejected transactions
+    pub transaction_pool: Arc<RwLock<TransactionPool>>,
     pub consensus_status_cache: ExpiringCache<ApiResult<FederationStatus>>,
     pub supported_api_versions: SupportedApiVersionsSummary,
+    /// Protects the API against clients exhausting our resources
+    pub api_limiter: ApiLimiter,
+    /// Password guardians authenticate with, which can change at runtime
+    pub api_auth: Arc<RwLock<ApiAuth>>,
+    /// Location of the config files, if they are stored on disk
+    pub data_dir: Option<PathBuf>,
 }
 
 impl ConsensusApi {
@@ -129,7 +150,23 @@ impl ConsensusApi {
         // We ignore any writes, as we only verify if the transaction is valid here
         dbtx.ignore_uncommitted();
 
-        process_transaction_with_dbtx(self.modules.clone(), &mut dbtx, transaction.clone()).await?;
+        if let Err(error) =
+            process_transaction_with_dbtx(self.modules.clone(), &mut dbtx, transaction.clone())
+                .await
+        {
+            self.transaction_pool.write().await.reject(
+                txid,
+                TransactionRejectionStage::Submission,
+                &error,
+            );
+
+            return Err(error);
+        }
+
+        self.transaction_pool
+            .write()
+            .await
+            .insert_pending(txid, transaction.consensus_encode_to_vec().len());
 
         self.submission_sender
             .send(ConsensusItem::Transaction(transaction))
@@ -205,6 +242,100 @@ impl ConsensusApi {
         }
     }
 
+    /// Lists the accepted items of a completed or the currently running session
+    pub async fn session_items(&self, session_index: u64) -> ApiResult<Vec<SessionItem>> {
+        let items = match self.session_status(session_index).await {
+            SessionStatus::Initial => {
+                return Err(ApiError::bad_request(format!(
+                    "Session {session_index} has not started yet"
+                )))
+            }
+            SessionStatus::Pending(items) => items,
+            SessionStatus::Complete(session_outcome) => session_outcome.items,
+        };
+
+        Ok(items
+            .into_iter()
+            .zip(0u64..)
+            .map(|(accepted_item, index)| SessionItem {
+                index,
+                peer: accepted_item.peer,
+                item: self.decode_consensus_item(accepted_item.item),
+            })
+            .collect())
+    }
+
+    fn decode_consensus_item(&self, item: ConsensusItem) -> SessionItemDetails {
+        match item {
+            ConsensusItem::Transaction(transaction) => SessionItemDetails::Transaction {
+                txid: transaction.tx_hash(),
+                nonce: transaction.nonce.consensus_encode_to_hex(),
+                inputs: transaction
+                    .inputs
+                    .iter()
+                    .map(|input| {
+                        let module_instance_id = input.module_instance_id();
+                        self.decoded_module_item(
+                            module_instance_id,
+                            self.modules
+                                .get(module_instance_id)
+                                .and_then(|module| module.input_to_json(input)),
+                            input.to_string(),
+                        )
+                    })
+                    .collect(),
+                outputs: transaction
+                    .outputs
+                    .iter()
+                    .map(|output| {
+                        let module_instance_id = output.module_instance_id();
+                        self.decoded_module_item(
+                            module_instance_id,
+                            self.modules
+                                .get(module_instance_id)
+                                .and_then(|module| module.output_to_json(output)),
+                            output.to_string(),
+                        )
+                    })
+                    .collect(),
+            },
+            ConsensusItem::Module(module_item) => {
+                SessionItemDetails::Module(self.decoded_module_item(
+                    module_item.module_instance_id(),
+                    None,
+                    module_item.to_string(),
+                ))
+            }
+            ConsensusItem::Default { variant, .. } => SessionItemDetails::Unknown { variant },
+        }
+    }
+
+    fn decoded_module_item(
+        &self,
+        module_instance_id: ModuleInstanceId,
+        json: Option<serde_json::Value>,
+        display: String,
+    ) -> DecodedModuleItem {
+        DecodedModuleItem {
+            module_instance_id,
+            kind: self
+                .modules
+                .get_with_kind(module_instance_id)
+                .map(|(kind, _)| kind.to_string()),
+            json,
+            display,
+        }
+    }
+
+    /// Finds the session and position of an accepted transaction
+    pub async fn find_transaction(&self, txid: TransactionId) -> Option<TransactionLocation> {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .get_value(&TransactionLocationKey(txid))
+            .await
+    }
+
     pub async fn get_federation_status(&self) -> ApiResult<FederationStatus> {
         let peers_connection_status = self.peer_status_channels.get_all_status().await;
         let latest_contribution_by_peer = self.latest_contribution_by_peer.read().await.clone();
@@ -259,7 +390,7 @@ impl ConsensusApi {
         })
     }
 
-    async fn get_federation_audit(&self) -> ApiResult<AuditSummary> {
+    pub async fn get_federation_audit(&self) -> ApiResult<AuditSummary> {
         let mut dbtx = self.db.begin_transaction_nc().await;
         // Writes are related to compacting audit keys, which we can safely ignore
         // within an API request since the compaction will happen when constructing an
@@ -284,6 +415,62 @@ impl ConsensusApi {
         ))
     }
 
+    pub async fn get_audit_history(
+        &self,
+        request: AuditHistoryRequest,
+    ) -> ApiResult<Vec<SessionAuditSummary>> {
+        let AuditHistoryRequest {
+            start_session,
+            end_session,
+        } = request;
+
+        if end_session.saturating_sub(start_session) > MAX_AUDIT_HISTORY_SESSIONS {
+            return Err(ApiError::bad_request(format!(
+                "Cannot request more than {MAX_AUDIT_HISTORY_SESSIONS} sessions at once"
+            )));
+        }
+
+        let mut dbtx = self.db.begin_transaction_nc().await;
+
+        // Sessions completed before audit summaries were persisted are skipped
+        let mut history = vec![];
+        for session_index in start_session..end_session {
+            if let Some(summary) = dbtx.get_value(&SessionAuditSummaryKey(session_index)).await {
+                history.push(summary);
+            }
+        }
+
+        Ok(history)
+    }
+
+    /// Our signature over the liabilities statement we produced at the end of
+    /// the given session
+    pub async fn get_liabilities_statement(
+        &self,
+        session_index: u64,
+    ) -> Option<SignedLiabilitiesStatement> {
+        let statement = self
+            .db
+            .begin_transaction_nc()
+            .await
+            .get_value(&LiabilitiesStatementKey(session_index))
+            .await?;
+
+        let signature = SECP256K1.sign_schnorr(
+            &statement.signing_message(),
+            &self.cfg.private.broadcast_secret_key.keypair(SECP256K1),
+        );
+
+        Some(SignedLiabilitiesStatement {
+            statement,
+            signatures: BTreeMap::from([(self.cfg.local.identity, signature)]),
+        })
+    }
+
+    async fn get_pending_transactions(&self) -> PendingTransactionsResponse {
+        self.transaction_pool.read().await.to_response()
+    }
+
     /// Uses the in-memory config to write a config backup tar archive that
     /// guardians can download. Private keys are encrypted with the guardian
     /// password, so it should be safe to store anywhere, this also means the
@@ -323,8 +510,8 @@ impl ConsensusApi {
         let encryption_salt = random_salt();
         append(&PathBuf::from(SALT_FILE), encryption_salt.as_bytes());
 
-        let private_config_bytes =
-            serde_json::to_vec(&self.cfg.private).expect("Error encoding private config");
+        let private_config_bytes = serde_json::to_vec(&self.private_config().await)
+            .expect("Error encoding private config");
         let encryption_key = get_encryption_key(&password, &encryption_salt)
             .expect("Generating key from password failed");
         let private_config_encrypted =
@@ -341,6 +528,52 @@ impl ConsensusApi {
         Ok(GuardianConfigBackup { tar_archive_bytes })
     }
 
+    /// Our private config including the current password
+    async fn private_config(&self) -> ServerConfigPrivate {
+        ServerConfigPrivate {
+            api_auth: self.api_auth.read().await.clone(),
+            ..self.cfg.private.clone()
+        }
+    }
+
+    /// Changes the guardian password, re-encrypting the private config on disk
+    /// before the new password is accepted by the API
+    async fn change_password(&self, new_auth: ApiAuth) -> ApiResult<()> {
+        if new_auth.0.is_empty() {
+            return Err(ApiError::bad_request("Password must not be empty".into()));
+        }
+
+        // Holding the lock for the whole operation serializes concurrent changes
+        let mut api_auth = self.api_auth.write().await;
+
+        if *api_auth == new_auth {
+            return Err(ApiError::bad_request(
+                "New password is identical to the current one".into(),
+            ));
+        }
+
+        // Without the config on disk the old password would be required again after a
+        // restart, so we refuse to change it
+        let data_dir = self.data_dir.as_ref().ok_or_else(|| {
+            ApiError::server_error("Config is not stored on disk, cannot change password".into())
+        })?;
+
+        let private = ServerConfigPrivate {
+            api_auth: new_auth.clone(),
+            ..self.cfg.private.clone()
+        };
+
+        reencrypt_private_config(&private, data_dir, &new_auth.0).map_err(|e| {
+            ApiError::server_error(format!("Failed to re-encrypt private config: {e:#}"))
+        })?;
+
+        *api_auth = new_auth;
+
+        info!(target: LOG_NET_API, "Guardian password was changed");
+
+        Ok(())
+    }
+
     async fn handle_backup_request<'s, 'dbtx, 'a>(
         &'s self,
         dbtx: &'dbtx mut DatabaseTransaction<'a>,
@@ -350,9 +583,9 @@ impl ConsensusApi {
             .verify_valid(SECP256K1)
             .map_err(|_| ApiError::bad_request("invalid request".into()))?;
 
-        if request.payload.len() > BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES {
-            return Err(ApiError::bad_request("snapshot too large".into()));
-        }
+        self.api_limiter
+            .check_backup(remote_ip(), request.id, request.payload.len())?;
+
         debug!(target: LOG_NET_API, id = %request.id, len = request.payload.len(), "Received client backup request");
         if let Some(prev) = dbtx.get_value(&ClientBackupKey(request.id)).await {
             if request.timestamp <= prev.timestamp {
@@ -371,6 +604,8 @@ impl ConsensusApi {
         )
         .await;
 
+        self.api_limiter.record_backup(request.id);
+
         Ok(())
     }
 
@@ -396,14 +631,10 @@ impl HasApiContext<ConsensusApi> for ConsensusApi {
             db = self.db.with_prefix_module_id(id);
             dbtx = dbtx.with_prefix_module_id(id)
         }
+        let authenticated = request.auth.as_ref() == Some(&*self.api_auth.read().await);
         (
             self,
-            ApiEndpointContext::new(
-                db,
-                dbtx,
-                request.auth == Some(self.cfg.private.api_auth.clone()),
-                request.auth.clone(),
-            ),
+            ApiEndpointContext::new(db, dbtx, authenticated, request.auth.clone()),
         )
     }
 }
@@ -436,6 +667,8 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
             SUBMIT_TRANSACTION_ENDPOINT,
             ApiVersion::new(0, 0),
             async |fedimint: &ConsensusApi, _context, transaction: SerdeTransaction| -> SerdeModuleEncoding<Result<TransactionId, TransactionError>> {
+                fedimint.api_limiter.check_submit_transaction(remote_ip())?;
+
                 let transaction = transaction
                     .try_into_inner(&fedimint.modules.decoder_registry())
                     .map_err(|e| ApiError::bad_request(e.to_string()))?;
@@ -491,6 +724,20 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
                 Ok(fedimint.cfg.consensus.consensus_hash())
             }
         },
+        api_endpoint! {
+            BROADCAST_PUBLIC_KEYS_ENDPOINT,
+            ApiVersion::new(0, 6),
+            async |fedimint: &ConsensusApi, _context, _v: ()| -> BTreeMap<PeerId, PublicKey> {
+                Ok(fedimint.cfg.consensus.broadcast_public_keys.clone())
+            }
+        },
+        api_endpoint! {
+            LIABILITIES_STATEMENT_ENDPOINT,
+            ApiVersion::new(0, 6),
+            async |fedimint: &ConsensusApi, _context, session_index: u64| -> Option<SignedLiabilitiesStatement> {
+                Ok(fedimint.get_liabilities_statement(session_index).await)
+            }
+        },
         api_endpoint! {
             STATUS_ENDPOINT,
             ApiVersion::new(0, 0),
@@ -523,6 +770,7 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
             AWAIT_SIGNED_SESSION_OUTCOME_ENDPOINT,
             ApiVersion::new(0, 0),
             async |fedimint: &ConsensusApi, _context, index: u64| -> SerdeModuleEncoding<SignedSessionOutcome> {
+                // Not limited since our peers rely on this endpoint to catch up with consensus
                 Ok((&fedimint.await_signed_session_outcome(index).await).into())
             }
         },
@@ -541,6 +789,38 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
                 Ok(fedimint.get_federation_audit().await?)
             }
         },
+        api_endpoint! {
+            PENDING_TRANSACTIONS_ENDPOINT,
+            ApiVersion::new(0, 3),
+            async |fedimint: &ConsensusApi, context, _v: ()| -> PendingTransactionsResponse {
+                check_auth(context)?;
+                Ok(fedimint.get_pending_transactions().await)
+            }
+        },
+        api_endpoint! {
+            AUDIT_HISTORY_ENDPOINT,
+            ApiVersion::new(0, 5),
+            async |fedimint: &ConsensusApi, context, request: AuditHistoryRequest| -> Vec<SessionAuditSummary> {
+                check_auth(context)?;
+                fedimint.get_audit_history(request).await
+            }
+        },
+        api_endpoint! {
+            SESSION_ITEMS_ENDPOINT,
+            ApiVersion::new(0, 4),
+            async |fedimint: &ConsensusApi, context, index: u64| -> Vec<SessionItem> {
+                check_auth(context)?;
+                fedimint.session_items(index).await
+            }
+        },
+        api_endpoint! {
+            FIND_TRANSACTION_ENDPOINT,
+            ApiVersion::new(0, 4),
+            async |fedimint: &ConsensusApi, context, txid: TransactionId| -> Option<TransactionLocation> {
+                check_auth(context)?;
+                Ok(fedimint.find_transaction(txid).await)
+            }
+        },
         api_endpoint! {
             GUARDIAN_CONFIG_BACKUP_ENDPOINT,
             ApiVersion::new(0, 2),
@@ -550,6 +830,14 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
                 Ok(fedimint.get_guardian_config_backup(password).await?)
             }
         },
+        api_endpoint! {
+            CHANGE_PASSWORD_ENDPOINT,
+            ApiVersion::new(0, 7),
+            async |fedimint: &ConsensusApi, context, new_auth: ApiAuth| -> () {
+                check_auth(context)?;
+                fedimint.change_password(new_auth).await
+            }
+        },
         api_endpoint! {
             VERIFY_CONFIG_HASH_ENDPOINT,
             ApiVersion::new(0, 0),
@@ -4,3 +4,4 @@ pub mod framed;
 pub mod peers;
 pub mod peers_reliable;
 pub mod queue;
+pub mod rate_limit;
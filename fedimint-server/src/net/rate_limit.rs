@@ -0,0 +1,704 @@
+//! Limits on how much work clients can make the API do
+//!
+//! Requests and concurrent `await_*` long-polls are limited per connection,
+//! long-polls and costly requests additionally per remote IP address and
+//! backups per backup id. The jsonrpsee version we use does not pass the
+//! connection to method handlers, so [`RemoteAddrLogger`] is installed as the
+//! server's logger middleware: jsonrpsee clones it once for every accepted
+//! connection, which lets it keep an [`ApiConnection`] with the address and
+//! limits of that connection and publish it right before a method handler is
+//! invoked. The handler wrapper picks it up with [`take_call_connection`],
+//! applies [`ApiLimiter::check_call`] and makes it available to the endpoint
+//! via [`scope_connection`] and [`remote_ip`].
+//!
+//! The remote address is the address of the socket unless
+//! [`ApiLimits::trust_forwarded_for`] is set, behind a reverse proxy all
+//! clients would otherwise share the limits of the proxy's address.
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use fedimint_core::core::backup::BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES;
+use fedimint_core::module::ApiError;
+use fedimint_metrics::{lazy_static, opts, register_int_counter_vec, IntCounterVec};
+use jsonrpsee::server::logger::{HttpRequest, Logger, MethodKind, Params, TransportProtocol};
+use secp256k1_zkp::PublicKey;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::envs::{
+    FM_API_BACKUP_MIN_INTERVAL_SECS_ENV, FM_API_BACKUP_RATE_ENV,
+    FM_API_CONNECTION_REQUEST_RATE_ENV, FM_API_MAX_BACKUP_SIZE_ENV,
+    FM_API_MAX_CONCURRENT_AWAITS_ENV, FM_API_MAX_CONNECTION_AWAITS_ENV,
+    FM_API_SUBMIT_TRANSACTION_RATE_ENV, FM_API_TRUST_FORWARDED_FOR_ENV,
+};
+
+/// How many addresses we keep limits for before we start evicting idle ones,
+/// requests from new addresses share a single entry while all are busy
+const MAX_TRACKED_ADDRS: usize = 10_000;
+
+/// Key used for requests whose remote address is unknown, and for new
+/// addresses once [`MAX_TRACKED_ADDRS`] busy addresses are tracked
+const UNKNOWN_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+/// Prefix of the long-polling endpoints, in the core API as well as in modules
+const AWAIT_ENDPOINT_PREFIX: &str = "await_";
+
+/// Header in which reverse proxies pass on the address of the client
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+thread_local! {
+    /// Connection of the method call that is about to be invoked on this
+    /// thread, see [`RemoteAddrLogger::on_call`]
+    static CALL_CONNECTION: Cell<Option<Arc<ApiConnection>>> = const { Cell::new(None) };
+}
+
+tokio::task_local! {
+    static CONNECTION: Option<Arc<ApiConnection>>;
+}
+
+lazy_static! {
+    static ref API_REQUESTS_REJECTED: IntCounterVec = register_int_counter_vec!(
+        opts!(
+            "api_requests_rejected_total",
+            "Number of API requests rejected due to rate limits"
+        ),
+        &["limit"]
+    )
+    .unwrap();
+}
+
+/// Limits applied to the client facing consensus API
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApiLimits {
+    /// Transactions per second accepted by `submit_transaction` from a single
+    /// address
+    pub submit_transaction_rate: u32,
+    /// How many `await_*` long-polls a single address can have open at the
+    /// same time
+    pub max_concurrent_awaits: u32,
+    /// Backups per second accepted by `backup` from a single address
+    pub backup_rate: u32,
+    /// Maximum size of a single backup payload in bytes
+    pub max_backup_size: usize,
+    /// Minimum time between two backups with the same id
+    pub backup_min_interval: Duration,
+    /// Requests per second accepted from a single connection
+    pub connection_request_rate: u32,
+    /// How many `await_*` long-polls a single connection can have open at the
+    /// same time
+    pub max_connection_awaits: u32,
+    /// Whether the per-address limits use the last address of the
+    /// `X-Forwarded-For` header instead of the address of the socket. Only
+    /// enable this if the API is exclusively reachable through a reverse proxy
+    /// that appends the client's address to the header, otherwise clients can
+    /// choose the address they are limited as.
+    ///
+    /// The address is read when the connection is accepted, which relies on
+    /// jsonrpsee's logger middleware (see the module docs) and has to be
+    /// revisited when upgrading jsonrpsee.
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for ApiLimits {
+    fn default() -> Self {
+        Self {
+            submit_transaction_rate: 100,
+            max_concurrent_awaits: 1_000,
+            backup_rate: 20,
+            max_backup_size: BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES,
+            backup_min_interval: Duration::from_secs(10),
+            connection_request_rate: 500,
+            max_connection_awaits: 200,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+impl ApiLimits {
+    /// Reads the limits from the `FM_API_*` env vars, using the defaults for
+    /// unset or invalid values
+    pub fn from_env() -> Self {
+        fn read<T: FromStr>(var: &str, default: T) -> T {
+            env::var(var)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(default)
+        }
+
+        let default = Self::default();
+
+        Self {
+            submit_transaction_rate: read(
+                FM_API_SUBMIT_TRANSACTION_RATE_ENV,
+                default.submit_transaction_rate,
+            ),
+            max_concurrent_awaits: read(
+                FM_API_MAX_CONCURRENT_AWAITS_ENV,
+                default.max_concurrent_awaits,
+            ),
+            backup_rate: read(FM_API_BACKUP_RATE_ENV, default.backup_rate),
+            // clients will never send anything larger, so there is no point in allowing it
+            max_backup_size: read(FM_API_MAX_BACKUP_SIZE_ENV, default.max_backup_size)
+                .min(BACKUP_REQUEST_MAX_PAYLOAD_SIZE_BYTES),
+            backup_min_interval: Duration::from_secs(read(
+                FM_API_BACKUP_MIN_INTERVAL_SECS_ENV,
+                default.backup_min_interval.as_secs(),
+            )),
+            connection_request_rate: read(
+                FM_API_CONNECTION_REQUEST_RATE_ENV,
+                default.connection_request_rate,
+            ),
+            max_connection_awaits: read(
+                FM_API_MAX_CONNECTION_AWAITS_ENV,
+                default.max_connection_awaits,
+            ),
+            trust_forwarded_for: read(FM_API_TRUST_FORWARDED_FOR_ENV, default.trust_forwarded_for),
+        }
+    }
+}
+
+/// Enforces the [`ApiLimits`] for an API server
+#[derive(Debug, Clone)]
+pub struct ApiLimiter {
+    limits: ApiLimits,
+    by_addr: Arc<Mutex<HashMap<IpAddr, AddrLimits>>>,
+    last_backup_by_id: Arc<Mutex<HashMap<PublicKey, Instant>>>,
+}
+
+impl ApiLimiter {
+    pub fn new(limits: ApiLimits) -> Self {
+        Self {
+            limits,
+            by_addr: Default::default(),
+            last_backup_by_id: Default::default(),
+        }
+    }
+
+    /// Logger middleware that creates the [`ApiConnection`]s of the server,
+    /// see [`RemoteAddrLogger`]
+    pub fn logger(&self) -> RemoteAddrLogger {
+        RemoteAddrLogger {
+            limits: self.limits,
+            connection: None,
+        }
+    }
+
+    /// Checks the limits that apply to every call of the endpoint at `path`
+    /// and returns the permits that have to be held while it is handled
+    pub fn check_call(
+        &self,
+        connection: Option<&ApiConnection>,
+        path: &str,
+    ) -> Result<Vec<OwnedSemaphorePermit>, ApiError> {
+        let mut permits = vec![];
+
+        if let Some(connection) = connection {
+            if !connection.requests.lock().expect("poisoned").try_take() {
+                return Err(reject("connection_request_rate"));
+            }
+
+            if path.starts_with(AWAIT_ENDPOINT_PREFIX) {
+                permits.push(
+                    connection
+                        .awaits
+                        .clone()
+                        .try_acquire_owned()
+                        .map_err(|_| reject("max_connection_awaits"))?,
+                );
+            }
+        }
+
+        if path.starts_with(AWAIT_ENDPOINT_PREFIX) {
+            let addr = connection
+                .and_then(ApiConnection::remote_ip)
+                .unwrap_or(UNKNOWN_ADDR);
+
+            permits.push(self.acquire_await(addr)?);
+        }
+
+        Ok(permits)
+    }
+
+    pub fn check_submit_transaction(&self, addr: IpAddr) -> Result<(), ApiError> {
+        if !self.with_addr(addr, |limits| limits.submit_transaction.try_take()) {
+            return Err(reject("submit_transaction_rate"));
+        }
+
+        Ok(())
+    }
+
+    /// Returns a permit that has to be held for the duration of an `await_*`
+    /// request
+    fn acquire_await(&self, addr: IpAddr) -> Result<OwnedSemaphorePermit, ApiError> {
+        self.with_addr(addr, |limits| limits.awaits.clone().try_acquire_owned())
+            .map_err(|_| reject("max_concurrent_awaits"))
+    }
+
+    /// Checks the size of the backup, the rate of backups from the address
+    /// and how recently a backup with the same id was stored. Call
+    /// [`Self::record_backup`] once the backup was stored.
+    pub fn check_backup(&self, addr: IpAddr, id: PublicKey, len: usize) -> Result<(), ApiError> {
+        if len > self.limits.max_backup_size {
+            API_REQUESTS_REJECTED
+                .with_label_values(&["max_backup_size"])
+                .inc();
+            return Err(ApiError::bad_request("snapshot too large".into()));
+        }
+
+        {
+            let mut last_backup_by_id = self.last_backup_by_id.lock().expect("poisoned");
+            let now = Instant::now();
+
+            // Entries older than the interval cannot cause a rejection anymore, we remove
+            // them so the map does not grow with the number of clients
+            last_backup_by_id
+                .retain(|_, last| now.duration_since(*last) < self.limits.backup_min_interval);
+
+            if last_backup_by_id.contains_key(&id) {
+                return Err(reject("backup_min_interval"));
+            }
+        }
+
+        if !self.with_addr(addr, |limits| limits.backup.try_take()) {
+            return Err(reject("backup_rate"));
+        }
+
+        Ok(())
+    }
+
+    /// Starts the minimum interval until the next backup with this id is
+    /// accepted
+    pub fn record_backup(&self, id: PublicKey) {
+        self.last_backup_by_id
+            .lock()
+            .expect("poisoned")
+            .insert(id, Instant::now());
+    }
+
+    fn with_addr<R>(&self, addr: IpAddr, f: impl FnOnce(&mut AddrLimits) -> R) -> R {
+        let mut by_addr = self.by_addr.lock().expect("poisoned");
+
+        let mut addr = addr;
+
+        if !by_addr.contains_key(&addr) && MAX_TRACKED_ADDRS <= by_addr.len() {
+            let now = Instant::now();
+            by_addr.retain(|_, limits| !limits.is_idle(&self.limits, now));
+
+            if MAX_TRACKED_ADDRS <= by_addr.len() {
+                addr = UNKNOWN_ADDR;
+            }
+        }
+
+        f(by_addr
+            .entry(addr)
+            .or_insert_with(|| AddrLimits::new(&self.limits)))
+    }
+}
+
+/// The limits of a single remote address
+#[derive(Debug)]
+struct AddrLimits {
+    submit_transaction: TokenBucket,
+    backup: TokenBucket,
+    awaits: Arc<Semaphore>,
+}
+
+impl AddrLimits {
+    fn new(limits: &ApiLimits) -> Self {
+        Self {
+            submit_transaction: TokenBucket::new(limits.submit_transaction_rate),
+            backup: TokenBucket::new(limits.backup_rate),
+            awaits: Arc::new(Semaphore::new(limits.max_concurrent_awaits as usize)),
+        }
+    }
+
+    /// Whether forgetting these limits would not let the address do more work
+    fn is_idle(&self, limits: &ApiLimits, now: Instant) -> bool {
+        self.submit_transaction.is_full(now)
+            && self.backup.is_full(now)
+            && self.awaits.available_permits() == limits.max_concurrent_awaits as usize
+    }
+}
+
+/// Remote address and limits of a single API connection
+#[derive(Debug)]
+pub struct ApiConnection {
+    remote_ip: Mutex<Option<IpAddr>>,
+    requests: Mutex<TokenBucket>,
+    awaits: Arc<Semaphore>,
+}
+
+impl ApiConnection {
+    fn new(limits: &ApiLimits) -> Self {
+        Self {
+            remote_ip: Mutex::new(None),
+            requests: Mutex::new(TokenBucket::new(limits.connection_request_rate)),
+            awaits: Arc::new(Semaphore::new(limits.max_connection_awaits as usize)),
+        }
+    }
+
+    pub fn remote_ip(&self) -> Option<IpAddr> {
+        *self.remote_ip.lock().expect("poisoned")
+    }
+}
+
+/// Remote IP address of the API request currently being handled, requests
+/// with an unknown address share the limits of the unspecified address
+pub fn remote_ip() -> IpAddr {
+    CONNECTION
+        .try_with(|connection| connection.as_deref().and_then(ApiConnection::remote_ip))
+        .ok()
+        .flatten()
+        .unwrap_or(UNKNOWN_ADDR)
+}
+
+/// Makes `connection` the connection of the request while `future` runs
+pub async fn scope_connection<F: Future>(
+    connection: Option<Arc<ApiConnection>>,
+    future: F,
+) -> F::Output {
+    CONNECTION.scope(connection, future).await
+}
+
+/// Returns the connection published by [`RemoteAddrLogger`] for the method
+/// call being invoked. Has to be called synchronously from the method callback
+/// registered with jsonrpsee, before the first `.await`.
+pub fn take_call_connection() -> Option<Arc<ApiConnection>> {
+    CALL_CONNECTION.with(Cell::take)
+}
+
+/// Logger middleware that tracks every connection, created with
+/// [`ApiLimiter::logger`]
+///
+/// jsonrpsee clones the logger passed to the server once per accepted
+/// connection and uses clones of that copy for the lifetime of the connection.
+/// Clones of the logger given to the server get a new [`ApiConnection`] while
+/// clones of a connection's copy share it.
+#[derive(Debug)]
+pub struct RemoteAddrLogger {
+    limits: ApiLimits,
+    connection: Option<Arc<ApiConnection>>,
+}
+
+impl Clone for RemoteAddrLogger {
+    fn clone(&self) -> Self {
+        let connection = self
+            .connection
+            .clone()
+            .unwrap_or_else(|| Arc::new(ApiConnection::new(&self.limits)));
+
+        Self {
+            limits: self.limits,
+            connection: Some(connection),
+        }
+    }
+}
+
+impl Logger for RemoteAddrLogger {
+    type Instant = ();
+
+    fn on_connect(&self, remote_addr: SocketAddr, request: &HttpRequest, _t: TransportProtocol) {
+        if let Some(connection) = &self.connection {
+            let forwarded_for = self
+                .limits
+                .trust_forwarded_for
+                .then(|| forwarded_for(request))
+                .flatten();
+
+            *connection.remote_ip.lock().expect("poisoned") =
+                Some(forwarded_for.unwrap_or(remote_addr.ip()));
+        }
+    }
+
+    fn on_request(&self, _transport: TransportProtocol) -> Self::Instant {}
+
+    fn on_call(
+        &self,
+        _method_name: &str,
+        _params: Params,
+        _kind: MethodKind,
+        _transport: TransportProtocol,
+    ) {
+        // jsonrpsee invokes the method callback right after this without yielding,
+        // so the callback runs on the same thread and can take the connection
+        CALL_CONNECTION.with(|call_connection| call_connection.set(self.connection.clone()));
+    }
+
+    fn on_result(
+        &self,
+        _method_name: &str,
+        _success: bool,
+        _started_at: Self::Instant,
+        _transport: TransportProtocol,
+    ) {
+    }
+
+    fn on_response(
+        &self,
+        _result: &str,
+        _started_at: Self::Instant,
+        _transport: TransportProtocol,
+    ) {
+    }
+
+    fn on_disconnect(&self, _remote_addr: SocketAddr, _transport: TransportProtocol) {}
+}
+
+/// The address appended to the `X-Forwarded-For` header by the proxy in front
+/// of us, addresses before it were sent by the client and cannot be trusted
+fn forwarded_for(request: &HttpRequest) -> Option<IpAddr> {
+    request
+        .headers()
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .next_back()?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn reject(limit: &str) -> ApiError {
+    API_REQUESTS_REJECTED.with_label_values(&[limit]).inc();
+    ApiError::too_many_requests(format!("Rate limit exceeded: {limit}"))
+}
+
+/// Allows `rate` requests per second with bursts of up to `rate` requests
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+
+        self.rate <= self.tokens + elapsed * self.rate
+    }
+
+    fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last_refill = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    use jsonrpsee::server::logger::{HttpRequest, Logger, MethodKind, Params, TransportProtocol};
+    use secp256k1_zkp::{PublicKey, SecretKey, SECP256K1};
+
+    use super::{take_call_connection, ApiLimiter, ApiLimits, TokenBucket};
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(3);
+
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+
+        std::thread::sleep(Duration::from_millis(500));
+
+        assert!(bucket.try_take());
+    }
+
+    #[test]
+    fn test_api_limiter() {
+        let limiter = ApiLimiter::new(ApiLimits {
+            submit_transaction_rate: 1,
+            max_concurrent_awaits: 1,
+            backup_rate: 2,
+            max_backup_size: 10,
+            backup_min_interval: Duration::from_secs(60),
+            ..ApiLimits::default()
+        });
+
+        let addr = |byte| IpAddr::V4(Ipv4Addr::new(10, 0, 0, byte));
+
+        assert!(limiter.check_submit_transaction(addr(1)).is_ok());
+        assert_eq!(
+            limiter.check_submit_transaction(addr(1)).unwrap_err().code,
+            429
+        );
+        assert!(limiter.check_submit_transaction(addr(2)).is_ok());
+
+        let permit = limiter.acquire_await(addr(1)).unwrap();
+        assert!(limiter.acquire_await(addr(1)).is_err());
+        assert!(limiter.acquire_await(addr(2)).is_ok());
+        drop(permit);
+        assert!(limiter.acquire_await(addr(1)).is_ok());
+
+        let id = |byte| {
+            PublicKey::from_secret_key(SECP256K1, &SecretKey::from_slice(&[byte; 32]).unwrap())
+        };
+
+        assert_eq!(
+            limiter.check_backup(addr(1), id(1), 11).unwrap_err().code,
+            400
+        );
+        // a backup that was not stored does not count against its id
+        assert!(limiter.check_backup(addr(1), id(1), 10).is_ok());
+        assert!(limiter.check_backup(addr(2), id(1), 10).is_ok());
+        limiter.record_backup(id(1));
+        assert_eq!(
+            limiter.check_backup(addr(2), id(1), 10).unwrap_err().code,
+            429
+        );
+        assert!(limiter.check_backup(addr(2), id(2), 10).is_ok());
+        assert_eq!(
+            limiter.check_backup(addr(2), id(3), 10).unwrap_err().code,
+            429
+        );
+        assert!(limiter.check_backup(addr(3), id(3), 10).is_ok());
+    }
+
+    #[test]
+    fn test_connection_limits() {
+        let limiter = ApiLimiter::new(ApiLimits {
+            max_concurrent_awaits: 2,
+            connection_request_rate: 3,
+            max_connection_awaits: 1,
+            ..ApiLimits::default()
+        });
+        let server_logger = limiter.logger();
+        let connection_a = server_logger.clone().connection.unwrap();
+        let connection_b = server_logger.clone().connection.unwrap();
+        let connection_c = server_logger.clone().connection.unwrap();
+
+        // module endpoints are limited the same as the core ones
+        let permits = limiter
+            .check_call(Some(&connection_a), "await_offer")
+            .unwrap();
+        assert_eq!(
+            limiter
+                .check_call(Some(&connection_a), "await_transaction")
+                .unwrap_err()
+                .code,
+            429
+        );
+        assert!(limiter.check_call(Some(&connection_a), "status").is_ok());
+        // the request rate of the connection is used up
+        assert!(limiter.check_call(Some(&connection_a), "status").is_err());
+
+        // all connections share the limits of their address
+        let _permits = limiter
+            .check_call(Some(&connection_b), "await_transaction")
+            .unwrap();
+        assert!(limiter
+            .check_call(Some(&connection_c), "await_transaction")
+            .is_err());
+        drop(permits);
+        assert!(limiter
+            .check_call(Some(&connection_c), "await_transaction")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_remote_addr_logger() {
+        let server_logger = ApiLimiter::new(ApiLimits::default()).logger();
+        let connection_a = server_logger.clone();
+        let connection_b = server_logger.clone();
+        let request = HttpRequest::default();
+
+        connection_a.on_connect(
+            "10.0.0.1:1000".parse().unwrap(),
+            &request,
+            TransportProtocol::WebSocket,
+        );
+        let call_logger = connection_a.clone();
+        connection_b.on_connect(
+            "10.0.0.2:1000".parse().unwrap(),
+            &request,
+            TransportProtocol::WebSocket,
+        );
+
+        call_logger.on_call(
+            "method",
+            Params::new(None),
+            MethodKind::MethodCall,
+            TransportProtocol::WebSocket,
+        );
+        assert_eq!(
+            take_call_connection().unwrap().remote_ip(),
+            Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))
+        );
+        assert!(take_call_connection().is_none());
+
+        server_logger.on_call(
+            "method",
+            Params::new(None),
+            MethodKind::MethodCall,
+            TransportProtocol::WebSocket,
+        );
+        assert!(take_call_connection().is_none());
+    }
+
+    #[test]
+    fn test_forwarded_for() {
+        let remote_ip = |trust_forwarded_for, forwarded_for: &[&str]| {
+            let connection = ApiLimiter::new(ApiLimits {
+                trust_forwarded_for,
+                ..ApiLimits::default()
+            })
+            .logger()
+            .clone();
+            let mut request = HttpRequest::default();
+            for value in forwarded_for {
+                request
+                    .headers_mut()
+                    .append("X-Forwarded-For", value.parse().unwrap());
+            }
+
+            connection.on_connect(
+                "10.0.0.1:1000".parse().unwrap(),
+                &request,
+                TransportProtocol::WebSocket,
+            );
+
+            connection.connection.unwrap().remote_ip().unwrap()
+        };
+        let addr = |byte| IpAddr::V4(Ipv4Addr::new(10, 0, 0, byte));
+
+        assert_eq!(remote_ip(false, &["10.0.0.2"]), addr(1));
+        assert_eq!(remote_ip(true, &[]), addr(1));
+        assert_eq!(remote_ip(true, &["invalid"]), addr(1));
+        assert_eq!(remote_ip(true, &["10.0.0.2"]), addr(2));
+        // only the address appended by the proxy is used, the client can send
+        // the header itself
+        assert_eq!(remote_ip(true, &["10.0.0.3, 10.0.0.2"]), addr(2));
+        assert_eq!(remote_ip(true, &["10.0.0.3", "10.0.0.2"]), addr(2));
+    }
+}
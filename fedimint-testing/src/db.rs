@@ -231,15 +231,19 @@ pub const BYTE_33: [u8; 33] = [
 pub const TEST_MODULE_INSTANCE_ID: u16 = 0;
 
 /// Retrieves a temporary database from the database backup directory.
-/// The first folder that starts with `db_prefix` will return as a temporary
-/// database.
+/// The folder named `db_prefix` or else the first folder that starts with
+/// `db_prefix` will return as a temporary database, so snapshots of later
+/// versions like `fedimint-server-v1` don't shadow `fedimint-server`.
 async fn get_temp_database(
     db_prefix: &str,
     decoders: ModuleDecoderRegistry,
 ) -> anyhow::Result<Database> {
     let snapshot_dirs = get_project_root().unwrap().join("db/migrations");
     if snapshot_dirs.exists() {
-        for file in fs::read_dir(snapshot_dirs)?.flatten() {
+        let mut snapshots = fs::read_dir(snapshot_dirs)?.flatten().collect::<Vec<_>>();
+        snapshots.sort_by_key(|file| file.file_name() != *db_prefix);
+
+        for file in snapshots {
             let name = file
                 .file_name()
                 .into_string()
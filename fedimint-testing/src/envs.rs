@@ -1 +1,5 @@
 pub const FM_PREPARE_DB_MIGRATION_SNAPSHOTS_ENV: &str = "FM_PREPARE_DB_MIGRATION_SNAPSHOTS";
+
+/// Seed of the simulation harness, set it to replay the faults of a failed run
+/// or to explore other fault schedules
+pub const FM_TEST_SIM_SEED_ENV: &str = "FM_TEST_SIM_SEED";
@@ -22,15 +22,20 @@ use tracing::info;
 use crate::btc::mock::FakeBitcoinFactory;
 use crate::btc::real::RealBitcoinTest;
 use crate::btc::BitcoinTest;
+use crate::envs::FM_TEST_SIM_SEED_ENV;
 use crate::federation::FederationTest;
 use crate::gateway::GatewayTest;
 use crate::ln::mock::FakeLightningTest;
 use crate::ln::real::{ClnLightningTest, LdkLightningTest, LndLightningTest};
 use crate::ln::LightningTest;
+use crate::sim::{Simulation, SimulationConfig};
 
 /// A default timeout for things happening in tests
 pub const TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Seed of the simulation harness unless `FM_TEST_SIM_SEED` is set
+pub const DEFAULT_SIM_SEED: u64 = 0;
+
 /// A tool for easily writing fedimint integration tests
 pub struct Fixtures {
     num_peers: u16,
@@ -149,6 +154,26 @@ impl Fixtures {
         .await
     }
 
+    /// Starts a new federation under the control of the simulation harness
+    pub async fn new_simulation(&self, config: SimulationConfig) -> Simulation {
+        Simulation::new(
+            config,
+            self.params.clone(),
+            ServerModuleInitRegistry::from(self.servers.clone()),
+        )
+        .await
+    }
+
+    /// Returns the seed set in `FM_TEST_SIM_SEED` or [`DEFAULT_SIM_SEED`], so
+    /// that the faults of simulations are reproducible unless another seed is
+    /// requested
+    pub fn sim_seed() -> u64 {
+        env::var(FM_TEST_SIM_SEED_ENV)
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or(DEFAULT_SIM_SEED)
+    }
+
     /// Starts a new gateway with a given lightning node
     pub async fn new_gateway(
         &self,
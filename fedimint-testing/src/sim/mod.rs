@@ -0,0 +1,719 @@
+//! Simulation harness that runs a federation of [`ConsensusServer`]s through
+//! many sessions while injecting scripted faults.
+//!
+//! All random decisions, the fault schedule generated by
+//! [`FaultSchedule::random`], the latency and loss of every message, the bad
+//! items sent by Byzantine peers and the client transactions submitted with
+//! [`Simulation::with_transactions`], are derived from a single seed which is
+//! logged at the start of every simulation.
+//!
+//! The harness and the guardians measure time on the virtual clock of the
+//! [`SimNetwork`]: faults are injected, items and transactions submitted and
+//! sessions timed out after some virtual time has passed. Every guardian runs
+//! on its own thread with a paused `current_thread` tokio runtime that is
+//! driven by a [`SimClock`](network::SimClock), so the timers of the guardians
+//! expire on the virtual clock as well and the guardians take turns in a
+//! fixed order. Crashing a guardian stops its clock, which shuts down its
+//! runtime at any point in a session. The guardian is restarted on the same
+//! database and therefore recovers the session from the aleph units saved by
+//! its [`UnitSaver`](fedimint_server::atomic_broadcast::backup::UnitSaver).
+//!
+//! The seed reproduces which faults are injected and which transactions are
+//! submitted, but not the run itself: aleph-bft schedules the creation of its
+//! units with `futures_timer` and `std::time::Instant`, which follow real time
+//! instead of the paused tokio clock, the guardians still download session
+//! outcomes from each others APIs over real websockets and tokio randomizes
+//! the order in which `select!` polls its branches. So the interleaving of
+//! messages differs between two runs with the same seed and a failure might
+//! only show in some of them.
+//!
+//! After every session the harness checks that all guardians agree on the
+//! outcome of every session they have completed, that the balance sheet of
+//! every guardian is non-negative, that no bad item sent by a Byzantine peer
+//! has been accepted and that no transaction has been accepted twice. Once all
+//! faults are reverted we also check that the federation accepted some of the
+//! submitted transactions.
+pub mod network;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use fedimint_core::config::{ServerModuleConfigGenParamsRegistry, ServerModuleInitRegistry};
+use fedimint_core::db::mem_impl::MemDatabase;
+use fedimint_core::db::Database;
+use fedimint_core::encoding::Encodable;
+use fedimint_core::epoch::ConsensusItem;
+use fedimint_core::session_outcome::SessionOutcome;
+use fedimint_core::task::{sleep, TaskGroup};
+use fedimint_core::transaction::{Transaction, TransactionSignature};
+use fedimint_core::PeerId;
+use fedimint_logging::LOG_TEST;
+use fedimint_server::atomic_broadcast::Message;
+use fedimint_server::config::ServerConfig;
+use fedimint_server::consensus::server::ConsensusServer;
+use fedimint_server::net::api::ConsensusApi;
+use fedimint_server::net::connect::Connector;
+use fedimint_server::net::peers::{DelayCalculator, PeerMessage};
+use fedimint_server::FedimintServer;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use tokio::sync::oneshot;
+use tracing::info;
+
+use crate::federation::local_config_gen_params;
+use crate::sim::network::{SimNetwork, SimNetworkConfig};
+
+/// How much virtual time may pass until the federation completes a session
+/// before we consider it stuck
+const SESSION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often every Byzantine peer submits a bad item
+const BYZANTINE_ITEM_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many client transactions are submitted to the federation in every
+/// session
+const TRANSACTIONS_PER_SESSION: usize = 10;
+
+/// Creates the client transactions submitted to the federation, see
+/// [`Simulation::with_transactions`]
+pub type TransactionGenerator = Box<dyn FnMut(&mut StdRng) -> Transaction + Send>;
+
+/// Defines a simulation run
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    /// Seed for all random decisions of the simulation
+    pub seed: u64,
+    /// Number of guardians in the federation
+    pub num_peers: u16,
+    /// Number of sessions to run
+    pub sessions: u64,
+    /// Expected rounds per session, kept low so sessions complete quickly
+    pub rounds_per_session: u16,
+    pub network: SimNetworkConfig,
+    pub faults: FaultSchedule,
+}
+
+impl SimulationConfig {
+    /// Simulation with a random fault schedule derived from `seed`
+    pub fn new(seed: u64, num_peers: u16, sessions: u64) -> Self {
+        Self {
+            seed,
+            num_peers,
+            sessions,
+            rounds_per_session: 10,
+            network: SimNetworkConfig::default(),
+            faults: FaultSchedule::random(seed, num_peers, sessions),
+        }
+    }
+}
+
+/// A fault injected into the federation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Splits the network into groups of peers that cannot reach each other.
+    /// This only affects the p2p network, peers can still download signed
+    /// session outcomes from each others API.
+    Partition(Vec<BTreeSet<PeerId>>),
+    /// Removes any partition
+    Heal,
+    /// Shuts down the peer without giving it a chance to clean up
+    Crash(PeerId),
+    /// Restarts a crashed peer on its previous database
+    Restart(PeerId),
+    /// Makes the peer submit bad items to the atomic broadcast
+    Byzantine(PeerId),
+    /// Stops the peer from submitting bad items
+    Honest(PeerId),
+}
+
+/// Faults to inject by the session in which they are injected
+#[derive(Debug, Clone, Default)]
+pub struct FaultSchedule(BTreeMap<u64, Vec<Fault>>);
+
+impl FaultSchedule {
+    /// Injects `fault` at a random point during session `session`
+    pub fn at(mut self, session: u64, fault: Fault) -> Self {
+        self.0.entry(session).or_default().push(fault);
+        self
+    }
+
+    /// Generates a schedule where in every other session up to `f` peers are
+    /// crashed, partitioned from the rest of the federation or turn
+    /// Byzantine. The faults are reverted in the following session, so the
+    /// federation never has more than `f` faulty peers at the same time.
+    pub fn random(seed: u64, num_peers: u16, sessions: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let max_faulty = ((num_peers - 1) / 3) as usize;
+        let peers = (0..num_peers).map(PeerId::from).collect::<Vec<_>>();
+
+        let mut schedule = Self::default();
+
+        if max_faulty == 0 {
+            return schedule;
+        }
+
+        for session in (0..sessions).step_by(2) {
+            let num_faulty = rng.gen_range(1..=max_faulty);
+            let faulty = peers
+                .choose_multiple(&mut rng, num_faulty)
+                .copied()
+                .collect::<BTreeSet<_>>();
+
+            match rng.gen_range(0..3) {
+                0 => {
+                    for peer in faulty {
+                        schedule = schedule
+                            .at(session, Fault::Crash(peer))
+                            .at(session + 1, Fault::Restart(peer));
+                    }
+                }
+                1 => {
+                    let correct = peers
+                        .iter()
+                        .copied()
+                        .filter(|peer| !faulty.contains(peer))
+                        .collect();
+
+                    schedule = schedule
+                        .at(session, Fault::Partition(vec![correct, faulty]))
+                        .at(session + 1, Fault::Heal);
+                }
+                _ => {
+                    for peer in faulty {
+                        schedule = schedule
+                            .at(session, Fault::Byzantine(peer))
+                            .at(session + 1, Fault::Honest(peer));
+                    }
+                }
+            }
+        }
+
+        schedule
+    }
+}
+
+/// State shared with the task submitting bad items for the Byzantine peers
+#[derive(Default)]
+struct ByzantineState {
+    /// APIs of the running Byzantine peers, used to submit bad items
+    apis: BTreeMap<PeerId, ConsensusApi>,
+    /// Encodings of all submitted items that must never be accepted
+    bad_items: BTreeSet<Vec<u8>>,
+    /// Accepted transactions the Byzantine peers try to replay
+    accepted_transactions: Vec<Transaction>,
+}
+
+/// A guardian of the simulated federation
+struct SimPeer {
+    cfg: ServerConfig,
+    db: Database,
+    /// The API of the guardian while it is running
+    api: Option<ConsensusApi>,
+}
+
+impl SimPeer {
+    async fn start(
+        &mut self,
+        server_init: ServerModuleInitRegistry,
+        network: &SimNetwork<PeerMessage<Message>>,
+    ) {
+        assert!(self.api.is_none(), "Peer is already running");
+
+        let peer_id = self.cfg.local.identity;
+        let cfg = self.cfg.clone();
+        let db = self.db.clone();
+        let connector = network.connector(peer_id).into_dyn();
+        let clock = network.clock(peer_id);
+        let (api_sender, api_receiver) = oneshot::channel();
+
+        std::thread::Builder::new()
+            .name(format!("sim-peer-{peer_id}"))
+            .spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .start_paused(true)
+                    .build()
+                    .expect("Failed to build runtime");
+
+                runtime.spawn(async move {
+                    let mut task_group = TaskGroup::new();
+
+                    let (consensus_server, consensus_api) = ConsensusServer::new_with(
+                        cfg,
+                        db,
+                        server_init,
+                        connector,
+                        DelayCalculator::TEST_DEFAULT,
+                        &mut task_group,
+                    )
+                    .await
+                    .expect("Failed to init server");
+
+                    let _api_handle =
+                        FedimintServer::spawn_consensus_api(consensus_api.clone(), false).await;
+
+                    api_sender.send(consensus_api).ok();
+
+                    consensus_server
+                        .run(task_group.make_handle())
+                        .await
+                        .expect("Consensus failed");
+                });
+
+                clock.run(&runtime);
+
+                // the clock was stopped because the peer crashed, so its tasks must not get a
+                // chance to clean up
+                runtime.shutdown_background();
+            })
+            .expect("Failed to spawn peer thread");
+
+        self.api = Some(api_receiver.await.expect("Peer failed to start"));
+    }
+
+    fn crash(&mut self, network: &SimNetwork<PeerMessage<Message>>) {
+        if self.api.take().is_some() {
+            network.stop_clock(self.cfg.local.identity);
+        }
+    }
+}
+
+/// A federation running under the control of the simulation harness
+pub struct Simulation {
+    config: SimulationConfig,
+    server_init: ServerModuleInitRegistry,
+    network: SimNetwork<PeerMessage<Message>>,
+    peers: BTreeMap<PeerId, SimPeer>,
+    rng: StdRng,
+    /// Peers that cannot reach the largest group of the current partition
+    isolated: BTreeSet<PeerId>,
+    byzantine: BTreeSet<PeerId>,
+    byzantine_state: Arc<Mutex<ByzantineState>>,
+    /// The session outcomes all peers have to agree on
+    outcomes: BTreeMap<u64, SessionOutcome>,
+    /// Number of sessions we have checked for every peer
+    checked_sessions: BTreeMap<PeerId, u64>,
+    transactions: Option<TransactionGenerator>,
+    task_group: TaskGroup,
+}
+
+impl Simulation {
+    /// Generates the configs and starts all guardians
+    pub async fn new(
+        config: SimulationConfig,
+        params: ServerModuleConfigGenParamsRegistry,
+        server_init: ServerModuleInitRegistry,
+    ) -> Self {
+        info!(target: LOG_TEST, seed = config.seed, faults = ?config.faults, "Starting simulation");
+
+        let peer_ids = (0..config.num_peers).map(PeerId::from).collect::<Vec<_>>();
+
+        // The guardians still talk to each others APIs via websockets
+        let base_port = fedimint_portalloc::port_alloc(config.num_peers * 2)
+            .expect("Failed to allocate a port range");
+
+        let params =
+            local_config_gen_params(&peer_ids, base_port, params).expect("Generates local config");
+
+        let mut configs = ServerConfig::trusted_dealer_gen(
+            &params,
+            server_init.clone(),
+            "fedimint-testing-sim-version-hash".to_owned(),
+        );
+
+        for cfg in configs.values_mut() {
+            cfg.consensus.broadcast_expected_rounds_per_session = config.rounds_per_session;
+        }
+
+        let mut task_group = TaskGroup::new();
+        let network = SimNetwork::new(config.seed, config.network, &mut task_group).await;
+
+        let mut peers = BTreeMap::new();
+
+        for (peer_id, cfg) in configs {
+            let instances = cfg.consensus.iter_module_instances();
+            let decoders = server_init.available_decoders(instances).unwrap();
+
+            let mut peer = SimPeer {
+                cfg,
+                db: Database::new(MemDatabase::new(), decoders),
+                api: None,
+            };
+
+            peer.start(server_init.clone(), &network).await;
+            peers.insert(peer_id, peer);
+        }
+
+        let byzantine_state = Arc::new(Mutex::new(ByzantineState::default()));
+
+        spawn_byzantine_task(
+            &mut task_group,
+            config.seed,
+            network.clone(),
+            Arc::clone(&byzantine_state),
+        )
+        .await;
+
+        Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            server_init,
+            network,
+            peers,
+            isolated: BTreeSet::new(),
+            byzantine: BTreeSet::new(),
+            byzantine_state,
+            outcomes: BTreeMap::new(),
+            checked_sessions: BTreeMap::new(),
+            transactions: None,
+            task_group,
+        }
+    }
+
+    /// Submits [`TRANSACTIONS_PER_SESSION`] transactions created by
+    /// `generator` to random running guardians in every session. The
+    /// transactions may conflict with each other or spend funds that were
+    /// never received, the guardians have to reject those.
+    pub fn with_transactions(
+        mut self,
+        generator: impl FnMut(&mut StdRng) -> Transaction + Send + 'static,
+    ) -> Self {
+        self.transactions = Some(Box::new(generator));
+        self
+    }
+
+    /// Runs the configured number of sessions, checking the invariants after
+    /// every session. Finally all faults are reverted and we check that every
+    /// peer catches up with the federation.
+    ///
+    /// Panics if an invariant is violated or the federation gets stuck.
+    pub async fn run(mut self) {
+        for session in 0..self.config.sessions {
+            let faults = self
+                .config
+                .faults
+                .0
+                .get(&session)
+                .cloned()
+                .unwrap_or_default();
+
+            if !faults.is_empty() {
+                // inject the faults at a random point of the session
+                let delay = self.rng.gen_range(0..1000);
+                self.network.sleep(Duration::from_millis(delay)).await;
+            }
+
+            for fault in faults {
+                self.inject(fault).await;
+            }
+
+            let live_peers = self
+                .peers
+                .iter()
+                .filter(|(peer_id, peer)| peer.api.is_some() && !self.isolated.contains(*peer_id))
+                .map(|(peer_id, _)| *peer_id)
+                .collect::<Vec<_>>();
+
+            self.submit_transactions(&live_peers).await;
+            self.wait_for_session(&live_peers, session).await;
+            self.check_invariants().await;
+
+            info!(
+                target: LOG_TEST,
+                session,
+                virtual_time = ?self.network.now(),
+                "Simulated session completed"
+            );
+        }
+
+        self.inject(Fault::Heal).await;
+
+        for peer_id in self.peers.keys().copied().collect::<Vec<_>>() {
+            self.inject(Fault::Honest(peer_id)).await;
+            self.inject(Fault::Restart(peer_id)).await;
+        }
+
+        let all_peers = self.peers.keys().copied().collect::<Vec<_>>();
+        let last_session = self.config.sessions.saturating_sub(1);
+
+        self.wait_for_session(&all_peers, last_session).await;
+        self.check_invariants().await;
+
+        if self.transactions.is_some() {
+            let seed = self.config.seed;
+
+            assert!(
+                !self
+                    .byzantine_state
+                    .lock()
+                    .expect("poisoned")
+                    .accepted_transactions
+                    .is_empty(),
+                "No submitted transaction was accepted (seed {seed})"
+            );
+        }
+
+        for peer in self.peers.values_mut() {
+            peer.crash(&self.network);
+        }
+
+        self.task_group.shutdown();
+    }
+
+    async fn inject(&mut self, fault: Fault) {
+        info!(target: LOG_TEST, ?fault, "Injecting fault");
+
+        match fault {
+            Fault::Partition(groups) => {
+                let largest = groups
+                    .iter()
+                    .max_by_key(|group| group.len())
+                    .cloned()
+                    .unwrap_or_default();
+
+                self.isolated = self
+                    .peers
+                    .keys()
+                    .copied()
+                    .filter(|peer_id| !largest.contains(peer_id))
+                    .collect();
+
+                self.network.partition(groups);
+            }
+            Fault::Heal => {
+                self.isolated.clear();
+                self.network.heal();
+            }
+            Fault::Crash(peer_id) => {
+                self.byzantine_state
+                    .lock()
+                    .expect("poisoned")
+                    .apis
+                    .remove(&peer_id);
+
+                let network = self.network.clone();
+                self.peer_mut(peer_id).crash(&network);
+            }
+            Fault::Restart(peer_id) => {
+                if self.peers[&peer_id].api.is_some() {
+                    return;
+                }
+
+                // give the runtime of the crashed peer time to release its API socket, which
+                // takes real time
+                sleep(Duration::from_secs(1)).await;
+
+                let server_init = self.server_init.clone();
+                let network = self.network.clone();
+                self.peer_mut(peer_id).start(server_init, &network).await;
+
+                if self.byzantine.contains(&peer_id) {
+                    self.set_byzantine(peer_id);
+                }
+            }
+            Fault::Byzantine(peer_id) => {
+                self.byzantine.insert(peer_id);
+                self.set_byzantine(peer_id);
+            }
+            Fault::Honest(peer_id) => {
+                self.byzantine.remove(&peer_id);
+                self.byzantine_state
+                    .lock()
+                    .expect("poisoned")
+                    .apis
+                    .remove(&peer_id);
+            }
+        }
+    }
+
+    fn set_byzantine(&mut self, peer_id: PeerId) {
+        if let Some(api) = &self.peers[&peer_id].api {
+            self.byzantine_state
+                .lock()
+                .expect("poisoned")
+                .apis
+                .insert(peer_id, api.clone());
+        }
+    }
+
+    fn peer_mut(&mut self, peer_id: PeerId) -> &mut SimPeer {
+        self.peers.get_mut(&peer_id).expect("Unknown peer")
+    }
+
+    async fn submit_transactions(&mut self, peers: &[PeerId]) {
+        let Some(generator) = &mut self.transactions else {
+            return;
+        };
+
+        for _ in 0..TRANSACTIONS_PER_SESSION {
+            let transaction = generator(&mut self.rng);
+            let peer_id = *peers.choose(&mut self.rng).expect("A peer is running");
+            let api = self.peers[&peer_id].api.as_ref().expect("Peer is running");
+
+            // invalid transactions are part of the workload, so we ignore rejections
+            api.submit_transaction(transaction).await.ok();
+        }
+    }
+
+    /// Waits until all `peers` have completed `session`
+    async fn wait_for_session(&self, peers: &[PeerId], session: u64) {
+        let seed = self.config.seed;
+
+        for peer_id in peers {
+            let api = self.peers[peer_id].api.as_ref().expect("Peer is running");
+
+            let completed = self
+                .network
+                .timeout(SESSION_TIMEOUT, async {
+                    while api.session_count().await <= session {
+                        self.network.sleep(Duration::from_millis(100)).await;
+                    }
+                })
+                .await;
+
+            assert!(
+                completed.is_ok(),
+                "Peer {peer_id} did not complete session {session} (seed {seed})"
+            );
+        }
+    }
+
+    async fn check_invariants(&mut self) {
+        let seed = self.config.seed;
+
+        for (peer_id, peer) in &self.peers {
+            let Some(api) = &peer.api else {
+                continue;
+            };
+
+            let checked = self.checked_sessions.entry(*peer_id).or_default();
+            let session_count = api.session_count().await;
+
+            for session in *checked..session_count {
+                let outcome = api
+                    .await_signed_session_outcome(session)
+                    .await
+                    .session_outcome;
+
+                let expected = self
+                    .outcomes
+                    .entry(session)
+                    .or_insert_with(|| outcome.clone());
+
+                assert_eq!(
+                    *expected, outcome,
+                    "Peer {peer_id} has a diverging outcome for session {session} (seed {seed})"
+                );
+            }
+
+            *checked = session_count;
+
+            let audit = api
+                .get_federation_audit()
+                .await
+                .expect("Failed to audit federation");
+
+            assert!(
+                audit.net_assets >= 0,
+                "Peer {peer_id} has a negative balance sheet {audit:?} (seed {seed})"
+            );
+        }
+
+        let mut state = self.byzantine_state.lock().expect("poisoned");
+        let mut txids = BTreeSet::new();
+        let mut accepted_transactions = vec![];
+
+        for (session, outcome) in &self.outcomes {
+            for accepted_item in &outcome.items {
+                assert!(
+                    !state
+                        .bad_items
+                        .contains(&accepted_item.item.consensus_encode_to_vec()),
+                    "Bad item from peer {} was accepted in session {session} (seed {seed})",
+                    accepted_item.peer
+                );
+
+                if let ConsensusItem::Transaction(transaction) = &accepted_item.item {
+                    assert!(
+                        txids.insert(transaction.tx_hash()),
+                        "Transaction {} was accepted twice (seed {seed})",
+                        transaction.tx_hash()
+                    );
+
+                    accepted_transactions.push(transaction.clone());
+                }
+            }
+        }
+
+        state.accepted_transactions = accepted_transactions;
+    }
+}
+
+/// Makes every Byzantine peer submit items to its atomic broadcast that the
+/// correct peers have to discard
+async fn spawn_byzantine_task(
+    task_group: &mut TaskGroup,
+    seed: u64,
+    network: SimNetwork<PeerMessage<Message>>,
+    state: Arc<Mutex<ByzantineState>>,
+) {
+    // we do not want to reuse the random numbers of the network
+    let mut rng = StdRng::seed_from_u64(seed.wrapping_add(1));
+
+    task_group
+        .spawn("sim byzantine peers", move |handle| async move {
+            while !handle.is_shutting_down() {
+                network.sleep(BYZANTINE_ITEM_INTERVAL).await;
+
+                let mut byzantine = state.lock().expect("poisoned");
+
+                for api in byzantine.apis.values().cloned().collect::<Vec<_>>() {
+                    let (item, is_bad) = match rng.gen_range(0..3) {
+                        0 => (
+                            ConsensusItem::Default {
+                                // variants 0 and 1 are used by transactions and module items
+                                variant: rng.gen_range(2..1000),
+                                bytes: random_bytes(&mut rng),
+                            },
+                            true,
+                        ),
+                        1 => (
+                            ConsensusItem::Transaction(Transaction {
+                                inputs: vec![],
+                                outputs: vec![],
+                                nonce: rng.gen(),
+                                signatures: TransactionSignature::Default {
+                                    variant: rng.gen_range(1..1000),
+                                    bytes: random_bytes(&mut rng),
+                                },
+                            }),
+                            true,
+                        ),
+                        // replaying a transaction is only bad if it is accepted twice, which
+                        // we check for all transactions
+                        _ => match byzantine.accepted_transactions.choose(&mut rng) {
+                            Some(transaction) => {
+                                (ConsensusItem::Transaction(transaction.clone()), false)
+                            }
+                            None => continue,
+                        },
+                    };
+
+                    if is_bad {
+                        byzantine.bad_items.insert(item.consensus_encode_to_vec());
+                    }
+
+                    // the peer might be busy or shutting down
+                    api.submission_sender.try_send(item).ok();
+                }
+            }
+        })
+        .await;
+}
+
+fn random_bytes(rng: &mut StdRng) -> Vec<u8> {
+    let len = rng.gen_range(0..64);
+    (0..len).map(|_| rng.gen()).collect()
+}
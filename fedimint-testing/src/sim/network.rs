@@ -0,0 +1,582 @@
+//! Message based network for the simulation harness
+//!
+//! Instead of simulating byte streams like
+//! [`MockNetwork`](fedimint_server::net::connect::mock::MockNetwork) every
+//! message sent over a connection is handed to a central scheduler which
+//! assigns it a delivery time on a virtual clock. Messages are delivered in
+//! the order of their delivery time, so latency jitter reorders messages, and
+//! can be dropped at random or because the sender and recipient are
+//! partitioned from each other at the time of delivery. The latency and loss
+//! of a message only depend on the seed, the link it is sent over and how
+//! many messages were sent over that link before, so a peer sending more
+//! messages does not change the fate of the messages of other peers.
+//!
+//! The scheduler advances the virtual clock in steps of [`TICK`]. In every
+//! step it delivers the messages and expires the timers of
+//! [`SimNetwork::sleep`] that are due, and then lets every attached
+//! [`SimClock`] catch up with the virtual clock one after another in the
+//! order of their peer ids. A guardian driven by a [`SimClock`] runs on a
+//! paused tokio runtime, so its own timers, like reconnects, pings and request
+//! timeouts, expire on the virtual clock as well and the guardians never run
+//! concurrently to each other.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{self, Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::{anyhow, format_err};
+use fedimint_core::task::{Elapsed, TaskGroup};
+use fedimint_core::util::SafeUrl;
+use fedimint_core::PeerId;
+use fedimint_server::net::connect::{
+    parse_host_port, ConnectResult, ConnectionListener, Connector,
+};
+use fedimint_server::net::framed::FramedTransport;
+use futures::channel::mpsc;
+use futures::{Sink, Stream, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+
+/// How far the scheduler advances the virtual clock in every step
+pub const TICK: Duration = Duration::from_millis(1);
+
+/// Properties of the links between all peers
+#[derive(Debug, Clone, Copy)]
+pub struct SimNetworkConfig {
+    /// Minimum time it takes to deliver a message
+    pub min_latency: Duration,
+    /// Maximum time it takes to deliver a message
+    pub max_latency: Duration,
+    /// Probability that a message is lost
+    pub drop_rate: f64,
+}
+
+impl Default for SimNetworkConfig {
+    fn default() -> Self {
+        Self {
+            min_latency: Duration::from_millis(1),
+            max_latency: Duration::from_millis(50),
+            drop_rate: 0.01,
+        }
+    }
+}
+
+/// A simulated network shared by all peers of a simulated federation
+pub struct SimNetwork<M> {
+    state: Arc<Mutex<SimNetworkState<M>>>,
+}
+
+impl<M> Clone for SimNetwork<M> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+struct SimNetworkState<M> {
+    config: SimNetworkConfig,
+    seed: u64,
+    /// Random numbers for the messages sent over every link, created on the
+    /// first message sent from one peer to another
+    links: BTreeMap<(PeerId, PeerId), StdRng>,
+    /// The virtual time of the current step of the scheduler
+    now: Duration,
+    /// Groups of peers that can reach each other, empty if the network is not
+    /// partitioned
+    partition: Vec<BTreeSet<PeerId>>,
+    listeners: HashMap<String, mpsc::UnboundedSender<ConnectResult<M>>>,
+    pipes: HashMap<u64, Pipe<M>>,
+    /// Messages ordered by delivery time and sequence number
+    queue: BTreeMap<(Duration, u64), (u64, M)>,
+    /// Timers of [`SimNetwork::sleep`] ordered by expiry and sequence number
+    timers: BTreeMap<(Duration, u64), oneshot::Sender<()>>,
+    /// Clocks of the running guardians, see [`SimNetwork::clock`]
+    clocks: BTreeMap<PeerId, sync::mpsc::Sender<Tick>>,
+    next_id: u64,
+}
+
+/// Asks a [`SimClock`] to catch up with the virtual time `until`
+struct Tick {
+    until: Duration,
+    done: oneshot::Sender<()>,
+}
+
+/// One direction of a connection between two peers
+struct Pipe<M> {
+    from: PeerId,
+    to: PeerId,
+    sender: mpsc::UnboundedSender<M>,
+    /// The pipe in the opposite direction, closed together with this one
+    reverse: u64,
+}
+
+impl<M> SimNetworkState<M> {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn can_reach(&self, from: PeerId, to: PeerId) -> bool {
+        self.partition.is_empty()
+            || self
+                .partition
+                .iter()
+                .any(|group| group.contains(&from) && group.contains(&to))
+    }
+
+    fn close(&mut self, pipe_id: u64) {
+        if let Some(pipe) = self.pipes.remove(&pipe_id) {
+            self.pipes.remove(&pipe.reverse);
+        }
+    }
+
+    fn link_rng(&mut self, from: PeerId, to: PeerId) -> &mut StdRng {
+        let seed = self.seed;
+
+        self.links.entry((from, to)).or_insert_with(|| {
+            let link = (u64::from(u16::from(from)) << 16) | u64::from(u16::from(to));
+            StdRng::seed_from_u64(seed ^ link.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        })
+    }
+}
+
+impl<M> SimNetwork<M>
+where
+    M: Debug + Send + Unpin + 'static,
+{
+    /// Creates the network and spawns its scheduler, all random decisions are
+    /// derived from `seed`
+    pub async fn new(seed: u64, config: SimNetworkConfig, task_group: &mut TaskGroup) -> Self {
+        assert!(config.min_latency <= config.max_latency);
+        assert!((0.0..=1.0).contains(&config.drop_rate));
+
+        let network = Self {
+            state: Arc::new(Mutex::new(SimNetworkState {
+                config,
+                seed,
+                links: BTreeMap::new(),
+                now: Duration::ZERO,
+                partition: vec![],
+                listeners: HashMap::new(),
+                pipes: HashMap::new(),
+                queue: BTreeMap::new(),
+                timers: BTreeMap::new(),
+                clocks: BTreeMap::new(),
+                next_id: 0,
+            })),
+        };
+
+        let scheduler = network.clone();
+        task_group
+            .spawn("sim network scheduler", move |handle| async move {
+                while !handle.is_shutting_down() {
+                    scheduler.step().await;
+                }
+
+                // lets the threads of the guardians exit
+                scheduler.state.lock().expect("poisoned").clocks.clear();
+            })
+            .await;
+
+        network
+    }
+
+    /// Returns a connector for the peer `id`
+    pub fn connector(&self, id: PeerId) -> SimConnector<M> {
+        SimConnector {
+            id,
+            network: self.clone(),
+        }
+    }
+
+    /// The virtual time that has passed in the network
+    pub fn now(&self) -> Duration {
+        self.state.lock().expect("poisoned").now
+    }
+
+    /// Splits the network into groups of peers, messages between peers in
+    /// different groups are dropped until the partition is healed
+    pub fn partition(&self, groups: Vec<BTreeSet<PeerId>>) {
+        self.state.lock().expect("poisoned").partition = groups;
+    }
+
+    /// Removes any partition
+    pub fn heal(&self) {
+        self.partition(vec![]);
+    }
+
+    /// Attaches a clock for the guardian `id`, replacing any previous clock of
+    /// the guardian. The guardian is only advanced by the scheduler once the
+    /// clock [runs](SimClock::run) its runtime.
+    pub fn clock(&self, id: PeerId) -> SimClock {
+        let (sender, receiver) = sync::mpsc::channel();
+        let mut state = self.state.lock().expect("poisoned");
+
+        state.clocks.insert(id, sender);
+
+        SimClock {
+            ticks: receiver,
+            attached_at: state.now,
+        }
+    }
+
+    /// Detaches the clock of the guardian `id`, which stops its runtime
+    /// without giving its tasks a chance to clean up
+    pub fn stop_clock(&self, id: PeerId) {
+        self.state.lock().expect("poisoned").clocks.remove(&id);
+    }
+
+    /// Completes once `duration` has passed on the virtual clock
+    pub async fn sleep(&self, duration: Duration) {
+        let (sender, receiver) = oneshot::channel();
+
+        {
+            let mut state = self.state.lock().expect("poisoned");
+            let expires_at = state.now + duration;
+            let sequence = state.next_id();
+
+            state.timers.insert((expires_at, sequence), sender);
+        }
+
+        // the sender is only dropped once the scheduler has been shut down
+        receiver.await.ok();
+    }
+
+    /// Fails if `future` does not complete within `duration` on the virtual
+    /// clock
+    pub async fn timeout<F: Future>(
+        &self,
+        duration: Duration,
+        future: F,
+    ) -> Result<F::Output, Elapsed> {
+        tokio::select! {
+            output = future => Ok(output),
+            () = self.sleep(duration) => Err(Elapsed),
+        }
+    }
+
+    fn send(&self, pipe_id: u64, message: M) -> anyhow::Result<()> {
+        let mut state = self.state.lock().expect("poisoned");
+
+        let Some(pipe) = state.pipes.get(&pipe_id) else {
+            return Err(anyhow!("Connection closed"));
+        };
+
+        let (from, to) = (pipe.from, pipe.to);
+
+        let SimNetworkConfig {
+            min_latency,
+            max_latency,
+            drop_rate,
+        } = state.config;
+
+        let rng = state.link_rng(from, to);
+
+        // we always draw the latency, so a dropped message does not shift the
+        // latencies of the following messages
+        let dropped = rng.gen_bool(drop_rate);
+        let latency = rng.gen_range(min_latency..=max_latency);
+
+        if dropped {
+            return Ok(());
+        }
+
+        let deliver_at = state.now + latency;
+        let sequence = state.next_id();
+
+        state
+            .queue
+            .insert((deliver_at, sequence), (pipe_id, message));
+
+        Ok(())
+    }
+
+    async fn step(&self) {
+        let (deliveries, expiries) = {
+            let mut state = self.state.lock().expect("poisoned");
+            let next = state.now + TICK;
+
+            // everything due before the end of this step, in the order of virtual time
+            let later_deliveries = state.queue.split_off(&(next, 0));
+            let later_expiries = state.timers.split_off(&(next, 0));
+
+            (
+                std::mem::replace(&mut state.queue, later_deliveries),
+                std::mem::replace(&mut state.timers, later_expiries),
+            )
+        };
+
+        for (_, (pipe_id, message)) in deliveries {
+            self.deliver(pipe_id, message);
+        }
+
+        for (_, sender) in expiries {
+            sender.send(()).ok();
+        }
+
+        // give the tasks of the harness woken by the timers a chance to act at the
+        // current virtual time before the guardians move on
+        tokio::task::yield_now().await;
+
+        let (until, clocks) = {
+            let state = self.state.lock().expect("poisoned");
+            (state.now + TICK, state.clocks.clone())
+        };
+
+        for clock in clocks.into_values() {
+            let (done, done_receiver) = oneshot::channel();
+
+            // the guardian might have crashed in the meantime
+            if clock.send(Tick { until, done }).is_ok() {
+                done_receiver.await.ok();
+            }
+        }
+
+        self.state.lock().expect("poisoned").now = until;
+    }
+
+    fn deliver(&self, pipe_id: u64, message: M) {
+        let mut state = self.state.lock().expect("poisoned");
+
+        let closed = match state.pipes.get(&pipe_id) {
+            Some(pipe) if state.can_reach(pipe.from, pipe.to) => {
+                pipe.sender.unbounded_send(message).is_err()
+            }
+            _ => false,
+        };
+
+        // The recipient has crashed, so we close the connection to let the sender
+        // reconnect once the recipient is back
+        if closed {
+            state.close(pipe_id);
+        }
+    }
+}
+
+/// Drives the runtime of a single guardian from the virtual clock of the
+/// [`SimNetwork`], see [`SimNetwork::clock`]
+pub struct SimClock {
+    ticks: sync::mpsc::Receiver<Tick>,
+    /// The virtual time at which the clock was attached
+    attached_at: Duration,
+}
+
+impl SimClock {
+    /// Blocks the current thread and runs the tasks of `runtime` whenever the
+    /// scheduler advances the virtual clock, until the clock is stopped.
+    /// The runtime has to be a `current_thread` runtime that was started
+    /// paused, its tasks only run while the guardian catches up with the
+    /// virtual clock.
+    pub fn run(self, runtime: &Runtime) {
+        let start = runtime.block_on(async { tokio::time::Instant::now() });
+
+        while let Ok(Tick { until, done }) = self.ticks.recv() {
+            let deadline = start + until.saturating_sub(self.attached_at);
+
+            // the paused clock of the runtime auto-advances from timer to timer until the
+            // deadline, whenever none of its tasks can make progress
+            runtime.block_on(tokio::time::sleep_until(deadline));
+
+            done.send(()).ok();
+        }
+    }
+}
+
+/// Connects a single peer to the [`SimNetwork`]
+pub struct SimConnector<M> {
+    id: PeerId,
+    network: SimNetwork<M>,
+}
+
+#[async_trait::async_trait]
+impl<M> Connector<M> for SimConnector<M>
+where
+    M: Debug + Send + Unpin + 'static,
+{
+    async fn connect_framed(&self, destination: SafeUrl, peer: PeerId) -> ConnectResult<M> {
+        let address = parse_host_port(destination)?;
+        let mut state = self.network.state.lock().expect("poisoned");
+
+        let listener = state
+            .listeners
+            .get(&address)
+            .ok_or_else(|| format_err!("can't connect"))?
+            .clone();
+
+        let (ours_id, theirs_id) = (state.next_id(), state.next_id());
+        let (ours_sender, ours_receiver) = mpsc::unbounded();
+        let (theirs_sender, theirs_receiver) = mpsc::unbounded();
+
+        // messages we send are delivered to them and vice versa
+        state.pipes.insert(
+            ours_id,
+            Pipe {
+                from: self.id,
+                to: peer,
+                sender: theirs_sender,
+                reverse: theirs_id,
+            },
+        );
+        state.pipes.insert(
+            theirs_id,
+            Pipe {
+                from: peer,
+                to: self.id,
+                sender: ours_sender,
+                reverse: ours_id,
+            },
+        );
+
+        // dropping a transport locks the state to close its pipes
+        drop(state);
+
+        let theirs = SimTransport {
+            sink: SimSink {
+                pipe_id: theirs_id,
+                network: self.network.clone(),
+            },
+            stream: SimStream(theirs_receiver),
+        };
+
+        if listener
+            .unbounded_send(Ok((self.id, theirs.into_dyn())))
+            .is_err()
+        {
+            return Err(anyhow!("can't connect"));
+        }
+
+        let ours = SimTransport {
+            sink: SimSink {
+                pipe_id: ours_id,
+                network: self.network.clone(),
+            },
+            stream: SimStream(ours_receiver),
+        };
+
+        Ok((peer, ours.into_dyn()))
+    }
+
+    async fn listen(&self, bind_addr: SocketAddr) -> anyhow::Result<ConnectionListener<M>> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        // A restarted peer listens on the same address again, so we replace any
+        // previous listener instead of failing
+        self.network
+            .state
+            .lock()
+            .expect("poisoned")
+            .listeners
+            .insert(bind_addr.to_string(), sender);
+
+        Ok(Box::pin(receiver))
+    }
+}
+
+struct SimSink<M> {
+    pipe_id: u64,
+    network: SimNetwork<M>,
+}
+
+impl<M> Sink<M> for SimSink<M>
+where
+    M: Debug + Send + Unpin + 'static,
+{
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: M) -> Result<(), Self::Error> {
+        self.network.send(self.pipe_id, item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.network
+            .state
+            .lock()
+            .expect("poisoned")
+            .close(self.pipe_id);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+struct SimStream<M>(mpsc::UnboundedReceiver<M>);
+
+impl<M> Stream for SimStream<M> {
+    type Item = anyhow::Result<M>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_next_unpin(cx).map(|message| message.map(Ok))
+    }
+}
+
+struct SimTransport<M> {
+    sink: SimSink<M>,
+    stream: SimStream<M>,
+}
+
+impl<M> Sink<M> for SimTransport<M>
+where
+    M: Debug + Send + Unpin + 'static,
+{
+    type Error = anyhow::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sink).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: M) -> Result<(), Self::Error> {
+        Pin::new(&mut self.sink).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sink).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sink).poll_close(cx)
+    }
+}
+
+impl<M> Stream for SimTransport<M> {
+    type Item = anyhow::Result<M>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+impl<M> FramedTransport<M> for SimTransport<M>
+where
+    M: Debug + Send + Unpin + 'static,
+{
+    fn borrow_split(
+        &mut self,
+    ) -> (
+        &'_ mut (dyn Sink<M, Error = anyhow::Error> + Send + Unpin),
+        &'_ mut (dyn Stream<Item = anyhow::Result<M>> + Send + Unpin),
+    ) {
+        (&mut self.sink, &mut self.stream)
+    }
+}
+
+impl<M> Drop for SimTransport<M> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.sink.network.state.lock() {
+            state.close(self.sink.pipe_id);
+        }
+    }
+}
@@ -1,18 +1,23 @@
 use std::collections::BTreeMap;
+use std::fs;
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::format_err;
-use clap::Parser;
+use anyhow::{ensure, format_err, Context};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use fedimint_core::admin_client::ConfigGenParamsRequest;
-use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
+use fedimint_core::api::GuardianConfigBackup;
+use fedimint_core::bitcoinrpc::{BitcoinRpcConfig, FM_BITCOIN_RPC_KIND, FM_BITCOIN_RPC_URL};
 use fedimint_core::config::{
     ModuleInitParams, ServerModuleConfigGenParamsRegistry, ServerModuleInitRegistry,
 };
 use fedimint_core::core::{ModuleInstanceId, ModuleKind};
 use fedimint_core::db::Database;
 use fedimint_core::envs::{is_env_var_set, FM_USE_UNKNOWN_MODULE_ENV};
+use fedimint_core::health::DynHealthChecks;
 use fedimint_core::module::ServerModuleInit;
 use fedimint_core::task::{sleep, TaskGroup};
 use fedimint_core::timing;
@@ -21,7 +26,15 @@ use fedimint_ln_server::LightningInit;
 use fedimint_logging::TracingSetup;
 use fedimint_mint_server::MintInit;
 use fedimint_server::config::api::ConfigGenSettings;
-use fedimint_server::config::io::{DB_FILE, PLAINTEXT_PASSWORD};
+use fedimint_server::config::io::{
+    check_config_password, read_consensus_config, DB_FILE, PLAINTEXT_PASSWORD,
+};
+use fedimint_server::config::reshare::prepare_reshare;
+use fedimint_server::config::restore::restore_guardian_config_backup;
+use fedimint_server::config::setup::HeadlessSetup;
+use fedimint_server::config::transcript::{verify_dkg_transcripts, SignedDkgTranscript};
+use fedimint_server::envs::{FM_RESTORE_FROM_BACKUP_ENV, FM_SETUP_FILE_ENV};
+use fedimint_server::health::ServerHealth;
 use fedimint_server::FedimintServer;
 use fedimint_unknown_server::UnknownInit;
 use fedimint_wallet_server::WalletInit;
@@ -29,6 +42,7 @@ use futures::FutureExt;
 use tokio::select;
 use tracing::{debug, error, info, warn};
 
+use crate::config_file::ServerConfigFile;
 use crate::{attach_default_module_init_params, attach_unknown_module_init_params};
 
 /// Time we will wait before forcefully shutting down tasks
@@ -39,10 +53,22 @@ pub const FM_EXTRA_DKG_META_VAR: &str = "FM_EXTRA_DKG_META";
 #[derive(Parser)]
 #[command(version)]
 pub struct ServerOpts {
+    #[command(subcommand)]
+    command: Option<ServerCommand>,
+
+    /// Path to a TOML file setting any of the options below, which take
+    /// precedence when passed as flag or env var
+    #[arg(long, env = "FM_CONFIG_FILE", global = true)]
+    pub config: Option<PathBuf>,
+
     /// Path to folder containing federation config files
     #[arg(long = "data-dir", env = "FM_DATA_DIR")]
-    pub data_dir: PathBuf,
+    pub data_dir: Option<PathBuf>,
     /// Password to encrypt sensitive config files
+    ///
+    /// Has to be updated whenever the guardian password is changed through the
+    /// admin API, `fedimintd` refuses to start with a password that does not
+    /// decrypt the config anymore.
     // TODO: should probably never send password to the server directly, rather send the hash via
     // the API
     #[arg(long, env = "FM_PASSWORD")]
@@ -53,6 +79,10 @@ pub struct ServerOpts {
     /// Enable telemetry logging
     #[arg(long, default_value = "false")]
     pub with_telemetry: bool,
+    /// Log level applied to most modules, directives in `RUST_LOG` take
+    /// precedence
+    #[arg(long)]
+    pub log_level: Option<String>,
 
     /// Address we bind to for federation communication
     #[arg(long, env = "FM_BIND_P2P", default_value = "127.0.0.1:8173")]
@@ -72,7 +102,16 @@ pub struct ServerOpts {
     /// The bitcoin network that fedimint will be running on
     #[arg(long, env = "FM_FINALITY_DELAY", default_value = "10")]
     finality_delay: u32,
-
+    /// Kind of the bitcoin RPC used to follow the chain, e.g. `bitcoind` or
+    /// `esplora`
+    #[arg(long, env = FM_BITCOIN_RPC_KIND)]
+    bitcoin_rpc_kind: Option<String>,
+    /// URL of the bitcoin RPC used to follow the chain
+    #[arg(long, env = FM_BITCOIN_RPC_URL)]
+    bitcoin_rpc_url: Option<SafeUrl>,
+
+    /// Address to serve the prometheus metrics and the unauthenticated health
+    /// endpoints on
     #[arg(long, env = "FM_BIND_METRICS_API")]
     bind_metrics_api: Option<SocketAddr>,
 
@@ -80,6 +119,159 @@ pub struct ServerOpts {
     /// `key1=value1,key2=value,...`)
     #[arg(long, env = FM_EXTRA_DKG_META_VAR, value_parser = parse_map, default_value="")]
     extra_dkg_meta: BTreeMap<String, String>,
+
+    /// Restore the guardian from a backup downloaded with `fedimint-cli admin
+    /// guardian-config-backup` before starting, requires the password the
+    /// backup is encrypted with. The consensus history is synced from the
+    /// peers afterwards.
+    #[arg(long, env = FM_RESTORE_FROM_BACKUP_ENV)]
+    restore_from_backup: Option<PathBuf>,
+
+    /// Set up the federation according to a TOML file, or JSON if the file
+    /// ends in `.json`, listing our name, the leader, all peers, meta and
+    /// module params instead of waiting for the guardian to use the setup UI.
+    /// Requires the password, which becomes the guardian password.
+    #[arg(long, env = FM_SETUP_FILE_ENV)]
+    setup_file: Option<PathBuf>,
+
+    /// Reshare the keys of our current federation to the guardians set up in
+    /// the following config gen, which has to include a threshold of the
+    /// current guardians. Requires the password of our current config.
+    #[arg(long, env = "FM_RESHARE")]
+    reshare: bool,
+
+    /// Verify the signed DKG transcripts of one or more guardians against the
+    /// consensus config in the data dir and exit instead of starting the
+    /// server. The transcript of every guardian is written to the data dir
+    /// as `dkg-transcript.json` after config gen.
+    #[arg(long)]
+    verify_dkg_transcript: Vec<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum ServerCommand {
+    /// Validate the config file passed with `--config` together with the
+    /// flags and env vars and exit, reporting keys of the file that do not
+    /// correspond to any option
+    CheckConfig,
+}
+
+impl ServerOpts {
+    /// Sets the options that were neither passed as flag nor as env var to
+    /// their value in the config file
+    fn apply_config_file(&mut self, file: &ServerConfigFile, matches: &ArgMatches) {
+        // options with a default value are only overridden if the default was used
+        fn set<T: Clone>(option: &mut T, value: &Option<T>, from_file: bool) {
+            if let Some(value) = value.as_ref().filter(|_| from_file) {
+                *option = value.clone();
+            }
+        }
+
+        fn set_opt<T: Clone>(option: &mut Option<T>, value: &Option<T>) {
+            if option.is_none() {
+                *option = value.clone();
+            }
+        }
+
+        let from_file = |id: &str| {
+            !matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+
+        set_opt(&mut self.data_dir, &file.data_dir);
+        set_opt(&mut self.password, &file.password);
+        set_opt(
+            &mut self.tokio_console_bind,
+            &file.logging.tokio_console_bind,
+        );
+        set(
+            &mut self.with_telemetry,
+            &file.logging.with_telemetry,
+            from_file("with_telemetry"),
+        );
+        set_opt(&mut self.log_level, &file.logging.level);
+        set(&mut self.bind_p2p, &file.bind_p2p, from_file("bind_p2p"));
+        set(&mut self.p2p_url, &file.p2p_url, from_file("p2p_url"));
+        set(&mut self.bind_api, &file.bind_api, from_file("bind_api"));
+        set(&mut self.api_url, &file.api_url, from_file("api_url"));
+        set(&mut self.network, &file.network, from_file("network"));
+        set(
+            &mut self.finality_delay,
+            &file.finality_delay,
+            from_file("finality_delay"),
+        );
+        set_opt(&mut self.bitcoin_rpc_kind, &file.bitcoin_rpc.kind);
+        set_opt(&mut self.bitcoin_rpc_url, &file.bitcoin_rpc.url);
+        set_opt(&mut self.bind_metrics_api, &file.bind_metrics_api);
+        set(
+            &mut self.extra_dkg_meta,
+            &file.extra_dkg_meta,
+            from_file("extra_dkg_meta"),
+        );
+        set_opt(&mut self.setup_file, &file.setup_file);
+    }
+
+    fn data_dir(&self) -> anyhow::Result<&Path> {
+        self.data_dir
+            .as_deref()
+            .context("The data dir has to be set with --data-dir, FM_DATA_DIR or the config file")
+    }
+
+    fn bitcoin_rpc(&self) -> anyhow::Result<BitcoinRpcConfig> {
+        Ok(BitcoinRpcConfig {
+            kind: self.bitcoin_rpc_kind.clone().with_context(|| {
+                format!("The bitcoin RPC kind has to be set with --bitcoin-rpc-kind, {FM_BITCOIN_RPC_KIND} or the config file")
+            })?,
+            url: self.bitcoin_rpc_url.clone().with_context(|| {
+                format!("The bitcoin RPC URL has to be set with --bitcoin-rpc-url, {FM_BITCOIN_RPC_URL} or the config file")
+            })?,
+        })
+    }
+
+    /// Checks that all required options are set and that the config file does
+    /// not contain unknown keys
+    fn check(&self, unknown_keys: &[String]) -> anyhow::Result<()> {
+        ensure!(
+            self.config.is_some(),
+            "No config file given, pass it with --config or FM_CONFIG_FILE"
+        );
+
+        for key in unknown_keys {
+            error!(%key, "Unknown key in config file");
+        }
+        ensure!(
+            unknown_keys.is_empty(),
+            "Config file contains {} unknown keys",
+            unknown_keys.len()
+        );
+
+        self.data_dir()?;
+        self.bitcoin_rpc()?;
+        self.check_password()?;
+
+        Ok(())
+    }
+
+    /// The password can come from a flag, env var or the config file, so clap
+    /// cannot require it for the options that need it
+    fn check_password(&self) -> anyhow::Result<()> {
+        let requires_password = [
+            (self.restore_from_backup.is_some(), "--restore-from-backup"),
+            (self.setup_file.is_some(), "--setup-file"),
+            (self.reshare, "--reshare"),
+        ];
+
+        for (is_set, option) in requires_password {
+            ensure!(
+                !is_set || self.password.is_some(),
+                "{option} requires the password to be set with --password, FM_PASSWORD or the config file"
+            );
+        }
+
+        Ok(())
+    }
 }
 
 fn parse_map(s: &str) -> anyhow::Result<BTreeMap<String, String>> {
@@ -198,13 +390,81 @@ impl Fedimintd {
 
     /// Block thread and run a Fedimintd server
     pub async fn run(self) -> ! {
-        let opts: ServerOpts = ServerOpts::parse();
-        TracingSetup::default()
+        let matches = ServerOpts::command().get_matches();
+        let mut opts = ServerOpts::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+        let config_file = opts
+            .config
+            .as_deref()
+            .map(ServerConfigFile::read)
+            .transpose();
+        if let Ok(Some(config_file)) = &config_file {
+            opts.apply_config_file(config_file, &matches);
+        }
+
+        let mut tracing_setup = TracingSetup::default();
+        if let Some(level) = &opts.log_level {
+            tracing_setup.with_base_level(level);
+        }
+        tracing_setup
             .tokio_console_bind(opts.tokio_console_bind)
             .with_jaeger(opts.with_telemetry)
             .init()
             .unwrap();
 
+        let unknown_keys = match config_file {
+            Ok(config_file) => config_file
+                .as_ref()
+                .map(ServerConfigFile::unknown_keys)
+                .unwrap_or_default(),
+            Err(error) => {
+                error!(?error, "Could not read config file");
+                std::process::exit(1);
+            }
+        };
+
+        if let Some(ServerCommand::CheckConfig) = opts.command {
+            match opts.check(&unknown_keys) {
+                Ok(()) => {
+                    info!("Config is valid");
+                    std::process::exit(0);
+                }
+                Err(error) => {
+                    error!(?error, "Config is invalid");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        for key in unknown_keys {
+            warn!(%key, "Ignoring unknown key in config file");
+        }
+
+        if !opts.verify_dkg_transcript.is_empty() {
+            let data_dir = match opts.data_dir() {
+                Ok(data_dir) => data_dir,
+                Err(error) => {
+                    error!(?error, "DKG transcript verification failed");
+                    std::process::exit(1);
+                }
+            };
+
+            match verify_dkg_transcript_files(
+                data_dir,
+                &self.server_gens,
+                &opts.verify_dkg_transcript,
+            ) {
+                Ok(()) => {
+                    info!("DKG transcripts match the consensus config");
+                    std::process::exit(0);
+                }
+                Err(error) => {
+                    error!(?error, "DKG transcript verification failed");
+                    std::process::exit(1);
+                }
+            }
+        }
+
         let root_task_group = TaskGroup::new();
         root_task_group.install_kill_handler();
 
@@ -271,6 +531,24 @@ impl Fedimintd {
     }
 }
 
+fn verify_dkg_transcript_files(
+    data_dir: &Path,
+    module_inits: &ServerModuleInitRegistry,
+    paths: &[PathBuf],
+) -> anyhow::Result<()> {
+    let consensus = read_consensus_config(data_dir)?;
+    let transcripts = paths
+        .iter()
+        .map(|path| {
+            let transcript = fs::read_to_string(path)
+                .with_context(|| format!("Could not read transcript {}", path.display()))?;
+            Ok(serde_json::from_str(&transcript)?)
+        })
+        .collect::<anyhow::Result<Vec<SignedDkgTranscript>>>()?;
+
+    verify_dkg_transcripts(&consensus, module_inits, &transcripts)
+}
+
 async fn run(
     opts: ServerOpts,
     task_group: TaskGroup,
@@ -278,8 +556,11 @@ async fn run(
     mut module_inits_params: ServerModuleConfigGenParamsRegistry,
     version_hash: String,
 ) -> anyhow::Result<()> {
+    let data_dir = opts.data_dir()?.to_owned();
+    opts.check_password()?;
+
     attach_default_module_init_params(
-        BitcoinRpcConfig::from_env_vars()?,
+        opts.bitcoin_rpc()?,
         &mut module_inits_params,
         opts.network,
         opts.finality_delay,
@@ -293,22 +574,58 @@ async fn run(
         .map(|(id, kind, _)| (id, kind));
     let decoders = module_inits.available_decoders(module_kinds.into_iter())?;
     let db = Database::new(
-        fedimint_rocksdb::RocksDb::open(opts.data_dir.join(DB_FILE))?,
+        fedimint_rocksdb::RocksDb::open(data_dir.join(DB_FILE))?,
         decoders.clone(),
     );
 
+    if let Some(backup_path) = &opts.restore_from_backup {
+        let password = opts
+            .password
+            .as_deref()
+            .context("A password is required to restore from a backup")?;
+        let backup: GuardianConfigBackup = serde_json::from_str(
+            &fs::read_to_string(backup_path)
+                .with_context(|| format!("Could not read backup {}", backup_path.display()))?,
+        )?;
+
+        restore_guardian_config_backup(&backup, password, &data_dir, &module_inits).await?;
+    }
+
+    let previous_config = if opts.reshare {
+        let password = opts
+            .password
+            .as_deref()
+            .context("A password is required to reshare the keys")?;
+
+        Some(prepare_reshare(&data_dir, password, &module_inits, &db).await?)
+    } else {
+        None
+    };
+
     // TODO: Fedimintd should use the config gen API
     // on each run we want to pass the currently passed password, so we need to
     // overwrite
     if let Some(password) = opts.password {
-        write_overwrite(opts.data_dir.join(PLAINTEXT_PASSWORD), password)?;
+        // Writing an outdated password would lock us out of the config after the
+        // password was changed through the admin API
+        check_config_password(&password, &data_dir).context(
+            "Wrong password, after changing the guardian password --password, FM_PASSWORD or \
+             the config file have to be updated as well",
+        )?;
+        write_overwrite(data_dir.join(PLAINTEXT_PASSWORD), password)?;
     };
+    let setup = opts
+        .setup_file
+        .as_deref()
+        .map(HeadlessSetup::read)
+        .transpose()?;
+
     let default_params = ConfigGenParamsRequest {
         meta: opts.extra_dkg_meta.clone(),
         modules: module_inits_params,
     };
     let mut api = FedimintServer {
-        data_dir: opts.data_dir,
+        data_dir,
         settings: ConfigGenSettings {
             download_token_limit: None,
             p2p_bind: opts.bind_p2p,
@@ -318,17 +635,20 @@ async fn run(
             default_params,
             max_connections: fedimint_server::config::max_connections(),
             registry: module_inits,
+            previous_config,
         },
+        health: ServerHealth::new(db.clone()),
         db,
+        setup,
         version_hash,
     };
     if let Some(bind_metrics_api) = opts.bind_metrics_api.as_ref() {
-        let (api_result, metrics_api_result) = futures::join!(
+        let health = Arc::new(api.health.clone());
+        // Fails as soon as either does, the metrics server never stops on its own
+        futures::try_join!(
             api.run(task_group.clone()),
-            spawn_metrics_server(bind_metrics_api, task_group)
-        );
-        api_result?;
-        metrics_api_result?;
+            spawn_metrics_server(bind_metrics_api, health, task_group)
+        )?;
     } else {
         api.run(task_group).await?;
     }
@@ -337,10 +657,140 @@ async fn run(
 
 async fn spawn_metrics_server(
     bind_address: &SocketAddr,
+    health: DynHealthChecks,
     mut task_group: TaskGroup,
 ) -> anyhow::Result<()> {
-    let rx = fedimint_metrics::run_api_server(bind_address, &mut task_group).await?;
+    let rx =
+        fedimint_metrics::run_api_server_with_health(bind_address, &mut task_group, Some(health))
+            .await?;
     info!("Metrics API listening on {bind_address}");
     rx.await;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Mutex, PoisonError};
+
+    use clap::{CommandFactory, FromArgMatches};
+
+    use super::ServerOpts;
+    use crate::config_file::ServerConfigFile;
+
+    /// Parsing reads the `FM_*` env vars of the process, tests hold this lock
+    /// while they replace them with their own
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    const CONFIG_FILE: &str = r#"
+        data_dir = "data"
+        bind_api = "0.0.0.0:9174"
+        bind_p2p = "0.0.0.0:9173"
+        p2p_url = "fedimint://file.example.com:9173"
+        finality_delay = 5
+
+        [bitcoin_rpc]
+        kind = "esplora"
+        url = "http://file.example.com:3002"
+    "#;
+
+    /// Parses `args` with only the given env vars of the options set and
+    /// applies the config file with `content`
+    fn opts_with_config_file(args: &[&str], env: &[(&str, &str)], content: &str) -> ServerOpts {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+
+        let _guard = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let saved_env = ServerOpts::command()
+            .get_arguments()
+            .filter_map(|arg| arg.get_env())
+            .map(|name| (name.to_owned(), std::env::var_os(name)))
+            .collect::<Vec<_>>();
+        for (name, _) in &saved_env {
+            std::env::remove_var(name);
+        }
+        for (name, value) in env {
+            std::env::set_var(name, value);
+        }
+
+        let matches = ServerOpts::command().try_get_matches_from(["fedimintd"].iter().chain(args));
+
+        for (name, value) in saved_env {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+
+        let matches = matches.unwrap();
+        let mut opts = ServerOpts::from_arg_matches(&matches).unwrap();
+        opts.apply_config_file(&ServerConfigFile::read(file.path()).unwrap(), &matches);
+        opts
+    }
+
+    #[test]
+    fn flags_take_precedence_over_config_file() {
+        let opts = opts_with_config_file(
+            &[
+                "--bind-api",
+                "127.0.0.1:1000",
+                "--bitcoin-rpc-kind",
+                "bitcoind",
+            ],
+            &[],
+            CONFIG_FILE,
+        );
+
+        assert_eq!(opts.bind_api, "127.0.0.1:1000".parse().unwrap());
+        assert_eq!(opts.bitcoin_rpc_kind.as_deref(), Some("bitcoind"));
+        assert_eq!(opts.bind_p2p, "0.0.0.0:9173".parse().unwrap());
+        assert_eq!(opts.finality_delay, 5);
+        assert_eq!(
+            opts.bitcoin_rpc_url,
+            Some("http://file.example.com:3002".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn env_vars_take_precedence_over_config_file() {
+        let opts = opts_with_config_file(
+            &[],
+            &[("FM_P2P_URL", "fedimint://env.example.com:1000")],
+            CONFIG_FILE,
+        );
+
+        assert_eq!(
+            opts.p2p_url,
+            "fedimint://env.example.com:1000".parse().unwrap()
+        );
+        assert_eq!(opts.bind_api, "0.0.0.0:9174".parse().unwrap());
+    }
+
+    #[test]
+    fn defaults_apply_without_config_file_value() {
+        let opts = opts_with_config_file(&[], &[], "");
+
+        assert_eq!(opts.bind_api, "127.0.0.1:8174".parse().unwrap());
+        assert_eq!(opts.finality_delay, 10);
+    }
+
+    #[test]
+    fn password_required_from_any_source() {
+        let opts = opts_with_config_file(&["--reshare"], &[], CONFIG_FILE);
+        assert!(opts.check_password().is_err());
+
+        let opts = opts_with_config_file(&["--reshare", "--password", "pass"], &[], CONFIG_FILE);
+        assert!(opts.check_password().is_ok());
+
+        let opts = opts_with_config_file(&["--reshare"], &[("FM_PASSWORD", "pass")], CONFIG_FILE);
+        assert!(opts.check_password().is_ok());
+
+        let opts = opts_with_config_file(
+            &["--setup-file", "setup.json"],
+            &[],
+            &format!("password = \"pass\"\n{CONFIG_FILE}"),
+        );
+        assert!(opts.check_password().is_ok());
+    }
+}
@@ -22,6 +22,9 @@ use fedimint_wallet_server::common::config::{
 };
 use fedimint_wallet_server::WalletInit;
 
+/// TOML config file with the options of `fedimintd`
+pub mod config_file;
+
 /// Module for creating `fedimintd` binary with custom modules
 pub mod fedimintd;
 
@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::time::SystemTime;
 
 use bitcoin::Network;
 use bitcoin_hashes::sha256;
@@ -21,6 +22,7 @@ pub enum DbKeyPrefix {
     GatewayPublicKey = 0x06,
     GatewayConfiguration = 0x07,
     PreimageAuthentication = 0x08,
+    HealthCheck = 0x09,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -101,6 +103,17 @@ impl_db_lookup!(
     query_prefix = PreimageAuthenticationPrefix
 );
 
+/// Written by the database health check to make sure the database accepts
+/// writes, holds the time of the last check
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable)]
+pub struct HealthCheckKey;
+
+impl_db_record!(
+    key = HealthCheckKey,
+    value = SystemTime,
+    db_prefix = DbKeyPrefix::HealthCheck,
+);
+
 pub fn get_gatewayd_database_migrations() -> BTreeMap<DatabaseVersion, ServerMigrationFn> {
     BTreeMap::new()
 }
@@ -231,6 +244,8 @@ mod fedimint_migration_tests {
                             ensure!(gateway_configuration.is_some(), "validate_migrations was not able to read GatewayConfiguration");
                             info!("Validated GatewayConfiguration");
                         }
+                        // Only records the time of the last health check
+                        DbKeyPrefix::HealthCheck => {}
                     }
                 }
                 Ok(())
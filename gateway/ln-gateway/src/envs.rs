@@ -7,3 +7,4 @@ pub const FM_GATEWAY_PASSWORD_ENV: &str = "FM_GATEWAY_PASSWORD";
 pub const FM_GATEWAY_NETWORK_ENV: &str = "FM_GATEWAY_NETWORK";
 pub const FM_GATEWAY_FEES_ENV: &str = "FM_GATEWAY_FEES";
 pub const FM_NUMBER_OF_ROUTE_HINTS_ENV: &str = "FM_NUMBER_OF_ROUTE_HINTS";
+pub const FM_GATEWAY_BIND_METRICS_API_ENV: &str = "FM_GATEWAY_BIND_METRICS_API";
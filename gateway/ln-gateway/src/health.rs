@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use fedimint_core::health::{check_database_writable, HealthReport, HealthStatus, IHealthChecks};
+use fedimint_core::task::timeout;
+use serde_json::json;
+
+use crate::db::HealthCheckKey;
+use crate::{Gateway, GatewayState};
+
+/// How long the lightning node may take to answer the health check
+const LIGHTNING_HEALTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl Gateway {
+    fn check_state(state: &GatewayState) -> HealthStatus {
+        HealthStatus::from_bool(
+            matches!(state, GatewayState::Running { .. }),
+            json!({ "state": state.to_string() }),
+        )
+    }
+
+    async fn check_lightning(state: &GatewayState) -> HealthStatus {
+        let GatewayState::Running { lightning_context } = state else {
+            return HealthStatus::unhealthy(json!({ "error": "Not connected to lightning node" }));
+        };
+
+        match timeout(LIGHTNING_HEALTH_TIMEOUT, lightning_context.lnrpc.info()).await {
+            Ok(Ok(info)) => HealthStatus::healthy(json!({
+                "alias": info.alias,
+                "network": info.network,
+            })),
+            Ok(Err(e)) => HealthStatus::unhealthy(json!({ "error": e.to_string() })),
+            Err(_) => HealthStatus::unhealthy(json!({ "error": "Lightning node timed out" })),
+        }
+    }
+}
+
+#[async_trait]
+impl IHealthChecks for Gateway {
+    async fn health_checks(&self) -> HealthReport {
+        let state = self.state.read().await.clone();
+
+        HealthReport::from([
+            ("state".to_string(), Self::check_state(&state)),
+            ("lightning".to_string(), Self::check_lightning(&state).await),
+            (
+                "database".to_string(),
+                check_database_writable(&self.gateway_db, &HealthCheckKey).await,
+            ),
+        ])
+    }
+
+    async fn health_check(&self, name: &str) -> Option<HealthStatus> {
+        let state = self.state.read().await.clone();
+
+        match name {
+            "state" => Some(Self::check_state(&state)),
+            "lightning" => Some(Self::check_lightning(&state).await),
+            "database" => Some(check_database_writable(&self.gateway_db, &HealthCheckKey).await),
+            _ => None,
+        }
+    }
+}
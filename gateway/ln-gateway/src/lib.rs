@@ -1,6 +1,7 @@
 pub mod client;
 mod db;
 pub mod envs;
+mod health;
 pub mod lightning;
 pub mod rpc;
 pub mod state_machine;
@@ -44,6 +45,7 @@ use fedimint_core::db::{
     apply_migrations_server, Database, DatabaseTransaction, IDatabaseTransactionOpsCoreTyped,
 };
 use fedimint_core::fmt_utils::OptStacktrace;
+use fedimint_core::health::DynHealthChecks;
 use fedimint_core::module::CommonModuleInit;
 use fedimint_core::task::{sleep, RwLock, TaskGroup, TaskHandle, TaskShutdownToken};
 use fedimint_core::time::now;
@@ -155,6 +157,11 @@ struct GatewayOpts {
         default_value_t = DEFAULT_NUM_ROUTE_HINTS
     )]
     pub num_route_hints: u32,
+
+    /// Address to serve the prometheus metrics and the unauthenticated health
+    /// endpoints on
+    #[arg(long = "bind-metrics-api", env = envs::FM_GATEWAY_BIND_METRICS_API_ENV)]
+    pub bind_metrics_api: Option<SocketAddr>,
 }
 
 impl GatewayOpts {
@@ -172,6 +179,7 @@ impl GatewayOpts {
             network: self.network,
             num_route_hints: self.num_route_hints,
             fees: self.fees.clone(),
+            bind_metrics_api: self.bind_metrics_api,
         })
     }
 }
@@ -190,6 +198,7 @@ pub struct GatewayParameters {
     network: Option<Network>,
     num_route_hints: u32,
     fees: Option<GatewayFee>,
+    bind_metrics_api: Option<SocketAddr>,
 }
 
 #[cfg_attr(doc, aquamarine::aquamarine)]
@@ -321,6 +330,7 @@ impl Gateway {
                 num_route_hints,
                 fees: Some(GatewayFee(fees)),
                 network,
+                bind_metrics_api: None,
             },
             gateway_db,
             client_builder,
@@ -458,6 +468,12 @@ impl Gateway {
         self.start_gateway(tg).await?;
         // start webserver last to avoid handling requests before fully initialized
         self.start_webserver(tg).await;
+        if let Some(bind_metrics_api) = self.gateway_parameters.bind_metrics_api {
+            let health: DynHealthChecks = Arc::new(self.clone());
+            fedimint_metrics::run_api_server_with_health(&bind_metrics_api, tg, Some(health))
+                .await?;
+            info!("Metrics API listening on {bind_metrics_api}");
+        }
         let handle = tg.make_handle();
         let shutdown_receiver = handle.make_shutdown_rx().await;
         Ok(shutdown_receiver)
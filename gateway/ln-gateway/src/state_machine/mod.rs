@@ -212,7 +212,7 @@ impl ClientModule for GatewayClientModule {
 
         Some(TransactionItemAmount {
             amount: input.amount,
-            fee: self.cfg.fee_consensus.contract_input,
+            fee: self.cfg.fee_consensus.contract_input_fee(input.amount),
         })
     }
 
@@ -225,7 +225,10 @@ impl ClientModule for GatewayClientModule {
         let amt = match output {
             LightningOutputV0::Contract(account_output) => TransactionItemAmount {
                 amount: account_output.amount,
-                fee: self.cfg.fee_consensus.contract_output,
+                fee: self
+                    .cfg
+                    .fee_consensus
+                    .contract_output_fee(account_output.amount),
             },
             LightningOutputV0::Offer(_) | LightningOutputV0::CancelOutgoing { .. } => {
                 TransactionItemAmount {
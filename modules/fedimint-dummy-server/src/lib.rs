@@ -14,7 +14,8 @@ use fedimint_core::db::{
 use fedimint_core::module::audit::Audit;
 use fedimint_core::module::{
     ApiEndpoint, CoreConsensusVersion, InputMeta, ModuleConsensusVersion, ModuleInit, PeerHandle,
-    ServerModuleInit, ServerModuleInitArgs, SupportedModuleApiVersions, TransactionItemAmount,
+    PreviousModuleConfig, ServerModuleInit, ServerModuleInitArgs, SupportedModuleApiVersions,
+    TransactionItemAmount,
 };
 use fedimint_core::server::DynServerModule;
 use fedimint_core::{push_db_pair_items, Amount, OutPoint, PeerId, ServerModule};
@@ -152,6 +153,24 @@ impl ServerModuleInit for DummyInit {
         .to_erased())
     }
 
+    async fn distributed_reshare(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+        previous: &PreviousModuleConfig,
+    ) -> DkgResult<ServerModuleConfig> {
+        let previous_consensus = DummyConfigConsensus::from_erased(&previous.consensus)?;
+        let mut config = self
+            .distributed_gen(peers, params)
+            .await?
+            .to_typed::<DummyConfig>()?;
+
+        // The dummy module has no keys, only the fee is carried over
+        config.consensus.tx_fee = previous_consensus.tx_fee;
+
+        Ok(config.to_erased())
+    }
+
     /// Converts the consensus config into the client config
     fn get_client_config(
         &self,
@@ -191,6 +210,7 @@ impl ServerModule for Dummy {
     /// Define the consensus types
     type Common = DummyModuleTypes;
     type Init = DummyInit;
+    type VerifiedInput = ();
 
     async fn consensus_proposal(
         &self,
@@ -249,6 +269,14 @@ impl ServerModule for Dummy {
         })
     }
 
+    fn input_to_json(&self, input: &DummyInput) -> Option<serde_json::Value> {
+        Some(serde_json::to_value(input).expect("serialization can't fail"))
+    }
+
+    fn output_to_json(&self, output: &DummyOutput) -> Option<serde_json::Value> {
+        Some(serde_json::to_value(output).expect("serialization can't fail"))
+    }
+
     async fn process_output<'a, 'b>(
         &'a self,
         dbtx: &mut DatabaseTransaction<'b>,
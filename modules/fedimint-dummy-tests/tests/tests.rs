@@ -6,14 +6,19 @@ use fedimint_core::config::ClientModuleConfig;
 use fedimint_core::core::{IntoDynInstance, ModuleKind, OperationId};
 use fedimint_core::db::mem_impl::MemDatabase;
 use fedimint_core::module::ModuleConsensusVersion;
+use fedimint_core::transaction::Transaction;
 use fedimint_core::{sats, Amount, OutPoint};
 use fedimint_dummy_client::states::DummyStateMachine;
 use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
-use fedimint_dummy_common::config::{DummyClientConfig, DummyGenParams};
-use fedimint_dummy_common::{broken_fed_key_pair, DummyInput, DummyOutput, KIND};
+use fedimint_dummy_common::config::{DummyClientConfig, DummyGenParams, DummyGenParamsConsensus};
+use fedimint_dummy_common::{broken_fed_key_pair, fed_key_pair, DummyInput, DummyOutput, KIND};
 use fedimint_dummy_server::DummyInit;
 use fedimint_testing::fixtures::Fixtures;
-use secp256k1::Secp256k1;
+use fedimint_testing::sim::SimulationConfig;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use secp256k1::{KeyPair, Secp256k1};
 
 fn fixtures() -> Fixtures {
     Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
@@ -39,6 +44,70 @@ async fn can_print_and_send_money() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn consensus_survives_simulated_faults() {
+    let tx_fee = sats(1);
+    let config = SimulationConfig::new(Fixtures::sim_seed(), 4, 6);
+    // with a fee every accepted transaction adds to the balance sheet of the
+    // federation
+    let params = DummyGenParams {
+        consensus: DummyGenParamsConsensus { tx_fee },
+        ..DummyGenParams::default()
+    };
+
+    Fixtures::new_primary(DummyClientInit, DummyInit, params)
+        .new_simulation(config)
+        .await
+        .with_transactions(dummy_transactions(tx_fee))
+        .run()
+        .await;
+}
+
+/// Creates transactions that either print money into one of a few accounts or
+/// move funds between them, which is rejected unless the account received
+/// enough funds before
+fn dummy_transactions(tx_fee: Amount) -> impl FnMut(&mut StdRng) -> Transaction + Send {
+    // instance id `Fixtures::new_primary` assigns to the dummy module
+    let module_id = 0;
+    let secp = Secp256k1::new();
+    let accounts = (1..=4)
+        .map(|byte| KeyPair::from_seckey_slice(&secp, &[byte; 32]).expect("32 bytes"))
+        .collect::<Vec<_>>();
+
+    move |rng| {
+        let amount = sats(rng.gen_range(1..100));
+        let payer = if rng.gen_bool(0.5) {
+            fed_key_pair()
+        } else {
+            *accounts.choose(rng).expect("not empty")
+        };
+        let payee = accounts.choose(rng).expect("not empty").public_key();
+
+        let input = ClientInput {
+            input: DummyInput {
+                // the fee is charged for the input and the output
+                amount: amount + tx_fee + tx_fee,
+                account: payer.public_key(),
+            },
+            keys: vec![payer],
+            state_machines: Arc::new(move |_, _| Vec::<DummyStateMachine>::new()),
+        };
+        let output = ClientOutput {
+            output: DummyOutput {
+                amount,
+                account: payee,
+            },
+            state_machines: Arc::new(move |_, _| Vec::<DummyStateMachine>::new()),
+        };
+
+        TransactionBuilder::new()
+            .with_input(input.into_dyn(module_id))
+            .with_output(output.into_dyn(module_id))
+            .build(&secp, rng)
+            .0
+    }
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn client_ignores_unknown_module() {
     let fed = fixtures().new_fed().await;
@@ -21,7 +21,7 @@ use fedimint_client::module::{ClientContext, ClientModule, IClientModule};
 use fedimint_client::oplog::UpdateStreamOrOutcome;
 use fedimint_client::sm::util::MapStateTransitions;
 use fedimint_client::sm::{DynState, ModuleNotifier, State, StateTransition};
-use fedimint_client::transaction::{ClientOutput, TransactionBuilder};
+use fedimint_client::transaction::{ClientOutput, TransactionBuilder, TransactionPreview};
 use fedimint_client::{sm_enum_variant_translation, DynGlobalClientContext};
 use fedimint_core::api::DynModuleApi;
 use fedimint_core::config::{FederationId, META_OVERRIDE_URL_KEY, META_VETTED_GATEWAYS_KEY};
@@ -360,7 +360,7 @@ impl ClientModule for LightningClientModule {
 
         Some(TransactionItemAmount {
             amount: input.amount,
-            fee: self.cfg.fee_consensus.contract_input,
+            fee: self.cfg.fee_consensus.contract_input_fee(input.amount),
         })
     }
 
@@ -373,7 +373,10 @@ impl ClientModule for LightningClientModule {
         let amt = match output {
             LightningOutputV0::Contract(account_output) => TransactionItemAmount {
                 amount: account_output.amount,
-                fee: self.cfg.fee_consensus.contract_output,
+                fee: self
+                    .cfg
+                    .fee_consensus
+                    .contract_output_fee(account_output.amount),
             },
             LightningOutputV0::Offer(_) | LightningOutputV0::CancelOutgoing { .. } => {
                 TransactionItemAmount {
@@ -1120,39 +1123,10 @@ impl LightningClientModule {
         )
         .await;
 
-        let is_internal_payment = invoice_has_internal_payment_markers(
-            &invoice,
-            self.client_ctx.get_internal_payment_markers()?,
-        )
-        .await
-            || invoice_routes_back_to_federation(
-                &invoice,
-                self.fetch_registered_gateways()
-                    .await?
-                    .into_iter()
-                    .map(|gw| gw.info)
-                    .collect(),
-            )
-            .await;
-
-        let (pay_type, client_output, contract_id) = if is_internal_payment {
-            let (output, contract_id) = self
-                .create_incoming_output(operation_id, invoice.clone())
-                .await?;
-            (PayType::Internal(operation_id), output, contract_id)
-        } else {
-            let gateway = maybe_gateway.context("No LN gateway available")?;
-            let (output, contract_id) = self
-                .create_outgoing_output(
-                    operation_id,
-                    invoice.clone(),
-                    gateway,
-                    self.client_ctx.get_config().global.federation_id(),
-                    rand::rngs::OsRng,
-                )
-                .await?;
-            (PayType::Lightning(operation_id), output, contract_id)
-        };
+        let (pay_type, client_output, contract_id) = self
+            .create_pay_output(operation_id, maybe_gateway, invoice.clone())
+            .await?;
+        let is_internal_payment = matches!(pay_type, PayType::Internal(_));
 
         // Verify that no other outgoing contract exists or the value is empty
         if let Ok(Some(contract)) = self.module_api.fetch_contract(contract_id).await {
@@ -1161,23 +1135,7 @@ impl LightningClientModule {
             }
         }
 
-        // TODO: return fee from create_outgoing_output or even let user supply
-        // it/bounds for it
-        let fee = match &client_output.output {
-            LightningOutputV0::Contract(contract) => {
-                let fee_msat = contract
-                    .amount
-                    .msats
-                    .checked_sub(
-                        invoice
-                            .amount_milli_satoshis()
-                            .ok_or(anyhow::anyhow!("MissingInvoiceAmount"))?,
-                    )
-                    .expect("Contract amount should be greater or equal than invoice amount");
-                Amount::from_msats(fee_msat)
-            }
-            _ => unreachable!("User client will only create contract outputs on spend"),
-        };
+        let fee = Self::pay_output_gateway_fee(&client_output.output, &invoice)?;
 
         let output = self.client_ctx.make_client_output(ClientOutput {
             output: LightningOutput::V0(client_output.output),
@@ -1219,6 +1177,102 @@ impl LightningClientModule {
         })
     }
 
+    /// Returns the gateway fee and the amounts and fees of the transaction
+    /// [`Self::pay_bolt11_invoice`] would submit without submitting it.
+    pub async fn preview_pay_bolt11_invoice(
+        &self,
+        maybe_gateway: Option<LightningGateway>,
+        invoice: Bolt11Invoice,
+    ) -> anyhow::Result<LightningPayPreview> {
+        let (pay_type, client_output, _) = self
+            .create_pay_output(OperationId::new_random(), maybe_gateway, invoice.clone())
+            .await?;
+        let gateway_fee = Self::pay_output_gateway_fee(&client_output.output, &invoice)?;
+
+        let output = self.client_ctx.make_client_output(ClientOutput {
+            output: LightningOutput::V0(client_output.output),
+            state_machines: client_output.state_machines,
+        });
+        let transaction = self
+            .client_ctx
+            .preview_transaction(TransactionBuilder::new().with_output(output))
+            .await?;
+
+        Ok(LightningPayPreview {
+            is_internal_payment: matches!(pay_type, PayType::Internal(_)),
+            gateway_fee,
+            transaction,
+        })
+    }
+
+    /// Creates the output paying `invoice`, internally if it belongs to the
+    /// federation and otherwise via `maybe_gateway`
+    async fn create_pay_output(
+        &self,
+        operation_id: OperationId,
+        maybe_gateway: Option<LightningGateway>,
+        invoice: Bolt11Invoice,
+    ) -> anyhow::Result<(
+        PayType,
+        ClientOutput<LightningOutputV0, LightningClientStateMachines>,
+        ContractId,
+    )> {
+        let is_internal_payment = invoice_has_internal_payment_markers(
+            &invoice,
+            self.client_ctx.get_internal_payment_markers()?,
+        )
+        .await
+            || invoice_routes_back_to_federation(
+                &invoice,
+                self.fetch_registered_gateways()
+                    .await?
+                    .into_iter()
+                    .map(|gw| gw.info)
+                    .collect(),
+            )
+            .await;
+
+        if is_internal_payment {
+            let (output, contract_id) = self.create_incoming_output(operation_id, invoice).await?;
+            Ok((PayType::Internal(operation_id), output, contract_id))
+        } else {
+            let gateway = maybe_gateway.context("No LN gateway available")?;
+            let (output, contract_id) = self
+                .create_outgoing_output(
+                    operation_id,
+                    invoice,
+                    gateway,
+                    self.client_ctx.get_config().global.federation_id(),
+                    rand::rngs::OsRng,
+                )
+                .await?;
+            Ok((PayType::Lightning(operation_id), output, contract_id))
+        }
+    }
+
+    // TODO: return fee from create_outgoing_output or even let user supply
+    // it/bounds for it
+    fn pay_output_gateway_fee(
+        output: &LightningOutputV0,
+        invoice: &Bolt11Invoice,
+    ) -> anyhow::Result<Amount> {
+        match output {
+            LightningOutputV0::Contract(contract) => {
+                let fee_msat = contract
+                    .amount
+                    .msats
+                    .checked_sub(
+                        invoice
+                            .amount_milli_satoshis()
+                            .ok_or(anyhow::anyhow!("MissingInvoiceAmount"))?,
+                    )
+                    .expect("Contract amount should be greater or equal than invoice amount");
+                Ok(Amount::from_msats(fee_msat))
+            }
+            _ => unreachable!("User client will only create contract outputs on spend"),
+        }
+    }
+
     pub async fn get_ln_pay_details_for(
         &self,
         operation_id: OperationId,
@@ -1572,6 +1626,17 @@ pub struct OutgoingLightningPayment {
     pub fee: Amount,
 }
 
+/// Outcome of [`LightningClientModule::preview_pay_bolt11_invoice`]
+#[derive(Debug, Clone, Serialize)]
+pub struct LightningPayPreview {
+    pub is_internal_payment: bool,
+    /// Fee charged by the gateway, already included in the amount of the
+    /// contract output
+    pub gateway_fee: Amount,
+    /// Fees charged by the federation
+    pub transaction: TransactionPreview,
+}
+
 async fn set_payment_result(
     dbtx: &mut DatabaseTransaction<'_>,
     payment_hash: sha256::Hash,
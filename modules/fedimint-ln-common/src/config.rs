@@ -2,8 +2,11 @@ use std::str::FromStr;
 
 pub use bitcoin::Network;
 use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
+use fedimint_core::config::{decode_config_extension, encode_config_extension};
 use fedimint_core::core::ModuleKind;
-use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_core::module::fee::ProportionalFee;
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::{msats, plugin_types_trait_impl_config, Amount};
 use lightning_invoice::RoutingFees;
 use serde::{Deserialize, Serialize};
@@ -51,7 +54,7 @@ pub struct LightningConfigLocal {
     pub bitcoin_rpc: BitcoinRpcConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LightningConfigConsensus {
     /// The threshold public keys for encrypting the LN preimage
     pub threshold_pub_keys: threshold_crypto::PublicKeySet,
@@ -60,6 +63,35 @@ pub struct LightningConfigConsensus {
     pub network: Network,
 }
 
+impl Encodable for LightningConfigConsensus {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.threshold_pub_keys.consensus_encode(writer)?;
+        len += self.fee_consensus.consensus_encode_abs(writer)?;
+        len += self.network.consensus_encode(writer)?;
+        len += encode_config_extension(&self.fee_consensus.extension(), writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for LightningConfigConsensus {
+    fn consensus_decode<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let threshold_pub_keys = Decodable::consensus_decode(reader, modules)?;
+        let fee_consensus = FeeConsensus::consensus_decode_abs(reader, modules)?;
+        let network = Decodable::consensus_decode(reader, modules)?;
+        let fee_extension = decode_config_extension(reader, modules)?;
+
+        Ok(Self {
+            threshold_pub_keys,
+            fee_consensus: fee_consensus.with_extension(fee_extension),
+            network,
+        })
+    }
+}
+
 impl LightningConfigConsensus {
     /// The number of decryption shares required
     pub fn threshold(&self) -> usize {
@@ -72,15 +104,55 @@ pub struct LightningConfigPrivate {
     // TODO: propose serde(with = "…") based protection upstream instead
     /// Our secret key for decrypting preimages
     pub threshold_sec_key: SerdeSecret<threshold_crypto::SecretKeyShare>,
+    /// The scalar of `threshold_sec_key`, which `threshold_crypto` does not
+    /// expose but resharing the key requires. Configs generated before
+    /// resharing was introduced lack it, so their keys cannot be reshared.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold_sec_key_scalar: Option<ThresholdSecKeyScalar>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+/// See [`LightningConfigPrivate::threshold_sec_key_scalar`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdSecKeyScalar(
+    #[serde(with = "tbs::serde_impl::scalar")] pub threshold_crypto::Scalar,
+);
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct LightningClientConfig {
     pub threshold_pub_key: threshold_crypto::PublicKey,
     pub fee_consensus: FeeConsensus,
     pub network: Network,
 }
 
+impl Encodable for LightningClientConfig {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.threshold_pub_key.consensus_encode(writer)?;
+        len += self.fee_consensus.consensus_encode_abs(writer)?;
+        len += self.network.consensus_encode(writer)?;
+        len += encode_config_extension(&self.fee_consensus.extension(), writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for LightningClientConfig {
+    fn consensus_decode<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let threshold_pub_key = Decodable::consensus_decode(reader, modules)?;
+        let fee_consensus = FeeConsensus::consensus_decode_abs(reader, modules)?;
+        let network = Decodable::consensus_decode(reader, modules)?;
+        let fee_extension = decode_config_extension(reader, modules)?;
+
+        Ok(Self {
+            threshold_pub_key,
+            fee_consensus: fee_consensus.with_extension(fee_extension),
+            network,
+        })
+    }
+}
+
 impl std::fmt::Display for LightningClientConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -104,10 +176,83 @@ plugin_types_trait_impl_config!(
     LightningClientConfig
 );
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+/// The fees charged for funding and claiming contracts
+///
+/// Only the absolute fees are part of the encoding of the configs containing
+/// the fees as they were released, the proportional fees added later are
+/// appended as an extension, see [`encode_config_extension`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct FeeConsensus {
     pub contract_input: fedimint_core::Amount,
     pub contract_output: fedimint_core::Amount,
+    /// Charged per contract input in addition to `contract_input`
+    #[serde(default)]
+    pub contract_input_ppm: ProportionalFee,
+    /// Charged per contract output in addition to `contract_output`
+    #[serde(default)]
+    pub contract_output_ppm: ProportionalFee,
+}
+
+impl FeeConsensus {
+    /// The fee for claiming `amount` from a contract
+    pub fn contract_input_fee(&self, amount: Amount) -> Amount {
+        self.contract_input + self.contract_input_ppm.fee(amount)
+    }
+
+    /// The fee for funding a contract with `amount`
+    pub fn contract_output_fee(&self, amount: Amount) -> Amount {
+        self.contract_output + self.contract_output_ppm.fee(amount)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.contract_input_ppm.validate()?;
+        self.contract_output_ppm.validate()
+    }
+
+    fn consensus_encode_abs<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.contract_input.consensus_encode(writer)?;
+        len += self.contract_output.consensus_encode(writer)?;
+        Ok(len)
+    }
+
+    /// Decodes the absolute fees, the proportional fees are zero until the
+    /// extension is added with [`Self::with_extension`]
+    fn consensus_decode_abs<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        Ok(Self {
+            contract_input: Decodable::consensus_decode(reader, modules)?,
+            contract_output: Decodable::consensus_decode(reader, modules)?,
+            ..Self::default()
+        })
+    }
+
+    fn extension(&self) -> FeeConsensusExtension {
+        FeeConsensusExtension {
+            contract_input_ppm: self.contract_input_ppm,
+            contract_output_ppm: self.contract_output_ppm,
+        }
+    }
+
+    fn with_extension(self, extension: FeeConsensusExtension) -> Self {
+        Self {
+            contract_input_ppm: extension.contract_input_ppm,
+            contract_output_ppm: extension.contract_output_ppm,
+            ..self
+        }
+    }
+}
+
+/// The fields of [`FeeConsensus`] added after its encoding was released
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable)]
+struct FeeConsensusExtension {
+    contract_input_ppm: ProportionalFee,
+    contract_output_ppm: ProportionalFee,
 }
 
 impl Default for FeeConsensus {
@@ -115,6 +260,8 @@ impl Default for FeeConsensus {
         Self {
             contract_input: fedimint_core::Amount::ZERO,
             contract_output: fedimint_core::Amount::ZERO,
+            contract_input_ppm: ProportionalFee::ZERO,
+            contract_output_ppm: ProportionalFee::ZERO,
         }
     }
 }
@@ -173,3 +320,125 @@ impl FeeToAmount for GatewayFee {
         self.0.to_amount(payment)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::encoding::{Decodable, Encodable};
+    use fedimint_core::module::fee::ProportionalFee;
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+    use fedimint_core::Amount;
+
+    use super::{FeeConsensus, LightningClientConfig, LightningConfigConsensus, Network};
+
+    /// The encoding of [`FeeConsensus`] before proportional fees were added
+    #[derive(Encodable)]
+    struct FeeConsensusV0 {
+        contract_input: Amount,
+        contract_output: Amount,
+    }
+
+    /// The encoding of [`LightningClientConfig`] before proportional fees were
+    /// added
+    #[derive(Encodable)]
+    struct LightningClientConfigV0 {
+        threshold_pub_key: threshold_crypto::PublicKey,
+        fee_consensus: FeeConsensusV0,
+        network: Network,
+    }
+
+    /// The encoding of [`LightningConfigConsensus`] before proportional fees
+    /// were added
+    #[derive(Encodable)]
+    struct LightningConfigConsensusV0 {
+        threshold_pub_keys: threshold_crypto::PublicKeySet,
+        fee_consensus: FeeConsensusV0,
+        network: Network,
+    }
+
+    fn fee_consensus() -> FeeConsensus {
+        FeeConsensus {
+            contract_input: Amount::from_sats(1),
+            contract_output: Amount::from_sats(2),
+            ..FeeConsensus::default()
+        }
+    }
+
+    fn fee_consensus_v0() -> FeeConsensusV0 {
+        FeeConsensusV0 {
+            contract_input: Amount::from_sats(1),
+            contract_output: Amount::from_sats(2),
+        }
+    }
+
+    #[test]
+    fn configs_without_proportional_fees_keep_encoding() {
+        let threshold_pub_keys =
+            threshold_crypto::SecretKeySet::random(1, &mut rand::thread_rng()).public_keys();
+
+        let client_config = LightningClientConfig {
+            threshold_pub_key: threshold_pub_keys.public_key(),
+            fee_consensus: fee_consensus(),
+            network: Network::Regtest,
+        };
+        let client_bytes_v0 = LightningClientConfigV0 {
+            threshold_pub_key: threshold_pub_keys.public_key(),
+            fee_consensus: fee_consensus_v0(),
+            network: Network::Regtest,
+        }
+        .consensus_encode_to_vec();
+
+        assert_eq!(
+            LightningClientConfig::consensus_decode_vec(
+                client_bytes_v0.clone(),
+                &ModuleDecoderRegistry::default()
+            )
+            .expect("decodes the encoding of v0"),
+            client_config
+        );
+        assert_eq!(client_config.consensus_encode_to_vec(), client_bytes_v0);
+
+        let consensus_config = LightningConfigConsensus {
+            threshold_pub_keys: threshold_pub_keys.clone(),
+            fee_consensus: fee_consensus(),
+            network: Network::Regtest,
+        };
+        let consensus_bytes_v0 = LightningConfigConsensusV0 {
+            threshold_pub_keys,
+            fee_consensus: fee_consensus_v0(),
+            network: Network::Regtest,
+        }
+        .consensus_encode_to_vec();
+
+        let decoded = LightningConfigConsensus::consensus_decode_vec(
+            consensus_bytes_v0.clone(),
+            &ModuleDecoderRegistry::default(),
+        )
+        .expect("decodes the encoding of v0");
+        assert_eq!(decoded.fee_consensus, consensus_config.fee_consensus);
+        assert_eq!(
+            consensus_config.consensus_encode_to_vec(),
+            consensus_bytes_v0
+        );
+    }
+
+    #[test]
+    fn client_config_with_proportional_fees_roundtrips() {
+        let client_config = LightningClientConfig {
+            threshold_pub_key: threshold_crypto::SecretKey::random().public_key(),
+            fee_consensus: FeeConsensus {
+                contract_input_ppm: ProportionalFee::new(100, Amount::ZERO, None),
+                ..fee_consensus()
+            },
+            network: Network::Regtest,
+        };
+
+        assert_eq!(
+            LightningClientConfig::consensus_decode_vec(
+                client_config.consensus_encode_to_vec(),
+                &ModuleDecoderRegistry::default()
+            )
+            .expect("decodes"),
+            client_config
+        );
+    }
+}
@@ -1,16 +1,16 @@
 use std::collections::BTreeMap;
 use std::time::Duration;
 
-use anyhow::{bail, Context};
+use anyhow::{bail, format_err, Context};
 use bitcoin_hashes::{sha256, Hash as BitcoinHash};
 use fedimint_bitcoind::{create_bitcoind, DynBitcoindRpc};
 use fedimint_core::config::{
-    ConfigGenModuleParams, DkgResult, ServerModuleConfig, ServerModuleConsensusConfig,
-    TypedServerModuleConfig, TypedServerModuleConsensusConfig,
+    ConfigGenModuleParams, DkgPublicKeys, DkgResult, ServerModuleConfig,
+    ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
 };
 use fedimint_core::core::ModuleInstanceId;
 use fedimint_core::db::{
-    DatabaseTransaction, DatabaseValue, DatabaseVersion, IDatabaseTransactionOpsCoreTyped,
+    Database, DatabaseTransaction, DatabaseValue, DatabaseVersion, IDatabaseTransactionOpsCoreTyped,
 };
 use fedimint_core::encoding::Encodable;
 use fedimint_core::endpoint_constants::{
@@ -20,10 +20,11 @@ use fedimint_core::endpoint_constants::{
     REGISTER_GATEWAY_ENDPOINT, REMOVE_GATEWAY_CHALLENGE_ENDPOINT, REMOVE_GATEWAY_ENDPOINT,
 };
 use fedimint_core::module::audit::Audit;
+use fedimint_core::module::liabilities::ModuleLiabilities;
 use fedimint_core::module::{
     api_endpoint, ApiEndpoint, ApiEndpointContext, ApiVersion, CoreConsensusVersion, InputMeta,
-    ModuleConsensusVersion, ModuleInit, PeerHandle, ServerModuleInit, ServerModuleInitArgs,
-    SupportedModuleApiVersions, TransactionItemAmount,
+    ModuleConsensusVersion, ModuleInit, PeerHandle, PreviousModuleConfig, ServerModuleInit,
+    ServerModuleInitArgs, SupportedModuleApiVersions, TransactionItemAmount,
 };
 use fedimint_core::server::DynServerModule;
 use fedimint_core::task::{sleep, TaskGroup};
@@ -34,7 +35,7 @@ use fedimint_core::{
 use fedimint_ln_common::api::RemoveGatewayRequest;
 use fedimint_ln_common::config::{
     FeeConsensus, LightningClientConfig, LightningConfig, LightningConfigConsensus,
-    LightningConfigLocal, LightningConfigPrivate, LightningGenParams,
+    LightningConfigLocal, LightningConfigPrivate, LightningGenParams, ThresholdSecKeyScalar,
 };
 use fedimint_ln_common::contracts::incoming::{IncomingContractAccount, IncomingContractOffer};
 use fedimint_ln_common::contracts::{
@@ -60,7 +61,7 @@ use fedimint_metrics::{
     histogram_opts, lazy_static, opts, prometheus, register_histogram, register_int_counter,
     Histogram, IntCounter,
 };
-use fedimint_server::config::distributedgen::PeerHandleOps;
+use fedimint_server::config::distributedgen::{dkg_key, scalar, PeerHandleOps, PreviousKeys};
 use futures::StreamExt;
 use rand::rngs::OsRng;
 use secp256k1::PublicKey;
@@ -261,12 +262,11 @@ impl ServerModuleInit for LightningInit {
         for metric in ALL_METRICS.iter() {
             metric.collect();
         }
-        Ok(Lightning::new(
-            args.cfg().to_typed()?,
-            &mut args.task_group().clone(),
-            args.our_peer_id(),
-        )?
-        .into())
+
+        let cfg: LightningConfig = args.cfg().to_typed()?;
+        remove_stale_peer_state(args.db(), &cfg, args.our_peer_id()).await;
+
+        Ok(Lightning::new(cfg, &mut args.task_group().clone(), args.our_peer_id())?.into())
     }
 
     fn trusted_dealer_gen(
@@ -275,13 +275,14 @@ impl ServerModuleInit for LightningInit {
         params: &ConfigGenModuleParams,
     ) -> BTreeMap<PeerId, ServerModuleConfig> {
         let params = self.parse_params(params).unwrap();
-        let sks = threshold_crypto::SecretKeySet::random(peers.degree(), &mut OsRng);
-        let pks = sks.public_keys();
+        let poly = threshold_crypto::poly::Poly::random(peers.degree(), &mut OsRng);
+        let pks = threshold_crypto::SecretKeySet::from(poly.clone()).public_keys();
 
         let server_cfg = peers
             .iter()
             .map(|&peer| {
-                let sk = sks.secret_key_share(peer.to_usize());
+                let sk_scalar = poly.evaluate(scalar(&peer));
+                let sk = threshold_crypto::SecretKeyShare::from_mut(&mut sk_scalar.clone());
 
                 (
                     peer,
@@ -296,6 +297,7 @@ impl ServerModuleInit for LightningInit {
                         },
                         private: LightningConfigPrivate {
                             threshold_sec_key: threshold_crypto::serde_impl::SerdeSecret(sk),
+                            threshold_sec_key_scalar: Some(ThresholdSecKeyScalar(sk_scalar)),
                         },
                     }
                     .to_erased(),
@@ -327,6 +329,61 @@ impl ServerModuleInit for LightningInit {
             },
             private: LightningConfigPrivate {
                 threshold_sec_key: keys.secret_key_share,
+                threshold_sec_key_scalar: Some(ThresholdSecKeyScalar(keys.secret_key_scalar)),
+            },
+        };
+
+        Ok(server.to_erased())
+    }
+
+    async fn distributed_reshare(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+        previous: &PreviousModuleConfig,
+    ) -> DkgResult<ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        let previous_consensus = LightningConfigConsensus::from_erased(&previous.consensus)?;
+        let previous_sk_scalar = previous
+            .ours
+            .as_ref()
+            .map(|cfg| {
+                cfg.to_typed::<LightningConfig>()?
+                    .private
+                    .threshold_sec_key_scalar
+                    .ok_or_else(|| {
+                        format_err!("Our lightning config predates resharing and lacks the scalar of our key share")
+                    })
+            })
+            .transpose()?
+            .map(|scalar| scalar.0);
+
+        let previous_keys = PreviousKeys::threshold_crypto(
+            &previous_consensus.threshold_pub_keys,
+            previous.previous_peers.values().copied(),
+            previous_sk_scalar,
+        );
+
+        let g1 = peers
+            .run_reshare_g1((), &previous.previous_peers, previous_keys)
+            .await?;
+
+        // Resharing keeps the public key, so contracts encrypted to the
+        // previous federation can still be decrypted
+        let keys = g1[&()].threshold_crypto();
+
+        let server = LightningConfig {
+            local: LightningConfigLocal {
+                bitcoin_rpc: params.local.bitcoin_rpc.clone(),
+            },
+            consensus: LightningConfigConsensus {
+                threshold_pub_keys: keys.public_key_set,
+                fee_consensus: previous_consensus.fee_consensus,
+                network: previous_consensus.network,
+            },
+            private: LightningConfigPrivate {
+                threshold_sec_key: keys.secret_key_share,
+                threshold_sec_key_scalar: Some(ThresholdSecKeyScalar(keys.secret_key_scalar)),
             },
         };
 
@@ -343,6 +400,18 @@ impl ServerModuleInit for LightningInit {
         {
             bail!("Lightning private key doesn't match pubkey share");
         }
+
+        if let Some(sk_scalar) = config.private.threshold_sec_key_scalar {
+            let mut sk_scalar = sk_scalar.0;
+            if threshold_crypto::SecretKeyShare::from_mut(&mut sk_scalar)
+                != *config.private.threshold_sec_key
+            {
+                bail!("Lightning private key scalar doesn't match private key");
+            }
+        }
+
+        config.consensus.fee_consensus.validate()?;
+
         Ok(())
     }
 
@@ -357,7 +426,104 @@ impl ServerModuleInit for LightningInit {
             network: config.network,
         })
     }
+
+    fn dkg_public_keys(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<BTreeMap<String, DkgPublicKeys>> {
+        let config = LightningConfigConsensus::from_erased(config)?;
+
+        Ok(BTreeMap::from([(
+            dkg_key(&()),
+            DkgPublicKeys::G1Commitment(
+                config.threshold_pub_keys.coefficients().cloned().collect(),
+            ),
+        )]))
+    }
 }
+/// Removes the state keyed by peer id that is invalid after the keys were
+/// reshared to a new set of guardians, see
+/// [`ServerModuleInit::distributed_reshare`]
+///
+/// Decryption shares created with a previous key share are replaced, and block
+/// count votes of peers that are not part of the federation anymore are
+/// removed. This does nothing if the keys were never reshared.
+async fn remove_stale_peer_state(db: &Database, cfg: &LightningConfig, our_peer_id: PeerId) {
+    let mut dbtx = db.begin_transaction().await;
+    let threshold_pub_keys = &cfg.consensus.threshold_pub_keys;
+
+    let proposed_shares = dbtx
+        .find_by_prefix(&ProposeDecryptionShareKeyPrefix)
+        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    for (key, share) in proposed_shares {
+        let Some(ContractAccount {
+            contract: FundedContract::Incoming(contract),
+            ..
+        }) = dbtx.get_value(&ContractKey(key.0)).await
+        else {
+            continue;
+        };
+
+        let encrypted_preimage = &contract.contract.encrypted_preimage.0;
+
+        if !threshold_pub_keys
+            .public_key_share(our_peer_id.to_usize())
+            .verify_decryption_share(&share.0, encrypted_preimage)
+        {
+            let share = cfg
+                .private
+                .threshold_sec_key
+                .decrypt_share(encrypted_preimage)
+                .expect("We checked for decryption share validity on contract creation");
+
+            dbtx.insert_entry(&key, &PreimageDecryptionShare(share))
+                .await;
+        }
+    }
+
+    let agreed_shares = dbtx
+        .find_by_prefix(&AgreedDecryptionShareKeyPrefix)
+        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    for (key, share) in agreed_shares {
+        let valid = match dbtx.get_value(&ContractKey(key.0)).await {
+            Some(ContractAccount {
+                contract: FundedContract::Incoming(contract),
+                ..
+            }) => threshold_pub_keys
+                .public_key_share(key.1.to_usize())
+                .verify_decryption_share(&share.0, &contract.contract.encrypted_preimage.0),
+            _ => false,
+        };
+
+        if !valid {
+            dbtx.remove_entry(&key).await;
+        }
+    }
+
+    let peer_count = 3 * (cfg.consensus.threshold() / 2) + 1;
+
+    let votes = dbtx
+        .find_by_prefix(&BlockCountVotePrefix)
+        .await
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>()
+        .await;
+
+    for key in votes {
+        if key.0.to_usize() >= peer_count {
+            dbtx.remove_entry(&key).await;
+        }
+    }
+
+    dbtx.commit_tx().await;
+}
+
 /// The lightning module implements an account system. It does not have the
 /// privacy guarantees of the e-cash mint module but instead allows for smart
 /// contracting. There exist two contract types that can be used to "lock"
@@ -390,6 +556,7 @@ pub struct Lightning {
 impl ServerModule for Lightning {
     type Common = LightningModuleTypes;
     type Init = LightningInit;
+    type VerifiedInput = ();
 
     async fn consensus_proposal(
         &self,
@@ -645,12 +812,24 @@ impl ServerModule for Lightning {
         Ok(InputMeta {
             amount: TransactionItemAmount {
                 amount: input.amount,
-                fee: self.cfg.consensus.fee_consensus.contract_input,
+                fee: self
+                    .cfg
+                    .consensus
+                    .fee_consensus
+                    .contract_input_fee(input.amount),
             },
             pub_key,
         })
     }
 
+    fn input_to_json(&self, input: &LightningInput) -> Option<serde_json::Value> {
+        Some(serde_json::to_value(input).expect("serialization can't fail"))
+    }
+
+    fn output_to_json(&self, output: &LightningOutput) -> Option<serde_json::Value> {
+        Some(serde_json::to_value(output).expect("serialization can't fail"))
+    }
+
     async fn process_output<'a, 'b>(
         &'a self,
         dbtx: &mut DatabaseTransaction<'b>,
@@ -744,7 +923,11 @@ impl ServerModule for Lightning {
 
                 Ok(TransactionItemAmount {
                     amount: contract.amount,
-                    fee: self.cfg.consensus.fee_consensus.contract_output,
+                    fee: self
+                        .cfg
+                        .consensus
+                        .fee_consensus
+                        .contract_output_fee(contract.amount),
                 })
             }
             LightningOutputV0::Offer(offer) => {
@@ -864,6 +1047,20 @@ impl ServerModule for Lightning {
             .await;
     }
 
+    async fn liabilities(&self, dbtx: &mut DatabaseTransaction<'_>) -> ModuleLiabilities {
+        ModuleLiabilities {
+            liabilities: dbtx
+                .find_by_prefix(&LightningAuditItemKeyPrefix)
+                .await
+                .map(|(_, amount)| amount)
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .sum(),
+            reserves: vec![],
+        }
+    }
+
     fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
         vec![
             api_endpoint! {
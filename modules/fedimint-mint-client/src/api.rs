@@ -0,0 +1,23 @@
+use fedimint_core::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_core::endpoint_constants::KEY_EPOCH_ENDPOINT;
+use fedimint_core::module::ApiRequestErased;
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send};
+
+#[apply(async_trait_maybe_send!)]
+pub trait MintFederationApi {
+    /// The key epoch the federation agreed on, see
+    /// [`fedimint_mint_common::config::KeyEpochParams`]
+    async fn fetch_key_epoch(&self) -> FederationResult<u64>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T: ?Sized> MintFederationApi for T
+where
+    T: IModuleFederationApi + MaybeSend + MaybeSync + 'static,
+{
+    async fn fetch_key_epoch(&self) -> FederationResult<u64> {
+        self.request_current_consensus(KEY_EPOCH_ENDPOINT.to_string(), ApiRequestErased::default())
+            .await
+    }
+}
@@ -132,6 +132,10 @@ impl RecoveryFromHistory for MintRecovery {
         Ok(())
     }
 
+    fn found_items(&self) -> u64 {
+        self.state.found_notes()
+    }
+
     /// Handle session outcome, adjusting the current state
     async fn finalize_dbtx(
         &self,
@@ -367,6 +371,9 @@ impl MintRecoveryState {
                 self.pending_outputs.remove(&input.note.nonce);
                 self.spendable_notes.remove(&input.note.nonce);
             }
+            MintInput::V1(_) => {
+                // Locked notes are not derived from the note secrets we recover
+            }
             MintInput::Default { variant, .. } => {
                 trace!("Ignoring future mint input variant {variant}");
             }
@@ -471,6 +478,11 @@ impl MintRecoveryState {
         }
     }
 
+    /// Number of our notes found so far, whether already signed or not
+    pub fn found_notes(&self) -> u64 {
+        (self.spendable_notes.len() + self.pending_outputs.len()) as u64
+    }
+
     pub fn finalize(self) -> EcashRecoveryFinalState {
         EcashRecoveryFinalState {
             spendable_notes: self.spendable_notes.into_values().collect(),
@@ -1,13 +1,16 @@
+use std::time::SystemTime;
+
 use fedimint_client::module::init::recovery::RecoveryFromHistoryCommon;
 use fedimint_core::core::OperationId;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{impl_db_lookup, impl_db_record, Amount};
-use fedimint_mint_common::Nonce;
-use serde::Serialize;
+use fedimint_mint_common::{Nonce, Note};
+use secp256k1::{KeyPair, PublicKey};
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
 use crate::backup::recovery::MintRecoveryState;
-use crate::SpendableNote;
+use crate::{NoteSelectionStrategy, SpendableNote};
 
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
@@ -17,6 +20,11 @@ pub enum DbKeyPrefix {
     CancelledOOBSpend = 0x2b,
     RecoveryState = 0x2c,
     RecoveryFinalized = 0x2d,
+    LastConsolidation = 0x2e,
+    NextLockedPaymentIndex = 0x2f,
+    LockedPaymentKey = 0x30,
+    ReceivedLockedNote = 0x31,
+    NoteSelectionStrategy = 0x32,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -79,6 +87,83 @@ impl_db_record!(
     value = bool,
     db_prefix = DbKeyPrefix::RecoveryFinalized,
 );
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct LastConsolidationKey;
+
+impl_db_record!(
+    key = LastConsolidationKey,
+    value = SystemTime,
+    db_prefix = DbKeyPrefix::LastConsolidation,
+);
+
+/// Index of the next key derived for a locked payment request
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct NextLockedPaymentIndexKey;
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct NextLockedPaymentIndexKeyPrefix;
+
+impl_db_record!(
+    key = NextLockedPaymentIndexKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::NextLockedPaymentIndex,
+);
+
+/// Key notes are locked to by the payer of one of our locked payment requests
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct LockedPaymentKey(pub PublicKey);
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct LockedPaymentKeyPrefix;
+
+impl_db_record!(
+    key = LockedPaymentKey,
+    value = KeyPair,
+    db_prefix = DbKeyPrefix::LockedPaymentKey,
+);
+impl_db_lookup!(
+    key = LockedPaymentKey,
+    query_prefix = LockedPaymentKeyPrefix
+);
+
+/// Note locked to one of our keys that we accepted, kept after it was redeemed
+/// to reject it if it is presented again
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct ReceivedLockedNoteKey {
+    pub recipient: PublicKey,
+    pub amount: Amount,
+    pub nonce: Nonce,
+}
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct ReceivedLockedNoteKeyPrefix;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct ReceivedLockedNote {
+    pub note: Note,
+    pub redeemed: bool,
+}
+
+impl_db_record!(
+    key = ReceivedLockedNoteKey,
+    value = ReceivedLockedNote,
+    db_prefix = DbKeyPrefix::ReceivedLockedNote,
+);
+impl_db_lookup!(
+    key = ReceivedLockedNoteKey,
+    query_prefix = ReceivedLockedNoteKeyPrefix
+);
+
+/// Strategy set by [`crate::MintClientModule::set_note_selection_strategy`]
+#[derive(Debug, Clone, Encodable, Decodable, Serialize)]
+pub struct NoteSelectionStrategyKey;
+
+impl_db_record!(
+    key = NoteSelectionStrategyKey,
+    value = NoteSelectionStrategy,
+    db_prefix = DbKeyPrefix::NoteSelectionStrategy,
+);
+
 #[derive(Debug, Clone, Encodable, Decodable, Serialize)]
 pub struct CancelledOOBSpendKey(pub OperationId);
 
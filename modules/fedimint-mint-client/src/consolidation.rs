@@ -0,0 +1,470 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use fedimint_client::module::{ClientContext, ClientDbTxContext};
+use fedimint_client::transaction::TransactionBuilder;
+use fedimint_core::core::OperationId;
+use fedimint_core::db::{AutocommitError, IDatabaseTransactionOpsCoreTyped};
+use fedimint_core::module::fee::ProportionalFee;
+use fedimint_core::module::CommonModuleInit;
+use fedimint_core::task::timeout;
+use fedimint_core::{Amount, Tiered, TieredMulti, TieredSummary};
+use fedimint_mint_common::MintCommonInit;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::client_db::{LastConsolidationKey, NoteKey, NoteKeyPrefix};
+use crate::{
+    total_spend_fees, MintClientModule, MintOperationMeta, MintOperationMetaVariant, SpendableNote,
+};
+
+/// How often the background task checks if the wallet needs to be
+/// consolidated, the policy's `min_interval` still applies
+const CONSOLIDATION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Policy for reissuing notes to keep the denominations held by the wallet
+/// balanced, see [`MintClientModule::set_note_consolidation_policy`]
+///
+/// Receiving many payments leaves the wallet with lots of notes of a few
+/// denominations, which makes spending expensive and exact out-of-band spends
+/// with [`crate::SelectNotesWithExactAmount`] fail for lack of the other
+/// denominations. Consolidation reissues the surplus notes of over-full
+/// denominations, and large notes if denominations are missing, into notes of
+/// the denominations the wallet lacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteConsolidationPolicy {
+    /// Notes per denomination kept when consolidating, also the number of
+    /// notes per denomination the reissued notes are distributed towards
+    pub target_notes_per_denomination: u16,
+    /// Denominations holding more notes than this are consolidated
+    pub max_notes_per_denomination: u16,
+    /// Maximum number of notes spent in a single consolidation transaction
+    pub max_notes_per_transaction: usize,
+    /// Maximum fees as parts per million of the reissued amount, more
+    /// expensive consolidations are skipped
+    pub max_fee_ppm: u64,
+    /// Minimum time between two consolidation transactions
+    pub min_interval: Duration,
+}
+
+impl Default for NoteConsolidationPolicy {
+    fn default() -> Self {
+        NoteConsolidationPolicy {
+            target_notes_per_denomination: 2,
+            max_notes_per_denomination: 10,
+            max_notes_per_transaction: 50,
+            max_fee_ppm: 1_000,
+            min_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl MintClientModule {
+    /// Sets the policy used to consolidate notes in the background for the
+    /// lifetime of the client, `None` disables consolidation
+    pub fn set_note_consolidation_policy(&self, policy: Option<NoteConsolidationPolicy>) {
+        self.consolidation_policy.send_replace(policy);
+
+        if policy.is_some() && !self.consolidation_task_spawned.swap(true, Ordering::SeqCst) {
+            fedimint_core::task::spawn(
+                "mint note consolidation",
+                run_note_consolidation(
+                    self.client_ctx.clone(),
+                    self.consolidation_policy.subscribe(),
+                ),
+            );
+        }
+    }
+
+    /// The policy set by [`Self::set_note_consolidation_policy`]
+    pub fn note_consolidation_policy(&self) -> Option<NoteConsolidationPolicy> {
+        *self.consolidation_policy.borrow()
+    }
+
+    /// Reissues the surplus notes of all denominations holding more than
+    /// `policy.max_notes_per_denomination` notes, and notes exceeding the
+    /// target distribution if the wallet lacks some of its denominations.
+    ///
+    /// Returns `None` if there is nothing to consolidate, the fees would exceed
+    /// `policy.max_fee_ppm` or the last consolidation happened less than
+    /// `policy.min_interval` ago. Otherwise the progress of the returned
+    /// operation can be observed with
+    /// [`MintClientModule::subscribe_reissue_external_notes`].
+    pub async fn consolidate_notes(
+        &self,
+        policy: &NoteConsolidationPolicy,
+    ) -> anyhow::Result<Option<OperationId>> {
+        let policy = *policy;
+
+        self.client_ctx
+            .module_autocommit(
+                move |dbtx, _| {
+                    Box::pin(async move { self.consolidate_notes_dbtx(dbtx, &policy).await })
+                },
+                Some(100),
+            )
+            .await
+            .map_err(|e| match e {
+                AutocommitError::ClosureError { error, .. } => error,
+                AutocommitError::CommitFailed { last_error, .. } => {
+                    anyhow!("Commit to DB failed: {last_error}")
+                }
+            })
+    }
+
+    async fn consolidate_notes_dbtx(
+        &self,
+        dbtx: &mut ClientDbTxContext<'_, '_, Self>,
+        policy: &NoteConsolidationPolicy,
+    ) -> anyhow::Result<Option<OperationId>> {
+        let now = fedimint_core::time::now();
+        let last_consolidation = dbtx.module_dbtx().get_value(&LastConsolidationKey).await;
+        if last_consolidation.map_or(false, |last| {
+            now.duration_since(last)
+                .map_or(true, |elapsed| elapsed < policy.min_interval)
+        }) {
+            return Ok(None);
+        }
+
+        let wallet_summary = self.get_wallet_summary(&mut dbtx.module_dbtx()).await;
+        let inputs_summary = consolidation_inputs(&wallet_summary, &self.cfg.tbs_pks, policy);
+
+        if inputs_summary.count_items() == 0 {
+            return Ok(None);
+        }
+
+        let notes = select_notes_by_summary(&mut dbtx.module_dbtx(), &inputs_summary).await;
+        let input_amount = notes.total_amount();
+        let spend_fees = total_spend_fees(&notes, &self.cfg.fee_consensus);
+        if input_amount <= spend_fees {
+            return Ok(None);
+        }
+
+        let remaining_summary = subtract_summary(&wallet_summary, &inputs_summary);
+        let notes_amount = self.amount_before_issuance_fees(
+            &remaining_summary,
+            policy.target_notes_per_denomination,
+            input_amount - spend_fees,
+        );
+        let fee = input_amount - notes_amount;
+        let max_fee =
+            ProportionalFee::new(policy.max_fee_ppm, Amount::ZERO, None).fee(input_amount);
+
+        if notes_amount == Amount::ZERO || max_fee < fee {
+            debug!(
+                %input_amount,
+                %fee,
+                %max_fee,
+                "Skipping note consolidation, fees are too high"
+            );
+            return Ok(None);
+        }
+
+        for (amount, note) in notes.iter_items() {
+            dbtx.module_dbtx()
+                .remove_entry(&NoteKey {
+                    amount,
+                    nonce: note.nonce(),
+                })
+                .await;
+        }
+
+        let operation_id = OperationId::new_random();
+        let inputs = self.create_input_from_notes(operation_id, notes).await?;
+        let outputs = self
+            .create_output(
+                &mut dbtx.module_dbtx(),
+                operation_id,
+                policy.target_notes_per_denomination,
+                notes_amount,
+            )
+            .await;
+        let num_outputs = outputs.len() as u64;
+
+        let tx = TransactionBuilder::new()
+            .with_inputs(self.client_ctx.map_dyn(inputs).collect())
+            .with_outputs(self.client_ctx.map_dyn(outputs).collect());
+
+        let (txid, _) = dbtx
+            .finalize_and_submit_transaction(
+                operation_id,
+                MintCommonInit::KIND.as_str(),
+                |txid, change| MintOperationMeta {
+                    variant: MintOperationMetaVariant::Consolidation {
+                        txid,
+                        out_point_indices: (0..num_outputs)
+                            .chain(change.iter().map(|out_point| out_point.out_idx))
+                            .collect(),
+                        fee,
+                    },
+                    amount: input_amount,
+                    extra_meta: serde_json::Value::Null,
+                },
+                tx,
+            )
+            .await?;
+
+        dbtx.module_dbtx()
+            .insert_entry(&LastConsolidationKey, &now)
+            .await;
+
+        info!(
+            %txid,
+            %input_amount,
+            %fee,
+            notes = inputs_summary.count_items(),
+            "Consolidating notes"
+        );
+
+        Ok(Some(operation_id))
+    }
+}
+
+/// Periodically consolidates the notes according to the current policy until
+/// the client is shut down
+async fn run_note_consolidation(
+    client_ctx: ClientContext<MintClientModule>,
+    mut policy_rx: watch::Receiver<Option<NoteConsolidationPolicy>>,
+) {
+    loop {
+        let policy = *policy_rx.borrow_and_update();
+
+        if let Some(policy) = policy {
+            let Some(module) = client_ctx.try_self_ref() else {
+                return;
+            };
+
+            if let Err(e) = module.consolidate_notes(&policy).await {
+                warn!("Failed to consolidate notes: {e:?}");
+            }
+        }
+
+        // wake up early if the policy changes, stop once the module is gone
+        if let Ok(Err(_)) = timeout(CONSOLIDATION_CHECK_INTERVAL, policy_rx.changed()).await {
+            return;
+        }
+    }
+}
+
+/// The number of notes per denomination to reissue
+///
+/// First the surplus above the target of every denomination holding more than
+/// the maximum is reissued, starting with the smallest denomination. If the
+/// wallet then lacks denominations the target distribution of its balance
+/// holds, notes exceeding the target distribution are added, starting with
+/// the largest denomination, until their amount covers the missing notes.
+/// Reissuing a single note is only worth it to fill missing denominations.
+fn consolidation_inputs<K>(
+    wallet_summary: &TieredSummary,
+    tiers: &Tiered<K>,
+    policy: &NoteConsolidationPolicy,
+) -> TieredSummary {
+    let mut budget = policy.max_notes_per_transaction;
+    let mut inputs = TieredSummary::default();
+
+    for (amount, count) in wallet_summary.iter() {
+        if count <= usize::from(policy.max_notes_per_denomination) {
+            continue;
+        }
+
+        let surplus = count
+            .saturating_sub(usize::from(policy.target_notes_per_denomination))
+            .min(budget);
+
+        if surplus != 0 {
+            inputs.inc(amount, surplus);
+            budget -= surplus;
+        }
+    }
+
+    let target = TieredSummary::represent_amount(
+        wallet_summary.total_amount(),
+        &TieredSummary::default(),
+        tiers,
+        policy.target_notes_per_denomination,
+    )
+    .iter()
+    .collect::<BTreeMap<_, _>>();
+    let remaining = subtract_summary(wallet_summary, &inputs)
+        .iter()
+        .collect::<BTreeMap<_, _>>();
+
+    let missing_amount = target
+        .iter()
+        .filter(|(amount, _)| remaining.get(amount).copied().unwrap_or_default() == 0)
+        .map(|(amount, count)| *amount * *count as u64)
+        .sum::<Amount>();
+
+    if missing_amount == Amount::ZERO {
+        return if inputs.count_items() < 2 {
+            TieredSummary::default()
+        } else {
+            inputs
+        };
+    }
+
+    for (amount, count) in remaining.iter().rev() {
+        let excess = count.saturating_sub(target.get(amount).copied().unwrap_or_default());
+
+        for _ in 0..excess {
+            if budget == 0 || missing_amount <= inputs.total_amount() {
+                return inputs;
+            }
+
+            inputs.inc(*amount, 1);
+            budget -= 1;
+        }
+    }
+
+    inputs
+}
+
+fn subtract_summary(summary: &TieredSummary, subtrahend: &TieredSummary) -> TieredSummary {
+    let subtrahend = subtrahend.iter().collect::<BTreeMap<_, _>>();
+
+    summary
+        .iter()
+        .map(|(amount, count)| {
+            let subtract = subtrahend.get(&amount).copied().unwrap_or_default();
+            (amount, count.saturating_sub(subtract))
+        })
+        .collect()
+}
+
+async fn select_notes_by_summary(
+    dbtx: &mut fedimint_core::db::DatabaseTransaction<'_>,
+    summary: &TieredSummary,
+) -> TieredMulti<SpendableNote> {
+    let mut remaining = summary.iter().collect::<BTreeMap<_, _>>();
+
+    let notes = dbtx
+        .find_by_prefix(&NoteKeyPrefix)
+        .await
+        .filter(|(key, _)| std::future::ready(remaining.contains_key(&key.amount)))
+        .map(|(key, note)| (key.amount, note))
+        .collect::<Vec<_>>()
+        .await;
+
+    notes
+        .into_iter()
+        .filter(|(amount, _)| match remaining.get_mut(amount) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::{Amount, Tiered, TieredSummary};
+
+    use super::{consolidation_inputs, subtract_summary, NoteConsolidationPolicy};
+
+    fn tiers(max_msats: u64) -> Tiered<()> {
+        (0..)
+            .map(|exponent| 1 << exponent)
+            .take_while(|msats| *msats <= max_msats)
+            .map(|msats| (Amount::from_msats(msats), ()))
+            .collect()
+    }
+
+    #[test]
+    fn consolidates_surplus_of_full_denominations() {
+        let policy = NoteConsolidationPolicy {
+            target_notes_per_denomination: 2,
+            max_notes_per_denomination: 5,
+            max_notes_per_transaction: 10,
+            ..NoteConsolidationPolicy::default()
+        };
+
+        let wallet = TieredSummary::from_iter([
+            (Amount::from_msats(1), 12),
+            (Amount::from_msats(2), 5),
+            (Amount::from_msats(4), 8),
+        ]);
+
+        // the smallest denominations are consolidated first until the budget of
+        // notes per transaction is exhausted
+        let inputs = consolidation_inputs(&wallet, &tiers(4), &policy);
+        assert_eq!(
+            inputs,
+            TieredSummary::from_iter([(Amount::from_msats(1), 10)])
+        );
+
+        let policy = NoteConsolidationPolicy {
+            max_notes_per_transaction: 100,
+            ..policy
+        };
+        let inputs = consolidation_inputs(&wallet, &tiers(4), &policy);
+        assert_eq!(
+            inputs,
+            TieredSummary::from_iter([(Amount::from_msats(1), 10), (Amount::from_msats(4), 6)])
+        );
+        assert_eq!(
+            subtract_summary(&wallet, &inputs),
+            TieredSummary::from_iter([
+                (Amount::from_msats(1), 2),
+                (Amount::from_msats(2), 5),
+                (Amount::from_msats(4), 2),
+            ])
+        );
+
+        let balanced = TieredSummary::from_iter([
+            (Amount::from_msats(1), 2),
+            (Amount::from_msats(2), 2),
+            (Amount::from_msats(4), 3),
+        ]);
+        assert_eq!(
+            consolidation_inputs(&balanced, &tiers(4), &policy).count_items(),
+            0
+        );
+    }
+
+    #[test]
+    fn reissues_large_notes_into_missing_denominations() {
+        let policy = NoteConsolidationPolicy {
+            target_notes_per_denomination: 2,
+            max_notes_per_denomination: 5,
+            max_notes_per_transaction: 10,
+            ..NoteConsolidationPolicy::default()
+        };
+
+        // The target distribution of 32 msats holds two notes of each
+        // denomination and an extra note of 2 msats, so the notes of 8 msats
+        // beyond the target cover the 16 msats missing
+        let wallet = TieredSummary::from_iter([(Amount::from_msats(8), 4)]);
+        assert_eq!(
+            consolidation_inputs(&wallet, &tiers(8), &policy),
+            TieredSummary::from_iter([(Amount::from_msats(8), 2)])
+        );
+
+        // A single note is reissued if it fills missing denominations
+        let wallet = TieredSummary::from_iter([
+            (Amount::from_msats(1), 2),
+            (Amount::from_msats(2), 2),
+            (Amount::from_msats(8), 1),
+        ]);
+        assert_eq!(
+            consolidation_inputs(&wallet, &tiers(8), &policy),
+            TieredSummary::from_iter([(Amount::from_msats(8), 1)])
+        );
+
+        // The budget of notes per transaction applies to rebalancing as well
+        let policy = NoteConsolidationPolicy {
+            max_notes_per_transaction: 1,
+            ..policy
+        };
+        let wallet = TieredSummary::from_iter([(Amount::from_msats(8), 4)]);
+        assert_eq!(
+            consolidation_inputs(&wallet, &tiers(8), &policy),
+            TieredSummary::from_iter([(Amount::from_msats(8), 1)])
+        );
+    }
+}
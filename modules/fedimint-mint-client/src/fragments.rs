@@ -0,0 +1,460 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context};
+use base64::Engine;
+use bitcoin_hashes::{sha256, Hash};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+
+use crate::{OOBNotes, BASE64_URL_SAFE};
+
+/// Prefix of the string representation of an [`OOBNotesFragment`]
+const FRAGMENT_PREFIX: &str = "fedimint-notes";
+
+/// Domain separation of the hashes choosing the parts mixed into a fragment
+const MIXING_TAG: &[u8] = b"fedimint-oob-notes-fragment";
+
+/// Upper bound of the length of encoded notes we reassemble, so a malicious
+/// fragment cannot make us allocate arbitrary amounts of memory
+const MAX_MESSAGE_LEN: u64 = 1 << 20;
+
+/// Upper bound of the number of parts the encoded notes are split into, so a
+/// malicious fragment cannot make choosing its mixed parts arbitrarily
+/// expensive
+const MAX_NUM_PARTS: u64 = 1 << 10;
+
+/// A fragment of encoded [`OOBNotes`] small enough to fit into a QR code, see
+/// [`OOBNotes::fragments`]
+///
+/// The encoded notes are split into `num_parts` parts of equal length. The
+/// first `num_parts` fragments contain a single part each, every later
+/// fragment contains the XOR of a pseudo-random selection of parts, similar to
+/// the fountain codes of Uniform Resources. This allows showing an endless
+/// animated QR sequence, the receiver can reassemble the notes from fragments
+/// in any order and fragments it missed are made up for by later ones, see
+/// [`OOBNotesFragmentDecoder`].
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct OOBNotesFragment {
+    /// Sequence number of the fragment, starting at 1
+    pub seq: u64,
+    /// Number of parts the encoded notes were split into
+    pub num_parts: u64,
+    /// Length of the encoded notes
+    pub message_len: u64,
+    /// Checksum of the encoded notes, identifies the notes the fragment belongs
+    /// to and verifies their reassembly
+    pub checksum: u32,
+    data: Vec<u8>,
+    /// Checksum of the fields above, so fragments corrupted by misreading a
+    /// QR code are dropped instead of spoiling the reassembly
+    fragment_checksum: u32,
+}
+
+impl OOBNotes {
+    /// Splits the encoded notes into an endless sequence of fragments with at
+    /// most `max_part_len` bytes of payload each, meant to be shown as an
+    /// animated QR code
+    ///
+    /// The notes can be reassembled from the first `num_parts` fragments,
+    /// fragments after those allow the receiver to recover from missed ones.
+    /// The notes are split into at most 1024 parts, which are longer than
+    /// `max_part_len` for notes larger than 1024 times `max_part_len`.
+    ///
+    /// # Panics
+    /// If `max_part_len` is zero
+    pub fn fragments(&self, max_part_len: usize) -> impl Iterator<Item = OOBNotesFragment> {
+        assert_ne!(max_part_len, 0, "Fragments need to contain data");
+
+        let message = self.consensus_encode_to_vec();
+        let checksum = checksum(&message);
+        let num_parts =
+            ((message.len() + max_part_len - 1) / max_part_len).min(MAX_NUM_PARTS as usize);
+        let part_len = (message.len() + num_parts - 1) / num_parts;
+
+        let parts = message
+            .chunks(part_len)
+            .map(|chunk| {
+                let mut part = chunk.to_vec();
+                part.resize(part_len, 0);
+                part
+            })
+            .collect::<Vec<_>>();
+
+        (1..).map(move |seq| {
+            let mut data = vec![0; part_len];
+            for idx in mixed_parts(seq, num_parts as u64, checksum) {
+                xor_into(&mut data, &parts[idx]);
+            }
+
+            let mut fragment = OOBNotesFragment {
+                seq,
+                num_parts: num_parts as u64,
+                message_len: message.len() as u64,
+                checksum,
+                data,
+                fragment_checksum: 0,
+            };
+            fragment.fragment_checksum = fragment.compute_fragment_checksum();
+
+            fragment
+        })
+    }
+}
+
+impl OOBNotesFragment {
+    fn part_len(&self) -> u64 {
+        (self.message_len + self.num_parts - 1) / self.num_parts
+    }
+
+    fn compute_fragment_checksum(&self) -> u32 {
+        let mut fields = Vec::with_capacity(28 + self.data.len());
+        fields.extend(self.seq.to_le_bytes());
+        fields.extend(self.num_parts.to_le_bytes());
+        fields.extend(self.message_len.to_le_bytes());
+        fields.extend(self.checksum.to_le_bytes());
+        fields.extend(&self.data);
+
+        checksum(&fields)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.fragment_checksum == self.compute_fragment_checksum(),
+            "Fragment does not match its checksum"
+        );
+        ensure!(self.seq != 0, "Fragment sequence numbers start at 1");
+        ensure!(
+            self.message_len <= MAX_MESSAGE_LEN,
+            "Fragment belongs to notes of {} bytes, more than we accept",
+            self.message_len
+        );
+        ensure!(
+            0 < self.num_parts && self.num_parts <= self.message_len.min(MAX_NUM_PARTS),
+            "Fragment has an invalid number of parts"
+        );
+        ensure!(
+            self.data.len() as u64 == self.part_len(),
+            "Fragment data has an invalid length"
+        );
+
+        Ok(())
+    }
+}
+
+impl Display for OOBNotesFragment {
+    /// Encodes the fragment as `fedimint-notes/<seq>-<num_parts>/<base64>`,
+    /// the human-readable header shows the progress of an animated QR code
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{FRAGMENT_PREFIX}/{}-{}/{}",
+            self.seq,
+            self.num_parts,
+            BASE64_URL_SAFE.encode(self.consensus_encode_to_vec())
+        )
+    }
+}
+
+impl FromStr for OOBNotesFragment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = s.split('/');
+        let (Some(prefix), Some(header), Some(data), None) = (
+            components.next(),
+            components.next(),
+            components.next(),
+            components.next(),
+        ) else {
+            bail!("Invalid e-cash notes fragment format");
+        };
+
+        ensure!(prefix == FRAGMENT_PREFIX, "Not an e-cash notes fragment");
+
+        let fragment = OOBNotesFragment::consensus_decode_vec(
+            BASE64_URL_SAFE.decode(data)?,
+            &ModuleDecoderRegistry::default(),
+        )?;
+
+        ensure!(
+            header == format!("{}-{}", fragment.seq, fragment.num_parts),
+            "Fragment header does not match its data"
+        );
+        fragment.validate()?;
+
+        Ok(fragment)
+    }
+}
+
+/// Reassembles [`OOBNotes`] from [`OOBNotesFragment`]s added in any order
+#[derive(Debug, Default)]
+pub struct OOBNotesFragmentDecoder {
+    /// Number of parts, message length and checksum of the notes we decode,
+    /// taken from the first fragment
+    header: Option<(u64, u64, u32)>,
+    /// Sequence numbers of the fragments we already added
+    seen_fragments: BTreeSet<u64>,
+    /// The parts we recovered so far by their index
+    parts: BTreeMap<usize, Vec<u8>>,
+    /// Fragments mixing more than one part we are still missing
+    mixed_fragments: Vec<(BTreeSet<usize>, Vec<u8>)>,
+}
+
+impl OOBNotesFragmentDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a fragment, returning the notes once enough fragments were added
+    /// to reassemble them
+    ///
+    /// Fails if the fragment is invalid, for example because it does not match
+    /// its checksum, or belongs to different notes than the fragments added
+    /// before. The fragment is dropped and the decoder remains usable in that
+    /// case.
+    pub fn add_fragment(&mut self, fragment: OOBNotesFragment) -> anyhow::Result<Option<OOBNotes>> {
+        fragment.validate()?;
+
+        let header = (fragment.num_parts, fragment.message_len, fragment.checksum);
+        ensure!(
+            *self.header.get_or_insert(header) == header,
+            "Fragment belongs to different e-cash notes"
+        );
+
+        if self.seen_fragments.insert(fragment.seq) {
+            let parts = mixed_parts(fragment.seq, fragment.num_parts, fragment.checksum);
+            self.mixed_fragments.push((parts, fragment.data));
+            self.reduce_mixed_fragments();
+        }
+
+        self.notes()
+    }
+
+    /// The number of parts recovered so far and the number of parts needed
+    pub fn progress(&self) -> (usize, usize) {
+        let num_parts = self
+            .header
+            .map_or(0, |(num_parts, _, _)| num_parts as usize);
+        (self.parts.len(), num_parts)
+    }
+
+    /// Removes the known parts from all mixed fragments until no fragment
+    /// mixing a single unknown part is left
+    fn reduce_mixed_fragments(&mut self) {
+        loop {
+            let mut recovered_part = false;
+
+            for (parts, data) in &mut self.mixed_fragments {
+                for (idx, part) in &self.parts {
+                    if parts.remove(idx) {
+                        xor_into(data, part);
+                    }
+                }
+            }
+
+            for (parts, data) in std::mem::take(&mut self.mixed_fragments) {
+                match parts.len() {
+                    // all its parts are known already
+                    0 => {}
+                    1 => {
+                        let idx = parts.into_iter().next().expect("Contains one part");
+                        self.parts.insert(idx, data);
+                        recovered_part = true;
+                    }
+                    _ => self.mixed_fragments.push((parts, data)),
+                }
+            }
+
+            if !recovered_part {
+                return;
+            }
+        }
+    }
+
+    fn notes(&self) -> anyhow::Result<Option<OOBNotes>> {
+        let Some((num_parts, message_len, expected_checksum)) = self.header else {
+            return Ok(None);
+        };
+
+        if (self.parts.len() as u64) < num_parts {
+            return Ok(None);
+        }
+
+        let mut message = self.parts.values().flatten().copied().collect::<Vec<_>>();
+        message.truncate(message_len as usize);
+
+        ensure!(
+            checksum(&message) == expected_checksum,
+            "Reassembled e-cash notes do not match their checksum"
+        );
+
+        let notes = OOBNotes::consensus_decode_vec(message, &ModuleDecoderRegistry::default())
+            .context("Reassembled e-cash notes are invalid")?;
+
+        Ok(Some(notes))
+    }
+}
+
+fn checksum(message: &[u8]) -> u32 {
+    let hash = sha256::Hash::hash(message).into_inner();
+    u32::from_le_bytes(hash[..4].try_into().expect("Hash is longer than 4 bytes"))
+}
+
+/// The indices of the parts XORed into the fragment `seq`
+///
+/// Fragments up to `num_parts` contain a single part in order, the parts of
+/// later fragments are chosen deterministically from the fragment's sequence
+/// number and the checksum of the notes.
+fn mixed_parts(seq: u64, num_parts: u64, checksum: u32) -> BTreeSet<usize> {
+    if seq <= num_parts {
+        return BTreeSet::from([(seq - 1) as usize]);
+    }
+
+    let mut counter = 0_u64;
+    let mut random = |bound: u64| {
+        let mut engine = sha256::Hash::engine();
+        bitcoin_hashes::HashEngine::input(&mut engine, MIXING_TAG);
+        bitcoin_hashes::HashEngine::input(&mut engine, &checksum.to_le_bytes());
+        bitcoin_hashes::HashEngine::input(&mut engine, &seq.to_le_bytes());
+        bitcoin_hashes::HashEngine::input(&mut engine, &counter.to_le_bytes());
+        counter += 1;
+
+        let hash = sha256::Hash::from_engine(engine).into_inner();
+        u64::from_le_bytes(hash[..8].try_into().expect("Hash is longer than 8 bytes")) % bound
+    };
+
+    // Mixing `num_parts / r` parts for a uniform `r` approximates the ideal
+    // soliton distribution, most fragments mix few parts but some mix many
+    let degree = num_parts / (random(num_parts) + 1);
+
+    // partial Fisher-Yates shuffle of the indices, which only tracks the
+    // positions that were swapped instead of materializing all indices
+    let mut swapped = BTreeMap::<u64, u64>::new();
+    let mut parts = BTreeSet::new();
+    for i in 0..degree {
+        let j = i + random(num_parts - i);
+        let index_at_i = swapped.get(&i).copied().unwrap_or(i);
+        let index_at_j = swapped.get(&j).copied().unwrap_or(j);
+        swapped.insert(j, index_at_i);
+        parts.insert(index_at_j as usize);
+    }
+
+    parts
+}
+
+fn xor_into(data: &mut [u8], part: &[u8]) {
+    for (byte, part_byte) in data.iter_mut().zip(part) {
+        *byte ^= part_byte;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::config::FederationId;
+    use fedimint_core::encoding::Decodable;
+    use fedimint_core::{Amount, TieredMulti};
+
+    use super::{OOBNotesFragment, OOBNotesFragmentDecoder, MAX_NUM_PARTS};
+    use crate::{OOBNotes, SpendableNote};
+
+    fn oob_notes(num_notes: u64) -> OOBNotes {
+        let note = SpendableNote::consensus_decode_hex("a5dd3ebacad1bc48bd8718eed5a8da1d68f91323bef2848ac4fa2e6f8eed710f3178fd4aef047cc234e6b1127086f33cc408b39818781d9521475360de6b205f3328e490a6d99d5e2553a4553207c8bd", &Default::default()).unwrap();
+
+        let notes = (0..num_notes)
+            .map(|i| (Amount::from_msats(1 << i), note))
+            .collect::<TieredMulti<_>>();
+
+        OOBNotes::new(FederationId::dummy().to_prefix(), notes)
+    }
+
+    #[test]
+    fn reassembles_notes_from_fragments_in_any_order() {
+        let notes = oob_notes(20);
+
+        let fragments = notes
+            .fragments(100)
+            .take(200)
+            .map(|fragment| fragment.to_string())
+            .collect::<Vec<_>>();
+        let num_parts = fragments[0].parse::<OOBNotesFragment>().unwrap().num_parts as usize;
+        assert!(1 < num_parts && num_parts < 50);
+
+        // the first fragments contain every part once
+        let mut decoder = OOBNotesFragmentDecoder::new();
+        for fragment in fragments[..num_parts - 1].iter().rev() {
+            let fragment = fragment.parse().expect("Fragment parses");
+            assert_eq!(decoder.add_fragment(fragment).unwrap(), None);
+        }
+        let fragment = fragments[num_parts - 1].parse().unwrap();
+        assert_eq!(decoder.add_fragment(fragment).unwrap(), Some(notes.clone()));
+
+        // every part has to be recovered from mixed fragments if we skip the
+        // first ones
+        let mut decoder = OOBNotesFragmentDecoder::new();
+        let reassembled = fragments[num_parts..]
+            .iter()
+            .find_map(|fragment| {
+                decoder
+                    .add_fragment(fragment.parse().unwrap())
+                    .expect("Fragment is valid")
+            })
+            .expect("Enough fragments to reassemble the notes");
+        assert_eq!(reassembled, notes);
+    }
+
+    #[test]
+    fn rejects_fragments_of_other_notes() {
+        let mut decoder = OOBNotesFragmentDecoder::new();
+        let fragment = oob_notes(10).fragments(50).next().unwrap();
+        assert_eq!(decoder.add_fragment(fragment).unwrap(), None);
+
+        let other_fragment = oob_notes(11).fragments(50).nth(1).unwrap();
+        assert!(decoder.add_fragment(other_fragment).is_err());
+        assert_eq!(decoder.progress().0, 1);
+
+        let fragment_str = oob_notes(10).fragments(50).next().unwrap().to_string();
+        let tampered_header = fragment_str.replacen("/1-", "/2-", 1);
+        assert!(tampered_header.parse::<OOBNotesFragment>().is_err());
+    }
+
+    #[test]
+    fn drops_corrupted_fragments() {
+        let notes = oob_notes(10);
+        let mut fragments = notes.fragments(50);
+        let mut decoder = OOBNotesFragmentDecoder::new();
+        assert_eq!(
+            decoder.add_fragment(fragments.next().unwrap()).unwrap(),
+            None
+        );
+
+        let mut corrupted = fragments.next().unwrap();
+        corrupted.data[0] ^= 1;
+        assert!(decoder.add_fragment(corrupted).is_err());
+        assert_eq!(decoder.progress().0, 1);
+
+        let reassembled = fragments
+            .find_map(|fragment| decoder.add_fragment(fragment).unwrap())
+            .expect("Enough fragments to reassemble the notes");
+        assert_eq!(reassembled, notes);
+    }
+
+    #[test]
+    fn limits_number_of_parts() {
+        let notes = oob_notes(20);
+        let fragment = notes.fragments(1).next().unwrap();
+        assert_eq!(fragment.num_parts, MAX_NUM_PARTS);
+
+        let mut too_many_parts = OOBNotesFragment {
+            num_parts: MAX_NUM_PARTS + 1,
+            data: vec![0; 1],
+            ..fragment
+        };
+        too_many_parts.message_len = too_many_parts.num_parts;
+        too_many_parts.fragment_checksum = too_many_parts.compute_fragment_checksum();
+        assert!(OOBNotesFragmentDecoder::new()
+            .add_fragment(too_many_parts)
+            .is_err());
+    }
+}
@@ -4,10 +4,13 @@ use fedimint_client::sm::{ClientSMDatabaseTransaction, State, StateTransition};
 use fedimint_client::transaction::ClientInput;
 use fedimint_client::DynGlobalClientContext;
 use fedimint_core::core::OperationId;
+use fedimint_core::db::IDatabaseTransactionOpsCoreTyped;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{Amount, TransactionId};
-use fedimint_mint_common::MintInput;
+use fedimint_mint_common::{MintInput, Note};
+use secp256k1::PublicKey;
 
+use crate::client_db::{ReceivedLockedNote, ReceivedLockedNoteKey};
 use crate::{MintClientContext, MintClientStateMachines, SpendableNote};
 
 // TODO: add retry with valid subset of e-cash notes
@@ -22,6 +25,8 @@ use crate::{MintClientContext, MintClientStateMachines, SpendableNote};
 ///     Created -- containing tx rejected --> Refund
 ///     Refund -- refund tx rejected --> Error
 ///     Refund -- refund tx accepted --> RS[Refund Success]
+///     CL[Created Locked] -- containing tx accepted --> Success
+///     CL -- containing tx rejected --> Error
 /// ```
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
 pub enum MintInputStates {
@@ -30,6 +35,7 @@ pub enum MintInputStates {
     Success(MintInputStateSuccess),
     Error(MintInputStateError),
     RefundSuccess(MintInputStateRefundSuccess),
+    CreatedLocked(MintInputStateCreatedLocked),
 }
 
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Decodable, Encodable)]
@@ -65,6 +71,9 @@ impl State for MintInputStateMachine {
             MintInputStates::RefundSuccess(_) => {
                 vec![]
             }
+            MintInputStates::CreatedLocked(created) => {
+                created.transitions(&self.common, global_context)
+            }
         }
     }
 
@@ -212,6 +221,75 @@ impl MintInputStateRefund {
     }
 }
 
+/// Redemption of a locked note we received, see
+/// [`crate::MintClientModule::redeem_locked_notes`]
+///
+/// The note can only be reissued to ourselves, so instead of refunding it on
+/// rejection we mark it as not redeemed again, so it is part of the next
+/// redemption.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub struct MintInputStateCreatedLocked {
+    pub(crate) amount: Amount,
+    pub(crate) note: Note,
+    pub(crate) recipient: PublicKey,
+}
+
+impl MintInputStateCreatedLocked {
+    fn transitions(
+        &self,
+        common: &MintInputCommon,
+        global_context: &DynGlobalClientContext,
+    ) -> Vec<StateTransition<MintInputStateMachine>> {
+        vec![StateTransition::new(
+            MintInputStateCreated::await_success(*common, global_context.clone()),
+            |dbtx, result, old_state| Box::pin(Self::transition_success(result, old_state, dbtx)),
+        )]
+    }
+
+    async fn transition_success(
+        result: Result<(), String>,
+        old_state: MintInputStateMachine,
+        dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+    ) -> MintInputStateMachine {
+        let created = match old_state.state {
+            MintInputStates::CreatedLocked(created) => created,
+            _ => panic!("Invalid state transition"),
+        };
+
+        match result {
+            Ok(()) => MintInputStateMachine {
+                common: old_state.common,
+                state: MintInputStates::Success(MintInputStateSuccess {}),
+            },
+            Err(error) => {
+                dbtx.module_tx()
+                    .insert_entry(
+                        &ReceivedLockedNoteKey {
+                            recipient: created.recipient,
+                            amount: created.amount,
+                            nonce: created.note.nonce,
+                        },
+                        &ReceivedLockedNote {
+                            note: created.note,
+                            redeemed: false,
+                        },
+                    )
+                    .await;
+
+                MintInputStateMachine {
+                    common: old_state.common,
+                    state: MintInputStates::Error(MintInputStateError {
+                        error: format!(
+                            "Transaction {} was rejected, the locked note will be redeemed again: {error}",
+                            old_state.common.txid
+                        ),
+                    }),
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
 pub struct MintInputStateSuccess {}
 
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use fedimint_client::module::{ClientContext, ClientDbTxContext};
+use fedimint_client::transaction::TransactionBuilder;
+use fedimint_core::core::OperationId;
+use fedimint_core::db::{AutocommitError, IDatabaseTransactionOpsCoreTyped};
+use fedimint_core::module::CommonModuleInit;
+use fedimint_core::task::sleep;
+use fedimint_core::{Amount, Tiered, TieredMulti};
+use fedimint_mint_common::MintCommonInit;
+use futures::StreamExt;
+use tbs::AggregatePublicKey;
+use tracing::{info, warn};
+
+use crate::api::MintFederationApi;
+use crate::client_db::{NoteKey, NoteKeyPrefix, ReceivedLockedNoteKeyPrefix};
+use crate::{
+    total_spend_fees, MintClientModule, MintOperationMeta, MintOperationMetaVariant, SpendableNote,
+};
+
+/// How often the background task checks for notes of key epochs that are about
+/// to retire, should be much shorter than the grace period of the federation
+const EXPIRING_NOTES_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+impl MintClientModule {
+    /// The key epoch the federation agreed on, see
+    /// [`fedimint_mint_common::config::KeyEpochParams`]
+    pub async fn current_key_epoch(&self) -> anyhow::Result<u64> {
+        Ok(self.module_api.fetch_key_epoch().await?)
+    }
+
+    /// Reissues all notes of key epochs the federation has left into notes of
+    /// the current epoch before their epoch retires and they become worthless.
+    /// Locked notes we received are redeemed for the same reason.
+    ///
+    /// This runs in the background if the federation rotates its keys. Returns
+    /// `None` if there are no such notes, otherwise the progress of the
+    /// returned operation can be observed with
+    /// [`MintClientModule::subscribe_reissue_external_notes`].
+    pub async fn reissue_expiring_notes(&self) -> anyhow::Result<Option<OperationId>> {
+        let current_epoch = self.current_key_epoch().await?;
+
+        if self.has_expiring_locked_notes(current_epoch).await {
+            self.redeem_locked_notes(serde_json::Value::Null).await?;
+        }
+
+        self.client_ctx
+            .module_autocommit(
+                move |dbtx, _| {
+                    Box::pin(
+                        async move { self.reissue_expiring_notes_dbtx(dbtx, current_epoch).await },
+                    )
+                },
+                Some(100),
+            )
+            .await
+            .map_err(|e| match e {
+                AutocommitError::ClosureError { error, .. } => error,
+                AutocommitError::CommitFailed { last_error, .. } => {
+                    anyhow!("Commit to DB failed: {last_error}")
+                }
+            })
+    }
+
+    async fn reissue_expiring_notes_dbtx(
+        &self,
+        dbtx: &mut ClientDbTxContext<'_, '_, Self>,
+        current_epoch: u64,
+    ) -> anyhow::Result<Option<OperationId>> {
+        let notes = dbtx
+            .module_dbtx()
+            .find_by_prefix(&NoteKeyPrefix)
+            .await
+            .map(|(key, note)| (key.amount, note))
+            .collect::<Vec<_>>()
+            .await;
+
+        let expiring_notes = notes
+            .into_iter()
+            .filter(|(amount, note)| {
+                self.is_expiring_note(current_epoch, |pks| {
+                    pks.get(*amount).map_or(false, |pk| note.note().verify(*pk))
+                })
+            })
+            .collect::<TieredMulti<SpendableNote>>();
+
+        if expiring_notes.count_items() == 0 {
+            return Ok(None);
+        }
+
+        let input_amount = expiring_notes.total_amount();
+        let spend_fees = total_spend_fees(&expiring_notes, &self.cfg.fee_consensus);
+        if input_amount <= spend_fees {
+            warn!(
+                %input_amount,
+                %spend_fees,
+                "Notes of an expiring key epoch are not worth reissuing"
+            );
+            return Ok(None);
+        }
+
+        for (amount, note) in expiring_notes.iter_items() {
+            dbtx.module_dbtx()
+                .remove_entry(&NoteKey {
+                    amount,
+                    nonce: note.nonce(),
+                })
+                .await;
+        }
+
+        // FIXME: don't hardcode notes per denomination
+        let notes_per_denomination = 2;
+
+        let wallet_summary = self.get_wallet_summary(&mut dbtx.module_dbtx()).await;
+        let notes_amount = self.amount_before_issuance_fees(
+            &wallet_summary,
+            notes_per_denomination,
+            input_amount - spend_fees,
+        );
+        let fee = input_amount - notes_amount;
+
+        let operation_id = OperationId::new_random();
+        let num_notes = expiring_notes.count_items();
+        let inputs = self
+            .create_input_from_notes(operation_id, expiring_notes)
+            .await?;
+        let outputs = if notes_amount == Amount::ZERO {
+            vec![]
+        } else {
+            self.create_output(
+                &mut dbtx.module_dbtx(),
+                operation_id,
+                notes_per_denomination,
+                notes_amount,
+            )
+            .await
+        };
+        let num_outputs = outputs.len() as u64;
+
+        let tx = TransactionBuilder::new()
+            .with_inputs(self.client_ctx.map_dyn(inputs).collect())
+            .with_outputs(self.client_ctx.map_dyn(outputs).collect());
+
+        let (txid, _) = dbtx
+            .finalize_and_submit_transaction(
+                operation_id,
+                MintCommonInit::KIND.as_str(),
+                |txid, change| MintOperationMeta {
+                    variant: MintOperationMetaVariant::KeyEpochMigration {
+                        txid,
+                        out_point_indices: (0..num_outputs)
+                            .chain(change.iter().map(|out_point| out_point.out_idx))
+                            .collect(),
+                        fee,
+                    },
+                    amount: input_amount,
+                    extra_meta: serde_json::Value::Null,
+                },
+                tx,
+            )
+            .await?;
+
+        info!(
+            %txid,
+            %input_amount,
+            %fee,
+            notes = num_notes,
+            current_epoch,
+            "Reissuing notes of expiring key epochs"
+        );
+
+        Ok(Some(operation_id))
+    }
+
+    async fn has_expiring_locked_notes(&self, current_epoch: u64) -> bool {
+        let mut dbtx = self.client_ctx.module_db().begin_transaction_nc().await;
+
+        let unredeemed_notes = dbtx
+            .find_by_prefix(&ReceivedLockedNoteKeyPrefix)
+            .await
+            .filter(|(_, received)| std::future::ready(!received.redeemed))
+            .collect::<Vec<_>>()
+            .await;
+
+        unredeemed_notes.iter().any(|(key, received)| {
+            self.is_expiring_note(current_epoch, |pks| {
+                pks.get(key.amount)
+                    .map_or(false, |pk| received.note.verify_locked(&key.recipient, *pk))
+            })
+        })
+    }
+
+    /// Whether a note verified by `verify` under the keys of its epoch belongs
+    /// to an epoch that the federation left but that did not retire yet
+    fn is_expiring_note(
+        &self,
+        current_epoch: u64,
+        verify: impl Fn(&Tiered<AggregatePublicKey>) -> bool,
+    ) -> bool {
+        (0..current_epoch)
+            .filter(|&epoch| self.cfg.key_epochs.is_expiring(epoch, current_epoch))
+            .filter_map(|epoch| self.cfg.tbs_pks_of_epoch(epoch))
+            .any(verify)
+    }
+}
+
+/// Periodically reissues the notes of expiring key epochs until the client is
+/// shut down
+pub(crate) async fn run_expiring_note_reissuance(client_ctx: ClientContext<MintClientModule>) {
+    loop {
+        // the client is only available once the module was initialized
+        sleep(EXPIRING_NOTES_CHECK_INTERVAL).await;
+
+        let Some(module) = client_ctx.try_self_ref() else {
+            return;
+        };
+
+        if let Err(e) = module.reissue_expiring_notes().await {
+            warn!("Failed to reissue notes of expiring key epochs: {e:?}");
+        }
+    }
+}
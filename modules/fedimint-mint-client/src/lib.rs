@@ -1,13 +1,24 @@
 // Backup and restore logic
+pub mod api;
 pub mod backup;
 /// Database keys used throughout the mint client module
 pub mod client_db;
+/// Background reissuance of notes to balance the held denominations
+mod consolidation;
+/// Splitting of out-of-band notes into fragments for animated QR codes
+mod fragments;
 /// State machines for mint inputs
 mod input;
+/// Reissuance of notes whose key epoch is about to retire
+mod key_epochs;
+/// Notes locked to a recipient key for offline payments
+mod locked;
 /// State machines for out-of-band transmitted e-cash notes
 mod oob;
 /// State machines for mint outputs
 pub mod output;
+/// Note selection strategies that hide the amounts paid from the federation
+mod selection;
 
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
@@ -15,6 +26,7 @@ use std::ffi;
 use std::fmt::{Display, Formatter};
 use std::io::Read;
 use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -31,9 +43,11 @@ use fedimint_client::module::{ClientContext, ClientModule, IClientModule};
 use fedimint_client::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
 use fedimint_client::sm::util::MapStateTransitions;
 use fedimint_client::sm::{Context, DynState, ModuleNotifier, State, StateTransition};
-use fedimint_client::transaction::{ClientInput, ClientOutput, TransactionBuilder};
+use fedimint_client::transaction::{
+    ClientInput, ClientOutput, TransactionBuilder, TransactionPreview,
+};
 use fedimint_client::{sm_enum_variant_translation, DynGlobalClientContext};
-use fedimint_core::api::InviteCode;
+use fedimint_core::api::{DynModuleApi, InviteCode};
 use fedimint_core::config::{FederationId, FederationIdPrefix};
 use fedimint_core::core::{Decoder, IntoDynInstance, ModuleInstanceId, OperationId};
 use fedimint_core::db::{
@@ -52,29 +66,36 @@ use fedimint_core::{
 };
 use fedimint_derive_secret::{ChildId, DerivableSecret};
 pub use fedimint_mint_common as common;
-use fedimint_mint_common::config::MintClientConfig;
+use fedimint_mint_common::config::{FeeConsensus, MintClientConfig};
 pub use fedimint_mint_common::*;
 use futures::{pin_mut, StreamExt};
 use secp256k1::{All, KeyPair, Secp256k1};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
-use tbs::AggregatePublicKey;
 use thiserror::Error;
+use tokio::sync::watch;
 use tracing::{debug, info, warn};
 
 use crate::backup::EcashBackup;
 use crate::client_db::{
-    CancelledOOBSpendKey, CancelledOOBSpendKeyPrefix, NextECashNoteIndexKey,
-    NextECashNoteIndexKeyPrefix, NoteKey, NoteKeyPrefix,
+    CancelledOOBSpendKey, CancelledOOBSpendKeyPrefix, LastConsolidationKey, LockedPaymentKeyPrefix,
+    NextECashNoteIndexKey, NextECashNoteIndexKeyPrefix, NextLockedPaymentIndexKey, NoteKey,
+    NoteKeyPrefix, NoteSelectionStrategyKey, ReceivedLockedNote, ReceivedLockedNoteKeyPrefix,
 };
+pub use crate::consolidation::NoteConsolidationPolicy;
+pub use crate::fragments::{OOBNotesFragment, OOBNotesFragmentDecoder};
 use crate::input::{
     MintInputCommon, MintInputStateCreated, MintInputStateMachine, MintInputStates,
 };
+pub use crate::locked::{LockedNotes, LockedPaymentRequest};
 use crate::oob::{MintOOBStateMachine, MintOOBStates, MintOOBStatesCreated};
 use crate::output::{
     MintOutputCommon, MintOutputStateMachine, MintOutputStates, MintOutputStatesCreated,
     NoteIssuanceRequest,
 };
+pub use crate::selection::{
+    NoteSelectionStrategy, SelectNotesRandomly, SelectNotesWithDecoys, SelectNotesWithMinimalChange,
+};
 
 const MINT_E_CASH_TYPE_CHILD_ID: ChildId = ChildId(0);
 
@@ -102,6 +123,10 @@ enum OOBNotesData {
         peer_apis: Vec<(PeerId, SafeUrl)>,
         federation_id: FederationId,
     },
+    /// Note to the recipient and metadata chosen by the sender
+    ///
+    /// Introduced in 0.3.0
+    Memo(OOBNotesMemo),
     #[encodable_default]
     Default {
         variant: u64,
@@ -109,6 +134,39 @@ enum OOBNotesData {
     },
 }
 
+/// Maximum length of [`OOBNotesMemo::memo`] in bytes
+pub const MAX_OOB_NOTES_MEMO_LEN: usize = 256;
+
+/// Maximum length of [`OOBNotesMemo::metadata`] in bytes
+pub const MAX_OOB_NOTES_METADATA_LEN: usize = 1024;
+
+/// Context the sender attached to [`OOBNotes`] for the recipient, see
+/// [`OOBNotes::with_memo`]
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct OOBNotesMemo {
+    /// Human-readable description of what the payment is for, at most
+    /// [`MAX_OOB_NOTES_MEMO_LEN`] bytes
+    pub memo: String,
+    /// Data for the recipient's software like an order id, at most
+    /// [`MAX_OOB_NOTES_METADATA_LEN`] bytes
+    pub metadata: String,
+}
+
+impl OOBNotesMemo {
+    fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.memo.len() <= MAX_OOB_NOTES_MEMO_LEN,
+            "Memo is longer than {MAX_OOB_NOTES_MEMO_LEN} bytes"
+        );
+        ensure!(
+            self.metadata.len() <= MAX_OOB_NOTES_METADATA_LEN,
+            "Metadata is longer than {MAX_OOB_NOTES_METADATA_LEN} bytes"
+        );
+
+        Ok(())
+    }
+}
+
 impl OOBNotes {
     pub fn new(
         federation_id_prefix: FederationIdPrefix,
@@ -170,6 +228,27 @@ impl OOBNotes {
             Some(InviteCode::new(api, peer_id, *federation_id))
         })
     }
+
+    /// Attaches `memo` to the notes, replacing any memo attached before. Fails
+    /// if the memo or metadata exceed their maximum length.
+    ///
+    /// Clients older than 0.3.0 ignore the memo.
+    pub fn with_memo(mut self, memo: OOBNotesMemo) -> anyhow::Result<Self> {
+        memo.validate()?;
+
+        self.0.retain(|data| !matches!(data, OOBNotesData::Memo(_)));
+        self.0.push(OOBNotesData::Memo(memo));
+
+        Ok(self)
+    }
+
+    /// The memo attached by the sender, if any
+    pub fn memo(&self) -> Option<&OOBNotesMemo> {
+        self.0.iter().find_map(|data| match data {
+            OOBNotesData::Memo(memo) => Some(memo),
+            _ => None,
+        })
+    }
 }
 
 impl Decodable for OOBNotes {
@@ -224,6 +303,12 @@ impl Decodable for OOBNotes {
             }
         }
 
+        for data in &inner {
+            if let OOBNotesData::Memo(memo) = data {
+                memo.validate().map_err(DecodeError::new_custom)?;
+            }
+        }
+
         Ok(OOBNotes(inner))
     }
 }
@@ -353,11 +438,36 @@ pub enum MintOperationMetaVariant {
         // Introduced in 0.3.0:
         #[serde(default)]
         out_point_indices: Vec<u64>,
+        /// Memo the sender attached to the reissued notes
+        // Introduced in 0.3.0:
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        memo: Option<OOBNotesMemo>,
     },
     SpendOOB {
         requested_amount: Amount,
         oob_notes: OOBNotes,
     },
+    /// Reissuance of our own notes by
+    /// [`MintClientModule::consolidate_notes`]
+    Consolidation {
+        txid: TransactionId,
+        out_point_indices: Vec<u64>,
+        fee: Amount,
+    },
+    /// Issuance of notes locked to the recipient of `request` by
+    /// [`MintClientModule::spend_locked_notes`]
+    SpendLocked {
+        txid: TransactionId,
+        out_point_indices: Vec<u64>,
+        request: LockedPaymentRequest,
+    },
+    /// Reissuance of our notes of key epochs that are about to retire by
+    /// [`MintClientModule::reissue_expiring_notes`]
+    KeyEpochMigration {
+        txid: TransactionId,
+        out_point_indices: Vec<u64>,
+        fee: Amount,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -413,6 +523,46 @@ impl ModuleInit for MintClientInit {
                 }
                 DbKeyPrefix::RecoveryState => {}
                 DbKeyPrefix::RecoveryFinalized => {}
+                DbKeyPrefix::LastConsolidation => {
+                    if let Some(last_consolidation) = dbtx.get_value(&LastConsolidationKey).await {
+                        mint_client_items.insert(
+                            "LastConsolidation".to_string(),
+                            Box::new(last_consolidation),
+                        );
+                    }
+                }
+                DbKeyPrefix::NextLockedPaymentIndex => {
+                    if let Some(index) = dbtx.get_value(&NextLockedPaymentIndexKey).await {
+                        mint_client_items
+                            .insert("NextLockedPaymentIndex".to_string(), Box::new(index));
+                    }
+                }
+                DbKeyPrefix::LockedPaymentKey => {
+                    push_db_pair_items!(
+                        dbtx,
+                        LockedPaymentKeyPrefix,
+                        LockedPaymentKey,
+                        KeyPair,
+                        mint_client_items,
+                        "LockedPaymentKeys"
+                    );
+                }
+                DbKeyPrefix::ReceivedLockedNote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ReceivedLockedNoteKeyPrefix,
+                        ReceivedLockedNoteKey,
+                        ReceivedLockedNote,
+                        mint_client_items,
+                        "ReceivedLockedNotes"
+                    );
+                }
+                DbKeyPrefix::NoteSelectionStrategy => {
+                    if let Some(strategy) = dbtx.get_value(&NoteSelectionStrategyKey).await {
+                        mint_client_items
+                            .insert("NoteSelectionStrategy".to_string(), Box::new(strategy));
+                    }
+                }
             }
         }
 
@@ -430,6 +580,21 @@ impl ClientModuleInit for MintClientInit {
     }
 
     async fn init(&self, args: &ClientModuleInitArgs<Self>) -> anyhow::Result<Self::Module> {
+        if 1 < args.cfg().key_epochs.num_epochs {
+            fedimint_core::task::spawn(
+                "mint expiring note reissuance",
+                key_epochs::run_expiring_note_reissuance(args.context()),
+            );
+        }
+
+        let selection_strategy = args
+            .db()
+            .begin_transaction_nc()
+            .await
+            .get_value(&NoteSelectionStrategyKey)
+            .await
+            .unwrap_or_default();
+
         Ok(MintClientModule {
             federation_id: *args.federation_id(),
             cfg: args.cfg().clone(),
@@ -437,6 +602,10 @@ impl ClientModuleInit for MintClientInit {
             secp: Secp256k1::new(),
             notifier: args.notifier().clone(),
             client_ctx: args.context(),
+            module_api: args.module_api().clone(),
+            consolidation_policy: watch::channel(None).0,
+            consolidation_task_spawned: AtomicBool::new(false),
+            selection_strategy: std::sync::Mutex::new(selection_strategy),
         })
     }
 
@@ -482,14 +651,18 @@ pub struct MintClientModule {
     secp: Secp256k1<All>,
     notifier: ModuleNotifier<MintClientStateMachines>,
     client_ctx: ClientContext<Self>,
+    module_api: DynModuleApi,
+    consolidation_policy: watch::Sender<Option<NoteConsolidationPolicy>>,
+    consolidation_task_spawned: AtomicBool,
+    selection_strategy: std::sync::Mutex<NoteSelectionStrategy>,
 }
 
 // TODO: wrap in Arc
 #[derive(Debug, Clone)]
 pub struct MintClientContext {
     pub mint_decoder: Decoder,
-    pub tbs_pks: Tiered<AggregatePublicKey>,
-    pub peer_tbs_pks: BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>,
+    /// Keys of all key epochs, needed to verify the blind signature shares
+    pub cfg: MintClientConfig,
     pub secret: DerivableSecret,
     // FIXME: putting a DB ref here is an antipattern, global context should become more powerful
     // but we need to consider it more carefully as its APIs will be harder to change.
@@ -519,8 +692,7 @@ impl ClientModule for MintClientModule {
     fn context(&self) -> Self::ModuleStateMachineContext {
         MintClientContext {
             mint_decoder: self.decoder(),
-            tbs_pks: self.cfg.tbs_pks.clone(),
-            peer_tbs_pks: self.cfg.peer_tbs_pks.clone(),
+            cfg: self.cfg.clone(),
             secret: self.secret.clone(),
             module_db: self.client_ctx.module_db().clone(),
         }
@@ -530,10 +702,10 @@ impl ClientModule for MintClientModule {
         &self,
         input: &<Self::Common as ModuleCommon>::Input,
     ) -> Option<TransactionItemAmount> {
-        let input = input.maybe_v0_ref()?;
+        let amount = input.maybe_amount()?;
         Some(TransactionItemAmount {
-            amount: input.amount,
-            fee: self.cfg.fee_consensus.note_spend_abs,
+            amount,
+            fee: self.cfg.fee_consensus.note_spend_fee(amount),
         })
     }
 
@@ -545,7 +717,7 @@ impl ClientModule for MintClientModule {
 
         Some(TransactionItemAmount {
             amount: output.amount,
-            fee: self.cfg.fee_consensus.note_issuance_abs,
+            fee: self.cfg.fee_consensus.note_issuance_fee(output.amount),
         })
     }
 
@@ -641,7 +813,18 @@ impl ClientModule for MintClientModule {
         amount: Amount,
     ) -> Vec<ClientOutput<MintOutput, MintClientStateMachines>> {
         // FIXME: don't hardcode notes per denomination
-        self.create_output(dbtx, operation_id, 2, amount).await
+        let notes_per_denomination = 2;
+
+        let wallet_summary = self.get_wallet_summary(dbtx).await;
+        let notes_amount =
+            self.amount_before_issuance_fees(&wallet_summary, notes_per_denomination, amount);
+
+        if notes_amount == Amount::ZERO {
+            return vec![];
+        }
+
+        self.create_output(dbtx, operation_id, notes_per_denomination, notes_amount)
+            .await
     }
 
     async fn await_primary_module_output(
@@ -768,6 +951,24 @@ impl MintClientModule {
         outputs
     }
 
+    /// Returns the amount of e-cash notes that can be issued to a wallet
+    /// holding `wallet_summary` such that the notes plus their issuance fees
+    /// add up to `amount`, see [`amount_before_issuance_fees`].
+    fn amount_before_issuance_fees(
+        &self,
+        wallet_summary: &TieredSummary,
+        notes_per_denomination: u16,
+        amount: Amount,
+    ) -> Amount {
+        amount_before_issuance_fees(
+            amount,
+            wallet_summary,
+            notes_per_denomination,
+            &self.cfg.tbs_pks,
+            &self.cfg.fee_consensus,
+        )
+    }
+
     /// Wait for the e-cash notes to be retrieved. If this is not possible
     /// because another terminal state was reached an error describing the
     /// failure is returned.
@@ -805,7 +1006,8 @@ impl MintClientModule {
     }
 
     // FIXME: use lazy e-cash note loading implemented in #2183
-    /// Creates a mint input of at least `min_amount`.
+    /// Creates a mint input of at least `min_amount`, the notes are selected
+    /// according to [`Self::note_selection_strategy`].
     pub async fn create_input(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -819,9 +1021,9 @@ impl MintClientModule {
 
         let selected_notes = Self::select_notes(
             dbtx,
-            &SelectNotesWithAtleastAmount,
+            &self.note_selection_strategy(),
             min_amount,
-            self.cfg.fee_consensus.note_spend_abs,
+            &self.cfg.fee_consensus,
         )
         .await?;
 
@@ -846,15 +1048,14 @@ impl MintClientModule {
         let mut inputs = Vec::new();
 
         for (amount, spendable_note) in notes.into_iter() {
-            let key = self
-                .cfg
+            self.cfg
                 .tbs_pks
                 .get(amount)
                 .ok_or(anyhow!("Invalid amount tier: {amount}"))?;
 
             let note = spendable_note.note();
 
-            if !note.verify(*key) {
+            if self.cfg.note_epoch(amount, &note).is_none() {
                 bail!("Invalid note");
             }
 
@@ -898,7 +1099,9 @@ impl MintClientModule {
             "zero-amount out-of-band spends are not supported"
         );
 
-        let selected_notes = Self::select_notes(dbtx, notes_selector, amount, Amount::ZERO).await?;
+        // notes spent out of band are reissued by the receiver, who pays the fees
+        let selected_notes =
+            Self::select_notes(dbtx, notes_selector, amount, &FeeConsensus::default()).await?;
 
         let operation_id = spendable_notes_to_operation_id(&selected_notes);
 
@@ -958,7 +1161,7 @@ impl MintClientModule {
         dbtx: &mut DatabaseTransaction<'_>,
         notes_selector: &impl NotesSelector<SpendableNote>,
         requested_amount: Amount,
-        fee_per_note_input: Amount,
+        fee_consensus: &FeeConsensus,
     ) -> anyhow::Result<TieredMulti<SpendableNote>> {
         let note_stream = dbtx
             .find_by_prefix_sorted_descending(&NoteKeyPrefix)
@@ -966,7 +1169,7 @@ impl MintClientModule {
             .map(|(key, note)| (key.amount, note));
 
         notes_selector
-            .select_notes(note_stream, requested_amount, fee_per_note_input)
+            .select_notes(note_stream, requested_amount, fee_consensus)
             .await
     }
 
@@ -1057,6 +1260,7 @@ impl MintClientModule {
     ) -> anyhow::Result<OperationId> {
         let notes = oob_notes.notes().clone();
         let federation_id_prefix = oob_notes.federation_id_prefix();
+        let memo = oob_notes.memo().cloned();
 
         ensure!(
             notes.total_amount() > Amount::ZERO,
@@ -1095,6 +1299,7 @@ impl MintClientModule {
                         .iter()
                         .map(|out_point| out_point.out_idx)
                         .collect(),
+                    memo: memo.clone(),
                 },
                 amount,
                 extra_meta: extra_meta.clone(),
@@ -1114,8 +1319,30 @@ impl MintClientModule {
         Ok(operation_id)
     }
 
+    /// Returns the amounts and fees of the transaction
+    /// [`MintClientModule::reissue_external_notes`] would submit for
+    /// `oob_notes` without submitting it. This does not check if the notes
+    /// have been spent already.
+    pub async fn preview_reissue_external_notes(
+        &self,
+        oob_notes: OOBNotes,
+    ) -> anyhow::Result<TransactionPreview> {
+        if oob_notes.federation_id_prefix() != self.federation_id.to_prefix() {
+            bail!("Federation ID does not match");
+        }
+
+        let mint_input = self
+            .create_input_from_notes(OperationId::new_random(), oob_notes.notes().clone())
+            .await?;
+        let tx =
+            TransactionBuilder::new().with_inputs(self.client_ctx.map_dyn(mint_input).collect());
+
+        self.client_ctx.preview_transaction(tx).await
+    }
+
     /// Subscribe to updates on the progress of a reissue operation started with
-    /// [`MintClientModule::reissue_external_notes`].
+    /// [`MintClientModule::reissue_external_notes`] or
+    /// [`MintClientModule::consolidate_notes`].
     pub async fn subscribe_reissue_external_notes(
         &self,
         operation_id: OperationId,
@@ -1126,6 +1353,7 @@ impl MintClientModule {
                 legacy_out_point,
                 txid,
                 out_point_indices,
+                ..
             } => {
                 // Either txid or legacy_out_point will be present, so we should always
                 // have a source for the txid
@@ -1141,6 +1369,23 @@ impl MintClientModule {
 
                 (txid, out_points)
             }
+            MintOperationMetaVariant::Consolidation {
+                txid,
+                out_point_indices,
+                ..
+            }
+            | MintOperationMetaVariant::KeyEpochMigration {
+                txid,
+                out_point_indices,
+                ..
+            } => {
+                let out_points = out_point_indices
+                    .into_iter()
+                    .map(|out_idx| OutPoint { txid, out_idx })
+                    .collect::<Vec<_>>();
+
+                (txid, out_points)
+            }
             _ => bail!("Operation is not a reissuance"),
         };
 
@@ -1204,6 +1449,33 @@ impl MintClientModule {
         .await
     }
 
+    /// Returns the amount of the notes [`Self::spend_notes_with_selector`]
+    /// would select for `requested_amount` without reserving them.
+    pub async fn preview_spend_notes_with_selector(
+        &self,
+        notes_selector: &impl NotesSelector<SpendableNote>,
+        requested_amount: Amount,
+    ) -> anyhow::Result<SpendNotesPreview> {
+        ensure!(
+            !notes_selector.relies_on_change(),
+            "Out-of-band spends have no change, the notes selector must not rely on it"
+        );
+
+        let mut dbtx = self.client_ctx.module_db().begin_transaction_nc().await;
+        let notes = Self::select_notes(
+            &mut dbtx,
+            notes_selector,
+            requested_amount,
+            &FeeConsensus::default(),
+        )
+        .await?;
+
+        Ok(SpendNotesPreview {
+            amount: notes.total_amount(),
+            reissue_fee: reissue_fee(&notes, &self.cfg.tbs_pks, &self.cfg.fee_consensus),
+        })
+    }
+
     /// Same as `spend_notes` but allows different to select notes to be used.
     pub async fn spend_notes_with_selector<M: Serialize + Send>(
         &self,
@@ -1213,6 +1485,33 @@ impl MintClientModule {
         include_invite: bool,
         extra_meta: M,
     ) -> anyhow::Result<(OperationId, OOBNotes)> {
+        self.spend_notes_with_selector_and_memo(
+            notes_selector,
+            requested_amount,
+            try_cancel_after,
+            include_invite,
+            None,
+            extra_meta,
+        )
+        .await
+    }
+
+    /// Same as `spend_notes_with_selector` but attaches a `memo` for the
+    /// recipient, see [`OOBNotes::with_memo`].
+    pub async fn spend_notes_with_selector_and_memo<M: Serialize + Send>(
+        &self,
+        notes_selector: &impl NotesSelector<SpendableNote>,
+        requested_amount: Amount,
+        try_cancel_after: Duration,
+        include_invite: bool,
+        memo: Option<OOBNotesMemo>,
+        extra_meta: M,
+    ) -> anyhow::Result<(OperationId, OOBNotes)> {
+        ensure!(
+            !notes_selector.relies_on_change(),
+            "Out-of-band spends have no change, the notes selector must not rely on it"
+        );
+
         let federation_id_prefix = self.federation_id.to_prefix();
         let extra_meta = serde_json::to_value(extra_meta)
             .expect("MintClientModule::spend_notes extra_meta is serializable");
@@ -1221,6 +1520,7 @@ impl MintClientModule {
             .module_autocommit(
                 move |dbtx, _| {
                     let extra_meta = extra_meta.clone();
+                    let memo = memo.clone();
                     Box::pin(async move {
                         let (operation_id, states, notes) = self
                             .spend_notes_oob(
@@ -1231,12 +1531,16 @@ impl MintClientModule {
                             )
                             .await?;
 
-                        let oob_notes = if include_invite {
+                        let mut oob_notes = if include_invite {
                             OOBNotes::new_with_invite(notes, self.client_ctx.get_invite_code())
                         } else {
                             OOBNotes::new(federation_id_prefix, notes)
                         };
 
+                        if let Some(memo) = memo {
+                            oob_notes = oob_notes.with_memo(memo)?;
+                        }
+
                         dbtx.add_state_machines(self.client_ctx.map_dyn(states).collect())
                             .await?;
                         dbtx.add_operation_log_entry(
@@ -1280,15 +1584,14 @@ impl MintClientModule {
             bail!("Federation ID does not match");
         }
 
-        let tbs_pks = &self.cfg.tbs_pks;
-
         for (idx, (amt, snote)) in notes.iter_items().enumerate() {
-            let key = tbs_pks
+            self.cfg
+                .tbs_pks
                 .get(amt)
                 .ok_or_else(|| anyhow!("Note {idx} uses an invalid amount tier {amt}"))?;
 
             let note = snote.note();
-            if !note.verify(*key) {
+            if self.cfg.note_epoch(amt, &note).is_none() {
                 bail!("Note {idx} has an invalid federation signature");
             }
 
@@ -1399,6 +1702,18 @@ pub fn spendable_notes_to_operation_id(
     )
 }
 
+/// Outcome of [`MintClientModule::preview_spend_notes_with_selector`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendNotesPreview {
+    /// Total amount of the selected notes, may exceed the requested amount
+    pub amount: Amount,
+    /// Fee the recipient will be charged by the federation for reissuing the
+    /// notes, including the issuance fees of the new notes. The new notes
+    /// depend on the notes the recipient already holds, so this assumes they
+    /// hold none.
+    pub reissue_fee: Amount,
+}
+
 pub struct SpendOOBRefund {
     pub user_triggered: bool,
     pub transaction_id: TransactionId,
@@ -1406,7 +1721,8 @@ pub struct SpendOOBRefund {
 
 #[apply(async_trait_maybe_send!)]
 pub trait NotesSelector<Note>: Send + Sync {
-    /// Select notes from stream for requested_amount.
+    /// Select notes from stream for requested_amount plus the fees for
+    /// spending the selected notes according to `fee_consensus`.
     /// The stream must produce items in non- decreasing order of amount.
     async fn select_notes(
         &self,
@@ -1414,8 +1730,15 @@ pub trait NotesSelector<Note>: Send + Sync {
         #[cfg(not(target_family = "wasm"))] stream: impl futures::Stream<Item = (Amount, Note)> + Send,
         #[cfg(target_family = "wasm")] stream: impl futures::Stream<Item = (Amount, Note)>,
         requested_amount: Amount,
-        fee_per_note_input: Amount,
+        fee_consensus: &FeeConsensus,
     ) -> anyhow::Result<TieredMulti<Note>>;
+
+    /// Whether the selected notes deliberately exceed the requested amount,
+    /// counting on the surplus to come back as change. Out-of-band spends
+    /// issue no change and reject such selectors.
+    fn relies_on_change(&self) -> bool {
+        false
+    }
 }
 
 /// Select notes with total amount of *at least* `request_amount`. If more than
@@ -1432,9 +1755,9 @@ impl<Note: Send> NotesSelector<Note> for SelectNotesWithAtleastAmount {
         #[cfg(not(target_family = "wasm"))] stream: impl futures::Stream<Item = (Amount, Note)> + Send,
         #[cfg(target_family = "wasm")] stream: impl futures::Stream<Item = (Amount, Note)>,
         requested_amount: Amount,
-        fee_per_note_input: Amount,
+        fee_consensus: &FeeConsensus,
     ) -> anyhow::Result<TieredMulti<Note>> {
-        Ok(select_notes_from_stream(stream, requested_amount, fee_per_note_input).await?)
+        Ok(select_notes_from_stream(stream, requested_amount, fee_consensus).await?)
     }
 }
 
@@ -1450,9 +1773,9 @@ impl<Note: Send> NotesSelector<Note> for SelectNotesWithExactAmount {
         #[cfg(not(target_family = "wasm"))] stream: impl futures::Stream<Item = (Amount, Note)> + Send,
         #[cfg(target_family = "wasm")] stream: impl futures::Stream<Item = (Amount, Note)>,
         requested_amount: Amount,
-        note_fee: Amount,
+        fee_consensus: &FeeConsensus,
     ) -> anyhow::Result<TieredMulti<Note>> {
-        let notes = select_notes_from_stream(stream, requested_amount, note_fee).await?;
+        let notes = select_notes_from_stream(stream, requested_amount, fee_consensus).await?;
 
         if notes.total_amount() != requested_amount {
             bail!(
@@ -1466,6 +1789,93 @@ impl<Note: Send> NotesSelector<Note> for SelectNotesWithExactAmount {
     }
 }
 
+/// Sum of the fees for spending all of `notes`
+fn total_spend_fees<Note>(notes: &TieredMulti<Note>, fee_consensus: &FeeConsensus) -> Amount {
+    notes
+        .iter_items()
+        .map(|(amount, _)| fee_consensus.note_spend_fee(amount))
+        .sum()
+}
+
+/// Returns the amount of e-cash notes in the denominations of `tiers` that can
+/// be issued to a wallet holding `wallet_summary` such that the notes plus
+/// their issuance fees add up to `amount`.
+///
+/// Since the fees depend on the notes chosen this searches for a fixed point,
+/// if none is found after a few iterations the largest amount whose fees do
+/// not exceed `amount` is returned and the transaction will fail to balance.
+fn amount_before_issuance_fees<K>(
+    amount: Amount,
+    wallet_summary: &TieredSummary,
+    notes_per_denomination: u16,
+    tiers: &Tiered<K>,
+    fee_consensus: &FeeConsensus,
+) -> Amount {
+    const MAX_FEE_ITERATIONS: usize = 16;
+
+    let issuance_fees = |notes_amount: Amount| {
+        TieredSummary::represent_amount(notes_amount, wallet_summary, tiers, notes_per_denomination)
+            .iter()
+            .map(|(note_amount, count)| fee_consensus.note_issuance_fee(note_amount) * count as u64)
+            .sum::<Amount>()
+    };
+
+    let mut best = Amount::ZERO;
+    let mut notes_amount = amount;
+    for _ in 0..MAX_FEE_ITERATIONS {
+        let total = notes_amount + issuance_fees(notes_amount);
+
+        if total == amount {
+            return notes_amount;
+        }
+
+        if total < amount {
+            best = best.max(notes_amount);
+            notes_amount += amount - total;
+        } else if total - amount >= notes_amount {
+            break;
+        } else {
+            notes_amount -= total - amount;
+        }
+    }
+
+    warn!(
+        %amount,
+        %best,
+        "Could not find notes matching the amount including issuance fees"
+    );
+
+    best
+}
+
+/// Fees a recipient without any notes is charged for reissuing `notes`, that
+/// is the fees for spending them plus the issuance fees for the notes the
+/// remaining amount is reissued into
+fn reissue_fee<Note, K>(
+    notes: &TieredMulti<Note>,
+    tiers: &Tiered<K>,
+    fee_consensus: &FeeConsensus,
+) -> Amount {
+    // same as the hardcoded value in `create_exact_output` of the recipient
+    let notes_per_denomination = 2;
+
+    let total_amount = notes.total_amount();
+    let spend_fees = total_spend_fees(notes, fee_consensus);
+    if spend_fees >= total_amount {
+        return total_amount;
+    }
+
+    let reissued_amount = amount_before_issuance_fees(
+        total_amount - spend_fees,
+        &TieredSummary::default(),
+        notes_per_denomination,
+        tiers,
+        fee_consensus,
+    );
+
+    total_amount - reissued_amount
+}
+
 // We are using a greedy algorithm to select notes. We start with the largest
 // then proceed to the lowest tiers/denominations.
 // But there is a catch: we don't know if there are enough notes in the lowest
@@ -1474,7 +1884,7 @@ impl<Note: Send> NotesSelector<Note> for SelectNotesWithExactAmount {
 async fn select_notes_from_stream<Note>(
     stream: impl futures::Stream<Item = (Amount, Note)>,
     requested_amount: Amount,
-    fee_per_note_input: Amount,
+    fee_consensus: &FeeConsensus,
 ) -> Result<TieredMulti<Note>, InsufficientBalanceError> {
     if requested_amount == Amount::ZERO {
         return Ok(TieredMulti::default());
@@ -1496,14 +1906,16 @@ async fn select_notes_from_stream<Note>(
             );
             previous_amount = Some(note_amount);
 
-            if note_amount <= fee_per_note_input {
+            let note_fee = fee_consensus.note_spend_fee(note_amount);
+
+            if note_amount <= note_fee {
                 continue;
             }
 
-            match note_amount.cmp(&(pending_amount + fee_per_note_input)) {
+            match note_amount.cmp(&(pending_amount + note_fee)) {
                 Ordering::Less => {
                     // keep adding notes until we have enough
-                    pending_amount += fee_per_note_input;
+                    pending_amount += note_fee;
                     pending_amount -= note_amount;
                     selected.push((note_amount, note))
                 }
@@ -1520,9 +1932,8 @@ async fn select_notes_from_stream<Note>(
                     let notes: TieredMulti<Note> = selected.into_iter().collect();
 
                     assert!(
-                        notes.total_amount().msats
-                            >= requested_amount.msats
-                                + notes.count_items() as u64 * fee_per_note_input.msats
+                        notes.total_amount()
+                            >= requested_amount + total_spend_fees(&notes, fee_consensus)
                     );
 
                     return Ok(notes);
@@ -1540,15 +1951,14 @@ async fn select_notes_from_stream<Note>(
                 let notes: TieredMulti<Note> = selected.into_iter().collect();
 
                 assert!(
-                    notes.total_amount().msats
-                        >= requested_amount.msats
-                            + notes.count_items() as u64 * fee_per_note_input.msats
+                    notes.total_amount()
+                        >= requested_amount + total_spend_fees(&notes, fee_consensus)
                 );
 
                 // so now we have enough to cover the requested amount, return
                 return Ok(notes);
             } else {
-                let total_amount = requested_amount - pending_amount;
+                let total_amount = selected.iter().map(|(amount, _)| *amount).sum();
                 // not enough notes, return
                 return Err(InsufficientBalanceError {
                     requested_amount,
@@ -1748,14 +2158,17 @@ mod tests {
     use fedimint_core::api::InviteCode;
     use fedimint_core::config::FederationId;
     use fedimint_core::encoding::Decodable;
+    use fedimint_core::module::fee::ProportionalFee;
     use fedimint_core::{
         Amount, OutPoint, PeerId, Tiered, TieredMulti, TieredSummary, TransactionId,
     };
+    use fedimint_mint_common::config::FeeConsensus;
     use itertools::Itertools;
     use serde_json::json;
 
     use crate::{
-        select_notes_from_stream, MintOperationMetaVariant, OOBNotes, OOBNotesData, SpendableNote,
+        reissue_fee, select_notes_from_stream, total_spend_fees, MintOperationMetaVariant,
+        OOBNotes, OOBNotesData, OOBNotesMemo, SpendableNote, MAX_OOB_NOTES_MEMO_LEN,
     };
 
     #[test_log::test(tokio::test)]
@@ -1771,7 +2184,7 @@ mod tests {
             let select = select_notes_from_stream(
                 stream,
                 Amount::from_sats(multiplier * 1000),
-                Amount::ZERO,
+                &FeeConsensus::default(),
             )
             .await;
             total_notes += select.unwrap().into_iter_items().count();
@@ -1789,13 +2202,13 @@ mod tests {
             ])
         };
         assert_eq!(
-            select_notes_from_stream(f(), Amount::from_sats(7), Amount::ZERO)
+            select_notes_from_stream(f(), Amount::from_sats(7), &FeeConsensus::default())
                 .await
                 .unwrap(),
             notes(vec![(Amount::from_sats(1), 2), (Amount::from_sats(5), 1)])
         );
         assert_eq!(
-            select_notes_from_stream(f(), Amount::from_sats(20), Amount::ZERO)
+            select_notes_from_stream(f(), Amount::from_sats(20), &FeeConsensus::default())
                 .await
                 .unwrap(),
             notes(vec![(Amount::from_sats(20), 1)])
@@ -1810,7 +2223,7 @@ mod tests {
             (Amount::from_sats(20), 5),
         ]);
         assert_eq!(
-            select_notes_from_stream(stream, Amount::from_sats(7), Amount::ZERO)
+            select_notes_from_stream(stream, Amount::from_sats(7), &FeeConsensus::default())
                 .await
                 .unwrap(),
             notes(vec![(Amount::from_sats(5), 2)])
@@ -1825,19 +2238,73 @@ mod tests {
             (Amount::from_sats(20), 2),
         ]);
         assert_eq!(
-            select_notes_from_stream(stream, Amount::from_sats(39), Amount::ZERO)
+            select_notes_from_stream(stream, Amount::from_sats(39), &FeeConsensus::default())
                 .await
                 .unwrap(),
             notes(vec![(Amount::from_sats(20), 2)])
         );
     }
 
+    #[test_log::test(tokio::test)]
+    async fn select_notes_covers_proportional_spend_fees() {
+        let fee_consensus = FeeConsensus {
+            note_spend_ppm: ProportionalFee::new(100_000, Amount::ZERO, None),
+            ..FeeConsensus::default()
+        };
+        let f = || {
+            reverse_sorted_note_stream(vec![
+                (Amount::from_sats(1), 10),
+                (Amount::from_sats(10), 10),
+            ])
+        };
+
+        // 10% of a 10 sat note leaves 9 sats, the rest has to be covered by
+        // two 1 sat notes which are charged 100 msats each
+        let selected = select_notes_from_stream(f(), Amount::from_sats(10), &fee_consensus)
+            .await
+            .unwrap();
+        assert_eq!(
+            selected,
+            notes(vec![(Amount::from_sats(10), 1), (Amount::from_sats(1), 2)])
+        );
+
+        // the fee makes a single note unable to cover its own amount
+        let error = select_notes_from_stream(
+            reverse_sorted_note_stream(vec![(Amount::from_sats(10), 1)]),
+            Amount::from_sats(10),
+            &fee_consensus,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.total_amount, Amount::from_sats(10));
+    }
+
+    #[test]
+    fn reissue_fee_includes_issuance_fees() {
+        let tiers = Tiered::gen_denominations(2, Amount::from_sats(1));
+        let fee_consensus = FeeConsensus {
+            note_spend_abs: Amount::from_msats(2),
+            note_issuance_abs: Amount::from_msats(1),
+            ..FeeConsensus::default()
+        };
+
+        // spending the 64 msat note costs 2 msats, the remaining 62 msats are
+        // reissued as 51 msats in 11 notes, two of every denomination up to 8
+        // msats and 16 + 2 + 1 msats, which cost 1 msat each
+        let notes = notes(vec![(Amount::from_msats(64), 1)]);
+        let fee = reissue_fee(&notes, &tiers, &fee_consensus);
+
+        assert!(fee > total_spend_fees(&notes, &fee_consensus));
+        assert_eq!(fee, Amount::from_msats(13));
+    }
+
     #[test_log::test(tokio::test)]
     async fn select_notes_returns_error_if_amount_is_too_large() {
         let stream = reverse_sorted_note_stream(vec![(Amount::from_sats(10), 1)]);
-        let error = select_notes_from_stream(stream, Amount::from_sats(100), Amount::ZERO)
-            .await
-            .unwrap_err();
+        let error =
+            select_notes_from_stream(stream, Amount::from_sats(100), &FeeConsensus::default())
+                .await
+                .unwrap_err();
         assert_eq!(error.total_amount, Amount::from_sats(10));
     }
 
@@ -1945,6 +2412,58 @@ mod tests {
         assert!(notes_inconsistent_str.parse::<OOBNotes>().is_err());
     }
 
+    #[test]
+    fn notes_with_memo_encode_decode() {
+        let federation_id = FederationId(bitcoin_hashes::sha256::Hash::from_inner([0x21; 32]));
+        let notes = vec![(
+            Amount::from_sats(1),
+            SpendableNote::consensus_decode_hex("a5dd3ebacad1bc48bd8718eed5a8da1d68f91323bef2848ac4fa2e6f8eed710f3178fd4aef047cc234e6b1127086f33cc408b39818781d9521475360de6b205f3328e490a6d99d5e2553a4553207c8bd", &Default::default()).unwrap(),
+        )]
+        .into_iter()
+        .collect::<TieredMulti<_>>();
+        let memo = OOBNotesMemo {
+            memo: "Pizza".to_string(),
+            metadata: r#"{"order":42}"#.to_string(),
+        };
+
+        let notes_memo = OOBNotes::new(federation_id.to_prefix(), notes.clone())
+            .with_memo(memo.clone())
+            .unwrap();
+        test_roundtrip_serialize_str(notes_memo, |oob_notes| {
+            assert_eq!(oob_notes.notes(), &notes);
+            assert_eq!(oob_notes.memo(), Some(&memo));
+        });
+
+        // A memo attached later replaces the previous one
+        let other_memo = OOBNotesMemo {
+            memo: "Pasta".to_string(),
+            metadata: String::new(),
+        };
+        let notes_other_memo = OOBNotes::new(federation_id.to_prefix(), notes.clone())
+            .with_memo(memo)
+            .unwrap()
+            .with_memo(other_memo.clone())
+            .unwrap();
+        assert_eq!(notes_other_memo.memo(), Some(&other_memo));
+        assert_eq!(notes_other_memo.0.len(), 3);
+
+        // Rejects memos exceeding the size limit, also when decoding
+        let long_memo = OOBNotesMemo {
+            memo: "a".repeat(MAX_OOB_NOTES_MEMO_LEN + 1),
+            metadata: String::new(),
+        };
+        assert!(OOBNotes::new(federation_id.to_prefix(), notes.clone())
+            .with_memo(long_memo.clone())
+            .is_err());
+
+        let notes_long_memo = OOBNotes(vec![
+            OOBNotesData::FederationIdPrefix(federation_id.to_prefix()),
+            OOBNotesData::Notes(notes),
+            OOBNotesData::Memo(long_memo),
+        ]);
+        assert!(notes_long_memo.to_string().parse::<OOBNotes>().is_err());
+    }
+
     #[test]
     fn reissuance_meta_compatibility_02_03() {
         let dummy_outpoint = OutPoint {
@@ -1966,6 +2485,7 @@ mod tests {
                 legacy_out_point: Some(dummy_outpoint),
                 txid: None,
                 out_point_indices: vec![],
+                memo: None,
             }
         );
 
@@ -1973,6 +2493,7 @@ mod tests {
             legacy_out_point: None,
             txid: Some(dummy_outpoint.txid),
             out_point_indices: vec![0],
+            memo: None,
         })
         .expect("serializing always works");
         assert_eq!(
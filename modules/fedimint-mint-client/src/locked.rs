@@ -0,0 +1,543 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, ensure};
+use base64::Engine as _;
+use fedimint_client::module::ClientDbTxContext;
+use fedimint_client::transaction::{ClientInput, ClientOutput, TransactionBuilder};
+use fedimint_core::config::FederationIdPrefix;
+use fedimint_core::core::OperationId;
+use fedimint_core::db::{AutocommitError, IDatabaseTransactionOpsCoreTyped};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::module::CommonModuleInit;
+use fedimint_core::util::NextOrPending;
+use fedimint_core::{Amount, OutPoint, TieredMulti, TieredSummary};
+use fedimint_derive_secret::ChildId;
+use fedimint_mint_common::{BlindNonce, MintCommonInit, MintInput, MintOutput, Note};
+use futures::{pin_mut, StreamExt};
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::client_db::{
+    LockedPaymentKey, NextLockedPaymentIndexKey, ReceivedLockedNote, ReceivedLockedNoteKey,
+    ReceivedLockedNoteKeyPrefix,
+};
+use crate::input::{
+    MintInputCommon, MintInputStateCreatedLocked, MintInputStateMachine, MintInputStates,
+};
+use crate::output::{
+    MintOutputCommon, MintOutputStateMachine, MintOutputStates, MintOutputStatesCreatedLocked,
+    NoteIssuanceRequest,
+};
+use crate::{
+    MintClientModule, MintClientStateMachines, MintOperationMeta, MintOperationMetaVariant,
+    BASE64_URL_SAFE,
+};
+
+/// Child ID used to derive the nonces of notes we lock to someone else, kept
+/// apart from our own notes so recovery never mistakes them for ours
+const MINT_LOCKED_NOTE_TYPE_CHILD_ID: ChildId = ChildId(1);
+
+/// Child ID used to derive the keys of our locked payment requests
+const MINT_LOCKED_PAYMENT_TYPE_CHILD_ID: ChildId = ChildId(2);
+
+/// Request for a payment in notes only spendable by `recipient`, created with
+/// [`MintClientModule::create_locked_payment_request`] and paid with
+/// [`MintClientModule::spend_locked_notes`]
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct LockedPaymentRequest {
+    pub federation_id_prefix: FederationIdPrefix,
+    pub amount: Amount,
+    pub recipient: PublicKey,
+}
+
+/// Notes locked to the recipient of a [`LockedPaymentRequest`]
+///
+/// Since the mint's signatures commit to the recipient key the notes can be
+/// verified offline with [`MintClientModule::receive_locked_notes`] and the
+/// payer cannot spend them anymore once they were handed over.
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct LockedNotes {
+    pub federation_id_prefix: FederationIdPrefix,
+    pub recipient: PublicKey,
+    pub notes: TieredMulti<Note>,
+}
+
+impl LockedNotes {
+    /// Returns the total value of all notes
+    pub fn total_amount(&self) -> Amount {
+        self.notes.total_amount()
+    }
+}
+
+/// Implements base64 string encoding for consensus encodable types that are
+/// exchanged between users, the same way as for [`crate::OOBNotes`]
+macro_rules! impl_base64_string_encoding {
+    ($name:ident) => {
+        impl FromStr for $name {
+            type Err = anyhow::Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let bytes = if let Ok(bytes) = BASE64_URL_SAFE.decode(s) {
+                    bytes
+                } else {
+                    base64::engine::general_purpose::STANDARD.decode(s)?
+                };
+
+                Ok(Decodable::consensus_decode(
+                    &mut std::io::Cursor::new(bytes),
+                    &ModuleDecoderRegistry::default(),
+                )?)
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                let mut bytes = Vec::new();
+                Encodable::consensus_encode(self, &mut bytes).expect("encodes correctly");
+                f.write_str(&base64::engine::general_purpose::STANDARD.encode(&bytes))
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                let s = String::deserialize(deserializer)?;
+                FromStr::from_str(&s).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+impl_base64_string_encoding!(LockedPaymentRequest);
+impl_base64_string_encoding!(LockedNotes);
+
+impl MintClientModule {
+    /// Creates a request for a payment of `amount` in notes locked to a fresh
+    /// key of ours.
+    ///
+    /// Notes paid to the request can be accepted while offline with
+    /// [`MintClientModule::receive_locked_notes`] and redeemed later with
+    /// [`MintClientModule::redeem_locked_notes`].
+    pub async fn create_locked_payment_request(
+        &self,
+        amount: Amount,
+    ) -> anyhow::Result<LockedPaymentRequest> {
+        ensure!(
+            amount > Amount::ZERO,
+            "zero-amount payment requests are not supported"
+        );
+
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        let index = dbtx
+            .get_value(&NextLockedPaymentIndexKey)
+            .await
+            .unwrap_or(0);
+        dbtx.insert_entry(&NextLockedPaymentIndexKey, &(index + 1))
+            .await;
+
+        let key = self
+            .secret
+            .child_key(MINT_LOCKED_PAYMENT_TYPE_CHILD_ID)
+            .child_key(ChildId(index))
+            .to_secp_key(&self.secp);
+        dbtx.insert_entry(&LockedPaymentKey(key.public_key()), &key)
+            .await;
+
+        dbtx.commit_tx_result().await?;
+
+        Ok(LockedPaymentRequest {
+            federation_id_prefix: self.federation_id.to_prefix(),
+            amount,
+            recipient: key.public_key(),
+        })
+    }
+
+    /// Pays `request` by issuing notes locked to its recipient, funded by the
+    /// primary module which also pays the issuance fees. The notes can be
+    /// retrieved with [`MintClientModule::await_locked_notes`] once issued.
+    pub async fn spend_locked_notes<M: Serialize + Send>(
+        &self,
+        request: LockedPaymentRequest,
+        extra_meta: M,
+    ) -> anyhow::Result<OperationId> {
+        ensure!(
+            request.amount > Amount::ZERO,
+            "zero-amount payment requests are not supported"
+        );
+
+        if request.federation_id_prefix != self.federation_id.to_prefix() {
+            bail!("Federation ID does not match");
+        }
+
+        let operation_id = OperationId::new_random();
+        let outputs = self.create_locked_output(operation_id, request.amount, request.recipient);
+        let num_outputs = outputs.len() as u64;
+
+        let tx = TransactionBuilder::new().with_outputs(self.client_ctx.map_dyn(outputs).collect());
+
+        let amount = request.amount;
+        let extra_meta = serde_json::to_value(extra_meta)
+            .expect("MintClientModule::spend_locked_notes extra_meta is serializable");
+        let operation_meta_gen = move |txid, _change: Vec<OutPoint>| MintOperationMeta {
+            variant: MintOperationMetaVariant::SpendLocked {
+                txid,
+                out_point_indices: (0..num_outputs).collect(),
+                request: request.clone(),
+            },
+            amount,
+            extra_meta: extra_meta.clone(),
+        };
+
+        self.client_ctx
+            .finalize_and_submit_transaction(
+                operation_id,
+                MintCommonInit::KIND.as_str(),
+                operation_meta_gen,
+                tx,
+            )
+            .await?;
+
+        Ok(operation_id)
+    }
+
+    /// Waits for the notes issued by [`MintClientModule::spend_locked_notes`]
+    /// to hand them to the recipient out of band
+    pub async fn await_locked_notes(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<LockedNotes> {
+        let operation = self.mint_operation(operation_id).await?;
+        let MintOperationMetaVariant::SpendLocked {
+            txid,
+            out_point_indices,
+            request,
+        } = operation.meta::<MintOperationMeta>().variant
+        else {
+            bail!("Operation is not a locked spend");
+        };
+
+        let mut notes = Vec::new();
+        for out_idx in out_point_indices {
+            notes.push(
+                self.await_locked_output_finalized(operation_id, OutPoint { txid, out_idx })
+                    .await?,
+            );
+        }
+
+        Ok(LockedNotes {
+            federation_id_prefix: request.federation_id_prefix,
+            recipient: request.recipient,
+            notes: notes.into_iter().collect(),
+        })
+    }
+
+    /// Verifies `locked_notes` and stores them to be redeemed with
+    /// [`MintClientModule::redeem_locked_notes`], returning their total amount.
+    ///
+    /// This works offline: the notes are checked against the federation's
+    /// public keys and have to be locked to one of our payment requests, so
+    /// the payer cannot spend them anymore. Notes that were received before
+    /// are rejected.
+    pub async fn receive_locked_notes(&self, locked_notes: LockedNotes) -> anyhow::Result<Amount> {
+        if locked_notes.federation_id_prefix != self.federation_id.to_prefix() {
+            bail!("Federation ID does not match");
+        }
+
+        ensure!(
+            locked_notes.total_amount() > Amount::ZERO,
+            "Receiving zero-amount e-cash isn't supported"
+        );
+
+        let recipient = locked_notes.recipient;
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        ensure!(
+            dbtx.get_value(&LockedPaymentKey(recipient)).await.is_some(),
+            "Notes are not locked to one of our payment requests"
+        );
+
+        for (amount, note) in locked_notes.notes.iter_items() {
+            self.cfg
+                .tbs_pks
+                .get(amount)
+                .ok_or(anyhow!("Invalid amount tier: {amount}"))?;
+
+            ensure!(
+                self.cfg
+                    .locked_note_epoch(amount, note, &recipient)
+                    .is_some(),
+                "Invalid note"
+            );
+
+            let received_note = ReceivedLockedNote {
+                note: *note,
+                redeemed: false,
+            };
+            ensure!(
+                dbtx.insert_entry(
+                    &ReceivedLockedNoteKey {
+                        recipient,
+                        amount,
+                        nonce: note.nonce,
+                    },
+                    &received_note,
+                )
+                .await
+                .is_none(),
+                "Note was already received"
+            );
+        }
+
+        dbtx.commit_tx_result().await?;
+
+        Ok(locked_notes.total_amount())
+    }
+
+    /// Reissues all notes accepted with
+    /// [`MintClientModule::receive_locked_notes`] that were not redeemed yet
+    /// into our wallet, paying the spend fees.
+    ///
+    /// Returns `None` if there is nothing to redeem, otherwise the progress can
+    /// be observed with [`MintClientModule::subscribe_reissue_external_notes`].
+    pub async fn redeem_locked_notes<M: Serialize + Send>(
+        &self,
+        extra_meta: M,
+    ) -> anyhow::Result<Option<OperationId>> {
+        let extra_meta = serde_json::to_value(extra_meta)
+            .expect("MintClientModule::redeem_locked_notes extra_meta is serializable");
+
+        self.client_ctx
+            .module_autocommit(
+                move |dbtx, _| {
+                    let extra_meta = extra_meta.clone();
+                    Box::pin(async move { self.redeem_locked_notes_dbtx(dbtx, extra_meta).await })
+                },
+                Some(100),
+            )
+            .await
+            .map_err(|e| match e {
+                AutocommitError::ClosureError { error, .. } => error,
+                AutocommitError::CommitFailed { last_error, .. } => {
+                    anyhow!("Commit to DB failed: {last_error}")
+                }
+            })
+    }
+
+    async fn redeem_locked_notes_dbtx(
+        &self,
+        dbtx: &mut ClientDbTxContext<'_, '_, Self>,
+        extra_meta: serde_json::Value,
+    ) -> anyhow::Result<Option<OperationId>> {
+        let received_notes = dbtx
+            .module_dbtx()
+            .find_by_prefix(&ReceivedLockedNoteKeyPrefix)
+            .await
+            .filter(|(_, received)| std::future::ready(!received.redeemed))
+            .collect::<Vec<_>>()
+            .await;
+
+        if received_notes.is_empty() {
+            return Ok(None);
+        }
+
+        let operation_id = OperationId::new_random();
+        let mut inputs = Vec::new();
+        let mut amount = Amount::ZERO;
+
+        for (key, received) in received_notes {
+            let keypair = dbtx
+                .module_dbtx()
+                .get_value(&LockedPaymentKey(key.recipient))
+                .await
+                .ok_or(anyhow!("Missing key of locked payment request"))?;
+
+            dbtx.module_dbtx()
+                .insert_entry(
+                    &key,
+                    &ReceivedLockedNote {
+                        redeemed: true,
+                        ..received
+                    },
+                )
+                .await;
+
+            amount += key.amount;
+            inputs.push(ClientInput::<MintInput, MintClientStateMachines> {
+                input: MintInput::new_v1(key.amount, received.note, key.recipient),
+                keys: vec![keypair],
+                // Marks the note as not redeemed again if the transaction is rejected
+                state_machines: Arc::new(move |txid, input_idx| {
+                    vec![MintClientStateMachines::Input(MintInputStateMachine {
+                        common: MintInputCommon {
+                            operation_id,
+                            txid,
+                            input_idx,
+                        },
+                        state: MintInputStates::CreatedLocked(MintInputStateCreatedLocked {
+                            amount: key.amount,
+                            note: received.note,
+                            recipient: key.recipient,
+                        }),
+                    })]
+                }),
+            });
+        }
+
+        let tx = TransactionBuilder::new().with_inputs(self.client_ctx.map_dyn(inputs).collect());
+
+        dbtx.finalize_and_submit_transaction(
+            operation_id,
+            MintCommonInit::KIND.as_str(),
+            |txid, change| MintOperationMeta {
+                variant: MintOperationMetaVariant::Reissuance {
+                    legacy_out_point: None,
+                    txid: Some(txid),
+                    out_point_indices: change.iter().map(|out_point| out_point.out_idx).collect(),
+                    memo: None,
+                },
+                amount,
+                extra_meta,
+            },
+            tx,
+        )
+        .await?;
+
+        Ok(Some(operation_id))
+    }
+
+    /// Creates outputs issuing notes of exactly `amount` locked to `recipient`
+    fn create_locked_output(
+        &self,
+        operation_id: OperationId,
+        amount: Amount,
+        recipient: PublicKey,
+    ) -> Vec<ClientOutput<MintOutput, MintClientStateMachines>> {
+        // the notes leave our wallet, so our own denominations are irrelevant
+        let denominations = TieredSummary::represent_amount(
+            amount,
+            &TieredSummary::default(),
+            &self.cfg.tbs_pks,
+            1,
+        );
+
+        let mut outputs = Vec::new();
+
+        for (amount, num) in denominations.iter() {
+            for _ in 0..num {
+                let secret = self
+                    .secret
+                    .child_key(MINT_LOCKED_NOTE_TYPE_CHILD_ID)
+                    .child_key(ChildId(rand::random()));
+                let (issuance_request, _) = NoteIssuanceRequest::new(&self.secp, secret);
+                let blind_nonce = BlindNonce(issuance_request.blinded_locked_message(&recipient));
+
+                let state_generator = Arc::new(move |txid, out_idx| {
+                    vec![MintClientStateMachines::Output(MintOutputStateMachine {
+                        common: MintOutputCommon {
+                            operation_id,
+                            out_point: OutPoint { txid, out_idx },
+                        },
+                        state: MintOutputStates::CreatedLocked(MintOutputStatesCreatedLocked {
+                            amount,
+                            issuance_request,
+                            recipient,
+                        }),
+                    })]
+                });
+
+                outputs.push(ClientOutput {
+                    output: MintOutput::new_v0(amount, blind_nonce),
+                    state_machines: state_generator,
+                });
+            }
+        }
+
+        outputs
+    }
+
+    async fn await_locked_output_finalized(
+        &self,
+        operation_id: OperationId,
+        out_point: OutPoint,
+    ) -> anyhow::Result<(Amount, Note)> {
+        let stream = self
+            .notifier
+            .subscribe(operation_id)
+            .await
+            .filter_map(|state| async move {
+                let MintClientStateMachines::Output(state) = state else {
+                    return None;
+                };
+
+                if state.common.out_point != out_point {
+                    return None;
+                }
+
+                match state.state {
+                    MintOutputStates::SucceededLocked(succeeded) => {
+                        Some(Ok((succeeded.amount, succeeded.note)))
+                    }
+                    MintOutputStates::Aborted(_) => Some(Err(anyhow!("Transaction was rejected"))),
+                    MintOutputStates::Failed(failed) => Some(Err(anyhow!(
+                        "Failed to finalize transaction: {}",
+                        failed.error
+                    ))),
+                    _ => None,
+                }
+            });
+        pin_mut!(stream);
+
+        stream.next_or_pending().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use fedimint_core::config::FederationId;
+    use fedimint_core::encoding::Decodable;
+    use fedimint_core::{Amount, TieredMulti};
+
+    use super::{LockedNotes, LockedPaymentRequest};
+    use crate::SpendableNote;
+
+    #[test]
+    fn locked_payment_encoding_roundtrip() {
+        let spendable_note = SpendableNote::consensus_decode_hex("a5dd3ebacad1bc48bd8718eed5a8da1d68f91323bef2848ac4fa2e6f8eed710f3178fd4aef047cc234e6b1127086f33cc408b39818781d9521475360de6b205f3328e490a6d99d5e2553a4553207c8bd", &Default::default()).unwrap();
+        let recipient = spendable_note.spend_key.public_key();
+
+        let request = LockedPaymentRequest {
+            federation_id_prefix: FederationId::dummy().to_prefix(),
+            amount: Amount::from_sats(21),
+            recipient,
+        };
+        assert_eq!(
+            LockedPaymentRequest::from_str(&request.to_string()).unwrap(),
+            request
+        );
+
+        let notes = LockedNotes {
+            federation_id_prefix: request.federation_id_prefix,
+            recipient,
+            notes: TieredMulti::from_iter([(Amount::from_sats(1), spendable_note.note())]),
+        };
+        let json = serde_json::to_string(&notes).unwrap();
+        assert_eq!(serde_json::from_str::<LockedNotes>(&json).unwrap(), notes);
+    }
+}
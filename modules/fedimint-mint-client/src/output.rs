@@ -13,16 +13,17 @@ use fedimint_core::endpoint_constants::AWAIT_OUTPUT_OUTCOME_ENDPOINT;
 use fedimint_core::module::ApiRequestErased;
 use fedimint_core::query::FilterMapThreshold;
 use fedimint_core::task::sleep;
-use fedimint_core::{Amount, NumPeers, OutPoint, PeerId, Tiered};
+use fedimint_core::{Amount, NumPeers, OutPoint, PeerId};
 use fedimint_derive_secret::{ChildId, DerivableSecret};
-use fedimint_mint_common::{BlindNonce, MintOutputOutcome, Nonce};
-use secp256k1::{KeyPair, Secp256k1, Signing};
+use fedimint_mint_common::config::MintClientConfig;
+use fedimint_mint_common::{BlindNonce, MintOutputOutcome, Nonce, Note};
+use secp256k1::{KeyPair, PublicKey, Secp256k1, Signing};
 use serde::{Deserialize, Serialize};
 use tbs::{
-    aggregate_signature_shares, blind_message, unblind_signature, AggregatePublicKey,
-    BlindedMessage, BlindedSignature, BlindedSignatureShare, BlindingKey, PublicKeyShare,
+    aggregate_signature_shares, blind_message, unblind_signature, BlindedMessage, BlindedSignature,
+    BlindedSignatureShare, BlindingKey,
 };
-use tracing::error;
+use tracing::{error, warn};
 
 use crate::client_db::NoteKey;
 use crate::{MintClientContext, SpendableNote};
@@ -63,6 +64,12 @@ pub enum MintOutputStates {
     /// The issuance was completed successfully and the e-cash notes added to
     /// our wallet
     Succeeded(MintOutputStatesSucceeded),
+    /// Issuance request for a note locked to a recipient key was created, we
+    /// are waiting for blind signatures
+    CreatedLocked(MintOutputStatesCreatedLocked),
+    /// The locked note was issued, it is kept in the state to be handed to the
+    /// recipient instead of being added to our wallet
+    SucceededLocked(MintOutputStatesSucceededLocked),
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
@@ -98,6 +105,12 @@ impl State for MintOutputStateMachine {
             MintOutputStates::Succeeded(_) => {
                 vec![]
             }
+            MintOutputStates::CreatedLocked(created) => {
+                created.transitions(context, global_context, self.common)
+            }
+            MintOutputStates::SucceededLocked(_) => {
+                vec![]
+            }
         }
     }
 
@@ -121,7 +134,7 @@ impl MintOutputStatesCreated {
         global_context: &DynGlobalClientContext,
         common: MintOutputCommon,
     ) -> Vec<StateTransition<MintOutputStateMachine>> {
-        let tbs_pks = context.tbs_pks.clone();
+        let cfg = context.cfg.clone();
 
         vec![
             // Check if transaction was rejected
@@ -137,14 +150,15 @@ impl MintOutputStatesCreated {
                     context.mint_decoder.clone(),
                     self.amount,
                     self.issuance_request.blinded_message(),
-                    context.peer_tbs_pks.clone(),
+                    context.cfg.clone(),
                 ),
-                move |dbtx, blinded_signature_shares, old_state| {
+                move |dbtx, (epoch, blinded_signature_shares), old_state| {
                     Box::pin(Self::transition_outcome_ready(
                         dbtx,
+                        epoch,
                         blinded_signature_shares,
                         old_state,
-                        tbs_pks.clone(),
+                        cfg.clone(),
                     ))
                 },
             ),
@@ -165,7 +179,10 @@ impl MintOutputStatesCreated {
     async fn transition_tx_rejected<'a>(
         old_state: MintOutputStateMachine,
     ) -> MintOutputStateMachine {
-        assert!(matches!(old_state.state, MintOutputStates::Created(_)));
+        assert!(matches!(
+            old_state.state,
+            MintOutputStates::Created(_) | MintOutputStates::CreatedLocked(_)
+        ));
 
         MintOutputStateMachine {
             common: old_state.common,
@@ -179,11 +196,11 @@ impl MintOutputStatesCreated {
         module_decoder: Decoder,
         amount: Amount,
         message: BlindedMessage,
-        peer_tbs_pks: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
-    ) -> BTreeMap<PeerId, BlindedSignatureShare> {
+        cfg: MintClientConfig,
+    ) -> (u64, BTreeMap<PeerId, BlindedSignatureShare>) {
         loop {
             let decoder = module_decoder.clone();
-            let pks = peer_tbs_pks.clone();
+            let verify_cfg = cfg.clone();
 
             match global_context
                 .api()
@@ -191,7 +208,14 @@ impl MintOutputStatesCreated {
                     // this query collects a threshold of 2f + 1 valid blind signature shares
                     FilterMapThreshold::new(
                         move |peer, outcome| {
-                            verify_blind_share(peer, outcome, amount, message, &decoder, &pks)
+                            verify_blind_share(
+                                peer,
+                                outcome,
+                                amount,
+                                message,
+                                &decoder,
+                                &verify_cfg,
+                            )
                         },
                         global_context.api().all_peers().total(),
                     ),
@@ -200,7 +224,18 @@ impl MintOutputStatesCreated {
                 )
                 .await
             {
-                Ok(outcome) => return outcome,
+                Ok(outcome) => {
+                    if let Some(epoch_shares) =
+                        select_epoch_shares(outcome, cfg.peer_tbs_pks.threshold())
+                    {
+                        return epoch_shares;
+                    }
+
+                    // Honest peers agree on the epoch, so we retry in case a faulty peer
+                    // answered before them
+                    warn!("Blind signature shares were created in different key epochs");
+                    sleep(RETRY_DELAY).await;
+                }
                 Err(error) => {
                     error.report_if_important();
 
@@ -212,9 +247,10 @@ impl MintOutputStatesCreated {
 
     async fn transition_outcome_ready(
         dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+        epoch: u64,
         blinded_signature_shares: BTreeMap<PeerId, BlindedSignatureShare>,
         old_state: MintOutputStateMachine,
-        tbs_pks: Tiered<AggregatePublicKey>,
+        cfg: MintClientConfig,
     ) -> MintOutputStateMachine {
         // we combine the shares, finalize the issuance request with the blind signature
         // and store the resulting note in the database
@@ -231,7 +267,9 @@ impl MintOutputStatesCreated {
                 .collect(),
         );
 
-        let amount_key = tbs_pks
+        let amount_key = cfg
+            .tbs_pks_of_epoch(epoch)
+            .expect("We verified the shares with the keys of this epoch")
             .tier(&created.amount)
             .expect("We obtained this amount from tbs_pks when we created the output");
 
@@ -276,24 +314,137 @@ impl MintOutputStatesCreated {
     }
 }
 
+/// See [`MintOutputStates`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub struct MintOutputStatesCreatedLocked {
+    pub(crate) amount: Amount,
+    pub(crate) issuance_request: NoteIssuanceRequest,
+    pub(crate) recipient: PublicKey,
+}
+
+impl MintOutputStatesCreatedLocked {
+    fn transitions(
+        &self,
+        context: &MintClientContext,
+        global_context: &DynGlobalClientContext,
+        common: MintOutputCommon,
+    ) -> Vec<StateTransition<MintOutputStateMachine>> {
+        let cfg = context.cfg.clone();
+
+        vec![
+            StateTransition::new(
+                MintOutputStatesCreated::await_tx_rejected(global_context.clone(), common),
+                |_dbtx, (), state| Box::pin(MintOutputStatesCreated::transition_tx_rejected(state)),
+            ),
+            StateTransition::new(
+                MintOutputStatesCreated::await_outcome_ready(
+                    global_context.clone(),
+                    common,
+                    context.mint_decoder.clone(),
+                    self.amount,
+                    self.issuance_request
+                        .blinded_locked_message(&self.recipient),
+                    context.cfg.clone(),
+                ),
+                move |_dbtx, (epoch, blinded_signature_shares), old_state| {
+                    Box::pin(Self::transition_outcome_ready(
+                        epoch,
+                        blinded_signature_shares,
+                        old_state,
+                        cfg.clone(),
+                    ))
+                },
+            ),
+        ]
+    }
+
+    async fn transition_outcome_ready(
+        epoch: u64,
+        blinded_signature_shares: BTreeMap<PeerId, BlindedSignatureShare>,
+        old_state: MintOutputStateMachine,
+        cfg: MintClientConfig,
+    ) -> MintOutputStateMachine {
+        let created = match old_state.state {
+            MintOutputStates::CreatedLocked(created) => created,
+            _ => panic!("Unexpected prior state"),
+        };
+
+        let agg_blind_signature = aggregate_signature_shares(
+            &blinded_signature_shares
+                .into_iter()
+                .map(|(peer, share)| (peer.to_usize() as u64 + 1, share))
+                .collect(),
+        );
+
+        let amount_key = cfg
+            .tbs_pks_of_epoch(epoch)
+            .expect("We verified the shares with the keys of this epoch")
+            .tier(&created.amount)
+            .expect("We obtained this amount from tbs_pks when we created the output");
+
+        if !tbs::verify_blinded_signature(
+            created
+                .issuance_request
+                .blinded_locked_message(&created.recipient),
+            agg_blind_signature,
+            *amount_key,
+        ) {
+            return MintOutputStateMachine {
+                common: old_state.common,
+                state: MintOutputStates::Failed(MintOutputStatesFailed {
+                    error: "Invalid blind signature".to_string(),
+                }),
+            };
+        }
+
+        let note = created
+            .issuance_request
+            .finalize(agg_blind_signature)
+            .note();
+
+        assert!(note.verify_locked(&created.recipient, *amount_key));
+
+        MintOutputStateMachine {
+            common: old_state.common,
+            state: MintOutputStates::SucceededLocked(MintOutputStatesSucceededLocked {
+                amount: created.amount,
+                note,
+                recipient: created.recipient,
+            }),
+        }
+    }
+}
+
+/// See [`MintOutputStates`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub struct MintOutputStatesSucceededLocked {
+    pub amount: Amount,
+    pub note: Note,
+    pub recipient: PublicKey,
+}
+
+/// Verifies the blind signature share of `peer` with its key of the key
+/// epoch the share was created in, which is returned along with the share
+///
 /// # Panics
-/// If the given `outcome` is not a [`MintOutputOutcome::V0`] outcome.
+/// If the given `outcome` is of an unknown [`MintOutputOutcome`] variant.
 pub fn verify_blind_share(
     peer: PeerId,
     outcome: SerdeOutputOutcome,
     amount: Amount,
     blinded_message: BlindedMessage,
     decoder: &Decoder,
-    peer_tbs_pks: &BTreeMap<PeerId, Tiered<PublicKeyShare>>,
-) -> anyhow::Result<BlindedSignatureShare> {
+    cfg: &MintClientConfig,
+) -> anyhow::Result<(u64, BlindedSignatureShare)> {
     let outcome = deserialize_outcome::<MintOutputOutcome>(outcome.clone(), decoder)?;
 
-    let blinded_signature_share = outcome
-        .ensure_v0_ref()
-        .expect("We only process output outcome versions created by ourselves")
-        .0;
+    let (epoch, blinded_signature_share) = outcome
+        .epoch_and_share()
+        .expect("We only process output outcome versions created by ourselves");
 
-    let amount_key = peer_tbs_pks
+    let amount_key = cfg
+        .peer_tbs_pks_of_epoch(epoch)
+        .ok_or(anyhow!("Unknown key epoch {epoch}"))?
         .get(&peer)
         .ok_or(anyhow!("Unknown peer"))?
         .tier(&amount)
@@ -303,7 +454,24 @@ pub fn verify_blind_share(
         bail!("Invalid blind signature")
     }
 
-    Ok(blinded_signature_share)
+    Ok((epoch, blinded_signature_share))
+}
+
+/// Returns the blind signature shares of the key epoch at least `threshold`
+/// peers created their shares in, which can be aggregated
+fn select_epoch_shares(
+    shares: BTreeMap<PeerId, (u64, BlindedSignatureShare)>,
+    threshold: usize,
+) -> Option<(u64, BTreeMap<PeerId, BlindedSignatureShare>)> {
+    let mut epoch_shares: BTreeMap<u64, BTreeMap<PeerId, BlindedSignatureShare>> = BTreeMap::new();
+
+    for (peer, (epoch, share)) in shares {
+        epoch_shares.entry(epoch).or_default().insert(peer, share);
+    }
+
+    epoch_shares
+        .into_iter()
+        .find(|(_, shares)| threshold <= shares.len())
 }
 
 /// See [`MintOutputStates`]
@@ -370,6 +538,11 @@ impl NoteIssuanceRequest {
         blind_message(self.nonce().to_message(), self.blinding_key)
     }
 
+    /// The blinded message for a note that can only be spent by `recipient`
+    pub fn blinded_locked_message(&self, recipient: &PublicKey) -> BlindedMessage {
+        blind_message(self.nonce().to_locked_message(recipient), self.blinding_key)
+    }
+
     /// Use the blind signature to create spendable e-cash notes
     pub fn finalize(&self, blinded_signature: BlindedSignature) -> SpendableNote {
         SpendableNote {
@@ -0,0 +1,728 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use anyhow::bail;
+use fedimint_core::db::IDatabaseTransactionOpsCoreTyped;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{apply, async_trait_maybe_send, Amount, TieredMulti};
+use fedimint_mint_common::config::FeeConsensus;
+use futures::StreamExt;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::client_db::NoteSelectionStrategyKey;
+use crate::{
+    select_notes_from_stream, InsufficientBalanceError, MintClientModule, NotesSelector,
+    SelectNotesWithAtleastAmount,
+};
+
+/// Number of decoy notes spent at most by [`NoteSelectionStrategy::Decoys`]
+/// if it is parsed without an explicit maximum
+const DEFAULT_MAX_DECOY_NOTES: u16 = 4;
+
+/// Number of the wallet's smallest notes per decoy note that
+/// [`SelectNotesWithDecoys`] picks its decoys from
+const DECOY_CANDIDATES_PER_NOTE: usize = 4;
+
+/// Number of combinations of notes [`SelectNotesWithMinimalChange`] considers
+/// at most before settling for the best one found so far
+const MAX_MINIMAL_CHANGE_ROUNDS: usize = 100_000;
+
+/// Strategy for selecting the notes that fund transactions, see
+/// [`MintClientModule::set_note_selection_strategy`]
+///
+/// The federation sees the denominations of the notes spent by a transaction
+/// and of the change it issues. Spending the largest notes first like the
+/// greedy default does makes the amount paid easy to guess from those.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSelectionStrategy {
+    /// See [`SelectNotesWithAtleastAmount`]
+    #[default]
+    Greedy,
+    /// See [`SelectNotesRandomly`]
+    Randomized,
+    /// See [`SelectNotesWithDecoys`]
+    Decoys { max_decoy_notes: u16 },
+    /// See [`SelectNotesWithMinimalChange`]
+    MinimalChange,
+}
+
+impl FromStr for NoteSelectionStrategy {
+    type Err = anyhow::Error;
+
+    /// Parses `greedy`, `randomized`, `minimal-change` and `decoys`, the latter
+    /// optionally followed by the maximum number of decoys like `decoys:2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(':') {
+            None if s == "greedy" => NoteSelectionStrategy::Greedy,
+            None if s == "randomized" => NoteSelectionStrategy::Randomized,
+            None if s == "minimal-change" => NoteSelectionStrategy::MinimalChange,
+            None if s == "decoys" => NoteSelectionStrategy::Decoys {
+                max_decoy_notes: DEFAULT_MAX_DECOY_NOTES,
+            },
+            Some(("decoys", max_decoy_notes)) => NoteSelectionStrategy::Decoys {
+                max_decoy_notes: max_decoy_notes.parse()?,
+            },
+            _ => bail!("Unknown note selection strategy: {s}"),
+        })
+    }
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<Note: Send> NotesSelector<Note> for NoteSelectionStrategy {
+    async fn select_notes(
+        &self,
+        #[cfg(not(target_family = "wasm"))] stream: impl futures::Stream<Item = (Amount, Note)> + Send,
+        #[cfg(target_family = "wasm")] stream: impl futures::Stream<Item = (Amount, Note)>,
+        requested_amount: Amount,
+        fee_consensus: &FeeConsensus,
+    ) -> anyhow::Result<TieredMulti<Note>> {
+        match *self {
+            NoteSelectionStrategy::Greedy => {
+                SelectNotesWithAtleastAmount
+                    .select_notes(stream, requested_amount, fee_consensus)
+                    .await
+            }
+            NoteSelectionStrategy::Randomized => {
+                SelectNotesRandomly
+                    .select_notes(stream, requested_amount, fee_consensus)
+                    .await
+            }
+            NoteSelectionStrategy::Decoys { max_decoy_notes } => {
+                SelectNotesWithDecoys {
+                    max_decoy_notes: max_decoy_notes.into(),
+                }
+                .select_notes(stream, requested_amount, fee_consensus)
+                .await
+            }
+            NoteSelectionStrategy::MinimalChange => {
+                SelectNotesWithMinimalChange
+                    .select_notes(stream, requested_amount, fee_consensus)
+                    .await
+            }
+        }
+    }
+
+    fn relies_on_change(&self) -> bool {
+        matches!(self, NoteSelectionStrategy::Decoys { .. })
+    }
+}
+
+/// Select notes with total amount of *at least* `request_amount` like
+/// [`SelectNotesWithAtleastAmount`], but pick them at random instead of the
+/// largest ones first. Notes that turn out to be redundant are dropped again,
+/// so every selected note is needed to cover the amount.
+///
+/// Payments of the same amount thus spend different denominations and receive
+/// different change.
+pub struct SelectNotesRandomly;
+
+#[apply(async_trait_maybe_send!)]
+impl<Note: Send> NotesSelector<Note> for SelectNotesRandomly {
+    async fn select_notes(
+        &self,
+        #[cfg(not(target_family = "wasm"))] stream: impl futures::Stream<Item = (Amount, Note)> + Send,
+        #[cfg(target_family = "wasm")] stream: impl futures::Stream<Item = (Amount, Note)>,
+        requested_amount: Amount,
+        fee_consensus: &FeeConsensus,
+    ) -> anyhow::Result<TieredMulti<Note>> {
+        let notes = stream.collect::<Vec<_>>().await;
+
+        Ok(select_notes_randomly(
+            notes,
+            requested_amount,
+            fee_consensus,
+        )?)
+    }
+}
+
+/// Select notes like [`SelectNotesWithAtleastAmount`] and additionally spend
+/// between zero and `max_decoy_notes` of the wallet's smallest notes, chosen at
+/// random.
+///
+/// The decoys come back as change, so the input amount of a transaction no
+/// longer reveals the amount paid, at the cost of the fees for spending them.
+/// Out-of-band spends have no change and reject this selector, the decoys
+/// would be given to the recipient.
+pub struct SelectNotesWithDecoys {
+    pub max_decoy_notes: usize,
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<Note: Send> NotesSelector<Note> for SelectNotesWithDecoys {
+    async fn select_notes(
+        &self,
+        #[cfg(not(target_family = "wasm"))] stream: impl futures::Stream<Item = (Amount, Note)> + Send,
+        #[cfg(target_family = "wasm")] stream: impl futures::Stream<Item = (Amount, Note)>,
+        requested_amount: Amount,
+        fee_consensus: &FeeConsensus,
+    ) -> anyhow::Result<TieredMulti<Note>> {
+        let notes = stream.collect::<Vec<_>>().await;
+        let amounts = notes.iter().map(|(amount, _)| *amount).collect::<Vec<_>>();
+
+        // select the indices of the notes so we know which ones are left over
+        let selected = select_notes_from_stream(
+            futures::stream::iter(amounts.into_iter().enumerate())
+                .map(|(idx, amount)| (amount, idx)),
+            requested_amount,
+            fee_consensus,
+        )
+        .await?;
+
+        let mut selected = selected
+            .into_iter_items()
+            .map(|(_, idx)| idx)
+            .collect::<BTreeSet<_>>();
+        let decoys = choose_decoys(&notes, &selected, fee_consensus, self.max_decoy_notes);
+        selected.extend(decoys);
+
+        Ok(notes
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| selected.contains(idx))
+            .map(|(_, note)| note)
+            .collect())
+    }
+
+    fn relies_on_change(&self) -> bool {
+        true
+    }
+}
+
+/// Select notes with total amount of *at least* `request_amount` that exceed
+/// it by as little as possible, i.e. the combination of the wallet's notes
+/// that leaves the least change.
+///
+/// Little change makes it hard to tell the amount paid apart from the notes
+/// spent, which are usually worth more. If there are too many combinations to
+/// try the best one found so far is selected.
+pub struct SelectNotesWithMinimalChange;
+
+#[apply(async_trait_maybe_send!)]
+impl<Note: Send> NotesSelector<Note> for SelectNotesWithMinimalChange {
+    async fn select_notes(
+        &self,
+        #[cfg(not(target_family = "wasm"))] stream: impl futures::Stream<Item = (Amount, Note)> + Send,
+        #[cfg(target_family = "wasm")] stream: impl futures::Stream<Item = (Amount, Note)>,
+        requested_amount: Amount,
+        fee_consensus: &FeeConsensus,
+    ) -> anyhow::Result<TieredMulti<Note>> {
+        let notes = stream.collect::<Vec<_>>().await;
+
+        Ok(select_notes_with_minimal_change(
+            notes,
+            requested_amount,
+            fee_consensus,
+        )?)
+    }
+}
+
+impl MintClientModule {
+    /// Sets the strategy used to select the notes funding transactions and
+    /// stores it in the database, so it survives restarts of the client.
+    /// Out-of-band spends are not affected, they take their selector as
+    /// argument of [`Self::spend_notes_with_selector`].
+    pub async fn set_note_selection_strategy(&self, strategy: NoteSelectionStrategy) {
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+        dbtx.insert_entry(&NoteSelectionStrategyKey, &strategy)
+            .await;
+        dbtx.commit_tx().await;
+
+        *self
+            .selection_strategy
+            .lock()
+            .expect("selection strategy lock poisoned") = strategy;
+    }
+
+    /// The strategy set by [`Self::set_note_selection_strategy`]
+    pub fn note_selection_strategy(&self) -> NoteSelectionStrategy {
+        *self
+            .selection_strategy
+            .lock()
+            .expect("selection strategy lock poisoned")
+    }
+}
+
+/// The amount a note contributes to a transaction after its spend fee
+fn spend_value(amount: Amount, fee_consensus: &FeeConsensus) -> Amount {
+    amount.saturating_sub(fee_consensus.note_spend_fee(amount))
+}
+
+fn select_notes_randomly<Note>(
+    mut notes: Vec<(Amount, Note)>,
+    requested_amount: Amount,
+    fee_consensus: &FeeConsensus,
+) -> Result<TieredMulti<Note>, InsufficientBalanceError> {
+    if requested_amount == Amount::ZERO {
+        return Ok(TieredMulti::default());
+    }
+
+    let total_amount = notes.iter().map(|(amount, _)| *amount).sum();
+
+    notes.retain(|(amount, _)| spend_value(*amount, fee_consensus) != Amount::ZERO);
+    notes.shuffle(&mut rand::thread_rng());
+
+    let mut value = Amount::ZERO;
+    let num_notes = notes
+        .iter()
+        .position(|(amount, _)| {
+            value += spend_value(*amount, fee_consensus);
+            requested_amount <= value
+        })
+        .ok_or(InsufficientBalanceError {
+            requested_amount,
+            total_amount,
+        })?;
+    notes.truncate(num_notes + 1);
+
+    // Every note kept is worth more than the final excess, since the excess
+    // only shrinks, so none of them can be dropped afterwards
+    let mut excess = value - requested_amount;
+    notes.retain(|(amount, _)| {
+        let note_value = spend_value(*amount, fee_consensus);
+        if note_value <= excess {
+            excess -= note_value;
+            false
+        } else {
+            true
+        }
+    });
+
+    Ok(notes.into_iter().collect())
+}
+
+/// Chooses up to `max_decoy_notes` of the smallest notes not `selected` yet,
+/// `notes` are sorted in descending order of amount
+fn choose_decoys<Note>(
+    notes: &[(Amount, Note)],
+    selected: &BTreeSet<usize>,
+    fee_consensus: &FeeConsensus,
+    max_decoy_notes: usize,
+) -> Vec<usize> {
+    let mut rng = rand::thread_rng();
+    let num_decoys = rng.gen_range(0..=max_decoy_notes);
+
+    let mut candidates = (0..notes.len())
+        .rev()
+        .filter(|idx| {
+            !selected.contains(idx) && spend_value(notes[*idx].0, fee_consensus) != Amount::ZERO
+        })
+        .take(max_decoy_notes * DECOY_CANDIDATES_PER_NOTE)
+        .collect::<Vec<_>>();
+
+    candidates.shuffle(&mut rng);
+    candidates.truncate(num_decoys);
+    candidates
+}
+
+fn select_notes_with_minimal_change<Note>(
+    notes: Vec<(Amount, Note)>,
+    requested_amount: Amount,
+    fee_consensus: &FeeConsensus,
+) -> Result<TieredMulti<Note>, InsufficientBalanceError> {
+    if requested_amount == Amount::ZERO {
+        return Ok(TieredMulti::default());
+    }
+
+    let total_amount = notes.iter().map(|(amount, _)| *amount).sum();
+
+    let mut tiers = BTreeMap::<Amount, Vec<Note>>::new();
+    for (amount, note) in notes {
+        if spend_value(amount, fee_consensus) != Amount::ZERO {
+            tiers.entry(amount).or_default().push(note);
+        }
+    }
+
+    // trying the largest denominations first finds combinations of few notes
+    let tier_values = tiers
+        .iter()
+        .rev()
+        .map(|(amount, notes)| {
+            (
+                spend_value(*amount, fee_consensus).msats,
+                notes.len() as u64,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let counts = minimal_change_counts(&tier_values, requested_amount.msats).ok_or(
+        InsufficientBalanceError {
+            requested_amount,
+            total_amount,
+        },
+    )?;
+
+    Ok(tiers
+        .into_iter()
+        .rev()
+        .zip(counts)
+        .flat_map(|((amount, notes), count)| {
+            notes
+                .into_iter()
+                .take(count as usize)
+                .map(move |note| (amount, note))
+        })
+        .collect())
+}
+
+/// The number of notes to take of every tier, given as the value and number
+/// of its notes, such that their total value exceeds `target` by as little as
+/// possible. Returns `None` if all notes together are worth less than `target`.
+fn minimal_change_counts(tiers: &[(u64, u64)], target: u64) -> Option<Vec<u64>> {
+    // the total value of the tiers starting at every index
+    let mut available = vec![0; tiers.len() + 1];
+    for (idx, (value, count)) in tiers.iter().enumerate().rev() {
+        available[idx] = available[idx + 1] + value * count;
+    }
+
+    if available[0] < target {
+        return None;
+    }
+
+    let mut search = MinimalChangeSearch {
+        tiers,
+        available,
+        counts: vec![],
+        best: None,
+        rounds: 0,
+    };
+    search.search(target);
+
+    search.best.map(|(_, counts)| counts)
+}
+
+/// Depth-first search over the number of notes per tier, the first path
+/// taken always reaches a combination covering the target
+struct MinimalChangeSearch<'a> {
+    tiers: &'a [(u64, u64)],
+    available: Vec<u64>,
+    counts: Vec<u64>,
+    best: Option<(u64, Vec<u64>)>,
+    rounds: usize,
+}
+
+impl MinimalChangeSearch<'_> {
+    fn search(&mut self, needed: u64) {
+        let idx = self.counts.len();
+        let Some(&(value, count)) = self.tiers.get(idx) else {
+            return;
+        };
+
+        let max_count = count.min((needed + value - 1) / value);
+        for take in (0..=max_count).rev() {
+            if self.rounds == MAX_MINIMAL_CHANGE_ROUNDS
+                || self.best.as_ref().map_or(false, |(excess, _)| *excess == 0)
+            {
+                return;
+            }
+            self.rounds += 1;
+
+            let taken_value = take * value;
+            if needed <= taken_value {
+                let excess = taken_value - needed;
+                if self
+                    .best
+                    .as_ref()
+                    .map_or(true, |(best_excess, _)| excess < *best_excess)
+                {
+                    let mut counts = self.counts.clone();
+                    counts.push(take);
+                    counts.resize(self.tiers.len(), 0);
+                    self.best = Some((excess, counts));
+                }
+            } else if needed - taken_value <= self.available[idx + 1] {
+                self.counts.push(take);
+                self.search(needed - taken_value);
+                self.counts.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use fedimint_core::encoding::{Decodable, Encodable};
+    use fedimint_core::module::fee::ProportionalFee;
+    use fedimint_core::{Amount, TieredMulti};
+    use fedimint_mint_common::config::FeeConsensus;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{
+        minimal_change_counts, NoteSelectionStrategy, SelectNotesRandomly, SelectNotesWithDecoys,
+        SelectNotesWithMinimalChange,
+    };
+    use crate::{select_notes_from_stream, NotesSelector};
+
+    /// Numbered notes of every amount in sats, sorted in descending order
+    fn wallet(notes: &[(u64, usize)]) -> Vec<(Amount, usize)> {
+        let mut wallet = notes
+            .iter()
+            .flat_map(|(sats, number)| vec![Amount::from_sats(*sats); *number])
+            .enumerate()
+            .map(|(idx, amount)| (amount, idx))
+            .collect::<Vec<_>>();
+        wallet.sort();
+        wallet.reverse();
+        wallet
+    }
+
+    fn spend_value(notes: &TieredMulti<usize>, fee_consensus: &FeeConsensus) -> Amount {
+        notes
+            .iter_items()
+            .map(|(amount, _)| amount - fee_consensus.note_spend_fee(amount))
+            .sum()
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn randomized_selection_spends_all_notes_alike_and_none_needlessly() {
+        let fee_consensus = FeeConsensus {
+            note_spend_ppm: ProportionalFee::new(10_000, Amount::ZERO, None),
+            ..FeeConsensus::default()
+        };
+        let wallet = wallet(&[(1, 5), (2, 5), (4, 5), (8, 5), (16, 5), (32, 5), (64, 5)]);
+        let requested_amount = Amount::from_sats(100);
+
+        let mut times_selected = BTreeMap::<usize, usize>::new();
+        let mut selections = BTreeMap::new();
+        let trials = 10_000;
+        for _ in 0..trials {
+            let selected = SelectNotesRandomly
+                .select_notes(
+                    futures::stream::iter(wallet.clone()),
+                    requested_amount,
+                    &fee_consensus,
+                )
+                .await
+                .unwrap();
+
+            let value = spend_value(&selected, &fee_consensus);
+            assert!(requested_amount <= value);
+            for (amount, idx) in selected.iter_items() {
+                assert!(value - (amount - fee_consensus.note_spend_fee(amount)) < requested_amount);
+                *times_selected.entry(*idx).or_default() += 1;
+            }
+
+            *selections
+                .entry(selected.summary().iter().collect::<Vec<_>>())
+                .or_insert(0) += 1;
+        }
+
+        // unlike the greedy selection the spent denominations vary
+        assert!(50 < selections.len());
+        assert!(selections.values().all(|count| *count < trials / 4));
+
+        // notes of the same denomination are equally likely to be spent
+        for tier in wallet.chunks(5) {
+            let counts = tier
+                .iter()
+                .map(|(_, idx)| times_selected.get(idx).copied().unwrap_or_default())
+                .collect::<Vec<_>>();
+            let mean = counts.iter().sum::<usize>() / counts.len();
+            assert!(trials / 50 < mean);
+            assert!(
+                counts
+                    .iter()
+                    .all(|count| mean * 2 / 3 < *count && *count < mean * 4 / 3),
+                "{counts:?}"
+            );
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn decoy_selection_adds_uniformly_many_small_notes() {
+        let fee_consensus = FeeConsensus::default();
+        let wallet = wallet(&[(1, 5), (2, 5), (4, 5), (8, 5), (16, 5), (32, 5), (64, 5)]);
+        let requested_amount = Amount::from_sats(20);
+        let max_decoy_notes = 4;
+
+        let greedy = select_notes_from_stream(
+            futures::stream::iter(wallet.clone()),
+            requested_amount,
+            &fee_consensus,
+        )
+        .await
+        .unwrap();
+
+        let mut num_decoys = vec![0; max_decoy_notes + 1];
+        let trials = 5000;
+        for _ in 0..trials {
+            let selected = SelectNotesWithDecoys { max_decoy_notes }
+                .select_notes(
+                    futures::stream::iter(wallet.clone()),
+                    requested_amount,
+                    &fee_consensus,
+                )
+                .await
+                .unwrap();
+
+            let decoys = selected
+                .iter_items()
+                .filter(|(_, idx)| {
+                    !greedy
+                        .iter_items()
+                        .any(|(_, greedy_idx)| greedy_idx == *idx)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(selected.count_items(), greedy.count_items() + decoys.len());
+
+            // the decoys are taken from the 16 smallest notes left over, which
+            // are worth at most 8 sats each
+            assert!(decoys
+                .iter()
+                .all(|(amount, _)| *amount <= Amount::from_sats(8)));
+
+            num_decoys[decoys.len()] += 1;
+        }
+
+        let expected = trials / num_decoys.len();
+        assert!(
+            num_decoys
+                .iter()
+                .all(|count| expected * 4 / 5 < *count && *count < expected * 6 / 5),
+            "{num_decoys:?}"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn minimal_change_selection_beats_greedy_selection() {
+        let f = || futures::stream::iter(wallet(&[(5, 2), (6, 1)]));
+
+        let greedy = select_notes_from_stream(f(), Amount::from_sats(10), &FeeConsensus::default())
+            .await
+            .unwrap();
+        assert_eq!(greedy.total_amount(), Amount::from_sats(11));
+
+        let selected = SelectNotesWithMinimalChange
+            .select_notes(f(), Amount::from_sats(10), &FeeConsensus::default())
+            .await
+            .unwrap();
+        assert_eq!(selected.total_amount(), Amount::from_sats(10));
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let wallet = wallet(
+                &(0..rng.gen_range(1..6))
+                    .map(|_| (rng.gen_range(1..50), rng.gen_range(1..4)))
+                    .collect::<Vec<_>>(),
+            );
+            let total_amount = wallet.iter().map(|(amount, _)| *amount).sum::<Amount>();
+            let requested_amount = Amount::from_msats(rng.gen_range(1..=total_amount.msats));
+
+            let greedy = select_notes_from_stream(
+                futures::stream::iter(wallet.clone()),
+                requested_amount,
+                &FeeConsensus::default(),
+            )
+            .await
+            .unwrap();
+            let selected = NoteSelectionStrategy::MinimalChange
+                .select_notes(
+                    futures::stream::iter(wallet),
+                    requested_amount,
+                    &FeeConsensus::default(),
+                )
+                .await
+                .unwrap();
+
+            assert!(requested_amount <= selected.total_amount());
+            assert!(selected.total_amount() <= greedy.total_amount());
+        }
+    }
+
+    #[test]
+    fn minimal_change_counts_are_optimal() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..500 {
+            let tiers = (0..rng.gen_range(1..4))
+                .map(|_| (rng.gen_range(1..20), rng.gen_range(1..4)))
+                .collect::<Vec<(u64, u64)>>();
+            let total = tiers
+                .iter()
+                .map(|(value, count)| value * count)
+                .sum::<u64>();
+            let target = rng.gen_range(1..=total + 5);
+
+            // enumerate all combinations of counts per tier
+            let mut best_excess = None;
+            let mut counts = vec![0; tiers.len()];
+            loop {
+                let value = tiers
+                    .iter()
+                    .zip(&counts)
+                    .map(|((value, _), count)| value * count)
+                    .sum::<u64>();
+                if target <= value {
+                    best_excess = Some(best_excess.unwrap_or(u64::MAX).min(value - target));
+                }
+
+                let Some(idx) = (0..tiers.len()).find(|idx| counts[*idx] < tiers[*idx].1) else {
+                    break;
+                };
+                counts[idx] += 1;
+                counts[..idx].fill(0);
+            }
+
+            let excess = minimal_change_counts(&tiers, target).map(|counts| {
+                tiers
+                    .iter()
+                    .zip(&counts)
+                    .map(|((value, _), count)| value * count)
+                    .sum::<u64>()
+                    - target
+            });
+            assert_eq!(excess, best_excess, "{tiers:?} {target}");
+        }
+    }
+
+    #[test]
+    fn parses_note_selection_strategies() {
+        assert_eq!(
+            "minimal-change".parse::<NoteSelectionStrategy>().unwrap(),
+            NoteSelectionStrategy::MinimalChange
+        );
+        assert_eq!(
+            "decoys:2".parse::<NoteSelectionStrategy>().unwrap(),
+            NoteSelectionStrategy::Decoys { max_decoy_notes: 2 }
+        );
+        assert!("decoys:many".parse::<NoteSelectionStrategy>().is_err());
+        assert!("largest-first".parse::<NoteSelectionStrategy>().is_err());
+    }
+
+    #[test]
+    fn only_decoys_rely_on_change() {
+        assert!(!NotesSelector::<usize>::relies_on_change(
+            &NoteSelectionStrategy::Greedy
+        ));
+        assert!(!NotesSelector::<usize>::relies_on_change(
+            &NoteSelectionStrategy::MinimalChange
+        ));
+        assert!(NotesSelector::<usize>::relies_on_change(
+            &NoteSelectionStrategy::Decoys { max_decoy_notes: 2 }
+        ));
+        assert!(NotesSelector::<usize>::relies_on_change(
+            &SelectNotesWithDecoys { max_decoy_notes: 2 }
+        ));
+    }
+
+    #[test]
+    fn encodes_note_selection_strategies() {
+        for strategy in [
+            NoteSelectionStrategy::Greedy,
+            NoteSelectionStrategy::Randomized,
+            NoteSelectionStrategy::Decoys { max_decoy_notes: 3 },
+            NoteSelectionStrategy::MinimalChange,
+        ] {
+            let bytes = strategy.consensus_encode_to_vec();
+            let decoded =
+                NoteSelectionStrategy::consensus_decode_vec(bytes, &Default::default()).unwrap();
+            assert_eq!(decoded, strategy);
+        }
+    }
+}
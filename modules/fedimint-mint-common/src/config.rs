@@ -1,14 +1,18 @@
 use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use fedimint_core::config::EmptyGenParams;
+use fedimint_core::config::{decode_config_extension, encode_config_extension, EmptyGenParams};
 use fedimint_core::core::ModuleKind;
-use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::encoding::{Decodable, DecodeError, Encodable};
 use fedimint_core::module::__reexports::serde_json;
+use fedimint_core::module::fee::ProportionalFee;
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::{plugin_types_trait_impl_config, Amount, PeerId, Tiered};
 use serde::{Deserialize, Serialize};
 use tbs::{AggregatePublicKey, PublicKeyShare};
 
-use crate::MintCommonInit;
+use crate::{MintCommonInit, Note};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MintGenParams {
@@ -20,6 +24,8 @@ pub struct MintGenParams {
 pub struct MintGenParamsConsensus {
     denomination_base: u16,
     fee_consensus: FeeConsensus,
+    #[serde(default)]
+    key_epochs: KeyEpochParams,
 }
 
 // The maximum size of an E-Cash note (1,000,000 coins)
@@ -32,9 +38,16 @@ impl MintGenParamsConsensus {
         Self {
             denomination_base,
             fee_consensus,
+            key_epochs: KeyEpochParams::default(),
         }
     }
 
+    /// Rotate the keys notes are issued under, see [`KeyEpochParams`]
+    pub fn with_key_epochs(mut self, key_epochs: KeyEpochParams) -> Self {
+        self.key_epochs = key_epochs;
+        self
+    }
+
     pub fn denomination_base(&self) -> u16 {
         self.denomination_base
     }
@@ -43,6 +56,10 @@ impl MintGenParamsConsensus {
         self.fee_consensus.clone()
     }
 
+    pub fn key_epochs(&self) -> KeyEpochParams {
+        self.key_epochs
+    }
+
     pub fn gen_denominations(&self) -> Vec<Amount> {
         Tiered::gen_denominations(self.denomination_base, MAX_DENOMINATION_SIZE)
             .tiers()
@@ -70,7 +87,7 @@ pub struct MintConfig {
 #[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
 pub struct MintConfigLocal;
 
-#[derive(Clone, Debug, Serialize, Deserialize, Encodable, Decodable)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MintConfigConsensus {
     /// The set of public keys for blind-signing all peers and note
     /// denominations
@@ -79,20 +96,229 @@ pub struct MintConfigConsensus {
     pub fee_consensus: FeeConsensus,
     /// The maximum amount of change a client can request
     pub max_notes_per_denomination: u16,
+    /// Schedule of the key epochs, a single epoch if keys are not rotated
+    #[serde(default)]
+    pub key_epochs: KeyEpochParams,
+    /// Public key shares of the key epochs following the first one, whose
+    /// keys are `peer_tbs_pks`, keyed by epoch
+    #[serde(default)]
+    pub epoch_peer_tbs_pks: BTreeMap<u64, BTreeMap<PeerId, Tiered<PublicKeyShare>>>,
+}
+
+impl Encodable for MintConfigConsensus {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.peer_tbs_pks.consensus_encode(writer)?;
+        len += self.fee_consensus.consensus_encode_abs(writer)?;
+        len += self.max_notes_per_denomination.consensus_encode(writer)?;
+        len += encode_config_extension(
+            &MintConfigConsensusExtension {
+                fees: self.fee_consensus.extension(),
+                key_epochs: self.key_epochs,
+                epoch_peer_tbs_pks: self.epoch_peer_tbs_pks.clone(),
+            },
+            writer,
+        )?;
+        Ok(len)
+    }
+}
+
+impl Decodable for MintConfigConsensus {
+    fn consensus_decode<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let peer_tbs_pks = Decodable::consensus_decode(reader, modules)?;
+        let fee_consensus = FeeConsensus::consensus_decode_abs(reader, modules)?;
+        let max_notes_per_denomination = Decodable::consensus_decode(reader, modules)?;
+        let extension: MintConfigConsensusExtension = decode_config_extension(reader, modules)?;
+
+        Ok(Self {
+            peer_tbs_pks,
+            fee_consensus: fee_consensus.with_extension(extension.fees),
+            max_notes_per_denomination,
+            key_epochs: extension.key_epochs,
+            epoch_peer_tbs_pks: extension.epoch_peer_tbs_pks,
+        })
+    }
+}
+
+/// The fields of [`MintConfigConsensus`] added after its encoding was
+/// released, see [`encode_config_extension`]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable)]
+struct MintConfigConsensusExtension {
+    fees: FeeConsensusExtension,
+    key_epochs: KeyEpochParams,
+    epoch_peer_tbs_pks: BTreeMap<u64, BTreeMap<PeerId, Tiered<PublicKeyShare>>>,
+}
+
+impl MintConfigConsensus {
+    /// The public key shares notes of `epoch` are signed with
+    pub fn peer_tbs_pks_of_epoch(
+        &self,
+        epoch: u64,
+    ) -> Option<&BTreeMap<PeerId, Tiered<PublicKeyShare>>> {
+        match epoch {
+            0 => Some(&self.peer_tbs_pks),
+            epoch => self.epoch_peer_tbs_pks.get(&epoch),
+        }
+    }
+
+    /// The key epochs we hold keys for in ascending order, the keys of retired
+    /// epochs are dropped when the schedule is extended except for the first
+    /// epoch
+    pub fn keyed_epochs(&self) -> impl Iterator<Item = u64> + '_ {
+        std::iter::once(0).chain(self.epoch_peer_tbs_pks.keys().copied())
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MintConfigPrivate {
     /// Secret keys for blind-signing ecash of varying note denominations
     pub tbs_sks: Tiered<tbs::SecretKeyShare>,
+    /// Secret keys of the key epochs following the first one, whose keys are
+    /// `tbs_sks`, keyed by epoch
+    #[serde(default)]
+    pub epoch_tbs_sks: BTreeMap<u64, Tiered<tbs::SecretKeyShare>>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable, Hash)]
+impl MintConfigPrivate {
+    /// The secret keys notes of `epoch` are signed with
+    pub fn tbs_sks_of_epoch(&self, epoch: u64) -> Option<&Tiered<tbs::SecretKeyShare>> {
+        match epoch {
+            0 => Some(&self.tbs_sks),
+            epoch => self.epoch_tbs_sks.get(&epoch),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Hash)]
 pub struct MintClientConfig {
     pub tbs_pks: Tiered<AggregatePublicKey>,
     pub fee_consensus: FeeConsensus,
     pub peer_tbs_pks: BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>,
     pub max_notes_per_denomination: u16,
+    #[serde(default)]
+    pub key_epochs: KeyEpochParams,
+    /// Aggregate public keys of the key epochs following the first one, whose
+    /// keys are `tbs_pks`, keyed by epoch
+    #[serde(default)]
+    pub epoch_tbs_pks: BTreeMap<u64, Tiered<AggregatePublicKey>>,
+    /// Public key shares of the key epochs following the first one, whose
+    /// keys are `peer_tbs_pks`, keyed by epoch
+    #[serde(default)]
+    pub epoch_peer_tbs_pks: BTreeMap<u64, BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>>,
+}
+
+impl Encodable for MintClientConfig {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.tbs_pks.consensus_encode(writer)?;
+        len += self.fee_consensus.consensus_encode_abs(writer)?;
+        len += self.peer_tbs_pks.consensus_encode(writer)?;
+        len += self.max_notes_per_denomination.consensus_encode(writer)?;
+        len += encode_config_extension(
+            &MintClientConfigExtension {
+                fees: self.fee_consensus.extension(),
+                key_epochs: self.key_epochs,
+                epoch_tbs_pks: self.epoch_tbs_pks.clone(),
+                epoch_peer_tbs_pks: self.epoch_peer_tbs_pks.clone(),
+            },
+            writer,
+        )?;
+        Ok(len)
+    }
+}
+
+impl Decodable for MintClientConfig {
+    fn consensus_decode<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let tbs_pks = Decodable::consensus_decode(reader, modules)?;
+        let fee_consensus = FeeConsensus::consensus_decode_abs(reader, modules)?;
+        let peer_tbs_pks = Decodable::consensus_decode(reader, modules)?;
+        let max_notes_per_denomination = Decodable::consensus_decode(reader, modules)?;
+        let extension: MintClientConfigExtension = decode_config_extension(reader, modules)?;
+
+        Ok(Self {
+            tbs_pks,
+            fee_consensus: fee_consensus.with_extension(extension.fees),
+            peer_tbs_pks,
+            max_notes_per_denomination,
+            key_epochs: extension.key_epochs,
+            epoch_tbs_pks: extension.epoch_tbs_pks,
+            epoch_peer_tbs_pks: extension.epoch_peer_tbs_pks,
+        })
+    }
+}
+
+/// The fields of [`MintClientConfig`] added after its encoding was released,
+/// see [`encode_config_extension`]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable)]
+struct MintClientConfigExtension {
+    fees: FeeConsensusExtension,
+    key_epochs: KeyEpochParams,
+    epoch_tbs_pks: BTreeMap<u64, Tiered<AggregatePublicKey>>,
+    epoch_peer_tbs_pks: BTreeMap<u64, BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>>,
+}
+
+impl MintClientConfig {
+    /// The aggregate public keys notes of `epoch` are signed with
+    pub fn tbs_pks_of_epoch(&self, epoch: u64) -> Option<&Tiered<AggregatePublicKey>> {
+        match epoch {
+            0 => Some(&self.tbs_pks),
+            epoch => self.epoch_tbs_pks.get(&epoch),
+        }
+    }
+
+    /// The key epochs the federation holds keys for in descending order, so
+    /// the most recent epoch comes first
+    fn keyed_epochs_rev(&self) -> impl Iterator<Item = u64> + '_ {
+        self.epoch_tbs_pks
+            .keys()
+            .rev()
+            .copied()
+            .chain(std::iter::once(0))
+    }
+
+    /// The public key shares notes of `epoch` are signed with
+    pub fn peer_tbs_pks_of_epoch(
+        &self,
+        epoch: u64,
+    ) -> Option<&BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>> {
+        match epoch {
+            0 => Some(&self.peer_tbs_pks),
+            epoch => self.epoch_peer_tbs_pks.get(&epoch),
+        }
+    }
+
+    /// The epoch whose key signed `note`, `None` if the signature is invalid
+    ///
+    /// Epochs are tried newest first since most notes held by a client are
+    /// usually recent.
+    pub fn note_epoch(&self, amount: Amount, note: &Note) -> Option<u64> {
+        self.keyed_epochs_rev().find(|&epoch| {
+            self.tbs_pks_of_epoch(epoch)
+                .and_then(|pks| pks.get(amount))
+                .map_or(false, |pk| note.verify(*pk))
+        })
+    }
+
+    /// The epoch whose key signed `note` locked to `recipient`, `None` if the
+    /// signature is invalid
+    pub fn locked_note_epoch(
+        &self,
+        amount: Amount,
+        note: &Note,
+        recipient: &secp256k1_zkp::PublicKey,
+    ) -> Option<u64> {
+        self.keyed_epochs_rev().find(|&epoch| {
+            self.tbs_pks_of_epoch(epoch)
+                .and_then(|pks| pks.get(amount))
+                .map_or(false, |pk| note.verify_locked(recipient, *pk))
+        })
+    }
 }
 
 impl std::fmt::Display for MintClientConfig {
@@ -118,10 +344,203 @@ plugin_types_trait_impl_config!(
     MintClientConfig
 );
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+/// The maximum number of key epochs the federation holds keys for, which
+/// bounds the size of the config and the number of epochs scheduled at once
+pub const MAX_KEYED_EPOCHS: usize = 32;
+
+/// Schedule for rotating the keys notes are issued under, which bounds the
+/// set of spent nonces the federation has to keep
+///
+/// Keys for the `num_epochs` epochs are generated during setup. Epoch `n`
+/// starts `n * epoch_duration_secs` seconds after `start_time_secs`, the
+/// federation agrees on the current epoch by consensus. New notes are always
+/// signed with the key of the current epoch. Notes of an epoch remain
+/// spendable for `grace_epochs` further epochs, during which clients reissue
+/// them. Afterwards the epoch is retired: its notes are rejected and the
+/// nonces of its spent notes are pruned.
+///
+/// The last epoch only retires once the schedule is extended, which happens
+/// when the federation reshares its keys in its last epoch, see
+/// [`KeyEpochParams::extend`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct KeyEpochParams {
+    pub num_epochs: u64,
+    pub start_time_secs: u64,
+    pub epoch_duration_secs: u64,
+    pub grace_epochs: u64,
+}
+
+impl KeyEpochParams {
+    /// The epoch the local clock is in at `time`
+    pub fn epoch_at(&self, time: SystemTime) -> u64 {
+        self.unbounded_epoch_at(time).min(self.last_epoch())
+    }
+
+    /// The epoch the local clock is in at `time` if the schedule never ended
+    fn unbounded_epoch_at(&self, time: SystemTime) -> u64 {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        secs.saturating_sub(self.start_time_secs) / self.epoch_duration_secs
+    }
+
+    pub fn last_epoch(&self) -> u64 {
+        self.num_epochs - 1
+    }
+
+    /// Whether notes of `epoch` are rejected once the federation reached
+    /// `current_epoch`
+    pub fn is_retired(&self, epoch: u64, current_epoch: u64) -> bool {
+        epoch != self.last_epoch() && epoch.saturating_add(self.grace_epochs) < current_epoch
+    }
+
+    /// Whether notes of `epoch` should be reissued by clients once the
+    /// federation reached `current_epoch` since the epoch will retire
+    pub fn is_expiring(&self, epoch: u64, current_epoch: u64) -> bool {
+        epoch < current_epoch && !self.is_retired(epoch, current_epoch)
+    }
+
+    /// The epochs whose notes can be spent once the federation reached
+    /// `current_epoch`
+    pub fn live_epochs(&self, current_epoch: u64) -> RangeInclusive<u64> {
+        current_epoch.saturating_sub(self.grace_epochs)..=current_epoch
+    }
+
+    /// Extends the schedule by the epochs of `extension` following our last
+    /// epoch when the federation reshares its keys at `now`
+    ///
+    /// Returns the schedule unchanged if `extension` does not add any epochs.
+    /// Otherwise the extension replaces the schedule, which is only possible in
+    /// our last epoch and as long as the first added epoch lies in the future
+    /// according to the extension, so clients get the full grace period to
+    /// reissue the notes of our last epoch.
+    pub fn extend(&self, extension: &KeyEpochParams, now: SystemTime) -> anyhow::Result<Self> {
+        if extension.num_epochs <= self.num_epochs {
+            return Ok(*self);
+        }
+
+        extension.validate()?;
+
+        if self.epoch_at(now) != self.last_epoch() {
+            anyhow::bail!("The key epoch schedule can only be extended in its last epoch");
+        }
+
+        if extension.unbounded_epoch_at(now) != self.last_epoch() {
+            anyhow::bail!(
+                "The extended key epoch schedule has to start its first new epoch in the future"
+            );
+        }
+
+        Ok(*extension)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.num_epochs == 0 {
+            anyhow::bail!("There has to be at least one key epoch");
+        }
+
+        if self.epoch_duration_secs == 0 {
+            anyhow::bail!("Key epochs cannot be empty");
+        }
+
+        if 1 < self.num_epochs && self.grace_epochs == 0 {
+            anyhow::bail!("Clients need at least one grace epoch to reissue their notes");
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for KeyEpochParams {
+    /// A single epoch, so the keys are never rotated
+    fn default() -> Self {
+        Self {
+            num_epochs: 1,
+            start_time_secs: 0,
+            epoch_duration_secs: u64::MAX,
+            grace_epochs: 0,
+        }
+    }
+}
+
+/// The fees charged for issuing and spending notes
+///
+/// Only the absolute fees are part of the encoding of the configs containing
+/// the fees as they were released, the proportional fees added later are
+/// appended as an extension, see [`encode_config_extension`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct FeeConsensus {
     pub note_issuance_abs: fedimint_core::Amount,
     pub note_spend_abs: fedimint_core::Amount,
+    /// Charged per issued note in addition to `note_issuance_abs`
+    #[serde(default)]
+    pub note_issuance_ppm: ProportionalFee,
+    /// Charged per spent note in addition to `note_spend_abs`
+    #[serde(default)]
+    pub note_spend_ppm: ProportionalFee,
+}
+
+impl FeeConsensus {
+    /// The fee for issuing a note of `amount`
+    pub fn note_issuance_fee(&self, amount: Amount) -> Amount {
+        self.note_issuance_abs + self.note_issuance_ppm.fee(amount)
+    }
+
+    /// The fee for spending a note of `amount`
+    pub fn note_spend_fee(&self, amount: Amount) -> Amount {
+        self.note_spend_abs + self.note_spend_ppm.fee(amount)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.note_issuance_ppm.validate()?;
+        self.note_spend_ppm.validate()
+    }
+
+    fn consensus_encode_abs<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.note_issuance_abs.consensus_encode(writer)?;
+        len += self.note_spend_abs.consensus_encode(writer)?;
+        Ok(len)
+    }
+
+    /// Decodes the absolute fees, the proportional fees are zero until the
+    /// extension is added with [`Self::with_extension`]
+    fn consensus_decode_abs<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        Ok(Self {
+            note_issuance_abs: Decodable::consensus_decode(reader, modules)?,
+            note_spend_abs: Decodable::consensus_decode(reader, modules)?,
+            ..Self::default()
+        })
+    }
+
+    fn extension(&self) -> FeeConsensusExtension {
+        FeeConsensusExtension {
+            note_issuance_ppm: self.note_issuance_ppm,
+            note_spend_ppm: self.note_spend_ppm,
+        }
+    }
+
+    fn with_extension(self, extension: FeeConsensusExtension) -> Self {
+        Self {
+            note_issuance_ppm: extension.note_issuance_ppm,
+            note_spend_ppm: extension.note_spend_ppm,
+            ..self
+        }
+    }
+}
+
+/// The fields of [`FeeConsensus`] added after its encoding was released
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable)]
+struct FeeConsensusExtension {
+    note_issuance_ppm: ProportionalFee,
+    note_spend_ppm: ProportionalFee,
 }
 
 impl Default for FeeConsensus {
@@ -129,6 +548,186 @@ impl Default for FeeConsensus {
         Self {
             note_issuance_abs: fedimint_core::Amount::ZERO,
             note_spend_abs: fedimint_core::Amount::ZERO,
+            note_issuance_ppm: ProportionalFee::ZERO,
+            note_spend_ppm: ProportionalFee::ZERO,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use fedimint_core::encoding::{Decodable, Encodable};
+    use fedimint_core::module::fee::ProportionalFee;
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+    use fedimint_core::{Amount, PeerId, Tiered};
+    use tbs::{AggregatePublicKey, PublicKeyShare, Scalar, SecretKeyShare};
+
+    use super::{FeeConsensus, KeyEpochParams, MintClientConfig, MintConfigConsensus};
+
+    /// The encoding of [`FeeConsensus`] before proportional fees were added
+    #[derive(Encodable)]
+    struct FeeConsensusV0 {
+        note_issuance_abs: Amount,
+        note_spend_abs: Amount,
+    }
+
+    /// The encoding of [`MintClientConfig`] before proportional fees and key
+    /// epochs were added
+    #[derive(Encodable)]
+    struct MintClientConfigV0 {
+        tbs_pks: Tiered<AggregatePublicKey>,
+        fee_consensus: FeeConsensusV0,
+        peer_tbs_pks: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+        max_notes_per_denomination: u16,
+    }
+
+    /// The encoding of [`MintConfigConsensus`] before proportional fees and
+    /// key epochs were added
+    #[derive(Encodable)]
+    struct MintConfigConsensusV0 {
+        peer_tbs_pks: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+        fee_consensus: FeeConsensusV0,
+        max_notes_per_denomination: u16,
+    }
+
+    /// Keys of three peers for a single denomination, derived from `seed`
+    fn keys(
+        seed: u64,
+    ) -> (
+        Tiered<AggregatePublicKey>,
+        BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+    ) {
+        let pk_shares = (0..3)
+            .map(|peer| {
+                let sk_share = SecretKeyShare(Scalar::from(seed + peer));
+                (peer, sk_share.to_pub_key_share())
+            })
+            .collect::<BTreeMap<u64, PublicKeyShare>>();
+
+        let tbs_pks = std::iter::once((
+            Amount::from_msats(1),
+            tbs::aggregate_public_key_shares(&pk_shares),
+        ))
+        .collect();
+        let peer_tbs_pks = pk_shares
+            .into_iter()
+            .map(|(peer, pk_share)| {
+                (
+                    PeerId::from(peer as u16),
+                    std::iter::once((Amount::from_msats(1), pk_share)).collect(),
+                )
+            })
+            .collect();
+
+        (tbs_pks, peer_tbs_pks)
+    }
+
+    fn fee_consensus() -> FeeConsensus {
+        FeeConsensus {
+            note_issuance_abs: Amount::from_msats(1),
+            note_spend_abs: Amount::from_msats(2),
+            ..FeeConsensus::default()
+        }
+    }
+
+    fn fee_consensus_v0() -> FeeConsensusV0 {
+        FeeConsensusV0 {
+            note_issuance_abs: Amount::from_msats(1),
+            note_spend_abs: Amount::from_msats(2),
+        }
+    }
+
+    fn client_config() -> MintClientConfig {
+        let (tbs_pks, peer_tbs_pks) = keys(1);
+
+        MintClientConfig {
+            tbs_pks,
+            fee_consensus: fee_consensus(),
+            peer_tbs_pks,
+            max_notes_per_denomination: 3,
+            key_epochs: KeyEpochParams::default(),
+            epoch_tbs_pks: BTreeMap::new(),
+            epoch_peer_tbs_pks: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn configs_without_new_fields_keep_encoding() {
+        let client_config = client_config();
+        let client_bytes_v0 = MintClientConfigV0 {
+            tbs_pks: client_config.tbs_pks.clone(),
+            fee_consensus: fee_consensus_v0(),
+            peer_tbs_pks: client_config.peer_tbs_pks.clone(),
+            max_notes_per_denomination: 3,
+        }
+        .consensus_encode_to_vec();
+
+        assert_eq!(
+            MintClientConfig::consensus_decode_vec(
+                client_bytes_v0.clone(),
+                &ModuleDecoderRegistry::default()
+            )
+            .expect("decodes the encoding of v0"),
+            client_config
+        );
+        assert_eq!(client_config.consensus_encode_to_vec(), client_bytes_v0);
+
+        let consensus_config = MintConfigConsensus {
+            peer_tbs_pks: client_config.peer_tbs_pks.clone(),
+            fee_consensus: fee_consensus(),
+            max_notes_per_denomination: 3,
+            key_epochs: KeyEpochParams::default(),
+            epoch_peer_tbs_pks: BTreeMap::new(),
+        };
+        let consensus_bytes_v0 = MintConfigConsensusV0 {
+            peer_tbs_pks: client_config.peer_tbs_pks,
+            fee_consensus: fee_consensus_v0(),
+            max_notes_per_denomination: 3,
+        }
+        .consensus_encode_to_vec();
+
+        let decoded = MintConfigConsensus::consensus_decode_vec(
+            consensus_bytes_v0.clone(),
+            &ModuleDecoderRegistry::default(),
+        )
+        .expect("decodes the encoding of v0");
+        assert_eq!(decoded.fee_consensus, consensus_config.fee_consensus);
+        assert_eq!(decoded.key_epochs, KeyEpochParams::default());
+        assert!(decoded.epoch_peer_tbs_pks.is_empty());
+        assert_eq!(
+            consensus_config.consensus_encode_to_vec(),
+            consensus_bytes_v0
+        );
+    }
+
+    #[test]
+    fn client_config_with_new_fields_roundtrips() {
+        let (epoch_tbs_pks, epoch_peer_tbs_pks) = keys(10);
+        let client_config = MintClientConfig {
+            fee_consensus: FeeConsensus {
+                note_spend_ppm: ProportionalFee::new(100, Amount::ZERO, None),
+                ..fee_consensus()
+            },
+            key_epochs: KeyEpochParams {
+                num_epochs: 2,
+                start_time_secs: 1_700_000_000,
+                epoch_duration_secs: 86_400,
+                grace_epochs: 1,
+            },
+            epoch_tbs_pks: BTreeMap::from([(1, epoch_tbs_pks)]),
+            epoch_peer_tbs_pks: BTreeMap::from([(1, epoch_peer_tbs_pks)]),
+            ..client_config()
+        };
+
+        assert_eq!(
+            MintClientConfig::consensus_decode_vec(
+                client_config.consensus_encode_to_vec(),
+                &ModuleDecoderRegistry::default()
+            )
+            .expect("decodes"),
+            client_config
+        );
+    }
+}
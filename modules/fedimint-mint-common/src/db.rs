@@ -1,7 +1,7 @@
 use std::time::SystemTime;
 
 use fedimint_core::encoding::{Decodable, Encodable};
-use fedimint_core::{impl_db_lookup, impl_db_record, Amount, OutPoint};
+use fedimint_core::{impl_db_lookup, impl_db_record, Amount, OutPoint, PeerId};
 use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
@@ -14,6 +14,9 @@ pub enum DbKeyPrefix {
     OutputOutcome = 0x13,
     MintAuditItem = 0x14,
     EcashBackup = 0x15,
+    LockedNoteNonce = 0x16,
+    EpochNoteNonce = 0x17,
+    KeyEpochVote = 0x18,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -35,6 +38,74 @@ impl_db_record!(
 );
 impl_db_lookup!(key = NonceKey, query_prefix = NonceKeyPrefix);
 
+/// Spent note signed with the key of a key epoch other than the first one,
+/// whose notes are tracked by [`NonceKey`]
+///
+/// Keyed by epoch first so the nonces of a retired epoch can be pruned.
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct EpochNonceKey {
+    pub epoch: u64,
+    pub nonce: Nonce,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct EpochNonceKeyPrefix;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct EpochNonceKeyEpochPrefix(pub u64);
+
+impl_db_record!(
+    key = EpochNonceKey,
+    value = (),
+    db_prefix = DbKeyPrefix::EpochNoteNonce,
+);
+impl_db_lookup!(
+    key = EpochNonceKey,
+    query_prefix = EpochNonceKeyPrefix,
+    query_prefix = EpochNonceKeyEpochPrefix
+);
+
+/// Spent note locked to a recipient key, tracked separately from [`NonceKey`]
+/// since the mint signature commits to both the nonce and the recipient
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct LockedNonceKey {
+    pub epoch: u64,
+    pub nonce: Nonce,
+    pub recipient: secp256k1_zkp::PublicKey,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct LockedNonceKeyPrefix;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct LockedNonceKeyEpochPrefix(pub u64);
+
+impl_db_record!(
+    key = LockedNonceKey,
+    value = (),
+    db_prefix = DbKeyPrefix::LockedNoteNonce,
+);
+impl_db_lookup!(
+    key = LockedNonceKey,
+    query_prefix = LockedNonceKeyPrefix,
+    query_prefix = LockedNonceKeyEpochPrefix
+);
+
+/// The key epoch a peer's clock is in, see
+/// [`crate::MintConsensusItem::KeyEpochVote`]
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct KeyEpochVoteKey(pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct KeyEpochVotePrefix;
+
+impl_db_record!(
+    key = KeyEpochVoteKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::KeyEpochVote,
+);
+impl_db_lookup!(key = KeyEpochVoteKey, query_prefix = KeyEpochVotePrefix);
+
 /// Transaction id and output index identifying an output outcome
 #[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
 pub struct MintOutputOutcomeKey(pub OutPoint);
@@ -60,6 +131,8 @@ pub enum MintAuditItemKey {
     IssuanceTotal,
     Redemption(NonceKey),
     RedemptionTotal,
+    LockedRedemption(LockedNonceKey),
+    EpochRedemption(EpochNonceKey),
 }
 
 #[derive(Debug, Encodable, Decodable)]
@@ -17,24 +17,49 @@ pub mod common;
 pub mod db;
 
 pub const KIND: ModuleKind = ModuleKind::from_static_str("mint");
-const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion::new(0, 0);
+
+/// Consensus version of federations created before locked notes and key
+/// epochs were introduced, their guardians reject [`MintInput::V1`] and
+/// [`MintConsensusItem::KeyEpochVote`] and only issue
+/// [`MintOutputOutcome::V0`]
+pub const MINT_CONSENSUS_VERSION_V0: ModuleConsensusVersion = ModuleConsensusVersion::new(0, 0);
+
+/// Consensus version of new federations, adds locked notes, see
+/// [`MintInput::V1`], and key epochs, see [`config::KeyEpochParams`].
+/// Federations created before keep running
+/// [`MINT_CONSENSUS_VERSION_V0`] until they reshare their keys, which
+/// generates the module configs anew.
+pub const MINT_CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion::new(0, 1);
 
 /// By default, the maximum notes per denomination when change-making for users
 pub const DEFAULT_MAX_NOTES_PER_DENOMINATION: u16 = 3;
 
-/// The mint module currently doesn't define any consensus items and generally
-/// throws an error on encountering one. To allow old clients to still decode
-/// blocks in the future, should we decide to add consensus items, this has to
-/// be an enum with only a default variant.
+/// Prefix of the message signed for notes locked to a recipient key
+const LOCKED_NOTE_MESSAGE_TAG: &[u8] = b"fedimint-mint-locked-note";
+
+/// Consensus items of the mint, which are only needed if the federation
+/// rotates its keys, see [`config::KeyEpochParams`]
+///
+/// Has a default variant to allow old clients to still decode blocks
+/// containing consensus items added in the future.
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
 pub enum MintConsensusItem {
+    /// The key epoch the clock of the proposing peer is in
+    KeyEpochVote(u64),
     #[encodable_default]
     Default { variant: u64, bytes: Vec<u8> },
 }
 
 impl std::fmt::Display for MintConsensusItem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "MintConsensusItem")
+        match self {
+            MintConsensusItem::KeyEpochVote(epoch) => {
+                write!(f, "Mint Key Epoch Vote {epoch}")
+            }
+            MintConsensusItem::Default { variant, .. } => {
+                write!(f, "Unknown Mint Consensus Item (variant={variant})")
+            }
+        }
     }
 }
 
@@ -102,7 +127,7 @@ pub struct BlindNonce(pub tbs::BlindedMessage);
 pub struct MintCommonInit;
 
 impl CommonModuleInit for MintCommonInit {
-    const CONSENSUS_VERSION: ModuleConsensusVersion = CONSENSUS_VERSION;
+    const CONSENSUS_VERSION: ModuleConsensusVersion = MINT_CONSENSUS_VERSION;
     const KIND: ModuleKind = KIND;
 
     type ClientConfig = MintClientConfig;
@@ -112,12 +137,80 @@ impl CommonModuleInit for MintCommonInit {
     }
 }
 
-extensible_associated_module_type!(MintInput, MintInputV0, UnknownMintInputVariantError);
+/// Spends a [`Note`], see [`MintInputV0`] and [`MintInputV1`]
+///
+/// Written out instead of using `extensible_associated_module_type!` since the
+/// macro only supports a single version.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum MintInput {
+    V0(MintInputV0),
+    /// Introduced in 0.3.0
+    V1(MintInputV1),
+    #[encodable_default]
+    Default {
+        variant: u64,
+        bytes: Vec<u8>,
+    },
+}
+
+#[derive(
+    Debug, Error, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable,
+)]
+#[error("Unknown MintInput variant {variant}")]
+pub struct UnknownMintInputVariantError {
+    pub variant: u64,
+}
 
 impl MintInput {
     pub fn new_v0(amount: Amount, note: Note) -> MintInput {
         MintInput::V0(MintInputV0 { amount, note })
     }
+
+    pub fn new_v1(amount: Amount, note: Note, recipient: secp256k1_zkp::PublicKey) -> MintInput {
+        MintInput::V1(MintInputV1 {
+            amount,
+            note,
+            recipient,
+        })
+    }
+
+    pub fn maybe_v0_ref(&self) -> Option<&MintInputV0> {
+        match self {
+            MintInput::V0(v0) => Some(v0),
+            _ => None,
+        }
+    }
+
+    pub fn ensure_v0_ref(&self) -> Result<&MintInputV0, UnknownMintInputVariantError> {
+        match self {
+            MintInput::V0(v0) => Ok(v0),
+            MintInput::V1(_) => Err(UnknownMintInputVariantError { variant: 1 }),
+            MintInput::Default { variant, .. } => {
+                Err(UnknownMintInputVariantError { variant: *variant })
+            }
+        }
+    }
+
+    /// The amount of the spent note, `None` for unknown variants
+    pub fn maybe_amount(&self) -> Option<Amount> {
+        match self {
+            MintInput::V0(v0) => Some(v0.amount),
+            MintInput::V1(v1) => Some(v1.amount),
+            MintInput::Default { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for MintInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MintInput::V0(inner) => std::fmt::Display::fmt(inner, f),
+            MintInput::V1(inner) => std::fmt::Display::fmt(inner, f),
+            MintInput::Default { variant, .. } => {
+                write!(f, "Unknown MintInput (variant={variant})")
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
@@ -132,6 +225,25 @@ impl std::fmt::Display for MintInputV0 {
     }
 }
 
+/// Spends a note locked to `recipient`, see [`Nonce::to_locked_message`]
+///
+/// The note's signature commits to the recipient key, so only the holder of
+/// the corresponding secret key can sign the spending transaction. This allows
+/// a payer to hand over notes to an offline recipient, who can verify them
+/// offline and be sure that the payer cannot spend them anymore.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct MintInputV1 {
+    pub amount: Amount,
+    pub note: Note,
+    pub recipient: secp256k1_zkp::PublicKey,
+}
+
+impl std::fmt::Display for MintInputV1 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Locked Mint Note {}", self.amount)
+    }
+}
+
 extensible_associated_module_type!(MintOutput, MintOutputV0, UnknownMintOutputVariantError);
 
 impl MintOutput {
@@ -155,16 +267,86 @@ impl std::fmt::Display for MintOutputV0 {
     }
 }
 
-extensible_associated_module_type!(
-    MintOutputOutcome,
-    MintOutputOutcomeV0,
-    UnknownMintOutputOutcomeVariantError
-);
+/// Blind signature share of a peer for a [`MintOutput`], see
+/// [`MintOutputOutcomeV0`] and [`MintOutputOutcomeV1`]
+///
+/// Written out instead of using `extensible_associated_module_type!` since the
+/// macro only supports a single version.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum MintOutputOutcome {
+    V0(MintOutputOutcomeV0),
+    /// Introduced in 0.3.0
+    V1(MintOutputOutcomeV1),
+    #[encodable_default]
+    Default {
+        variant: u64,
+        bytes: Vec<u8>,
+    },
+}
+
+#[derive(
+    Debug, Error, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable,
+)]
+#[error("Unknown MintOutputOutcome variant {variant}")]
+pub struct UnknownMintOutputOutcomeVariantError {
+    pub variant: u64,
+}
 
 impl MintOutputOutcome {
     pub fn new_v0(blind_signature_share: BlindedSignatureShare) -> MintOutputOutcome {
         MintOutputOutcome::V0(MintOutputOutcomeV0(blind_signature_share))
     }
+
+    pub fn new_v1(epoch: u64, blind_signature_share: BlindedSignatureShare) -> MintOutputOutcome {
+        MintOutputOutcome::V1(MintOutputOutcomeV1 {
+            epoch,
+            blind_signature_share,
+        })
+    }
+
+    pub fn maybe_v0_ref(&self) -> Option<&MintOutputOutcomeV0> {
+        match self {
+            MintOutputOutcome::V0(v0) => Some(v0),
+            _ => None,
+        }
+    }
+
+    pub fn ensure_v0_ref(
+        &self,
+    ) -> Result<&MintOutputOutcomeV0, UnknownMintOutputOutcomeVariantError> {
+        match self {
+            MintOutputOutcome::V0(v0) => Ok(v0),
+            MintOutputOutcome::V1(_) => Err(UnknownMintOutputOutcomeVariantError { variant: 1 }),
+            MintOutputOutcome::Default { variant, .. } => {
+                Err(UnknownMintOutputOutcomeVariantError { variant: *variant })
+            }
+        }
+    }
+
+    /// The key epoch the share was created in along with the share itself
+    pub fn epoch_and_share(
+        &self,
+    ) -> Result<(u64, BlindedSignatureShare), UnknownMintOutputOutcomeVariantError> {
+        match self {
+            MintOutputOutcome::V0(v0) => Ok((0, v0.0)),
+            MintOutputOutcome::V1(v1) => Ok((v1.epoch, v1.blind_signature_share)),
+            MintOutputOutcome::Default { variant, .. } => {
+                Err(UnknownMintOutputOutcomeVariantError { variant: *variant })
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for MintOutputOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MintOutputOutcome::V0(inner) => std::fmt::Display::fmt(inner, f),
+            MintOutputOutcome::V1(inner) => std::fmt::Display::fmt(inner, f),
+            MintOutputOutcome::Default { variant, .. } => {
+                write!(f, "Unknown MintOutputOutcome (variant={variant})")
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
@@ -176,6 +358,21 @@ impl std::fmt::Display for MintOutputOutcomeV0 {
     }
 }
 
+/// Blind signature share created with the key of a key epoch other than the
+/// first one, whose shares are sent as [`MintOutputOutcomeV0`] to remain
+/// readable by old clients
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct MintOutputOutcomeV1 {
+    pub epoch: u64,
+    pub blind_signature_share: tbs::BlindedSignatureShare,
+}
+
+impl std::fmt::Display for MintOutputOutcomeV1 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MintOutputOutcome (key epoch {})", self.epoch)
+    }
+}
+
 pub struct MintModuleTypes;
 
 impl Note {
@@ -184,6 +381,16 @@ impl Note {
         tbs::verify(self.nonce.to_message(), self.signature, pk)
     }
 
+    /// Verify the validity of a note locked to `recipient` under a mint key
+    /// `pk`
+    pub fn verify_locked(
+        &self,
+        recipient: &secp256k1_zkp::PublicKey,
+        pk: tbs::AggregatePublicKey,
+    ) -> bool {
+        tbs::verify(self.nonce.to_locked_message(recipient), self.signature, pk)
+    }
+
     /// Access the nonce as the public key to the spend key
     pub fn spend_key(&self) -> &secp256k1_zkp::PublicKey {
         &self.nonce.0
@@ -205,6 +412,18 @@ impl Nonce {
     pub fn to_message(&self) -> tbs::Message {
         tbs::Message::from_bytes(&self.0.serialize()[..])
     }
+
+    /// The message signed by the mint for a note that can only be spent with a
+    /// signature of `recipient`
+    ///
+    /// Domain separated from [`Nonce::to_message`] so a locked note can never
+    /// be spent as a regular one.
+    pub fn to_locked_message(&self, recipient: &secp256k1_zkp::PublicKey) -> tbs::Message {
+        let mut bytes = LOCKED_NOTE_MESSAGE_TAG.to_vec();
+        bytes.extend_from_slice(&self.0.serialize());
+        bytes.extend_from_slice(&recipient.serialize());
+        tbs::Message::from_bytes(&bytes)
+    }
 }
 
 plugin_types_trait_impl_common!(
@@ -228,6 +447,10 @@ pub enum MintInputError {
     InvalidSignature,
     #[error("The mint input version is not supported by this federation")]
     UnknownInputVariant(#[from] UnknownMintInputVariantError),
+    #[error("The note was issued in a key epoch that has been retired")]
+    ExpiredNote,
+    #[error("The guardian holds no keys of the key epoch {0}")]
+    UnknownKeyEpoch(u64),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Error, Encodable, Decodable)]
@@ -236,4 +459,6 @@ pub enum MintOutputError {
     InvalidAmountTier(Amount),
     #[error("The mint output version is not supported by this federation")]
     UnknownOutputVariant(#[from] UnknownMintOutputVariantError),
+    #[error("The guardian holds no keys of the key epoch {0}")]
+    UnknownKeyEpoch(u64),
 }
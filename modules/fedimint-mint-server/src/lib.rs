@@ -1,19 +1,20 @@
 use std::collections::{BTreeMap, HashMap};
 use std::iter::FromIterator;
 
-use anyhow::bail;
+use anyhow::{bail, ensure, format_err};
 use fedimint_core::config::{
-    ConfigGenModuleParams, DkgResult, ServerModuleConfig, ServerModuleConsensusConfig,
-    TypedServerModuleConfig, TypedServerModuleConsensusConfig,
+    ConfigGenModuleParams, DkgPublicKeys, DkgResult, ServerModuleConfig,
+    ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
 };
 use fedimint_core::core::ModuleInstanceId;
 use fedimint_core::db::{DatabaseTransaction, DatabaseVersion, IDatabaseTransactionOpsCoreTyped};
-use fedimint_core::endpoint_constants::{BACKUP_ENDPOINT, RECOVER_ENDPOINT};
+use fedimint_core::endpoint_constants::{BACKUP_ENDPOINT, KEY_EPOCH_ENDPOINT, RECOVER_ENDPOINT};
 use fedimint_core::module::audit::Audit;
+use fedimint_core::module::liabilities::ModuleLiabilities;
 use fedimint_core::module::{
     api_endpoint, ApiEndpoint, ApiError, ApiVersion, CoreConsensusVersion, InputMeta,
-    ModuleConsensusVersion, ModuleInit, PeerHandle, ServerModuleInit, ServerModuleInitArgs,
-    SupportedModuleApiVersions, TransactionItemAmount,
+    ModuleConsensusVersion, ModuleInit, PeerHandle, PreviousModuleConfig, ServerModuleInit,
+    ServerModuleInitArgs, SupportedModuleApiVersions, TransactionItemAmount,
 };
 use fedimint_core::server::DynServerModule;
 use fedimint_core::{
@@ -23,24 +24,30 @@ use fedimint_core::{
 use fedimint_metrics::{histogram_opts, lazy_static, prometheus, register_histogram, Histogram};
 pub use fedimint_mint_common as common;
 use fedimint_mint_common::config::{
-    MintClientConfig, MintConfig, MintConfigConsensus, MintConfigLocal, MintConfigPrivate,
-    MintGenParams,
+    FeeConsensus, KeyEpochParams, MintClientConfig, MintConfig, MintConfigConsensus,
+    MintConfigLocal, MintConfigPrivate, MintGenParams, MAX_KEYED_EPOCHS,
 };
 use fedimint_mint_common::db::{
-    DbKeyPrefix, ECashUserBackupSnapshot, EcashBackupKey, EcashBackupKeyPrefix, MintAuditItemKey,
+    DbKeyPrefix, ECashUserBackupSnapshot, EcashBackupKey, EcashBackupKeyPrefix, EpochNonceKey,
+    EpochNonceKeyEpochPrefix, EpochNonceKeyPrefix, KeyEpochVoteKey, KeyEpochVotePrefix,
+    LockedNonceKey, LockedNonceKeyEpochPrefix, LockedNonceKeyPrefix, MintAuditItemKey,
     MintAuditItemKeyPrefix, MintOutputOutcomeKey, MintOutputOutcomePrefix, NonceKey,
     NonceKeyPrefix,
 };
 pub use fedimint_mint_common::{BackupRequest, SignedBackupRequest};
 use fedimint_mint_common::{
     MintCommonInit, MintConsensusItem, MintInput, MintInputError, MintModuleTypes, MintOutput,
-    MintOutputError, MintOutputOutcome, DEFAULT_MAX_NOTES_PER_DENOMINATION,
+    MintOutputError, MintOutputOutcome, UnknownMintInputVariantError,
+    DEFAULT_MAX_NOTES_PER_DENOMINATION, MINT_CONSENSUS_VERSION, MINT_CONSENSUS_VERSION_V0,
+};
+use fedimint_server::config::distributedgen::{
+    dkg_key, evaluate_polynomial_g2, scalar, DkgKeys, PeerHandleOps, PreviousKeys,
 };
-use fedimint_server::config::distributedgen::{evaluate_polynomial_g2, scalar, PeerHandleOps};
 use futures::StreamExt;
 use itertools::Itertools;
 use rand::rngs::OsRng;
 use secp256k1_zkp::SECP256K1;
+use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use tbs::{
     aggregate_public_key_shares, sign_blinded_msg, AggregatePublicKey, PublicKeyShare,
@@ -49,7 +56,7 @@ use tbs::{
 use threshold_crypto::ff::Field;
 use threshold_crypto::group::Curve;
 use threshold_crypto::{G2Projective, Scalar};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 lazy_static! {
     static ref AMOUNTS_BUCKETS_SATS: Vec<f64> = vec![
@@ -119,6 +126,34 @@ impl ModuleInit for MintInit {
                 DbKeyPrefix::NoteNonce => {
                     push_db_key_items!(dbtx, NonceKeyPrefix, NonceKey, mint, "Used Coins");
                 }
+                DbKeyPrefix::LockedNoteNonce => {
+                    push_db_key_items!(
+                        dbtx,
+                        LockedNonceKeyPrefix,
+                        LockedNonceKey,
+                        mint,
+                        "Used Locked Coins"
+                    );
+                }
+                DbKeyPrefix::EpochNoteNonce => {
+                    push_db_key_items!(
+                        dbtx,
+                        EpochNonceKeyPrefix,
+                        EpochNonceKey,
+                        mint,
+                        "Used Coins Of Later Key Epochs"
+                    );
+                }
+                DbKeyPrefix::KeyEpochVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        KeyEpochVotePrefix,
+                        KeyEpochVoteKey,
+                        u64,
+                        mint,
+                        "Key Epoch Votes"
+                    );
+                }
                 DbKeyPrefix::MintAuditItem => {
                     push_db_pair_items!(
                         dbtx,
@@ -161,8 +196,7 @@ impl ServerModuleInit for MintInit {
     type Params = MintGenParams;
 
     fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
-        const MODULE_CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion::new(0, 0);
-        &[MODULE_CONSENSUS_VERSION]
+        &[MINT_CONSENSUS_VERSION_V0, MINT_CONSENSUS_VERSION]
     }
 
     fn supported_api_versions(&self) -> SupportedModuleApiVersions {
@@ -174,7 +208,22 @@ impl ServerModuleInit for MintInit {
         for metric in ALL_METRICS.iter() {
             metric.collect();
         }
-        Ok(Mint::new(args.cfg().to_typed()?).into())
+        let consensus_version = args.cfg().consensus.version;
+
+        ensure!(
+            [MINT_CONSENSUS_VERSION_V0, MINT_CONSENSUS_VERSION].contains(&consensus_version),
+            "Unsupported mint consensus version {consensus_version:?}"
+        );
+
+        let cfg = args.cfg().to_typed::<MintConfig>()?;
+
+        ensure!(
+            consensus_version != MINT_CONSENSUS_VERSION_V0
+                || cfg.consensus.key_epochs.num_epochs == 1,
+            "Key epochs require the mint consensus version {MINT_CONSENSUS_VERSION:?}"
+        );
+
+        Ok(Mint::new_with_version(cfg, consensus_version).into())
     }
 
     fn trusted_dealer_gen(
@@ -183,46 +232,58 @@ impl ServerModuleInit for MintInit {
         params: &ConfigGenModuleParams,
     ) -> BTreeMap<PeerId, ServerModuleConfig> {
         let params = self.parse_params(params).unwrap();
+        let denominations = params.consensus.gen_denominations();
+        let key_epochs = params.consensus.key_epochs();
 
-        let tbs_keys = params
-            .consensus
-            .gen_denominations()
-            .iter()
-            .map(|&amount| {
-                let (tbs_pk, tbs_pks, tbs_sks) = dealer_keygen(peers.threshold(), peers.len());
-                (amount, (tbs_pk, tbs_pks, tbs_sks))
+        let tbs_keys = (0..key_epochs.num_epochs)
+            .map(|epoch| {
+                let keys = denominations
+                    .iter()
+                    .map(|&amount| (amount, dealer_keygen(peers.threshold(), peers.len())))
+                    .collect::<DealerKeys>();
+                (epoch, keys)
             })
-            .collect::<HashMap<_, _>>();
+            .collect::<BTreeMap<_, _>>();
 
         let mint_cfg: BTreeMap<_, MintConfig> = peers
             .iter()
             .map(|&peer| {
+                let peer_tbs_pks = |epoch_keys: &DealerKeys| {
+                    peers
+                        .iter()
+                        .map(|&key_peer| {
+                            let keys = denominations
+                                .iter()
+                                .map(|amount| (*amount, epoch_keys[amount].1[key_peer.to_usize()]))
+                                .collect();
+                            (key_peer, keys)
+                        })
+                        .collect::<BTreeMap<_, _>>()
+                };
+                let tbs_sks = |epoch_keys: &DealerKeys| {
+                    denominations
+                        .iter()
+                        .map(|amount| (*amount, epoch_keys[amount].2[peer.to_usize()]))
+                        .collect::<Tiered<_>>()
+                };
+
                 let config = MintConfig {
                     local: MintConfigLocal,
                     consensus: MintConfigConsensus {
-                        peer_tbs_pks: peers
-                            .iter()
-                            .map(|&key_peer| {
-                                let keys = params
-                                    .consensus
-                                    .gen_denominations()
-                                    .iter()
-                                    .map(|amount| {
-                                        (*amount, tbs_keys[amount].1[key_peer.to_usize()])
-                                    })
-                                    .collect();
-                                (key_peer, keys)
-                            })
-                            .collect(),
+                        peer_tbs_pks: peer_tbs_pks(&tbs_keys[&0]),
                         fee_consensus: params.consensus.fee_consensus(),
                         max_notes_per_denomination: DEFAULT_MAX_NOTES_PER_DENOMINATION,
+                        key_epochs,
+                        epoch_peer_tbs_pks: tbs_keys
+                            .range(1..)
+                            .map(|(epoch, keys)| (*epoch, peer_tbs_pks(keys)))
+                            .collect(),
                     },
                     private: MintConfigPrivate {
-                        tbs_sks: params
-                            .consensus
-                            .gen_denominations()
-                            .iter()
-                            .map(|amount| (*amount, tbs_keys[amount].2[peer.to_usize()]))
+                        tbs_sks: tbs_sks(&tbs_keys[&0]),
+                        epoch_tbs_sks: tbs_keys
+                            .range(1..)
+                            .map(|(epoch, keys)| (*epoch, tbs_sks(keys)))
                             .collect(),
                     },
                 };
@@ -242,74 +303,192 @@ impl ServerModuleInit for MintInit {
         params: &ConfigGenModuleParams,
     ) -> DkgResult<ServerModuleConfig> {
         let params = self.parse_params(params).unwrap();
+        let key_epochs = params.consensus.key_epochs();
+
+        if MAX_KEYED_EPOCHS < key_epochs.num_epochs as usize {
+            return Err(
+                format_err!("At most {MAX_KEYED_EPOCHS} key epochs can be scheduled").into(),
+            );
+        }
 
         let g2 = peers
-            .run_dkg_multi_g2(params.consensus.gen_denominations())
+            .run_dkg_multi_g2(MintDkgKey::all(
+                0..key_epochs.num_epochs,
+                &params.consensus.gen_denominations(),
+            ))
             .await?;
 
-        let amounts_keys = g2
-            .into_iter()
-            .map(|(amount, keys)| (amount, keys.tbs()))
-            .collect::<HashMap<_, _>>();
+        Ok(mint_config(
+            peers,
+            g2,
+            params.consensus.fee_consensus(),
+            DEFAULT_MAX_NOTES_PER_DENOMINATION,
+            key_epochs,
+        )
+        .to_erased())
+    }
 
-        let server = MintConfig {
-            local: MintConfigLocal,
-            private: MintConfigPrivate {
-                tbs_sks: amounts_keys
-                    .iter()
-                    .map(|(amount, (_, sks))| (*amount, *sks))
-                    .collect(),
-            },
-            consensus: MintConfigConsensus {
-                peer_tbs_pks: peers
-                    .peer_ids()
+    async fn distributed_reshare(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+        previous: &PreviousModuleConfig,
+    ) -> DkgResult<ServerModuleConfig> {
+        let previous_consensus = MintConfigConsensus::from_erased(&previous.consensus)?;
+        let previous_sks = previous
+            .ours
+            .as_ref()
+            .map(|cfg| cfg.to_typed::<MintConfig>())
+            .transpose()?
+            .map(|cfg| cfg.private);
+        let threshold = previous_consensus.peer_tbs_pks.threshold();
+
+        // Notes issued by the previous federation stay valid, so we keep its
+        // denominations along with the fees
+        let denominations = previous_consensus
+            .peer_tbs_pks
+            .values()
+            .next()
+            .ok_or_else(|| format_err!("The previous federation has no peers"))?
+            .tiers()
+            .copied()
+            .collect::<Vec<Amount>>();
+
+        // The schedule stays the same unless the new params extend it, in which
+        // case keys for the added epochs are generated from scratch
+        let previous_epochs = previous_consensus.key_epochs;
+        let key_epochs = previous_epochs.extend(
+            &self.parse_params(params)?.consensus.key_epochs(),
+            fedimint_core::time::now(),
+        )?;
+        let new_epochs = previous_epochs.num_epochs..key_epochs.num_epochs;
+
+        // Extending happens in the last epoch, so we drop the keys of the epochs
+        // that retired before it. The first epoch is kept for old clients.
+        let reshared_epochs = previous_consensus
+            .keyed_epochs()
+            .filter(|&epoch| {
+                epoch == 0
+                    || new_epochs.is_empty()
+                    || !previous_epochs.is_retired(epoch, previous_epochs.last_epoch())
+            })
+            .collect::<Vec<_>>();
+
+        if MAX_KEYED_EPOCHS < reshared_epochs.len() + new_epochs.clone().count() {
+            return Err(
+                format_err!("At most {MAX_KEYED_EPOCHS} key epochs can be scheduled").into(),
+            );
+        }
+
+        let previous_keys = MintDkgKey::all(reshared_epochs, &denominations)
+            .into_iter()
+            .map(|key| {
+                let (epoch, amount) = key.epoch_and_amount();
+                let public_key_shares = previous_consensus
+                    .peer_tbs_pks_of_epoch(epoch)
+                    .ok_or_else(|| format_err!("Missing public keys of key epoch {epoch}"))?
                     .iter()
-                    .map(|peer| {
-                        let pks = amounts_keys
-                            .iter()
-                            .map(|(amount, (pks, _))| {
-                                (
-                                    *amount,
-                                    PublicKeyShare(evaluate_polynomial_g2(pks, &scalar(peer))),
-                                )
-                            })
-                            .collect::<Tiered<_>>();
-
-                        (*peer, pks)
+                    .map(|(peer, pks)| {
+                        let pk = pks.tier(&amount).map_err(|error| format_err!("{error}"))?;
+                        Ok((*peer, G2Projective::from(pk.0)))
                     })
-                    .collect(),
-                fee_consensus: params.consensus.fee_consensus(),
-                max_notes_per_denomination: DEFAULT_MAX_NOTES_PER_DENOMINATION,
-            },
-        };
+                    .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
+                let secret_key_share = previous_sks
+                    .as_ref()
+                    .map(|private| {
+                        private
+                            .tbs_sks_of_epoch(epoch)
+                            .ok_or_else(|| format_err!("Missing secret keys of key epoch {epoch}"))?
+                            .tier(&amount)
+                            .map(|sk| sk.0)
+                            .map_err(|error| format_err!("{error}"))
+                    })
+                    .transpose()?;
+
+                Ok((
+                    key,
+                    PreviousKeys {
+                        threshold,
+                        public_key_shares,
+                        secret_key_share,
+                    },
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
 
-        Ok(server.to_erased())
+        let mut g2 = peers
+            .run_reshare_multi_g2(previous_keys, &previous.previous_peers)
+            .await?;
+
+        if !new_epochs.is_empty() {
+            info!(?new_epochs, "Extending the key epoch schedule");
+
+            g2.extend(
+                peers
+                    .run_dkg_multi_g2(MintDkgKey::all(new_epochs, &denominations))
+                    .await?,
+            );
+        }
+
+        Ok(mint_config(
+            peers,
+            g2,
+            previous_consensus.fee_consensus,
+            previous_consensus.max_notes_per_denomination,
+            key_epochs,
+        )
+        .to_erased())
     }
 
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
         let config = config.to_typed::<MintConfig>()?;
-        let sks: BTreeMap<Amount, PublicKeyShare> = config
-            .private
-            .tbs_sks
-            .iter()
-            .map(|(amount, sk)| (amount, sk.to_pub_key_share()))
-            .collect();
-        let pks: BTreeMap<Amount, PublicKeyShare> = config
+        let key_epochs = config.consensus.key_epochs;
+        key_epochs.validate()?;
+
+        if !config
             .consensus
-            .peer_tbs_pks
-            .get(identity)
-            .unwrap()
-            .as_map()
-            .iter()
-            .map(|(k, v)| (*k, *v))
-            .collect();
-        if sks != pks {
-            bail!("Mint private key doesn't match pubkey share");
+            .epoch_peer_tbs_pks
+            .keys()
+            .eq(config.private.epoch_tbs_sks.keys())
+        {
+            bail!("Mint keys don't match the key epochs");
         }
-        if !sks.keys().contains(&Amount::from_msats(1)) {
-            bail!("No msat 1 denomination");
+
+        // The keys of every epoch that did not retire before the last one are kept
+        if config.consensus.keyed_epochs().last() != Some(key_epochs.last_epoch())
+            || MAX_KEYED_EPOCHS < config.consensus.keyed_epochs().count()
+        {
+            bail!("Mint keys don't match the key epoch schedule");
         }
 
+        for epoch in config.consensus.keyed_epochs() {
+            let sks: BTreeMap<Amount, PublicKeyShare> = config
+                .private
+                .tbs_sks_of_epoch(epoch)
+                .expect("Checked above")
+                .iter()
+                .map(|(amount, sk)| (amount, sk.to_pub_key_share()))
+                .collect();
+            let pks: BTreeMap<Amount, PublicKeyShare> = config
+                .consensus
+                .peer_tbs_pks_of_epoch(epoch)
+                .expect("Checked above")
+                .get(identity)
+                .unwrap()
+                .as_map()
+                .iter()
+                .map(|(k, v)| (*k, *v))
+                .collect();
+            if sks != pks {
+                bail!("Mint private key doesn't match pubkey share");
+            }
+            if !sks.keys().contains(&Amount::from_msats(1)) {
+                bail!("No msat 1 denomination");
+            }
+        }
+
+        config.consensus.fee_consensus.validate()?;
+
         Ok(())
     }
 
@@ -318,34 +497,56 @@ impl ServerModuleInit for MintInit {
         config: &ServerModuleConsensusConfig,
     ) -> anyhow::Result<MintClientConfig> {
         let config = MintConfigConsensus::from_erased(config)?;
-        // TODO: the aggregate pks should become part of the MintConfigConsensus as they
-        // can be obtained by evaluating the polynomial returned by the DKG at
-        // zero
-        let pub_keys = TieredMultiZip::new(
-            config
-                .peer_tbs_pks
-                .values()
-                .map(|keys| keys.iter())
-                .collect(),
-        )
-        .map(|(amt, keys)| {
-            let keys = (1_u64..)
-                .zip(keys.into_iter().cloned())
-                .take(config.peer_tbs_pks.threshold())
-                .collect();
-
-            (amt, aggregate_public_key_shares(&keys))
-        });
 
         Ok(MintClientConfig {
-            tbs_pks: Tiered::from_iter(pub_keys),
+            tbs_pks: Tiered::from_iter(aggregate_pub_keys(&config.peer_tbs_pks)),
             fee_consensus: config.fee_consensus.clone(),
             peer_tbs_pks: config.peer_tbs_pks.clone(),
             max_notes_per_denomination: config.max_notes_per_denomination,
+            key_epochs: config.key_epochs,
+            epoch_tbs_pks: config
+                .epoch_peer_tbs_pks
+                .iter()
+                .map(|(epoch, peer_tbs_pks)| {
+                    (*epoch, Tiered::from_iter(aggregate_pub_keys(peer_tbs_pks)))
+                })
+                .collect(),
+            epoch_peer_tbs_pks: config.epoch_peer_tbs_pks.clone(),
         })
     }
+
+    fn dkg_public_keys(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<BTreeMap<String, DkgPublicKeys>> {
+        let config = MintConfigConsensus::from_erased(config)?;
+
+        let mut shares: BTreeMap<String, BTreeMap<PeerId, G2Projective>> = BTreeMap::new();
+        for epoch in config.keyed_epochs() {
+            let peer_tbs_pks = config
+                .peer_tbs_pks_of_epoch(epoch)
+                .ok_or_else(|| format_err!("Missing public keys of key epoch {epoch}"))?;
+
+            for (peer, pks) in peer_tbs_pks {
+                for (amount, pk) in pks.iter() {
+                    shares
+                        .entry(dkg_key(&MintDkgKey::new(epoch, amount)))
+                        .or_default()
+                        .insert(*peer, pk.0.into());
+                }
+            }
+        }
+
+        Ok(shares
+            .into_iter()
+            .map(|(key, shares)| (key, DkgPublicKeys::G2Shares(shares)))
+            .collect())
+    }
 }
 
+/// Keys of every denomination of a key epoch generated by [`dealer_keygen`]
+type DealerKeys = HashMap<Amount, (AggregatePublicKey, Vec<PublicKeyShare>, Vec<SecretKeyShare>)>;
+
 fn dealer_keygen(
     threshold: usize,
     keys: usize,
@@ -376,32 +577,307 @@ fn eval_polynomial(coefficients: &[Scalar], x: &Scalar) -> Scalar {
         .expect("We have at least one coefficient")
 }
 
+/// Identifies a key of the mint generated during the DKG
+///
+/// Serialized untagged so the keys of the first key epoch are identified by
+/// their denomination like before keys were rotated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+enum MintDkgKey {
+    Denomination(Amount),
+    EpochDenomination(u64, Amount),
+}
+
+impl MintDkgKey {
+    fn new(epoch: u64, amount: Amount) -> MintDkgKey {
+        match epoch {
+            0 => MintDkgKey::Denomination(amount),
+            epoch => MintDkgKey::EpochDenomination(epoch, amount),
+        }
+    }
+
+    /// The keys of every denomination in each of the key `epochs`
+    fn all(epochs: impl IntoIterator<Item = u64>, denominations: &[Amount]) -> Vec<MintDkgKey> {
+        epochs
+            .into_iter()
+            .flat_map(|epoch| {
+                denominations
+                    .iter()
+                    .map(move |&amount| MintDkgKey::new(epoch, amount))
+            })
+            .collect()
+    }
+
+    fn epoch_and_amount(self) -> (u64, Amount) {
+        match self {
+            MintDkgKey::Denomination(amount) => (0, amount),
+            MintDkgKey::EpochDenomination(epoch, amount) => (epoch, amount),
+        }
+    }
+}
+
+/// Creates the config of the mint from the keys generated for every
+/// denomination of every key epoch
+fn mint_config(
+    peers: &PeerHandle,
+    keys: HashMap<MintDkgKey, DkgKeys<G2Projective>>,
+    fee_consensus: FeeConsensus,
+    max_notes_per_denomination: u16,
+    key_epochs: KeyEpochParams,
+) -> MintConfig {
+    let mut epoch_keys = BTreeMap::<u64, HashMap<_, _>>::new();
+    for (key, keys) in keys {
+        let (epoch, amount) = key.epoch_and_amount();
+        epoch_keys
+            .entry(epoch)
+            .or_default()
+            .insert(amount, keys.tbs());
+    }
+
+    let mut tbs_sks = epoch_keys
+        .iter()
+        .map(|(epoch, amounts_keys)| {
+            let sks = amounts_keys
+                .iter()
+                .map(|(amount, (_, sks))| (*amount, *sks))
+                .collect::<Tiered<_>>();
+            (*epoch, sks)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut peer_tbs_pks = epoch_keys
+        .iter()
+        .map(|(epoch, amounts_keys)| {
+            let pks = peers
+                .peer_ids()
+                .iter()
+                .map(|peer| {
+                    let pks = amounts_keys
+                        .iter()
+                        .map(|(amount, (pks, _))| {
+                            (
+                                *amount,
+                                PublicKeyShare(evaluate_polynomial_g2(pks, &scalar(peer))),
+                            )
+                        })
+                        .collect::<Tiered<_>>();
+
+                    (*peer, pks)
+                })
+                .collect::<BTreeMap<_, _>>();
+            (*epoch, pks)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let epoch_tbs_sks = tbs_sks.split_off(&1);
+    let epoch_peer_tbs_pks = peer_tbs_pks.split_off(&1);
+
+    MintConfig {
+        local: MintConfigLocal,
+        private: MintConfigPrivate {
+            tbs_sks: tbs_sks
+                .remove(&0)
+                .expect("The keys of the first epoch are always kept"),
+            epoch_tbs_sks,
+        },
+        consensus: MintConfigConsensus {
+            peer_tbs_pks: peer_tbs_pks
+                .remove(&0)
+                .expect("The keys of the first epoch are always kept"),
+            fee_consensus,
+            max_notes_per_denomination,
+            key_epochs,
+            epoch_peer_tbs_pks,
+        },
+    }
+}
+
+/// Aggregates a threshold of the public key shares of every denomination
+// TODO: the aggregate pks should become part of the MintConfigConsensus as they
+// can be obtained by evaluating the polynomial returned by the DKG at zero
+fn aggregate_pub_keys(
+    peer_tbs_pks: &BTreeMap<PeerId, Tiered<PublicKeyShare>>,
+) -> impl Iterator<Item = (Amount, AggregatePublicKey)> + '_ {
+    TieredMultiZip::new(peer_tbs_pks.values().map(|keys| keys.iter()).collect()).map(
+        |(amt, keys)| {
+            let keys = (1_u64..)
+                .zip(keys.into_iter().cloned())
+                .take(peer_tbs_pks.threshold())
+                .collect();
+
+            (amt, aggregate_public_key_shares(&keys))
+        },
+    )
+}
+
+/// The amount, message and signature of the note spent by `input`
+fn signed_message(
+    input: &MintInput,
+) -> Result<(Amount, tbs::Message, tbs::Signature), MintInputError> {
+    match input {
+        MintInput::V0(input) => Ok((
+            input.amount,
+            input.note.nonce.to_message(),
+            input.note.signature,
+        )),
+        MintInput::V1(input) => Ok((
+            input.amount,
+            input.note.nonce.to_locked_message(&input.recipient),
+            input.note.signature,
+        )),
+        MintInput::Default { variant, .. } => {
+            Err(UnknownMintInputVariantError { variant: *variant }.into())
+        }
+    }
+}
+
 /// Federated mint member mint
 #[derive(Debug)]
 pub struct Mint {
     cfg: MintConfig,
-    sec_key: Tiered<SecretKeyShare>,
-    pub_key: HashMap<Amount, AggregatePublicKey>,
+    our_peer_id: PeerId,
+    /// Secret keys of every key epoch we hold keys for, keyed by epoch
+    sec_keys: BTreeMap<u64, Tiered<SecretKeyShare>>,
+    /// Aggregate public keys of every key epoch we hold keys for, keyed by
+    /// epoch
+    pub_keys: BTreeMap<u64, HashMap<Amount, AggregatePublicKey>>,
+    /// The consensus version the federation runs, see
+    /// [`MINT_CONSENSUS_VERSION`]
+    consensus_version: ModuleConsensusVersion,
 }
 #[apply(async_trait_maybe_send!)]
 impl ServerModule for Mint {
     type Common = MintModuleTypes;
     type Init = MintInit;
+    /// The key epoch the note was issued in, if its signature was verified
+    type VerifiedInput = Option<u64>;
 
     async fn consensus_proposal(
         &self,
-        _dbtx: &mut DatabaseTransaction<'_>,
+        dbtx: &mut DatabaseTransaction<'_>,
     ) -> Vec<MintConsensusItem> {
-        Vec::new()
+        let key_epochs = self.cfg.consensus.key_epochs;
+
+        // Without key rotation there is nothing to agree on
+        if key_epochs.num_epochs == 1 || !self.supports_key_epochs() {
+            return Vec::new();
+        }
+
+        let epoch = key_epochs.epoch_at(fedimint_core::time::now());
+        let current_vote = dbtx
+            .get_value(&KeyEpochVoteKey(self.our_peer_id))
+            .await
+            .unwrap_or(0);
+
+        if epoch <= current_vote {
+            return Vec::new();
+        }
+
+        debug!(?current_vote, ?epoch, "Proposing key epoch");
+
+        vec![MintConsensusItem::KeyEpochVote(epoch)]
     }
 
     async fn process_consensus_item<'a, 'b>(
         &'a self,
-        _dbtx: &mut DatabaseTransaction<'b>,
-        _consensus_item: MintConsensusItem,
-        _peer_id: PeerId,
+        dbtx: &mut DatabaseTransaction<'b>,
+        consensus_item: MintConsensusItem,
+        peer_id: PeerId,
     ) -> anyhow::Result<()> {
-        bail!("Mint does not process consensus items");
+        match consensus_item {
+            MintConsensusItem::KeyEpochVote(epoch) => {
+                if !self.supports_key_epochs() {
+                    bail!("Key epoch votes are not supported by the consensus version");
+                }
+
+                let key_epochs = self.cfg.consensus.key_epochs;
+
+                if key_epochs.last_epoch() < epoch {
+                    bail!("Key epoch vote exceeds the last key epoch");
+                }
+
+                let current_vote = dbtx.get_value(&KeyEpochVoteKey(peer_id)).await.unwrap_or(0);
+
+                if epoch <= current_vote {
+                    debug!(?peer_id, ?epoch, "Received outdated key epoch vote");
+                    bail!("Key epoch vote is redundant");
+                }
+
+                let old_epoch = self.consensus_key_epoch(dbtx).await;
+
+                dbtx.insert_entry(&KeyEpochVoteKey(peer_id), &epoch).await;
+
+                let new_epoch = self.consensus_key_epoch(dbtx).await;
+
+                debug!(
+                    ?peer_id,
+                    ?current_vote,
+                    ?epoch,
+                    ?old_epoch,
+                    ?new_epoch,
+                    "Received key epoch vote"
+                );
+
+                if old_epoch < new_epoch {
+                    info!(?old_epoch, ?new_epoch, "Entered new key epoch");
+
+                    self.prune_retired_epochs(dbtx, old_epoch, new_epoch).await;
+
+                    if new_epoch == key_epochs.last_epoch() {
+                        warn!(
+                            ?new_epoch,
+                            "Entered the last key epoch, its spent nonces are kept until the schedule is extended by resharing the keys"
+                        );
+                    }
+                }
+
+                Ok(())
+            }
+            MintConsensusItem::Default { variant, .. } => {
+                bail!("Received unknown consensus item variant {variant}");
+            }
+        }
+    }
+
+    async fn verify_inputs_batch<'a, 'b>(
+        &'a self,
+        dbtx: &mut DatabaseTransaction<'b>,
+        inputs: &[&MintInput],
+    ) -> Result<Vec<Option<u64>>, MintInputError> {
+        // a single note is verified just as fast by process_verified_input
+        if inputs.len() < 2 {
+            return Ok(vec![None; inputs.len()]);
+        }
+
+        let current_epoch = self.consensus_key_epoch(dbtx).await;
+        let pub_keys = self
+            .pub_keys
+            .get(&current_epoch)
+            .ok_or(MintInputError::UnknownKeyEpoch(current_epoch))?;
+
+        let mut batch = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            self.ensure_supported_input(input)?;
+
+            let (amount, message, signature) = signed_message(input)?;
+
+            let pub_key = pub_keys
+                .get(&amount)
+                .ok_or(MintInputError::InvalidAmountTier(amount))?;
+
+            batch.push((message, signature, *pub_key));
+        }
+
+        // Usually all notes were issued in the current key epoch, otherwise we
+        // check the notes one by one to find their epochs or the invalid note
+        if tbs::verify_batch(&batch) {
+            Ok(vec![Some(current_epoch); inputs.len()])
+        } else {
+            inputs
+                .iter()
+                .map(|input| self.verify_input_epoch(current_epoch, input).map(Some))
+                .collect()
+        }
     }
 
     async fn process_input<'a, 'b, 'c>(
@@ -409,39 +885,97 @@ impl ServerModule for Mint {
         dbtx: &mut DatabaseTransaction<'c>,
         input: &'b MintInput,
     ) -> Result<InputMeta, MintInputError> {
-        let input = input.ensure_v0_ref()?;
+        self.process_verified_input(dbtx, input, None).await
+    }
 
-        let amount_key = self
-            .pub_key
-            .get(&input.amount)
-            .ok_or(MintInputError::InvalidAmountTier(input.amount))?;
+    async fn process_verified_input<'a, 'b, 'c>(
+        &'a self,
+        dbtx: &mut DatabaseTransaction<'c>,
+        input: &'b MintInput,
+        verified_epoch: Option<u64>,
+    ) -> Result<InputMeta, MintInputError> {
+        self.ensure_supported_input(input)?;
+
+        let current_epoch = self.consensus_key_epoch(dbtx).await;
+        let epoch = self.spendable_input_epoch(current_epoch, input, verified_epoch)?;
+
+        let (amount, pub_key) = match input {
+            MintInput::V0(input) => {
+                // Notes of the first epoch are tracked like before keys were rotated
+                let spent = if epoch == 0 {
+                    dbtx.insert_entry(&NonceKey(input.note.nonce), &())
+                        .await
+                        .is_some()
+                } else {
+                    dbtx.insert_entry(
+                        &EpochNonceKey {
+                            epoch,
+                            nonce: input.note.nonce,
+                        },
+                        &(),
+                    )
+                    .await
+                    .is_some()
+                };
 
-        if !input.note.verify(*amount_key) {
-            return Err(MintInputError::InvalidSignature);
-        }
+                if spent {
+                    return Err(MintInputError::SpentCoin);
+                }
 
-        if dbtx
-            .insert_entry(&NonceKey(input.note.nonce), &())
-            .await
-            .is_some()
-        {
-            return Err(MintInputError::SpentCoin);
-        }
+                let audit_key = if epoch == 0 {
+                    MintAuditItemKey::Redemption(NonceKey(input.note.nonce))
+                } else {
+                    MintAuditItemKey::EpochRedemption(EpochNonceKey {
+                        epoch,
+                        nonce: input.note.nonce,
+                    })
+                };
 
-        dbtx.insert_new_entry(
-            &MintAuditItemKey::Redemption(NonceKey(input.note.nonce)),
-            &input.amount,
-        )
-        .await;
-        let amount = input.amount;
-        let fee = self.cfg.consensus.fee_consensus.note_spend_abs;
+                dbtx.insert_new_entry(&audit_key, &input.amount).await;
+
+                (input.amount, *input.note.spend_key())
+            }
+            MintInput::V1(input) => {
+                let locked_nonce_key = LockedNonceKey {
+                    epoch,
+                    nonce: input.note.nonce,
+                    recipient: input.recipient,
+                };
+
+                if dbtx.insert_entry(&locked_nonce_key, &()).await.is_some() {
+                    return Err(MintInputError::SpentCoin);
+                }
+
+                dbtx.insert_new_entry(
+                    &MintAuditItemKey::LockedRedemption(locked_nonce_key),
+                    &input.amount,
+                )
+                .await;
+
+                // the recipient has to sign the transaction instead of the note's owner
+                (input.amount, input.recipient)
+            }
+            MintInput::Default { variant, .. } => {
+                return Err(UnknownMintInputVariantError { variant: *variant }.into());
+            }
+        };
+
+        let fee = self.cfg.consensus.fee_consensus.note_spend_fee(amount);
         calculate_mint_redeemed_ecash_metrics(dbtx, amount, fee);
         Ok(InputMeta {
             amount: TransactionItemAmount { amount, fee },
-            pub_key: *input.note.spend_key(),
+            pub_key,
         })
     }
 
+    fn input_to_json(&self, input: &MintInput) -> Option<serde_json::Value> {
+        Some(serde_json::to_value(input).expect("serialization can't fail"))
+    }
+
+    fn output_to_json(&self, output: &MintOutput) -> Option<serde_json::Value> {
+        Some(serde_json::to_value(output).expect("serialization can't fail"))
+    }
+
     async fn process_output<'a, 'b>(
         &'a self,
         dbtx: &mut DatabaseTransaction<'b>,
@@ -450,21 +984,37 @@ impl ServerModule for Mint {
     ) -> Result<TransactionItemAmount, MintOutputError> {
         let output = output.ensure_v0_ref()?;
 
+        // New notes are always signed with the key of the current epoch
+        let epoch = self.consensus_key_epoch(dbtx).await;
+
         let amount_key = self
-            .sec_key
+            .sec_keys
+            .get(&epoch)
+            .ok_or(MintOutputError::UnknownKeyEpoch(epoch))?
             .get(output.amount)
             .ok_or(MintOutputError::InvalidAmountTier(output.amount))?;
 
-        dbtx.insert_new_entry(
-            &MintOutputOutcomeKey(out_point),
-            &MintOutputOutcome::new_v0(sign_blinded_msg(output.blind_nonce.0, *amount_key)),
-        )
-        .await;
+        let blind_signature_share = sign_blinded_msg(output.blind_nonce.0, *amount_key);
+
+        // Old clients can only read outcomes of the first epoch, which is the only
+        // epoch of federations that do not support key epochs
+        let outcome = if epoch == 0 {
+            MintOutputOutcome::new_v0(blind_signature_share)
+        } else {
+            MintOutputOutcome::new_v1(epoch, blind_signature_share)
+        };
+
+        dbtx.insert_new_entry(&MintOutputOutcomeKey(out_point), &outcome)
+            .await;
 
         dbtx.insert_new_entry(&MintAuditItemKey::Issuance(out_point), &output.amount)
             .await;
         let amount = output.amount;
-        let fee = self.cfg.consensus.fee_consensus.note_issuance_abs;
+        let fee = self
+            .cfg
+            .consensus
+            .fee_consensus
+            .note_issuance_fee(output.amount);
         calculate_mint_issued_ecash_metrics(dbtx, amount, fee);
         Ok(TransactionItemAmount { amount, fee })
     }
@@ -494,6 +1044,8 @@ impl ServerModule for Mint {
                     MintAuditItemKey::IssuanceTotal => issuances += amount,
                     MintAuditItemKey::Redemption(_) => redemptions += amount,
                     MintAuditItemKey::RedemptionTotal => redemptions += amount,
+                    MintAuditItemKey::LockedRedemption(_) => redemptions += amount,
+                    MintAuditItemKey::EpochRedemption(_) => redemptions += amount,
                 }
                 key
             })
@@ -519,11 +1071,43 @@ impl ServerModule for Mint {
                     MintAuditItemKey::IssuanceTotal => -(v.msats as i64),
                     MintAuditItemKey::Redemption(_) => v.msats as i64,
                     MintAuditItemKey::RedemptionTotal => v.msats as i64,
+                    MintAuditItemKey::LockedRedemption(_) => v.msats as i64,
+                    MintAuditItemKey::EpochRedemption(_) => v.msats as i64,
                 },
             )
             .await;
     }
 
+    async fn liabilities(&self, dbtx: &mut DatabaseTransaction<'_>) -> ModuleLiabilities {
+        let mut redemptions = Amount::ZERO;
+        let mut issuances = Amount::ZERO;
+
+        let audit_items = dbtx
+            .find_by_prefix(&MintAuditItemKeyPrefix)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        for (key, amount) in audit_items {
+            match key {
+                MintAuditItemKey::Issuance(_) | MintAuditItemKey::IssuanceTotal => {
+                    issuances += amount;
+                }
+                MintAuditItemKey::Redemption(_)
+                | MintAuditItemKey::RedemptionTotal
+                | MintAuditItemKey::LockedRedemption(_)
+                | MintAuditItemKey::EpochRedemption(_) => {
+                    redemptions += amount;
+                }
+            }
+        }
+
+        ModuleLiabilities {
+            liabilities: issuances.saturating_sub(redemptions),
+            reserves: vec![],
+        }
+    }
+
     fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
         vec![
             api_endpoint! {
@@ -535,6 +1119,13 @@ impl ServerModule for Mint {
                     Ok(())
                 }
             },
+            api_endpoint! {
+                KEY_EPOCH_ENDPOINT,
+                ApiVersion::new(0, 0),
+                async |module: &Mint, context, _params: ()| -> u64 {
+                    Ok(module.consensus_key_epoch(&mut context.dbtx().into_nc()).await)
+                }
+            },
             api_endpoint! {
                 RECOVER_ENDPOINT,
                 ApiVersion::new(0, 0),
@@ -585,6 +1176,134 @@ impl Mint {
     ) -> Option<ECashUserBackupSnapshot> {
         dbtx.get_value(&EcashBackupKey(id)).await
     }
+
+    /// The key epoch the federation agreed on, the median of the votes of all
+    /// peers which can only increase
+    pub async fn consensus_key_epoch(&self, dbtx: &mut DatabaseTransaction<'_>) -> u64 {
+        let peer_count = self.cfg.consensus.peer_tbs_pks.total();
+
+        let mut votes = dbtx
+            .find_by_prefix(&KeyEpochVotePrefix)
+            .await
+            .map(|(.., epoch)| epoch)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(votes.len() <= peer_count);
+        votes.resize(peer_count, 0);
+        votes.sort_unstable();
+
+        votes[peer_count / 2]
+    }
+
+    /// Rejects locked notes unless the federation runs a consensus version
+    /// that supports them, just like guardians of
+    /// [`MINT_CONSENSUS_VERSION_V0`] which cannot decode them
+    fn ensure_supported_input(&self, input: &MintInput) -> Result<(), MintInputError> {
+        if matches!(input, MintInput::V1(_)) && self.consensus_version == MINT_CONSENSUS_VERSION_V0
+        {
+            return Err(UnknownMintInputVariantError { variant: 1 }.into());
+        }
+
+        Ok(())
+    }
+
+    /// Whether the federation runs a consensus version that supports key
+    /// epochs, otherwise it stays in the first epoch
+    fn supports_key_epochs(&self) -> bool {
+        self.consensus_version != MINT_CONSENSUS_VERSION_V0
+    }
+
+    /// The key epoch in which the note of `input` was issued, fails if it is
+    /// not a valid note of an epoch that is not retired yet. The signature is
+    /// only checked if `verified_epoch` is not known yet.
+    fn spendable_input_epoch(
+        &self,
+        current_epoch: u64,
+        input: &MintInput,
+        verified_epoch: Option<u64>,
+    ) -> Result<u64, MintInputError> {
+        let epoch = match verified_epoch {
+            Some(epoch) => epoch,
+            None => self.verify_input_epoch(current_epoch, input)?,
+        };
+
+        // the federation might have advanced its key epoch since verification
+        if self
+            .cfg
+            .consensus
+            .key_epochs
+            .is_retired(epoch, current_epoch)
+        {
+            return Err(MintInputError::ExpiredNote);
+        }
+
+        Ok(epoch)
+    }
+
+    /// Finds the key epoch in which the note of `input` was issued by checking
+    /// its signature against the keys of the epochs that are still live at
+    /// `current_epoch`, so an invalid note costs at most `grace_epochs + 1`
+    /// pairings
+    fn verify_input_epoch(
+        &self,
+        current_epoch: u64,
+        input: &MintInput,
+    ) -> Result<u64, MintInputError> {
+        let (amount, message, signature) = signed_message(input)?;
+
+        let current_pub_keys = self
+            .pub_keys
+            .get(&current_epoch)
+            .ok_or(MintInputError::UnknownKeyEpoch(current_epoch))?;
+
+        if !current_pub_keys.contains_key(&amount) {
+            return Err(MintInputError::InvalidAmountTier(amount));
+        }
+
+        self.cfg
+            .consensus
+            .key_epochs
+            .live_epochs(current_epoch)
+            .rev()
+            .filter_map(|epoch| Some((epoch, self.pub_keys.get(&epoch)?.get(&amount)?)))
+            .find(|(_, pub_key)| tbs::verify(message, signature, **pub_key))
+            .map(|(epoch, _)| epoch)
+            .ok_or(MintInputError::InvalidSignature)
+    }
+
+    /// Removes the nonces of the notes spent in the epochs that retired when
+    /// the federation advanced from `old_epoch` to `new_epoch`, their notes
+    /// are rejected as expired from now on
+    async fn prune_retired_epochs(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        old_epoch: u64,
+        new_epoch: u64,
+    ) {
+        let key_epochs = self.cfg.consensus.key_epochs;
+
+        for epoch in 0..new_epoch {
+            if !key_epochs.is_retired(epoch, new_epoch) || key_epochs.is_retired(epoch, old_epoch) {
+                continue;
+            }
+
+            warn!(
+                ?epoch,
+                "Retiring key epoch, its notes can no longer be spent"
+            );
+
+            if epoch == 0 {
+                dbtx.remove_by_prefix(&NonceKeyPrefix).await;
+            } else {
+                dbtx.remove_by_prefix(&EpochNonceKeyEpochPrefix(epoch))
+                    .await;
+            }
+
+            dbtx.remove_by_prefix(&LockedNonceKeyEpochPrefix(epoch))
+                .await;
+        }
+    }
 }
 
 fn calculate_mint_issued_ecash_metrics(
@@ -610,7 +1329,7 @@ fn calculate_mint_redeemed_ecash_metrics(
 }
 
 impl Mint {
-    /// Constructs a new mint
+    /// Constructs a new mint running the current [`MINT_CONSENSUS_VERSION`]
     ///
     /// # Panics
     /// * If there are no amount tiers
@@ -618,14 +1337,42 @@ impl Mint {
     /// * If the pub key belonging to the secret key share is not in the pub key
     ///   list.
     pub fn new(cfg: MintConfig) -> Mint {
+        Self::new_with_version(cfg, MINT_CONSENSUS_VERSION)
+    }
+
+    /// Constructs a new mint running `consensus_version`, see [`Mint::new`]
+    pub fn new_with_version(cfg: MintConfig, consensus_version: ModuleConsensusVersion) -> Mint {
         assert!(cfg.private.tbs_sks.tiers().count() > 0);
 
+        let sec_keys = cfg
+            .consensus
+            .keyed_epochs()
+            .map(|epoch| {
+                let sks = cfg
+                    .private
+                    .tbs_sks_of_epoch(epoch)
+                    .expect("Missing secret keys of a key epoch")
+                    .clone();
+                (epoch, sks)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let peer_pub_keys = cfg
+            .consensus
+            .keyed_epochs()
+            .map(|epoch| {
+                let pks = cfg
+                    .consensus
+                    .peer_tbs_pks_of_epoch(epoch)
+                    .expect("Missing public keys of a key epoch");
+                (epoch, pks)
+            })
+            .collect::<BTreeMap<_, _>>();
+
         // The amount tiers are implicitly provided by the key sets, make sure they are
         // internally consistent.
-        assert!(cfg
-            .consensus
-            .peer_tbs_pks
+        assert!(peer_pub_keys
             .values()
+            .flat_map(|peer_tbs_pks| peer_tbs_pks.values())
             .all(|pk| pk.structural_eq(&cfg.private.tbs_sks)));
 
         let ref_pub_key = cfg.private.tbs_sks.to_public();
@@ -639,57 +1386,54 @@ impl Mint {
             .find_map(|(&id, pk)| if *pk == ref_pub_key { Some(id) } else { None })
             .expect("Own key not found among pub keys.");
 
-        assert_eq!(
-            cfg.consensus.peer_tbs_pks[&our_id],
-            cfg.private
-                .tbs_sks
-                .iter()
-                .map(|(amount, sk)| (amount, sk.to_pub_key_share()))
-                .collect()
-        );
-
-        // TODO: the aggregate pks should become part of the MintConfigConsensus as they
-        // can be obtained by evaluating the polynomial returned by the DKG at
-        // zero
-        let aggregate_pub_keys = TieredMultiZip::new(
-            cfg.consensus
-                .peer_tbs_pks
-                .values()
-                .map(|keys| keys.iter())
-                .collect(),
-        )
-        .map(|(amt, keys)| {
-            let keys = (1_u64..)
-                .zip(keys.into_iter().cloned())
-                .take(cfg.consensus.peer_tbs_pks.threshold())
-                .collect();
+        for (peer_tbs_pks, tbs_sks) in peer_pub_keys.values().zip(sec_keys.values()) {
+            assert_eq!(
+                peer_tbs_pks[&our_id],
+                tbs_sks
+                    .iter()
+                    .map(|(amount, sk)| (amount, sk.to_pub_key_share()))
+                    .collect()
+            );
+        }
 
-            (amt, aggregate_public_key_shares(&keys))
-        })
-        .collect();
+        let pub_keys = peer_pub_keys
+            .into_iter()
+            .map(|(epoch, peer_tbs_pks)| (epoch, aggregate_pub_keys(peer_tbs_pks).collect()))
+            .collect();
 
         Mint {
-            cfg: cfg.clone(),
-            sec_key: cfg.private.tbs_sks,
-            pub_key: aggregate_pub_keys,
+            cfg,
+            our_peer_id: our_id,
+            sec_keys,
+            pub_keys,
+            consensus_version,
         }
     }
 
+    /// The aggregate public keys of the first key epoch
     pub fn pub_key(&self) -> HashMap<Amount, AggregatePublicKey> {
-        self.pub_key.clone()
+        self.pub_keys[&0].clone()
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::collections::BTreeMap;
+    use std::time::{Duration, UNIX_EPOCH};
+
     use assert_matches::assert_matches;
+    use bitcoin_hashes::Hash as _;
     use fedimint_core::config::{ClientModuleConfig, ConfigGenModuleParams, ServerModuleConfig};
     use fedimint_core::db::mem_impl::MemDatabase;
-    use fedimint_core::db::Database;
+    use fedimint_core::db::{Database, DatabaseTransaction, IDatabaseTransactionOpsCoreTyped};
     use fedimint_core::module::{ModuleConsensusVersion, ServerModuleInit};
-    use fedimint_core::{Amount, PeerId, ServerModule};
-    use fedimint_mint_common::config::FeeConsensus;
-    use fedimint_mint_common::{MintInput, Nonce, Note};
+    use fedimint_core::{Amount, OutPoint, PeerId, ServerModule, TransactionId};
+    use fedimint_mint_common::config::{FeeConsensus, KeyEpochParams};
+    use fedimint_mint_common::db::{EpochNonceKey, MintOutputOutcomeKey, NonceKey};
+    use fedimint_mint_common::{
+        BlindNonce, MintConsensusItem, MintInput, MintInputError, MintOutput, MintOutputError,
+        MintOutputOutcome, Nonce, Note, MINT_CONSENSUS_VERSION_V0,
+    };
     use tbs::blind_message;
 
     use crate::common::config::MintGenParamsConsensus;
@@ -701,12 +1445,19 @@ mod test {
     const MINTS: usize = 5;
 
     fn build_configs() -> (Vec<ServerModuleConfig>, ClientModuleConfig) {
+        build_configs_with_key_epochs(KeyEpochParams::default())
+    }
+
+    fn build_configs_with_key_epochs(
+        key_epochs: KeyEpochParams,
+    ) -> (Vec<ServerModuleConfig>, ClientModuleConfig) {
         let peers = (0..MINTS as u16).map(PeerId::from).collect::<Vec<_>>();
         let mint_cfg = MintInit.trusted_dealer_gen(
             &peers,
             &ConfigGenModuleParams::from_typed(MintGenParams {
                 local: Default::default(),
-                consensus: MintGenParamsConsensus::new(2, FeeConsensus::default()),
+                consensus: MintGenParamsConsensus::new(2, FeeConsensus::default())
+                    .with_key_epochs(key_epochs),
             })
             .unwrap(),
         );
@@ -739,6 +1490,8 @@ mod test {
                     .peer_tbs_pks,
                 fee_consensus: FeeConsensus::default(),
                 max_notes_per_denomination: 0,
+                key_epochs: KeyEpochParams::default(),
+                epoch_peer_tbs_pks: BTreeMap::new(),
             },
             private: MintConfigPrivate {
                 tbs_sks: mint_server_cfg1[0]
@@ -746,6 +1499,7 @@ mod test {
                     .unwrap()
                     .private
                     .tbs_sks,
+                epoch_tbs_sks: BTreeMap::new(),
             },
         });
     }
@@ -753,10 +1507,45 @@ mod test {
     fn issue_note(
         server_cfgs: &[ServerModuleConfig],
         denomination: Amount,
+    ) -> (secp256k1::KeyPair, Note) {
+        issue_note_in_epoch(server_cfgs, 0, denomination)
+    }
+
+    fn issue_note_in_epoch(
+        server_cfgs: &[ServerModuleConfig],
+        epoch: u64,
+        denomination: Amount,
     ) -> (secp256k1::KeyPair, Note) {
         let note_key = secp256k1::KeyPair::new(secp256k1::SECP256K1, &mut rand::thread_rng());
         let nonce = Nonce(note_key.public_key());
-        let message = nonce.to_message();
+        let signature = sign_message(server_cfgs, epoch, denomination, nonce.to_message());
+
+        (note_key, Note { nonce, signature })
+    }
+
+    fn issue_locked_note(
+        server_cfgs: &[ServerModuleConfig],
+        denomination: Amount,
+        recipient: &secp256k1::PublicKey,
+    ) -> Note {
+        let note_key = secp256k1::KeyPair::new(secp256k1::SECP256K1, &mut rand::thread_rng());
+        let nonce = Nonce(note_key.public_key());
+        let signature = sign_message(
+            server_cfgs,
+            0,
+            denomination,
+            nonce.to_locked_message(recipient),
+        );
+
+        Note { nonce, signature }
+    }
+
+    fn sign_message(
+        server_cfgs: &[ServerModuleConfig],
+        epoch: u64,
+        denomination: Amount,
+        message: tbs::Message,
+    ) -> tbs::Signature {
         let blinding_key = tbs::BlindingKey::random();
         let blind_msg = blind_message(message, blinding_key);
 
@@ -766,7 +1555,8 @@ mod test {
                     .to_typed::<MintConfig>()
                     .unwrap()
                     .private
-                    .tbs_sks
+                    .tbs_sks_of_epoch(epoch)
+                    .expect("Mint has no keys for this epoch")
                     .get(denomination)
                     .expect("Mint cannot issue a note of this denomination");
                 tbs::sign_blinded_msg(blind_msg, sks)
@@ -775,9 +1565,7 @@ mod test {
             .collect();
 
         let blind_signature = tbs::aggregate_signature_shares(&bsig_shares);
-        let signature = tbs::unblind_signature(blinding_key, blind_signature);
-
-        (note_key, Note { nonce, signature })
+        tbs::unblind_signature(blinding_key, blind_signature)
     }
 
     #[test_log::test(tokio::test)]
@@ -805,4 +1593,356 @@ mod test {
             Err(_)
         );
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_locked_notes_require_recipient_signature() {
+        let (mint_server_cfg, _) = build_configs();
+        let amount = Amount::from_msats(1024);
+
+        let mint = Mint::new(mint_server_cfg[0].to_typed().unwrap());
+        let recipient = secp256k1::KeyPair::new(secp256k1::SECP256K1, &mut rand::thread_rng());
+        let note = issue_locked_note(&mint_server_cfg, amount, &recipient.public_key());
+
+        let db = Database::new(MemDatabase::new(), Default::default());
+        let mut dbtx = db.begin_transaction().await;
+
+        // The payer cannot spend the locked note as a regular one
+        assert_matches!(
+            mint.process_input(
+                &mut dbtx.to_ref_with_prefix_module_id(42).into_nc(),
+                &MintInput::new_v0(amount, note)
+            )
+            .await,
+            Err(MintInputError::InvalidSignature)
+        );
+
+        // Nor lock it to another key
+        let other_key = secp256k1::KeyPair::new(secp256k1::SECP256K1, &mut rand::thread_rng());
+        assert_matches!(
+            mint.process_input(
+                &mut dbtx.to_ref_with_prefix_module_id(42).into_nc(),
+                &MintInput::new_v1(amount, note, other_key.public_key())
+            )
+            .await,
+            Err(MintInputError::InvalidSignature)
+        );
+
+        let input = MintInput::new_v1(amount, note, recipient.public_key());
+        let meta = mint
+            .process_input(&mut dbtx.to_ref_with_prefix_module_id(42).into_nc(), &input)
+            .await
+            .expect("Spend of valid locked e-cash works");
+        assert_eq!(meta.pub_key, recipient.public_key());
+
+        assert_matches!(
+            mint.process_input(&mut dbtx.to_ref_with_prefix_module_id(42).into_nc(), &input)
+                .await,
+            Err(MintInputError::SpentCoin)
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_locked_notes_are_rejected_before_consensus_version_bump() {
+        let (mint_server_cfg, _) = build_configs();
+        let amount = Amount::from_msats(1024);
+
+        let mint = Mint::new_with_version(
+            mint_server_cfg[0].to_typed().unwrap(),
+            MINT_CONSENSUS_VERSION_V0,
+        );
+        let recipient = secp256k1::KeyPair::new(secp256k1::SECP256K1, &mut rand::thread_rng());
+        let input = MintInput::new_v1(
+            amount,
+            issue_locked_note(&mint_server_cfg, amount, &recipient.public_key()),
+            recipient.public_key(),
+        );
+
+        let db = Database::new(MemDatabase::new(), Default::default());
+        let mut dbtx = db.begin_transaction().await;
+        let mut dbtx = dbtx.to_ref_with_prefix_module_id(42).into_nc();
+
+        assert_matches!(
+            mint.process_input(&mut dbtx, &input).await,
+            Err(MintInputError::UnknownInputVariant(_))
+        );
+        assert_matches!(
+            mint.verify_inputs_batch(&mut dbtx, &[&input, &input]).await,
+            Err(MintInputError::UnknownInputVariant(_))
+        );
+
+        // Regular notes are still accepted
+        let (_, note) = issue_note(&mint_server_cfg, amount);
+        mint.process_input(&mut dbtx, &MintInput::new_v0(amount, note))
+            .await
+            .expect("Spend of valid e-cash works");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_key_epoch_votes_are_rejected_before_consensus_version_bump() {
+        let (mint_server_cfg, _) = build_configs();
+        let mint = Mint::new_with_version(
+            mint_server_cfg[0].to_typed().unwrap(),
+            MINT_CONSENSUS_VERSION_V0,
+        );
+
+        let db = Database::new(MemDatabase::new(), Default::default());
+        let mut dbtx = db.begin_transaction().await;
+        let mut dbtx = dbtx.to_ref_with_prefix_module_id(42).into_nc();
+
+        assert!(mint.consensus_proposal(&mut dbtx).await.is_empty());
+        assert!(mint
+            .process_consensus_item(
+                &mut dbtx,
+                MintConsensusItem::KeyEpochVote(1),
+                PeerId::from(0)
+            )
+            .await
+            .is_err());
+        assert_eq!(mint.consensus_key_epoch(&mut dbtx).await, 0);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_missing_keys_of_current_epoch_are_an_error() {
+        let (mint_server_cfg, _) = build_configs_with_key_epochs(KeyEpochParams {
+            num_epochs: 2,
+            start_time_secs: 0,
+            epoch_duration_secs: 60,
+            grace_epochs: 1,
+        });
+        let amount = Amount::from_msats(1024);
+
+        let mut mint = Mint::new(mint_server_cfg[0].to_typed().unwrap());
+        let (_, note) = issue_note(&mint_server_cfg, amount);
+
+        let db = Database::new(MemDatabase::new(), Default::default());
+        let mut dbtx = db.begin_transaction().await;
+        let mut dbtx = dbtx.to_ref_with_prefix_module_id(42).into_nc();
+
+        vote_key_epoch(&mint, &mut dbtx, 1, 0..MINTS as u16).await;
+
+        // a guardian that dropped the keys of the current epoch must not panic
+        mint.pub_keys.remove(&1);
+        mint.sec_keys.remove(&1);
+
+        let input = MintInput::new_v0(amount, note);
+        assert_matches!(
+            mint.process_input(&mut dbtx, &input).await,
+            Err(MintInputError::UnknownKeyEpoch(1))
+        );
+        assert_matches!(
+            mint.verify_inputs_batch(&mut dbtx, &[&input, &input]).await,
+            Err(MintInputError::UnknownKeyEpoch(1))
+        );
+
+        let blind_nonce = BlindNonce(blind_message(
+            Nonce(
+                secp256k1::KeyPair::new(secp256k1::SECP256K1, &mut rand::thread_rng()).public_key(),
+            )
+            .to_message(),
+            tbs::BlindingKey::random(),
+        ));
+        assert_matches!(
+            mint.process_output(
+                &mut dbtx,
+                &MintOutput::new_v0(amount, blind_nonce),
+                OutPoint {
+                    txid: TransactionId::all_zeros(),
+                    out_idx: 0,
+                },
+            )
+            .await,
+            Err(MintOutputError::UnknownKeyEpoch(1))
+        );
+    }
+
+    async fn vote_key_epoch(
+        mint: &Mint,
+        dbtx: &mut DatabaseTransaction<'_>,
+        epoch: u64,
+        peers: std::ops::Range<u16>,
+    ) {
+        for peer in peers {
+            mint.process_consensus_item(
+                dbtx,
+                MintConsensusItem::KeyEpochVote(epoch),
+                PeerId::from(peer),
+            )
+            .await
+            .expect("Key epoch vote is valid");
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_key_epochs_retire_notes_and_prune_nonces() {
+        let (mint_server_cfg, _) = build_configs_with_key_epochs(KeyEpochParams {
+            num_epochs: 3,
+            start_time_secs: 0,
+            epoch_duration_secs: 60,
+            grace_epochs: 1,
+        });
+        let amount = Amount::from_msats(1024);
+
+        let mint = Mint::new(mint_server_cfg[0].to_typed().unwrap());
+        let (_, spent_note) = issue_note_in_epoch(&mint_server_cfg, 0, amount);
+        let (_, expiring_note) = issue_note_in_epoch(&mint_server_cfg, 0, amount);
+        let (_, next_epoch_note) = issue_note_in_epoch(&mint_server_cfg, 1, amount);
+
+        let db = Database::new(MemDatabase::new(), Default::default());
+        let mut dbtx = db.begin_transaction().await;
+        let mut dbtx = dbtx.to_ref_with_prefix_module_id(42).into_nc();
+
+        // Keys of future epochs are not used yet
+        assert_matches!(
+            mint.process_input(&mut dbtx, &MintInput::new_v0(amount, next_epoch_note))
+                .await,
+            Err(MintInputError::InvalidSignature)
+        );
+
+        mint.process_input(&mut dbtx, &MintInput::new_v0(amount, spent_note))
+            .await
+            .expect("Spend of valid e-cash works");
+        assert!(dbtx.get_value(&NonceKey(spent_note.nonce)).await.is_some());
+
+        // A minority of peers cannot advance the epoch
+        vote_key_epoch(&mint, &mut dbtx, 1, 0..2).await;
+        assert_eq!(mint.consensus_key_epoch(&mut dbtx).await, 0);
+
+        vote_key_epoch(&mint, &mut dbtx, 1, 2..MINTS as u16).await;
+        assert_eq!(mint.consensus_key_epoch(&mut dbtx).await, 1);
+
+        // New notes are signed with the key of the new epoch
+        let out_point = OutPoint {
+            txid: TransactionId::all_zeros(),
+            out_idx: 0,
+        };
+        let blind_nonce = BlindNonce(blind_message(
+            Nonce(
+                secp256k1::KeyPair::new(secp256k1::SECP256K1, &mut rand::thread_rng()).public_key(),
+            )
+            .to_message(),
+            tbs::BlindingKey::random(),
+        ));
+        mint.process_output(
+            &mut dbtx,
+            &MintOutput::new_v0(amount, blind_nonce),
+            out_point,
+        )
+        .await
+        .expect("Issuance of e-cash works");
+        assert_matches!(
+            dbtx.get_value(&MintOutputOutcomeKey(out_point)).await,
+            Some(MintOutputOutcome::V1(outcome)) if outcome.epoch == 1
+        );
+
+        mint.process_input(&mut dbtx, &MintInput::new_v0(amount, next_epoch_note))
+            .await
+            .expect("Spend of valid e-cash works");
+        assert!(dbtx
+            .get_value(&EpochNonceKey {
+                epoch: 1,
+                nonce: next_epoch_note.nonce
+            })
+            .await
+            .is_some());
+
+        // Notes of the previous epoch can still be spent during the grace epoch
+        assert_matches!(
+            mint.process_input(&mut dbtx, &MintInput::new_v0(amount, spent_note))
+                .await,
+            Err(MintInputError::SpentCoin)
+        );
+
+        vote_key_epoch(&mint, &mut dbtx, 2, 0..MINTS as u16).await;
+        assert_eq!(mint.consensus_key_epoch(&mut dbtx).await, 2);
+
+        // Once the first epoch retired its notes are rejected and its nonces pruned,
+        // their signatures are not even checked against the keys of the epoch
+        assert_matches!(
+            mint.process_input(&mut dbtx, &MintInput::new_v0(amount, expiring_note))
+                .await,
+            Err(MintInputError::InvalidSignature)
+        );
+        assert!(dbtx.get_value(&NonceKey(spent_note.nonce)).await.is_none());
+        assert!(dbtx
+            .get_value(&EpochNonceKey {
+                epoch: 1,
+                nonce: next_epoch_note.nonce
+            })
+            .await
+            .is_some());
+    }
+
+    #[test_log::test]
+    fn test_key_epoch_schedule_extends_in_last_epoch() {
+        let key_epochs = KeyEpochParams {
+            num_epochs: 3,
+            start_time_secs: 0,
+            epoch_duration_secs: 60,
+            grace_epochs: 1,
+        };
+        let extension = KeyEpochParams {
+            num_epochs: 5,
+            ..key_epochs
+        };
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+
+        // Without new epochs the schedule stays the same
+        assert_eq!(key_epochs.extend(&key_epochs, at(0)).unwrap(), key_epochs);
+
+        // Extending before the last epoch would rush the current epochs
+        assert!(key_epochs.extend(&extension, at(60)).is_err());
+        assert_eq!(key_epochs.extend(&extension, at(120)).unwrap(), extension);
+
+        // Once the schedule ran out the added epochs have to start later
+        assert!(key_epochs.extend(&extension, at(600)).is_err());
+        let delayed = KeyEpochParams {
+            start_time_secs: 480,
+            ..extension
+        };
+        assert_eq!(key_epochs.extend(&delayed, at(600)).unwrap(), delayed);
+
+        // An invalid note is only checked against the keys of the live epochs
+        assert_eq!(extension.live_epochs(3), 2..=3);
+        assert_eq!(extension.live_epochs(0), 0..=0);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_verify_inputs_batch_finds_invalid_note() {
+        let (mint_server_cfg, _) = build_configs();
+        let amount = Amount::from_msats(1024);
+
+        let mint = Mint::new(mint_server_cfg[0].to_typed().unwrap());
+        let inputs = (0..10)
+            .map(|_| MintInput::new_v0(amount, issue_note(&mint_server_cfg, amount).1))
+            .collect::<Vec<_>>();
+
+        let db = Database::new(MemDatabase::new(), Default::default());
+        let mut dbtx = db.begin_transaction().await;
+        let mut dbtx = dbtx.to_ref_with_prefix_module_id(42).into_nc();
+
+        let verified_epochs = mint
+            .verify_inputs_batch(&mut dbtx, &inputs.iter().collect::<Vec<_>>())
+            .await
+            .expect("Batch of valid e-cash verifies");
+        assert_eq!(verified_epochs, vec![Some(0); inputs.len()]);
+
+        for (input, verified_epoch) in inputs.iter().zip(verified_epochs) {
+            mint.process_verified_input(&mut dbtx, input, verified_epoch)
+                .await
+                .expect("Spend of valid e-cash works");
+        }
+
+        // A note signed for another amount invalidates the batch
+        let mut invalid_inputs = (0..10)
+            .map(|_| MintInput::new_v0(amount, issue_note(&mint_server_cfg, amount).1))
+            .collect::<Vec<_>>();
+        let (_, other_amount_note) = issue_note(&mint_server_cfg, Amount::from_msats(2048));
+        invalid_inputs[7] = MintInput::new_v0(amount, other_amount_note);
+
+        assert_matches!(
+            mint.verify_inputs_batch(&mut dbtx, &invalid_inputs.iter().collect::<Vec<_>>())
+                .await,
+            Err(MintInputError::InvalidSignature)
+        );
+    }
 }
@@ -2,15 +2,17 @@ use std::io::Cursor;
 use std::time::Duration;
 
 use fedimint_client::backup::{ClientBackup, Metadata};
+use fedimint_client::ClientArc;
 use fedimint_core::config::EmptyGenParams;
 use fedimint_core::task::sleep_in_test;
 use fedimint_core::util::NextOrPending;
-use fedimint_core::{sats, Amount};
+use fedimint_core::{sats, Amount, TieredMulti};
 use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
 use fedimint_dummy_common::config::DummyGenParams;
 use fedimint_dummy_server::DummyInit;
 use fedimint_mint_client::{
-    MintClientInit, MintClientModule, OOBNotes, ReissueExternalNotesState, SpendOOBState,
+    LockedPaymentRequest, MintClientInit, MintClientModule, NoteConsolidationPolicy, NotesSelector,
+    OOBNotes, ReissueExternalNotesState, SpendOOBState,
 };
 use fedimint_mint_common::config::{FeeConsensus, MintGenParams, MintGenParamsConsensus};
 use fedimint_mint_server::MintInit;
@@ -30,6 +32,7 @@ fn fixtures() -> Fixtures {
                 FeeConsensus {
                     note_issuance_abs: Amount::ZERO,
                     note_spend_abs: Amount::from_sats(1),
+                    ..FeeConsensus::default()
                 },
             ),
             local: EmptyGenParams {},
@@ -77,6 +80,191 @@ async fn sends_ecash_out_of_band() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn pays_locked_notes_to_offline_recipient() -> anyhow::Result<()> {
+    let fed = fixtures().new_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let client1_dummy_module = client1.get_first_module::<DummyClientModule>();
+    let (op, outpoint) = client1_dummy_module.print_money(sats(1000)).await?;
+    client1.await_primary_module_output(op, outpoint).await?;
+
+    let client1_mint = client1.get_first_module::<MintClientModule>();
+    let client2_mint = client2.get_first_module::<MintClientModule>();
+
+    let request = client2_mint
+        .create_locked_payment_request(sats(500))
+        .await?;
+    let request = request.to_string().parse::<LockedPaymentRequest>()?;
+
+    let op = client1_mint.spend_locked_notes(request, ()).await?;
+    let locked_notes = client1_mint.await_locked_notes(op).await?;
+    assert_eq!(locked_notes.total_amount(), sats(500));
+    assert!(client1.get_balance().await <= sats(500));
+
+    // The recipient verifies the notes without talking to the federation and
+    // rejects them if they are presented twice
+    assert_eq!(
+        client2_mint
+            .receive_locked_notes(locked_notes.clone())
+            .await?,
+        sats(500)
+    );
+    assert!(client2_mint
+        .receive_locked_notes(locked_notes.clone())
+        .await
+        .is_err());
+    assert!(
+        client1_mint
+            .receive_locked_notes(locked_notes)
+            .await
+            .is_err(),
+        "Notes are not locked to the payer"
+    );
+
+    let op = client2_mint
+        .redeem_locked_notes(())
+        .await?
+        .expect("There are notes to redeem");
+    let sub = client2_mint.subscribe_reissue_external_notes(op).await?;
+    let mut sub = sub.into_stream();
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Created);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Issuing);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Done);
+
+    assert!(client2.get_balance().await >= sats(500) - EXPECTED_MAXIMUM_FEE);
+    assert_eq!(client2_mint.redeem_locked_notes(()).await?, None);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn previews_reissue_without_spending_notes() -> anyhow::Result<()> {
+    let fed = fixtures().new_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let client1_dummy_module = client1.get_first_module::<DummyClientModule>();
+    let (op, outpoint) = client1_dummy_module.print_money(sats(1000)).await?;
+    client1.await_primary_module_output(op, outpoint).await?;
+
+    let client1_mint = client1.get_first_module::<MintClientModule>();
+    let client2_mint = client2.get_first_module::<MintClientModule>();
+    let (_, notes) = client1_mint
+        .spend_notes(sats(750), TIMEOUT, false, ())
+        .await?;
+
+    let preview = client2_mint
+        .preview_reissue_external_notes(notes.clone())
+        .await?;
+    let num_notes = notes.notes().count_items() as u64;
+    assert_eq!(preview.input_amount, notes.total_amount());
+    assert_eq!(preview.fee, sats(num_notes));
+    assert_eq!(
+        preview.input_amount,
+        preview.output_amount + preview.fee,
+        "Change absorbs everything but the fees"
+    );
+    assert_eq!(client2.get_balance().await, sats(0));
+
+    // The preview did not reserve anything, so the notes can still be reissued
+    let op = client2_mint.reissue_external_notes(notes, ()).await?;
+    let mut sub = client2_mint
+        .subscribe_reissue_external_notes(op)
+        .await?
+        .into_stream();
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Created);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Issuing);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Done);
+    assert_eq!(client2.get_balance().await, preview.output_amount);
+
+    Ok(())
+}
+
+/// Number of denominations below `amount` the client holds notes of
+async fn denominations_below(client: &ClientArc, amount: Amount) -> usize {
+    let client_mint = client.get_first_module::<MintClientModule>();
+
+    client_mint
+        .get_wallet_summary(
+            &mut client
+                .db()
+                .begin_transaction_nc()
+                .await
+                .to_ref_with_prefix_module_id(client_mint.id),
+        )
+        .await
+        .iter()
+        .filter(|(note_amount, count)| *note_amount < amount && *count != 0)
+        .count()
+}
+
+/// Selects all notes of at most the given amount regardless of the requested
+/// amount
+struct SelectNotesUpTo(Amount);
+
+#[async_trait::async_trait]
+impl<Note: Send> NotesSelector<Note> for SelectNotesUpTo {
+    async fn select_notes(
+        &self,
+        stream: impl futures::Stream<Item = (Amount, Note)> + Send,
+        _requested_amount: Amount,
+        _fee_consensus: &FeeConsensus,
+    ) -> anyhow::Result<TieredMulti<Note>> {
+        let notes = stream
+            .filter(|(amount, _)| futures::future::ready(*amount <= self.0))
+            .collect::<Vec<_>>()
+            .await;
+
+        Ok(notes.into_iter().collect())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn consolidation_reissues_notes_into_missing_denominations() -> anyhow::Result<()> {
+    let fed = fixtures().new_fed().await;
+    let client = fed.new_client().await;
+    let client_dummy_module = client.get_first_module::<DummyClientModule>();
+    let (op, outpoint) = client_dummy_module.print_money(sats(1000)).await?;
+    client.await_primary_module_output(op, outpoint).await?;
+
+    let client_mint = client.get_first_module::<MintClientModule>();
+
+    // Give away all notes below one sat so the wallet lacks these denominations,
+    // without them being refunded while the test runs
+    client_mint
+        .spend_notes_with_selector(
+            &SelectNotesUpTo(Amount::from_msats(999)),
+            Amount::from_msats(1),
+            Duration::from_secs(60 * 60),
+            false,
+            (),
+        )
+        .await?;
+    assert_eq!(denominations_below(&client, sats(1)).await, 0);
+
+    let policy = NoteConsolidationPolicy {
+        max_fee_ppm: 100_000,
+        min_interval: Duration::ZERO,
+        ..NoteConsolidationPolicy::default()
+    };
+    let op = client_mint
+        .consolidate_notes(&policy)
+        .await?
+        .expect("The wallet lacks denominations");
+    let mut sub = client_mint
+        .subscribe_reissue_external_notes(op)
+        .await?
+        .into_stream();
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Created);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Issuing);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Done);
+
+    // All ten denominations below one sat are held again
+    assert_eq!(denominations_below(&client, sats(1)).await, 10);
+
+    // The wallet is balanced now, so there is nothing left to consolidate
+    assert_eq!(client_mint.consolidate_notes(&policy).await?, None);
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn sends_ecash_oob_highly_parallel() -> anyhow::Result<()> {
     // Print notes for client1
@@ -536,6 +724,15 @@ mod fedimint_migration_tests {
                         );
                         info!("Validated EcashBackup");
                     }
+                    DbKeyPrefix::LockedNoteNonce => {
+                        // Introduced after the v0 snapshot, nothing to migrate
+                    }
+                    DbKeyPrefix::EpochNoteNonce => {
+                        // Introduced after the v0 snapshot, nothing to migrate
+                    }
+                    DbKeyPrefix::KeyEpochVote => {
+                        // Introduced after the v0 snapshot, nothing to migrate
+                    }
                 }
             }
 
@@ -622,6 +819,14 @@ mod fedimint_migration_tests {
                             );
                             info!("Validated RecoveryFinalized");
                         }
+                        fedimint_mint_client::client_db::DbKeyPrefix::LastConsolidation
+                        | fedimint_mint_client::client_db::DbKeyPrefix::NextLockedPaymentIndex
+                        | fedimint_mint_client::client_db::DbKeyPrefix::LockedPaymentKey
+                        | fedimint_mint_client::client_db::DbKeyPrefix::ReceivedLockedNote
+                        | fedimint_mint_client::client_db::DbKeyPrefix::NoteSelectionStrategy => {
+                            // Introduced after the v0 snapshot, nothing to
+                            // migrate
+                        }
                     }
                 }
 
@@ -11,7 +11,8 @@ use fedimint_core::db::{DatabaseTransaction, DatabaseVersion, ServerMigrationFn}
 use fedimint_core::module::audit::Audit;
 use fedimint_core::module::{
     ApiEndpoint, CoreConsensusVersion, InputMeta, ModuleConsensusVersion, ModuleInit, PeerHandle,
-    ServerModuleInit, ServerModuleInitArgs, SupportedModuleApiVersions, TransactionItemAmount,
+    PreviousModuleConfig, ServerModuleInit, ServerModuleInitArgs, SupportedModuleApiVersions,
+    TransactionItemAmount,
 };
 use fedimint_core::server::DynServerModule;
 use fedimint_core::{OutPoint, PeerId, ServerModule};
@@ -102,6 +103,15 @@ impl ServerModuleInit for UnknownInit {
         .to_erased())
     }
 
+    async fn distributed_reshare(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+        _previous: &PreviousModuleConfig,
+    ) -> DkgResult<ServerModuleConfig> {
+        self.distributed_gen(peers, params).await
+    }
+
     /// Converts the consensus config into the client config
     fn get_client_config(
         &self,
@@ -138,6 +148,7 @@ impl ServerModule for Unknown {
     /// Define the consensus types
     type Common = UnknownModuleTypes;
     type Init = UnknownInit;
+    type VerifiedInput = ();
 
     async fn consensus_proposal(
         &self,
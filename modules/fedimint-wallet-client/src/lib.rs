@@ -20,7 +20,7 @@ use fedimint_client::module::{ClientContext, ClientModule, IClientModule};
 use fedimint_client::oplog::UpdateStreamOrOutcome;
 use fedimint_client::sm::util::MapStateTransitions;
 use fedimint_client::sm::{Context, DynState, ModuleNotifier, State, StateTransition};
-use fedimint_client::transaction::{ClientOutput, TransactionBuilder};
+use fedimint_client::transaction::{ClientOutput, TransactionBuilder, TransactionPreview};
 use fedimint_client::{sm_enum_variant_translation, DynGlobalClientContext};
 use fedimint_core::api::DynModuleApi;
 use fedimint_core::bitcoin_migration::{
@@ -32,6 +32,7 @@ use fedimint_core::db::{
     AutocommitError, DatabaseTransaction, DatabaseVersion, IDatabaseTransactionOpsCoreTyped,
 };
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::liabilities::LiabilitiesStatement;
 use fedimint_core::module::{
     ApiVersion, CommonModuleInit, ModuleCommon, ModuleInit, MultiApiVersion, TransactionItemAmount,
 };
@@ -206,6 +207,17 @@ pub enum WalletOperationMetaVariant {
     },
 }
 
+/// Result of checking the reserves of a liabilities statement on-chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiedReserves {
+    /// Total value of the reserves that are still unspent
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub unspent: bitcoin::Amount,
+    /// Reserves that were spent since, e.g. by peg-outs after the statement
+    /// was produced
+    pub spent: Vec<bitcoin::OutPoint>,
+}
+
 #[derive(Debug)]
 pub struct WalletClientModule {
     cfg: WalletClientConfig,
@@ -239,9 +251,11 @@ impl ClientModule for WalletClientModule {
     ) -> Option<TransactionItemAmount> {
         let input = input.maybe_v0_ref()?;
 
+        let amount = Amount::from_sats(input.0.tx_output().value);
+
         Some(TransactionItemAmount {
-            amount: Amount::from_sats(input.0.tx_output().value),
-            fee: self.cfg.fee_consensus.peg_in_abs,
+            amount,
+            fee: self.cfg.fee_consensus.peg_in_fee(amount),
         })
     }
 
@@ -251,9 +265,11 @@ impl ClientModule for WalletClientModule {
     ) -> Option<TransactionItemAmount> {
         let output = output.maybe_v0_ref()?;
 
+        let amount = output.amount().into();
+
         Some(TransactionItemAmount {
-            amount: output.amount().into(),
-            fee: self.cfg.fee_consensus.peg_out_abs,
+            amount,
+            fee: self.cfg.fee_consensus.peg_out_fee(amount),
         })
     }
 }
@@ -320,6 +336,61 @@ impl WalletClientModule {
         (operation_id, deposit_sm, address)
     }
 
+    /// Checks the reserves listed in a liabilities statement against the
+    /// blockchain. Every reserve has to be created by a confirmed transaction
+    /// with an output holding the stated amount at the stated script.
+    pub async fn verify_reserves(
+        &self,
+        statement: &LiabilitiesStatement,
+    ) -> anyhow::Result<VerifiedReserves> {
+        let mut verified = VerifiedReserves {
+            unspent: bitcoin::Amount::ZERO,
+            spent: vec![],
+        };
+
+        for reserve in statement.reserves() {
+            let outpoint = reserve.outpoint;
+
+            self.rpc
+                .watch_script_history(&reserve.script_pubkey)
+                .await?;
+            let history = self.rpc.get_script_history(&reserve.script_pubkey).await?;
+
+            let output = history
+                .iter()
+                .find(|tx| tx.txid() == outpoint.txid)
+                .and_then(|tx| tx.output.get(outpoint.vout as usize))
+                .with_context(|| format!("Reserve {outpoint} was not found on-chain"))?;
+
+            ensure!(
+                output.script_pubkey == reserve.script_pubkey
+                    && output.value == reserve.amount.to_sat(),
+                "Reserve {outpoint} does not match the on-chain output"
+            );
+            ensure!(
+                self.rpc
+                    .get_tx_block_height(&outpoint.txid)
+                    .await?
+                    .is_some(),
+                "Reserve {outpoint} is not confirmed"
+            );
+
+            let is_spent = history.iter().any(|tx| {
+                tx.input
+                    .iter()
+                    .any(|input| input.previous_output == outpoint)
+            });
+
+            if is_spent {
+                verified.spent.push(outpoint);
+            } else {
+                verified.unspent += reserve.amount;
+            }
+        }
+
+        Ok(verified)
+    }
+
     /// Fetches the fees that would need to be paid to make the withdraw request
     /// using [`Self::withdraw`] work *right now*.
     ///
@@ -538,11 +609,9 @@ impl WalletClientModule {
         {
             let operation_id = OperationId(thread_rng().gen());
 
-            let withdraw_output = self
-                .create_withdraw_output(operation_id, address.clone(), amount, fee)
+            let tx_builder = self
+                .withdraw_tx_builder(operation_id, address.clone(), amount, fee)
                 .await?;
-            let tx_builder = TransactionBuilder::new()
-                .with_output(self.client_ctx.make_client_output(withdraw_output));
 
             let extra_meta =
                 serde_json::to_value(extra_meta).expect("Failed to serialize extra meta");
@@ -567,6 +636,36 @@ impl WalletClientModule {
         }
     }
 
+    /// Returns the amounts and fees of the transaction [`Self::withdraw`]
+    /// would submit without reserving any funds.
+    pub async fn preview_withdraw(
+        &self,
+        address: bitcoin::Address,
+        amount: bitcoin::Amount,
+        fee: PegOutFees,
+    ) -> anyhow::Result<TransactionPreview> {
+        let tx_builder = self
+            .withdraw_tx_builder(OperationId::new_random(), address, amount, fee)
+            .await?;
+
+        self.client_ctx.preview_transaction(tx_builder).await
+    }
+
+    async fn withdraw_tx_builder(
+        &self,
+        operation_id: OperationId,
+        address: bitcoin::Address,
+        amount: bitcoin::Amount,
+        fee: PegOutFees,
+    ) -> anyhow::Result<TransactionBuilder> {
+        let withdraw_output = self
+            .create_withdraw_output(operation_id, address, amount, fee)
+            .await?;
+
+        Ok(TransactionBuilder::new()
+            .with_output(self.client_ctx.make_client_output(withdraw_output)))
+    }
+
     /// Attempt to increase the fee of a onchain withdraw transaction using
     /// replace by fee (RBF).
     /// This can prevent transactions from getting stuck
@@ -2,9 +2,11 @@ use std::collections::BTreeMap;
 
 use bitcoin::Network;
 use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
+use fedimint_core::config::{decode_config_extension, encode_config_extension};
 use fedimint_core::core::ModuleKind;
-use fedimint_core::encoding::{Decodable, Encodable};
-use fedimint_core::module::__reexports::serde_json;
+use fedimint_core::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_core::module::fee::ProportionalFee;
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::util::SafeUrl;
 use fedimint_core::{plugin_types_trait_impl_config, Feerate, PeerId};
 use miniscript::descriptor::{Wpkh, Wsh};
@@ -70,9 +72,13 @@ pub struct WalletConfigLocal {
 pub struct WalletConfigPrivate {
     /// Secret key for signing bitcoin multisig transactions
     pub peg_in_key: SecretKey,
+    /// Our secret key of the multisig the federation was reshared from, only
+    /// set for guardians that were part of the previous federation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_peg_in_key: Option<SecretKey>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Encodable, Decodable)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WalletConfigConsensus {
     /// Bitcoin network (e.g. testnet, bitcoin)
     pub network: Network,
@@ -96,9 +102,90 @@ pub struct WalletConfigConsensus {
     /// **This is only used by the client, the RPC used by the server is defined
     /// in [`WalletConfigLocal`].**
     pub client_default_bitcoin_rpc: BitcoinRpcConfig,
+    /// The multisig of the federation the keys were reshared from, its UTXOs
+    /// are migrated to [`Self::peg_in_descriptor`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<PreviousWalletConfig>,
+}
+
+impl Encodable for WalletConfigConsensus {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.network.consensus_encode(writer)?;
+        len += self.peg_in_descriptor.consensus_encode(writer)?;
+        len += self.peer_peg_in_keys.consensus_encode(writer)?;
+        len += self.finality_delay.consensus_encode(writer)?;
+        len += self.default_fee.consensus_encode(writer)?;
+        len += self.fee_consensus.consensus_encode_abs(writer)?;
+        len += self.client_default_bitcoin_rpc.consensus_encode(writer)?;
+        len += encode_config_extension(
+            &WalletConfigConsensusExtension {
+                fees: self.fee_consensus.extension(),
+                previous: self.previous.clone(),
+            },
+            writer,
+        )?;
+        Ok(len)
+    }
+}
+
+impl Decodable for WalletConfigConsensus {
+    fn consensus_decode<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let network = Decodable::consensus_decode(reader, modules)?;
+        let peg_in_descriptor = Decodable::consensus_decode(reader, modules)?;
+        let peer_peg_in_keys = Decodable::consensus_decode(reader, modules)?;
+        let finality_delay = Decodable::consensus_decode(reader, modules)?;
+        let default_fee = Decodable::consensus_decode(reader, modules)?;
+        let fee_consensus = FeeConsensus::consensus_decode_abs(reader, modules)?;
+        let client_default_bitcoin_rpc = Decodable::consensus_decode(reader, modules)?;
+        let extension: WalletConfigConsensusExtension = decode_config_extension(reader, modules)?;
+
+        Ok(Self {
+            network,
+            peg_in_descriptor,
+            peer_peg_in_keys,
+            finality_delay,
+            default_fee,
+            fee_consensus: fee_consensus.with_extension(extension.fees),
+            client_default_bitcoin_rpc,
+            previous: extension.previous,
+        })
+    }
+}
+
+/// The fields of [`WalletConfigConsensus`] added after its encoding was
+/// released, see [`encode_config_extension`]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable)]
+struct WalletConfigConsensusExtension {
+    fees: FeeConsensusExtension,
+    previous: Option<PreviousWalletConfig>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+/// The multisig of a federation whose keys were reshared to a new set of
+/// guardians
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct PreviousWalletConfig {
+    /// The peg-in descriptor of the previous federation
+    pub peg_in_descriptor: PegInDescriptor,
+    /// The public keys of the previous multisig by previous peer id
+    pub peer_peg_in_keys: BTreeMap<PeerId, CompressedPublicKey>,
+    /// The previous peer ids of the guardians continuing in the new
+    /// federation, keyed by their new peer id
+    pub previous_peers: BTreeMap<PeerId, PeerId>,
+}
+
+impl PreviousWalletConfig {
+    /// The key `peer` of the new federation signs inputs of the previous
+    /// multisig with, if it was part of the previous federation
+    pub fn peer_peg_in_key(&self, peer: &PeerId) -> Option<&CompressedPublicKey> {
+        self.peer_peg_in_keys.get(self.previous_peers.get(peer)?)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct WalletClientConfig {
     /// The federations public peg-in-descriptor
     pub peg_in_descriptor: PegInDescriptor,
@@ -114,6 +201,41 @@ pub struct WalletClientConfig {
     pub default_bitcoin_rpc: BitcoinRpcConfig,
 }
 
+impl Encodable for WalletClientConfig {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.peg_in_descriptor.consensus_encode(writer)?;
+        len += self.network.consensus_encode(writer)?;
+        len += self.finality_delay.consensus_encode(writer)?;
+        len += self.fee_consensus.consensus_encode_abs(writer)?;
+        len += self.default_bitcoin_rpc.consensus_encode(writer)?;
+        len += encode_config_extension(&self.fee_consensus.extension(), writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for WalletClientConfig {
+    fn consensus_decode<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let peg_in_descriptor = Decodable::consensus_decode(reader, modules)?;
+        let network = Decodable::consensus_decode(reader, modules)?;
+        let finality_delay = Decodable::consensus_decode(reader, modules)?;
+        let fee_consensus = FeeConsensus::consensus_decode_abs(reader, modules)?;
+        let default_bitcoin_rpc = Decodable::consensus_decode(reader, modules)?;
+        let fee_extension = decode_config_extension(reader, modules)?;
+
+        Ok(Self {
+            peg_in_descriptor,
+            network,
+            finality_delay,
+            fee_consensus: fee_consensus.with_extension(fee_extension),
+            default_bitcoin_rpc,
+        })
+    }
+}
+
 impl std::fmt::Display for WalletClientConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -124,10 +246,83 @@ impl std::fmt::Display for WalletClientConfig {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+/// The fees charged for pegging in and out
+///
+/// Only the absolute fees are part of the encoding of the configs containing
+/// the fees as they were released, the proportional fees added later are
+/// appended as an extension, see [`encode_config_extension`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct FeeConsensus {
     pub peg_in_abs: fedimint_core::Amount,
     pub peg_out_abs: fedimint_core::Amount,
+    /// Charged per peg-in in addition to `peg_in_abs`
+    #[serde(default)]
+    pub peg_in_ppm: ProportionalFee,
+    /// Charged per peg-out in addition to `peg_out_abs`
+    #[serde(default)]
+    pub peg_out_ppm: ProportionalFee,
+}
+
+impl FeeConsensus {
+    /// The fee for pegging in `amount`
+    pub fn peg_in_fee(&self, amount: fedimint_core::Amount) -> fedimint_core::Amount {
+        self.peg_in_abs + self.peg_in_ppm.fee(amount)
+    }
+
+    /// The fee for pegging out `amount`, not including the on-chain fees
+    pub fn peg_out_fee(&self, amount: fedimint_core::Amount) -> fedimint_core::Amount {
+        self.peg_out_abs + self.peg_out_ppm.fee(amount)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.peg_in_ppm.validate()?;
+        self.peg_out_ppm.validate()
+    }
+
+    fn consensus_encode_abs<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<usize, std::io::Error> {
+        let mut len = 0;
+        len += self.peg_in_abs.consensus_encode(writer)?;
+        len += self.peg_out_abs.consensus_encode(writer)?;
+        Ok(len)
+    }
+
+    /// Decodes the absolute fees, the proportional fees are zero until the
+    /// extension is added with [`Self::with_extension`]
+    fn consensus_decode_abs<R: std::io::Read>(
+        reader: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        Ok(Self {
+            peg_in_abs: Decodable::consensus_decode(reader, modules)?,
+            peg_out_abs: Decodable::consensus_decode(reader, modules)?,
+            ..Self::default()
+        })
+    }
+
+    fn extension(&self) -> FeeConsensusExtension {
+        FeeConsensusExtension {
+            peg_in_ppm: self.peg_in_ppm,
+            peg_out_ppm: self.peg_out_ppm,
+        }
+    }
+
+    fn with_extension(self, extension: FeeConsensusExtension) -> Self {
+        Self {
+            peg_in_ppm: extension.peg_in_ppm,
+            peg_out_ppm: extension.peg_out_ppm,
+            ..self
+        }
+    }
+}
+
+/// The fields of [`FeeConsensus`] added after its encoding was released
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable)]
+struct FeeConsensusExtension {
+    peg_in_ppm: ProportionalFee,
+    peg_out_ppm: ProportionalFee,
 }
 
 impl Default for FeeConsensus {
@@ -135,6 +330,8 @@ impl Default for FeeConsensus {
         Self {
             peg_in_abs: fedimint_core::Amount::ZERO,
             peg_out_abs: fedimint_core::Amount::ZERO,
+            peg_in_ppm: ProportionalFee::ZERO,
+            peg_out_ppm: ProportionalFee::ZERO,
         }
     }
 }
@@ -167,7 +364,10 @@ impl WalletConfig {
 
         Self {
             local: WalletConfigLocal { bitcoin_rpc },
-            private: WalletConfigPrivate { peg_in_key: sk },
+            private: WalletConfigPrivate {
+                peg_in_key: sk,
+                previous_peg_in_key: None,
+            },
             consensus: WalletConfigConsensus {
                 network,
                 peg_in_descriptor,
@@ -176,6 +376,7 @@ impl WalletConfig {
                 default_fee: Feerate { sats_per_kvb: 1000 },
                 fee_consensus: Default::default(),
                 client_default_bitcoin_rpc,
+                previous: None,
             },
         }
     }
@@ -209,3 +410,185 @@ plugin_types_trait_impl_config!(
     WalletConfigConsensus,
     WalletClientConfig
 );
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
+    use fedimint_core::encoding::{Decodable, Encodable};
+    use fedimint_core::module::fee::ProportionalFee;
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+    use fedimint_core::util::SafeUrl;
+    use fedimint_core::{Amount, Feerate, PeerId};
+    use miniscript::descriptor::Wpkh;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    use super::{
+        FeeConsensus, PreviousWalletConfig, WalletClientConfig, WalletConfig, WalletConfigConsensus,
+    };
+    use crate::keys::CompressedPublicKey;
+    use crate::PegInDescriptor;
+
+    /// The encoding of [`FeeConsensus`] before proportional fees were added
+    #[derive(Encodable)]
+    struct FeeConsensusV0 {
+        peg_in_abs: Amount,
+        peg_out_abs: Amount,
+    }
+
+    /// The encoding of [`WalletClientConfig`] before proportional fees were
+    /// added
+    #[derive(Encodable)]
+    struct WalletClientConfigV0 {
+        peg_in_descriptor: PegInDescriptor,
+        network: bitcoin::Network,
+        finality_delay: u32,
+        fee_consensus: FeeConsensusV0,
+        default_bitcoin_rpc: BitcoinRpcConfig,
+    }
+
+    /// The encoding of [`super::WalletConfigConsensus`] before proportional
+    /// fees and resharing were added
+    #[derive(Encodable)]
+    struct WalletConfigConsensusV0 {
+        network: bitcoin::Network,
+        peg_in_descriptor: PegInDescriptor,
+        peer_peg_in_keys: BTreeMap<PeerId, CompressedPublicKey>,
+        finality_delay: u32,
+        default_fee: Feerate,
+        fee_consensus: FeeConsensusV0,
+        client_default_bitcoin_rpc: BitcoinRpcConfig,
+    }
+
+    fn wallet_config() -> WalletConfig {
+        let secret_key = SecretKey::from_slice(&[1; 32]).expect("valid key");
+        let key =
+            CompressedPublicKey::new(PublicKey::from_secret_key(&Secp256k1::new(), &secret_key));
+        let rpc = BitcoinRpcConfig {
+            kind: "bitcoind".to_string(),
+            url: SafeUrl::from_str("http://localhost:18443").expect("valid url"),
+        };
+
+        WalletConfig::new(
+            BTreeMap::from([(PeerId::from(0), key)]),
+            secret_key,
+            1,
+            bitcoin::Network::Regtest,
+            10,
+            rpc.clone(),
+            rpc,
+        )
+    }
+
+    #[test]
+    fn consensus_config_without_resharing_keeps_encoding() {
+        let config = wallet_config().consensus;
+        let bytes_v0 = WalletConfigConsensusV0 {
+            network: config.network,
+            peg_in_descriptor: config.peg_in_descriptor.clone(),
+            peer_peg_in_keys: config.peer_peg_in_keys.clone(),
+            finality_delay: config.finality_delay,
+            default_fee: config.default_fee,
+            fee_consensus: FeeConsensusV0 {
+                peg_in_abs: config.fee_consensus.peg_in_abs,
+                peg_out_abs: config.fee_consensus.peg_out_abs,
+            },
+            client_default_bitcoin_rpc: config.client_default_bitcoin_rpc.clone(),
+        }
+        .consensus_encode_to_vec();
+
+        assert_eq!(config.consensus_encode_to_vec(), bytes_v0);
+
+        let decoded = WalletConfigConsensus::consensus_decode_vec(
+            bytes_v0,
+            &ModuleDecoderRegistry::default(),
+        )
+        .expect("decodes the encoding of v0");
+
+        assert_eq!(decoded.previous, None);
+    }
+
+    #[test]
+    fn consensus_config_with_previous_multisig_roundtrips() {
+        let mut config = wallet_config().consensus;
+        config.previous = Some(PreviousWalletConfig {
+            peg_in_descriptor: config.peg_in_descriptor.clone(),
+            peer_peg_in_keys: config.peer_peg_in_keys.clone(),
+            previous_peers: BTreeMap::from([(PeerId::from(1), PeerId::from(0))]),
+        });
+
+        let bytes = config.consensus_encode_to_vec();
+        let decoded = WalletConfigConsensus::consensus_decode_vec(
+            bytes.clone(),
+            &ModuleDecoderRegistry::default(),
+        )
+        .expect("decodes");
+
+        assert_eq!(decoded.previous, config.previous);
+        assert_eq!(decoded.consensus_encode_to_vec(), bytes);
+    }
+
+    fn client_config() -> WalletClientConfig {
+        let secret_key = SecretKey::from_slice(&[1; 32]).expect("valid key");
+        let key =
+            CompressedPublicKey::new(PublicKey::from_secret_key(&Secp256k1::new(), &secret_key));
+
+        WalletClientConfig {
+            fee_consensus: FeeConsensus {
+                peg_in_abs: Amount::from_sats(1),
+                peg_out_abs: Amount::from_sats(2),
+                ..FeeConsensus::default()
+            },
+            ..WalletClientConfig::new(
+                PegInDescriptor::Wpkh(Wpkh::new(key).expect("compressed key")),
+                bitcoin::Network::Regtest,
+                10,
+                BitcoinRpcConfig {
+                    kind: "bitcoind".to_string(),
+                    url: SafeUrl::from_str("http://localhost:18443").expect("valid url"),
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn client_config_without_proportional_fees_keeps_encoding() {
+        let config = client_config();
+        let bytes_v0 = WalletClientConfigV0 {
+            peg_in_descriptor: config.peg_in_descriptor.clone(),
+            network: config.network,
+            finality_delay: config.finality_delay,
+            fee_consensus: FeeConsensusV0 {
+                peg_in_abs: config.fee_consensus.peg_in_abs,
+                peg_out_abs: config.fee_consensus.peg_out_abs,
+            },
+            default_bitcoin_rpc: config.default_bitcoin_rpc.clone(),
+        }
+        .consensus_encode_to_vec();
+
+        let decoded = WalletClientConfig::consensus_decode_vec(
+            bytes_v0.clone(),
+            &ModuleDecoderRegistry::default(),
+        )
+        .expect("decodes the encoding of v0");
+
+        assert_eq!(decoded, config);
+        assert_eq!(config.consensus_encode_to_vec(), bytes_v0);
+    }
+
+    #[test]
+    fn client_config_with_proportional_fees_roundtrips() {
+        let mut config = client_config();
+        config.fee_consensus.peg_out_ppm =
+            ProportionalFee::new(1_000, Amount::from_sats(1), Some(Amount::from_sats(100)));
+
+        let bytes = config.consensus_encode_to_vec();
+        let decoded =
+            WalletClientConfig::consensus_decode_vec(bytes, &ModuleDecoderRegistry::default())
+                .expect("decodes");
+
+        assert_eq!(decoded, config);
+    }
+}
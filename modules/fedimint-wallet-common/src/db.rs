@@ -5,7 +5,9 @@ use secp256k1::ecdsa::Signature;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
-use crate::{PendingTransaction, SpendableUTXO, UnsignedTransaction, WalletOutputOutcome};
+use crate::{
+    PegInDescriptor, PendingTransaction, SpendableUTXO, UnsignedTransaction, WalletOutputOutcome,
+};
 
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
@@ -19,6 +21,8 @@ pub enum DbKeyPrefix {
     PegOutTxSigCi = 0x36,
     PegOutBitcoinOutPoint = 0x37,
     PegOutNonce = 0x38,
+    PreviousUtxo = 0x39,
+    ReshareMigration = 0x3a,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -53,6 +57,21 @@ impl_db_record!(
 );
 impl_db_lookup!(key = UTXOKey, query_prefix = UTXOPrefixKey);
 
+/// UTXO locked to the multisig of the federation the keys were reshared from,
+/// waiting to be migrated to our own multisig
+#[derive(Clone, Debug, Eq, PartialEq, Encodable, Decodable, Serialize)]
+pub struct PreviousUTXOKey(pub bitcoin::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct PreviousUTXOPrefixKey;
+
+impl_db_record!(
+    key = PreviousUTXOKey,
+    value = SpendableUTXO,
+    db_prefix = DbKeyPrefix::PreviousUtxo,
+);
+impl_db_lookup!(key = PreviousUTXOKey, query_prefix = PreviousUTXOPrefixKey);
+
 #[derive(Clone, Debug, Encodable, Decodable, Serialize)]
 pub struct UnsignedTransactionKey(pub Txid);
 
@@ -154,3 +173,14 @@ impl_db_record!(
     value = u64,
     db_prefix = DbKeyPrefix::PegOutNonce
 );
+
+/// The peg-in descriptor of the previous federation whose wallet state was
+/// migrated after resharing, so the migration is only applied once
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct ReshareMigrationKey;
+
+impl_db_record!(
+    key = ReshareMigrationKey,
+    value = PegInDescriptor,
+    db_prefix = DbKeyPrefix::ReshareMigration
+);
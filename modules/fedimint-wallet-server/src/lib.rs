@@ -1,9 +1,9 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::{Infallible, TryInto};
 #[cfg(not(target_family = "wasm"))]
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
-use anyhow::{bail, format_err, Context};
+use anyhow::{bail, ensure, format_err, Context};
 use bitcoin::hashes::{sha256, Hash as BitcoinHash, HashEngine, Hmac, HmacEngine};
 use bitcoin::policy::DEFAULT_MIN_RELAY_TX_FEE;
 use bitcoin::secp256k1::{All, Secp256k1, Verification};
@@ -13,13 +13,13 @@ use bitcoin::{
     Address, BlockHash, EcdsaSig, EcdsaSighashType, Network, PackedLockTime, Script, Sequence,
     Transaction, TxIn, TxOut, Txid,
 };
-use common::config::WalletConfigConsensus;
+use common::config::{PreviousWalletConfig, WalletConfigConsensus};
 use common::db::{
     BlockCountVoteKey, BlockCountVotePrefix, DbKeyPrefix, FeeRateVoteKey, FeeRateVotePrefix,
-    PegOutNonceKey,
+    PegOutNonceKey, PreviousUTXOKey, PreviousUTXOPrefixKey, ReshareMigrationKey,
 };
 use common::{
-    proprietary_tweak_key, PegOutFees, PegOutSignatureItem, PendingTransaction,
+    proprietary_tweak_key, PegInDescriptor, PegOutFees, PegOutSignatureItem, PendingTransaction,
     ProcessPegOutSigError, SpendableUTXO, UnsignedTransaction, WalletCommonInit,
     WalletConsensusItem, WalletCreationError, WalletInput, WalletModuleTypes, WalletOutput,
     WalletOutputOutcome, CONFIRMATION_TARGET,
@@ -38,11 +38,13 @@ use fedimint_core::encoding::Encodable;
 use fedimint_core::endpoint_constants::{
     BLOCK_COUNT_ENDPOINT, BLOCK_COUNT_LOCAL_ENDPOINT, PEG_OUT_FEES_ENDPOINT,
 };
+use fedimint_core::health::{age_secs, HealthReport, HealthStatus};
 use fedimint_core::module::audit::Audit;
+use fedimint_core::module::liabilities::{ModuleLiabilities, ReserveUtxo};
 use fedimint_core::module::{
     api_endpoint, ApiEndpoint, ApiVersion, CoreConsensusVersion, InputMeta, ModuleConsensusVersion,
-    ModuleInit, PeerHandle, ServerModuleInit, ServerModuleInitArgs, SupportedModuleApiVersions,
-    TransactionItemAmount,
+    ModuleInit, PeerHandle, PreviousModuleConfig, ServerModuleInit, ServerModuleInitArgs,
+    SupportedModuleApiVersions, TransactionItemAmount,
 };
 use fedimint_core::server::DynServerModule;
 #[cfg(not(target_family = "wasm"))]
@@ -70,6 +72,7 @@ use miniscript::{translate_hash_fail, Descriptor, TranslatePk};
 use miniscript9::psbt::PsbtExt;
 use rand::rngs::OsRng;
 use secp256k1::{Message, Scalar};
+use serde_json::json;
 use strum::IntoEnumIterator;
 use tracing::{debug, error, info, instrument, trace, warn};
 
@@ -218,6 +221,24 @@ impl ModuleInit for WalletInit {
                         "Fee Rate Votes"
                     );
                 }
+                DbKeyPrefix::PreviousUtxo => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PreviousUTXOPrefixKey,
+                        PreviousUTXOKey,
+                        SpendableUTXO,
+                        wallet,
+                        "Previous UTXOs"
+                    );
+                }
+                DbKeyPrefix::ReshareMigration => {
+                    if let Some(descriptor) = dbtx.get_value(&ReshareMigrationKey).await {
+                        wallet.insert(
+                            "Reshare Migration".to_string(),
+                            Box::new(descriptor.to_string()),
+                        );
+                    }
+                }
             }
         }
 
@@ -243,8 +264,12 @@ impl ServerModuleInit for WalletInit {
         for metric in ALL_METRICS.iter() {
             metric.collect();
         }
+
+        let cfg: WalletConfig = args.cfg().to_typed()?;
+        migrate_reshared_state(args.db(), &cfg, args.our_peer_id()).await?;
+
         Ok(Wallet::new(
-            args.cfg().to_typed()?,
+            cfg,
             args.db().clone(),
             &mut args.task_group().clone(),
             args.our_peer_id(),
@@ -320,6 +345,54 @@ impl ServerModuleInit for WalletInit {
         Ok(wallet_cfg.to_erased())
     }
 
+    async fn distributed_reshare(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+        previous: &PreviousModuleConfig,
+    ) -> DkgResult<ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        let previous_consensus = WalletConfigConsensus::from_erased(&previous.consensus)?;
+        let previous_peg_in_key = previous
+            .ours
+            .as_ref()
+            .map(|cfg| cfg.to_typed::<WalletConfig>())
+            .transpose()?
+            .map(|cfg| cfg.private.peg_in_key);
+
+        // ECDSA multisig keys cannot be reshared, so we generate a new multisig
+        // and migrate the UTXOs of the previous one once consensus is running
+        let secp = secp256k1::Secp256k1::new();
+        let (sk, pk) = secp.generate_keypair(&mut OsRng);
+        let peer_peg_in_keys: BTreeMap<PeerId, CompressedPublicKey> = peers
+            .exchange_pubkeys("wallet".to_string(), pk)
+            .await?
+            .into_iter()
+            .map(|(k, key)| (k, CompressedPublicKey { key }))
+            .collect();
+
+        let mut wallet_cfg = WalletConfig::new(
+            peer_peg_in_keys,
+            sk,
+            peers.peer_ids().threshold(),
+            previous_consensus.network,
+            previous_consensus.finality_delay,
+            params.local.bitcoin_rpc.clone(),
+            previous_consensus.client_default_bitcoin_rpc.clone(),
+        );
+
+        wallet_cfg.private.previous_peg_in_key = previous_peg_in_key;
+        wallet_cfg.consensus.default_fee = previous_consensus.default_fee;
+        wallet_cfg.consensus.fee_consensus = previous_consensus.fee_consensus;
+        wallet_cfg.consensus.previous = Some(PreviousWalletConfig {
+            peg_in_descriptor: previous_consensus.peg_in_descriptor,
+            peer_peg_in_keys: previous_consensus.peer_peg_in_keys,
+            previous_peers: previous.previous_peers.clone(),
+        });
+
+        Ok(wallet_cfg.to_erased())
+    }
+
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
         let config = config.to_typed::<WalletConfig>()?;
         let pubkey = secp256k1::PublicKey::from_secret_key_global(&config.private.peg_in_key);
@@ -334,6 +407,21 @@ impl ServerModuleInit for WalletInit {
             bail!(" Bitcoin wallet private key doesn't match multisig pubkey");
         }
 
+        if let (Some(previous), Some(previous_key)) = (
+            &config.consensus.previous,
+            &config.private.previous_peg_in_key,
+        ) {
+            let previous_pubkey = secp256k1::PublicKey::from_secret_key_global(previous_key);
+
+            if previous.peer_peg_in_key(identity)
+                != Some(&CompressedPublicKey::new(previous_pubkey))
+            {
+                bail!("Previous bitcoin wallet private key doesn't match previous multisig pubkey");
+            }
+        }
+
+        config.consensus.fee_consensus.validate()?;
+
         Ok(())
     }
 
@@ -356,6 +444,7 @@ impl ServerModuleInit for WalletInit {
 impl ServerModule for Wallet {
     type Common = WalletModuleTypes;
     type Init = WalletInit;
+    type VerifiedInput = ();
 
     async fn consensus_proposal<'a>(
         &'a self,
@@ -540,25 +629,46 @@ impl ServerModule for Wallet {
             ));
         }
 
-        input.verify(&self.secp, &self.cfg.consensus.peg_in_descriptor)?;
+        let spendable_utxo = SpendableUTXO {
+            tweak: input.tweak_contract_key().serialize(),
+            amount: bitcoin::Amount::from_sat(input.tx_output().value),
+        };
 
-        debug!(outpoint = %input.outpoint(), "Claiming peg-in");
+        // Deposits to the multisig of the federation we were reshared from are
+        // accepted as well and migrated with its other UTXOs
+        let claimed = match input.verify(&self.secp, &self.cfg.consensus.peg_in_descriptor) {
+            Ok(()) => {
+                debug!(outpoint = %input.outpoint(), "Claiming peg-in");
 
-        if dbtx
-            .insert_entry(
-                &UTXOKey(input.outpoint()),
-                &SpendableUTXO {
-                    tweak: input.tweak_contract_key().serialize(),
-                    amount: bitcoin::Amount::from_sat(input.tx_output().value),
-                },
-            )
-            .await
-            .is_some()
-        {
+                dbtx.insert_entry(&UTXOKey(input.outpoint()), &spendable_utxo)
+                    .await
+                    .is_some()
+            }
+            Err(error) => {
+                let previous = self
+                    .cfg
+                    .consensus
+                    .previous
+                    .as_ref()
+                    .ok_or_else(|| error.clone())?;
+
+                input
+                    .verify(&self.secp, &previous.peg_in_descriptor)
+                    .map_err(|_| error)?;
+
+                debug!(outpoint = %input.outpoint(), "Claiming peg-in to previous multisig");
+
+                dbtx.insert_entry(&PreviousUTXOKey(input.outpoint()), &spendable_utxo)
+                    .await
+                    .is_some()
+            }
+        };
+
+        if claimed {
             return Err(WalletInputError::PegInAlreadyClaimed);
         }
         let amount = fedimint_core::Amount::from_sats(input.tx_output().value);
-        let fee = self.cfg.consensus.fee_consensus.peg_in_abs;
+        let fee = self.cfg.consensus.fee_consensus.peg_in_fee(amount);
         calculate_pegin_metrics(dbtx, amount, fee);
         Ok(InputMeta {
             amount: TransactionItemAmount { amount, fee },
@@ -566,6 +676,14 @@ impl ServerModule for Wallet {
         })
     }
 
+    fn input_to_json(&self, input: &WalletInput) -> Option<serde_json::Value> {
+        Some(serde_json::to_value(input).expect("serialization can't fail"))
+    }
+
+    fn output_to_json(&self, output: &WalletOutput) -> Option<serde_json::Value> {
+        Some(serde_json::to_value(output).expect("serialization can't fail"))
+    }
+
     async fn process_output<'a, 'b>(
         &'a self,
         dbtx: &mut DatabaseTransaction<'b>,
@@ -592,31 +710,7 @@ impl ServerModule for Wallet {
             "Signing peg out",
         );
 
-        let sigs = tx
-            .psbt
-            .inputs
-            .iter_mut()
-            .map(|input| {
-                assert_eq!(
-                    input.partial_sigs.len(),
-                    1,
-                    "There was already more than one (our) or no signatures in input"
-                );
-
-                // TODO: don't put sig into PSBT in the first place
-                // We actually take out our own signature so everyone finalizes the tx in the
-                // same epoch.
-                let sig = std::mem::take(&mut input.partial_sigs)
-                    .into_values()
-                    .next()
-                    .expect("asserted previously");
-
-                // We drop SIGHASH_ALL, because we always use that and it is only present in the
-                // PSBT for compatibility with other tools.
-                secp256k1::ecdsa::Signature::from_der(&sig.to_vec()[..sig.to_vec().len() - 1])
-                    .expect("we serialized it ourselves that way")
-            })
-            .collect::<Vec<_>>();
+        let sigs = take_our_signatures(&mut tx.psbt);
 
         // Delete used UTXOs
         for input in tx.psbt.unsigned_tx.input.iter() {
@@ -635,7 +729,7 @@ impl ServerModule for Wallet {
         )
         .await;
         let amount: fedimint_core::Amount = output.amount().into();
-        let fee = self.cfg.consensus.fee_consensus.peg_out_abs;
+        let fee = self.cfg.consensus.fee_consensus.peg_out_fee(amount);
         calculate_pegout_metrics(dbtx, amount, fee);
         Ok(TransactionItemAmount { amount, fee })
     }
@@ -659,6 +753,11 @@ impl ServerModule for Wallet {
                 v.amount.to_sat() as i64 * 1000
             })
             .await;
+        audit
+            .add_items(dbtx, module_instance_id, &PreviousUTXOPrefixKey, |_, v| {
+                v.amount.to_sat() as i64 * 1000
+            })
+            .await;
         audit
             .add_items(
                 dbtx,
@@ -683,6 +782,63 @@ impl ServerModule for Wallet {
             .await;
     }
 
+    async fn health(&self) -> HealthReport {
+        let block_count = *self.block_count_local.lock().expect("Locking failed");
+        let updates = *self.block_count_updates.lock().expect("Locking failed");
+
+        // The block count is fetched with every consensus proposal, so we only
+        // report on the last fetch instead of blocking on an unresponsive bitcoind
+        let status = match updates {
+            Some(updates) => HealthStatus::from_bool(
+                age_secs(updates.fetched) <= BITCOIND_HEALTH_MAX_FETCH_AGE.as_secs(),
+                json!({
+                    "block_count": block_count,
+                    "fetched_age_secs": age_secs(updates.fetched),
+                    "changed_age_secs": age_secs(updates.changed),
+                }),
+            ),
+            None => HealthStatus::unhealthy(json!({ "block_count": null })),
+        };
+
+        HealthReport::from([("bitcoind".to_string(), status)])
+    }
+
+    async fn liabilities(&self, dbtx: &mut DatabaseTransaction<'_>) -> ModuleLiabilities {
+        let reserve = |outpoint, utxo: SpendableUTXO, descriptor: &PegInDescriptor| ReserveUtxo {
+            outpoint,
+            amount: utxo.amount,
+            script_pubkey: bitcoin30_to_bitcoin29_script(
+                descriptor.tweak(&utxo.tweak, &self.secp).script_pubkey(),
+            ),
+        };
+
+        let mut reserves = dbtx
+            .find_by_prefix(&UTXOPrefixKey)
+            .await
+            .map(|(UTXOKey(outpoint), utxo)| {
+                reserve(outpoint, utxo, &self.cfg.consensus.peg_in_descriptor)
+            })
+            .collect::<Vec<_>>()
+            .await;
+
+        if let Some(previous) = &self.cfg.consensus.previous {
+            reserves.extend(
+                dbtx.find_by_prefix(&PreviousUTXOPrefixKey)
+                    .await
+                    .map(|(PreviousUTXOKey(outpoint), utxo)| {
+                        reserve(outpoint, utxo, &previous.peg_in_descriptor)
+                    })
+                    .collect::<Vec<_>>()
+                    .await,
+            );
+        }
+
+        ModuleLiabilities {
+            liabilities: Amount::ZERO,
+            reserves,
+        }
+    }
+
     fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
         vec![
             api_endpoint! {
@@ -748,6 +904,13 @@ fn calculate_pegout_metrics(dbtx: &mut DatabaseTransaction<'_>, amount: Amount,
     });
 }
 
+/// Transactions heavier than this are not relayed by Bitcoin Core
+const MAX_STANDARD_TX_WEIGHT: u64 = 400_000;
+
+/// How long ago the block count may have been fetched from bitcoind before the
+/// health check fails
+const BITCOIND_HEALTH_MAX_FETCH_AGE: Duration = Duration::from_secs(5 * 60);
+
 #[derive(Debug)]
 pub struct Wallet {
     cfg: WalletConfig,
@@ -755,9 +918,17 @@ pub struct Wallet {
     btc_rpc: DynBitcoindRpc,
     /// The result of last successful get_block_count
     block_count_local: std::sync::Mutex<Option<u32>>,
+    /// When the local block count was last fetched and last changed
+    block_count_updates: std::sync::Mutex<Option<BlockCountUpdates>>,
     our_peer_id: PeerId,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct BlockCountUpdates {
+    fetched: SystemTime,
+    changed: SystemTime,
+}
+
 impl Wallet {
     pub async fn new(
         cfg: WalletConfig,
@@ -801,6 +972,7 @@ impl Wallet {
             cfg,
             secp: Default::default(),
             block_count_local: Default::default(),
+            block_count_updates: Default::default(),
             btc_rpc: bitcoind_rpc,
             our_peer_id,
         };
@@ -837,6 +1009,15 @@ impl Wallet {
             .get(peer)
             .expect("always called with valid peer id");
 
+        // Inputs of the multisig we were reshared from are signed with the key
+        // the peer had in the previous federation
+        let previous_peer_key = self
+            .cfg
+            .consensus
+            .previous
+            .as_ref()
+            .and_then(|previous| previous.peer_peg_in_key(peer));
+
         if psbt.inputs.len() != signature.signature.len() {
             return Err(ProcessPegOutSigError::WrongSignatureCount(
                 psbt.inputs.len(),
@@ -868,7 +1049,13 @@ impl Wallet {
                 .get(&proprietary_tweak_key())
                 .expect("we saved it with a tweak");
 
-            let tweaked_peer_key = peer_key.tweak(tweak, &self.secp);
+            let tweaked_peer_key = if self.spends_previous_multisig(input) {
+                previous_peer_key
+                    .ok_or(ProcessPegOutSigError::InvalidSignature)?
+                    .tweak(tweak, &self.secp)
+            } else {
+                peer_key.tweak(tweak, &self.secp)
+            };
             self.secp
                 .verify_ecdsa(
                     &Message::from_slice(&tx_hash[..]).unwrap(),
@@ -889,6 +1076,28 @@ impl Wallet {
         Ok(())
     }
 
+    /// Whether the PSBT input spends a UTXO of the multisig of the federation
+    /// we were reshared from
+    fn spends_previous_multisig(&self, input: &Input) -> bool {
+        let Some(tweak) = input.proprietary.get(&proprietary_tweak_key()) else {
+            return false;
+        };
+
+        self.cfg
+            .consensus
+            .previous
+            .as_ref()
+            .is_some_and(|previous| {
+                let script_code = previous
+                    .peg_in_descriptor
+                    .tweak(tweak, &self.secp)
+                    .script_code()
+                    .expect("Failed to tweak descriptor");
+
+                input.witness_script.as_ref() == Some(&bitcoin30_to_bitcoin29_script(script_code))
+            })
+    }
+
     fn finalize_peg_out_psbt(
         &self,
         mut unsigned: UnsignedTransaction,
@@ -935,7 +1144,24 @@ impl Wallet {
             .and_then(|count| Ok(u32::try_from(count)?));
 
         match res {
-            Ok(count) => *self.block_count_local.lock().expect("Failed to lock") = Some(count),
+            Ok(count) => {
+                let previous = self
+                    .block_count_local
+                    .lock()
+                    .expect("Failed to lock")
+                    .replace(count);
+
+                let now = fedimint_core::time::now();
+                let mut updates = self.block_count_updates.lock().expect("Failed to lock");
+                let changed = match *updates {
+                    Some(updates) if previous == Some(count) => updates.changed,
+                    _ => now,
+                };
+                *updates = Some(BlockCountUpdates {
+                    fetched: now,
+                    changed,
+                });
+            }
             Err(ref err) => error!("Error while calling get_block_count: {:?}", err),
         }
 
@@ -1061,6 +1287,93 @@ impl Wallet {
             )
             .await;
         }
+
+        self.migrate_previous_utxos(dbtx).await;
+    }
+
+    /// Spends all UTXOs of the multisig of the federation we were reshared
+    /// from to our own multisig
+    ///
+    /// The migration transactions are signed by the guardians continuing from
+    /// the previous federation and their fees are paid from the assets of the
+    /// federation. As many transactions as needed to stay below the standard
+    /// transaction weight are created.
+    async fn migrate_previous_utxos(&self, dbtx: &mut DatabaseTransaction<'_>) {
+        let Some(previous) = &self.cfg.consensus.previous else {
+            return;
+        };
+
+        let mut utxos = dbtx
+            .find_by_prefix(&PreviousUTXOPrefixKey)
+            .await
+            .map(|(PreviousUTXOKey(outpoint), utxo)| (UTXOKey(outpoint), utxo))
+            .collect::<Vec<_>>()
+            .await;
+
+        if utxos.is_empty() {
+            return;
+        }
+
+        let fee_rate = self.consensus_fee_rate(dbtx).await;
+
+        while !utxos.is_empty() {
+            let change_tweak = self.consensus_nonce(dbtx).await;
+
+            let tx = match create_migration_tx(
+                &previous.peg_in_descriptor,
+                &self.secp,
+                &mut utxos,
+                self.offline_wallet().derive_script(&change_tweak),
+                fee_rate,
+                &change_tweak,
+            ) {
+                Ok(tx) => tx,
+                Err(error) => {
+                    warn!(%error, "Not migrating the UTXOs of the previous federation yet");
+                    return;
+                }
+            };
+
+            self.store_migration_tx(dbtx, previous, tx).await;
+        }
+    }
+
+    /// Stores a transaction created by [`create_migration_tx`] to be signed
+    /// and broadcast like our peg-out transactions
+    async fn store_migration_tx(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        previous: &PreviousWalletConfig,
+        mut tx: UnsignedTransaction,
+    ) {
+        for (UTXOKey(outpoint), _) in &tx.selected_utxos {
+            dbtx.remove_entry(&PreviousUTXOKey(*outpoint)).await;
+        }
+
+        let txid = tx.psbt.unsigned_tx.txid();
+
+        info!(
+            %txid,
+            inputs = tx.selected_utxos.len(),
+            "Migrating the UTXOs of the previous federation",
+        );
+
+        if let Some(previous_key) = &self.cfg.private.previous_peg_in_key {
+            StatelessWallet {
+                descriptor: &previous.peg_in_descriptor,
+                secret_key: previous_key,
+                secp: &self.secp,
+            }
+            .sign_psbt(&mut tx.psbt);
+
+            let sigs = take_our_signatures(&mut tx.psbt);
+
+            dbtx.insert_new_entry(&PegOutTxSignatureCI(txid), &sigs)
+                .await;
+        }
+
+        dbtx.insert_new_entry(&UnsignedTransactionKey(txid), &tx)
+            .await;
     }
 
     /// Add a change UTXO to our spendable UTXO database after it was included
@@ -1072,26 +1385,39 @@ impl Wallet {
     ) {
         self.remove_rbf_transactions(dbtx, pending_tx).await;
 
-        let script_pk = bitcoin30_to_bitcoin29_script(
-            self.cfg
-                .consensus
-                .peg_in_descriptor
-                .tweak(&pending_tx.tweak, &self.secp)
-                .script_pubkey(),
-        );
+        let script_pk = |descriptor: &PegInDescriptor| {
+            bitcoin30_to_bitcoin29_script(
+                descriptor
+                    .tweak(&pending_tx.tweak, &self.secp)
+                    .script_pubkey(),
+            )
+        };
+
+        let our_script_pk = script_pk(&self.cfg.consensus.peg_in_descriptor);
+
+        // Transactions signed before we were reshared pay their change to the
+        // previous multisig, from where it is migrated
+        let previous_script_pk = self
+            .cfg
+            .consensus
+            .previous
+            .as_ref()
+            .map(|previous| script_pk(&previous.peg_in_descriptor));
+
         for (idx, output) in pending_tx.tx.output.iter().enumerate() {
-            if output.script_pubkey == script_pk {
-                dbtx.insert_entry(
-                    &UTXOKey(bitcoin::OutPoint {
-                        txid: pending_tx.tx.txid(),
-                        vout: idx as u32,
-                    }),
-                    &SpendableUTXO {
-                        tweak: pending_tx.tweak,
-                        amount: bitcoin::Amount::from_sat(output.value),
-                    },
-                )
-                .await;
+            let outpoint = bitcoin::OutPoint {
+                txid: pending_tx.tx.txid(),
+                vout: idx as u32,
+            };
+            let utxo = SpendableUTXO {
+                tweak: pending_tx.tweak,
+                amount: bitcoin::Amount::from_sat(output.value),
+            };
+
+            if output.script_pubkey == our_script_pk {
+                dbtx.insert_entry(&UTXOKey(outpoint), &utxo).await;
+            } else if Some(&output.script_pubkey) == previous_script_pk.as_ref() {
+                dbtx.insert_entry(&PreviousUTXOKey(outpoint), &utxo).await;
             }
         }
     }
@@ -1188,11 +1514,20 @@ impl Wallet {
     }
 
     pub async fn get_wallet_value(&self, dbtx: &mut DatabaseTransaction<'_>) -> bitcoin::Amount {
+        let previous_utxos = dbtx
+            .find_by_prefix(&PreviousUTXOPrefixKey)
+            .await
+            .map(|(_, utxo)| utxo)
+            .collect::<Vec<SpendableUTXO>>()
+            .await;
+
         let sat_sum = self
             .available_utxos(dbtx)
             .await
             .into_iter()
-            .map(|(_, utxo)| utxo.amount.to_sat())
+            .map(|(_, utxo)| utxo)
+            .chain(previous_utxos)
+            .map(|utxo| utxo.amount.to_sat())
             .sum();
         bitcoin::Amount::from_sat(sat_sum)
     }
@@ -1432,45 +1767,7 @@ impl<'a> StatelessWallet<'a> {
             unknown: Default::default(),
             inputs: selected_utxos
                 .iter()
-                .map(|(_utxo_key, utxo)| {
-                    let script_pubkey = self
-                        .descriptor
-                        .tweak(&utxo.tweak, self.secp)
-                        .script_pubkey();
-                    Input {
-                        non_witness_utxo: None,
-                        witness_utxo: Some(TxOut {
-                            value: utxo.amount.to_sat(),
-                            script_pubkey: bitcoin30_to_bitcoin29_script(script_pubkey),
-                        }),
-                        partial_sigs: Default::default(),
-                        sighash_type: None,
-                        redeem_script: None,
-                        witness_script: Some(bitcoin30_to_bitcoin29_script(
-                            self.descriptor
-                                .tweak(&utxo.tweak, self.secp)
-                                .script_code()
-                                .expect("Failed to tweak descriptor"),
-                        )),
-                        bip32_derivation: Default::default(),
-                        final_script_sig: None,
-                        final_script_witness: None,
-                        ripemd160_preimages: Default::default(),
-                        sha256_preimages: Default::default(),
-                        hash160_preimages: Default::default(),
-                        hash256_preimages: Default::default(),
-                        proprietary: vec![(proprietary_tweak_key(), utxo.tweak.to_vec())]
-                            .into_iter()
-                            .collect(),
-                        tap_key_sig: Default::default(),
-                        tap_script_sigs: Default::default(),
-                        tap_scripts: Default::default(),
-                        tap_key_origins: Default::default(),
-                        tap_internal_key: Default::default(),
-                        tap_merkle_root: Default::default(),
-                        unknown: Default::default(),
-                    }
-                })
+                .map(|(_utxo_key, utxo)| psbt_input(self.descriptor, self.secp, utxo))
                 .collect(),
             outputs: vec![Default::default(), change_out],
         };
@@ -1580,6 +1877,260 @@ impl<'a> StatelessWallet<'a> {
     }
 }
 
+fn psbt_input(descriptor: &PegInDescriptor, secp: &Secp256k1<All>, utxo: &SpendableUTXO) -> Input {
+    let script_pubkey = descriptor.tweak(&utxo.tweak, secp).script_pubkey();
+    Input {
+        non_witness_utxo: None,
+        witness_utxo: Some(TxOut {
+            value: utxo.amount.to_sat(),
+            script_pubkey: bitcoin30_to_bitcoin29_script(script_pubkey),
+        }),
+        partial_sigs: Default::default(),
+        sighash_type: None,
+        redeem_script: None,
+        witness_script: Some(bitcoin30_to_bitcoin29_script(
+            descriptor
+                .tweak(&utxo.tweak, secp)
+                .script_code()
+                .expect("Failed to tweak descriptor"),
+        )),
+        bip32_derivation: Default::default(),
+        final_script_sig: None,
+        final_script_witness: None,
+        ripemd160_preimages: Default::default(),
+        sha256_preimages: Default::default(),
+        hash160_preimages: Default::default(),
+        hash256_preimages: Default::default(),
+        proprietary: vec![(proprietary_tweak_key(), utxo.tweak.to_vec())]
+            .into_iter()
+            .collect(),
+        tap_key_sig: Default::default(),
+        tap_script_sigs: Default::default(),
+        tap_scripts: Default::default(),
+        tap_key_origins: Default::default(),
+        tap_internal_key: Default::default(),
+        tap_merkle_root: Default::default(),
+        unknown: Default::default(),
+    }
+}
+
+/// Creates a transaction spending the largest of the `utxos` of the multisig
+/// `descriptor` that fit into a standard transaction to a single output paying
+/// `destination`, which is our own multisig tweaked with `change_tweak`
+///
+/// The spent UTXOs are removed from `utxos`, so a migration of more UTXOs than
+/// fit into one transaction calls this until `utxos` is empty.
+fn create_migration_tx(
+    descriptor: &PegInDescriptor,
+    secp: &Secp256k1<All>,
+    utxos: &mut Vec<(UTXOKey, SpendableUTXO)>,
+    destination: Script,
+    fee_rate: Feerate,
+    change_tweak: &[u8; 33],
+) -> Result<UnsignedTransaction, WalletOutputError> {
+    // Ensure deterministic ordering of UTXOs for all peers, the largest ones
+    // are spent first so the fees of later transactions are covered too
+    utxos.sort_by_key(|(utxo_key, utxo)| (std::cmp::Reverse(utxo.amount), utxo_key.0));
+
+    // See `StatelessWallet::create_tx` for the weight calculation
+    let out_weight = (1 + destination.len() * 4 + 32) as u64;
+    let max_input_weight = (descriptor
+        .max_satisfaction_weight()
+        .expect("is satisfyable")
+        + 128
+        + 16
+        + 16) as u64;
+    let base_weight = 16 + 12 + 12 + out_weight + 16;
+    let max_inputs = ((MAX_STANDARD_TX_WEIGHT - base_weight) / max_input_weight) as usize;
+
+    let remaining = utxos.split_off(max_inputs.min(utxos.len()));
+    let utxos = std::mem::replace(utxos, remaining);
+
+    let total_weight = base_weight + max_input_weight * utxos.len() as u64;
+
+    let fees = fee_rate.calculate_fee(total_weight);
+    let total_value = utxos
+        .iter()
+        .map(|(_, utxo)| utxo.amount)
+        .fold(bitcoin::Amount::ZERO, |total, amount| total + amount);
+
+    if total_value < fees + destination.dust_value() {
+        return Err(WalletOutputError::NotEnoughSpendableUTXO);
+    }
+
+    let change = total_value - fees;
+
+    let mut change_out = bitcoin::util::psbt::Output::default();
+    change_out
+        .proprietary
+        .insert(proprietary_tweak_key(), change_tweak.to_vec());
+
+    let transaction = Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: utxos
+            .iter()
+            .map(|(utxo_key, _utxo)| TxIn {
+                previous_output: utxo_key.0,
+                script_sig: Default::default(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: bitcoin::Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut {
+            value: change.to_sat(),
+            script_pubkey: destination.clone(),
+        }],
+    };
+
+    let psbt = PartiallySignedTransaction {
+        unsigned_tx: transaction,
+        version: 0,
+        xpub: Default::default(),
+        proprietary: Default::default(),
+        unknown: Default::default(),
+        inputs: utxos
+            .iter()
+            .map(|(_utxo_key, utxo)| psbt_input(descriptor, secp, utxo))
+            .collect(),
+        outputs: vec![change_out],
+    };
+
+    Ok(UnsignedTransaction {
+        psbt,
+        signatures: vec![],
+        change,
+        fees: PegOutFees {
+            fee_rate,
+            total_weight,
+        },
+        destination,
+        selected_utxos: utxos,
+        peg_out_amount: bitcoin::Amount::ZERO,
+        rbf: None,
+    })
+}
+
+/// Takes our own signatures out of a PSBT we just signed, so they are only
+/// added once they were agreed on in consensus and every peer finalizes the
+/// transaction in the same session
+fn take_our_signatures(psbt: &mut PartiallySignedTransaction) -> Vec<secp256k1::ecdsa::Signature> {
+    psbt.inputs
+        .iter_mut()
+        .map(|input| {
+            assert_eq!(
+                input.partial_sigs.len(),
+                1,
+                "There was already more than one (our) or no signatures in input"
+            );
+
+            // TODO: don't put sig into PSBT in the first place
+            let sig = std::mem::take(&mut input.partial_sigs)
+                .into_values()
+                .next()
+                .expect("asserted previously");
+
+            // We drop SIGHASH_ALL, because we always use that and it is only present in the
+            // PSBT for compatibility with other tools.
+            secp256k1::ecdsa::Signature::from_der(&sig.to_vec()[..sig.to_vec().len() - 1])
+                .expect("we serialized it ourselves that way")
+        })
+        .collect()
+}
+
+/// Applies resharing to the wallet state carried over from the previous
+/// federation when the new federation starts for the first time
+///
+/// The UTXOs of the previous multisig are set aside to be migrated and the
+/// block count and fee rate votes, which are keyed by peer id, are replaced by
+/// votes of the new peers for the previous consensus, so no blocks are skipped.
+async fn migrate_reshared_state(
+    db: &Database,
+    cfg: &WalletConfig,
+    our_peer_id: PeerId,
+) -> anyhow::Result<()> {
+    let Some(previous) = &cfg.consensus.previous else {
+        return Ok(());
+    };
+
+    let mut dbtx = db.begin_transaction().await;
+
+    if let Some(migrated) = dbtx.get_value(&ReshareMigrationKey).await {
+        if migrated == previous.peg_in_descriptor {
+            return Ok(());
+        }
+
+        ensure!(
+            dbtx.find_by_prefix(&PreviousUTXOPrefixKey)
+                .await
+                .next()
+                .await
+                .is_none(),
+            "The UTXOs of an earlier resharing have not been migrated yet"
+        );
+    }
+
+    let utxos = dbtx
+        .find_by_prefix(&UTXOPrefixKey)
+        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    for (UTXOKey(outpoint), utxo) in utxos {
+        dbtx.remove_entry(&UTXOKey(outpoint)).await;
+        dbtx.insert_new_entry(&PreviousUTXOKey(outpoint), &utxo)
+            .await;
+    }
+
+    let previous_peer_count = previous.peer_peg_in_keys.len();
+
+    let mut block_counts = dbtx
+        .find_by_prefix(&BlockCountVotePrefix)
+        .await
+        .map(|(.., count)| Some(count))
+        .collect::<Vec<_>>()
+        .await;
+    block_counts.resize(previous_peer_count, None);
+    block_counts.sort_unstable();
+
+    let mut fee_rates = dbtx
+        .find_by_prefix(&FeeRateVotePrefix)
+        .await
+        .map(|(.., rate)| rate)
+        .collect::<Vec<_>>()
+        .await;
+    fee_rates.resize(previous_peer_count, cfg.consensus.default_fee);
+    fee_rates.sort_unstable();
+
+    dbtx.remove_by_prefix(&BlockCountVotePrefix).await;
+    dbtx.remove_by_prefix(&FeeRateVotePrefix).await;
+
+    for peer in cfg.consensus.peer_peg_in_keys.keys() {
+        if let Some(block_count) = block_counts[previous_peer_count / 2] {
+            dbtx.insert_new_entry(&BlockCountVoteKey(*peer), &block_count)
+                .await;
+        }
+
+        dbtx.insert_new_entry(&FeeRateVoteKey(*peer), &fee_rates[previous_peer_count / 2])
+            .await;
+    }
+
+    // A joining guardian started from a copy of the database of a continuing
+    // guardian, whose peg-out signatures are not ours to propose
+    if !previous.previous_peers.contains_key(&our_peer_id) {
+        dbtx.remove_by_prefix(&PegOutTxSignatureCIPrefix).await;
+    }
+
+    dbtx.insert_entry(&ReshareMigrationKey, &previous.peg_in_descriptor)
+        .await;
+
+    dbtx.commit_tx_result().await?;
+
+    info!("Applied resharing to the wallet state of the previous federation");
+
+    Ok(())
+}
+
 pub fn nonce_from_idx(nonce_idx: u64) -> [u8; 33] {
     let mut nonce: [u8; 33] = [0; 33];
     // Make it look like a compressed pubkey, has to be either 0x02 or 0x03
@@ -1596,13 +2147,15 @@ mod tests {
 
     use bitcoin::Network::{Bitcoin, Testnet};
     use bitcoin::{Address, Amount, Network, OutPoint, Txid};
+    use fedimint_core::bitcoin_migration::bitcoin30_to_bitcoin29_script;
     use fedimint_core::{BitcoinHash, Feerate};
     use fedimint_wallet_common::{PegOut, PegOutFees, Rbf, WalletOutputV0};
     use miniscript::descriptor::Wsh;
 
     use crate::common::PegInDescriptor;
     use crate::{
-        CompressedPublicKey, OsRng, SpendableUTXO, StatelessWallet, UTXOKey, WalletOutputError,
+        create_migration_tx, CompressedPublicKey, OsRng, SpendableUTXO, StatelessWallet, UTXOKey,
+        WalletOutputError, MAX_STANDARD_TX_WEIGHT,
     };
 
     #[test]
@@ -1704,6 +2257,68 @@ mod tests {
         assert_eq!(res, Err(WalletOutputError::WrongNetwork(Testnet, Bitcoin)));
     }
 
+    #[test]
+    fn migration_txs_stay_below_standard_weight() {
+        let secp = secp256k1::Secp256k1::new();
+
+        let descriptor = PegInDescriptor::Wsh(
+            Wsh::new_sortedmulti(
+                3,
+                (0..4)
+                    .map(|_| secp.generate_keypair(&mut OsRng))
+                    .map(|(_, key)| CompressedPublicKey { key })
+                    .collect(),
+            )
+            .unwrap(),
+        );
+
+        // more UTXOs than fit into a single standard transaction
+        let utxo_count = 2000;
+        let mut utxos = (0..utxo_count)
+            .map(|vout| {
+                (
+                    UTXOKey(OutPoint {
+                        txid: Txid::all_zeros(),
+                        vout,
+                    }),
+                    SpendableUTXO {
+                        tweak: [0; 33],
+                        amount: Amount::from_sat(100_000 + u64::from(vout)),
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let destination = bitcoin30_to_bitcoin29_script(descriptor.script_pubkey());
+        let fee = Feerate { sats_per_kvb: 1000 };
+
+        let mut spent = vec![];
+        let mut txs = 0;
+        while !utxos.is_empty() {
+            let tx = create_migration_tx(
+                &descriptor,
+                &secp,
+                &mut utxos,
+                destination.clone(),
+                fee,
+                &[0; 33],
+            )
+            .expect("UTXOs cover the fees");
+
+            assert!(tx.fees.total_weight <= MAX_STANDARD_TX_WEIGHT);
+            assert_eq!(tx.psbt.unsigned_tx.input.len(), tx.selected_utxos.len());
+
+            spent.extend(tx.selected_utxos);
+            txs += 1;
+        }
+
+        assert!(txs > 1);
+        assert_eq!(spent.len(), utxo_count as usize);
+        assert!(spent
+            .windows(2)
+            .all(|utxos| utxos[0].1.amount >= utxos[1].1.amount));
+    }
+
     fn rbf(sats_per_kvb: u64, total_weight: u64) -> WalletOutputV0 {
         WalletOutputV0::Rbf(Rbf {
             fees: PegOutFees::new(sats_per_kvb, total_weight),
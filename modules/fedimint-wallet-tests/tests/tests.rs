@@ -938,6 +938,9 @@ mod fedimint_migration_tests {
                             );
                             info!("Validated FeeRateVote");
                         }
+                        // Only written after resharing the keys, which the v0 database
+                        // predates
+                        DbKeyPrefix::PreviousUtxo | DbKeyPrefix::ReshareMigration => {}
                     }
                 }
                 Ok(())